@@ -0,0 +1,381 @@
+/**
+    Ludicrous Load Test
+    Spawns a swarm of headless bots that log in and move along scripted paths to force chunk
+    loading, then reports chunk throughput, keep alive latency and disconnects.
+
+    Usage: `ludicrous-loadtest [options]`
+        * `--address <host:port>` server to connect to (default `127.0.0.1:25565`)
+        * `--bots <n>` number of bots (default 10)
+        * `--duration <secs>` time each bot stays connected after login (default 60)
+        * `--speed <blocks/sec>` movement speed (default 10)
+        * `--path <line|circle|square|idle>` movement pattern (default line)
+        * `--fly` moves bots 32 blocks above their spawn instead of walking
+        * `--spawn-interval <ms>` delay between bot logins (default 100)
+        * `--name-prefix <prefix>` bot username prefix (default `bot`)
+*/
+use ludicrous::client::{BotClient, ClientEvent};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const USAGE: &str = "Usage: ludicrous-loadtest [--address <host:port>] [--bots <n>] \
+    [--duration <secs>] [--speed <blocks/sec>] [--path <line|circle|square|idle>] [--fly] \
+    [--spawn-interval <ms>] [--name-prefix <prefix>]";
+/// Interval at which bots send a movement update, same as the client tick rate
+const MOVE_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MovePath {
+    /// Walk straight away from spawn, each bot in a different direction
+    Line,
+    Circle,
+    Square,
+    Idle,
+}
+
+#[derive(Debug, Clone)]
+struct LoadTestConfig {
+    address: String,
+    bots: usize,
+    duration: Duration,
+    speed: f64,
+    path: MovePath,
+    fly: bool,
+    spawn_interval: Duration,
+    name_prefix: String,
+}
+
+impl LoadTestConfig {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self {
+            address: "127.0.0.1:25565".to_string(),
+            bots: 10,
+            duration: Duration::from_secs(60),
+            speed: 10.0,
+            path: MovePath::Line,
+            fly: false,
+            spawn_interval: Duration::from_millis(100),
+            name_prefix: "bot".to_string(),
+        };
+        while let Some(arg) = args.next() {
+            if arg == "--fly" {
+                config.fly = true;
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {arg}"))?;
+            let invalid = || format!("Invalid value for {arg}: {value}");
+            match arg.as_str() {
+                "--address" => config.address = value.clone(),
+                "--bots" => config.bots = value.parse().map_err(|_| invalid())?,
+                "--duration" => {
+                    let secs = value.parse::<f64>().map_err(|_| invalid())?;
+                    config.duration = Duration::try_from_secs_f64(secs).map_err(|_| invalid())?
+                }
+                "--speed" => config.speed = value.parse().map_err(|_| invalid())?,
+                "--path" => {
+                    config.path = match value.as_str() {
+                        "line" => MovePath::Line,
+                        "circle" => MovePath::Circle,
+                        "square" => MovePath::Square,
+                        "idle" => MovePath::Idle,
+                        _ => return Err(format!("Unknown path {value}")),
+                    }
+                }
+                "--spawn-interval" => {
+                    config.spawn_interval =
+                        Duration::from_millis(value.parse().map_err(|_| invalid())?)
+                }
+                "--name-prefix" => config.name_prefix = value.clone(),
+                _ => return Err(format!("Unknown option {arg}")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Default)]
+struct BotReport {
+    index: usize,
+    logged_in: bool,
+    login_time: Option<Duration>,
+    time_to_first_chunk: Option<Duration>,
+    chunks_received: u64,
+    /// Keep alive round trips the server measured and sent in player info updates
+    keep_alive_latencies: Vec<Duration>,
+    /// Time between login and the end of the bot's session
+    connected_time: Duration,
+    disconnect: Option<String>,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() {
+    let config = match LoadTestConfig::from_args(std::env::args().skip(1)) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!("{USAGE}");
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "Starting {} bots against {} for {:?} ({:?} path at {} blocks/sec{})",
+        config.bots,
+        config.address,
+        config.duration,
+        config.path,
+        config.speed,
+        if config.fly { ", flying" } else { "" }
+    );
+    let reports = Arc::new(Mutex::new(Vec::with_capacity(config.bots)));
+    let mut tasks = Vec::with_capacity(config.bots);
+    for index in 0..config.bots {
+        let bot_config = config.clone();
+        let reports = reports.clone();
+        tasks.push(tokio::spawn(async move {
+            let report = run_bot(index, &bot_config).await;
+            reports.lock().await.push(report);
+        }));
+        tokio::time::sleep(config.spawn_interval).await;
+    }
+    for task in tasks {
+        let _ = task.await;
+    }
+    print_summary(&reports.lock().await, &config);
+}
+
+async fn run_bot(index: usize, config: &LoadTestConfig) -> BotReport {
+    let mut report = BotReport {
+        index,
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let username = format!("{}{index}", config.name_prefix);
+    let mut client = match BotClient::connect(&config.address, &username).await {
+        Ok(client) => client,
+        Err(e) => {
+            report.disconnect = Some(format!("login failed: {e}"));
+            return report;
+        }
+    };
+    report.logged_in = true;
+    report.login_time = Some(start.elapsed());
+    let session_start = Instant::now();
+    let mut move_interval = tokio::time::interval(MOVE_INTERVAL);
+    let mut spawn_pos = None;
+    let mut ticks = 0u64;
+
+    while session_start.elapsed() < config.duration {
+        tokio::select! {
+            packet = client.read_packet() => {
+                let event = match packet {
+                    Ok((id, data)) => client.handle_packet(id, data).await,
+                    Err(e) => Err(e),
+                };
+                match event {
+                    Ok(Some(ClientEvent::PositionSync { x, y, z, .. })) => {
+                        spawn_pos.get_or_insert((x, y, z));
+                    }
+                    Ok(Some(ClientEvent::ChunkData { .. })) => {
+                        report.chunks_received += 1;
+                        report.time_to_first_chunk.get_or_insert(start.elapsed());
+                    }
+                    Ok(Some(ClientEvent::Latency { millis })) => {
+                        let millis = millis.max(0) as u64;
+                        report.keep_alive_latencies.push(Duration::from_millis(millis));
+                    }
+                    Ok(Some(ClientEvent::Disconnect { reason })) => {
+                        report.disconnect = Some(String::from_utf8_lossy(&reason).to_string());
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        report.disconnect = Some(e.to_string());
+                        break;
+                    }
+                }
+            }
+            _ = move_interval.tick() => {
+                let Some(spawn_pos) = spawn_pos else {
+                    continue;
+                };
+                ticks += 1;
+                let (x, y, z) = path_position(index, config, spawn_pos, ticks);
+                if let Err(e) = client.move_to(x, y, z).await {
+                    report.disconnect = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+    }
+    report.connected_time = session_start.elapsed();
+    report
+}
+
+/// Position of a bot along its path after the given number of movement ticks
+fn path_position(
+    index: usize,
+    config: &LoadTestConfig,
+    spawn_pos: (f64, f64, f64),
+    ticks: u64,
+) -> (f64, f64, f64) {
+    let distance = config.speed * MOVE_INTERVAL.as_secs_f64() * ticks as f64;
+    // Spread bots evenly around the spawn so they load different chunks
+    let angle = index as f64 * std::f64::consts::TAU / config.bots.max(1) as f64;
+    let (offset_x, offset_z) = match config.path {
+        MovePath::Idle => (0.0, 0.0),
+        MovePath::Line => (angle.cos() * distance, angle.sin() * distance),
+        MovePath::Circle => {
+            const RADIUS: f64 = 256.0;
+            let theta = angle + distance / RADIUS;
+            (theta.cos() * RADIUS, theta.sin() * RADIUS)
+        }
+        MovePath::Square => {
+            const SIDE: f64 = 512.0;
+            let along = distance % (SIDE * 4.0);
+            let (edge, progress) = ((along / SIDE) as u8, along % SIDE);
+            let half = SIDE / 2.0;
+            match edge {
+                0 => (-half + progress, -half),
+                1 => (half, -half + progress),
+                2 => (half - progress, half),
+                _ => (-half, half - progress),
+            }
+        }
+    };
+    let y = if config.fly {
+        spawn_pos.1 + 32.0
+    } else {
+        spawn_pos.1
+    };
+    (spawn_pos.0 + offset_x, y, spawn_pos.2 + offset_z)
+}
+
+fn percentile(sorted: &[Duration], percentile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+    sorted[index]
+}
+
+fn print_percentiles(name: &str, mut values: Vec<Duration>) {
+    values.sort();
+    println!(
+        "{name:<24} p50 {:>10.2?}  p90 {:>10.2?}  p99 {:>10.2?}  max {:>10.2?}  (n = {})",
+        percentile(&values, 0.5),
+        percentile(&values, 0.9),
+        percentile(&values, 0.99),
+        values.last().copied().unwrap_or_default(),
+        values.len()
+    );
+}
+
+fn print_summary(reports: &[BotReport], config: &LoadTestConfig) {
+    let logged_in = reports.iter().filter(|r| r.logged_in).count();
+    let total_chunks: u64 = reports.iter().map(|r| r.chunks_received).sum();
+    let connected_secs: f64 = reports.iter().map(|r| r.connected_time.as_secs_f64()).sum();
+    let longest_session = reports
+        .iter()
+        .map(|r| r.connected_time)
+        .max()
+        .unwrap_or_default();
+    let disconnects = reports
+        .iter()
+        .filter(|r| r.logged_in && r.disconnect.is_some())
+        .count();
+
+    println!();
+    println!("==== Load Test Results ====");
+    println!(
+        "Bots logged in:          {logged_in}/{} ({} failed logins)",
+        config.bots,
+        config.bots - logged_in
+    );
+    println!("Disconnects:             {disconnects}");
+    println!("Chunks received:         {total_chunks}");
+    println!(
+        "Chunks/sec (total):      {:.1}",
+        total_chunks as f64 / longest_session.as_secs_f64().max(f64::EPSILON)
+    );
+    println!(
+        "Chunks/sec (per bot):    {:.1}",
+        total_chunks as f64 / connected_secs.max(f64::EPSILON)
+    );
+    print_percentiles(
+        "Login time",
+        reports.iter().filter_map(|r| r.login_time).collect(),
+    );
+    print_percentiles(
+        "Time to first chunk",
+        reports
+            .iter()
+            .filter_map(|r| r.time_to_first_chunk)
+            .collect(),
+    );
+    print_percentiles(
+        "Keep alive latency",
+        reports
+            .iter()
+            .flat_map(|r| r.keep_alive_latencies.iter().copied())
+            .collect(),
+    );
+    for report in reports.iter() {
+        if let Some(reason) = &report.disconnect {
+            println!("  bot {}: {reason}", report.index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> impl Iterator<Item = String> + '_ {
+        args.split_whitespace().map(str::to_string)
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        let config =
+            LoadTestConfig::from_args(args("--bots 3 --path square --fly --duration 1.5")).unwrap();
+        assert_eq!(config.bots, 3);
+        assert_eq!(config.path, MovePath::Square);
+        assert!(config.fly);
+        assert_eq!(config.duration, Duration::from_millis(1500));
+        assert_eq!(config.address, "127.0.0.1:25565");
+        assert!(LoadTestConfig::from_args(args("--bots")).is_err());
+        assert!(LoadTestConfig::from_args(args("--path zigzag")).is_err());
+        for duration in ["-1", "NaN", "inf", "1e300"] {
+            let option = format!("--duration {duration}");
+            assert!(LoadTestConfig::from_args(args(&option)).is_err());
+        }
+    }
+
+    #[test]
+    fn square_path_walks_the_edges() {
+        let config = LoadTestConfig::from_args(args("--path square --speed 20")).unwrap();
+        // One block per tick
+        assert_eq!(
+            path_position(0, &config, (0.0, 64.0, 0.0), 0),
+            (-256.0, 64.0, -256.0)
+        );
+        assert_eq!(
+            path_position(0, &config, (0.0, 64.0, 0.0), 512),
+            (256.0, 64.0, -256.0)
+        );
+        assert_eq!(
+            path_position(0, &config, (0.0, 64.0, 0.0), 768),
+            (256.0, 64.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn percentiles_pick_the_nearest_rank() {
+        let values = (1..=10).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&values, 0.5), Duration::from_millis(6));
+        assert_eq!(percentile(&values, 0.99), Duration::from_millis(10));
+        assert_eq!(percentile(&[], 0.5), Duration::ZERO);
+    }
+}
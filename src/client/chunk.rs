@@ -0,0 +1,165 @@
+use super::PacketReader;
+
+/// Block states per chunk section
+const SECTION_BLOCKS: usize = 4096;
+/// Biomes per chunk section (4x4x4)
+const SECTION_BIOMES: usize = 64;
+/// Bits per entry used when the block palette is sent directly (global block state ids)
+const DIRECT_BLOCK_BITS: u8 = 15;
+
+/// A chunk as decoded from a `Chunk Data and Update Light` packet
+#[derive(Debug, Clone)]
+pub struct ClientChunk {
+    pub x: i32,
+    pub z: i32,
+    /// Heightmap type and the packed heightmap longs
+    pub heightmaps: Vec<(i32, Vec<i64>)>,
+    /// Sections from the bottom of the world upwards
+    pub sections: Vec<ClientChunkSection>,
+    pub sky_light_mask: Vec<i64>,
+    pub block_light_mask: Vec<i64>,
+    pub empty_sky_light_mask: Vec<i64>,
+    pub empty_block_light_mask: Vec<i64>,
+    pub sky_light: Vec<Vec<u8>>,
+    pub block_light: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientChunkSection {
+    /// Non air block count as reported by the server
+    pub block_count: i16,
+    /// Global block state ids indexed by `(y << 8) | (z << 4) | x`
+    pub block_states: Vec<u32>,
+    /// Biome ids indexed by `(y << 4) | (z << 2) | x`
+    pub biomes: Vec<u32>,
+}
+
+impl ClientChunk {
+    /// Decodes the packet data after the packet id
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut reader = PacketReader::new(data);
+        let x = reader.read_i32()?;
+        let z = reader.read_i32()?;
+
+        let heightmap_count = reader.read_varint()?;
+        let mut heightmaps = Vec::with_capacity(heightmap_count.max(0) as usize);
+        for _ in 0..heightmap_count {
+            let heightmap_type = reader.read_varint()?;
+            heightmaps.push((heightmap_type, reader.read_long_array()?));
+        }
+
+        let section_data_len = reader.read_varint()? as usize;
+        let mut section_reader = PacketReader::new(reader.read_bytes(section_data_len)?);
+        let mut sections = Vec::new();
+        while !section_reader.is_empty() {
+            let block_count = section_reader.read_i16()?;
+            let block_states =
+                Self::decode_paletted_container(&mut section_reader, SECTION_BLOCKS, 4, 8)?;
+            let biomes =
+                Self::decode_paletted_container(&mut section_reader, SECTION_BIOMES, 1, 3)?;
+            sections.push(ClientChunkSection {
+                block_count,
+                block_states,
+                biomes,
+            });
+        }
+
+        let block_entities = reader.read_varint()?;
+        for _ in 0..block_entities {
+            // Block entities are not kept, skip packed xz, y, type and nbt
+            reader.read_bytes(3)?;
+            reader.read_varint()?;
+            reader.skip_nbt()?;
+        }
+
+        let sky_light_mask = reader.read_long_array()?;
+        let block_light_mask = reader.read_long_array()?;
+        let empty_sky_light_mask = reader.read_long_array()?;
+        let empty_block_light_mask = reader.read_long_array()?;
+        let mut sky_light = Vec::new();
+        for _ in 0..reader.read_varint()? {
+            let len = reader.read_varint()? as usize;
+            sky_light.push(reader.read_bytes(len)?.to_vec());
+        }
+        let mut block_light = Vec::new();
+        for _ in 0..reader.read_varint()? {
+            let len = reader.read_varint()? as usize;
+            block_light.push(reader.read_bytes(len)?.to_vec());
+        }
+
+        Some(Self {
+            x,
+            z,
+            heightmaps,
+            sections,
+            sky_light_mask,
+            block_light_mask,
+            empty_sky_light_mask,
+            empty_block_light_mask,
+            sky_light,
+            block_light,
+        })
+    }
+
+    /// Reads a paletted container and returns the global id of every entry
+    ///
+    /// Indirect palettes use at least `min_bits` and at most `max_bits` bits per entry, anything
+    /// above `max_bits` is a direct palette
+    fn decode_paletted_container(
+        reader: &mut PacketReader,
+        entries: usize,
+        min_bits: u8,
+        max_bits: u8,
+    ) -> Option<Vec<u32>> {
+        let bits_per_entry = reader.read_u8()?;
+        if bits_per_entry == 0 {
+            let value = reader.read_varint()? as u32;
+            return Some(vec![value; entries]);
+        }
+        let palette = if bits_per_entry <= max_bits {
+            let palette_len = reader.read_varint()?;
+            let mut palette = Vec::with_capacity(palette_len.max(0) as usize);
+            for _ in 0..palette_len {
+                palette.push(reader.read_varint()? as u32);
+            }
+            Some(palette)
+        } else {
+            None
+        };
+        let bits_per_entry = match &palette {
+            Some(_) => bits_per_entry.max(min_bits),
+            // Direct biome palettes send the bits they use, direct block palettes always use the
+            // global block state bits
+            None if entries == SECTION_BLOCKS => DIRECT_BLOCK_BITS.max(bits_per_entry),
+            None => bits_per_entry,
+        } as usize;
+        let entries_per_long = 64 / bits_per_entry;
+        let long_count = entries.div_ceil(entries_per_long);
+        let mask = (1u64 << bits_per_entry) - 1;
+        let mut values = Vec::with_capacity(entries);
+        for _ in 0..long_count {
+            let long = reader.read_i64()? as u64;
+            for i in 0..entries_per_long {
+                if values.len() == entries {
+                    break;
+                }
+                let value = ((long >> (i * bits_per_entry)) & mask) as u32;
+                values.push(match &palette {
+                    Some(palette) => *palette.get(value as usize)?,
+                    None => value,
+                });
+            }
+        }
+        Some(values)
+    }
+
+    /// Returns the block state at the given position relative to the chunk, where `section_y` is
+    /// the index of the section from the bottom of the world
+    pub fn get_block(&self, x: usize, section_y: usize, y: usize, z: usize) -> Option<u32> {
+        self.sections
+            .get(section_y)?
+            .block_states
+            .get((y << 8) | (z << 4) | x)
+            .copied()
+    }
+}
@@ -0,0 +1,657 @@
+//! Headless Client
+//! A minimal bot that speaks the same protocol as the vanilla client so the server can be
+//! exercised end to end without a real Minecraft client.
+//!
+//! The bot logs in in offline mode, follows the encryption and compression requests of the
+//! server, acknowledges the configuration stage and then decodes play packets into
+//! `ClientEvent`s. Keep alives and teleports are answered automatically.
+
+mod chunk;
+pub use chunk::{ClientChunk, ClientChunkSection};
+
+use super::packets::configuration::{FinishConfig, KnownPacks, RegistryData};
+use super::packets::play::{
+    ChunkLightData, GameEvent, KeepAlive, Login, PlayerInfoUpdate, SetCenterChunk,
+    SynchronizePlayerPos, UnloadChunks,
+};
+use super::packets::{peek_varint_and_len_from_slice, write_string, write_varint, PacketStatic};
+use super::player::PlayerState;
+use crate::MC_PROTOCOL;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use openssl::symm::{Cipher, Crypter, Mode};
+use rsa::pkcs8::DecodePublicKey;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read, Write};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Serverbound packet ids that are not declared by the server's packet types
+mod serverbound {
+    pub const HANDSHAKE: i32 = 0x00;
+    pub const LOGIN_START: i32 = 0x00;
+    pub const ENCRYPTION_RESPONSE: i32 = 0x01;
+    pub const LOGIN_ACKNOWLEDGED: i32 = 0x03;
+    pub const CONFIG_KEEP_ALIVE: i32 = 0x04;
+    pub const CONFIG_PONG: i32 = 0x05;
+    pub const ACCEPT_TELEPORTATION: i32 = 0x00;
+    pub const PLAY_KEEP_ALIVE: i32 = 0x1B;
+    pub const MOVE_PLAYER_POS: i32 = 0x1D;
+    pub const MOVE_PLAYER_POS_ROT: i32 = 0x1E;
+    pub const PING_REQUEST: i32 = 0x25;
+}
+
+/// Clientbound packet ids that are not declared by the server's packet types
+mod clientbound {
+    pub const LOGIN_DISCONNECT: i32 = 0x00;
+    pub const ENCRYPTION_REQUEST: i32 = 0x01;
+    pub const LOGIN_SUCCESS: i32 = 0x02;
+    pub const SET_COMPRESSION: i32 = 0x03;
+    pub const CONFIG_DISCONNECT: i32 = 0x02;
+    pub const CONFIG_KEEP_ALIVE: i32 = 0x04;
+    pub const CONFIG_PING: i32 = 0x05;
+    pub const BUNDLE_DELIMITER: i32 = 0x00;
+    pub const PLAY_DISCONNECT: i32 = 0x1C;
+    pub const PONG_RESPONSE: i32 = 0x37;
+}
+
+/// Play packets decoded by the bot
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    Login {
+        entity_id: i32,
+    },
+    /// Position was synchronized, the teleport has already been accepted
+    PositionSync {
+        teleport_id: i32,
+        x: f64,
+        y: f64,
+        z: f64,
+    },
+    /// Chunk was decoded and stored in `BotClient::chunks`
+    ChunkData {
+        x: i32,
+        z: i32,
+    },
+    ChunkUnload {
+        x: i32,
+        z: i32,
+    },
+    CenterChunk {
+        x: i32,
+        z: i32,
+    },
+    /// Keep alive was received and already answered
+    KeepAlive {
+        id: i64,
+    },
+    GameEvent {
+        event: u8,
+        value: f32,
+    },
+    Pong {
+        id: i64,
+    },
+    /// Latency of the bot the server measured with keep alives, in milliseconds
+    Latency {
+        millis: i32,
+    },
+    Disconnect {
+        reason: Vec<u8>,
+    },
+    /// Any packet the bot does not decode
+    Other {
+        id: i32,
+        data: Vec<u8>,
+    },
+}
+
+pub struct BotClient {
+    socket: TcpStream,
+    encryptor: Option<Crypter>,
+    decryptor: Option<Crypter>,
+    /// Set once the server enabled compression
+    compression_threshold: Option<i32>,
+    read_buf: Vec<u8>,
+    decrypted_buf: Vec<u8>,
+    /// Decrypted stream data that does not form a full packet yet
+    pending_data: Vec<u8>,
+    pub state: PlayerState,
+    pub username: String,
+    pub uuid: [u8; 16],
+    pub entity_id: Option<i32>,
+    pub position: Option<(f64, f64, f64)>,
+    pub center_chunk: Option<(i32, i32)>,
+    /// Registries received during configuration
+    pub registries: Vec<String>,
+    pub chunks: HashMap<(i32, i32), ClientChunk>,
+}
+
+impl BotClient {
+    /// Connects to a server and logs in as an offline player, returning once the client is in
+    /// the play state
+    pub async fn connect(address: &str, username: &str) -> std::io::Result<Self> {
+        let socket = TcpStream::connect(address).await?;
+        socket.set_nodelay(true)?;
+        let mut client = Self {
+            socket,
+            encryptor: None,
+            decryptor: None,
+            compression_threshold: None,
+            read_buf: vec![0u8; 4096],
+            decrypted_buf: vec![0u8; 4096],
+            pending_data: Vec::new(),
+            state: PlayerState::HandShake,
+            username: username.to_string(),
+            uuid: Self::offline_uuid(username),
+            entity_id: None,
+            position: None,
+            center_chunk: None,
+            registries: Vec::new(),
+            chunks: HashMap::new(),
+        };
+        let (host, port) = address.rsplit_once(':').unwrap_or((address, "25565"));
+        client
+            .handshake(host, port.parse().unwrap_or(25565))
+            .await?;
+        client.login().await?;
+        client.configure().await?;
+        Ok(client)
+    }
+
+    /// Same uuid derivation as the vanilla server uses for offline players
+    fn offline_uuid(username: &str) -> [u8; 16] {
+        use sha1::Digest;
+        let hash = sha1::Sha1::digest(format!("OfflinePlayer:{username}").as_bytes());
+        let mut uuid = [0u8; 16];
+        uuid.copy_from_slice(&hash[..16]);
+        uuid[6] = (uuid[6] & 0x0F) | 0x30;
+        uuid[8] = (uuid[8] & 0x3F) | 0x80;
+        uuid
+    }
+
+    async fn handshake(&mut self, host: &str, port: u16) -> std::io::Result<()> {
+        let mut handshake_packet = write_varint(serverbound::HANDSHAKE);
+        handshake_packet.extend(write_varint(MC_PROTOCOL as i32));
+        handshake_packet.extend(write_string(host));
+        handshake_packet.extend(port.to_be_bytes());
+        handshake_packet.extend(write_varint(2)); // Login
+        self.write_packet(handshake_packet).await?;
+        self.state = PlayerState::Login;
+        Ok(())
+    }
+
+    async fn login(&mut self) -> std::io::Result<()> {
+        let mut login_start_packet = write_varint(serverbound::LOGIN_START);
+        login_start_packet.extend(write_string(&self.username));
+        login_start_packet.extend(self.uuid);
+        self.write_packet(login_start_packet).await?;
+        loop {
+            let (id, data) = self.read_packet().await?;
+            let mut reader = PacketReader::new(&data);
+            match id {
+                clientbound::ENCRYPTION_REQUEST => {
+                    let _server_id = reader.read_string().ok_or_else(malformed)?;
+                    let public_key = reader.read_byte_array().ok_or_else(malformed)?;
+                    let verify_token = reader.read_byte_array().ok_or_else(malformed)?;
+                    self.enable_encryption(public_key, verify_token).await?;
+                }
+                clientbound::SET_COMPRESSION => {
+                    self.compression_threshold = Some(reader.read_varint().ok_or_else(malformed)?);
+                }
+                clientbound::LOGIN_SUCCESS => {
+                    let uuid = reader.read_bytes(16).ok_or_else(malformed)?;
+                    self.uuid.copy_from_slice(uuid);
+                    self.username = reader.read_string().ok_or_else(malformed)?;
+                    self.write_packet(write_varint(serverbound::LOGIN_ACKNOWLEDGED))
+                        .await?;
+                    self.state = PlayerState::Configuration;
+                    return Ok(());
+                }
+                clientbound::LOGIN_DISCONNECT => {
+                    return Err(Error::new(
+                        ErrorKind::ConnectionRefused,
+                        format!(
+                            "Disconnected during login: {}",
+                            String::from_utf8_lossy(&data)
+                        ),
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn enable_encryption(
+        &mut self,
+        public_key: &[u8],
+        verify_token: &[u8],
+    ) -> std::io::Result<()> {
+        let public_key = rsa::RsaPublicKey::from_public_key_der(public_key)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        let shared_secret: [u8; 16] = rand::random();
+        let (encrypted_secret, encrypted_token) = {
+            let mut rng = rand::thread_rng();
+            let encrypted_secret = public_key
+                .encrypt(&mut rng, rsa::Pkcs1v15Encrypt, &shared_secret)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+            let encrypted_token = public_key
+                .encrypt(&mut rng, rsa::Pkcs1v15Encrypt, verify_token)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+            (encrypted_secret, encrypted_token)
+        };
+        let mut encryption_response = write_varint(serverbound::ENCRYPTION_RESPONSE);
+        encryption_response.extend(write_varint(encrypted_secret.len() as i32));
+        encryption_response.extend(encrypted_secret);
+        encryption_response.extend(write_varint(encrypted_token.len() as i32));
+        encryption_response.extend(encrypted_token);
+        self.write_packet(encryption_response).await?;
+
+        let mut encryptor = Crypter::new(
+            Cipher::aes_128_cfb8(),
+            Mode::Encrypt,
+            &shared_secret,
+            Some(&shared_secret),
+        )?;
+        encryptor.pad(false);
+        let mut decryptor = Crypter::new(
+            Cipher::aes_128_cfb8(),
+            Mode::Decrypt,
+            &shared_secret,
+            Some(&shared_secret),
+        )?;
+        decryptor.pad(false);
+        self.encryptor = Some(encryptor);
+        self.decryptor = Some(decryptor);
+        Ok(())
+    }
+
+    async fn configure(&mut self) -> std::io::Result<()> {
+        loop {
+            let (id, data) = self.read_packet().await?;
+            match id {
+                KnownPacks::CLIENTBOUND_ID => {
+                    // Claim to know every pack the server offered
+                    let mut known_packs_packet = write_varint(KnownPacks::SERVERBOUND_ID);
+                    known_packs_packet.extend(data);
+                    self.write_packet(known_packs_packet).await?;
+                }
+                RegistryData::CLIENTBOUND_ID => {
+                    if let Some(registry) = PacketReader::new(&data).read_string() {
+                        self.registries.push(registry);
+                    }
+                }
+                clientbound::CONFIG_KEEP_ALIVE => {
+                    let mut keep_alive_packet = write_varint(serverbound::CONFIG_KEEP_ALIVE);
+                    keep_alive_packet.extend(data);
+                    self.write_packet(keep_alive_packet).await?;
+                }
+                clientbound::CONFIG_PING => {
+                    let mut pong_packet = write_varint(serverbound::CONFIG_PONG);
+                    pong_packet.extend(data);
+                    self.write_packet(pong_packet).await?;
+                }
+                FinishConfig::CLIENTBOUND_ID => {
+                    self.write_packet(write_varint(FinishConfig::SERVERBOUND_ID))
+                        .await?;
+                    self.state = PlayerState::Play;
+                    return Ok(());
+                }
+                clientbound::CONFIG_DISCONNECT => {
+                    return Err(Error::new(
+                        ErrorKind::ConnectionAborted,
+                        "Disconnected during configuration",
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads play packets until one can be turned into an event
+    pub async fn next_event(&mut self) -> std::io::Result<ClientEvent> {
+        loop {
+            let (id, data) = self.read_packet().await?;
+            if let Some(event) = self.handle_packet(id, data).await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Decodes a play packet read with `read_packet` and answers it if needed
+    ///
+    /// Returns `None` for packets that carry no event such as bundle delimiters
+    pub async fn handle_packet(
+        &mut self,
+        id: i32,
+        data: Vec<u8>,
+    ) -> std::io::Result<Option<ClientEvent>> {
+        let mut reader = PacketReader::new(&data);
+        let event = match id {
+            clientbound::BUNDLE_DELIMITER => return Ok(None),
+            Login::CLIENTBOUND_ID => {
+                let entity_id = reader.read_i32().ok_or_else(malformed)?;
+                self.entity_id = Some(entity_id);
+                ClientEvent::Login { entity_id }
+            }
+            SynchronizePlayerPos::CLIENTBOUND_ID => {
+                let teleport_id = reader.read_varint().ok_or_else(malformed)?;
+                let x = reader.read_f64().ok_or_else(malformed)?;
+                let y = reader.read_f64().ok_or_else(malformed)?;
+                let z = reader.read_f64().ok_or_else(malformed)?;
+                self.position = Some((x, y, z));
+                let mut accept_packet = write_varint(serverbound::ACCEPT_TELEPORTATION);
+                accept_packet.extend(write_varint(teleport_id));
+                self.write_packet(accept_packet).await?;
+                let mut pos_rot_packet = write_varint(serverbound::MOVE_PLAYER_POS_ROT);
+                pos_rot_packet.extend(x.to_be_bytes());
+                pos_rot_packet.extend(y.to_be_bytes());
+                pos_rot_packet.extend(z.to_be_bytes());
+                pos_rot_packet.extend(0f32.to_be_bytes()); // yaw
+                pos_rot_packet.extend(0f32.to_be_bytes()); // pitch
+                pos_rot_packet.push(0x01); // on ground
+                self.write_packet(pos_rot_packet).await?;
+                ClientEvent::PositionSync {
+                    teleport_id,
+                    x,
+                    y,
+                    z,
+                }
+            }
+            ChunkLightData::CLIENTBOUND_ID => {
+                let chunk = ClientChunk::decode(&data).ok_or_else(malformed)?;
+                let (x, z) = (chunk.x, chunk.z);
+                self.chunks.insert((x, z), chunk);
+                ClientEvent::ChunkData { x, z }
+            }
+            UnloadChunks::CLIENTBOUND_ID => {
+                // Forget Chunk is sent as z then x
+                let z = reader.read_i32().ok_or_else(malformed)?;
+                let x = reader.read_i32().ok_or_else(malformed)?;
+                self.chunks.remove(&(x, z));
+                ClientEvent::ChunkUnload { x, z }
+            }
+            SetCenterChunk::CLIENTBOUND_ID => {
+                let x = reader.read_varint().ok_or_else(malformed)?;
+                let z = reader.read_varint().ok_or_else(malformed)?;
+                self.center_chunk = Some((x, z));
+                ClientEvent::CenterChunk { x, z }
+            }
+            KeepAlive::CLIENTBOUND_ID => {
+                let mut keep_alive_packet = write_varint(serverbound::PLAY_KEEP_ALIVE);
+                keep_alive_packet.extend(&data);
+                self.write_packet(keep_alive_packet).await?;
+                ClientEvent::KeepAlive {
+                    id: reader.read_i64().ok_or_else(malformed)?,
+                }
+            }
+            GameEvent::CLIENTBOUND_ID => ClientEvent::GameEvent {
+                event: reader.read_u8().ok_or_else(malformed)?,
+                value: reader.read_f32().ok_or_else(malformed)?,
+            },
+            PlayerInfoUpdate::CLIENTBOUND_ID
+                if reader.read_u8() == Some(PlayerInfoUpdate::UPDATE_LATENCY) =>
+            {
+                let mut latency = None;
+                for _ in 0..reader.read_varint().ok_or_else(malformed)? {
+                    let uuid = reader.read_bytes(16).ok_or_else(malformed)?;
+                    let millis = reader.read_varint().ok_or_else(malformed)?;
+                    if uuid == self.uuid {
+                        latency = Some(millis);
+                    }
+                }
+                match latency {
+                    Some(millis) => ClientEvent::Latency { millis },
+                    None => ClientEvent::Other { id, data },
+                }
+            }
+            clientbound::PONG_RESPONSE => ClientEvent::Pong {
+                id: reader.read_i64().ok_or_else(malformed)?,
+            },
+            clientbound::PLAY_DISCONNECT => ClientEvent::Disconnect { reason: data },
+            _ => ClientEvent::Other { id, data },
+        };
+        Ok(Some(event))
+    }
+
+    /// Sends a ping request, the server answers with a `ClientEvent::Pong` carrying the same id
+    pub async fn ping(&mut self, id: i64) -> std::io::Result<()> {
+        let mut ping_packet = write_varint(serverbound::PING_REQUEST);
+        ping_packet.extend(id.to_be_bytes());
+        self.write_packet(ping_packet).await
+    }
+
+    /// Reads events until `predicate` returns true or the timeout elapses
+    pub async fn wait_for<F: FnMut(&Self, &ClientEvent) -> bool>(
+        &mut self,
+        timeout: std::time::Duration,
+        mut predicate: F,
+    ) -> std::io::Result<ClientEvent> {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let event = self.next_event().await?;
+                if predicate(self, &event) {
+                    return Ok(event);
+                }
+            }
+        })
+        .await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "Timed out waiting for event"))?
+    }
+
+    /// Moves the player to the given position
+    pub async fn move_to(&mut self, x: f64, y: f64, z: f64) -> std::io::Result<()> {
+        let mut pos_packet = write_varint(serverbound::MOVE_PLAYER_POS);
+        pos_packet.extend(x.to_be_bytes());
+        pos_packet.extend(y.to_be_bytes());
+        pos_packet.extend(z.to_be_bytes());
+        pos_packet.push(0x01); // on ground
+        self.write_packet(pos_packet).await?;
+        self.position = Some((x, y, z));
+        Ok(())
+    }
+
+    /// Writes a packet (id + data), compressing and encrypting it when enabled
+    pub async fn write_packet(&mut self, mut packet: Vec<u8>) -> std::io::Result<()> {
+        if let Some(threshold) = self.compression_threshold {
+            if threshold >= 0 && packet.len() >= threshold as usize {
+                let data_len = packet.len() as i32;
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&packet)?;
+                packet = encoder.finish()?;
+                packet.splice(0..0, write_varint(data_len));
+            } else {
+                packet.splice(0..0, write_varint(0));
+            }
+        }
+        packet.splice(0..0, write_varint(packet.len() as i32));
+        if let Some(encryptor) = &mut self.encryptor {
+            let mut encrypted = vec![0u8; packet.len()];
+            encryptor.update(&packet, &mut encrypted)?;
+            packet = encrypted;
+        }
+        self.socket.write_all(&packet).await
+    }
+
+    /// Reads the next packet and returns its id and data
+    ///
+    /// Cancel safe, partially received packets are kept until the next call
+    pub async fn read_packet(&mut self) -> std::io::Result<(i32, Vec<u8>)> {
+        let mut packet = loop {
+            if let Some((packet_len, len_bytes)) =
+                peek_varint_and_len_from_slice(&self.pending_data)
+            {
+                let total_len = packet_len as usize + len_bytes;
+                if self.pending_data.len() >= total_len {
+                    let packet = self.pending_data[len_bytes..total_len].to_vec();
+                    self.pending_data.drain(..total_len);
+                    break packet;
+                }
+            }
+            let n = self.socket.read(&mut self.read_buf).await?;
+            if n == 0 {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Server closed the connection",
+                ));
+            }
+            if let Some(decryptor) = &mut self.decryptor {
+                let decrypted_n = decryptor.update(&self.read_buf[..n], &mut self.decrypted_buf)?;
+                self.pending_data
+                    .extend_from_slice(&self.decrypted_buf[..decrypted_n]);
+            } else {
+                self.pending_data.extend_from_slice(&self.read_buf[..n]);
+            }
+        };
+        if self.compression_threshold.is_some() {
+            let (data_len, len_bytes) =
+                peek_varint_and_len_from_slice(&packet).ok_or_else(malformed)?;
+            packet.drain(..len_bytes);
+            if data_len != 0 {
+                let mut decompressed = Vec::with_capacity(data_len as usize);
+                ZlibDecoder::new(packet.as_slice()).read_to_end(&mut decompressed)?;
+                packet = decompressed;
+            }
+        }
+        let (id, id_len) = peek_varint_and_len_from_slice(&packet).ok_or_else(malformed)?;
+        packet.drain(..id_len);
+        Ok((id, packet))
+    }
+}
+
+fn malformed() -> Error {
+    Error::new(ErrorKind::InvalidData, "Malformed packet")
+}
+
+/// Cursor over packet data
+pub struct PacketReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PacketReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Some(array)
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        Some(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_i16(&mut self) -> Option<i16> {
+        Some(i16::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_i64(&mut self) -> Option<i64> {
+        Some(i64::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> Option<f32> {
+        Some(f32::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_f64(&mut self) -> Option<f64> {
+        Some(f64::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_varint(&mut self) -> Option<i32> {
+        let (value, len) =
+            peek_varint_and_len_from_slice(&self.data[self.position.min(self.data.len())..])?;
+        self.position += len;
+        Some(value)
+    }
+
+    pub fn read_byte_array(&mut self) -> Option<&'a [u8]> {
+        let len = self.read_varint()?;
+        self.read_bytes(usize::try_from(len).ok()?)
+    }
+
+    pub fn read_string(&mut self) -> Option<String> {
+        Some(String::from_utf8_lossy(self.read_byte_array()?).to_string())
+    }
+
+    /// Reads a varint prefixed array of longs
+    pub fn read_long_array(&mut self) -> Option<Vec<i64>> {
+        let len = self.read_varint()?;
+        (0..len).map(|_| self.read_i64()).collect()
+    }
+
+    /// Skips a nameless network nbt compound
+    pub fn skip_nbt(&mut self) -> Option<()> {
+        let tag = self.read_u8()?;
+        self.skip_nbt_payload(tag)
+    }
+
+    fn skip_nbt_payload(&mut self, tag: u8) -> Option<()> {
+        match tag {
+            0 => {}
+            1 => {
+                self.read_bytes(1)?;
+            }
+            2 => {
+                self.read_bytes(2)?;
+            }
+            3 | 5 => {
+                self.read_bytes(4)?;
+            }
+            4 | 6 => {
+                self.read_bytes(8)?;
+            }
+            7 => {
+                let len = self.read_i32()?;
+                self.read_bytes(usize::try_from(len).ok()?)?;
+            }
+            8 => {
+                let len = u16::from_be_bytes(self.read_array()?);
+                self.read_bytes(len as usize)?;
+            }
+            9 => {
+                let list_tag = self.read_u8()?;
+                for _ in 0..self.read_i32()? {
+                    self.skip_nbt_payload(list_tag)?;
+                }
+            }
+            10 => loop {
+                let field_tag = self.read_u8()?;
+                if field_tag == 0 {
+                    break;
+                }
+                let name_len = u16::from_be_bytes(self.read_array()?);
+                self.read_bytes(name_len as usize)?;
+                self.skip_nbt_payload(field_tag)?;
+            },
+            11 => {
+                let len = self.read_i32()?;
+                self.read_bytes(usize::try_from(len).ok()?.checked_mul(4)?)?;
+            }
+            12 => {
+                let len = self.read_i32()?;
+                self.read_bytes(usize::try_from(len).ok()?.checked_mul(8)?)?;
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
@@ -0,0 +1,214 @@
+#![feature(thread_id_value)]
+pub mod client;
+pub mod entities;
+pub mod packets;
+pub mod player;
+pub mod server;
+use packets::configuration::*;
+use packets::handshake::HandshakeState;
+use packets::play::*;
+use packets::{Packet, PacketStatic};
+use player::{PlayerState, PlayerStream};
+use rsa::{pkcs8::EncodePublicKey, RsaPrivateKey};
+use server::chunk_system::LudiChunkLoader;
+use server::logger::{LogDomain, LogLevel, LOGGER};
+pub use server::randomness::{RandomGenerator, RandomPositionalGenerator};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+pub const MC_VERSION: &str = "1.21.6";
+pub const MC_PROTOCOL: usize = 771;
+/// Data version written to saved chunks
+pub const MC_DATA_VERSION: i32 = 4435;
+pub const JAR_RESOURCES_DIR: &str = "versions/1.21.6/minecraft";
+/// The preset found in "versions/[version]/minecraft/worldgen/world_preset/"
+pub const WORLD_PRESET: &str = "normal";
+/// The density function to evaluate for dimension terrain generation
+pub const MAIN_DENSITY_FUNCTION: &str = "final_density";
+pub const MAX_BLOCKSTATES: usize = 27_946;
+
+/// Handles a single connection from handshake until the client disconnects
+pub async fn handle_client(mut player_stream: PlayerStream, server_data: Arc<server::ServerData>) {
+    let mut handshake_state = HandshakeState::Status;
+    // Handle handshake or begin Login process
+    packets::handshake::Handshake::new(&mut handshake_state)
+        .handle(&mut player_stream)
+        .await;
+    // Hold Player Packet tx until end of function to prevent closing rx
+    let ((tx, mut rx), (_low_tx, mut low_rx)) = match handshake_state {
+        HandshakeState::Status => {
+            packets::status::Status::new()
+                .handle(&mut player_stream)
+                .await;
+            packets::status::Ping::new()
+                .handle(&mut player_stream)
+                .await;
+            return;
+        }
+        HandshakeState::Login => {
+            // Start Login Stage and Initialize Player Packet Send Queue
+            let mut login_pkt = packets::login::LoginStart::new(server_data.clone());
+            login_pkt.handle(&mut player_stream).await;
+            (
+                login_pkt.high_channel.take().unwrap(),
+                login_pkt.low_channel.take().unwrap(),
+            )
+        }
+    };
+    // Enable Encryption
+    packets::login::Encryption::new()
+        .handle(&mut player_stream)
+        .await;
+    // Enable Compression
+    if server_data.config.network_compression_threshold > -1 {
+        packets::login::SetCompression::new(server_data.config.network_compression_threshold)
+            .handle(&mut player_stream)
+            .await;
+    }
+
+    // Register player with server
+    let player_data = player_stream.read.data.clone().unwrap().clone();
+    server_data.add_player(player_data.clone());
+    LOGGER.get().unwrap().println(&format!(
+        "Registered player {} ({}) with entity id {}",
+        player_data.username,
+        player_data.get_uuid_string(),
+        player_data.id
+    ));
+
+    // Add Authentication
+
+    // Start Configuration Stage
+    packets::login::LoginSuccess::new()
+        .handle(&mut player_stream)
+        .await;
+
+    if player_data.state.load(Ordering::Relaxed) != PlayerState::Configuration as u8 {
+        return;
+    }
+    let (mut player_read, mut player_write) = player_stream.split();
+
+    // Player Packet Sender
+    tokio::spawn({
+        async move {
+            // Send Configuration Packets
+            let initial_config_packets = vec![
+                PluginMessage::CLIENTBOUND_ID,
+                FeatureFlags::CLIENTBOUND_ID,
+                KnownPacks::CLIENTBOUND_ID,
+                RegistryData::CLIENTBOUND_ID,
+                FinishConfig::CLIENTBOUND_ID,
+            ];
+
+            for pkt in initial_config_packets {
+                packets::configuration::InitialConfigurationPackets::match_and_send(
+                    pkt,
+                    &mut player_write,
+                )
+                .await;
+            }
+
+            // Listen for packets to send
+            loop {
+                tokio::select! {
+                    biased;
+                    Some(mut pkt) = rx.recv() => pkt.send(&mut player_write).await,
+                    Some(mut pkt) = low_rx.recv() => pkt.send(&mut player_write).await,
+                    else => break,
+                }
+            }
+        }
+    });
+
+    // Player Packet Receiver
+    loop {
+        let packet = player_read.decrypt_packet().await;
+        if packet.is_none() {
+            break;
+        }
+        let mut packet = packet.unwrap();
+        let id = packets::read_varint_from_vec(&mut packet);
+        if let Some(id) = id {
+            let state = PlayerState::from_u8(player_data.state.load(Ordering::Relaxed)).unwrap();
+            match state {
+                PlayerState::Configuration => {
+                    if id == FinishConfig::SERVERBOUND_ID {
+                        player_data
+                            .state
+                            .store(PlayerState::Play as u8, Ordering::Relaxed);
+                        // Send Play Packets
+                        let initial_login_packets = vec![
+                            Login::new(server_data.clone()).into(),
+                            SynchronizePlayerPos::new().into(),
+                            PlayerInfoUpdate::new().into(),
+                        ];
+
+                        for pkt in initial_login_packets {
+                            if tx.send(pkt).await.is_err() {
+                                break;
+                            }
+                        }
+                        server_data.load_init_chunks(player_data.clone(), 2).await;
+                        let _ = tx.send(GameEvent::new(13, 0.0).into()).await;
+                        server_data
+                            .load_init_chunks(
+                                player_data.clone(),
+                                player_data.server.config.view_distance,
+                            )
+                            .await;
+                        let _ = tx
+                            .send(PlayerAbilities::new(0b00000110, 3.0, 0.0).into())
+                            .await;
+                        let player = player_data.clone();
+                        let server = server_data.clone();
+                        tokio::spawn(async move {
+                            while let Some(tx) = player.outbound.upgrade() {
+                                let _ = tx.send(KeepAlive::new(server.clone()).into()).await;
+                                tokio::time::sleep(tokio::time::Duration::from_secs(15)).await;
+                            }
+                        });
+                    }
+                }
+                PlayerState::Play => {
+                    packets::play::ReceivablePlayPackets::match_and_receive(
+                        id,
+                        &packet,
+                        &mut player_read,
+                    )
+                    .await;
+                }
+                _ => {
+                    // Disconnect if invalid state
+                    break;
+                }
+            }
+        }
+    }
+    server_data.remove_player(player_data.id);
+    LOGGER.get().unwrap().println_as(
+        &format!(
+            "Disconnected player {} ({})",
+            player_data.username,
+            player_data.get_uuid_string()
+        ),
+        LogDomain::Network,
+        LogLevel::Info,
+    );
+}
+
+/// Generates the RSA key pair used for the encryption request
+pub fn generate_server_encryption() {
+    let mut rng = rand::thread_rng();
+    let private_key = RsaPrivateKey::new(&mut rng, 1024).unwrap();
+    let public_key = private_key
+        .to_public_key()
+        .to_public_key_der()
+        .unwrap()
+        .as_bytes()
+        .to_vec();
+
+    let _ = packets::ENCRYPTION_DATA.set(packets::EncryptionData {
+        public_key,
+        private_key,
+    });
+}
@@ -1,36 +1,29 @@
-#![feature(thread_id_value)]
-mod entities;
-mod packets;
-mod player;
-mod server;
-use crate::server::terrain_gen::noise_generator::initialize_noise_instances;
-use packets::configuration::*;
-use packets::handshake::HandshakeState;
-use packets::play::*;
-use packets::{Packet, PacketStatic};
-use player::{PlayerState, PlayerStream};
-use rsa::{pkcs8::EncodePublicKey, RsaPrivateKey};
-use server::chunk_system::LudiChunkLoader;
-use server::logger::{LogDomain, LogLevel, ServerLogger, LOGGER};
-use server::randomness::{RandomGenerator, RandomPositionalGenerator};
+use ludicrous::server::logger::{LogDomain, LogLevel, ServerLogger, LOGGER};
+use ludicrous::server::terrain_gen::noise_generator::initialize_noise_instances;
+use ludicrous::{generate_server_encryption, handle_client, packets, player, server, MC_VERSION};
+use player::PlayerStream;
 use std::fs::{create_dir_all, File};
 use std::io::{Read, Write};
-use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tokio::net::TcpListener;
 
-pub const MC_VERSION: &str = "1.21.6";
-pub const MC_PROTOCOL: usize = 771;
-pub const JAR_RESOURCES_DIR: &str = "versions/1.21.6/minecraft";
-/// The preset found in "versions/[version]/minecraft/worldgen/world_preset/"
-pub const WORLD_PRESET: &str = "normal";
-/// The density function to evaluate for dimension terrain generation
-pub const MAIN_DENSITY_FUNCTION: &str = "final_density";
-pub const MAX_BLOCKSTATES: usize = 27_946;
-
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> std::io::Result<()> {
     LOGGER.get_or_init(ServerLogger::new);
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(|a| a.as_str()) == Some("capture") {
+        return run_capture_command(&args[2..]).await;
+    }
+    let pregen = match args.get(1).map(|a| a.as_str()) {
+        Some("pregen") => match parse_pregen_args(&args[2..]) {
+            Some(pregen) => Some(pregen),
+            None => {
+                println!("Usage: ludicrous pregen --radius <chunks> [--dimension <dimension>]");
+                return Ok(());
+            }
+        },
+        _ => None,
+    };
     LOGGER
         .get()
         .unwrap()
@@ -44,6 +37,9 @@ async fn main() -> std::io::Result<()> {
         &server::terrain_gen::func_deserialize::EXTERNAL_DENSITY_FUNCTIONS,
     );
     initialize_noise_instances();
+    if let Some((dimension, radius)) = pregen {
+        return run_pregen(server, dimension, radius).await;
+    }
 
     LOGGER.get().unwrap().println("Loading Server Mappings...");
     let time = std::time::Instant::now();
@@ -55,6 +51,11 @@ async fn main() -> std::io::Result<()> {
         "Finished Loading Server Mappings! ({:?})",
         std::time::Instant::now().duration_since(time)
     ));
+    if server.config.enable_metrics {
+        tokio::spawn(server::metrics::ServerMetrics::serve(
+            server.config.metrics_port,
+        ));
+    }
     let listener = TcpListener::bind(format!("0.0.0.0:{}", server.config.server_port)).await?;
     LOGGER.get().unwrap().println_as(
         &format!(
@@ -66,180 +67,84 @@ async fn main() -> std::io::Result<()> {
         LogLevel::Info,
     );
     loop {
-        let (socket, _addr) = listener.accept().await?;
+        let (socket, addr) = listener.accept().await?;
         socket.set_nodelay(true)?;
+        let mut player_stream = PlayerStream::new(socket);
+        if server.config.packet_capture {
+            match packets::capture::PacketRecorder::new(&addr.to_string()) {
+                Ok(recorder) => player_stream.set_capture(Arc::new(recorder)),
+                Err(e) => LOGGER.get().unwrap().println_as(
+                    &format!("Could not start packet capture for {addr}: {e}"),
+                    LogDomain::Network,
+                    LogLevel::Error,
+                ),
+            }
+        }
 
-        tokio::spawn(handle_client(socket, server.clone()));
+        tokio::spawn(handle_client(player_stream, server.clone()));
     }
 }
 
-async fn handle_client(socket: tokio::net::TcpStream, server_data: Arc<server::ServerData>) {
-    let mut handshake_state = HandshakeState::Status;
-    let mut player_stream = PlayerStream::new(socket);
-    // Handle handshake or begin Login process
-    packets::handshake::Handshake::new(&mut handshake_state)
-        .handle(&mut player_stream)
-        .await;
-    // Hold Player Packet tx until end of function to prevent closing rx
-    let ((tx, mut rx), (_low_tx, mut low_rx)) = match handshake_state {
-        HandshakeState::Status => {
-            packets::status::Status::new()
-                .handle(&mut player_stream)
-                .await;
-            packets::status::Ping::new()
-                .handle(&mut player_stream)
-                .await;
-            return;
+/// Handles `ludicrous capture print <file>` and `ludicrous capture replay <file> [port]`
+async fn run_capture_command(args: &[String]) -> std::io::Result<()> {
+    match (args.first().map(|a| a.as_str()), args.get(1)) {
+        (Some("print"), Some(file)) => packets::capture::print_capture(file),
+        (Some("replay"), Some(file)) => {
+            let port = args
+                .get(2)
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(25565);
+            packets::capture::replay_capture(file, port).await
         }
-        HandshakeState::Login => {
-            // Start Login Stage and Initialize Player Packet Send Queue
-            let mut login_pkt = packets::login::LoginStart::new(server_data.clone());
-            login_pkt.handle(&mut player_stream).await;
-            (
-                login_pkt.high_channel.take().unwrap(),
-                login_pkt.low_channel.take().unwrap(),
-            )
+        _ => {
+            println!("Usage: ludicrous capture print <file>");
+            println!("       ludicrous capture replay <file> [port]");
+            Ok(())
         }
-    };
-    // Enable Encryption
-    packets::login::Encryption::new()
-        .handle(&mut player_stream)
-        .await;
-    // Enable Compression
-    if server_data.config.network_compression_threshold > -1 {
-        packets::login::SetCompression::new(server_data.config.network_compression_threshold)
-            .handle(&mut player_stream)
-            .await;
-    }
-
-    // Register player with server
-    let player_data = player_stream.read.data.clone().unwrap().clone();
-    server_data.add_player(player_data.clone());
-    LOGGER.get().unwrap().println(&format!(
-        "Registered player {} ({}) with entity id {}",
-        player_data.username,
-        player_data.get_uuid_string(),
-        player_data.id
-    ));
-
-    // Add Authentication
-
-    // Start Configuration Stage
-    packets::login::LoginSuccess::new()
-        .handle(&mut player_stream)
-        .await;
-
-    if player_data.state.load(Ordering::Relaxed) != PlayerState::Configuration as u8 {
-        return;
     }
-    let (mut player_read, mut player_write) = player_stream.split();
-
-    // Player Packet Sender
-    tokio::spawn({
-        async move {
-            // Send Configuration Packets
-            let initial_config_packets = vec![
-                PluginMessage::CLIENTBOUND_ID,
-                FeatureFlags::CLIENTBOUND_ID,
-                KnownPacks::CLIENTBOUND_ID,
-                RegistryData::CLIENTBOUND_ID,
-                FinishConfig::CLIENTBOUND_ID,
-            ];
-
-            for pkt in initial_config_packets {
-                packets::configuration::InitialConfigurationPackets::match_and_send(
-                    pkt,
-                    &mut player_write,
-                )
-                .await;
-            }
+}
 
-            // Listen for packets to send
-            loop {
-                tokio::select! {
-                    biased;
-                    Some(mut pkt) = rx.recv() => pkt.send(&mut player_write).await,
-                    Some(mut pkt) = low_rx.recv() => pkt.send(&mut player_write).await,
-                    else => break,
-                }
+/// Parses `--radius <chunks> [--dimension <dimension>]`, the overworld is pregenerated by default
+fn parse_pregen_args(args: &[String]) -> Option<(server::Dimension, i32)> {
+    let mut dimension = server::Dimension::Overworld;
+    let mut radius = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--radius" => {
+                let parsed = args.next()?.parse().ok();
+                radius = Some(parsed.filter(|r| server::pregen::chunk_count(*r).is_some())?)
             }
+            "--dimension" => dimension = server::Dimension::from_name(args.next()?)?,
+            _ => return None,
         }
-    });
-
-    // Player Packet Receiver
-    loop {
-        let packet = player_read.decrypt_packet().await;
-        if packet.is_none() {
-            break;
-        }
-        let mut packet = packet.unwrap();
-        let id = packets::read_varint_from_vec(&mut packet);
-        if let Some(id) = id {
-            let state = PlayerState::from_u8(player_data.state.load(Ordering::Relaxed)).unwrap();
-            match state {
-                PlayerState::Configuration => {
-                    if id == FinishConfig::SERVERBOUND_ID {
-                        player_data
-                            .state
-                            .store(PlayerState::Play as u8, Ordering::Relaxed);
-                        // Send Play Packets
-                        let initial_login_packets = vec![
-                            Login::new(server_data.clone()).into(),
-                            SynchronizePlayerPos::new().into(),
-                            PlayerInfoUpdate::new().into(),
-                        ];
+    }
+    Some((dimension, radius?))
+}
 
-                        for pkt in initial_login_packets {
-                            if tx.send(pkt).await.is_err() {
-                                break;
-                            }
-                        }
-                        server_data.load_init_chunks(player_data.clone(), 2).await;
-                        let _ = tx.send(GameEvent::new(13, 0.0).into()).await;
-                        server_data
-                            .load_init_chunks(
-                                player_data.clone(),
-                                player_data.server.config.view_distance,
-                            )
-                            .await;
-                        let _ = tx
-                            .send(PlayerAbilities::new(0b00000110, 3.0, 0.0).into())
-                            .await;
-                        let player = player_data.clone();
-                        let server = server_data.clone();
-                        tokio::spawn(async move {
-                            while let Some(tx) = player.outbound.upgrade() {
-                                let _ = tx.send(KeepAlive::new(server.clone()).into()).await;
-                                tokio::time::sleep(tokio::time::Duration::from_secs(15)).await;
-                            }
-                        });
-                    }
-                }
-                PlayerState::Play => {
-                    packets::play::ReceivablePlayPackets::match_and_receive(
-                        id,
-                        &packet,
-                        &mut player_read,
-                    )
-                    .await;
-                }
-                _ => {
-                    // Disconnect if invalid state
-                    break;
-                }
-            }
-        }
+/// Handles `ludicrous pregen`, generates the chunks around the center of the dimension and exits
+async fn run_pregen(
+    server: Arc<server::ServerData>,
+    dimension: server::Dimension,
+    radius: i32,
+) -> std::io::Result<()> {
+    let center = server::pregen::pregen_center(&server, dimension);
+    let result = server::pregen::pregenerate(server, dimension, center, radius, |progress| {
+        LOGGER.get().unwrap().println(&progress.describe())
+    })
+    .await;
+    match result {
+        Ok(progress) => LOGGER.get().unwrap().println(&format!(
+            "Pregenerated {} chunks in {:?}",
+            progress.done - progress.skipped,
+            progress.elapsed
+        )),
+        Err(e) => LOGGER
+            .get()
+            .unwrap()
+            .println_as(&e, LogDomain::Server, LogLevel::Error),
     }
-    server_data.remove_player(player_data.id);
-    LOGGER.get().unwrap().println_as(
-        &format!(
-            "Disconnected player {} ({})",
-            player_data.username,
-            player_data.get_uuid_string()
-        ),
-        LogDomain::Network,
-        LogLevel::Info,
-    );
+    Ok(())
 }
 
 fn process_eula() {
@@ -289,8 +194,20 @@ eula=false
     std::process::exit(1);
 }
 
+/// Folders of the vanilla data pack extracted from the client jar
+const EXTRACTED_FOLDERS: [&str; 5] = [
+    "worldgen",
+    "dimension_type",
+    "tags/block",
+    "tags/worldgen/biome",
+    "structure",
+];
+
 async fn download_game_assets() -> Result<(), reqwest::Error> {
-    if std::fs::read_dir(format!("versions/{MC_VERSION}")).is_ok() {
+    // Older installs lack the folders added since, so every folder must be present
+    if EXTRACTED_FOLDERS.iter().all(|folder| {
+        std::fs::read_dir(format!("versions/{MC_VERSION}/minecraft/{folder}")).is_ok()
+    }) {
         return Ok(());
     }
     let manifest_res: serde_json::Value =
@@ -327,18 +244,14 @@ async fn download_game_assets() -> Result<(), reqwest::Error> {
         .println(&format!("Retrieving game assets from {asset_url}"));
     let jar = std::io::Cursor::new(reqwest::get(asset_url).await?.bytes().await?.to_vec());
     let mut archive = zip::ZipArchive::new(jar).unwrap();
-    let worldgen_output = "versions/".to_owned() + MC_VERSION + "/minecraft/worldgen";
-    get_folder_from_archive(
-        &mut archive,
-        "data/minecraft/worldgen",
-        std::path::Path::new(&worldgen_output),
-    );
-    let dim_type_output = "versions/".to_owned() + MC_VERSION + "/minecraft/dimension_type";
-    get_folder_from_archive(
-        &mut archive,
-        "data/minecraft/dimension_type",
-        std::path::Path::new(&dim_type_output),
-    );
+    for folder in EXTRACTED_FOLDERS {
+        let output = format!("versions/{MC_VERSION}/minecraft/{folder}");
+        get_folder_from_archive(
+            &mut archive,
+            &format!("data/minecraft/{folder}"),
+            std::path::Path::new(&output),
+        );
+    }
     Ok(())
 }
 
@@ -363,25 +276,9 @@ fn get_folder_from_archive(
                 if let Some(parent_path) = entry_path.parent() {
                     create_dir_all(parent_path).unwrap();
                 }
-                let mut file = File::create_new(entry_path).unwrap();
+                let mut file = File::create(entry_path).unwrap();
                 std::io::copy(&mut entry, &mut file).unwrap();
             }
         }
     }
 }
-
-fn generate_server_encryption() {
-    let mut rng = rand::thread_rng();
-    let private_key = RsaPrivateKey::new(&mut rng, 1024).unwrap();
-    let public_key = private_key
-        .to_public_key()
-        .to_public_key_der()
-        .unwrap()
-        .as_bytes()
-        .to_vec();
-
-    let _ = packets::ENCRYPTION_DATA.set(packets::EncryptionData {
-        public_key,
-        private_key,
-    });
-}
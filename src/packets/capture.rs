@@ -0,0 +1,542 @@
+//! Packet Capture
+//! Records the decrypted and decompressed packets of a session to a file so a desync can be
+//! inspected or reproduced offline with `ludicrous capture print|replay <file>`
+//!
+//! # File Format
+//! * magic: `LUDICAP1`
+//! * protocol: `u32`
+//! * records until EOF:
+//!     * time since session start in microseconds: `u64`
+//!     * direction: `u8` (0 = serverbound, 1 = clientbound)
+//!     * connection state: `u8` (`PlayerState as u8`)
+//!     * payload length: `u32`
+//!     * payload: packet id as varint followed by the packet data
+use super::super::player::PlayerState;
+use super::super::server::logger::{LogDomain, LogLevel, LOGGER};
+use super::{peek_varint_and_len_from_slice, read_varint_from_vec, write_string, write_varint};
+use crate::{MC_PROTOCOL, MC_VERSION};
+use flate2::read::ZlibDecoder;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const CAPTURE_MAGIC: &[u8; 8] = b"LUDICAP1";
+pub const CAPTURE_DIR: &str = "captures";
+/// Largest packet length the protocol allows, 3 bytes of varint
+const MAX_PACKET_LEN: i32 = (1 << 21) - 1;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PacketDirection {
+    Serverbound,
+    Clientbound,
+}
+
+#[derive(Debug)]
+pub struct CapturedPacket {
+    pub time_micros: u64,
+    pub direction: PacketDirection,
+    pub state: u8,
+    pub data: Vec<u8>,
+}
+
+/// Shared by the read and write half of a connection. Records are written to the file by a
+/// separate thread so connections never wait for the disk
+pub struct PacketRecorder {
+    start_time: std::time::Instant,
+    records: Option<Sender<Vec<u8>>>,
+    writer: Option<JoinHandle<()>>,
+    /// Formatted clientbound data that has not formed a full packet yet
+    /// (See `PlayerWriteConn::write_packet_data`)
+    pending_formatted: Mutex<Vec<u8>>,
+}
+
+impl PacketRecorder {
+    pub fn new(session_name: &str) -> std::io::Result<Self> {
+        std::fs::create_dir_all(CAPTURE_DIR)?;
+        let time = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
+        let session_name = session_name.replace([':', '/', '\\', '[', ']'], "_");
+        Self::create(&format!("{CAPTURE_DIR}/{time}_{session_name}.ludicap"))
+    }
+
+    /// Starts a capture in the given file
+    pub fn create(path: &str) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(CAPTURE_MAGIC)?;
+        file.write_all(&(MC_PROTOCOL as u32).to_be_bytes())?;
+        let (records, receiver) = std::sync::mpsc::channel();
+        let writer = std::thread::Builder::new()
+            .name("packet-capture".to_string())
+            .spawn(move || write_records(file, receiver))?;
+        Ok(Self {
+            start_time: std::time::Instant::now(),
+            records: Some(records),
+            writer: Some(writer),
+            pending_formatted: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Records an uncompressed and decrypted packet (id + data)
+    pub fn record(&self, direction: PacketDirection, state: u8, packet: &[u8]) {
+        let time = self.start_time.elapsed().as_micros() as u64;
+        let mut record = Vec::with_capacity(packet.len() + 14);
+        record.extend(time.to_be_bytes());
+        record.push(direction as u8);
+        record.push(state);
+        record.extend((packet.len() as u32).to_be_bytes());
+        record.extend(packet);
+        if let Some(records) = &self.records {
+            // Fails only once the writer stopped after an error, which it already logged
+            let _ = records.send(record);
+        }
+    }
+
+    /// Records clientbound data that was already formatted with a length header and possibly
+    /// compressed. Data may be given in multiple parts, so it is buffered until a full packet
+    /// is available
+    pub fn record_formatted(&self, state: u8, compression_enabled: bool, data: &[u8]) {
+        let mut pending = self.pending_formatted.lock().unwrap();
+        pending.extend_from_slice(data);
+        while let Some((packet_len, len_bytes)) = peek_varint_and_len_from_slice(&pending) {
+            let total_len = packet_len as usize + len_bytes;
+            if pending.len() < total_len {
+                break;
+            }
+            let mut packet = pending[len_bytes..total_len].to_vec();
+            pending.drain(..total_len);
+            if compression_enabled {
+                let Some(data_len) = read_varint_from_vec(&mut packet) else {
+                    continue;
+                };
+                if data_len != 0 {
+                    let mut decompressed = Vec::with_capacity(data_len as usize);
+                    if ZlibDecoder::new(packet.as_slice())
+                        .read_to_end(&mut decompressed)
+                        .is_err()
+                    {
+                        continue;
+                    }
+                    packet = decompressed;
+                }
+            }
+            self.record(PacketDirection::Clientbound, state, &packet);
+        }
+    }
+}
+
+impl Drop for PacketRecorder {
+    /// Closes the capture, the writer flushes the remaining records on its own. Outside of the
+    /// async runtime this waits until they are written
+    fn drop(&mut self) {
+        self.records.take();
+        if let Some(writer) = self.writer.take() {
+            if tokio::runtime::Handle::try_current().is_err() {
+                let _ = writer.join();
+            }
+        }
+    }
+}
+
+/// Writes records to a capture until every sender is dropped. The file is flushed whenever no
+/// record is queued, so captures are still usable if the server is stopped abruptly
+fn write_records(mut file: BufWriter<File>, records: Receiver<Vec<u8>>) {
+    let error = loop {
+        let record = match records.try_recv() {
+            Ok(record) => record,
+            Err(TryRecvError::Empty) => {
+                if let Err(e) = file.flush() {
+                    break Some(e);
+                }
+                match records.recv() {
+                    Ok(record) => record,
+                    Err(_) => break None,
+                }
+            }
+            Err(TryRecvError::Disconnected) => break None,
+        };
+        if let Err(e) = file.write_all(&record) {
+            break Some(e);
+        }
+    };
+    if let Some(e) = error.or_else(|| file.flush().err()) {
+        LOGGER.get().unwrap().println_as(
+            &format!("Error writing packet capture: {e}"),
+            LogDomain::Network,
+            LogLevel::Error,
+        );
+    }
+}
+
+/// Reads every packet stored in a capture file
+pub fn read_capture(path: &str) -> std::io::Result<Vec<CapturedPacket>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != CAPTURE_MAGIC {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{path} is not a packet capture"),
+        ));
+    }
+    let mut protocol = [0u8; 4];
+    reader.read_exact(&mut protocol)?;
+    let protocol = u32::from_be_bytes(protocol);
+    if protocol as usize != MC_PROTOCOL {
+        LOGGER.get().unwrap().println_as(
+            &format!("Capture was recorded with protocol {protocol}, expected {MC_PROTOCOL}"),
+            LogDomain::Network,
+            LogLevel::Warn,
+        );
+    }
+    let mut packets = Vec::new();
+    let mut record_header = [0u8; 14];
+    loop {
+        match reader.read_exact(&mut record_header) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let mut time = [0u8; 8];
+        time.copy_from_slice(&record_header[..8]);
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&record_header[10..14]);
+        // Read without allocating the length up front, it is only trusted once the data is there
+        let len = u32::from_be_bytes(len) as usize;
+        let mut data = Vec::new();
+        if (&mut reader).take(len as u64).read_to_end(&mut data)? < len {
+            // Truncated capture, keep what was read
+            break;
+        }
+        packets.push(CapturedPacket {
+            time_micros: u64::from_be_bytes(time),
+            direction: if record_header[8] == 0 {
+                PacketDirection::Serverbound
+            } else {
+                PacketDirection::Clientbound
+            },
+            state: record_header[9],
+            data,
+        });
+    }
+    Ok(packets)
+}
+
+fn state_name(state: u8) -> &'static str {
+    match PlayerState::from_u8(state) {
+        Some(PlayerState::HandShake) => "handshake",
+        Some(PlayerState::Login) => "login",
+        Some(PlayerState::Configuration) => "configuration",
+        Some(PlayerState::Play) => "play",
+        None => "unknown",
+    }
+}
+
+/// Pretty prints a capture file to stdout
+pub fn print_capture(path: &str) -> std::io::Result<()> {
+    let packets = read_capture(path)?;
+    let mut clientbound = (0usize, 0usize);
+    let mut serverbound = (0usize, 0usize);
+    for packet in packets.iter() {
+        let (id, id_len) = peek_varint_and_len_from_slice(&packet.data).unwrap_or((-1, 0));
+        let arrow = match packet.direction {
+            PacketDirection::Serverbound => {
+                serverbound.0 += 1;
+                serverbound.1 += packet.data.len();
+                "C -> S"
+            }
+            PacketDirection::Clientbound => {
+                clientbound.0 += 1;
+                clientbound.1 += packet.data.len();
+                "S -> C"
+            }
+        };
+        let body = &packet.data[id_len..];
+        let preview = body
+            .iter()
+            .take(24)
+            .map(|b| format!("{b:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!(
+            "[{:>10.3}ms] {arrow} {:<13} 0x{id:02X} {:>8} bytes | {preview}{}",
+            packet.time_micros as f64 / 1000.0,
+            state_name(packet.state),
+            body.len(),
+            if body.len() > 24 { " ..." } else { "" }
+        );
+    }
+    println!(
+        "{} packets ({} clientbound / {} bytes, {} serverbound / {} bytes)",
+        packets.len(),
+        clientbound.0,
+        clientbound.1,
+        serverbound.0,
+        serverbound.1
+    );
+    Ok(())
+}
+
+/// Waits for a client on the given port and replays the clientbound configuration and play
+/// packets of a capture to it. Login is handled in offline mode without encryption or
+/// compression and serverbound packets are read and discarded
+pub async fn replay_capture(path: &str, port: u32) -> std::io::Result<()> {
+    let packets = read_capture(path)?
+        .into_iter()
+        .filter(|p| {
+            p.direction == PacketDirection::Clientbound
+                && (p.state == PlayerState::Configuration as u8
+                    || p.state == PlayerState::Play as u8)
+        })
+        .collect::<Vec<_>>();
+    let listener = TcpListener::bind(format!("0.0.0.0:{port}")).await?;
+    LOGGER.get().unwrap().println(&format!(
+        "Replaying {} packets from {path}, waiting for a client on port {port}...",
+        packets.len()
+    ));
+    loop {
+        let (mut socket, addr) = listener.accept().await?;
+        socket.set_nodelay(true)?;
+        let mut handshake = read_raw_packet(&mut socket).await?;
+        let _id = read_varint_from_vec(&mut handshake);
+        let _protocol = read_varint_from_vec(&mut handshake);
+        let addr_len = read_varint_from_vec(&mut handshake).unwrap_or(0) as usize;
+        let next_state = handshake.get(addr_len + 2..).and_then(|rest| rest.first());
+        if next_state != Some(&2) {
+            // Answer server list pings so the replay server shows up in the client
+            let _status_request = read_raw_packet(&mut socket).await?;
+            let status = serde_json::json!({
+                "version": {"name": MC_VERSION, "protocol": MC_PROTOCOL},
+                "players": {"max": 1, "online": 0, "sample": []},
+                "description": {"text": "Ludicrous Capture Replay"}
+            });
+            let mut status_packet = write_varint(0x00);
+            status_packet.extend(write_string(&status.to_string()));
+            write_raw_packet(&mut socket, status_packet).await?;
+            if let Ok(ping) = read_raw_packet(&mut socket).await {
+                write_raw_packet(&mut socket, ping).await?;
+            }
+            continue;
+        }
+        // Login Start -> Login Success
+        let login_start = read_raw_packet(&mut socket).await?;
+        let Some((username, uuid)) = parse_login_start(login_start) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid Login Start packet from {addr}"),
+            ));
+        };
+        let mut login_success = write_varint(0x02);
+        login_success.extend(uuid);
+        login_success.extend(write_string(&username));
+        login_success.extend(write_varint(0));
+        write_raw_packet(&mut socket, login_success).await?;
+        let _login_ack = read_raw_packet(&mut socket).await?;
+        LOGGER
+            .get()
+            .unwrap()
+            .println(&format!("Replaying capture to {username} ({addr})"));
+
+        let (mut read_half, mut write_half) = socket.into_split();
+        let config_finished = std::sync::Arc::new(tokio::sync::Notify::new());
+        let reader_finished = config_finished.clone();
+        let reader = tokio::spawn(async move {
+            let mut in_config = true;
+            while let Ok(mut packet) = read_raw_packet(&mut read_half).await {
+                let id = read_varint_from_vec(&mut packet);
+                // Acknowledge Finish Configuration
+                if in_config && id == Some(0x03) {
+                    in_config = false;
+                    reader_finished.notify_one();
+                }
+            }
+            // The client disconnected, the replay stops at the next write
+            reader_finished.notify_one();
+        });
+        let replay_start = std::time::Instant::now();
+        let first_time = packets.first().map(|p| p.time_micros).unwrap_or(0);
+        // Without a Finish Configuration in the capture the client never acknowledges it
+        let mut waited_for_config = !has_finish_configuration(&packets);
+        for packet in packets.iter() {
+            if packet.state == PlayerState::Play as u8 && !waited_for_config {
+                config_finished.notified().await;
+                waited_for_config = true;
+            }
+            let target =
+                std::time::Duration::from_micros(packet.time_micros.saturating_sub(first_time));
+            if let Some(delay) = target.checked_sub(replay_start.elapsed()) {
+                tokio::time::sleep(delay).await;
+            }
+            if write_raw_packet(&mut write_half, packet.data.clone())
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+        LOGGER
+            .get()
+            .unwrap()
+            .println("Finished replaying capture, keeping connection open");
+        let _ = reader.await;
+    }
+}
+
+/// Whether the clientbound packets of a capture end the configuration state
+fn has_finish_configuration(packets: &[CapturedPacket]) -> bool {
+    packets.iter().any(|packet| {
+        packet.state == PlayerState::Configuration as u8
+            && packet.direction == PacketDirection::Clientbound
+            && peek_varint_and_len_from_slice(&packet.data).map(|(id, _)| id) == Some(0x03)
+    })
+}
+
+/// Username and uuid of a Login Start packet
+fn parse_login_start(mut packet: Vec<u8>) -> Option<(String, Vec<u8>)> {
+    let _id = read_varint_from_vec(&mut packet)?;
+    let name_len = usize::try_from(read_varint_from_vec(&mut packet)?).ok()?;
+    let username = String::from_utf8_lossy(packet.get(..name_len)?).to_string();
+    let uuid = packet.get(name_len..name_len + 16)?.to_vec();
+    Some((username, uuid))
+}
+
+async fn read_raw_packet<R: AsyncReadExt + Unpin>(socket: &mut R) -> std::io::Result<Vec<u8>> {
+    let mut len = 0i32;
+    let mut position = 0;
+    loop {
+        let byte = socket.read_u8().await?;
+        len |= (byte as i32 & 0x7F) << position;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        position += 7;
+        if position >= 32 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "varint too long",
+            ));
+        }
+    }
+    if !(0..=MAX_PACKET_LEN).contains(&len) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid packet length {len}"),
+        ));
+    }
+    let mut packet = vec![0u8; len as usize];
+    socket.read_exact(&mut packet).await?;
+    Ok(packet)
+}
+
+async fn write_raw_packet<W: AsyncWriteExt + Unpin>(
+    socket: &mut W,
+    packet: Vec<u8>,
+) -> std::io::Result<()> {
+    let mut formatted = write_varint(packet.len() as i32);
+    formatted.extend(packet);
+    socket.write_all(&formatted).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_packets_are_read_back() {
+        let path = std::env::temp_dir().join(format!("ludicrous-capture-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let recorder = PacketRecorder::create(path).unwrap();
+        recorder.record(
+            PacketDirection::Serverbound,
+            PlayerState::Login as u8,
+            &[0x00, 1, 2],
+        );
+        // A formatted packet split in two writes, length 3 and no compression
+        recorder.record_formatted(PlayerState::Play as u8, false, &[3, 0x27]);
+        recorder.record_formatted(PlayerState::Play as u8, false, &[4, 5]);
+        drop(recorder);
+        let packets = read_capture(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].direction, PacketDirection::Serverbound);
+        assert_eq!(packets[0].state, PlayerState::Login as u8);
+        assert_eq!(packets[0].data, [0x00, 1, 2]);
+        assert_eq!(packets[1].direction, PacketDirection::Clientbound);
+        assert_eq!(packets[1].data, [0x27, 4, 5]);
+    }
+
+    #[test]
+    fn truncated_login_start_is_rejected() {
+        let mut packet = write_varint(0x00);
+        packet.extend(write_string("Steve"));
+        assert!(parse_login_start(packet.clone()).is_none());
+        packet.extend([7; 16]);
+        let (username, uuid) = parse_login_start(packet).unwrap();
+        assert_eq!(username, "Steve");
+        assert_eq!(uuid, [7; 16]);
+        assert!(parse_login_start(vec![0x00, 0x7F]).is_none());
+    }
+
+    #[test]
+    fn truncated_records_are_dropped() {
+        let path = std::env::temp_dir().join(format!("ludicrous-truncated-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut data = CAPTURE_MAGIC.to_vec();
+        data.extend((MC_PROTOCOL as u32).to_be_bytes());
+        data.extend([0; 8]);
+        data.extend([1, PlayerState::Play as u8]);
+        data.extend(u32::MAX.to_be_bytes());
+        data.extend([0x27, 1]);
+        std::fs::write(path, data).unwrap();
+        let packets = read_capture(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(packets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn packet_lengths_are_bounded() {
+        let mut packet = write_varint(3);
+        packet.extend([0x00, 1, 2]);
+        assert_eq!(
+            read_raw_packet(&mut packet.as_slice()).await.unwrap(),
+            [0x00, 1, 2]
+        );
+        let negative = write_varint(-1);
+        assert!(read_raw_packet(&mut negative.as_slice()).await.is_err());
+        let huge = write_varint(MAX_PACKET_LEN + 1);
+        assert!(read_raw_packet(&mut huge.as_slice()).await.is_err());
+    }
+
+    #[test]
+    fn replay_waits_only_for_captured_finish_configuration() {
+        let packet = |state: PlayerState, direction, id| CapturedPacket {
+            time_micros: 0,
+            direction,
+            state: state as u8,
+            data: write_varint(id),
+        };
+        let mut packets = vec![
+            packet(
+                PlayerState::Configuration,
+                PacketDirection::Clientbound,
+                0x0E,
+            ),
+            packet(
+                PlayerState::Configuration,
+                PacketDirection::Serverbound,
+                0x03,
+            ),
+            packet(PlayerState::Play, PacketDirection::Clientbound, 0x03),
+        ];
+        assert!(!has_finish_configuration(&packets));
+        packets.push(packet(
+            PlayerState::Configuration,
+            PacketDirection::Clientbound,
+            0x03,
+        ));
+        assert!(has_finish_configuration(&packets));
+    }
+}
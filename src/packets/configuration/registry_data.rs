@@ -1,6 +1,6 @@
 use super::super::{
-    super::server::chunk_system::BIOMES, write_string, write_varint, Packet, PacketMode,
-    PacketStatic, PlayerWriteConn,
+    super::server::{chunk_system::BIOMES, Dimension},
+    write_string, write_varint, Packet, PacketMode, PacketStatic, PlayerWriteConn,
 };
 use tokio::io::AsyncWriteExt;
 
@@ -26,9 +26,11 @@ impl Packet for RegistryData {
     async fn send(&mut self, write_conn: &mut PlayerWriteConn) {
         let mut registry_packet = write_varint(Self::CLIENTBOUND_ID);
         registry_packet.extend(write_string("dimension_type")); // reg id
-        registry_packet.extend(write_varint(1)); // entries length
-        registry_packet.extend(write_string("overworld")); // entry id
-        registry_packet.push(0x00); // has data bool
+        registry_packet.extend(write_varint(Dimension::ALL.len() as i32)); // entries length
+        for dimension in Dimension::ALL {
+            registry_packet.extend(write_string(dimension.type_name())); // entry id
+            registry_packet.push(0x00); // has data bool
+        }
         write_conn.write_packet(registry_packet).await;
         /*self.format_packet(&mut registry_packet);
         self.encrypt_packet(&mut registry_packet);
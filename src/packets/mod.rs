@@ -1,9 +1,12 @@
+pub mod capture;
 pub mod configuration;
 pub mod handshake;
 pub mod login;
 pub mod play;
 pub mod status;
-use super::player::{Player, PlayerReadConn, PlayerStream, PlayerWriteConn};
+use super::player::{Player, PlayerReadConn, PlayerState, PlayerStream, PlayerWriteConn};
+use super::server::metrics::METRICS;
+use capture::PacketDirection;
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use rsa::RsaPrivateKey;
@@ -104,6 +107,18 @@ impl<T: Packet + 'static> From<T> for Box<dyn Packet> {
 impl PlayerReadConn {
     /// Decrypt packet of self
     pub async fn decrypt_packet(&mut self) -> Option<Vec<u8>> {
+        let packet = self.read_packet().await;
+        if let Some(packet) = &packet {
+            let state = connection_state(&self.data);
+            METRICS.packet_in(state, packet.len());
+            if let Some(capture) = &self.capture {
+                capture.record(PacketDirection::Serverbound, state, packet);
+            }
+        }
+        packet
+    }
+
+    async fn read_packet(&mut self) -> Option<Vec<u8>> {
         if self.decryptor.is_some() {
             let mut packet_payload = self.decrypt_data().await?;
             if self
@@ -226,7 +241,15 @@ impl PlayerWriteConn {
         Write a packet to the TCP Stream
     */
     async fn write_packet(&mut self, mut packet: Vec<u8>) -> std::io::Result<()> {
+        if let Some(capture) = &self.capture {
+            capture.record(
+                PacketDirection::Clientbound,
+                connection_state(&self.data),
+                &packet,
+            );
+        }
         let mut header = create_pkt_header(&mut packet, self.data.clone());
+        METRICS.packet_out(connection_state(&self.data), header.len() + packet.len());
         self.encrypt_packet(&mut header);
         self.socket_write.write_all(&header).await?;
         self.encrypt_packet(&mut packet);
@@ -234,7 +257,18 @@ impl PlayerWriteConn {
         Ok(())
     }
 
+    /// Writes already formatted packet data to the TCP Stream
+    /// Only the bytes are recorded in metrics since a packet may be written in multiple parts
     async fn write_packet_data(&mut self, mut formatted_packet: Vec<u8>) -> std::io::Result<()> {
+        let state = connection_state(&self.data);
+        METRICS.bytes_out[state as usize].add(formatted_packet.len() as u64);
+        if let Some(capture) = &self.capture {
+            let compression_enabled = self
+                .data
+                .as_ref()
+                .is_some_and(|p| p.compression_enabled.load(Ordering::Relaxed));
+            capture.record_formatted(state, compression_enabled, &formatted_packet);
+        }
         self.encrypt_packet(&mut formatted_packet);
         self.socket_write.write_all(&formatted_packet).await?;
         Ok(())
@@ -249,6 +283,14 @@ impl PlayerWriteConn {
     }
 }
 
+/// Returns the connection state used to label packet metrics
+fn connection_state(player_data: &Option<Arc<Player>>) -> u8 {
+    player_data
+        .as_ref()
+        .map(|p| p.state.load(Ordering::Relaxed))
+        .unwrap_or(PlayerState::HandShake as u8)
+}
+
 /// Compresses given packet and returns the previous length as varint if at compression
 /// threshold, 0 if below threshold, or None if compression disabled
 fn compress_packet(payload: &mut Vec<u8>, player_data: Arc<Player>) -> Option<Vec<u8>> {
@@ -380,3 +422,11 @@ pub fn write_string(s: &str) -> Vec<u8> {
     buf.extend(s.as_bytes());
     buf
 }
+
+/// Writes a plain text component as a nameless NBT string tag
+pub fn write_text_component(text: &str) -> Vec<u8> {
+    let mut buf = vec![0x08];
+    buf.extend((text.len() as u16).to_be_bytes());
+    buf.extend(text.as_bytes());
+    buf
+}
@@ -0,0 +1,47 @@
+use super::super::{
+    super::server::commands, peek_varint_and_len_from_slice, Packet, PacketStatic, PlayerReadConn,
+};
+
+/**
+    Chat Command Packet
+    Runs a command typed by the player, commands with signed arguments are not supported
+
+    # Serverbound
+    * id: `0x06`
+    * resource: `chat_command`
+
+    # Clientbound
+    * Command feedback is sent with `System Chat Message` (system_chat.rs)
+*/
+pub struct ChatCommand<'a> {
+    packet_data: &'a [u8],
+}
+
+impl<'a> ChatCommand<'a> {
+    pub fn new(packet_data: &'a [u8]) -> Self {
+        ChatCommand { packet_data }
+    }
+}
+
+impl PacketStatic for ChatCommand<'_> {
+    const SERVERBOUND_ID: i32 = 0x06;
+}
+
+#[async_trait::async_trait]
+impl Packet for ChatCommand<'_> {
+    async fn receive(&mut self, read_conn: &mut PlayerReadConn) {
+        let Some((len, varint_len)) = peek_varint_and_len_from_slice(self.packet_data) else {
+            return;
+        };
+        let Some(command) = self
+            .packet_data
+            .get(varint_len..varint_len + len.max(0) as usize)
+        else {
+            return;
+        };
+        let command = String::from_utf8_lossy(command).into_owned();
+        let player = read_conn.data.clone().unwrap();
+        // Commands may load chunks, do not hold up the packet receiver
+        tokio::spawn(commands::run_command(player, command));
+    }
+}
@@ -1,3 +1,5 @@
+use super::super::super::player::PlayerState;
+use super::super::super::server::metrics::METRICS;
 use super::super::super::server::Chunk;
 use super::super::{
     create_pkt_header, prepend_len_as_varint, write_varint, Packet, PacketStatic, Player,
@@ -18,37 +20,12 @@ impl ChunkLightData {
             chunk_data_packet.extend(chunk.z.to_be_bytes()); // Chunk Z
 
             // HeightMap
-            chunk_data_packet.extend(write_varint(chunk.get_heightmap_count()));
-            if let Some(world_surface) = &chunk.heightmaps.world_surface {
-                chunk_data_packet.extend(write_varint(1)); // Heightmap type
-                                                           // Heightmap length
-                chunk_data_packet.extend(write_varint(world_surface.len() as i32));
-                for value in world_surface.iter() {
-                    chunk_data_packet.extend(value.to_be_bytes());
-                }
-            }
-            if let Some(ocean_floor) = &chunk.heightmaps.ocean_floor {
-                chunk_data_packet.extend(write_varint(3)); // Heightmap type
-                                                           // Heightmap length
-                chunk_data_packet.extend(write_varint(ocean_floor.len() as i32));
-                for value in ocean_floor.iter() {
-                    chunk_data_packet.extend(value.to_be_bytes());
-                }
-            }
-            if let Some(motion_blocking) = &chunk.heightmaps.motion_blocking {
-                chunk_data_packet.extend(write_varint(4)); // Heightmap Type
-                                                           // Heightmap length
-                chunk_data_packet.extend(write_varint(motion_blocking.len() as i32));
-                // Heightmap values
-                for value in motion_blocking.iter() {
-                    chunk_data_packet.extend(value.to_be_bytes());
-                }
-            }
-            if let Some(motion_blocking_nl) = &chunk.heightmaps.motion_blocking_no_leaves {
-                chunk_data_packet.extend(write_varint(5)); // Heightmap type
-                                                           // Heightmap length
-                chunk_data_packet.extend(write_varint(motion_blocking_nl.len() as i32));
-                for value in motion_blocking_nl.iter() {
+            let heightmaps = chunk.client_heightmaps();
+            chunk_data_packet.extend(write_varint(heightmaps.len() as i32));
+            for (heightmap_type, heightmap) in heightmaps {
+                chunk_data_packet.extend(write_varint(heightmap_type)); // Heightmap type
+                chunk_data_packet.extend(write_varint(heightmap.len() as i32)); // Heightmap length
+                for value in heightmap.iter() {
                     chunk_data_packet.extend(value.to_be_bytes());
                 }
             }
@@ -168,7 +145,8 @@ impl ChunkLightData {
                     break;
                 }
 
-                let bit = 1u64 << (section.y + 4) as u8;
+                // Light sections start one section below the chunk
+                let bit = 1u64 << (section.y - chunk.y + 1) as u8;
                 if let Some(sky_light) = &section.sky_light {
                     sky_light_mask |= bit;
                     sky_light_arrays.extend(write_varint(2048));
@@ -187,6 +165,17 @@ impl ChunkLightData {
                     empty_block_light_mask |= bit;
                 }
             }
+            // The sections below and above the chunk have no blocks, sunlight enters from above
+            let above_bit = 1u64 << (chunk.sections.len() + 1);
+            empty_sky_light_mask |= 1;
+            empty_block_light_mask |= 1 | above_bit;
+            if chunk.sections.last().is_some_and(|s| s.sky_light.is_some()) {
+                sky_light_mask |= above_bit;
+                sky_light_arrays.extend(write_varint(2048));
+                sky_light_arrays.extend([0xFF; 2048]);
+            } else {
+                empty_sky_light_mask |= above_bit;
+            }
 
             // Format chunk data length
             prepend_len_as_varint(&mut chunk_palette_data);
@@ -239,7 +228,10 @@ impl Packet for ChunkLightData {
     async fn send(&mut self, write_conn: &mut PlayerWriteConn) {
         // bundle delim
         write_conn.write_packet(write_varint(0x00)).await;
-        for pkt in self.raw_data.take().unwrap() {
+        let raw_data = self.raw_data.take().unwrap();
+        // Each chunk is written as a header and a payload
+        METRICS.packets_out[PlayerState::Play as usize].add(raw_data.len() as u64 / 2);
+        for pkt in raw_data {
             write_conn.write_packet_data(pkt).await;
         }
         //bundle delim
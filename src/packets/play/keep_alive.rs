@@ -2,6 +2,7 @@ use super::super::{
     super::server::ServerData, write_varint, Packet, PacketStatic, Player, PlayerWriteConn,
 };
 use std::sync::Arc;
+use std::time::Duration;
 
 /**
     Keep Alive Packet
@@ -11,7 +12,7 @@ use std::sync::Arc;
     * resource: `keep_alive`
 
     # Serverbound
-    * id: `0x1A`
+    * id: `0x1B`
     * resource: `keep_alive`
 */
 pub struct KeepAlive {
@@ -22,11 +23,18 @@ impl KeepAlive {
     pub fn new(server: Arc<ServerData>) -> Self {
         KeepAlive { server }
     }
+
+    /// Round trip of an answered keep alive, its id is the server uptime in milliseconds when
+    /// it was sent. `None` for ids the server can not have sent
+    pub fn round_trip(uptime: Duration, packet_data: &[u8]) -> Option<Duration> {
+        let id = i64::from_be_bytes(packet_data.get(..8)?.try_into().ok()?);
+        uptime.checked_sub(Duration::from_millis(u64::try_from(id).ok()?))
+    }
 }
 
 impl PacketStatic for KeepAlive {
     const CLIENTBOUND_ID: i32 = 0x26;
-    const SERVERBOUND_ID: i32 = 0x1A;
+    const SERVERBOUND_ID: i32 = 0x1B;
 }
 
 #[async_trait::async_trait]
@@ -44,3 +52,23 @@ impl Packet for KeepAlive {
         write_conn.write_packet(keep_alive_packet).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_is_measured_from_the_id() {
+        let uptime = Duration::from_millis(15_250);
+        assert_eq!(
+            KeepAlive::round_trip(uptime, &15_000i64.to_be_bytes()),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            KeepAlive::round_trip(uptime, &16_000i64.to_be_bytes()),
+            None
+        );
+        assert_eq!(KeepAlive::round_trip(uptime, &(-1i64).to_be_bytes()), None);
+        assert_eq!(KeepAlive::round_trip(uptime, &[0, 1]), None);
+    }
+}
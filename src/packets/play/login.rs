@@ -1,6 +1,7 @@
 use super::super::{
-    super::server::ServerData, read_varint_from_vec, write_string, write_varint, Packet,
-    PacketStatic, Player, PlayerWriteConn,
+    super::server::{Dimension, ServerData},
+    read_varint_from_vec, write_string, write_varint, Packet, PacketStatic, Player,
+    PlayerWriteConn,
 };
 use std::sync::Arc;
 use tokio::io::AsyncWriteExt;
@@ -16,6 +17,21 @@ impl Login {
     pub fn new(server: Arc<ServerData>) -> Self {
         Login { server }
     }
+
+    /// Dimension and game mode fields shared by the `Login` and `Respawn` packets
+    pub fn spawn_info(dimension: Dimension) -> Vec<u8> {
+        let mut spawn_info = write_varint(dimension.type_id()); // dimension type
+        spawn_info.extend(write_string(dimension.name())); // dim name
+        spawn_info.extend(0i64.to_be_bytes()); // hashed seed
+        spawn_info.push(0x01); // game mode
+        spawn_info.push(0x01); // previous game mode
+        spawn_info.push(0x00); // is debug
+        spawn_info.push(0x01); // is flat
+        spawn_info.push(0x00); // has death location
+        spawn_info.extend(write_varint(0x00)); // Portal cooldown
+        spawn_info.extend(write_varint(0x00)); // sea level
+        spawn_info
+    }
 }
 
 impl PacketStatic for Login {
@@ -29,25 +45,17 @@ impl Packet for Login {
         let player_data = write_conn.data.clone().unwrap().clone();
         login_packet.extend(player_data.id.to_be_bytes()); // player id
         login_packet.push(0x00); // is hardcore
-        login_packet.extend(write_varint(0x01)); // dimensions
-        login_packet.extend(write_string("minecraft:overworld"));
+        login_packet.extend(write_varint(Dimension::ALL.len() as i32)); // dimensions
+        for dimension in Dimension::ALL {
+            login_packet.extend(write_string(dimension.name()));
+        }
         login_packet.extend(write_varint(0x02)); // max players
         login_packet.extend(write_varint(self.server.config.view_distance as i32)); // view distance
         login_packet.extend(write_varint(self.server.config.simulation_distance as i32)); // sim distance
         login_packet.push(0x00); // reduced debug
         login_packet.push(0x01); // respawn screen
         login_packet.extend(write_varint(0x00)); // do limited crafting
-        login_packet.extend(write_varint(0x00)); // dimension type
-                                                 //login_packet.extend(write_varint("overworld".len() as i32).await); // dim name len
-        login_packet.extend(write_string("minecraft:overworld")); // dim name
-        login_packet.extend(0i64.to_be_bytes()); // hashed seed
-        login_packet.push(0x01); // game mode
-        login_packet.push(0x01); // previous game mode
-        login_packet.push(0x00); // is debug
-        login_packet.push(0x01); // is flat
-        login_packet.push(0x00); // has death location
-        login_packet.extend(write_varint(0x00)); // Portal cooldown
-        login_packet.extend(write_varint(0x00)); // sea level
+        login_packet.extend(Self::spawn_info(player_data.get_dimension()));
         login_packet.push(0x00); // secure chat
         write_conn.write_packet(login_packet).await;
     }
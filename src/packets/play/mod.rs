@@ -1,5 +1,6 @@
 /*mod bundle_delimiter;
 mod change_difficulty;*/
+mod chat_command;
 mod chunk_light_data;
 mod game_event;
 mod initialize_border;
@@ -8,6 +9,7 @@ mod login;
 mod ping_sb;
 mod player_abilities;
 mod player_info_update;
+mod respawn;
 /*mod recipe_book_settings;
 mod remove_entities;*/
 mod set_center_chunk;
@@ -20,6 +22,7 @@ mod set_player_pos_rot;
 mod set_spawn_pos;
 mod spawn_entity;*/
 mod synchronize_player_pos;
+mod system_chat;
 //mod teleport_entity;
 mod unload_chunks;
 /*mod update_entity_pos;
@@ -27,6 +30,7 @@ mod update_entity_pos_rot;
 mod update_entity_rot;
 pub use bundle_delimiter::BundleDelimiter;
 pub use change_difficulty::ChangeDifficulty;*/
+use chat_command::ChatCommand;
 pub use chunk_light_data::ChunkLightData;
 pub use game_event::GameEvent;
 pub use initialize_border::InitializeBorder;
@@ -35,6 +39,7 @@ pub use login::Login;
 use ping_sb::PingSB;
 pub use player_abilities::PlayerAbilities;
 pub use player_info_update::PlayerInfoUpdate;
+pub use respawn::Respawn;
 //pub use recipe_book_settings::RecipeBookSettings;
 //pub use remove_entities::RemoveEntities;
 pub use set_center_chunk::SetCenterChunk;
@@ -44,6 +49,7 @@ pub use set_entity_vel::{SetEntityVel, SetEntityVelPayload};
 pub use set_spawn_pos::SetSpawnPos;
 pub use spawn_entity::{SpawnEntity, SpawnEntityPayload};*/
 pub use synchronize_player_pos::SynchronizePlayerPos;
+pub use system_chat::SystemChat;
 //pub use teleport_entity::{TeleportEntity, TeleportEntityPayload};
 pub use unload_chunks::UnloadChunks;
 use {
@@ -57,9 +63,10 @@ use {
 };*/
 
 use super::{
-    super::server::ServerData, Packet, PacketStatic, Player, PlayerReadConn, PlayerWriteConn,
+    super::server::{metrics::METRICS, ServerData},
+    Packet, PacketStatic, Player, PlayerReadConn, PlayerWriteConn,
 };
-use std::sync::{Arc, Weak};
+use std::sync::{atomic::Ordering, Arc, Weak};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::sync::Mutex as AMutex;
 pub struct ReceivablePlayPackets;
@@ -77,7 +84,26 @@ impl ReceivablePlayPackets {
             SetPlayerPosRot::SERVERBOUND_ID => {
                 SetPlayerPosRot::new(packet).receive(read_conn).await
             }
+            KeepAlive::SERVERBOUND_ID => {
+                // Timed like vanilla's player latency, which clients show in the player list
+                let player = read_conn.data.clone().unwrap();
+                let uptime = player.server.start_time.elapsed();
+                if let Some(latency) = KeepAlive::round_trip(uptime, packet) {
+                    METRICS.keep_alive_latency.observe(latency);
+                    let latency =
+                        PlayerInfoUpdate::latency(player.uuid, latency.as_millis() as i32);
+                    if let Some(tx) = player.outbound.upgrade() {
+                        let _ = tx.try_send(latency.into());
+                    }
+                }
+            }
+            SynchronizePlayerPos::SERVERBOUND_ID => {
+                // Movement is accepted again once the client confirms the teleport
+                let player = read_conn.data.clone().unwrap();
+                player.awaiting_teleport.store(false, Ordering::Relaxed);
+            }
             SetPlayerPos::SERVERBOUND_ID => SetPlayerPos::new(packet).receive(read_conn).await,
+            ChatCommand::SERVERBOUND_ID => ChatCommand::new(packet).receive(read_conn).await,
             //SetPlayerRot::SERVERBOUND_ID => SetPlayerRot::new(player, packet).receive().await,
             _ => {}
         };
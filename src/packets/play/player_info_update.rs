@@ -1,10 +1,24 @@
 use super::super::{write_string, write_varint, Packet, PacketStatic, PlayerWriteConn};
 
-pub struct PlayerInfoUpdate;
+pub struct PlayerInfoUpdate {
+    /// Uuid and latency in milliseconds of a player, every action is sent for the player of
+    /// the connection when `None`
+    latency: Option<([u8; 16], i32)>,
+}
 
 impl PlayerInfoUpdate {
+    /// Action bit of the latency of a player
+    pub const UPDATE_LATENCY: u8 = 0x10;
+
     pub fn new() -> Self {
-        PlayerInfoUpdate
+        PlayerInfoUpdate { latency: None }
+    }
+
+    /// Updates the latency shown for a player
+    pub fn latency(uuid: [u8; 16], latency: i32) -> Self {
+        PlayerInfoUpdate {
+            latency: Some((uuid, latency)),
+        }
     }
 }
 
@@ -16,18 +30,25 @@ impl PacketStatic for PlayerInfoUpdate {
 impl Packet for PlayerInfoUpdate {
     async fn send(&mut self, write_conn: &mut PlayerWriteConn) {
         let mut info_update_packet = write_varint(Self::CLIENTBOUND_ID);
-        info_update_packet.push(0xFF); // actions
-        info_update_packet.push(0x01); // player array len
-        info_update_packet.extend(write_conn.data.clone().unwrap().uuid); // add player uuid
-        info_update_packet.extend(write_string("Dev"));
-        info_update_packet.push(0x00); // player signature false
-        info_update_packet.push(0x00); // init chat optional = false
-        info_update_packet.push(0x00); // gamemode 0
-        info_update_packet.push(0x01); // set player listed
-        info_update_packet.push(0x00); // latency
-        info_update_packet.push(0x00); // optional display name
-        info_update_packet.push(0x00); // list priority
-        info_update_packet.push(0x01); // show player hat layer
+        if let Some((uuid, latency)) = self.latency {
+            info_update_packet.push(Self::UPDATE_LATENCY);
+            info_update_packet.push(0x01); // player array len
+            info_update_packet.extend(uuid);
+            info_update_packet.extend(write_varint(latency));
+        } else {
+            info_update_packet.push(0xFF); // actions
+            info_update_packet.push(0x01); // player array len
+            info_update_packet.extend(write_conn.data.clone().unwrap().uuid); // add player uuid
+            info_update_packet.extend(write_string("Dev"));
+            info_update_packet.push(0x00); // player signature false
+            info_update_packet.push(0x00); // init chat optional = false
+            info_update_packet.push(0x00); // gamemode 0
+            info_update_packet.push(0x01); // set player listed
+            info_update_packet.push(0x00); // latency
+            info_update_packet.push(0x00); // optional display name
+            info_update_packet.push(0x00); // list priority
+            info_update_packet.push(0x01); // show player hat layer
+        }
         write_conn.write_packet(info_update_packet).await;
     }
 }
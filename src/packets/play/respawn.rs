@@ -0,0 +1,37 @@
+use super::super::{super::server::Dimension, write_varint, Packet, PacketStatic, PlayerWriteConn};
+use super::Login;
+
+/**
+    Respawn Packet
+    Moves the player to another dimension, the client drops all loaded chunks
+
+    # Clientbound
+    * id: `0x4B`
+    * resource: `respawn`
+
+    # Serverbound
+    * No relevant serverbound packet
+*/
+pub struct Respawn {
+    dimension: Dimension,
+}
+
+impl Respawn {
+    pub fn new(dimension: Dimension) -> Self {
+        Respawn { dimension }
+    }
+}
+
+impl PacketStatic for Respawn {
+    const CLIENTBOUND_ID: i32 = 0x4B;
+}
+
+#[async_trait::async_trait]
+impl Packet for Respawn {
+    async fn send(&mut self, write_conn: &mut PlayerWriteConn) {
+        let mut respawn_packet = write_varint(Self::CLIENTBOUND_ID);
+        respawn_packet.extend(Login::spawn_info(self.dimension));
+        respawn_packet.push(0x00); // data kept
+        let _ = write_conn.write_packet(respawn_packet).await;
+    }
+}
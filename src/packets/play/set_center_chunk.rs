@@ -6,14 +6,14 @@ use tokio::sync::Mutex as AMutex;
 
 /**
     Set Center Chunk Packet
-        Tells the client what chunk to load other chunks around
+    Tells the client what chunk to load other chunks around
 
     # Clientbound
-        * id: `0x57`
-        * resource: `set_chunk_cache_center`
+    * id: `0x57`
+    * resource: `set_chunk_cache_center`
 
     # Serverbound
-        * No relevant serverbound packet
+    * No relevant serverbound packet
 */
 pub struct SetCenterChunk {
     center_chunk: (i32, i32),
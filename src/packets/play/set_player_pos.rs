@@ -5,11 +5,11 @@ use super::super::{super::LudiChunkLoader, Packet, PacketStatic, Player, PlayerR
     Sets a player's position and broadcasts to nearby players
 
     # Serverbound
-        * id: `0x1D`
-        * resource: `move_player_pos`
+    * id: `0x1D`
+    * resource: `move_player_pos`
 
     # Clientbound
-        * See `Update Entity Position` (update_entity_pos.rs)
+    * See `Update Entity Position` (update_entity_pos.rs)
 */
 pub struct SetPlayerPos<'a> {
     packet_data: &'a [u8],
@@ -37,6 +37,15 @@ impl Packet for SetPlayerPos<'_> {
         let mut z = [0u8; 8];
         z.copy_from_slice(&self.packet_data[16..24]);
         let z = f64::from_be_bytes(z);
+        if read_conn
+            .data
+            .clone()
+            .unwrap()
+            .awaiting_teleport
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            return;
+        }
         let old_player_pos = read_conn.data.clone().unwrap().get_position();
         read_conn
             .data
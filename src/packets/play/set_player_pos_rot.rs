@@ -7,11 +7,11 @@ use tokio::sync::Mutex as AMutex;
     Sets a player's position and rotation and broadcasts to nearby players
 
     # Serverbound
-        * id: `0x1E`
-        * resource: `move_player_pos_rot`
+    * id: `0x1E`
+    * resource: `move_player_pos_rot`
 
     # Clientbound
-        * See `Update Entity Position and Rotation` (update_entity_pos_rot.rs)
+    * See `Update Entity Position and Rotation` (update_entity_pos_rot.rs)
 */
 pub struct SetPlayerPosRot<'a> {
     packet_data: &'a [u8],
@@ -45,6 +45,15 @@ impl Packet for SetPlayerPosRot<'_> {
         let mut pitch = [0u8; 4];
         pitch.copy_from_slice(&self.packet_data[28..32]);
         let pitch = f32::from_be_bytes(pitch);
+        if read_conn
+            .data
+            .clone()
+            .unwrap()
+            .awaiting_teleport
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            return;
+        }
         let old_player_pos = read_conn.data.clone().unwrap().get_position();
         read_conn
             .data
@@ -7,12 +7,12 @@ use super::super::{
     Synchronize Player Position Packet
 
     # Clientbound
-        * id: `0x41`
-        * resource: `player_position`
+    * id: `0x41`
+    * resource: `player_position`
 
     # Serverbound
-        * id: `0x00`
-        * resource: `accept_teleportation`
+    * id: `0x00`
+    * resource: `accept_teleportation`
 */
 pub struct SynchronizePlayerPos;
 
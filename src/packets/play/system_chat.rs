@@ -0,0 +1,36 @@
+use super::super::{write_text_component, write_varint, Packet, PacketStatic, PlayerWriteConn};
+
+/**
+    System Chat Message Packet
+    Shows an unsigned message in chat, used for command feedback
+
+    # Clientbound
+    * id: `0x72`
+    * resource: `system_chat`
+
+    # Serverbound
+    * No relevant serverbound packet
+*/
+pub struct SystemChat {
+    message: String,
+}
+
+impl SystemChat {
+    pub fn new(message: String) -> Self {
+        SystemChat { message }
+    }
+}
+
+impl PacketStatic for SystemChat {
+    const CLIENTBOUND_ID: i32 = 0x72;
+}
+
+#[async_trait::async_trait]
+impl Packet for SystemChat {
+    async fn send(&mut self, write_conn: &mut PlayerWriteConn) {
+        let mut chat_packet = write_varint(Self::CLIENTBOUND_ID);
+        chat_packet.extend(write_text_component(&self.message));
+        chat_packet.push(0x00); // overlay
+        let _ = write_conn.write_packet(chat_packet).await;
+    }
+}
@@ -1,6 +1,7 @@
 mod containers;
 use super::entities::Entity;
-use super::server::LudiChunkLoader;
+use super::packets::capture::PacketRecorder;
+use super::server::{Dimension, LudiChunkLoader};
 use super::server::{ServerData, ServerMappings};
 use super::Packet;
 use containers::PlayerInventory;
@@ -48,6 +49,8 @@ pub struct PlayerReadConn {
     /// Intended for saving previous excess stream data to avoid data loss
     pub decrypted_buf: Vec<u8>,
     pub decrypted_data: Vec<u8>,
+    /// Records received packets when `packet-capture` is enabled
+    pub capture: Option<Arc<PacketRecorder>>,
 }
 
 pub struct PlayerWriteConn {
@@ -56,6 +59,8 @@ pub struct PlayerWriteConn {
     pub encryptor: Option<Crypter>,
     /// Intended for reusing space to avoid allocating new vec for each encryption
     pub encrypt_buf: Vec<u8>,
+    /// Records sent packets when `packet-capture` is enabled
+    pub capture: Option<Arc<PacketRecorder>>,
 }
 
 pub struct PlayerStream {
@@ -72,6 +77,12 @@ impl PlayerStream {
         }
     }
 
+    /// Records all packets of this connection with the given recorder
+    pub fn set_capture(&mut self, recorder: Arc<PacketRecorder>) {
+        self.read.capture = Some(recorder.clone());
+        self.write.capture = Some(recorder);
+    }
+
     pub fn split(self) -> (PlayerReadConn, PlayerWriteConn) {
         (self.read, self.write)
     }
@@ -91,6 +102,7 @@ impl PlayerReadConn {
             encrypted_buf: vec![0u8; BUF_LEN],
             decrypted_buf: vec![0u8; BUF_LEN],
             decrypted_data: vec![],
+            capture: None,
         }
     }
 }
@@ -102,6 +114,7 @@ impl PlayerWriteConn {
             data: None,
             encryptor: None,
             encrypt_buf: Vec::new(),
+            capture: None,
         }
     }
 }
@@ -143,7 +156,14 @@ pub struct Player {
     pub counters: PlayerCounters,
     pub data: PlayerData,
     pub server: Arc<ServerData>,
+    /// Dimension as atomic u8
+    pub dimension: AtomicU8,
+    /// Set while a server side teleport has not been accepted by the client
+    pub awaiting_teleport: AtomicBool,
     pub chunk: (AtomicI32, AtomicI32),
+    /// Incremented whenever the center chunk or dimension changes, pending chunk requests
+    /// compare it to know when their distance to the player has to be recomputed
+    pub chunk_generation: AtomicU32,
     // Players within simulation distance that will have data broadcasted to them
     // Identified by entity id. eid -> Player
     pub nearby_players: RwLock<HashMap<i32, Arc<Player>>>,
@@ -187,6 +207,11 @@ impl Player {
             new_player
         };
         player_data.mappings = Some(&server.mappings);
+        let dimension = player_data
+            .dimension
+            .as_deref()
+            .and_then(Dimension::from_name)
+            .unwrap_or(Dimension::Overworld);
         let data: PlayerData = player_data.into();
         let id = server.next_eid() as i32;
         let x = f64::from_bits(data.pos[0].load(Ordering::Relaxed));
@@ -202,7 +227,10 @@ impl Player {
             counters: Default::default(),
             data,
             server,
+            dimension: (dimension as u8).into(),
+            awaiting_teleport: AtomicBool::new(false),
             chunk: (AtomicI32::new(chunk.0), AtomicI32::new(chunk.1)),
+            chunk_generation: AtomicU32::new(0),
             nearby_players: RwLock::new(HashMap::new()),
             entities: HashMap::new(),
             compression_enabled: AtomicBool::new(false),
@@ -250,6 +278,24 @@ impl Player {
         (x, y, z)
     }
 
+    pub fn get_center_chunk(&self) -> (i32, i32) {
+        (
+            self.chunk.0.load(Ordering::Relaxed),
+            self.chunk.1.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Moves the center of the loaded chunks, invalidating the priority of pending chunk requests
+    pub fn set_center_chunk(&self, chunk: (i32, i32)) {
+        self.chunk.0.store(chunk.0, Ordering::Relaxed);
+        self.chunk.1.store(chunk.1, Ordering::Relaxed);
+        self.chunk_generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get_dimension(&self) -> Dimension {
+        Dimension::from_u8(self.dimension.load(Ordering::Relaxed)).unwrap()
+    }
+
     pub fn f32_to_angle(angle: f32) -> i8 {
         let quadrant = ((angle * 2.0 * std::f32::consts::PI) / 360.0).sin();
         let new = if angle.is_sign_positive() {
@@ -280,6 +326,7 @@ pub struct PlayerNBT<'a> {
     pub mappings: Option<&'a ServerMappings>,
     pub pos: [f64; 3],
     pub rotation: [f32; 2],
+    pub dimension: Option<String>,
     pub inventory: Vec<ISlot<'a>>,
     #[serde(rename = "EnderItems")]
     pub ender_chest: Vec<ISlot<'a>>,
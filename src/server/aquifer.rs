@@ -0,0 +1,466 @@
+use super::chunk_system::BLOCKSTATE_MAPPINGS;
+use super::terrain_gen::func_deserialize::{DensityArg, DensityFnArgs};
+use super::util::{clamped_map_f64, map_f64};
+use super::world_state::WorldState;
+use ahash::AHashMap;
+
+/// Size of an aquifer cell, every cell has one randomly placed aquifer center
+const X_SPACING: i32 = 16;
+const Y_SPACING: i32 = 12;
+const Z_SPACING: i32 = 16;
+/// Range of the aquifer center inside of its cell
+const X_RANGE: u32 = 10;
+const Y_RANGE: u32 = 9;
+const Z_RANGE: u32 = 10;
+/// Offsets in chunks of the columns checked for a surface above an aquifer
+const SURFACE_SAMPLING_OFFSETS_IN_CHUNKS: [(i32, i32); 13] = [
+    (0, 0),
+    (-2, -1),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-3, 0),
+    (-2, 0),
+    (-1, 0),
+    (1, 0),
+    (-2, 1),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+/// Fluid level of aquifers without fluid
+const WAY_BELOW_MIN_Y: i32 = -32512;
+/// Lava fills every air pocket below this height
+const LAVA_LEVEL: i32 = -54;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FluidStatus {
+    fluid_level: i32,
+    fluid: u32,
+}
+
+/// Router functions deciding the fluid level of aquifers
+struct AquiferFunctions<'a> {
+    barrier: &'a DensityArg,
+    floodedness: &'a DensityArg,
+    spread: &'a DensityArg,
+    lava: &'a DensityArg,
+    erosion: &'a DensityArg,
+    depth: &'a DensityArg,
+}
+
+/// Decides which block a position of a generated chunk becomes from its density. Solid terrain
+/// gets the default block, caves below the sea level are filled with the default fluid and
+/// aquifers give underground caves their own fluid levels
+pub struct Aquifer<'a> {
+    world_state: &'a WorldState,
+    dimension: &'a str,
+    default_block: u32,
+    air: u32,
+    water: u32,
+    lava: u32,
+    sea: FluidStatus,
+    /// `None` when aquifers are disabled, caves are then only filled up to the sea level
+    functions: Option<AquiferFunctions<'a>>,
+    min_grid_x: i32,
+    min_grid_y: i32,
+    min_grid_z: i32,
+    grid_size_x: i32,
+    grid_size_z: i32,
+    locations: Vec<Option<(i32, i32, i32)>>,
+    statuses: Vec<Option<FluidStatus>>,
+    preliminary_levels: AHashMap<(i32, i32), i32>,
+}
+
+impl<'a> Aquifer<'a> {
+    pub fn new(
+        world_state: &'a WorldState,
+        chunk_x: i32,
+        chunk_z: i32,
+        dimension: &'a str,
+    ) -> Self {
+        let settings = &world_state.settings;
+        let router = &settings.noise_router;
+        let functions = if settings.aquifers_enabled {
+            (|| {
+                Some(AquiferFunctions {
+                    barrier: router.get("barrier")?,
+                    floodedness: router.get("fluid_level_floodedness")?,
+                    spread: router.get("fluid_level_spread")?,
+                    lava: router.get("lava")?,
+                    erosion: router.get("erosion")?,
+                    depth: router.get("depth")?,
+                })
+            })()
+        } else {
+            None
+        };
+        let min_grid_x = (chunk_x * 16).div_euclid(X_SPACING) - 1;
+        let max_grid_x = (chunk_x * 16 + 15).div_euclid(X_SPACING) + 1;
+        let min_grid_y = settings.noise.min_y.div_euclid(Y_SPACING) - 1;
+        let max_grid_y = (settings.noise.min_y + settings.noise.height).div_euclid(Y_SPACING) + 1;
+        let min_grid_z = (chunk_z * 16).div_euclid(Z_SPACING) - 1;
+        let max_grid_z = (chunk_z * 16 + 15).div_euclid(Z_SPACING) + 1;
+        let grid_size_x = max_grid_x - min_grid_x + 1;
+        let grid_size_y = max_grid_y - min_grid_y + 1;
+        let grid_size_z = max_grid_z - min_grid_z + 1;
+        let cache_size = (grid_size_x * grid_size_y * grid_size_z) as usize;
+        Aquifer {
+            world_state,
+            dimension,
+            default_block: settings.default_block,
+            air: *BLOCKSTATE_MAPPINGS.get("air").unwrap(),
+            water: *BLOCKSTATE_MAPPINGS.get("water[level=0]").unwrap(),
+            lava: *BLOCKSTATE_MAPPINGS.get("lava[level=0]").unwrap(),
+            sea: FluidStatus {
+                fluid_level: settings.sea_level,
+                fluid: settings.default_fluid,
+            },
+            functions,
+            min_grid_x,
+            min_grid_y,
+            min_grid_z,
+            grid_size_x,
+            grid_size_z,
+            locations: vec![None; cache_size],
+            statuses: vec![None; cache_size],
+            preliminary_levels: AHashMap::new(),
+        }
+    }
+
+    pub fn default_block(&self) -> u32 {
+        self.default_block
+    }
+
+    /// Block state of a position from its final density, `None` for solid terrain which is
+    /// filled with the default block unless an ore vein replaces it
+    pub fn compute_substance(&mut self, x: i32, y: i32, z: i32, density: f64) -> Option<u32> {
+        if density > 0.0 {
+            return None;
+        }
+        let global_fluid = self.global_fluid(y);
+        if self.functions.is_none() {
+            return Some(self.fluid_at(global_fluid, y));
+        }
+        if self.fluid_at(global_fluid, y) == self.lava {
+            return Some(self.lava);
+        }
+        let grid_x = (x - 5).div_euclid(X_SPACING);
+        let grid_y = (y + 1).div_euclid(Y_SPACING);
+        let grid_z = (z - 5).div_euclid(Z_SPACING);
+        // Distances and cache indices of the three closest aquifer centers
+        let mut closest = [(i32::MAX, 0usize); 3];
+        for offset_x in 0..=1 {
+            for offset_y in -1..=1 {
+                for offset_z in 0..=1 {
+                    let index =
+                        self.grid_index(grid_x + offset_x, grid_y + offset_y, grid_z + offset_z);
+                    let (center_x, center_y, center_z) = self.aquifer_location(
+                        index,
+                        grid_x + offset_x,
+                        grid_y + offset_y,
+                        grid_z + offset_z,
+                    );
+                    let distance =
+                        (center_x - x).pow(2) + (center_y - y).pow(2) + (center_z - z).pow(2);
+                    if let Some(position) = closest.iter().position(|(d, _)| *d >= distance) {
+                        closest.copy_within(position..2, position + 1);
+                        closest[position] = (distance, index);
+                    }
+                }
+            }
+        }
+        let [(dist_1, index_1), (dist_2, index_2), (dist_3, index_3)] = closest;
+        let status_1 = self.aquifer_status(index_1);
+        let similarity_12 = similarity(dist_1, dist_2);
+        let block = self.fluid_at(status_1, y);
+        if similarity_12 <= 0.0 {
+            return Some(block);
+        }
+        if block == self.water && self.fluid_at(self.global_fluid(y - 1), y - 1) == self.lava {
+            return Some(block);
+        }
+        let mut barrier = None;
+        let status_2 = self.aquifer_status(index_2);
+        let pressure = similarity_12 * self.pressure(x, y, z, &mut barrier, status_1, status_2);
+        if density + pressure > 0.0 {
+            return None;
+        }
+        let status_3 = self.aquifer_status(index_3);
+        let similarity_13 = similarity(dist_1, dist_3);
+        if similarity_13 > 0.0 {
+            let pressure = similarity_12
+                * similarity_13
+                * self.pressure(x, y, z, &mut barrier, status_1, status_3);
+            if density + pressure > 0.0 {
+                return None;
+            }
+        }
+        let similarity_23 = similarity(dist_2, dist_3);
+        if similarity_23 > 0.0 {
+            let pressure = similarity_12
+                * similarity_23
+                * self.pressure(x, y, z, &mut barrier, status_2, status_3);
+            if density + pressure > 0.0 {
+                return None;
+            }
+        }
+        Some(block)
+    }
+
+    fn fluid_at(&self, status: FluidStatus, y: i32) -> u32 {
+        if y < status.fluid_level {
+            status.fluid
+        } else {
+            self.air
+        }
+    }
+
+    /// Fluid of positions outside of aquifers, lava deep down and the default fluid up to the
+    /// sea level
+    fn global_fluid(&self, y: i32) -> FluidStatus {
+        if y < LAVA_LEVEL.min(self.sea.fluid_level) {
+            FluidStatus {
+                fluid_level: LAVA_LEVEL,
+                fluid: self.lava,
+            }
+        } else {
+            self.sea
+        }
+    }
+
+    fn grid_index(&self, grid_x: i32, grid_y: i32, grid_z: i32) -> usize {
+        let x = grid_x - self.min_grid_x;
+        let y = grid_y - self.min_grid_y;
+        let z = grid_z - self.min_grid_z;
+        ((y * self.grid_size_z + z) * self.grid_size_x + x) as usize
+    }
+
+    fn aquifer_location(
+        &mut self,
+        index: usize,
+        grid_x: i32,
+        grid_y: i32,
+        grid_z: i32,
+    ) -> (i32, i32, i32) {
+        if let Some(location) = self.locations[index] {
+            return location;
+        }
+        let mut rand = self
+            .world_state
+            .aquifer_random
+            .pos_to_rand(grid_x, grid_y, grid_z);
+        let location = (
+            grid_x * X_SPACING + rand.next_i32_range(X_RANGE),
+            grid_y * Y_SPACING + rand.next_i32_range(Y_RANGE),
+            grid_z * Z_SPACING + rand.next_i32_range(Z_RANGE),
+        );
+        self.locations[index] = Some(location);
+        location
+    }
+
+    fn aquifer_status(&mut self, index: usize) -> FluidStatus {
+        if let Some(status) = self.statuses[index] {
+            return status;
+        }
+        let (x, y, z) = self.locations[index].unwrap();
+        let status = self.compute_fluid(x, y, z);
+        self.statuses[index] = Some(status);
+        status
+    }
+
+    fn preliminary_surface_level(&mut self, x: i32, z: i32) -> i32 {
+        let (world_state, dimension) = (self.world_state, self.dimension);
+        *self
+            .preliminary_levels
+            .entry((x & !3, z & !3))
+            .or_insert_with(|| world_state.preliminary_surface_level(x, z, dimension))
+    }
+
+    /// Fluid of the aquifer centered at a position, aquifers close to the surface take the
+    /// fluid of the sea while deeper ones get a level of their own
+    fn compute_fluid(&mut self, x: i32, y: i32, z: i32) -> FluidStatus {
+        let global_fluid = self.global_fluid(y);
+        let mut min_surface_level = i32::MAX;
+        let top = y + 12;
+        let bottom = y - 12;
+        let mut surface_flooded = false;
+        for (offset_x, offset_z) in SURFACE_SAMPLING_OFFSETS_IN_CHUNKS {
+            let sample_x = x + offset_x * 16;
+            let sample_z = z + offset_z * 16;
+            let surface_level = self.preliminary_surface_level(sample_x, sample_z);
+            let level = surface_level.wrapping_add(8);
+            let is_center = offset_x == 0 && offset_z == 0;
+            if is_center && bottom > level {
+                return global_fluid;
+            }
+            let above_surface = top > level;
+            if above_surface || is_center {
+                let fluid = self.global_fluid(level);
+                if self.fluid_at(fluid, level) != self.air {
+                    if is_center {
+                        surface_flooded = true;
+                    }
+                    if above_surface {
+                        return fluid;
+                    }
+                }
+            }
+            min_surface_level = min_surface_level.min(surface_level);
+        }
+        let fluid_level =
+            self.compute_surface_level(x, y, z, global_fluid, min_surface_level, surface_flooded);
+        FluidStatus {
+            fluid_level,
+            fluid: self.compute_fluid_type(x, y, z, global_fluid, fluid_level),
+        }
+    }
+
+    fn compute_surface_level(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        global_fluid: FluidStatus,
+        min_surface_level: i32,
+        surface_flooded: bool,
+    ) -> i32 {
+        let functions = self.functions.as_ref().unwrap();
+        let mut args = DensityFnArgs::new(x, y, z, self.dimension);
+        // Deep dark regions never have fluid
+        let (partially_flooded, fully_flooded) = if functions.erosion.compute(&mut args)
+            < -0.225f32 as f64
+            && functions.depth.compute(&mut args) > 0.9f32 as f64
+        {
+            (-1.0, -1.0)
+        } else {
+            let distance_below_surface = min_surface_level.wrapping_add(8).wrapping_sub(y);
+            let surface_factor = if surface_flooded {
+                clamped_map_f64(distance_below_surface as f64, 0.0, 64.0, 1.0, 0.0)
+            } else {
+                0.0
+            };
+            let floodedness = functions.floodedness.compute(&mut args).clamp(-1.0, 1.0);
+            let full_threshold = map_f64(surface_factor, 1.0, 0.0, -0.3, 0.8);
+            let partial_threshold = map_f64(surface_factor, 1.0, 0.0, -0.8, 0.4);
+            (
+                floodedness - partial_threshold,
+                floodedness - full_threshold,
+            )
+        };
+        if fully_flooded > 0.0 {
+            global_fluid.fluid_level
+        } else if partially_flooded > 0.0 {
+            self.randomized_fluid_level(x, y, z, min_surface_level)
+        } else {
+            WAY_BELOW_MIN_Y
+        }
+    }
+
+    fn randomized_fluid_level(&self, x: i32, y: i32, z: i32, min_surface_level: i32) -> i32 {
+        let functions = self.functions.as_ref().unwrap();
+        let grid_x = x.div_euclid(16);
+        let grid_y = y.div_euclid(40);
+        let grid_z = z.div_euclid(16);
+        let center = grid_y * 40 + 20;
+        let mut args = DensityFnArgs::new(grid_x, grid_y, grid_z, self.dimension);
+        let spread = functions.spread.compute(&mut args) * 10.0;
+        let offset = (spread / 3.0).floor() as i32 * 3;
+        min_surface_level.min(center + offset)
+    }
+
+    /// Deep aquifers of water can turn into lava
+    fn compute_fluid_type(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        global_fluid: FluidStatus,
+        fluid_level: i32,
+    ) -> u32 {
+        if fluid_level <= -10 && fluid_level != WAY_BELOW_MIN_Y && global_fluid.fluid != self.lava {
+            let functions = self.functions.as_ref().unwrap();
+            let mut args = DensityFnArgs::new(
+                x.div_euclid(64),
+                y.div_euclid(40),
+                z.div_euclid(64),
+                self.dimension,
+            );
+            if functions.lava.compute(&mut args).abs() > 0.3 {
+                return self.lava;
+            }
+        }
+        global_fluid.fluid
+    }
+
+    /// Keeps a barrier of terrain between aquifers of different fluid levels
+    fn pressure(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        barrier: &mut Option<f64>,
+        first: FluidStatus,
+        second: FluidStatus,
+    ) -> f64 {
+        let first_block = self.fluid_at(first, y);
+        let second_block = self.fluid_at(second, y);
+        if (first_block == self.lava && second_block == self.water)
+            || (first_block == self.water && second_block == self.lava)
+        {
+            return 2.0;
+        }
+        let level_difference = (first.fluid_level - second.fluid_level).abs();
+        if level_difference == 0 {
+            return 0.0;
+        }
+        let middle = 0.5 * (first.fluid_level + second.fluid_level) as f64;
+        let offset = y as f64 + 0.5 - middle;
+        let half_difference = level_difference as f64 / 2.0;
+        let distance = half_difference - offset.abs();
+        let pressure = if offset > 0.0 {
+            if distance > 0.0 {
+                distance / 1.5
+            } else {
+                distance / 2.5
+            }
+        } else {
+            let distance = 3.0 + distance;
+            if distance > 0.0 {
+                distance / 3.0
+            } else {
+                distance / 10.0
+            }
+        };
+        let barrier = if (-2.0..=2.0).contains(&pressure) {
+            *barrier.get_or_insert_with(|| {
+                let functions = self.functions.as_ref().unwrap();
+                functions
+                    .barrier
+                    .compute(&mut DensityFnArgs::new(x, y, z, self.dimension))
+            })
+        } else {
+            0.0
+        };
+        2.0 * (barrier + pressure)
+    }
+}
+
+fn similarity(first_distance: i32, second_distance: i32) -> f64 {
+    1.0 - (second_distance - first_distance).abs() as f64 / 25.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aquifer_similarity_fades_with_distance() {
+        assert_eq!(similarity(0, 0), 1.0);
+        assert_eq!(similarity(40, 50), 0.6);
+        // Only the difference between the distances matters, in either order
+        assert_eq!(similarity(140, 150), similarity(50, 40));
+        assert_eq!(similarity(0, 25), 0.0);
+        assert!(similarity(0, 30) < 0.0);
+    }
+}
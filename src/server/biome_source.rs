@@ -0,0 +1,1677 @@
+use super::chunk_system::{Chunk, ChunkBiomes, BIOMES, BIOME_MAPPINGS};
+use super::level::WorldBiomeSource;
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use super::randomness::LCG48;
+use super::terrain_gen::func_deserialize::{DensityArg, DensityFnArgs};
+use super::terrain_gen::noise_generator::PerlinSimplexNoise;
+use crate::{JAR_RESOURCES_DIR, MC_VERSION};
+use ahash::AHashMap;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+/// Number of climate parameters, the last one is the offset which is always 0 when sampled
+const PARAMETER_COUNT: usize = 7;
+const CHILDREN_PER_NODE: usize = 6;
+
+/// Converts a climate value into the fixed point representation used by the biome search
+pub fn quantize(value: f32) -> i64 {
+    (value * 10000.0) as i64
+}
+
+/// Quantized range of a single climate parameter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClimateParameter {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl ClimateParameter {
+    pub fn point(value: f32) -> Self {
+        Self::span(value, value)
+    }
+
+    pub fn span(min: f32, max: f32) -> Self {
+        ClimateParameter {
+            min: quantize(min),
+            max: quantize(max),
+        }
+    }
+
+    /// Range from the minimum of `self` to the maximum of `to`
+    fn to(self, to: ClimateParameter) -> Self {
+        ClimateParameter {
+            min: self.min,
+            max: to.max,
+        }
+    }
+
+    /// Smallest range containing both ranges
+    fn union(self, other: ClimateParameter) -> Self {
+        ClimateParameter {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    fn distance(&self, value: i64) -> i64 {
+        let above = value - self.max;
+        let below = self.min - value;
+        if above > 0 {
+            above
+        } else {
+            below.max(0)
+        }
+    }
+
+    fn midpoint(&self) -> i64 {
+        (self.min + self.max) / 2
+    }
+}
+
+/// Climate parameters in the order temperature, humidity, continentalness, erosion, depth,
+/// weirdness and offset
+pub type ParameterPoint = [ClimateParameter; PARAMETER_COUNT];
+
+fn parameter_point(
+    temperature: ClimateParameter,
+    humidity: ClimateParameter,
+    continentalness: ClimateParameter,
+    erosion: ClimateParameter,
+    depth: ClimateParameter,
+    weirdness: ClimateParameter,
+    offset: f32,
+) -> ParameterPoint {
+    [
+        temperature,
+        humidity,
+        continentalness,
+        erosion,
+        depth,
+        weirdness,
+        ClimateParameter::point(offset),
+    ]
+}
+
+/// Entry of a parameter list as found in `multi_noise_biome_source_parameter_list` files or
+/// inline in `level.dat`
+#[derive(Deserialize, Debug)]
+pub struct BiomeParameters {
+    pub biome: String,
+    pub parameters: BiomeParameterPoint,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BiomeParameterPoint {
+    pub temperature: ParameterValue,
+    pub humidity: ParameterValue,
+    pub continentalness: ParameterValue,
+    pub erosion: ParameterValue,
+    pub depth: ParameterValue,
+    pub weirdness: ParameterValue,
+    pub offset: f32,
+}
+
+impl BiomeParameterPoint {
+    fn to_point(&self) -> ParameterPoint {
+        parameter_point(
+            self.temperature.to_parameter(),
+            self.humidity.to_parameter(),
+            self.continentalness.to_parameter(),
+            self.erosion.to_parameter(),
+            self.depth.to_parameter(),
+            self.weirdness.to_parameter(),
+            self.offset,
+        )
+    }
+}
+
+/// A climate parameter written either as a single value or as `[min, max]`
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ParameterValue {
+    Point(f32),
+    Span([f32; 2]),
+}
+
+impl ParameterValue {
+    fn to_parameter(&self) -> ClimateParameter {
+        match self {
+            Self::Point(value) => ClimateParameter::point(*value),
+            Self::Span([min, max]) => ClimateParameter::span(*min, *max),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ParameterListFile {
+    preset: Option<String>,
+    biomes: Option<Vec<BiomeParameters>>,
+}
+
+/// Biomes of a dimension and the climate each one is placed in
+#[derive(Debug)]
+pub struct BiomeParameterList {
+    root: RTreeNode,
+    /// Every biome of the list once, in the order they first appear
+    biomes: Vec<u16>,
+}
+
+impl BiomeParameterList {
+    pub fn new(entries: Vec<(ParameterPoint, u16)>) -> Option<Self> {
+        if entries.is_empty() {
+            return None;
+        }
+        let mut biomes = Vec::new();
+        for (_, biome) in &entries {
+            if !biomes.contains(biome) {
+                biomes.push(*biome);
+            }
+        }
+        let leaves = entries
+            .into_iter()
+            .map(|(space, biome)| RTreeLeaf { space, biome })
+            .collect();
+        Some(BiomeParameterList {
+            root: RTreeNode::build(leaves),
+            biomes,
+        })
+    }
+
+    /// Resolves a parameter list by name, e.g. `minecraft:overworld`, from the worldgen files or
+    /// the presets built into the game
+    pub fn from_name(name: &str) -> Option<Self> {
+        let file_name = name.split_once(':').map_or(name, |(_, path)| path);
+        let data = std::fs::read_to_string(format!(
+            "versions/{MC_VERSION}/minecraft/worldgen/multi_noise_biome_source_parameter_list/{file_name}.json"
+        ));
+        let Ok(data) = data else {
+            return Self::from_preset(name);
+        };
+        let file: ParameterListFile = match serde_json::from_str(&data) {
+            Ok(file) => file,
+            Err(e) => {
+                LOGGER.get().unwrap().println_as(
+                    &format!("Invalid biome parameter list {file_name}: {e}"),
+                    LogDomain::Server,
+                    LogLevel::Error,
+                );
+                return None;
+            }
+        };
+        match (file.preset, file.biomes) {
+            (_, Some(biomes)) => Self::from_entries(&biomes),
+            (Some(preset), None) => Self::from_preset(&preset),
+            (None, None) => None,
+        }
+    }
+
+    pub fn from_entries(entries: &[BiomeParameters]) -> Option<Self> {
+        Self::new(
+            entries
+                .iter()
+                .filter_map(|entry| Some((entry.parameters.to_point(), biome_id(&entry.biome)?)))
+                .collect(),
+        )
+    }
+
+    /// Parameter lists defined in code by the game rather than in data files
+    pub fn from_preset(preset: &str) -> Option<Self> {
+        let entries = match preset.strip_prefix("minecraft:").unwrap_or(preset) {
+            "overworld" => OverworldBiomeBuilder::new().build(),
+            "nether" => nether_biomes(),
+            _ => return None,
+        };
+        Self::new(
+            entries
+                .into_iter()
+                .filter_map(|(point, biome)| Some((point, biome_id(biome)?)))
+                .collect(),
+        )
+    }
+
+    /// Biome closest to the target climate, `last` is the result of the previous search which
+    /// is kept on ties like vanilla
+    fn find_biome<'a>(
+        &'a self,
+        target: &[i64; PARAMETER_COUNT],
+        last: &mut Option<&'a RTreeLeaf>,
+    ) -> u16 {
+        let leaf = self.root.search(target, *last);
+        *last = Some(leaf);
+        leaf.biome
+    }
+}
+
+pub fn biome_id(name: &str) -> Option<u16> {
+    let id = BIOME_MAPPINGS
+        .get(name.split_once(':').map_or(name, |(_, path)| path))
+        .copied();
+    if id.is_none() {
+        LOGGER.get().unwrap().println_as(
+            &format!("Unknown biome {name}"),
+            LogDomain::Server,
+            LogLevel::Warn,
+        );
+    }
+    id
+}
+
+#[derive(Clone, Copy, Debug)]
+struct RTreeLeaf {
+    space: ParameterPoint,
+    biome: u16,
+}
+
+impl RTreeLeaf {
+    fn distance(&self, target: &[i64; PARAMETER_COUNT]) -> i64 {
+        space_distance(&self.space, target)
+    }
+}
+
+/// Node of the tree the nearest biome is searched in, built the same way as vanilla so ties
+/// between equally distant biomes resolve to the same biome
+#[derive(Debug)]
+enum RTreeNode {
+    Leaf(RTreeLeaf),
+    SubTree {
+        space: ParameterPoint,
+        children: Vec<RTreeNode>,
+    },
+}
+
+impl RTreeNode {
+    fn build(mut leaves: Vec<RTreeLeaf>) -> Self {
+        if leaves.len() == 1 {
+            return RTreeNode::Leaf(leaves[0]);
+        }
+        if leaves.len() <= CHILDREN_PER_NODE {
+            leaves.sort_by_key(|leaf| {
+                leaf.space
+                    .iter()
+                    .map(|parameter| parameter.midpoint().abs())
+                    .sum::<i64>()
+            });
+            return Self::subtree(leaves.into_iter().map(RTreeNode::Leaf).collect());
+        }
+        let mut best_cost = i64::MAX;
+        let mut best = None;
+        for axis in 0..PARAMETER_COUNT {
+            sort_by_axis(&mut leaves, |leaf| &leaf.space, axis, false);
+            let buckets = bucketize(&leaves);
+            let cost = buckets
+                .iter()
+                .map(|(space, _)| {
+                    space
+                        .iter()
+                        .map(|parameter| (parameter.max - parameter.min).abs())
+                        .sum::<i64>()
+                })
+                .sum::<i64>();
+            if best_cost > cost {
+                best_cost = cost;
+                best = Some((axis, buckets));
+            }
+        }
+        let (axis, mut buckets) = best.unwrap();
+        sort_by_axis(&mut buckets, |(space, _)| space, axis, true);
+        Self::subtree(
+            buckets
+                .into_iter()
+                .map(|(_, bucket)| Self::build(bucket))
+                .collect(),
+        )
+    }
+
+    fn subtree(children: Vec<RTreeNode>) -> Self {
+        let mut space = *children[0].space();
+        for child in &children[1..] {
+            for (parameter, child_parameter) in space.iter_mut().zip(child.space()) {
+                *parameter = parameter.union(*child_parameter);
+            }
+        }
+        RTreeNode::SubTree { space, children }
+    }
+
+    fn space(&self) -> &ParameterPoint {
+        match self {
+            RTreeNode::Leaf(leaf) => &leaf.space,
+            RTreeNode::SubTree { space, .. } => space,
+        }
+    }
+
+    fn search<'a>(
+        &'a self,
+        target: &[i64; PARAMETER_COUNT],
+        candidate: Option<&'a RTreeLeaf>,
+    ) -> &'a RTreeLeaf {
+        let children = match self {
+            RTreeNode::Leaf(leaf) => return leaf,
+            RTreeNode::SubTree { children, .. } => children,
+        };
+        let mut best_distance = candidate.map_or(i64::MAX, |leaf| leaf.distance(target));
+        let mut best = candidate;
+        for child in children {
+            let distance = space_distance(child.space(), target);
+            if best_distance > distance {
+                let leaf = child.search(target, best);
+                let leaf_distance = match child {
+                    RTreeNode::Leaf(_) => distance,
+                    RTreeNode::SubTree { .. } => leaf.distance(target),
+                };
+                if best_distance > leaf_distance {
+                    best_distance = leaf_distance;
+                    best = Some(leaf);
+                }
+            }
+        }
+        best.unwrap()
+    }
+}
+
+fn space_distance(space: &ParameterPoint, target: &[i64; PARAMETER_COUNT]) -> i64 {
+    space
+        .iter()
+        .zip(target)
+        .map(|(parameter, value)| parameter.distance(*value).pow(2))
+        .sum()
+}
+
+/// Stable sort by the midpoints of one parameter, ties are broken by the following parameters
+fn sort_by_axis<T>(
+    nodes: &mut [T],
+    space: impl Fn(&T) -> &ParameterPoint,
+    axis: usize,
+    absolute: bool,
+) {
+    let key = |node: &T, i: usize| {
+        let midpoint = space(node)[(axis + i) % PARAMETER_COUNT].midpoint();
+        if absolute {
+            midpoint.abs()
+        } else {
+            midpoint
+        }
+    };
+    nodes.sort_by(|a, b| {
+        (0..PARAMETER_COUNT)
+            .map(|i| key(a, i).cmp(&key(b, i)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+}
+
+/// Splits sorted leaves into groups sized by the largest power of `CHILDREN_PER_NODE` below the
+/// number of leaves, along with the space each group covers
+fn bucketize(leaves: &[RTreeLeaf]) -> Vec<(ParameterPoint, Vec<RTreeLeaf>)> {
+    let base = CHILDREN_PER_NODE as f64;
+    let bucket_size =
+        base.powi((((leaves.len() as f64 - 0.01).ln() / base.ln()).floor()) as i32) as usize;
+    leaves
+        .chunks(bucket_size)
+        .map(|bucket| {
+            let mut space = bucket[0].space;
+            for leaf in &bucket[1..] {
+                for (parameter, leaf_parameter) in space.iter_mut().zip(leaf.space) {
+                    *parameter = parameter.union(leaf_parameter);
+                }
+            }
+            (space, bucket.to_vec())
+        })
+        .collect()
+}
+
+/// Density functions of the noise router that make up the climate at a position
+pub struct ClimateSampler<'a> {
+    temperature: &'a DensityArg,
+    humidity: &'a DensityArg,
+    continentalness: &'a DensityArg,
+    erosion: &'a DensityArg,
+    depth: &'a DensityArg,
+    weirdness: &'a DensityArg,
+}
+
+impl<'a> ClimateSampler<'a> {
+    pub fn new(noise_router: &'a AHashMap<String, DensityArg>) -> Option<Self> {
+        Some(ClimateSampler {
+            temperature: noise_router.get("temperature")?,
+            humidity: noise_router.get("vegetation")?,
+            continentalness: noise_router.get("continents")?,
+            erosion: noise_router.get("erosion")?,
+            depth: noise_router.get("depth")?,
+            weirdness: noise_router.get("ridges")?,
+        })
+    }
+
+    /// Quantized climate at a position in quart (4 block) coordinates
+    pub fn sample(
+        &self,
+        quart_x: i32,
+        quart_y: i32,
+        quart_z: i32,
+        dimension: &str,
+    ) -> [i64; PARAMETER_COUNT] {
+        let mut args = DensityFnArgs::new(quart_x << 2, quart_y << 2, quart_z << 2, dimension);
+        let mut sample = |function: &DensityArg| quantize(function.compute(&mut args) as f32);
+        [
+            sample(self.temperature),
+            sample(self.humidity),
+            sample(self.continentalness),
+            sample(self.erosion),
+            sample(self.depth),
+            sample(self.weirdness),
+            0,
+        ]
+    }
+}
+
+/// Places the biomes of generated chunks
+#[derive(Debug)]
+pub enum BiomeSource {
+    /// Biome nearest to the sampled climate
+    MultiNoise(BiomeParameterList),
+    Fixed(u16),
+    /// The end, its outer islands are placed by the erosion of the end islands noise
+    TheEnd([u16; 5]),
+}
+
+/// Biomes of the end source, in the order vanilla collects them
+const END_BIOMES: [&str; 5] = [
+    "the_end",
+    "end_highlands",
+    "end_midlands",
+    "small_end_islands",
+    "end_barrens",
+];
+
+impl BiomeSource {
+    pub fn new(source: &WorldBiomeSource) -> Option<Self> {
+        let source_type = source.noise_type.trim_start_matches("minecraft:");
+        let biome_source = match source_type {
+            "multi_noise" => if let Some(biomes) = &source.biomes {
+                BiomeParameterList::from_entries(biomes)
+            } else {
+                source
+                    .preset
+                    .as_deref()
+                    .and_then(BiomeParameterList::from_name)
+            }
+            .map(BiomeSource::MultiNoise),
+            "fixed" => source
+                .biome
+                .as_deref()
+                .and_then(biome_id)
+                .map(BiomeSource::Fixed),
+            "the_end" => END_BIOMES
+                .iter()
+                .map(|name| biome_id(name))
+                .collect::<Option<Vec<u16>>>()
+                .map(|ids| BiomeSource::TheEnd(ids.try_into().unwrap())),
+            _ => None,
+        };
+        if biome_source.is_none() {
+            LOGGER.get().unwrap().println_as(
+                &format!(
+                    "Unsupported biome source {source_type}, generated chunks use a placeholder biome"
+                ),
+                LogDomain::Server,
+                LogLevel::Warn,
+            );
+        }
+        biome_source
+    }
+
+    /// Biomes the source can place, in the order vanilla collects them
+    pub fn possible_biomes(&self) -> &[u16] {
+        match self {
+            BiomeSource::Fixed(biome) => std::slice::from_ref(biome),
+            BiomeSource::MultiNoise(parameters) => &parameters.biomes,
+            BiomeSource::TheEnd(biomes) => biomes,
+        }
+    }
+
+    /// Biome at a position in quart (4 block) coordinates
+    pub fn noise_biome(
+        &self,
+        noise_router: &AHashMap<String, DensityArg>,
+        dimension: &str,
+        quart_x: i32,
+        quart_y: i32,
+        quart_z: i32,
+    ) -> Option<u16> {
+        match self {
+            BiomeSource::Fixed(biome) => Some(*biome),
+            BiomeSource::MultiNoise(parameters) => {
+                let target =
+                    ClimateSampler::new(noise_router)?.sample(quart_x, quart_y, quart_z, dimension);
+                Some(parameters.find_biome(&target, &mut None))
+            }
+            BiomeSource::TheEnd(biomes) => {
+                let erosion = noise_router.get("erosion")?;
+                Some(end_biome(
+                    biomes, erosion, dimension, quart_x, quart_y, quart_z,
+                ))
+            }
+        }
+    }
+
+    /// Fills the biomes of every section of a generated chunk, the climate is only sampled when
+    /// the chunk is placed by multi noise
+    pub fn fill_biomes(
+        &self,
+        chunk: &mut Chunk,
+        noise_router: &AHashMap<String, DensityArg>,
+        dimension: &str,
+    ) {
+        let parameters = match self {
+            BiomeSource::Fixed(biome) => {
+                for section in chunk.sections.iter_mut() {
+                    section.biomes = ChunkBiomes::from_biome_ids(&[*biome]);
+                }
+                return;
+            }
+            BiomeSource::MultiNoise(parameters) => parameters,
+            BiomeSource::TheEnd(biomes) => {
+                let Some(erosion) = noise_router.get("erosion") else {
+                    return;
+                };
+                let (quart_x, quart_z) = (chunk.x << 2, chunk.z << 2);
+                for section in chunk.sections.iter_mut() {
+                    let quart_y = (section.y as i32) << 2;
+                    let mut biome_ids = [0u16; 64];
+                    for y in 0..4 {
+                        for z in 0..4 {
+                            for x in 0..4 {
+                                biome_ids[(y << 4 | z << 2 | x) as usize] = end_biome(
+                                    biomes,
+                                    erosion,
+                                    dimension,
+                                    quart_x + x,
+                                    quart_y + y,
+                                    quart_z + z,
+                                );
+                            }
+                        }
+                    }
+                    section.biomes = ChunkBiomes::from_biome_ids(&biome_ids);
+                }
+                return;
+            }
+        };
+        let Some(sampler) = ClimateSampler::new(noise_router) else {
+            return;
+        };
+        let quart_x = chunk.x << 2;
+        let quart_z = chunk.z << 2;
+        let mut last = None;
+        for section in chunk.sections.iter_mut() {
+            let quart_y = (section.y as i32) << 2;
+            let mut biome_ids = [0u16; 64];
+            for y in 0..4 {
+                for z in 0..4 {
+                    for x in 0..4 {
+                        let target =
+                            sampler.sample(quart_x + x, quart_y + y, quart_z + z, dimension);
+                        biome_ids[(y << 4 | z << 2 | x) as usize] =
+                            parameters.find_biome(&target, &mut last);
+                    }
+                }
+            }
+            section.biomes = ChunkBiomes::from_biome_ids(&biome_ids);
+        }
+    }
+}
+
+/// End biome at a position in quart coordinates, everything within 1024 blocks of the origin is
+/// the central island and the outer islands are split by the erosion at the section center
+fn end_biome(
+    biomes: &[u16; 5],
+    erosion: &DensityArg,
+    dimension: &str,
+    quart_x: i32,
+    quart_y: i32,
+    quart_z: i32,
+) -> u16 {
+    let section_x = quart_x >> 2;
+    let section_z = quart_z >> 2;
+    if (section_x as i64).pow(2) + (section_z as i64).pow(2) <= 4096 {
+        return biomes[0];
+    }
+    let mut args = DensityFnArgs::new(
+        (section_x * 2 + 1) * 8,
+        quart_y << 2,
+        (section_z * 2 + 1) * 8,
+        dimension,
+    );
+    let erosion = erosion.compute(&mut args);
+    if erosion > 0.25 {
+        biomes[1]
+    } else if erosion >= -0.0625 {
+        biomes[2]
+    } else if erosion < -0.21875 {
+        biomes[3]
+    } else {
+        biomes[4]
+    }
+}
+
+/// Climate of every biome read from its worldgen file, indexed by biome id
+pub static BIOME_CLIMATES: Lazy<Vec<BiomeClimate>> = Lazy::new(|| {
+    BIOMES
+        .iter()
+        .map(|name| {
+            std::fs::read_to_string(format!("{JAR_RESOURCES_DIR}/worldgen/biome/{name}.json"))
+                .ok()
+                .and_then(|data| serde_json::from_str(&data).ok())
+                .unwrap_or_default()
+        })
+        .collect()
+});
+
+static TEMPERATURE_NOISE: Lazy<PerlinSimplexNoise> =
+    Lazy::new(|| PerlinSimplexNoise::new(&mut LCG48::new(1234), &[0]));
+static FROZEN_TEMPERATURE_NOISE: Lazy<PerlinSimplexNoise> =
+    Lazy::new(|| PerlinSimplexNoise::new(&mut LCG48::new(3456), &[-2, -1, 0]));
+/// Noise of the biome temperatures and of the noise based feature counts
+pub static BIOME_INFO_NOISE: Lazy<PerlinSimplexNoise> =
+    Lazy::new(|| PerlinSimplexNoise::new(&mut LCG48::new(2345), &[0]));
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TemperatureModifier {
+    #[default]
+    None,
+    Frozen,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct BiomeClimate {
+    pub temperature: f32,
+    #[serde(default)]
+    pub temperature_modifier: TemperatureModifier,
+}
+
+impl Default for BiomeClimate {
+    fn default() -> Self {
+        BiomeClimate {
+            temperature: 0.5,
+            temperature_modifier: TemperatureModifier::None,
+        }
+    }
+}
+
+impl BiomeClimate {
+    /// Temperature at a block position, it drops above 17 blocks over the sea level
+    pub fn temperature_at(&self, x: i32, y: i32, z: i32, sea_level: i32) -> f32 {
+        let temperature = match self.temperature_modifier {
+            TemperatureModifier::None => self.temperature,
+            TemperatureModifier::Frozen => {
+                let frozen =
+                    FROZEN_TEMPERATURE_NOISE.get_val(x as f64 * 0.05, z as f64 * 0.05, false) * 7.0;
+                let info = BIOME_INFO_NOISE.get_val(x as f64 * 0.2, z as f64 * 0.2, false);
+                if frozen + info < 0.3
+                    && BIOME_INFO_NOISE.get_val(x as f64 * 0.09, z as f64 * 0.09, false) < 0.8
+                {
+                    0.2
+                } else {
+                    self.temperature
+                }
+            }
+        };
+        let snow_line = sea_level + 17;
+        if y > snow_line {
+            let noise =
+                (TEMPERATURE_NOISE.get_val((x as f32 / 8.0) as f64, (z as f32 / 8.0) as f64, false)
+                    * 8.0) as f32;
+            temperature - (noise + y as f32 - snow_line as f32) * 0.05 / 40.0
+        } else {
+            temperature
+        }
+    }
+
+    pub fn cold_enough_to_snow(&self, x: i32, y: i32, z: i32, sea_level: i32) -> bool {
+        self.temperature_at(x, y, z, sea_level) < 0.15
+    }
+}
+
+/// Seed of the biome zoom, the first 8 bytes of the sha256 hash of the world seed
+pub fn obfuscate_seed(seed: i64) -> i64 {
+    let hash = openssl::sha::sha256(&seed.to_le_bytes());
+    i64::from_le_bytes(hash[..8].try_into().unwrap())
+}
+
+/// Biome at a block position, picks one of the 8 surrounding quart biomes with a seeded jitter
+/// so biome borders are not aligned to the 4 block grid
+pub fn zoomed_biome(
+    zoom_seed: i64,
+    x: i32,
+    y: i32,
+    z: i32,
+    mut noise_biome: impl FnMut(i32, i32, i32) -> u16,
+) -> u16 {
+    let (x, y, z) = (x - 2, y - 2, z - 2);
+    let (quart_x, quart_y, quart_z) = (x >> 2, y >> 2, z >> 2);
+    let (frac_x, frac_y, frac_z) = (
+        (x & 3) as f64 / 4.0,
+        (y & 3) as f64 / 4.0,
+        (z & 3) as f64 / 4.0,
+    );
+    let mut closest = 0;
+    let mut closest_distance = f64::INFINITY;
+    for corner in 0..8 {
+        let (offset_x, offset_y, offset_z) = (corner >> 2 & 1, corner >> 1 & 1, corner & 1);
+        let distance = fiddled_distance(
+            zoom_seed,
+            quart_x + offset_x,
+            quart_y + offset_y,
+            quart_z + offset_z,
+            frac_x - offset_x as f64,
+            frac_y - offset_y as f64,
+            frac_z - offset_z as f64,
+        );
+        if closest_distance > distance {
+            closest = corner;
+            closest_distance = distance;
+        }
+    }
+    noise_biome(
+        quart_x + (closest >> 2 & 1),
+        quart_y + (closest >> 1 & 1),
+        quart_z + (closest & 1),
+    )
+}
+
+fn fiddled_distance(seed: i64, x: i32, y: i32, z: i32, dx: f64, dy: f64, dz: f64) -> f64 {
+    let next = |state: i64, value: i64| {
+        state
+            .wrapping_mul(
+                state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407),
+            )
+            .wrapping_add(value)
+    };
+    let fiddle = |state: i64| ((state >> 24).rem_euclid(1024) as f64 / 1024.0 - 0.5) * 0.9;
+    let mut state = seed;
+    for value in [x, y, z, x, y, z] {
+        state = next(state, value as i64);
+    }
+    let fiddle_x = fiddle(state);
+    state = next(state, seed);
+    let fiddle_y = fiddle(state);
+    state = next(state, seed);
+    let fiddle_z = fiddle(state);
+    (dz + fiddle_z).powi(2) + (dy + fiddle_y).powi(2) + (dx + fiddle_x).powi(2)
+}
+
+fn nether_biomes() -> Vec<(ParameterPoint, &'static str)> {
+    let point = ClimateParameter::point;
+    let nether_point = |temperature: f32, humidity: f32, offset: f32| {
+        let zero = point(0.0);
+        parameter_point(
+            point(temperature),
+            point(humidity),
+            zero,
+            zero,
+            zero,
+            zero,
+            offset,
+        )
+    };
+    vec![
+        (nether_point(0.0, 0.0, 0.0), "nether_wastes"),
+        (nether_point(0.0, -0.5, 0.0), "soul_sand_valley"),
+        (nether_point(0.4, 0.0, 0.0), "crimson_forest"),
+        (nether_point(0.0, 0.5, 0.375), "warped_forest"),
+        (nether_point(-0.5, 0.0, 0.175), "basalt_deltas"),
+    ]
+}
+
+type BiomeTable = [[Option<&'static str>; 5]; 5];
+
+const MIDDLE_BIOMES: BiomeTable = [
+    [
+        Some("snowy_plains"),
+        Some("snowy_plains"),
+        Some("snowy_plains"),
+        Some("snowy_taiga"),
+        Some("taiga"),
+    ],
+    [
+        Some("plains"),
+        Some("plains"),
+        Some("forest"),
+        Some("taiga"),
+        Some("old_growth_spruce_taiga"),
+    ],
+    [
+        Some("flower_forest"),
+        Some("plains"),
+        Some("forest"),
+        Some("birch_forest"),
+        Some("dark_forest"),
+    ],
+    [
+        Some("savanna"),
+        Some("savanna"),
+        Some("forest"),
+        Some("jungle"),
+        Some("jungle"),
+    ],
+    [
+        Some("desert"),
+        Some("desert"),
+        Some("desert"),
+        Some("desert"),
+        Some("desert"),
+    ],
+];
+
+const MIDDLE_BIOMES_VARIANT: BiomeTable = [
+    [Some("ice_spikes"), None, Some("snowy_taiga"), None, None],
+    [None, None, None, None, Some("old_growth_pine_taiga")],
+    [
+        Some("sunflower_plains"),
+        None,
+        None,
+        Some("old_growth_birch_forest"),
+        None,
+    ],
+    [
+        None,
+        None,
+        Some("plains"),
+        Some("sparse_jungle"),
+        Some("bamboo_jungle"),
+    ],
+    [None, None, None, None, None],
+];
+
+const PLATEAU_BIOMES: BiomeTable = [
+    [
+        Some("snowy_plains"),
+        Some("snowy_plains"),
+        Some("snowy_plains"),
+        Some("snowy_taiga"),
+        Some("snowy_taiga"),
+    ],
+    [
+        Some("meadow"),
+        Some("meadow"),
+        Some("forest"),
+        Some("taiga"),
+        Some("old_growth_spruce_taiga"),
+    ],
+    [
+        Some("meadow"),
+        Some("meadow"),
+        Some("meadow"),
+        Some("meadow"),
+        Some("pale_garden"),
+    ],
+    [
+        Some("savanna_plateau"),
+        Some("savanna_plateau"),
+        Some("forest"),
+        Some("forest"),
+        Some("jungle"),
+    ],
+    [
+        Some("badlands"),
+        Some("badlands"),
+        Some("badlands"),
+        Some("wooded_badlands"),
+        Some("wooded_badlands"),
+    ],
+];
+
+const PLATEAU_BIOMES_VARIANT: BiomeTable = [
+    [Some("ice_spikes"), None, None, None, None],
+    [
+        Some("cherry_grove"),
+        None,
+        Some("meadow"),
+        Some("meadow"),
+        Some("old_growth_pine_taiga"),
+    ],
+    [
+        Some("cherry_grove"),
+        Some("cherry_grove"),
+        Some("forest"),
+        Some("birch_forest"),
+        None,
+    ],
+    [None, None, None, None, None],
+    [
+        Some("eroded_badlands"),
+        Some("eroded_badlands"),
+        None,
+        None,
+        None,
+    ],
+];
+
+const SHATTERED_BIOMES: BiomeTable = [
+    [
+        Some("windswept_gravelly_hills"),
+        Some("windswept_gravelly_hills"),
+        Some("windswept_hills"),
+        Some("windswept_forest"),
+        Some("windswept_forest"),
+    ],
+    [
+        Some("windswept_gravelly_hills"),
+        Some("windswept_gravelly_hills"),
+        Some("windswept_hills"),
+        Some("windswept_forest"),
+        Some("windswept_forest"),
+    ],
+    [
+        Some("windswept_hills"),
+        Some("windswept_hills"),
+        Some("windswept_hills"),
+        Some("windswept_forest"),
+        Some("windswept_forest"),
+    ],
+    [None, None, None, None, None],
+    [None, None, None, None, None],
+];
+
+const OCEANS: [[&str; 5]; 2] = [
+    [
+        "deep_frozen_ocean",
+        "deep_cold_ocean",
+        "deep_ocean",
+        "deep_lukewarm_ocean",
+        "warm_ocean",
+    ],
+    [
+        "frozen_ocean",
+        "cold_ocean",
+        "ocean",
+        "lukewarm_ocean",
+        "warm_ocean",
+    ],
+];
+
+/// Builds the overworld parameter list the same way as the game's `OverworldBiomeBuilder`, the
+/// order of the entries matters since it decides the shape of the search tree
+struct OverworldBiomeBuilder {
+    full_range: ClimateParameter,
+    temperatures: [ClimateParameter; 5],
+    humidities: [ClimateParameter; 5],
+    erosions: [ClimateParameter; 7],
+    frozen_range: ClimateParameter,
+    unfrozen_range: ClimateParameter,
+    mushroom_fields_continentalness: ClimateParameter,
+    deep_ocean_continentalness: ClimateParameter,
+    ocean_continentalness: ClimateParameter,
+    coast_continentalness: ClimateParameter,
+    inland_continentalness: ClimateParameter,
+    near_inland_continentalness: ClimateParameter,
+    mid_inland_continentalness: ClimateParameter,
+    far_inland_continentalness: ClimateParameter,
+    entries: Vec<(ParameterPoint, &'static str)>,
+}
+
+impl OverworldBiomeBuilder {
+    fn new() -> Self {
+        let span = ClimateParameter::span;
+        let temperatures = [
+            span(-1.0, -0.45),
+            span(-0.45, -0.15),
+            span(-0.15, 0.2),
+            span(0.2, 0.55),
+            span(0.55, 1.0),
+        ];
+        OverworldBiomeBuilder {
+            full_range: span(-1.0, 1.0),
+            temperatures,
+            humidities: [
+                span(-1.0, -0.35),
+                span(-0.35, -0.1),
+                span(-0.1, 0.1),
+                span(0.1, 0.3),
+                span(0.3, 1.0),
+            ],
+            erosions: [
+                span(-1.0, -0.78),
+                span(-0.78, -0.375),
+                span(-0.375, -0.2225),
+                span(-0.2225, 0.05),
+                span(0.05, 0.45),
+                span(0.45, 0.55),
+                span(0.55, 1.0),
+            ],
+            frozen_range: temperatures[0],
+            unfrozen_range: temperatures[1].to(temperatures[4]),
+            mushroom_fields_continentalness: span(-1.2, -1.05),
+            deep_ocean_continentalness: span(-1.05, -0.455),
+            ocean_continentalness: span(-0.455, -0.19),
+            coast_continentalness: span(-0.19, -0.11),
+            inland_continentalness: span(-0.11, 0.55),
+            near_inland_continentalness: span(-0.11, 0.03),
+            mid_inland_continentalness: span(0.03, 0.3),
+            far_inland_continentalness: span(0.3, 1.0),
+            entries: Vec::new(),
+        }
+    }
+
+    fn build(mut self) -> Vec<(ParameterPoint, &'static str)> {
+        self.add_off_coast_biomes();
+        self.add_inland_biomes();
+        self.add_underground_biomes();
+        self.entries
+    }
+
+    fn add_off_coast_biomes(&mut self) {
+        self.add_surface_biome(
+            self.full_range,
+            self.full_range,
+            self.mushroom_fields_continentalness,
+            self.full_range,
+            self.full_range,
+            "mushroom_fields",
+        );
+        for (i, temperature) in self.temperatures.into_iter().enumerate() {
+            self.add_surface_biome(
+                temperature,
+                self.full_range,
+                self.deep_ocean_continentalness,
+                self.full_range,
+                self.full_range,
+                OCEANS[0][i],
+            );
+            self.add_surface_biome(
+                temperature,
+                self.full_range,
+                self.ocean_continentalness,
+                self.full_range,
+                self.full_range,
+                OCEANS[1][i],
+            );
+        }
+    }
+
+    fn add_inland_biomes(&mut self) {
+        let span = ClimateParameter::span;
+        self.add_mid_slice(span(-1.0, -0.93333334));
+        self.add_high_slice(span(-0.93333334, -0.7666667));
+        self.add_peaks(span(-0.7666667, -0.56666666));
+        self.add_high_slice(span(-0.56666666, -0.4));
+        self.add_mid_slice(span(-0.4, -0.26666668));
+        self.add_low_slice(span(-0.26666668, -0.05));
+        self.add_valleys(span(-0.05, 0.05));
+        self.add_low_slice(span(0.05, 0.26666668));
+        self.add_mid_slice(span(0.26666668, 0.4));
+        self.add_high_slice(span(0.4, 0.56666666));
+        self.add_peaks(span(0.56666666, 0.7666667));
+        self.add_high_slice(span(0.7666667, 0.93333334));
+        self.add_mid_slice(span(0.93333334, 1.0));
+    }
+
+    fn add_peaks(&mut self, weirdness: ClimateParameter) {
+        let e = self.erosions;
+        let coast_to_far = self
+            .coast_continentalness
+            .to(self.far_inland_continentalness);
+        let coast_to_near = self
+            .coast_continentalness
+            .to(self.near_inland_continentalness);
+        let mid_to_far = self
+            .mid_inland_continentalness
+            .to(self.far_inland_continentalness);
+        for (i, temperature) in self.temperatures.into_iter().enumerate() {
+            for (j, humidity) in self.humidities.into_iter().enumerate() {
+                let middle = self.pick_middle_biome(i, j, weirdness);
+                let middle_or_badlands = self.pick_middle_biome_or_badlands_if_hot(i, j, weirdness);
+                let middle_or_badlands_or_slope =
+                    self.pick_middle_biome_or_badlands_if_hot_or_slope_if_cold(i, j, weirdness);
+                let plateau = self.pick_plateau_biome(i, j, weirdness);
+                let shattered = self.pick_shattered_biome(i, j, weirdness);
+                let shattered_or_savanna =
+                    self.maybe_pick_windswept_savanna_biome(i, j, weirdness, shattered);
+                let peak = self.pick_peak_biome(i, j, weirdness);
+                let mid = self.mid_inland_continentalness;
+                let far = self.far_inland_continentalness;
+                let mut add = |continentalness, erosion, biome| {
+                    self.add_surface_biome(
+                        temperature,
+                        humidity,
+                        continentalness,
+                        erosion,
+                        weirdness,
+                        biome,
+                    )
+                };
+                add(coast_to_far, e[0], peak);
+                add(coast_to_near, e[1], middle_or_badlands_or_slope);
+                add(mid_to_far, e[1], peak);
+                add(coast_to_near, e[2].to(e[3]), middle);
+                add(mid_to_far, e[2], plateau);
+                add(mid, e[3], middle_or_badlands);
+                add(far, e[3], plateau);
+                add(coast_to_far, e[4], middle);
+                add(coast_to_near, e[5], shattered_or_savanna);
+                add(mid_to_far, e[5], shattered);
+                add(coast_to_far, e[6], middle);
+            }
+        }
+    }
+
+    fn add_high_slice(&mut self, weirdness: ClimateParameter) {
+        let e = self.erosions;
+        let coast_to_far = self
+            .coast_continentalness
+            .to(self.far_inland_continentalness);
+        let coast_to_near = self
+            .coast_continentalness
+            .to(self.near_inland_continentalness);
+        let mid_to_far = self
+            .mid_inland_continentalness
+            .to(self.far_inland_continentalness);
+        for (i, temperature) in self.temperatures.into_iter().enumerate() {
+            for (j, humidity) in self.humidities.into_iter().enumerate() {
+                let middle = self.pick_middle_biome(i, j, weirdness);
+                let middle_or_badlands = self.pick_middle_biome_or_badlands_if_hot(i, j, weirdness);
+                let middle_or_badlands_or_slope =
+                    self.pick_middle_biome_or_badlands_if_hot_or_slope_if_cold(i, j, weirdness);
+                let plateau = self.pick_plateau_biome(i, j, weirdness);
+                let shattered = self.pick_shattered_biome(i, j, weirdness);
+                let middle_or_savanna =
+                    self.maybe_pick_windswept_savanna_biome(i, j, weirdness, middle);
+                let slope = self.pick_slope_biome(i, j, weirdness);
+                let peak = self.pick_peak_biome(i, j, weirdness);
+                let coast = self.coast_continentalness;
+                let near = self.near_inland_continentalness;
+                let mid = self.mid_inland_continentalness;
+                let far = self.far_inland_continentalness;
+                let mut add = |continentalness, erosion, biome| {
+                    self.add_surface_biome(
+                        temperature,
+                        humidity,
+                        continentalness,
+                        erosion,
+                        weirdness,
+                        biome,
+                    )
+                };
+                add(coast, e[0].to(e[1]), middle);
+                add(near, e[0], slope);
+                add(mid_to_far, e[0], peak);
+                add(near, e[1], middle_or_badlands_or_slope);
+                add(mid_to_far, e[1], slope);
+                add(coast_to_near, e[2].to(e[3]), middle);
+                add(mid_to_far, e[2], plateau);
+                add(mid, e[3], middle_or_badlands);
+                add(far, e[3], plateau);
+                add(coast_to_far, e[4], middle);
+                add(coast_to_near, e[5], middle_or_savanna);
+                add(mid_to_far, e[5], shattered);
+                add(coast_to_far, e[6], middle);
+            }
+        }
+    }
+
+    fn add_mid_slice(&mut self, weirdness: ClimateParameter) {
+        let e = self.erosions;
+        let coast = self.coast_continentalness;
+        let near = self.near_inland_continentalness;
+        let mid = self.mid_inland_continentalness;
+        let far = self.far_inland_continentalness;
+        self.add_surface_biome(
+            self.full_range,
+            self.full_range,
+            coast,
+            e[0].to(e[2]),
+            weirdness,
+            "stony_shore",
+        );
+        self.add_surface_biome(
+            self.temperatures[1].to(self.temperatures[2]),
+            self.full_range,
+            near.to(far),
+            e[6],
+            weirdness,
+            "swamp",
+        );
+        self.add_surface_biome(
+            self.temperatures[3].to(self.temperatures[4]),
+            self.full_range,
+            near.to(far),
+            e[6],
+            weirdness,
+            "mangrove_swamp",
+        );
+        for (i, temperature) in self.temperatures.into_iter().enumerate() {
+            for (j, humidity) in self.humidities.into_iter().enumerate() {
+                let middle = self.pick_middle_biome(i, j, weirdness);
+                let middle_or_badlands = self.pick_middle_biome_or_badlands_if_hot(i, j, weirdness);
+                let middle_or_badlands_or_slope =
+                    self.pick_middle_biome_or_badlands_if_hot_or_slope_if_cold(i, j, weirdness);
+                let shattered = self.pick_shattered_biome(i, j, weirdness);
+                let plateau = self.pick_plateau_biome(i, j, weirdness);
+                let beach = self.pick_beach_biome(i, j);
+                let middle_or_savanna =
+                    self.maybe_pick_windswept_savanna_biome(i, j, weirdness, middle);
+                let shattered_coast = self.pick_shattered_coast_biome(i, j, weirdness);
+                let slope = self.pick_slope_biome(i, j, weirdness);
+                let mut add = |continentalness, erosion, biome| {
+                    self.add_surface_biome(
+                        temperature,
+                        humidity,
+                        continentalness,
+                        erosion,
+                        weirdness,
+                        biome,
+                    )
+                };
+                add(near.to(far), e[0], slope);
+                add(near.to(mid), e[1], middle_or_badlands_or_slope);
+                add(far, e[1], if i == 0 { slope } else { plateau });
+                add(near, e[2], middle);
+                add(mid, e[2], middle_or_badlands);
+                add(far, e[2], plateau);
+                add(coast.to(near), e[3], middle);
+                add(mid.to(far), e[3], middle_or_badlands);
+                if weirdness.max < 0 {
+                    add(coast, e[4], beach);
+                    add(near.to(far), e[4], middle);
+                } else {
+                    add(coast.to(far), e[4], middle);
+                }
+                add(coast, e[5], shattered_coast);
+                add(near, e[5], middle_or_savanna);
+                add(mid.to(far), e[5], shattered);
+                if weirdness.max < 0 {
+                    add(coast, e[6], beach);
+                } else {
+                    add(coast, e[6], middle);
+                }
+                if i == 0 {
+                    add(near.to(far), e[6], middle);
+                }
+            }
+        }
+    }
+
+    fn add_low_slice(&mut self, weirdness: ClimateParameter) {
+        let e = self.erosions;
+        let coast = self.coast_continentalness;
+        let near = self.near_inland_continentalness;
+        let mid = self.mid_inland_continentalness;
+        let far = self.far_inland_continentalness;
+        self.add_surface_biome(
+            self.full_range,
+            self.full_range,
+            coast,
+            e[0].to(e[2]),
+            weirdness,
+            "stony_shore",
+        );
+        self.add_surface_biome(
+            self.temperatures[1].to(self.temperatures[2]),
+            self.full_range,
+            near.to(far),
+            e[6],
+            weirdness,
+            "swamp",
+        );
+        self.add_surface_biome(
+            self.temperatures[3].to(self.temperatures[4]),
+            self.full_range,
+            near.to(far),
+            e[6],
+            weirdness,
+            "mangrove_swamp",
+        );
+        for (i, temperature) in self.temperatures.into_iter().enumerate() {
+            for (j, humidity) in self.humidities.into_iter().enumerate() {
+                let middle = self.pick_middle_biome(i, j, weirdness);
+                let middle_or_badlands = self.pick_middle_biome_or_badlands_if_hot(i, j, weirdness);
+                let middle_or_badlands_or_slope =
+                    self.pick_middle_biome_or_badlands_if_hot_or_slope_if_cold(i, j, weirdness);
+                let beach = self.pick_beach_biome(i, j);
+                let middle_or_savanna =
+                    self.maybe_pick_windswept_savanna_biome(i, j, weirdness, middle);
+                let shattered_coast = self.pick_shattered_coast_biome(i, j, weirdness);
+                let mut add = |continentalness, erosion, biome| {
+                    self.add_surface_biome(
+                        temperature,
+                        humidity,
+                        continentalness,
+                        erosion,
+                        weirdness,
+                        biome,
+                    )
+                };
+                add(near, e[0].to(e[1]), middle_or_badlands);
+                add(mid.to(far), e[0].to(e[1]), middle_or_badlands_or_slope);
+                add(near, e[2].to(e[3]), middle);
+                add(mid.to(far), e[2].to(e[3]), middle_or_badlands);
+                add(coast, e[3].to(e[4]), beach);
+                add(near.to(far), e[4], middle);
+                add(coast, e[5], shattered_coast);
+                add(near, e[5], middle_or_savanna);
+                add(mid.to(far), e[5], middle);
+                add(coast, e[6], beach);
+                if i == 0 {
+                    add(near.to(far), e[6], middle);
+                }
+            }
+        }
+    }
+
+    fn add_valleys(&mut self, weirdness: ClimateParameter) {
+        let e = self.erosions;
+        let coast = self.coast_continentalness;
+        let near = self.near_inland_continentalness;
+        let mid = self.mid_inland_continentalness;
+        let far = self.far_inland_continentalness;
+        let inland = self.inland_continentalness;
+        let frozen = self.frozen_range;
+        let unfrozen = self.unfrozen_range;
+        let full = self.full_range;
+        let temperatures = self.temperatures;
+        let (frozen_coast, unfrozen_coast) = if weirdness.max < 0 {
+            ("stony_shore", "stony_shore")
+        } else {
+            ("frozen_river", "river")
+        };
+        let mut add = |temperature, continentalness, erosion, biome| {
+            self.add_surface_biome(
+                temperature,
+                full,
+                continentalness,
+                erosion,
+                weirdness,
+                biome,
+            )
+        };
+        add(frozen, coast, e[0].to(e[1]), frozen_coast);
+        add(unfrozen, coast, e[0].to(e[1]), unfrozen_coast);
+        add(frozen, near, e[0].to(e[1]), "frozen_river");
+        add(unfrozen, near, e[0].to(e[1]), "river");
+        add(frozen, coast.to(far), e[2].to(e[5]), "frozen_river");
+        add(unfrozen, coast.to(far), e[2].to(e[5]), "river");
+        add(frozen, coast, e[6], "frozen_river");
+        add(unfrozen, coast, e[6], "river");
+        add(
+            temperatures[1].to(temperatures[2]),
+            inland.to(far),
+            e[6],
+            "swamp",
+        );
+        add(
+            temperatures[3].to(temperatures[4]),
+            inland.to(far),
+            e[6],
+            "mangrove_swamp",
+        );
+        add(frozen, inland.to(far), e[6], "frozen_river");
+        for (i, temperature) in self.temperatures.into_iter().enumerate() {
+            for (j, humidity) in self.humidities.into_iter().enumerate() {
+                let middle_or_badlands = self.pick_middle_biome_or_badlands_if_hot(i, j, weirdness);
+                self.add_surface_biome(
+                    temperature,
+                    humidity,
+                    mid.to(far),
+                    e[0].to(e[1]),
+                    weirdness,
+                    middle_or_badlands,
+                );
+            }
+        }
+    }
+
+    fn add_underground_biomes(&mut self) {
+        let full = self.full_range;
+        let span = ClimateParameter::span;
+        self.entries.push((
+            parameter_point(full, full, span(0.8, 1.0), full, span(0.2, 0.9), full, 0.0),
+            "dripstone_caves",
+        ));
+        self.entries.push((
+            parameter_point(full, span(0.7, 1.0), full, full, span(0.2, 0.9), full, 0.0),
+            "lush_caves",
+        ));
+        self.entries.push((
+            parameter_point(
+                full,
+                full,
+                full,
+                self.erosions[0].to(self.erosions[1]),
+                ClimateParameter::point(1.1),
+                full,
+                0.0,
+            ),
+            "deep_dark",
+        ));
+    }
+
+    /// Surface biomes are placed at the surface and at the depth where underground biomes start
+    fn add_surface_biome(
+        &mut self,
+        temperature: ClimateParameter,
+        humidity: ClimateParameter,
+        continentalness: ClimateParameter,
+        erosion: ClimateParameter,
+        weirdness: ClimateParameter,
+        biome: &'static str,
+    ) {
+        for depth in [0.0, 1.0] {
+            self.entries.push((
+                parameter_point(
+                    temperature,
+                    humidity,
+                    continentalness,
+                    erosion,
+                    ClimateParameter::point(depth),
+                    weirdness,
+                    0.0,
+                ),
+                biome,
+            ));
+        }
+    }
+
+    fn pick_middle_biome(&self, t: usize, h: usize, weirdness: ClimateParameter) -> &'static str {
+        if weirdness.max < 0 {
+            MIDDLE_BIOMES[t][h].unwrap()
+        } else {
+            MIDDLE_BIOMES_VARIANT[t][h].unwrap_or_else(|| MIDDLE_BIOMES[t][h].unwrap())
+        }
+    }
+
+    fn pick_middle_biome_or_badlands_if_hot(
+        &self,
+        t: usize,
+        h: usize,
+        weirdness: ClimateParameter,
+    ) -> &'static str {
+        if t == 4 {
+            self.pick_badlands_biome(h, weirdness)
+        } else {
+            self.pick_middle_biome(t, h, weirdness)
+        }
+    }
+
+    fn pick_middle_biome_or_badlands_if_hot_or_slope_if_cold(
+        &self,
+        t: usize,
+        h: usize,
+        weirdness: ClimateParameter,
+    ) -> &'static str {
+        if t == 0 {
+            self.pick_slope_biome(t, h, weirdness)
+        } else {
+            self.pick_middle_biome_or_badlands_if_hot(t, h, weirdness)
+        }
+    }
+
+    fn maybe_pick_windswept_savanna_biome(
+        &self,
+        t: usize,
+        h: usize,
+        weirdness: ClimateParameter,
+        biome: &'static str,
+    ) -> &'static str {
+        if t > 1 && h < 4 && weirdness.max >= 0 {
+            "windswept_savanna"
+        } else {
+            biome
+        }
+    }
+
+    fn pick_shattered_coast_biome(
+        &self,
+        t: usize,
+        h: usize,
+        weirdness: ClimateParameter,
+    ) -> &'static str {
+        let biome = if weirdness.max >= 0 {
+            self.pick_middle_biome(t, h, weirdness)
+        } else {
+            self.pick_beach_biome(t, h)
+        };
+        self.maybe_pick_windswept_savanna_biome(t, h, weirdness, biome)
+    }
+
+    fn pick_beach_biome(&self, t: usize, _h: usize) -> &'static str {
+        match t {
+            0 => "snowy_beach",
+            4 => "desert",
+            _ => "beach",
+        }
+    }
+
+    fn pick_badlands_biome(&self, h: usize, weirdness: ClimateParameter) -> &'static str {
+        if h < 2 {
+            if weirdness.max < 0 {
+                "badlands"
+            } else {
+                "eroded_badlands"
+            }
+        } else if h < 3 {
+            "badlands"
+        } else {
+            "wooded_badlands"
+        }
+    }
+
+    fn pick_plateau_biome(&self, t: usize, h: usize, weirdness: ClimateParameter) -> &'static str {
+        if weirdness.max >= 0 {
+            if let Some(variant) = PLATEAU_BIOMES_VARIANT[t][h] {
+                return variant;
+            }
+        }
+        PLATEAU_BIOMES[t][h].unwrap()
+    }
+
+    fn pick_peak_biome(&self, t: usize, h: usize, weirdness: ClimateParameter) -> &'static str {
+        if t <= 2 {
+            if weirdness.max < 0 {
+                "jagged_peaks"
+            } else {
+                "frozen_peaks"
+            }
+        } else if t == 3 {
+            "stony_peaks"
+        } else {
+            self.pick_badlands_biome(h, weirdness)
+        }
+    }
+
+    fn pick_slope_biome(&self, t: usize, h: usize, weirdness: ClimateParameter) -> &'static str {
+        if t >= 3 {
+            self.pick_plateau_biome(t, h, weirdness)
+        } else if h <= 1 {
+            "snowy_slopes"
+        } else {
+            "grove"
+        }
+    }
+
+    fn pick_shattered_biome(
+        &self,
+        t: usize,
+        h: usize,
+        weirdness: ClimateParameter,
+    ) -> &'static str {
+        SHATTERED_BIOMES[t][h].unwrap_or_else(|| self.pick_middle_biome(t, h, weirdness))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::logger::ServerLogger;
+
+    /// Leaf closest to the target found by checking every leaf
+    fn nearest_leaf<'a>(node: &'a RTreeNode, target: &[i64; PARAMETER_COUNT]) -> &'a RTreeLeaf {
+        match node {
+            RTreeNode::Leaf(leaf) => leaf,
+            RTreeNode::SubTree { children, .. } => children
+                .iter()
+                .map(|child| nearest_leaf(child, target))
+                .min_by_key(|leaf| leaf.distance(target))
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn tree_search_finds_the_nearest_biome() {
+        LOGGER.get_or_init(ServerLogger::new);
+        let mut random = LCG48::new(42);
+        for preset in ["minecraft:overworld", "minecraft:nether"] {
+            let list = BiomeParameterList::from_preset(preset).unwrap();
+            let mut last = None;
+            for _ in 0..500 {
+                let mut target = [0; PARAMETER_COUNT];
+                for value in &mut target[..PARAMETER_COUNT - 1] {
+                    *value = quantize(random.next_f32() * 4.0 - 2.0);
+                }
+                let expected = nearest_leaf(&list.root, &target).distance(&target);
+                assert_eq!(list.root.search(&target, None).distance(&target), expected);
+                // Starting from the previous result does not change the distance found
+                list.find_biome(&target, &mut last);
+                assert_eq!(last.unwrap().distance(&target), expected);
+            }
+        }
+        assert!(BiomeParameterList::from_preset("minecraft:the_end").is_none());
+    }
+
+    #[test]
+    fn end_biomes_match_vanilla() {
+        use crate::server::terrain_gen::func_deserialize::DensityFnType;
+        use crate::server::terrain_gen::functions::EndIslandsFn;
+
+        LOGGER.get_or_init(ServerLogger::new);
+        let source = BiomeSource::TheEnd(END_BIOMES.map(|name| biome_id(name).unwrap()));
+        let erosion = DensityFnType::EndIslands(EndIslandsFn::with_seed(1));
+        let router = AHashMap::from([(
+            "erosion".to_owned(),
+            DensityArg::DensityFn(Box::new(erosion)),
+        )]);
+        // Biomes of seed 1 by block position, from vanilla's `TheEndBiomeSource` and
+        // `EndIslandDensityFunction`
+        let expected = [
+            (0, 0, "the_end"),
+            (1000, 0, "the_end"),
+            (-700, 700, "the_end"),
+            (1000, -300, "small_end_islands"),
+            (1024, -1500, "end_highlands"),
+            (2080, 2000, "end_highlands"),
+            (1216, 2000, "end_midlands"),
+            (1600, 2000, "end_midlands"),
+            (1120, -1500, "small_end_islands"),
+            (1312, 2000, "small_end_islands"),
+            (1024, 2000, "end_barrens"),
+            (1408, -1500, "end_barrens"),
+        ];
+        for (x, z, biome) in expected {
+            let placed = source
+                .noise_biome(&router, "minecraft:the_end", x >> 2, 16, z >> 2)
+                .unwrap();
+            assert_eq!(BIOMES[placed as usize], biome, "biome at {x} {z}");
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use super::chunk_system::Chunk;
+use super::features::{block_name, FEATURE_BLOCKS};
+use super::heightmap::HEIGHTMAP_BLOCKS;
+use ahash::AHashMap;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Marks a column or cell without a value
+const NO_VALUE: f64 = f64::MAX;
+/// Columns sampled along the north and west sides of a chunk, including the corner
+const INSIDE_COLUMNS: usize = 7;
+/// Inside columns and the 9 columns along the south and east sides
+const COLUMNS: usize = 16;
+/// Distance in quarts heights of old chunks reach into new terrain
+const HEIGHT_RANGE_CELLS: i32 = 27;
+const HEIGHT_RANGE_CHUNKS: i32 = 7;
+/// Blocks that mark the surface of an old chunk
+const SURFACE_BLOCKS: &[&str] = &[
+    "podzol",
+    "gravel",
+    "grass_block",
+    "stone",
+    "coarse_dirt",
+    "sand",
+    "red_sand",
+    "mycelium",
+    "snow_block",
+    "terracotta",
+    "dirt",
+];
+
+/// Stored with chunks generated before 1.18 so the terrain generated next to them blends into
+/// theirs, like vanilla's `BlendingData`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlendingData {
+    min_section: i32,
+    max_section: i32,
+    /// Surface heights of the columns, kept from the version the chunk was generated in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    heights: Option<Vec<f64>>,
+    /// Computed once the chunk is first blended with, from the sides facing newer chunks
+    #[serde(skip)]
+    columns: OnceCell<Arc<BlendingColumns>>,
+}
+
+/// Heights and densities of an old chunk sampled every 4 blocks along its sides. Inside columns
+/// run along the north and west sides at the chunk's own quarts, outside columns along the south
+/// and east sides at the last block of the chunk
+#[derive(Debug)]
+pub struct BlendingColumns {
+    heights: [f64; COLUMNS],
+    /// Densities of the cells of a column, from the lowest cell above the bottom of the chunk
+    densities: [Option<Vec<f64>>; COLUMNS],
+    /// Cell at the bottom of the chunk, cells are 8 blocks high
+    min_cell_y: i32,
+}
+
+impl BlendingData {
+    fn min_y(&self) -> i32 {
+        self.min_section * 16
+    }
+
+    fn max_y(&self) -> i32 {
+        self.max_section * 16
+    }
+
+    /// Samples the columns of the chunk on the sides whose neighbours are new chunks, like
+    /// vanilla's `calculateData`
+    fn columns(&self, chunk: &Chunk, is_new: impl Fn(i32, i32) -> bool) -> Arc<BlendingColumns> {
+        self.columns
+            .get_or_init(|| {
+                let sections = chunk
+                    .sections
+                    .iter()
+                    .map(|section| (section.y as i32, section.block_states.block_ids()))
+                    .collect::<Vec<_>>();
+                let block = |x: i32, y: i32, z: i32| {
+                    let (_, blocks) = sections
+                        .iter()
+                        .find(|(section_y, _)| *section_y == y.div_euclid(16))?;
+                    blocks
+                        .get((y.rem_euclid(16) << 8 | z << 4 | x) as usize)
+                        .copied()
+                };
+                let mut columns = BlendingColumns {
+                    heights: [NO_VALUE; COLUMNS],
+                    densities: Default::default(),
+                    min_cell_y: self.min_section * 2,
+                };
+                if let Some(heights) = self.heights.as_ref().filter(|h| h.len() == COLUMNS) {
+                    columns.heights.copy_from_slice(heights);
+                }
+                let mut add_column = |index: usize, x: i32, z: i32| {
+                    if columns.heights[index] == NO_VALUE {
+                        columns.heights[index] = self.surface_height(&block, x, z) as f64;
+                    }
+                    columns.densities[index] = Some(self.density_column(
+                        &block,
+                        x,
+                        z,
+                        columns.heights[index].floor() as i32,
+                    ));
+                };
+                let (north, east, south, west) =
+                    (is_new(0, -1), is_new(1, 0), is_new(0, 1), is_new(-1, 0));
+                if north || west || is_new(-1, -1) {
+                    add_column(inside_index(0, 0), 0, 0);
+                }
+                for i in 1..4 {
+                    if north {
+                        add_column(inside_index(i, 0), 4 * i, 0);
+                    }
+                    if west {
+                        add_column(inside_index(0, i), 0, 4 * i);
+                    }
+                    if east {
+                        add_column(outside_index(4, i), 15, 4 * i);
+                    }
+                }
+                if south {
+                    for i in 0..4 {
+                        add_column(outside_index(i, 4), 4 * i, 15);
+                    }
+                }
+                if east && is_new(1, -1) {
+                    add_column(outside_index(4, 0), 15, 0);
+                }
+                if east && south && is_new(1, 1) {
+                    add_column(outside_index(4, 4), 15, 15);
+                }
+                Arc::new(columns)
+            })
+            .clone()
+    }
+
+    /// Highest surface block of a column, the bottom of the chunk if there is none
+    fn surface_height(&self, block: &impl Fn(i32, i32, i32) -> Option<u32>, x: i32, z: i32) -> i32 {
+        (self.min_y()..self.max_y() - 1)
+            .rev()
+            .find(|y| block(x, *y, z).is_some_and(|b| SURFACE_BLOCKS.contains(&block_name(b))))
+            .unwrap_or(self.min_y())
+    }
+
+    /// Share of ground in the 15 blocks around every cell corner of a column, the cells at the
+    /// surface are set so the surface lands at the height of the column once interpolated
+    fn density_column(
+        &self,
+        block: &impl Fn(i32, i32, i32) -> Option<u32>,
+        x: i32,
+        z: i32,
+        height: i32,
+    ) -> Vec<f64> {
+        let cells = ((self.max_section - self.min_section) * 2) as usize;
+        let mut densities = vec![-1.0; cells];
+        let mut y = self.max_y();
+        let mut read = |count: usize| {
+            (0..count)
+                .map(|_| {
+                    y -= 1;
+                    if block(x, y, z).is_some_and(is_ground) {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                })
+                .sum::<f64>()
+        };
+        let mut above = read(7);
+        for i in (0..cells - 1).rev() {
+            let center = read(1);
+            let below = read(7);
+            densities[i] = (above + center + below) / 15.0;
+            above = below;
+        }
+        let cell = height.div_euclid(8) - (self.min_section * 2 + 1);
+        if cell >= 0 && (cell as usize) < cells - 1 {
+            let offset = (height as f64 + 0.5) % 8.0 / 8.0;
+            let ratio = (1.0 - offset) / offset;
+            let scale = ratio.max(1.0) * 0.25;
+            densities[cell as usize + 1] = -ratio / scale;
+            densities[cell as usize] = 1.0 / scale;
+        }
+        densities
+    }
+}
+
+impl BlendingColumns {
+    /// Height of the column at quarts relative to the chunk
+    fn height(&self, x: i32, _cell_y: i32, z: i32) -> f64 {
+        if x == 4 || z == 4 {
+            self.heights[outside_index(x, z)]
+        } else if x != 0 && z != 0 {
+            NO_VALUE
+        } else {
+            self.heights[inside_index(x, z)]
+        }
+    }
+
+    /// Density of a cell of the column at quarts relative to the chunk
+    fn density(&self, x: i32, cell_y: i32, z: i32) -> f64 {
+        let column = if cell_y == self.min_cell_y {
+            return 0.1;
+        } else if x == 4 || z == 4 {
+            &self.densities[outside_index(x, z)]
+        } else if x != 0 && z != 0 {
+            return NO_VALUE;
+        } else {
+            &self.densities[inside_index(x, z)]
+        };
+        let Some(densities) = column else {
+            return NO_VALUE;
+        };
+        let index = cell_y - (self.min_cell_y + 1);
+        if index >= 0 && (index as usize) < densities.len() {
+            densities[index as usize] * 0.1
+        } else {
+            NO_VALUE
+        }
+    }
+}
+
+/// Blends the terrain of new chunks into the old chunks around them, like vanilla's `Blender`.
+/// Empty when there are no old chunks nearby
+#[derive(Default, Debug)]
+pub struct Blender {
+    /// Old chunks within 7 chunks, their heights shape the terrain around them
+    height_data: AHashMap<(i32, i32), Arc<BlendingColumns>>,
+    /// Old chunks next to the generated chunk, their densities are blended in at the border
+    density_data: Vec<((i32, i32), Arc<BlendingColumns>)>,
+}
+
+impl Blender {
+    /// Collects the old chunks around a chunk from `chunk`, chunks it returns nothing for count
+    /// as new chunks
+    pub fn new(chunk_x: i32, chunk_z: i32, chunk: impl Fn(i32, i32) -> Option<Arc<Chunk>>) -> Self {
+        let old_chunk = |x: i32, z: i32| chunk(x, z).filter(|chunk| chunk.blending_data.is_some());
+        let mut blender = Blender::default();
+        for offset_x in -HEIGHT_RANGE_CHUNKS..=HEIGHT_RANGE_CHUNKS {
+            for offset_z in -HEIGHT_RANGE_CHUNKS..=HEIGHT_RANGE_CHUNKS {
+                if offset_x * offset_x + offset_z * offset_z > (HEIGHT_RANGE_CHUNKS + 1).pow(2) {
+                    continue;
+                }
+                let (x, z) = (chunk_x + offset_x, chunk_z + offset_z);
+                let Some(old) = old_chunk(x, z) else {
+                    continue;
+                };
+                let columns = old
+                    .blending_data
+                    .as_ref()
+                    .unwrap()
+                    .columns(&old, |dx, dz| old_chunk(x + dx, z + dz).is_none());
+                if offset_x.abs() <= 1 && offset_z.abs() <= 1 {
+                    blender.density_data.push(((x, z), columns.clone()));
+                }
+                blender.height_data.insert((x, z), columns);
+            }
+        }
+        blender
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.height_data.is_empty()
+    }
+
+    /// Alpha and offset of the terrain height at a block column. An alpha of 1 keeps the new
+    /// terrain, lower alphas move it towards the height of the old chunks nearby
+    pub fn blend_offset_and_factor(&self, x: i32, z: i32) -> (f64, f64) {
+        let (quart_x, quart_z) = (x >> 2, z >> 2);
+        let height = self.cell_value(quart_x, 0, quart_z, BlendingColumns::height);
+        if height != NO_VALUE {
+            return (0.0, height_to_offset(height));
+        }
+        let (mut weights, mut weighted, mut closest) = (0.0, 0.0, f64::INFINITY);
+        for (&(chunk_x, chunk_z), columns) in &self.height_data {
+            for (i, &height) in columns.heights.iter().enumerate() {
+                if height == NO_VALUE {
+                    continue;
+                }
+                let dx = (quart_x - chunk_x * 4 - column_x(i)) as f64;
+                let dz = (quart_z - chunk_z * 4 - column_z(i)) as f64;
+                let distance = (dx * dx + dz * dz).sqrt();
+                if distance > HEIGHT_RANGE_CELLS as f64 {
+                    continue;
+                }
+                closest = closest.min(distance);
+                let weight = 1.0 / distance.powi(4);
+                weighted += height * weight;
+                weights += weight;
+            }
+        }
+        if closest == f64::INFINITY {
+            return (1.0, 0.0);
+        }
+        let alpha = (closest / (HEIGHT_RANGE_CELLS + 1) as f64).clamp(0.0, 1.0);
+        (
+            3.0 * alpha * alpha - 2.0 * alpha * alpha * alpha,
+            height_to_offset(weighted / weights),
+        )
+    }
+
+    /// Moves a density towards the densities of the old chunks next to the chunk
+    pub fn blend_density(&self, x: i32, y: i32, z: i32, density: f64) -> f64 {
+        let (quart_x, cell_y, quart_z) = (x >> 2, y / 8, z >> 2);
+        let value = self.cell_value(quart_x, cell_y, quart_z, BlendingColumns::density);
+        if value != NO_VALUE {
+            return value;
+        }
+        let (mut weights, mut weighted, mut closest) = (0.0, 0.0, f64::INFINITY);
+        for ((chunk_x, chunk_z), columns) in &self.density_data {
+            let column_min_y = columns.min_cell_y + 1;
+            for (i, column) in columns.densities.iter().enumerate() {
+                let Some(densities) = column else {
+                    continue;
+                };
+                let dx = (quart_x - chunk_x * 4 - column_x(i)) as f64;
+                let dz = (quart_z - chunk_z * 4 - column_z(i)) as f64;
+                let from = (cell_y - 1 - column_min_y).max(0);
+                let to = (cell_y + 1 - column_min_y).min(densities.len() as i32);
+                for index in from..to {
+                    let dy = ((cell_y - index - column_min_y) * 2) as f64;
+                    let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+                    if distance > 2.0 {
+                        continue;
+                    }
+                    closest = closest.min(distance);
+                    let weight = 1.0 / distance.powi(4);
+                    weighted += densities[index as usize] * 0.1 * weight;
+                    weights += weight;
+                }
+            }
+        }
+        if closest == f64::INFINITY {
+            return density;
+        }
+        let blended = weighted / weights;
+        let alpha = (closest / 3.0).clamp(0.0, 1.0);
+        blended + alpha * (density - blended)
+    }
+
+    /// Value of the column at a quart, columns on the border of a chunk are also looked up in
+    /// the chunks to the north and west of it
+    fn cell_value(
+        &self,
+        quart_x: i32,
+        cell_y: i32,
+        quart_z: i32,
+        value: fn(&BlendingColumns, i32, i32, i32) -> f64,
+    ) -> f64 {
+        let (chunk_x, chunk_z) = (quart_x >> 2, quart_z >> 2);
+        let value_in = |chunk_x: i32, chunk_z: i32| {
+            self.height_data
+                .get(&(chunk_x, chunk_z))
+                .map_or(NO_VALUE, |columns| {
+                    value(
+                        columns,
+                        quart_x - chunk_x * 4,
+                        cell_y,
+                        quart_z - chunk_z * 4,
+                    )
+                })
+        };
+        let (x_border, z_border) = (quart_x & 3 == 0, quart_z & 3 == 0);
+        let mut found = value_in(chunk_x, chunk_z);
+        if found == NO_VALUE && x_border && z_border {
+            found = value_in(chunk_x - 1, chunk_z - 1);
+        }
+        if found == NO_VALUE && x_border {
+            found = value_in(chunk_x - 1, chunk_z);
+        }
+        if found == NO_VALUE && z_border {
+            found = value_in(chunk_x, chunk_z - 1);
+        }
+        found
+    }
+}
+
+/// Offset of the terrain that puts its surface at a height once interpolated
+fn height_to_offset(height: f64) -> f64 {
+    let height = height + 0.5;
+    let cell_offset = height.rem_euclid(8.0);
+    (32.0 * (height - 128.0) - 3.0 * (height - 120.0) * cell_offset
+        + 3.0 * cell_offset * cell_offset)
+        / (128.0 * (32.0 - 3.0 * cell_offset))
+}
+
+/// Whether a block counts as ground, trees and huge mushrooms are left out
+fn is_ground(block: u32) -> bool {
+    let blocks = &*FEATURE_BLOCKS;
+    let properties = HEIGHTMAP_BLOCKS[block as usize];
+    !properties.is_air
+        && !blocks.leaves.contains(block)
+        && !blocks.logs.contains(block)
+        && !matches!(
+            block_name(block),
+            "brown_mushroom_block" | "red_mushroom_block"
+        )
+        && properties.blocks_motion
+}
+
+fn inside_index(x: i32, z: i32) -> usize {
+    (3 - x + z) as usize
+}
+
+fn outside_index(x: i32, z: i32) -> usize {
+    (INSIDE_COLUMNS as i32 + x + 4 - z) as usize
+}
+
+fn column_x(index: usize) -> i32 {
+    let index = index as i32;
+    if index < INSIDE_COLUMNS as i32 {
+        (3 - index).max(0)
+    } else {
+        4 - (4 - (index - INSIDE_COLUMNS as i32)).max(0)
+    }
+}
+
+fn column_z(index: usize) -> i32 {
+    let index = index as i32;
+    if index < INSIDE_COLUMNS as i32 {
+        (index - 3).max(0)
+    } else {
+        4 - (index - INSIDE_COLUMNS as i32 - 4).max(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::chunk_system::ChunkSection;
+    use crate::server::logger::{ServerLogger, LOGGER};
+
+    #[test]
+    fn columns_are_indexed_along_the_sides() {
+        for i in 0..COLUMNS {
+            let (x, z) = (column_x(i), column_z(i));
+            let index = if i < INSIDE_COLUMNS {
+                inside_index(x, z)
+            } else {
+                outside_index(x, z)
+            };
+            assert_eq!(index, i);
+        }
+        assert_eq!(height_to_offset(127.5), 0.0);
+        assert!(height_to_offset(64.0) < height_to_offset(100.0));
+    }
+
+    #[test]
+    fn new_terrain_moves_towards_old_chunks() {
+        LOGGER.get_or_init(ServerLogger::new);
+        let old = Arc::new(Chunk {
+            sections: (-4..20).map(ChunkSection::air).collect(),
+            x: 1,
+            y: -4,
+            blending_data: Some(
+                serde_json::from_str(&format!(
+                    r#"{{"min_section": -4, "max_section": 20, "heights": {:?}}}"#,
+                    [70.0; COLUMNS]
+                ))
+                .unwrap(),
+            ),
+            ..Default::default()
+        });
+        let blender = Blender::new(0, 0, |x, z| (x == 1 && z == 0).then(|| old.clone()));
+        assert!(!blender.is_empty());
+        let offset = height_to_offset(70.0);
+        // Columns of the old chunk keep its height
+        assert_eq!(blender.blend_offset_and_factor(16, 4), (0.0, offset));
+        // New columns nearby are pulled towards it, less the further away they are
+        let (near_alpha, near_offset) = blender.blend_offset_and_factor(0, 4);
+        let (far_alpha, _) = blender.blend_offset_and_factor(-64, 4);
+        assert!(0.0 < near_alpha && near_alpha < far_alpha && far_alpha < 1.0);
+        assert!((near_offset - offset).abs() < 1e-9);
+        assert_eq!(blender.blend_offset_and_factor(-200, 4), (1.0, 0.0));
+
+        let empty = Blender::new(0, 0, |_, _| None);
+        assert!(empty.is_empty());
+        assert_eq!(empty.blend_density(3, 64, 3, 0.25), 0.25);
+    }
+}
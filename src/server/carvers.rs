@@ -0,0 +1,715 @@
+use super::aquifer::Aquifer;
+use super::chunk_system::{
+    split_block_state, Chunk, ChunkBlockStates, BIOMES, BLOCKSTATES, BLOCKSTATE_MAPPINGS,
+};
+use super::heightmap::{self, HEIGHTMAP_BLOCKS};
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use super::randomness::LCG48;
+use super::surface::SurfaceProbe;
+use super::tags::BlockSet;
+use super::util::{table_cos, table_sin};
+use super::value_providers::{FloatProvider, HeightProvider, VerticalAnchor};
+use super::world_state::WorldState;
+use crate::{RandomGenerator, JAR_RESOURCES_DIR};
+use ahash::AHashMap;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// Distance in chunks a carver can reach from the chunk it starts in
+const CARVER_RANGE: i32 = 8;
+/// Length of the tunnels in blocks before the distance factors are applied
+const TUNNEL_LENGTH: i32 = 16 * (4 * 2 - 1);
+
+/// Every configured carver of the data pack, keyed by its namespaced id
+static CONFIGURED_CARVERS: Lazy<AHashMap<String, ConfiguredCarver>> = Lazy::new(|| {
+    let mut carvers = AHashMap::new();
+    let Ok(dir) = std::fs::read_dir(format!("{JAR_RESOURCES_DIR}/worldgen/configured_carver"))
+    else {
+        return carvers;
+    };
+    for entry in dir.flatten() {
+        let path = entry.path();
+        let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let carver = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<ConfiguredCarver>(&data).ok());
+        match carver {
+            Some(carver) => {
+                carvers.insert(format!("minecraft:{name}"), carver);
+            }
+            None => LOGGER.get().unwrap().println_as(
+                &format!("Unsupported configured carver minecraft:{name}"),
+                LogDomain::Server,
+                LogLevel::Warn,
+            ),
+        }
+    }
+    carvers
+});
+
+/// Carvers of every biome in the order they run, indexed by biome id
+static BIOME_CARVERS: Lazy<Vec<Vec<&'static ConfiguredCarver>>> = Lazy::new(|| {
+    BIOMES
+        .iter()
+        .map(|name| {
+            let biome =
+                std::fs::read_to_string(format!("{JAR_RESOURCES_DIR}/worldgen/biome/{name}.json"))
+                    .ok()
+                    .and_then(|data| serde_json::from_str::<serde_json::Value>(&data).ok());
+            let ids = match biome.as_ref().and_then(|biome| biome.get("carvers")) {
+                Some(serde_json::Value::String(id)) => vec![id.as_str()],
+                Some(serde_json::Value::Array(ids)) => {
+                    ids.iter().filter_map(|id| id.as_str()).collect()
+                }
+                _ => Vec::new(),
+            };
+            ids.into_iter()
+                .filter_map(|id| CONFIGURED_CARVERS.get(id))
+                .collect()
+        })
+        .collect()
+});
+
+#[derive(Deserialize)]
+struct CarverConfig {
+    probability: f32,
+    y: HeightProvider,
+    #[serde(rename = "yScale")]
+    y_scale: FloatProvider,
+    lava_level: VerticalAnchor,
+    replaceable: BlockSet,
+}
+
+#[derive(Deserialize)]
+struct CaveCarverConfig {
+    #[serde(flatten)]
+    base: CarverConfig,
+    horizontal_radius_multiplier: FloatProvider,
+    vertical_radius_multiplier: FloatProvider,
+    floor_level: FloatProvider,
+}
+
+#[derive(Deserialize)]
+struct CanyonShape {
+    distance_factor: FloatProvider,
+    thickness: FloatProvider,
+    width_smoothness: i32,
+    horizontal_radius_factor: FloatProvider,
+    vertical_radius_default_factor: f32,
+    vertical_radius_center_factor: f32,
+}
+
+#[derive(Deserialize)]
+struct CanyonCarverConfig {
+    #[serde(flatten)]
+    base: CarverConfig,
+    vertical_rotation: FloatProvider,
+    shape: CanyonShape,
+}
+
+/// Carver deserialized from `worldgen/configured_carver`
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "config")]
+enum ConfiguredCarver {
+    #[serde(rename = "minecraft:cave")]
+    Cave(CaveCarverConfig),
+    /// Wider caves of the nether, filled with lava near the bottom and ignoring aquifers
+    #[serde(rename = "minecraft:nether_cave")]
+    NetherCave(CaveCarverConfig),
+    #[serde(rename = "minecraft:canyon")]
+    Canyon(CanyonCarverConfig),
+}
+
+/// Positions of a chunk already carved, stored like vanilla's `CarvingMask`
+#[derive(Debug, Clone, Default)]
+pub struct CarvingMask {
+    min_y: i32,
+    bits: Vec<u64>,
+}
+
+impl CarvingMask {
+    pub fn new(min_y: i32, height: i32) -> Self {
+        CarvingMask {
+            min_y,
+            bits: vec![0; (height.max(0) as usize * 256).div_ceil(64)],
+        }
+    }
+
+    fn index(&self, x: usize, y: i32, z: usize) -> usize {
+        x | z << 4 | ((y - self.min_y) as usize) << 8
+    }
+
+    /// Whether the block at chunk relative `x` and `z` was carved
+    pub fn get(&self, x: usize, y: i32, z: usize) -> bool {
+        if y < self.min_y {
+            return false;
+        }
+        let index = self.index(x, y, z);
+        self.bits
+            .get(index >> 6)
+            .is_some_and(|bits| bits & 1 << (index & 63) != 0)
+    }
+
+    pub fn set(&mut self, x: usize, y: i32, z: usize) {
+        if y < self.min_y {
+            return;
+        }
+        let index = self.index(x, y, z);
+        if let Some(bits) = self.bits.get_mut(index >> 6) {
+            *bits |= 1 << (index & 63);
+        }
+    }
+
+    /// Every carved position as chunk relative `x`, world `y` and chunk relative `z`, in the
+    /// order of their bits
+    pub fn positions(&self) -> impl Iterator<Item = (usize, i32, usize)> + '_ {
+        self.bits.iter().enumerate().flat_map(move |(word, bits)| {
+            (0..64)
+                .filter(move |bit| bits & 1 << bit != 0)
+                .map(move |bit| {
+                    let index = word << 6 | bit;
+                    (
+                        index & 15,
+                        (index >> 8) as i32 + self.min_y,
+                        index >> 4 & 15,
+                    )
+                })
+        })
+    }
+}
+
+/// Carves the caves and canyons of every carver starting within 8 chunks of the chunk, after the
+/// surface was built. Carved positions are recorded in the carving mask of the chunk
+pub fn carve_chunk<'a>(
+    world_state: &'a WorldState,
+    chunk: &mut Chunk,
+    aquifer: &mut Aquifer<'a>,
+    dimension: &'a str,
+) {
+    let Some(biome_source) = &world_state.biome_source else {
+        return;
+    };
+    let noise_router = &world_state.settings.noise_router;
+    let min_y = chunk.y as i32 * 16;
+    let world_height = chunk.world_height();
+    let mut ctx = CarvingContext {
+        aquifer,
+        probe: world_state.surface.probe(world_state, chunk, dimension),
+        mask: CarvingMask::new(min_y, world_height),
+        blocks: chunk
+            .sections
+            .iter()
+            .map(|section| section.block_states.block_ids())
+            .collect(),
+        modified: vec![false; chunk.sections.len()],
+        chunk_x: chunk.x,
+        chunk_z: chunk.z,
+        min_y,
+        max_y: min_y + world_height,
+        gen_min_y: world_state.settings.noise.min_y,
+        gen_depth: world_state.settings.noise.height,
+        air: *BLOCKSTATE_MAPPINGS.get("air").unwrap(),
+        cave_air: *BLOCKSTATE_MAPPINGS.get("cave_air").unwrap(),
+        lava: *BLOCKSTATE_MAPPINGS.get("lava[level=0]").unwrap(),
+        dirt: *BLOCKSTATE_MAPPINGS.get("dirt").unwrap(),
+    };
+    for start_x in chunk.x - CARVER_RANGE..=chunk.x + CARVER_RANGE {
+        for start_z in chunk.z - CARVER_RANGE..=chunk.z + CARVER_RANGE {
+            // Carvers of a chunk come from the biome at its corner
+            let Some(biome) =
+                biome_source.noise_biome(noise_router, dimension, start_x << 2, 0, start_z << 2)
+            else {
+                continue;
+            };
+            for (index, carver) in BIOME_CARVERS[biome as usize].iter().enumerate() {
+                let mut random = LCG48::with_large_feature_seed(
+                    world_state.seed.wrapping_add(index as i64),
+                    start_x,
+                    start_z,
+                );
+                if random.next_f32() <= carver.config().probability {
+                    carver.carve(&mut ctx, &mut random, start_x, start_z);
+                }
+            }
+        }
+    }
+    let CarvingContext {
+        mask,
+        blocks,
+        modified,
+        ..
+    } = ctx;
+    for ((section, block_ids), modified) in chunk.sections.iter_mut().zip(blocks).zip(modified) {
+        if modified {
+            section.block_states = ChunkBlockStates::from_block_ids(&block_ids);
+        }
+    }
+    chunk.carving_mask = Some(mask);
+    heightmap::compute_heightmaps(chunk);
+}
+
+/// Condition excluding a position relative to the center and radii of a carved ellipsoid, also
+/// given its height
+type SkipCheck<'a> = &'a dyn Fn(f64, f64, f64, i32) -> bool;
+
+impl ConfiguredCarver {
+    fn config(&self) -> &CarverConfig {
+        match self {
+            ConfiguredCarver::Cave(config) | ConfiguredCarver::NetherCave(config) => &config.base,
+            ConfiguredCarver::Canyon(config) => &config.base,
+        }
+    }
+
+    fn carve(
+        &self,
+        ctx: &mut CarvingContext,
+        random: &mut dyn RandomGenerator,
+        start_x: i32,
+        start_z: i32,
+    ) {
+        match self {
+            ConfiguredCarver::Cave(config) => {
+                carve_caves(ctx, config, false, random, start_x, start_z)
+            }
+            ConfiguredCarver::NetherCave(config) => {
+                carve_caves(ctx, config, true, random, start_x, start_z)
+            }
+            ConfiguredCarver::Canyon(config) => carve_canyon(ctx, config, random, start_x, start_z),
+        }
+    }
+}
+
+fn carve_caves(
+    ctx: &mut CarvingContext,
+    config: &CaveCarverConfig,
+    nether: bool,
+    random: &mut dyn RandomGenerator,
+    start_x: i32,
+    start_z: i32,
+) {
+    let cave_bound = if nether { 10 } else { 15 };
+    let y_scale = if nether { 5.0 } else { 1.0 };
+    let count = random.next_i32_range(cave_bound) + 1;
+    let count = random.next_i32_range(count as u32) + 1;
+    let count = random.next_i32_range(count as u32);
+    for _ in 0..count {
+        let x = (start_x * 16 + random.next_i32_range(16)) as f64;
+        let y = config.base.y.sample(random, ctx.gen_min_y, ctx.gen_depth) as f64;
+        let z = (start_z * 16 + random.next_i32_range(16)) as f64;
+        let horizontal_multiplier = config.horizontal_radius_multiplier.sample(random) as f64;
+        let vertical_multiplier = config.vertical_radius_multiplier.sample(random) as f64;
+        let floor_level = config.floor_level.sample(random) as f64;
+        let skip = |rel_x: f64, rel_y: f64, rel_z: f64, _y: i32| {
+            rel_y <= floor_level || rel_x * rel_x + rel_y * rel_y + rel_z * rel_z >= 1.0
+        };
+        let mut tunnels = 1;
+        if random.next_i32_range(4) == 0 {
+            let room_y_scale = config.base.y_scale.sample(random) as f64;
+            let radius = 1.0 + random.next_f32() * 6.0;
+            let horizontal_radius = 1.5 + (table_sin(FRAC_PI_2 as f64) * radius) as f64;
+            ctx.carve_ellipsoid(
+                &config.base,
+                nether,
+                (x + 1.0, y, z),
+                horizontal_radius,
+                horizontal_radius * room_y_scale,
+                &skip,
+            );
+            tunnels += random.next_i32_range(4);
+        }
+        for _ in 0..tunnels {
+            let yaw = random.next_f32() * TAU;
+            let pitch = (random.next_f32() - 0.5) / 4.0;
+            let mut thickness = random.next_f32() * 2.0 + random.next_f32();
+            if nether {
+                thickness *= 2.0;
+            } else if random.next_i32_range(10) == 0 {
+                thickness *= random.next_f32() * random.next_f32() * 3.0 + 1.0;
+            }
+            let branch_count = TUNNEL_LENGTH - random.next_i32_range((TUNNEL_LENGTH / 4) as u32);
+            let tunnel = Tunnel {
+                config,
+                nether,
+                horizontal_multiplier,
+                vertical_multiplier,
+                branch_count,
+                skip: &skip,
+            };
+            ctx.carve_tunnel(
+                &tunnel,
+                random.next_i64(),
+                (x, y, z),
+                thickness,
+                yaw,
+                pitch,
+                0,
+                y_scale,
+            );
+        }
+    }
+}
+
+fn carve_canyon(
+    ctx: &mut CarvingContext,
+    config: &CanyonCarverConfig,
+    random: &mut dyn RandomGenerator,
+    start_x: i32,
+    start_z: i32,
+) {
+    let mut x = (start_x * 16 + random.next_i32_range(16)) as f64;
+    let mut y = config.base.y.sample(random, ctx.gen_min_y, ctx.gen_depth) as f64;
+    let mut z = (start_z * 16 + random.next_i32_range(16)) as f64;
+    let mut yaw = random.next_f32() * TAU;
+    let mut pitch = config.vertical_rotation.sample(random);
+    let y_scale = config.base.y_scale.sample(random) as f64;
+    let thickness = config.shape.thickness.sample(random);
+    let branch_count = (TUNNEL_LENGTH as f32 * config.shape.distance_factor.sample(random)) as i32;
+    let mut random = LCG48::new(random.next_i64());
+    // Squared width of the canyon at each height, changing every few blocks
+    let mut width_factors = vec![0.0f32; ctx.gen_depth.max(0) as usize];
+    let mut width = 1.0f32;
+    for (y, factor) in width_factors.iter_mut().enumerate() {
+        if y == 0 || random.next_i32_range(config.shape.width_smoothness as u32) == 0 {
+            width = 1.0 + random.next_f32() * random.next_f32();
+        }
+        *factor = width * width;
+    }
+    let gen_min_y = ctx.gen_min_y;
+    let skip = |rel_x: f64, rel_y: f64, rel_z: f64, y: i32| {
+        let width = width_factors[(y - gen_min_y - 1) as usize] as f64;
+        (rel_x * rel_x + rel_z * rel_z) * width + rel_y * rel_y / 6.0 >= 1.0
+    };
+    let mut yaw_change = 0.0f32;
+    let mut pitch_change = 0.0f32;
+    for branch in 0..branch_count {
+        let mut horizontal_radius =
+            1.5 + (table_sin((branch as f32 * PI / branch_count as f32) as f64) * thickness) as f64;
+        let vertical_radius = horizontal_radius * y_scale;
+        horizontal_radius *= config
+            .shape
+            .horizontal_radius_factor
+            .sample(random.as_mut()) as f64;
+        let center_distance = 1.0 - (0.5 - branch as f32 / branch_count as f32).abs() * 2.0;
+        let vertical_factor = config.shape.vertical_radius_default_factor
+            + config.shape.vertical_radius_center_factor * center_distance;
+        let vertical_radius = vertical_factor as f64
+            * vertical_radius
+            * (random.next_f32() * (1.0 - 0.75) + 0.75) as f64;
+        let cos_pitch = table_cos(pitch as f64);
+        let sin_pitch = table_sin(pitch as f64);
+        x += (table_cos(yaw as f64) * cos_pitch) as f64;
+        y += sin_pitch as f64;
+        z += (table_sin(yaw as f64) * cos_pitch) as f64;
+        pitch *= 0.7;
+        pitch += pitch_change * 0.05;
+        yaw += yaw_change * 0.05;
+        pitch_change *= 0.8;
+        yaw_change *= 0.5;
+        pitch_change += (random.next_f32() - random.next_f32()) * random.next_f32() * 2.0;
+        yaw_change += (random.next_f32() - random.next_f32()) * random.next_f32() * 4.0;
+        if random.next_i32_range(4) != 0 {
+            if !ctx.can_reach(x, z, branch, branch_count, thickness) {
+                return;
+            }
+            ctx.carve_ellipsoid(
+                &config.base,
+                false,
+                (x, y, z),
+                horizontal_radius,
+                vertical_radius,
+                &skip,
+            );
+        }
+    }
+}
+
+/// Settings shared by a cave tunnel and the branches splitting from it
+struct Tunnel<'a> {
+    config: &'a CaveCarverConfig,
+    nether: bool,
+    horizontal_multiplier: f64,
+    vertical_multiplier: f64,
+    branch_count: i32,
+    skip: SkipCheck<'a>,
+}
+
+/// Blocks of the chunk being carved and the state shared by its carvers
+struct CarvingContext<'a, 'b> {
+    aquifer: &'b mut Aquifer<'a>,
+    probe: Option<SurfaceProbe<'a>>,
+    mask: CarvingMask,
+    /// Block states of each section, indexed by `y << 8 | z << 4 | x`
+    blocks: Vec<Vec<u32>>,
+    modified: Vec<bool>,
+    chunk_x: i32,
+    chunk_z: i32,
+    min_y: i32,
+    max_y: i32,
+    gen_min_y: i32,
+    gen_depth: i32,
+    air: u32,
+    cave_air: u32,
+    lava: u32,
+    dirt: u32,
+}
+
+impl CarvingContext<'_, '_> {
+    fn get_block(&self, x: usize, y: i32, z: usize) -> u32 {
+        if y < self.min_y || y >= self.max_y {
+            return self.air;
+        }
+        let y = y - self.min_y;
+        self.blocks[(y >> 4) as usize][((y & 15) as usize) << 8 | z << 4 | x]
+    }
+
+    fn set_block(&mut self, x: usize, y: i32, z: usize, block: u32) {
+        if y < self.min_y || y >= self.max_y {
+            return;
+        }
+        let y = y - self.min_y;
+        self.blocks[(y >> 4) as usize][((y & 15) as usize) << 8 | z << 4 | x] = block;
+        self.modified[(y >> 4) as usize] = true;
+    }
+
+    /// Whether a tunnel at the position can still reach the chunk with its remaining branches
+    fn can_reach(&self, x: f64, z: f64, branch: i32, branch_count: i32, thickness: f32) -> bool {
+        let rel_x = x - (self.chunk_x * 16 + 8) as f64;
+        let rel_z = z - (self.chunk_z * 16 + 8) as f64;
+        let remaining = (branch_count - branch) as f64;
+        let reach = (thickness + 2.0 + 16.0) as f64;
+        rel_x * rel_x + rel_z * rel_z - remaining * remaining <= reach * reach
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn carve_tunnel(
+        &mut self,
+        tunnel: &Tunnel,
+        seed: i64,
+        (mut x, mut y, mut z): (f64, f64, f64),
+        thickness: f32,
+        mut yaw: f32,
+        mut pitch: f32,
+        branch_index: i32,
+        y_scale: f64,
+    ) {
+        let branch_count = tunnel.branch_count;
+        let mut random = LCG48::new(seed);
+        let split_branch = random.next_i32_range((branch_count / 2) as u32) + branch_count / 4;
+        let steep = random.next_i32_range(6) == 0;
+        let mut yaw_change = 0.0f32;
+        let mut pitch_change = 0.0f32;
+        for branch in branch_index..branch_count {
+            let horizontal_radius = 1.5
+                + (table_sin((PI * branch as f32 / branch_count as f32) as f64) * thickness) as f64;
+            let vertical_radius = horizontal_radius * y_scale;
+            let cos_pitch = table_cos(pitch as f64);
+            x += (table_cos(yaw as f64) * cos_pitch) as f64;
+            y += table_sin(pitch as f64) as f64;
+            z += (table_sin(yaw as f64) * cos_pitch) as f64;
+            pitch *= if steep { 0.92 } else { 0.7 };
+            pitch += pitch_change * 0.1;
+            yaw += yaw_change * 0.1;
+            pitch_change *= 0.9;
+            yaw_change *= 0.75;
+            pitch_change += (random.next_f32() - random.next_f32()) * random.next_f32() * 2.0;
+            yaw_change += (random.next_f32() - random.next_f32()) * random.next_f32() * 4.0;
+            if branch == split_branch && thickness > 1.0 {
+                for turn in [-FRAC_PI_2, FRAC_PI_2] {
+                    let seed = random.next_i64();
+                    let thickness = random.next_f32() * 0.5 + 0.5;
+                    self.carve_tunnel(
+                        tunnel,
+                        seed,
+                        (x, y, z),
+                        thickness,
+                        yaw + turn,
+                        pitch / 3.0,
+                        branch,
+                        1.0,
+                    );
+                }
+                return;
+            }
+            if random.next_i32_range(4) != 0 {
+                if !self.can_reach(x, z, branch, branch_count, thickness) {
+                    return;
+                }
+                self.carve_ellipsoid(
+                    &tunnel.config.base,
+                    tunnel.nether,
+                    (x, y, z),
+                    horizontal_radius * tunnel.horizontal_multiplier,
+                    vertical_radius * tunnel.vertical_multiplier,
+                    tunnel.skip,
+                );
+            }
+        }
+    }
+
+    /// Carves the part of an ellipsoid inside the chunk, positions already carved are skipped
+    fn carve_ellipsoid(
+        &mut self,
+        config: &CarverConfig,
+        nether: bool,
+        (x, y, z): (f64, f64, f64),
+        horizontal_radius: f64,
+        vertical_radius: f64,
+        skip: SkipCheck,
+    ) -> bool {
+        let chunk_min_x = self.chunk_x * 16;
+        let chunk_min_z = self.chunk_z * 16;
+        let reach = 16.0 + horizontal_radius * 2.0;
+        if (x - (chunk_min_x + 8) as f64).abs() > reach
+            || (z - (chunk_min_z + 8) as f64).abs() > reach
+        {
+            return false;
+        }
+        let min_x = ((x - horizontal_radius).floor() as i32 - chunk_min_x - 1).max(0);
+        let max_x = ((x + horizontal_radius).floor() as i32 - chunk_min_x).min(15);
+        let min_y = ((y - vertical_radius).floor() as i32 - 1).max(self.gen_min_y + 1);
+        // Keeps the top 7 blocks of the world intact
+        let max_y =
+            ((y + vertical_radius).floor() as i32 + 1).min(self.gen_min_y + self.gen_depth - 1 - 7);
+        let min_z = ((z - horizontal_radius).floor() as i32 - chunk_min_z - 1).max(0);
+        let max_z = ((z + horizontal_radius).floor() as i32 - chunk_min_z).min(15);
+        let mut carved = false;
+        for rel_x in min_x..=max_x {
+            let block_x = chunk_min_x + rel_x;
+            let dist_x = (block_x as f64 + 0.5 - x) / horizontal_radius;
+            for rel_z in min_z..=max_z {
+                let block_z = chunk_min_z + rel_z;
+                let dist_z = (block_z as f64 + 0.5 - z) / horizontal_radius;
+                if dist_x * dist_x + dist_z * dist_z >= 1.0 {
+                    continue;
+                }
+                let mut reached_surface = false;
+                for block_y in (min_y + 1..=max_y).rev() {
+                    let dist_y = (block_y as f64 - 0.5 - y) / vertical_radius;
+                    let (rel_x, rel_z) = (rel_x as usize, rel_z as usize);
+                    if skip(dist_x, dist_y, dist_z, block_y) || self.mask.get(rel_x, block_y, rel_z)
+                    {
+                        continue;
+                    }
+                    self.mask.set(rel_x, block_y, rel_z);
+                    carved |= self.carve_block(
+                        config,
+                        nether,
+                        (rel_x, block_y, rel_z),
+                        &mut reached_surface,
+                    );
+                }
+            }
+        }
+        carved
+    }
+
+    fn carve_block(
+        &mut self,
+        config: &CarverConfig,
+        nether: bool,
+        (x, y, z): (usize, i32, usize),
+        reached_surface: &mut bool,
+    ) -> bool {
+        let block = self.get_block(x, y, z);
+        if nether {
+            if !config.replaceable.contains(block) {
+                return false;
+            }
+            let carved = if y <= self.gen_min_y + 31 {
+                self.lava
+            } else {
+                self.cave_air
+            };
+            self.set_block(x, y, z, carved);
+            return true;
+        }
+        if matches!(
+            split_block_state(&BLOCKSTATES[block as usize]).0,
+            "grass_block" | "mycelium"
+        ) {
+            *reached_surface = true;
+        }
+        if !config.replaceable.contains(block) {
+            return false;
+        }
+        let block_x = self.chunk_x * 16 + x as i32;
+        let block_z = self.chunk_z * 16 + z as i32;
+        let carved = if y <= config.lava_level.resolve_y(self.gen_min_y, self.gen_depth) {
+            self.lava
+        } else {
+            // Aquifer barriers stay solid
+            match self.aquifer.compute_substance(block_x, y, block_z, 0.0) {
+                Some(carved) => carved,
+                None => return false,
+            }
+        };
+        self.set_block(x, y, z, carved);
+        // Dirt uncovered below the carved surface becomes the top block of the biome
+        if *reached_surface && self.get_block(x, y - 1, z) == self.dirt {
+            let under_fluid = HEIGHTMAP_BLOCKS[carved as usize].has_fluid;
+            if let Some(top) = self
+                .probe
+                .as_mut()
+                .and_then(|probe| probe.top_material(block_x, y - 1, block_z, under_fluid))
+            {
+                self.set_block(x, y - 1, z, top);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carving_mask_keeps_carved_positions() {
+        let mut mask = CarvingMask::new(-64, 384);
+        mask.set(3, -64, 7);
+        mask.set(15, 319, 15);
+        mask.set(0, 10, 1);
+        // Positions outside of the chunk height are ignored
+        mask.set(0, -65, 0);
+        mask.set(0, 320, 0);
+        assert!(mask.get(3, -64, 7));
+        assert!(!mask.get(7, -64, 3));
+        assert!(!mask.get(0, -65, 0));
+        assert_eq!(
+            mask.positions().collect::<Vec<_>>(),
+            [(3, -64, 7), (0, 10, 1), (15, 319, 15)]
+        );
+    }
+
+    #[test]
+    fn configured_carvers_are_read() {
+        let carver: ConfiguredCarver = serde_json::from_str(
+            r#"{
+                "type": "minecraft:cave",
+                "config": {
+                    "probability": 0.15,
+                    "y": {
+                        "type": "minecraft:uniform",
+                        "min_inclusive": { "above_bottom": 8 },
+                        "max_inclusive": { "absolute": 180 }
+                    },
+                    "yScale": { "type": "minecraft:uniform", "min_inclusive": 0.1, "max_exclusive": 0.9 },
+                    "lava_level": { "above_bottom": 8 },
+                    "replaceable": ["minecraft:stone", "minecraft:dirt"],
+                    "horizontal_radius_multiplier": 1.0,
+                    "vertical_radius_multiplier": 1.0,
+                    "floor_level": -0.7
+                }
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(carver, ConfiguredCarver::Cave(_)));
+        let config = carver.config();
+        assert_eq!(config.probability, 0.15);
+        assert!(config.replaceable.contains(BLOCKSTATE_MAPPINGS["stone"]));
+        assert!(!config.replaceable.contains(BLOCKSTATE_MAPPINGS["bedrock"]));
+    }
+}
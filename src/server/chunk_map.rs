@@ -0,0 +1,285 @@
+use super::chunk_system::Chunk;
+use super::metrics::METRICS;
+use super::Dimension;
+use dashmap::{mapref::entry::Entry, DashMap};
+use std::collections::HashSet;
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, OnceLock, RwLock,
+};
+use tokio::sync::Notify;
+
+/// Chunks held in memory for the whole server, shared by every player viewing them
+///
+/// A chunk stays resident while at least one player or ticket holds it, so players standing
+/// together share the same `Arc<Chunk>` and a chunk is only read or generated once
+#[derive(Debug, Default)]
+pub struct ChunkMap {
+    chunks: DashMap<(Dimension, u64), Arc<ChunkLoad>>,
+}
+
+impl ChunkMap {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Adds a player as a viewer of a chunk, returns the chunk entry and whether the caller is
+    /// responsible for loading it
+    pub fn acquire(
+        &self,
+        dimension: Dimension,
+        coord: u64,
+        player_id: i32,
+    ) -> (Arc<ChunkLoad>, bool) {
+        self.hold(dimension, coord, |load| {
+            if load.players.write().unwrap().insert(player_id) {
+                load.view_count.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+    }
+
+    /// Removes a player as a viewer of a chunk, evicting the chunk if nothing else holds it
+    pub fn release(&self, dimension: Dimension, coord: u64, player_id: i32) {
+        let key = (dimension, coord);
+        let Some(load) = self.chunks.get(&key).map(|load| load.clone()) else {
+            return;
+        };
+        if load.players.write().unwrap().remove(&player_id) {
+            load.view_count.fetch_sub(1, Ordering::SeqCst);
+            self.evict_if_unused(&key);
+        }
+    }
+
+    /// Releases every chunk a player views in a dimension, or in all dimensions if `None`
+    pub fn release_player(&self, player_id: i32, dimension: Option<Dimension>) {
+        let keys = self
+            .chunks
+            .iter()
+            .filter(|entry| dimension.is_none_or(|dim| entry.key().0 == dim))
+            .filter(|entry| entry.players.read().unwrap().contains(&player_id))
+            .map(|entry| *entry.key())
+            .collect::<Vec<_>>();
+        for (dimension, coord) in keys {
+            self.release(dimension, coord, player_id);
+        }
+    }
+
+    /// Keeps a chunk resident without a player viewing it, returns the chunk entry and whether
+    /// the caller is responsible for loading it
+    pub fn add_ticket(&self, dimension: Dimension, coord: u64) -> (Arc<ChunkLoad>, bool) {
+        self.hold(dimension, coord, |load| {
+            load.tickets.fetch_add(1, Ordering::SeqCst);
+            load.view_count.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    pub fn remove_ticket(&self, dimension: Dimension, coord: u64) {
+        let key = (dimension, coord);
+        let Some(load) = self.chunks.get(&key).map(|load| load.clone()) else {
+            return;
+        };
+        let removed = load
+            .tickets
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |t| t.checked_sub(1));
+        if removed.is_ok() {
+            load.view_count.fetch_sub(1, Ordering::SeqCst);
+            self.evict_if_unused(&key);
+        }
+    }
+
+    /// Fails the load of a chunk no player or ticket holds anymore, returns whether it was
+    /// cancelled. The entry is removed while the map shard is locked, so a concurrent acquire
+    /// either keeps the chunk loading or gets a new entry instead of the failed one
+    pub fn cancel_if_unused(
+        &self,
+        dimension: Dimension,
+        coord: u64,
+        load: &Arc<ChunkLoad>,
+    ) -> bool {
+        match self.chunks.entry((dimension, coord)) {
+            Entry::Occupied(entry) if Arc::ptr_eq(entry.get(), load) => {
+                if !load.is_unused() {
+                    return false;
+                }
+                entry.remove();
+                METRICS.chunks_resident.set(self.chunks.len() as i64);
+            }
+            // An evicted entry can not be acquired again
+            _ => {}
+        }
+        load.complete(None);
+        true
+    }
+
+    pub fn get(&self, dimension: Dimension, coord: u64) -> Option<Arc<ChunkLoad>> {
+        self.chunks
+            .get(&(dimension, coord))
+            .map(|load| load.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Gets or inserts a chunk entry and adds a holder while the map shard is locked, so the
+    /// chunk can not be evicted before it is held
+    fn hold(
+        &self,
+        dimension: Dimension,
+        coord: u64,
+        add_holder: impl FnOnce(&ChunkLoad),
+    ) -> (Arc<ChunkLoad>, bool) {
+        let entry = self.chunks.entry((dimension, coord));
+        let is_new = matches!(entry, Entry::Vacant(_));
+        let load = {
+            let load = entry.or_default();
+            add_holder(&load);
+            load.clone()
+        };
+        if is_new {
+            METRICS.chunks_resident.set(self.chunks.len() as i64);
+        }
+        (load, is_new)
+    }
+
+    fn evict_if_unused(&self, key: &(Dimension, u64)) {
+        // Checked while the map shard is locked so a concurrent acquire keeps the chunk
+        let evicted = self
+            .chunks
+            .remove_if(key, |_, load| load.view_count.load(Ordering::SeqCst) == 0);
+        if evicted.is_some() {
+            METRICS.chunks_resident.set(self.chunks.len() as i64);
+        }
+    }
+}
+
+/// A resident chunk, shared by concurrent requests while it is being loaded
+#[derive(Default)]
+pub struct ChunkLoad {
+    /// Notified once `data` is set
+    pub load_notify: Notify,
+    /// Number of players and tickets holding the chunk
+    pub view_count: AtomicU32,
+    pub tickets: AtomicU32,
+    /// Entity ids of the players viewing the chunk
+    pub players: RwLock<HashSet<i32>>,
+    /// Set once loading finished, `None` if the chunk could not be loaded
+    data: OnceLock<Option<Arc<Chunk>>>,
+}
+
+impl ChunkLoad {
+    /// Stores the loaded chunk and wakes every request waiting for it
+    pub fn complete(&self, chunk: Option<Arc<Chunk>>) {
+        let _ = self.data.set(chunk);
+        self.load_notify.notify_waiters();
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.data.get().is_some()
+    }
+
+    pub fn chunk(&self) -> Option<Arc<Chunk>> {
+        self.data.get().cloned().flatten()
+    }
+
+    /// Waits until the chunk finished loading
+    pub async fn wait(&self) -> Option<Arc<Chunk>> {
+        let notified = self.load_notify.notified();
+        tokio::pin!(notified);
+        // Registers as a waiter before checking so a completion in between is not missed
+        notified.as_mut().enable();
+        if !self.is_loaded() {
+            notified.await;
+        }
+        self.chunk()
+    }
+
+    /// True once no player or ticket holds the chunk, pending work for it can be skipped
+    pub fn is_unused(&self) -> bool {
+        self.view_count.load(Ordering::SeqCst) == 0
+    }
+
+    pub fn is_viewed_by(&self, player_id: i32) -> bool {
+        self.players.read().unwrap().contains(&player_id)
+    }
+}
+
+impl std::fmt::Debug for ChunkLoad {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkLoad {...}").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_stay_resident_while_held() {
+        let map = ChunkMap::new();
+        let (first, is_new) = map.acquire(Dimension::Overworld, 1, 10);
+        assert!(is_new);
+        let (second, is_new) = map.acquire(Dimension::Overworld, 1, 11);
+        assert!(!is_new);
+        assert!(Arc::ptr_eq(&first, &second));
+        // Acquiring twice counts a player once
+        map.acquire(Dimension::Overworld, 1, 10);
+        assert_eq!(first.view_count.load(Ordering::SeqCst), 2);
+
+        map.add_ticket(Dimension::Overworld, 1);
+        map.release(Dimension::Overworld, 1, 10);
+        map.release(Dimension::Overworld, 1, 11);
+        assert_eq!(map.len(), 1);
+        map.remove_ticket(Dimension::Overworld, 1);
+        assert_eq!(map.len(), 0);
+        assert!(first.is_unused());
+        // Releasing a chunk that is no longer resident does nothing
+        map.remove_ticket(Dimension::Overworld, 1);
+        map.release(Dimension::Overworld, 1, 10);
+        assert!(map.get(Dimension::Overworld, 1).is_none());
+    }
+
+    #[test]
+    fn players_are_released_per_dimension() {
+        let map = ChunkMap::new();
+        map.acquire(Dimension::Overworld, 1, 10);
+        map.acquire(Dimension::Overworld, 2, 10);
+        map.acquire(Dimension::Nether, 1, 10);
+        map.acquire(Dimension::Nether, 1, 11);
+        map.release_player(10, Some(Dimension::Overworld));
+        assert_eq!(map.len(), 1);
+        let nether = map.get(Dimension::Nether, 1).unwrap();
+        assert!(nether.is_viewed_by(10));
+        map.release_player(10, None);
+        assert!(!nether.is_viewed_by(10));
+        assert!(nether.is_viewed_by(11));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn cancelled_chunks_are_never_handed_out() {
+        let map = ChunkMap::new();
+        for _ in 0..2000 {
+            let (load, _) = map.acquire(Dimension::Overworld, 1, 10);
+            let cancelled = std::thread::scope(|s| {
+                let cancel = s.spawn(|| {
+                    map.release(Dimension::Overworld, 1, 10);
+                    map.cancel_if_unused(Dimension::Overworld, 1, &load)
+                });
+                let (other, _) = map.acquire(Dimension::Overworld, 1, 11);
+                let cancelled = cancel.join().unwrap();
+                // A request sharing the entry must still get the chunk loaded
+                if Arc::ptr_eq(&other, &load) {
+                    assert!(!load.is_loaded());
+                }
+                cancelled
+            });
+            assert_eq!(cancelled, load.is_loaded());
+            let (current, _) = map.acquire(Dimension::Overworld, 1, 11);
+            assert!(!current.is_loaded());
+            map.release(Dimension::Overworld, 1, 11);
+            map.cancel_if_unused(Dimension::Overworld, 1, &current);
+            assert_eq!(map.len(), 0);
+        }
+    }
+}
@@ -1,24 +1,36 @@
 use super::super::packets::play::{ChunkLightData, UnloadChunks};
+use super::logger::{LogDomain, LogLevel, LOGGER};
 use super::util::lerp_f64;
 use super::{
+    aquifer::Aquifer,
+    blender::{Blender, BlendingData},
+    carvers::CarvingMask,
+    chunk_map::{ChunkLoad, ChunkMap},
     events::ChunkLoadTask,
+    features::region::{FeatureRegion, RegionChunk},
+    heightmap::{self, HeightmapType},
+    light::{self, ChunkLight, ChunkNeighbors},
+    metrics::METRICS,
+    ore_veins::OreVeinifier,
     region::RegionManager,
+    structures::{beardifier::Beardifier, ChunkStructures},
     terrain_gen::func_deserialize::{DensityArg, DensityFnArgs},
-    world_state::WORLD_STATES,
-    DimensionType, Player,
+    world_state::{WorldState, WORLD_STATES},
+    CorruptChunkPolicy, Dimension, DimensionType, Player,
 };
-use crate::{MAIN_DENSITY_FUNCTION, MAX_BLOCKSTATES, MC_VERSION};
+use crate::{MAIN_DENSITY_FUNCTION, MAX_BLOCKSTATES, MC_DATA_VERSION, MC_VERSION};
 use ahash::AHashMap;
 use once_cell::sync::Lazy;
 use serde::de::{MapAccess, Visitor};
-use serde::{Deserialize, Deserializer};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::hash::{Hash, Hasher};
 use std::io::Read;
-use std::sync::{atomic::AtomicU32, Arc, RwLock};
-use tokio::sync::{Notify, Semaphore};
+use std::sync::{atomic::Ordering, Arc, Condvar, Mutex};
+use tokio::sync::Semaphore;
 
 pub static BLOCKSTATES: Lazy<Vec<String>> = Lazy::new(|| {
     let mut mappings_file = File::open(&format!("assets/block-mapping-{MC_VERSION}.json"))
@@ -58,26 +70,52 @@ pub static BIOME_MAPPINGS: Lazy<AHashMap<String, u16>> = Lazy::new(|| {
         .collect()
 });
 
+/// Number of chunks generated by a generation task before the queue is reprioritized
+const GEN_BATCH_SIZE: usize = 8;
+
+/// Splits a block state into its name and properties, e.g. `lantern[hanging=false]`
+pub fn split_block_state(state: &str) -> (&str, &str) {
+    state
+        .split_once('[')
+        .map(|(name, properties)| (name, properties.trim_end_matches(']')))
+        .unwrap_or((state, ""))
+}
+
+/// Value of a property in the properties of a block state from `split_block_state`
+pub fn block_state_property<'a>(properties: &'a str, key: &str) -> Option<&'a str> {
+    properties
+        .split(',')
+        .filter_map(|property| property.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+/// block states, so light classifies blocks by their name with these lists
+/// block states, so light and heightmaps classify blocks by their name with these lists
+pub fn block_name_matches(name: &str, names: &[&str], parts: &[&str]) -> bool {
+    names.contains(&name) || parts.iter().any(|part| name.contains(part))
+}
+
 #[derive(Debug)]
 pub struct LudiChunkLoader {
     region_manager: Arc<RegionManager>,
     sampling_settings: ChunkSampleSettings,
-    /// A map of player loaded chunks.
-    /// Packed chunk coords (x, z) -> Chunk Data.
-    loaded_chunks: HashMap<u64, Arc<ChunkLoad>>,
+    /// Chunks resident for the whole server, shared with the other shards
+    chunk_map: Arc<ChunkMap>,
     load_tasks: Arc<Semaphore>,
     gen_tasks: Arc<Semaphore>,
+    gen_queue: Arc<GenerationQueue>,
 }
 
 impl LudiChunkLoader {
-    pub fn new(region_manager: Arc<RegionManager>) -> Self {
+    pub fn new(region_manager: Arc<RegionManager>, chunk_map: Arc<ChunkMap>) -> Self {
         Self {
             region_manager,
-            // Valid sampling sizes are 2, 3, 5, 9, and 17
-            sampling_settings: ChunkSampleSettings::new(5, 3, 5),
-            loaded_chunks: HashMap::new(),
+            sampling_settings: ChunkSampleSettings::default(),
+            chunk_map,
             load_tasks: Arc::new(Semaphore::new(2)),
             gen_tasks: Arc::new(Semaphore::new(2)),
+            gen_queue: Arc::new(GenerationQueue::default()),
         }
     }
 
@@ -137,13 +175,70 @@ impl LudiChunkLoader {
     pub async fn handle_chunk_task(&self, task: &ChunkLoadTask) {
         let load_semaphore = self.load_tasks.clone();
         let gen_semaphore = self.gen_tasks.clone();
+        let gen_queue = self.gen_queue.clone();
+        let chunk_map = self.chunk_map.clone();
         let player = task.player.clone();
-        let chunkloads = task.chunks_requested.clone();
         let chunkunloads = task.chunks_unloaded.clone();
         let region_manager = self.region_manager.clone();
         let sample_settings = self.sampling_settings.clone();
+        let dimension = task.dimension;
+        // Residency is updated before spawning so it follows the order of the events, a chunk is
+        // always handled by the same shard
+        for chunk in task.chunks_unloaded.iter() {
+            self.chunk_map.release(dimension, *chunk, player.id);
+        }
+        // Chunks acquired for a player that left or changed dimension would never be released,
+        // since their release is queued on this shard the check can not miss it
+        if !player.server.has_player(player.id) || player.get_dimension() != dimension {
+            return;
+        }
+        let mut chunks_ready = Vec::new();
+        let mut chunks_waiting = Vec::new();
+        let mut chunks_to_load = HashMap::new();
+        for chunk in task.chunks_requested.iter() {
+            let (load, is_new) = self.chunk_map.acquire(dimension, *chunk, player.id);
+            if is_new {
+                chunks_to_load.insert(*chunk, load);
+            } else if load.is_loaded() {
+                chunks_ready.extend(load.chunk());
+            } else {
+                chunks_waiting.push(load);
+            }
+        }
         tokio::task::spawn(async move {
-            let _permit = load_semaphore.acquire().await.unwrap();
+            if let Some(tx) = player.low_priority_outbound.upgrade() {
+                let _ = tx.send(UnloadChunks::new(chunkunloads).into()).await;
+            }
+            if !chunks_waiting.is_empty() {
+                // Chunks being loaded by another request are waited on without a load permit
+                let player = player.clone();
+                let chunk_map = chunk_map.clone();
+                tokio::task::spawn(async move {
+                    let mut chunks = Vec::new();
+                    for load in chunks_waiting {
+                        chunks.extend(load.wait().await);
+                    }
+                    Self::send_chunks(&chunk_map, player, dimension, chunks).await;
+                });
+            }
+            if chunks_to_load.is_empty() {
+                Self::send_chunks(&chunk_map, player, dimension, chunks_ready).await;
+                return;
+            }
+
+            let wait_start = std::time::Instant::now();
+            let permit = load_semaphore.acquire().await.unwrap();
+            METRICS.load_permit_wait.observe(wait_start.elapsed());
+            // The player may have moved away while waiting for the permit
+            chunks_to_load.retain(|coord, load| {
+                let cancelled =
+                    load.is_unused() && chunk_map.cancel_if_unused(dimension, *coord, load);
+                if cancelled {
+                    METRICS.chunk_requests_cancelled.inc();
+                }
+                !cancelled
+            });
+            let chunkloads = chunks_to_load.keys().copied().collect::<Vec<_>>();
             let region_map = tokio::task::spawn_blocking(move || {
                 let mut region_map = HashMap::new();
                 for chunk in chunkloads.iter() {
@@ -160,78 +255,470 @@ impl LudiChunkLoader {
             })
             .await
             .unwrap();
-            if let Some(tx) = player.low_priority_outbound.upgrade() {
-                let _ = tx
-                    .send(UnloadChunks::new(chunkunloads.clone()).into())
-                    .await;
-            }
-            let mut chunks_found = Vec::new();
             let mut chunks_not_found = Vec::new();
+            let corrupt_policy = player.server.config.corrupt_chunk_policy;
+            let dim_type = &player.server.dimension_settings[dimension.type_name()];
             for (region, chunk_indexes) in region_map {
-                let (chunks, not_found) = region_manager
-                    .get_region_chunks(1, region, chunk_indexes)
+                let (chunks, not_found, corrupted) = region_manager
+                    .get_region_chunks(dimension, region, chunk_indexes)
                     .await;
-                chunks_found.extend(chunks);
+                let chunks = Self::light_unlit(chunks, dim_type.has_skylight).await;
+                for chunk in chunks {
+                    let load = chunks_to_load.remove(&Self::pack_coords((chunk.x, chunk.z)));
+                    if let Some(load) = load {
+                        load.complete(Some(chunk.clone()));
+                        chunks_ready.push(chunk);
+                    }
+                }
                 for c in not_found {
-                    chunks_not_found.push(Self::pack_coords(Self::chunk_idx_to_coord(c, region)));
+                    let coord = Self::pack_coords(Self::chunk_idx_to_coord(c, region));
+                    chunks_not_found.extend(chunks_to_load.remove_entry(&coord));
+                }
+                for c in corrupted {
+                    let coord = Self::pack_coords(Self::chunk_idx_to_coord(c, region));
+                    let Some(load) = chunks_to_load.remove(&coord) else {
+                        continue;
+                    };
+                    match corrupt_policy {
+                        // Overwrites the corrupt chunk once generated, a copy is kept in the
+                        // corrupted folder
+                        CorruptChunkPolicy::Regenerate => chunks_not_found.push((coord, load)),
+                        CorruptChunkPolicy::Empty => {
+                            let mut chunk = Chunk::empty(coord, dim_type);
+                            light::light_chunk(&mut chunk, dim_type.has_skylight, &[None; 4]);
+                            let chunk = Arc::new(chunk);
+                            load.complete(Some(chunk.clone()));
+                            chunks_ready.push(chunk);
+                        }
+                        CorruptChunkPolicy::Refuse => load.complete(None),
+                    }
                 }
                 //println!("{chunks:?}");
             }
-            if let Some(tx) = player.low_priority_outbound.upgrade() {
-                let player = player.clone();
-                let chunk_pkt =
-                    tokio::task::spawn_blocking(move || ChunkLightData::new(chunks_found, player))
-                        .await
-                        .unwrap();
-                let _ = tx.send(chunk_pkt.into()).await;
+            drop(permit);
+            Self::send_chunks(&chunk_map, player.clone(), dimension, chunks_ready).await;
+
+            if chunks_not_found.is_empty() {
+                return;
+            }
+            let has_generator = WORLD_STATES
+                .get()
+                .unwrap_or_else(|| panic!("World state not initialized"))
+                .contains_key(dimension.name());
+            if !has_generator {
+                LOGGER.get().unwrap().println_as(
+                    &format!("No generator for dimension {}", dimension.name()),
+                    LogDomain::LudiGen,
+                    LogLevel::Error,
+                );
+                for (_, load) in chunks_not_found {
+                    load.complete(None);
+                }
+                return;
             }
+            gen_queue.push(
+                chunks_not_found
+                    .into_iter()
+                    .map(|(coord, load)| ChunkRequest::new(player.clone(), dimension, coord, load)),
+            );
+            Self::generate_queued(
+                gen_queue,
+                gen_semaphore,
+                chunk_map,
+                region_manager,
+                sample_settings,
+            )
+            .await;
+        });
+    }
 
-            if !chunks_not_found.is_empty() {
-                tokio::task::spawn(async move {
-                    let permit = gen_semaphore.acquire().await.unwrap();
-                    let main_dense_fn = WORLD_STATES
-                        .get()
-                        .unwrap_or_else(|| panic!("World state not initialized"))
-                        .get("minecraft:overworld")
-                        .unwrap()
-                        .settings
-                        .noise_router
-                        .get(MAIN_DENSITY_FUNCTION)
-                        .unwrap();
-                    let dim_type = player
-                        .server
-                        .dimension_settings
-                        .get("overworld")
-                        .unwrap()
-                        .clone();
-                    let chunks_gen = tokio::task::spawn_blocking(move || {
-                        let mut sample_cache = AHashMap::new();
-                        let mut chunks_gen = Vec::new();
-                        for chunk in chunks_not_found {
-                            chunks_gen.push(Arc::new(Chunk::generate(
-                                chunk,
-                                "minecraft:overworld",
-                                &dim_type,
-                                main_dense_fn,
-                                &mut sample_cache,
-                                &sample_settings,
-                            )));
-                        }
-                        chunks_gen
+    /// Generates queued chunks in batches, closest to their player first, until the queue is
+    /// empty. Every task that queued chunks runs this so a batch is never left behind
+    async fn generate_queued(
+        gen_queue: Arc<GenerationQueue>,
+        gen_semaphore: Arc<Semaphore>,
+        chunk_map: Arc<ChunkMap>,
+        region_manager: Arc<RegionManager>,
+        sample_settings: ChunkSampleSettings,
+    ) {
+        let wait_start = std::time::Instant::now();
+        let _permit = gen_semaphore.acquire().await.unwrap();
+        METRICS.gen_permit_wait.observe(wait_start.elapsed());
+        loop {
+            let requests = gen_queue.pop_closest(&chunk_map, GEN_BATCH_SIZE);
+            if requests.is_empty() {
+                return;
+            }
+            let sample_settings = sample_settings.clone();
+            let resident = chunk_map.clone();
+            let chunks_gen = tokio::task::spawn_blocking(move || {
+                let world_states = WORLD_STATES.get().unwrap();
+                let server = requests[0].player.server.clone();
+                let mut sample_caches: AHashMap<Dimension, AHashMap<u64, f64>> = AHashMap::new();
+                let mut chunks_gen = Vec::new();
+                for request in &requests {
+                    let dimension = request.dimension;
+                    let world_state = &world_states[dimension.name()];
+                    let dim_type = &server.dimension_settings[dimension.type_name()];
+                    let gen_start = std::time::Instant::now();
+                    let chunk = Self::generate_decorated(
+                        world_state,
+                        dimension,
+                        dim_type,
+                        request.coord,
+                        &resident,
+                        &chunks_gen,
+                        sample_caches.entry(dimension).or_default(),
+                        &sample_settings,
+                    );
+                    METRICS.chunk_generation_time.observe(gen_start.elapsed());
+                    METRICS.chunks_generated.inc();
+                    chunks_gen.push(((dimension, request.coord), chunk));
+                }
+                Self::light_generated(&resident, &server.dimension_settings, &mut chunks_gen);
+                let chunks_gen = requests
+                    .into_iter()
+                    .zip(chunks_gen.into_iter().map(|(_, chunk)| chunk))
+                    .map(|(request, chunk)| {
+                        let chunk = Arc::new(chunk);
+                        // Requests waiting on the chunk are served as soon as it is ready
+                        request.load.complete(Some(chunk.clone()));
+                        (request, chunk)
                     })
-                    .await
-                    .unwrap();
-                    if let Some(tx) = player.low_priority_outbound.upgrade() {
-                        let chunk_pkt = tokio::task::spawn_blocking(move || {
-                            ChunkLightData::new(chunks_gen, player)
-                        })
-                        .await
-                        .unwrap();
-                        let _ = tx.send(chunk_pkt.into()).await;
-                    }
+                    .collect::<Vec<_>>();
+                for (request, _) in &chunks_gen {
+                    Self::forget_finished_protos(request.dimension, [request.coord]);
+                }
+                chunks_gen
+            })
+            .await
+            .unwrap();
+            let mut player_chunks: HashMap<(i32, Dimension), (Arc<Player>, Vec<Arc<Chunk>>)> =
+                HashMap::new();
+            let mut region_map: HashMap<(Dimension, u64), Vec<Arc<Chunk>>> = HashMap::new();
+            for (request, chunk) in chunks_gen {
+                region_map
+                    .entry((
+                        request.dimension,
+                        Self::pack_coords(Self::chunk_to_region(chunk.x, chunk.z)),
+                    ))
+                    .or_default()
+                    .push(chunk.clone());
+                player_chunks
+                    .entry((request.player.id, request.dimension))
+                    .or_insert_with(|| (request.player.clone(), Vec::new()))
+                    .1
+                    .push(chunk);
+            }
+            for ((_, dimension), (player, chunks)) in player_chunks {
+                Self::send_chunks(&chunk_map, player, dimension, chunks).await;
+            }
+            // Save after sending so the player does not wait on disk writes
+            let region_manager = region_manager.clone();
+            tokio::task::spawn(async move {
+                for ((dimension, region), chunks) in region_map {
+                    region_manager
+                        .write_region_chunks(dimension, region, chunks)
+                        .await;
+                }
+            });
+        }
+    }
+
+    /// Generates a chunk up to its carvers, the state vanilla decorates chunks in
+    fn generate_proto(
+        world_state: &WorldState,
+        dimension: Dimension,
+        dim_type: &DimensionType,
+        coord: u64,
+        resident: &ChunkMap,
+        sample_cache: &mut AHashMap<u64, f64>,
+        sample_settings: &ChunkSampleSettings,
+    ) -> Chunk {
+        let chunk_coord = Self::unpack_coords(coord);
+        if let Some(flat) = &world_state.flat {
+            let mut chunk = flat.generate(coord, dim_type);
+            let starts =
+                world_state.structure_starts(chunk_coord.0, chunk_coord.1, dimension.name());
+            chunk.structures = ChunkStructures::new(&starts, chunk_coord.0, chunk_coord.1);
+            world_state.fill_biomes(&mut chunk, dimension.name());
+            return chunk;
+        }
+        let main_dense_fn = world_state
+            .settings
+            .noise_router
+            .get(MAIN_DENSITY_FUNCTION)
+            .unwrap();
+        let mut aquifer = Aquifer::new(world_state, chunk_coord.0, chunk_coord.1, dimension.name());
+        let ore_veins = OreVeinifier::new(world_state, dimension.name());
+        let starts = world_state.structure_starts(chunk_coord.0, chunk_coord.1, dimension.name());
+        let beardifier = Beardifier::new(&starts, chunk_coord.0, chunk_coord.1);
+        let blender = Blender::new(chunk_coord.0, chunk_coord.1, |x, z| {
+            resident
+                .get(dimension, Self::pack_coords((x, z)))
+                .and_then(|load| load.chunk())
+        });
+        let mut chunk = Chunk::generate(
+            coord,
+            dimension.name(),
+            dim_type,
+            main_dense_fn,
+            &mut aquifer,
+            ore_veins.as_ref(),
+            &beardifier,
+            &blender,
+            sample_cache,
+            sample_settings,
+        );
+        chunk.structures = ChunkStructures::new(&starts, chunk_coord.0, chunk_coord.1);
+        world_state.fill_biomes(&mut chunk, dimension.name());
+        world_state.build_surface(&mut chunk, dimension.name());
+        world_state.apply_carvers(&mut chunk, &mut aquifer, dimension.name());
+        chunk
+    }
+
+    /// Generates a chunk with the features of every chunk around it. Features reach into the 8
+    /// chunks around the chunk they are placed from, so every chunk around it is decorated first
+    /// and neighbours that are neither resident nor in the batch are kept as proto chunks. Once
+    /// its neighbours are decorated no feature places blocks in the chunk anymore, the result does
+    /// not depend on the order chunks are generated in
+    #[allow(clippy::too_many_arguments)]
+    fn generate_decorated(
+        world_state: &WorldState,
+        dimension: Dimension,
+        dim_type: &DimensionType,
+        coord: u64,
+        resident: &ChunkMap,
+        batch: &[((Dimension, u64), Chunk)],
+        sample_cache: &mut AHashMap<u64, f64>,
+        sample_settings: &ChunkSampleSettings,
+    ) -> Chunk {
+        // Flat worlds without features nor structures have nothing to place
+        if world_state.flat.is_some()
+            && world_state.features.is_empty()
+            && world_state.structures.is_empty()
+        {
+            return Self::generate_proto(
+                world_state,
+                dimension,
+                dim_type,
+                coord,
+                resident,
+                sample_cache,
+                sample_settings,
+            );
+        }
+        let resident_chunk = |neighbor| {
+            resident
+                .get(dimension, neighbor)
+                .and_then(|load| load.chunk())
+        };
+        let batch_chunk = |neighbor| {
+            batch
+                .iter()
+                .find(|(key, _)| *key == (dimension, neighbor))
+                .map(|(_, chunk)| chunk)
+        };
+        let mut chunk = PROTO_CHUNKS.finish(
+            dimension,
+            coord,
+            |neighbor| batch_chunk(neighbor).is_some() || resident_chunk(neighbor).is_some(),
+            |neighbor| {
+                Self::generate_proto(
+                    world_state,
+                    dimension,
+                    dim_type,
+                    neighbor,
+                    resident,
+                    sample_cache,
+                    sample_settings,
+                )
+            },
+            |center, chunks| {
+                let coords = Self::neighborhood_coords(center);
+                let resident_chunks = coords.map(resident_chunk);
+                let mut region_chunks = chunks
+                    .into_iter()
+                    .zip(coords)
+                    .zip(&resident_chunks)
+                    .map(|((chunk, neighbor), resident_chunk)| match chunk {
+                        Some(chunk) => RegionChunk::Writable(chunk),
+                        None => match batch_chunk(neighbor).or(resident_chunk.as_deref()) {
+                            Some(chunk) => RegionChunk::ReadOnly(chunk),
+                            None => RegionChunk::Missing,
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                let mut region = FeatureRegion::new(world_state, dimension.name(), &region_chunks);
+                world_state.decorate(&mut region);
+                region.write_back(&mut region_chunks);
+            },
+        );
+        chunk.carving_mask = None;
+        chunk
+    }
+
+    /// Lights generated chunks from their resident neighbours and from the chunks generated in the
+    /// same batch
+    fn light_generated(
+        chunk_map: &ChunkMap,
+        dimension_settings: &HashMap<String, DimensionType>,
+        chunks: &mut [((Dimension, u64), Chunk)],
+    ) {
+        fn batch_neighbor(
+            chunks: &[((Dimension, u64), Chunk)],
+            dimension: Dimension,
+            coord: u64,
+        ) -> Option<&Chunk> {
+            chunks
+                .iter()
+                .find(|(key, _)| *key == (dimension, coord))
+                .map(|(_, chunk)| chunk)
+        }
+        let has_skylight =
+            |dimension: Dimension| dimension_settings[dimension.type_name()].has_skylight;
+        for i in 0..chunks.len() {
+            let (dimension, coord) = chunks[i].0;
+            let neighbor_coords = Self::neighbor_coords(coord);
+            let resident = neighbor_coords.map(|coord| {
+                chunk_map
+                    .get(dimension, coord)
+                    .and_then(|load| load.chunk())
+            });
+            let light = {
+                let neighbors: ChunkNeighbors = std::array::from_fn(|n| {
+                    resident[n]
+                        .as_deref()
+                        .or_else(|| batch_neighbor(&chunks[..i], dimension, neighbor_coords[n]))
                 });
+                let mut light = ChunkLight::new(&chunks[i].1, has_skylight(dimension));
+                light.light_all(&neighbors);
+                light
+            };
+            light.store(&mut chunks[i].1);
+        }
+        // Chunks lit earlier in the batch receive the light of the chunks lit after them
+        for i in 0..chunks.len() {
+            let (dimension, coord) = chunks[i].0;
+            let neighbor_coords = Self::neighbor_coords(coord);
+            let light = {
+                let neighbors: ChunkNeighbors = std::array::from_fn(|n| {
+                    batch_neighbor(&chunks[i + 1..], dimension, neighbor_coords[n])
+                });
+                if neighbors.iter().all(Option::is_none) {
+                    continue;
+                }
+                let mut light = ChunkLight::new(&chunks[i].1, has_skylight(dimension));
+                light.light_borders(&neighbors);
+                light
+            };
+            light.store(&mut chunks[i].1);
+        }
+    }
+
+    /// Keeps more proto chunks than usual, pregeneration keeps the protos of a whole row of
+    /// chunks until the next row is generated. 0 goes back to the usual capacity
+    pub fn reserve_proto_chunks(count: usize) {
+        PROTO_CHUNKS.cache.lock().unwrap().reserved = count;
+    }
+
+    /// Forgets generated chunks once they are resident or saved, until then generation tasks
+    /// treat them as finished instead of generating them as neighbours again
+    pub fn forget_finished_protos(dimension: Dimension, coords: impl IntoIterator<Item = u64>) {
+        PROTO_CHUNKS.forget_finished(dimension, coords);
+    }
+
+    /// Generates, decorates and lights chunks of a dimension outside of player requests, like a
+    /// generation batch. Chunks of the batch are finished one after the other, the features of
+    /// later chunks only read them. They count as finished until `forget_finished_protos`
+    pub fn generate_batch(
+        world_state: &WorldState,
+        dimension: Dimension,
+        dimension_settings: &HashMap<String, DimensionType>,
+        coords: &[u64],
+        resident: &ChunkMap,
+    ) -> Vec<Chunk> {
+        let dim_type = &dimension_settings[dimension.type_name()];
+        let sample_settings = ChunkSampleSettings::default();
+        let mut sample_cache = AHashMap::new();
+        let mut chunks_gen = Vec::with_capacity(coords.len());
+        for coord in coords {
+            let gen_start = std::time::Instant::now();
+            let chunk = Self::generate_decorated(
+                world_state,
+                dimension,
+                dim_type,
+                *coord,
+                resident,
+                &chunks_gen,
+                &mut sample_cache,
+                &sample_settings,
+            );
+            METRICS.chunk_generation_time.observe(gen_start.elapsed());
+            METRICS.chunks_generated.inc();
+            chunks_gen.push(((dimension, *coord), chunk));
+        }
+        Self::light_generated(resident, dimension_settings, &mut chunks_gen);
+        chunks_gen.into_iter().map(|(_, chunk)| chunk).collect()
+    }
+
+    /// Lights chunks that were saved without light
+    async fn light_unlit(mut chunks: Vec<Arc<Chunk>>, has_skylight: bool) -> Vec<Arc<Chunk>> {
+        if chunks.iter().all(|chunk| chunk.is_light_on) {
+            return chunks;
+        }
+        tokio::task::spawn_blocking(move || {
+            for chunk in chunks.iter_mut() {
+                // Chunks read from a region are not shared yet
+                if let Some(chunk) = Arc::get_mut(chunk).filter(|chunk| !chunk.is_light_on) {
+                    light::light_chunk(chunk, has_skylight, &[None; 4]);
+                }
             }
+            chunks
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Coordinates of the chunks next to a chunk, in the order of `ChunkNeighbors`
+    pub fn neighbor_coords(coord: u64) -> [u64; 4] {
+        let (x, z) = Self::unpack_coords(coord);
+        [(x - 1, z), (x + 1, z), (x, z - 1), (x, z + 1)].map(Self::pack_coords)
+    }
+
+    /// Coordinates of the 3x3 chunks around a chunk row by row, in the order of the chunks of a
+    /// `FeatureRegion`
+    fn neighborhood_coords(coord: u64) -> [u64; 9] {
+        let (x, z) = Self::unpack_coords(coord);
+        std::array::from_fn(|i| Self::pack_coords((x + i as i32 % 3 - 1, z + i as i32 / 3 - 1)))
+    }
+
+    /// Sends chunks to a player, skipping chunks the player unloaded since requesting them and
+    /// every chunk if the player changed dimension
+    async fn send_chunks(
+        chunk_map: &ChunkMap,
+        player: Arc<Player>,
+        dimension: Dimension,
+        mut chunks: Vec<Arc<Chunk>>,
+    ) {
+        if player.get_dimension() != dimension {
+            return;
+        }
+        chunks.retain(|chunk| {
+            chunk_map
+                .get(dimension, Self::pack_coords((chunk.x, chunk.z)))
+                .is_some_and(|load| load.is_viewed_by(player.id))
         });
+        if chunks.is_empty() {
+            return;
+        }
+        let Some(tx) = player.low_priority_outbound.upgrade() else {
+            return;
+        };
+        let chunk_pkt = tokio::task::spawn_blocking(move || ChunkLightData::new(chunks, player))
+            .await
+            .unwrap();
+        let _ = tx.send(chunk_pkt.into()).await;
     }
 
     pub fn shard_chunks(chunks: &[&u64], num_of_shards: usize) -> HashMap<usize, Vec<u64>> {
@@ -269,29 +756,298 @@ impl LudiChunkLoader {
     }
 }
 
-/// To be used if multiple players request the same chunk while chunk is loading and to keep track
-/// of chunk interest
+/// A chunk waiting to be generated for a player
+struct ChunkRequest {
+    player: Arc<Player>,
+    dimension: Dimension,
+    coord: u64,
+    load: Arc<ChunkLoad>,
+    /// `Player::chunk_generation` when `distance` was computed
+    generation: u32,
+    distance: i32,
+}
+
+impl ChunkRequest {
+    fn new(player: Arc<Player>, dimension: Dimension, coord: u64, load: Arc<ChunkLoad>) -> Self {
+        let mut request = Self {
+            player,
+            dimension,
+            coord,
+            load,
+            generation: 0,
+            distance: 0,
+        };
+        request.update_distance();
+        request
+    }
+
+    /// Chebyshev distance in chunks to the current center chunk of the player
+    fn update_distance(&mut self) {
+        self.generation = self.player.chunk_generation.load(Ordering::Relaxed);
+        self.distance = chunk_distance(self.coord, self.player.get_center_chunk());
+    }
+}
+
+/// Chebyshev distance in chunks between a packed chunk coordinate and a chunk position
+fn chunk_distance(coord: u64, (center_x, center_z): (i32, i32)) -> i32 {
+    let (x, z) = LudiChunkLoader::unpack_coords(coord);
+    (x - center_x).abs().max((z - center_z).abs())
+}
+
+/// Splits off up to `count` items with the smallest distance, closest first
+fn split_closest<T>(items: &mut Vec<T>, count: usize, distance: impl Fn(&T) -> i32) -> Vec<T> {
+    // Closest items are moved to the end so they can be split off
+    items.sort_unstable_by_key(|item| std::cmp::Reverse(distance(item)));
+    let split = items.len().saturating_sub(count);
+    let mut closest = items.split_off(split);
+    closest.reverse();
+    closest
+}
+
+/// Chunks generated next to decorated chunks that were not requested yet, holding the blocks
+/// features of their neighbours placed in them
+static PROTO_CHUNKS: Lazy<ProtoChunks> = Lazy::new(Default::default);
+
+/// Number of proto chunks kept, the oldest are dropped first. A dropped chunk that was decorated
+/// loses the blocks its neighbours placed in it, like chunks finished in an earlier run that are
+/// generated again as neighbours
+const PROTO_CHUNK_CAPACITY: usize = 1024;
+
 #[derive(Default)]
-pub struct ChunkLoad {
-    pub load_notify: Notify,
-    pub view_count: AtomicU32,
-    pub players: Option<RwLock<HashMap<i32, Arc<Player>>>>,
-    pub data: Option<Chunk>,
-}
-
-impl ChunkLoad {
-    pub fn new(load_notify: Notify) -> Arc<Self> {
-        Arc::new(Self {
-            load_notify,
-            view_count: AtomicU32::new(1),
-            ..Default::default()
-        })
+struct ProtoChunks<T = Chunk> {
+    cache: Mutex<ProtoCache<T>>,
+    /// Notified when claimed chunks are given back
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct ProtoCache<T> {
+    /// Chunks with whether they were decorated and the insertion number they were stored with
+    chunks: AHashMap<(Dimension, u64), (T, bool, u64)>,
+    /// Chunks a generation task took out, other tasks wait until they are given back
+    claimed: HashSet<(Dimension, u64)>,
+    /// Chunks taken out finished that may not be resident yet, see
+    /// `LudiChunkLoader::forget_finished_protos`
+    finished: HashSet<(Dimension, u64)>,
+    order: VecDeque<((Dimension, u64), u64)>,
+    inserted: u64,
+    /// Chunks kept on top of `PROTO_CHUNK_CAPACITY`, see `LudiChunkLoader::reserve_proto_chunks`
+    reserved: usize,
+}
+
+/// A chunk taken out of the proto chunks
+enum ClaimedProto<T> {
+    /// With whether it was decorated
+    Stored(T, bool),
+    Missing,
+    /// Finished by another task, only read
+    Finished,
+}
+
+impl<T> ProtoChunks<T> {
+    /// Decorates every chunk around a chunk that is not decorated yet and takes the chunk out.
+    /// Finished chunks are only read, `decorate` gets `None` for them and missing chunks are made
+    /// with `generate`
+    fn finish(
+        &self,
+        dimension: Dimension,
+        coord: u64,
+        is_finished: impl Fn(u64) -> bool,
+        mut generate: impl FnMut(u64) -> T,
+        mut decorate: impl FnMut(u64, [Option<&mut T>; 9]),
+    ) -> T {
+        loop {
+            for center in LudiChunkLoader::neighborhood_coords(coord) {
+                let coords = LudiChunkLoader::neighborhood_coords(center);
+                let writable = coords
+                    .into_iter()
+                    .filter(|neighbor| !is_finished(*neighbor))
+                    .collect::<Vec<_>>();
+                let Some(center_index) = writable.iter().position(|c| *c == center) else {
+                    continue;
+                };
+                let mut chunks = self.claim(dimension, &writable);
+                // Chunks another task finished since they were checked are only read
+                let finished_since = writable
+                    .iter()
+                    .zip(chunks.iter_mut())
+                    .filter(|(neighbor, chunk)| {
+                        matches!(chunk, ClaimedProto::Missing) && is_finished(**neighbor)
+                    })
+                    .map(|(neighbor, chunk)| {
+                        *chunk = ClaimedProto::Finished;
+                        (*neighbor, ClaimedProto::Missing)
+                    })
+                    .collect::<Vec<_>>();
+                self.give_back(dimension, finished_since);
+                if !matches!(
+                    chunks[center_index],
+                    ClaimedProto::Stored(_, true) | ClaimedProto::Finished
+                ) {
+                    let mut region: [Option<&mut T>; 9] = Default::default();
+                    for (neighbor, chunk) in writable.iter().zip(chunks.iter_mut()) {
+                        if let ClaimedProto::Missing = chunk {
+                            *chunk = ClaimedProto::Stored(generate(*neighbor), false);
+                        }
+                        if let ClaimedProto::Stored(chunk, decorated) = chunk {
+                            *decorated |= *neighbor == center;
+                            let i = coords.iter().position(|c| c == neighbor).unwrap();
+                            region[i] = Some(chunk);
+                        }
+                    }
+                    decorate(center, region);
+                }
+                self.give_back(dimension, writable.into_iter().zip(chunks));
+            }
+            // Only missing if it was dropped since, it is generated and decorated again
+            if let Some(chunk) = self.take_finished(dimension, coord) {
+                return chunk;
+            }
+        }
+    }
+
+    /// Takes chunks out once no other task holds any of them. Every chunk is claimed at once so
+    /// tasks never hold some while waiting for others
+    fn claim(&self, dimension: Dimension, coords: &[u64]) -> Vec<ClaimedProto<T>> {
+        let mut cache = self.cache.lock().unwrap();
+        while coords
+            .iter()
+            .any(|coord| cache.claimed.contains(&(dimension, *coord)))
+        {
+            cache = self.released.wait(cache).unwrap();
+        }
+        coords
+            .iter()
+            .map(|coord| {
+                let key = (dimension, *coord);
+                if cache.finished.contains(&key) {
+                    return ClaimedProto::Finished;
+                }
+                cache.claimed.insert(key);
+                match cache.chunks.remove(&key) {
+                    Some((chunk, decorated, _)) => ClaimedProto::Stored(chunk, decorated),
+                    None => ClaimedProto::Missing,
+                }
+            })
+            .collect()
+    }
+
+    /// Stores claimed chunks again and lets waiting tasks claim them
+    fn give_back(
+        &self,
+        dimension: Dimension,
+        chunks: impl IntoIterator<Item = (u64, ClaimedProto<T>)>,
+    ) {
+        let mut cache = self.cache.lock().unwrap();
+        for (coord, chunk) in chunks {
+            match chunk {
+                ClaimedProto::Stored(chunk, decorated) => {
+                    cache.claimed.remove(&(dimension, coord));
+                    cache.insert(dimension, coord, chunk, decorated);
+                }
+                ClaimedProto::Missing => {
+                    cache.claimed.remove(&(dimension, coord));
+                }
+                ClaimedProto::Finished => {}
+            }
+        }
+        drop(cache);
+        self.released.notify_all();
+    }
+
+    /// Takes a decorated chunk out, other tasks treat it as finished until it is forgotten
+    fn take_finished(&self, dimension: Dimension, coord: u64) -> Option<T> {
+        let mut cache = self.cache.lock().unwrap();
+        let key = (dimension, coord);
+        while cache.claimed.contains(&key) {
+            cache = self.released.wait(cache).unwrap();
+        }
+        // A chunk unloaded before it was saved is generated again
+        if cache.finished.remove(&key) {
+            return None;
+        }
+        if !cache
+            .chunks
+            .get(&key)
+            .is_some_and(|(_, decorated, _)| *decorated)
+        {
+            return None;
+        }
+        cache.finished.insert(key);
+        cache.chunks.remove(&key).map(|(chunk, _, _)| chunk)
+    }
+
+    /// Stops treating chunks as finished once they are resident or saved
+    fn forget_finished(&self, dimension: Dimension, coords: impl IntoIterator<Item = u64>) {
+        let mut cache = self.cache.lock().unwrap();
+        for coord in coords {
+            cache.finished.remove(&(dimension, coord));
+        }
     }
 }
 
-impl std::fmt::Debug for ChunkLoad {
+impl<T> ProtoCache<T> {
+    fn insert(&mut self, dimension: Dimension, coord: u64, chunk: T, decorated: bool) {
+        self.inserted += 1;
+        self.chunks
+            .insert((dimension, coord), (chunk, decorated, self.inserted));
+        self.order.push_back(((dimension, coord), self.inserted));
+        // Taken chunks leave their place in the order behind
+        if self.order.len() > (PROTO_CHUNK_CAPACITY + self.reserved) * 2 {
+            let chunks = &self.chunks;
+            self.order
+                .retain(|(key, inserted)| chunks.get(key).is_some_and(|(_, _, i)| i == inserted));
+        }
+        while self.chunks.len() > PROTO_CHUNK_CAPACITY + self.reserved {
+            let Some((key, inserted)) = self.order.pop_front() else {
+                break;
+            };
+            if self
+                .chunks
+                .get(&key)
+                .is_some_and(|(_, _, i)| *i == inserted)
+            {
+                self.chunks.remove(&key);
+            }
+        }
+    }
+}
+
+/// Chunks waiting to be generated, shared by the generation tasks of a shard
+#[derive(Default)]
+struct GenerationQueue {
+    requests: Mutex<Vec<ChunkRequest>>,
+}
+
+impl GenerationQueue {
+    fn push(&self, requests: impl IntoIterator<Item = ChunkRequest>) {
+        self.requests.lock().unwrap().extend(requests);
+    }
+
+    /// Takes up to `count` requests closest to their players. Requests for chunks no player
+    /// holds anymore are dropped, requests of players that moved since they were queued get
+    /// their distance recomputed
+    fn pop_closest(&self, chunk_map: &ChunkMap, count: usize) -> Vec<ChunkRequest> {
+        let mut requests = self.requests.lock().unwrap();
+        requests.retain_mut(|request| {
+            if request.load.is_unused()
+                && chunk_map.cancel_if_unused(request.dimension, request.coord, &request.load)
+            {
+                METRICS.chunk_requests_cancelled.inc();
+                return false;
+            }
+            if request.generation != request.player.chunk_generation.load(Ordering::Relaxed) {
+                request.update_distance();
+            }
+            true
+        });
+        split_closest(&mut requests, count, |request| request.distance)
+    }
+}
+
+impl std::fmt::Debug for GenerationQueue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ChunkLoadTask {...}").finish()
+        f.debug_struct("GenerationQueue {...}").finish()
     }
 }
 
@@ -306,6 +1062,21 @@ pub struct Chunk {
     pub y: i8,
     #[serde(rename = "zPos")]
     pub z: i32,
+    /// Set once the light of every section was computed
+    #[serde(rename = "isLightOn", default)]
+    pub is_light_on: bool,
+    /// Generation status as stored by vanilla, kept when the chunk is saved again. Chunks
+    /// generated by the server have none and are saved as `full`
+    #[serde(rename = "Status", default, skip_serializing)]
+    pub status: Option<String>,
+    /// Positions carved by carvers, only kept while the chunk is generated
+    #[serde(skip)]
+    pub carving_mask: Option<CarvingMask>,
+    #[serde(default)]
+    pub structures: ChunkStructures,
+    /// Only set on chunks generated before 1.18, the terrain of new chunks is blended into them
+    #[serde(default)]
+    pub blending_data: Option<BlendingData>,
 }
 
 impl std::fmt::Debug for Chunk {
@@ -321,6 +1092,10 @@ impl Chunk {
         dimension: &str,
         dimension_type: &DimensionType,
         density_function: &DensityArg,
+        aquifer: &mut Aquifer,
+        ore_veins: Option<&OreVeinifier>,
+        beardifier: &Beardifier,
+        blender: &Blender,
         sample_cache: &mut AHashMap<u64, f64>,
         sampling_settings: &ChunkSampleSettings,
     ) -> Self {
@@ -329,11 +1104,17 @@ impl Chunk {
         let max_y = dimension_type.logical_height + min_y;
         let max_section_y = (max_y as f32 / 16.0).ceil() as i8;
         let chunk_sections = (max_section_y + min_section_y.abs()) as usize;
+        // Sections above the logical height are left empty but still sent to the client
+        let total_sections = (dimension_type.height / 16) as usize;
         let chunk_coord = LudiChunkLoader::unpack_coords(packed_coord);
         // Generate chunk sections
         let mut positions_to_gen = Vec::new();
         let mut cached_positions = Vec::new();
+        let mut sample_positions = Vec::new();
         let mut pos_count = 0 as usize;
+        // Border samples of blended chunks depend on the old chunks around them so they are
+        // neither shared with nor taken from other chunks
+        let blender = (!blender.is_empty()).then_some(blender);
         {
             for y in (min_y..max_y).step_by(sampling_settings.y_sample_spacing as usize) {
                 for z in ((chunk_coord.1 * 16)..=(chunk_coord.1 * 16 + 16))
@@ -343,7 +1124,11 @@ impl Chunk {
                         .step_by(sampling_settings.x_sample_spacing as usize)
                     {
                         let packed_coord = LudiChunkLoader::pack_xyz((x, y, z));
-                        if let Some(sample) = sample_cache.get(&packed_coord) {
+                        sample_positions.push(packed_coord);
+                        if let Some(sample) = sample_cache
+                            .get(&packed_coord)
+                            .filter(|_| blender.is_none())
+                        {
                             cached_positions.push((pos_count, *sample));
                         } else {
                             positions_to_gen.push(packed_coord);
@@ -353,86 +1138,171 @@ impl Chunk {
                 }
             }
         }
+        // Vein functions are interpolated over the same samples as the density
+        let vein_samples = ore_veins.map(|veins| {
+            let sample = |function: &DensityArg| {
+                let mut args = DensityFnArgs::new_from_positions(dimension, &sample_positions);
+                args.column_cache_passthrough = true;
+                args.blender = blender;
+                let mut samples = vec![0f64; sample_positions.len()];
+                function.compute_slice(&mut args, &mut samples);
+                samples
+            };
+            (veins, sample(veins.vein_toggle), sample(veins.vein_ridged))
+        });
         let mut args = DensityFnArgs::new_from_positions(dimension, &positions_to_gen);
         args.column_cache_passthrough = true;
+        args.blender = blender;
         let block_states = Self::generate_chunk_blockstates(
             density_function,
             &mut args,
+            aquifer,
+            vein_samples,
+            beardifier,
+            (chunk_coord.0 * 16, min_y, chunk_coord.1 * 16),
             chunk_sections,
             cached_positions,
             sample_cache,
             sampling_settings,
         );
-        let mut sections = vec![ChunkSection::default(); chunk_sections];
-        for (i, section_block_states) in block_states.into_iter().enumerate() {
-            sections[i] = ChunkSection {
-                y: i as i8 + min_section_y,
-                biomes: ChunkBiomes::default(),
-                block_states: section_block_states,
-                sky_light: None,
-                block_light: None,
-            };
+        let mut sections = (0..total_sections.max(chunk_sections))
+            .map(|i| ChunkSection::air(i as i8 + min_section_y))
+            .collect::<Vec<_>>();
+        for (section, section_block_states) in sections.iter_mut().zip(block_states) {
+            section.block_states = section_block_states;
         }
-        Chunk {
-            /*heightmaps: ChunkHeightmaps {
-                world_surface: Some(Self::encode_heightmap(world_surface)),
-                ..Default::default()
-            },*/
+        let mut chunk = Chunk {
             sections,
             x: chunk_coord.0,
             y: min_section_y,
             z: chunk_coord.1,
             ..Default::default()
-        }
+        };
+        heightmap::compute_heightmaps(&mut chunk);
+        chunk
+    }
+
+    /// A chunk with only air, sent in place of chunks that can not be loaded
+    pub fn empty(packed_coord: u64, dimension_type: &DimensionType) -> Self {
+        let min_section_y = (dimension_type.min_y as f32 / 16.0).floor() as i8;
+        let chunk_coord = LudiChunkLoader::unpack_coords(packed_coord);
+        let sections = (0..dimension_type.height / 16)
+            .map(|i| ChunkSection::air(i as i8 + min_section_y))
+            .collect();
+        let mut chunk = Chunk {
+            sections,
+            x: chunk_coord.0,
+            y: min_section_y,
+            z: chunk_coord.1,
+            ..Default::default()
+        };
+        heightmap::compute_heightmaps(&mut chunk);
+        chunk
     }
 
     pub fn from_data(data: &[u8]) -> Result<Self, fastnbt::error::Error> {
         fastnbt::from_bytes::<Chunk>(data)
     }
 
-    pub fn get_heightmap_count(&self) -> i32 {
-        let mut count = 0;
-        let heightmaps = &self.heightmaps;
-        if heightmaps.motion_blocking.is_some() {
-            count += 1;
-        }
-        if heightmaps.motion_blocking_no_leaves.is_some() {
-            count += 1;
-        }
-        if heightmaps.ocean_floor.is_some() {
-            count += 1;
-        }
-        if heightmaps.world_surface.is_some() {
-            count += 1;
-        }
-        count
+    /// Serializes the chunk to vanilla chunk nbt as stored in region files
+    pub fn to_data(&self) -> Result<Vec<u8>, fastnbt::error::Error> {
+        fastnbt::to_bytes(&ChunkNbt {
+            data_version: MC_DATA_VERSION,
+            x: self.x,
+            y: self.y as i32,
+            z: self.z,
+            status: self.status.as_deref().unwrap_or("minecraft:full"),
+            last_update: 0,
+            inhabited_time: 0,
+            is_light_on: self.is_light_on,
+            heightmaps: &self.heightmaps,
+            sections: &self.sections,
+            block_entities: Vec::new(),
+            structures: &self.structures,
+            blending_data: self.blending_data.as_ref(),
+        })
     }
 
-    fn encode_heightmap(heightmap: [[i32; 16]; 16]) -> fastnbt::LongArray {
-        const BPE: usize = 9;
-        let mut packed_heightmap = vec![0i64; 37];
-        let mut bit_index = 0;
-        for z in 0..16 {
-            for x in 0..16 {
-                let height = heightmap[x][z];
-                let value = (height + 64).clamp(0, 511) as i64;
+    /// Block state at a position relative to the chunk, `y` is the world height
+    pub fn get_block(&self, x: usize, y: i32, z: usize) -> Option<u32> {
+        let section = self.section(y)?;
+        let idx = (y.rem_euclid(16) as usize) << 8 | z << 4 | x;
+        section.block_states.block_ids().get(idx).copied()
+    }
 
-                let start_long = bit_index / 64;
-                let start_offset = bit_index % 64;
+    /// Sets the block state at a position relative to the chunk, returns the previous block
+    /// state or `None` if the position is outside of the chunk. The heightmaps are updated with
+    /// it, light is updated by `light::update_block`
+    pub fn set_block(&mut self, x: usize, y: i32, z: usize, block: u32) -> Option<u32> {
+        let section = self
+            .sections
+            .iter_mut()
+            .find(|section| section.y as i32 == y.div_euclid(16))?;
+        let idx = (y.rem_euclid(16) as usize) << 8 | z << 4 | x;
+        let mut block_ids = section.block_states.block_ids();
+        let previous = std::mem::replace(block_ids.get_mut(idx)?, block);
+        section.block_states = ChunkBlockStates::from_block_ids(&block_ids);
+        heightmap::update_heightmaps(self, x, y, z);
+        Some(previous)
+    }
 
-                if start_offset + BPE <= 64 {
-                    packed_heightmap[start_long] |= value << start_offset;
-                } else {
-                    let low_bits = 64 - start_offset;
-                    packed_heightmap[start_long] |= (value & ((1 << low_bits) - 1)) << start_offset;
-                    packed_heightmap[start_long + 1] |= value >> low_bits;
-                }
-                bit_index += BPE;
+    /// Height of the chunk in blocks, the heightmaps are encoded for this height
+    pub fn world_height(&self) -> i32 {
+        self.sections
+            .iter()
+            .filter(|section| section.y >= self.y)
+            .count() as i32
+            * 16
+    }
+
+    fn section(&self, y: i32) -> Option<&ChunkSection> {
+        self.sections
+            .iter()
+            .find(|section| section.y as i32 == y.div_euclid(16))
+    }
+
+    /// Heightmaps sent to the client with their protocol id
+    pub fn client_heightmaps(&self) -> Vec<(i32, &fastnbt::LongArray)> {
+        HeightmapType::ALL
+            .iter()
+            .filter_map(|heightmap| {
+                Some((heightmap.protocol_id()?, self.heightmaps.get(*heightmap)?))
+            })
+            .collect()
+    }
+
+    /// Packs heights relative to the bottom of the world, entries do not span across longs
+    pub fn encode_heightmap(heightmap: [[i32; 16]; 16], world_height: i32) -> fastnbt::LongArray {
+        let bpe = (32 - (world_height as u32).leading_zeros()) as usize;
+        let values_per_long = 64 / bpe;
+        let mut packed_heightmap = vec![0i64; 256usize.div_ceil(values_per_long)];
+        for z in 0..16 {
+            for x in 0..16 {
+                let index = z * 16 + x;
+                let value = heightmap[x][z].clamp(0, world_height) as i64;
+                packed_heightmap[index / values_per_long] |=
+                    value << ((index % values_per_long) * bpe);
             }
         }
         fastnbt::LongArray::new(packed_heightmap)
     }
 
+    /// Unpacks a heightmap written by `encode_heightmap`
+    pub fn decode_heightmap(packed: &[i64], world_height: i32) -> [[i32; 16]; 16] {
+        let bpe = (32 - (world_height as u32).leading_zeros()) as usize;
+        let values_per_long = 64 / bpe;
+        let mask = (1u64 << bpe) - 1;
+        let mut heightmap = [[0; 16]; 16];
+        for z in 0..16 {
+            for x in 0..16 {
+                let index = z * 16 + x;
+                let long = packed.get(index / values_per_long).copied().unwrap_or(0) as u64;
+                heightmap[x][z] = ((long >> ((index % values_per_long) * bpe)) & mask) as i32;
+            }
+        }
+        heightmap
+    }
+
     fn find_block_height(
         block_x: i32,
         block_z: i32,
@@ -457,6 +1327,10 @@ impl Chunk {
     fn generate_chunk_blockstates(
         function: &DensityArg,
         args: &mut DensityFnArgs,
+        aquifer: &mut Aquifer,
+        vein_samples: Option<(&OreVeinifier, Vec<f64>, Vec<f64>)>,
+        beardifier: &Beardifier,
+        origin: (i32, i32, i32),
         chunk_sections: usize,
         cached_positions: Vec<(usize, f64)>,
         sample_cache: &mut AHashMap<u64, f64>,
@@ -471,7 +1345,7 @@ impl Chunk {
             let coord = LudiChunkLoader::unpack_xyz(pos);
             let chunk_rel_x = coord.0 % 16;
             let chunk_rel_z = coord.2 % 16;
-            if chunk_rel_x == 0 || chunk_rel_z == 0 {
+            if (chunk_rel_x == 0 || chunk_rel_z == 0) && args.blender.is_none() {
                 sample_cache.insert(pos, *density);
             }
         }
@@ -479,8 +1353,7 @@ impl Chunk {
         for (index, density) in cached_positions {
             densities.insert(index, density);
         }
-        let air_id = *BLOCKSTATE_MAPPINGS.get("air").unwrap();
-        let stone_id = *BLOCKSTATE_MAPPINGS.get("stone").unwrap();
+        let default_block = aquifer.default_block();
         let mut section_block_states = vec![ChunkBlockStates::default(); chunk_sections];
         // Chunk size is amount of blocks per chunk section (4096)
         for (section, section_state) in section_block_states.iter_mut().enumerate() {
@@ -493,8 +1366,25 @@ impl Chunk {
                 let y = (i / 256) + (section as i32 * 16);
                 let z = (i / 16) % 16;
 
-                let density = Self::trilinear_interpolate(&densities, x, y, z, sampling_settings);
-                let block_type = if density > 0.0 { stone_id } else { air_id };
+                let (block_x, block_y, block_z) = (origin.0 + x, origin.1 + y, origin.2 + z);
+                let mut density =
+                    Self::trilinear_interpolate(&densities, x, y, z, sampling_settings);
+                if !beardifier.is_empty() {
+                    density += beardifier.compute(block_x, block_y, block_z);
+                }
+                let block_type = aquifer
+                    .compute_substance(block_x, block_y, block_z, density)
+                    .or_else(|| {
+                        let (veins, toggle, ridged) = vein_samples.as_ref()?;
+                        veins.vein_block(
+                            block_x,
+                            block_y,
+                            block_z,
+                            Self::trilinear_interpolate(toggle, x, y, z, sampling_settings),
+                            Self::trilinear_interpolate(ridged, x, y, z, sampling_settings),
+                        )
+                    })
+                    .unwrap_or(default_block);
                 let block_indice = palette_map.get_mut(block_type as usize).unwrap();
                 *block = if *block_indice == u16::MAX {
                     let id = palette.len() as u16;
@@ -516,24 +1406,13 @@ impl Chunk {
         section_block_states
     }
 
+    /// Packs palette indices without spanning entries across longs
     #[inline]
     fn pack_block_indices(data: &[u16], bits: usize) -> fastnbt::LongArray {
-        let total_bits = data.len() * bits;
-        let longs_needed = (total_bits + 63) / 64;
-        let mut packed_blocks = vec![0i64; longs_needed];
-        let mut bit_index = 0;
-        for &value in data {
-            let value = value as i64;
-            let long_index = bit_index / 64;
-            let offset = bit_index % 64;
-            if offset + bits <= 64 {
-                packed_blocks[long_index] |= value << offset;
-            } else {
-                let low_bits = 64 - offset;
-                packed_blocks[long_index] |= (value & ((1 << low_bits) - 1)) << offset;
-                packed_blocks[long_index + 1] |= value >> low_bits;
-            }
-            bit_index += bits;
+        let values_per_long = 64 / bits;
+        let mut packed_blocks = vec![0i64; data.len().div_ceil(values_per_long)];
+        for (i, &value) in data.iter().enumerate() {
+            packed_blocks[i / values_per_long] |= (value as i64) << ((i % values_per_long) * bits);
         }
         fastnbt::LongArray::new(packed_blocks)
     }
@@ -612,6 +1491,13 @@ pub struct ChunkSampleSettings {
     samples_per_section: u8,
 }
 
+impl Default for ChunkSampleSettings {
+    fn default() -> Self {
+        // Valid sampling sizes are 2, 3, 5, 9, and 17
+        Self::new(5, 3, 5)
+    }
+}
+
 impl ChunkSampleSettings {
     pub fn new(num_x_samples: u8, num_y_samples: u8, num_z_samples: u8) -> Self {
         ChunkSampleSettings {
@@ -626,16 +1512,48 @@ impl ChunkSampleSettings {
     }
 }
 
-#[derive(Deserialize, Default, Debug)]
+/// Vanilla chunk nbt written to region files, see `Chunk::to_data`
+#[derive(Serialize)]
+struct ChunkNbt<'a> {
+    #[serde(rename = "DataVersion")]
+    data_version: i32,
+    #[serde(rename = "xPos")]
+    x: i32,
+    #[serde(rename = "yPos")]
+    y: i32,
+    #[serde(rename = "zPos")]
+    z: i32,
+    #[serde(rename = "Status")]
+    status: &'a str,
+    #[serde(rename = "LastUpdate")]
+    last_update: i64,
+    #[serde(rename = "InhabitedTime")]
+    inhabited_time: i64,
+    #[serde(rename = "isLightOn")]
+    is_light_on: bool,
+    #[serde(rename = "Heightmaps")]
+    heightmaps: &'a ChunkHeightmaps,
+    sections: &'a [ChunkSection],
+    block_entities: Vec<fastnbt::Value>,
+    structures: &'a ChunkStructures,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blending_data: Option<&'a BlendingData>,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct ChunkHeightmaps {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub motion_blocking: Option<fastnbt::LongArray>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub motion_blocking_no_leaves: Option<fastnbt::LongArray>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub ocean_floor: Option<fastnbt::LongArray>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub world_surface: Option<fastnbt::LongArray>,
 }
 
-#[derive(Deserialize, Default, Debug, Clone)]
+#[derive(Deserialize, Serialize, Default, Debug, Clone)]
 #[serde(rename_all = "PascalCase")]
 pub struct ChunkSection {
     pub y: i8,
@@ -643,14 +1561,36 @@ pub struct ChunkSection {
     pub biomes: ChunkBiomes,
     #[serde(rename = "block_states")]
     pub block_states: ChunkBlockStates,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub sky_light: Option<fastnbt::ByteArray>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub block_light: Option<fastnbt::ByteArray>,
 }
 
-#[derive(Deserialize, Default, Debug, Clone)]
+impl ChunkSection {
+    /// A section filled with air
+    pub fn air(y: i8) -> Self {
+        ChunkSection {
+            y,
+            biomes: ChunkBiomes::default(),
+            block_states: ChunkBlockStates {
+                palette: vec![ChunkBlock { id: 0 }],
+                data: None,
+            },
+            sky_light: None,
+            block_light: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Clone)]
 pub struct ChunkBiomes {
-    #[serde(deserialize_with = "map_biome_to_id")]
+    #[serde(
+        deserialize_with = "map_biome_to_id",
+        serialize_with = "map_id_to_biome"
+    )]
     pub palette: Vec<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<fastnbt::LongArray>,
 }
 
@@ -661,6 +1601,43 @@ impl ChunkBiomes {
             data: None,
         }
     }
+
+    /// Builds the palette and packed data of a section from the biome of each 4x4x4 cell,
+    /// indexed by `y << 4 | z << 2 | x`
+    pub fn from_biome_ids(biome_ids: &[u16]) -> Self {
+        let mut palette: Vec<u16> = Vec::new();
+        let biome_indices = biome_ids
+            .iter()
+            .map(|id| {
+                palette.iter().position(|b| b == id).unwrap_or_else(|| {
+                    palette.push(*id);
+                    palette.len() - 1
+                }) as u16
+            })
+            .collect::<Vec<_>>();
+        let data = if palette.len() == 1 {
+            None
+        } else {
+            let bits = (palette.len() as f64).log2().ceil() as usize;
+            Some(Chunk::pack_block_indices(&biome_indices, bits))
+        };
+        ChunkBiomes { palette, data }
+    }
+
+    /// Biome of each 4x4x4 cell of the section, indexed by `y << 4 | z << 2 | x`
+    pub fn biome_ids(&self) -> [u16; 64] {
+        let Some(data) = &self.data else {
+            return [self.palette.first().copied().unwrap_or(0); 64];
+        };
+        let bits = ((self.palette.len() as f64).log2().ceil() as usize).max(1);
+        let values_per_long = 64 / bits;
+        let bitmask = (1u64 << bits) - 1;
+        std::array::from_fn(|i| {
+            let long = data.get(i / values_per_long).copied().unwrap_or(0) as u64;
+            let index = (long >> ((i % values_per_long) * bits)) & bitmask;
+            self.palette.get(index as usize).copied().unwrap_or(0)
+        })
+    }
 }
 
 fn map_biome_to_id<'de, D>(deserializer: D) -> Result<Vec<u16>, D::Error>
@@ -679,12 +1656,70 @@ where
         .collect()
 }
 
-#[derive(Deserialize, Default, Debug, Clone)]
+fn map_id_to_biome<S>(palette: &[u16], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    palette
+        .iter()
+        .map(|id| {
+            BIOMES
+                .get(*id as usize)
+                .map(|b| format!("minecraft:{b}"))
+                .ok_or_else(|| serde::ser::Error::custom(format!("Biome id not found: {id}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .serialize(serializer)
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Clone)]
 pub struct ChunkBlockStates {
     pub palette: Vec<ChunkBlock>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<fastnbt::LongArray>,
 }
 
+impl ChunkBlockStates {
+    /// Block state of each block in the section, indexed by `y << 8 | z << 4 | x`
+    pub fn block_ids(&self) -> Vec<u32> {
+        let Some(data) = &self.data else {
+            return vec![self.palette.first().map_or(0, |block| block.id); 4096];
+        };
+        let bits_per_block = std::cmp::max(4, (self.palette.len() as f64).log2().ceil() as usize);
+        let blocks_per_long = 64 / bits_per_block;
+        let bitmask = (1u64 << bits_per_block) - 1;
+        (0..4096)
+            .map(|i| {
+                let long = data.get(i / blocks_per_long).copied().unwrap_or(0) as u64;
+                let index = (long >> ((i % blocks_per_long) * bits_per_block)) & bitmask;
+                self.palette.get(index as usize).map_or(0, |block| block.id)
+            })
+            .collect()
+    }
+
+    /// Builds the palette and packed data of a section from the state of each block
+    pub fn from_block_ids(block_ids: &[u32]) -> Self {
+        let mut palette: Vec<ChunkBlock> = Vec::new();
+        let mut palette_map = AHashMap::new();
+        let block_indices = block_ids
+            .iter()
+            .map(|id| {
+                *palette_map.entry(*id).or_insert_with(|| {
+                    palette.push(ChunkBlock { id: *id });
+                    palette.len() as u16 - 1
+                })
+            })
+            .collect::<Vec<_>>();
+        let bpe = ((palette.len().next_power_of_two() as f32).log2().ceil() as usize).max(4);
+        let data = if palette.len() == 1 {
+            None
+        } else {
+            Some(Chunk::pack_block_indices(&block_indices, bpe))
+        };
+        ChunkBlockStates { palette, data }
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct ChunkBlock {
     pub id: u32,
@@ -736,6 +1771,31 @@ impl<'de> Deserialize<'de> for ChunkBlock {
     }
 }
 
+impl Serialize for ChunkBlock {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let blockstate = BLOCKSTATES
+            .get(self.id as usize)
+            .ok_or_else(|| serde::ser::Error::custom(format!("Block id not found: {}", self.id)))?;
+        let (name, properties) = match blockstate.split_once('[') {
+            Some((name, properties)) => (name, Some(properties.trim_end_matches(']'))),
+            None => (blockstate.as_str(), None),
+        };
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("Name", &format!("minecraft:{name}"))?;
+        if let Some(properties) = properties {
+            let properties: HashMap<&str, &str> = properties
+                .split(',')
+                .filter_map(|p| p.split_once('='))
+                .collect();
+            map.serialize_entry("Properties", &properties)?;
+        }
+        map.end()
+    }
+}
+
 pub fn deserialize_format_blockstate(
     name: &str,
     properties: Option<HashMap<Cow<&str>, Cow<&str>>>,
@@ -751,3 +1811,95 @@ pub fn deserialize_format_blockstate(
     }
     blockstate
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closest_chunks_are_generated_first() {
+        let pack = LudiChunkLoader::pack_coords;
+        assert_eq!(chunk_distance(pack((-3, 1)), (0, 0)), 3);
+        assert_eq!(chunk_distance(pack((5, -7)), (4, -4)), 3);
+
+        let center = (10, -10);
+        let mut coords = [(16, -10), (10, -10), (8, -13), (11, -9), (10, -30)]
+            .map(pack)
+            .to_vec();
+        let closest = split_closest(&mut coords, 3, |coord| chunk_distance(*coord, center));
+        assert_eq!(closest, [(10, -10), (11, -9), (8, -13)].map(pack));
+        assert_eq!(coords.len(), 2);
+        // Asking for more than is queued takes everything
+        let rest = split_closest(&mut coords, 10, |coord| chunk_distance(*coord, center));
+        assert_eq!(rest, [(16, -10), (10, -30)].map(pack));
+        assert!(coords.is_empty());
+    }
+
+    #[test]
+    fn features_do_not_depend_on_the_generation_order() {
+        let pack = LudiChunkLoader::pack_coords;
+        // Every chunk records the chunks whose features reached into it
+        let generate_area = |orders: &[Vec<(i32, i32)>]| {
+            let protos = ProtoChunks::<Vec<u64>>::default();
+            let finished = Mutex::new(HashMap::new());
+            let generated = Mutex::new(HashMap::<u64, u32>::new());
+            std::thread::scope(|s| {
+                for order in orders {
+                    let (protos, finished, generated) = (&protos, &finished, &generated);
+                    s.spawn(move || {
+                        for coord in order.iter().map(|coord| pack(*coord)) {
+                            let chunk = protos.finish(
+                                Dimension::Overworld,
+                                coord,
+                                |neighbor| finished.lock().unwrap().contains_key(&neighbor),
+                                |neighbor| {
+                                    *generated.lock().unwrap().entry(neighbor).or_default() += 1;
+                                    Vec::new()
+                                },
+                                |center, region| {
+                                    for chunk in region {
+                                        // Finished chunks never get features placed in them
+                                        chunk.unwrap().push(center);
+                                    }
+                                },
+                            );
+                            finished.lock().unwrap().insert(coord, chunk);
+                            protos.forget_finished(Dimension::Overworld, [coord]);
+                        }
+                    });
+                }
+            });
+            // Chunks are generated once, the features placed in them are kept
+            assert!(generated
+                .into_inner()
+                .unwrap()
+                .values()
+                .all(|count| *count == 1));
+            let mut chunks = finished.into_inner().unwrap();
+            chunks.values_mut().for_each(|features| features.sort());
+            chunks
+        };
+        let area = (0..6)
+            .flat_map(|z| (0..6).map(move |x| (x, z)))
+            .collect::<Vec<_>>();
+        let by_rows = generate_area(std::slice::from_ref(&area));
+        for (coord, features) in &by_rows {
+            let mut around = LudiChunkLoader::neighborhood_coords(*coord);
+            around.sort();
+            assert_eq!(*features, around);
+        }
+        let mut scattered = area.clone();
+        scattered.sort_by_key(|(x, z)| (x * 7 + z * 13) % 36);
+        for orders in [
+            vec![scattered],
+            vec![area.iter().rev().copied().collect()],
+            // Two generation tasks at once
+            vec![
+                area[..18].to_vec(),
+                area[18..].iter().rev().copied().collect(),
+            ],
+        ] {
+            assert_eq!(generate_area(&orders), by_rows);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use super::super::packets::play::SystemChat;
+use super::logger::LOGGER;
+use super::operators::{ADMIN_LEVEL, GAMEMASTER_LEVEL};
+use super::pregen;
+use super::{Dimension, Player};
+use std::sync::Arc;
+
+/// Runs a command sent by a player and replies with the result in chat
+///
+/// Supported commands, only available to operators:
+///     * `/tp [@s|<player>] <x> <y> <z>`
+///     * `/execute in <dimension> run tp [@s|<player>] <x> <y> <z>`
+///     * `/pregen <radius>`, only for operators of the highest level
+pub async fn run_command(player: Arc<Player>, command: String) {
+    LOGGER.get().unwrap().println(&format!(
+        "{} issued server command: /{command}",
+        player.username
+    ));
+    let args = command.split_whitespace().collect::<Vec<_>>();
+    let permission_level = player.server.permission_level(&player);
+    let result = match args.as_slice() {
+        [] => return,
+        [name, ..] if permission_level < required_level(name) => {
+            Err(format!("You do not have permission to use /{name}"))
+        }
+        ["tp" | "teleport", args @ ..] => teleport(&player, player.get_dimension(), args).await,
+        ["execute", "in", dimension, "run", command @ ..] => {
+            match Dimension::from_name(dimension) {
+                Some(dimension) => match command {
+                    ["tp" | "teleport", args @ ..] => teleport(&player, dimension, args).await,
+                    _ => Err("Only tp can be run in another dimension".to_string()),
+                },
+                None => Err(format!("Unknown dimension {dimension}")),
+            }
+        }
+        ["pregen", args @ ..] => start_pregen(&player, args),
+        [name, ..] => Err(format!("Unknown or unsupported command: {name}")),
+    };
+    let message = result.unwrap_or_else(|e| e);
+    if let Some(tx) = player.outbound.upgrade() {
+        let _ = tx.send(SystemChat::new(message).into()).await;
+    }
+}
+
+async fn teleport(
+    player: &Arc<Player>,
+    dimension: Dimension,
+    args: &[&str],
+) -> Result<String, String> {
+    let coords = match args {
+        [target, coords @ ..] if *target == "@s" || *target == player.username => coords,
+        coords => coords,
+    };
+    let [x, y, z] = coords else {
+        return Err("Usage: /tp [@s] <x> <y> <z>".to_string());
+    };
+    let (old_x, old_y, old_z) = player.get_position();
+    let x = parse_coordinate(x, old_x)?;
+    let y = parse_coordinate(y, old_y)?;
+    let z = parse_coordinate(z, old_z)?;
+    player
+        .server
+        .teleport_player(player.clone(), dimension, (x, y, z))
+        .await;
+    Ok(format!(
+        "Teleported {} to {x:.2}, {y:.2}, {z:.2}",
+        player.username
+    ))
+}
+
+/// Permission level a player needs to run a command
+fn required_level(name: &str) -> u8 {
+    match name {
+        "pregen" => ADMIN_LEVEL,
+        _ => GAMEMASTER_LEVEL,
+    }
+}
+
+/// Pregenerates the chunks around the center of the player's dimension in the background,
+/// progress is sent to the player and logged
+fn start_pregen(player: &Arc<Player>, args: &[&str]) -> Result<String, String> {
+    let [radius] = args else {
+        return Err("Usage: /pregen <radius>".to_string());
+    };
+    let (radius, chunk_count) = radius
+        .parse::<i32>()
+        .ok()
+        .and_then(|radius| Some((radius, pregen::chunk_count(radius)?)))
+        .ok_or_else(|| {
+            format!(
+                "Invalid radius {radius}, it has to be between 0 and {}",
+                pregen::MAX_RADIUS
+            )
+        })?;
+    if pregen::is_running() {
+        return Err("Chunks are already being pregenerated".to_string());
+    }
+    let dimension = player.get_dimension();
+    let center = pregen::pregen_center(&player.server, dimension);
+    let outbound = player.outbound.clone();
+    tokio::spawn(pregen::pregenerate(
+        player.server.clone(),
+        dimension,
+        center,
+        radius,
+        move |progress| {
+            let message = progress.describe();
+            LOGGER.get().unwrap().println(&message);
+            if let Some(tx) = outbound.upgrade() {
+                let _ = tx.try_send(SystemChat::new(message).into());
+            }
+        },
+    ));
+    Ok(format!(
+        "Pregenerating {chunk_count} chunks around chunk {}, {} in {}",
+        center.0,
+        center.1,
+        dimension.name()
+    ))
+}
+
+/// Parses an absolute coordinate or one relative to `current` prefixed with `~`
+fn parse_coordinate(arg: &str, current: f64) -> Result<f64, String> {
+    let invalid = || format!("Invalid coordinate {arg}");
+    match arg.strip_prefix('~') {
+        Some("") => Ok(current),
+        Some(offset) => Ok(current + offset.parse::<f64>().map_err(|_| invalid())?),
+        None => arg.parse().map_err(|_| invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coordinates_can_be_relative() {
+        assert_eq!(parse_coordinate("12.5", 3.0), Ok(12.5));
+        assert_eq!(parse_coordinate("~", 3.0), Ok(3.0));
+        assert_eq!(parse_coordinate("~-4", 3.0), Ok(-1.0));
+        assert!(parse_coordinate("~x", 3.0).is_err());
+        assert!(parse_coordinate("north", 3.0).is_err());
+    }
+
+    #[test]
+    fn pregen_needs_the_highest_level() {
+        assert_eq!(required_level("tp"), GAMEMASTER_LEVEL);
+        assert_eq!(required_level("execute"), GAMEMASTER_LEVEL);
+        assert_eq!(required_level("pregen"), ADMIN_LEVEL);
+    }
+}
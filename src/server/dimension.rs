@@ -0,0 +1,63 @@
+/// A dimension of the world, each with its own region folder, generator and dimension type
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Overworld,
+    Nether,
+    End,
+}
+
+impl Dimension {
+    pub const ALL: [Dimension; 3] = [Self::Overworld, Self::Nether, Self::End];
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    /// Accepts the dimension name with or without the `minecraft` namespace
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.strip_prefix("minecraft:").unwrap_or(name);
+        Self::ALL.into_iter().find(|dim| dim.type_name() == name)
+    }
+
+    /// Name of the dimension as used by `level.dat` and the `WORLD_STATES` keys
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Overworld => "minecraft:overworld",
+            Self::Nether => "minecraft:the_nether",
+            Self::End => "minecraft:the_end",
+        }
+    }
+
+    /// Name of the dimension type as found in "versions/[version]/minecraft/dimension_type/"
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Overworld => "overworld",
+            Self::Nether => "the_nether",
+            Self::End => "the_end",
+        }
+    }
+
+    /// Index of the dimension type in the `dimension_type` registry sent to the client
+    pub fn type_id(&self) -> i32 {
+        *self as i32
+    }
+
+    /// Legacy dimension id, used to name the dimension folders
+    pub fn id(&self) -> i32 {
+        match self {
+            Self::Overworld => 0,
+            Self::Nether => -1,
+            Self::End => 1,
+        }
+    }
+
+    /// Folder of the dimension relative to the world folder
+    pub fn folder(&self) -> &'static str {
+        match self {
+            Self::Overworld => "",
+            Self::Nether => "DIM-1/",
+            Self::End => "DIM1/",
+        }
+    }
+}
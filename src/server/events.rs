@@ -1,12 +1,20 @@
-use super::Player;
+use super::{Dimension, Player};
 use std::sync::Arc;
 
 pub enum ServerEvent {
     ChunkLoad(ChunkLoadTask),
+    /// Releases the chunks a player views in a dimension, or in all dimensions if `None`. Sent
+    /// to every shard so it runs after the chunk loads already queued for the player
+    ReleasePlayer {
+        player_id: i32,
+        dimension: Option<Dimension>,
+    },
 }
 
 pub struct ChunkLoadTask {
     pub player: Arc<Player>,
+    /// Dimension the player was in when the task was created
+    pub dimension: Dimension,
     pub chunks_requested: Vec<u64>,
     pub chunks_unloaded: Vec<u64>,
 }
@@ -14,6 +22,7 @@ pub struct ChunkLoadTask {
 impl ChunkLoadTask {
     pub fn new(player: Arc<Player>) -> Self {
         Self {
+            dimension: player.get_dimension(),
             player,
             chunks_requested: Vec::new(),
             chunks_unloaded: Vec::new(),
@@ -0,0 +1,283 @@
+use super::super::chunk_system::{split_block_state, BLOCKSTATES};
+use super::super::heightmap::HEIGHTMAP_BLOCKS;
+use super::super::tags::BlockSet;
+use super::region::{FeatureRegion, WorldHeightmap};
+use super::state_provider::BlockState;
+use super::{block_name, property, Direction, Fluid, FEATURE_BLOCKS};
+use crate::RandomGenerator;
+use serde::{Deserialize, Deserializer};
+
+/// Reads a block tag id without the leading `#`
+pub fn deserialize_tag<'de, D>(deserializer: D) -> Result<BlockSet, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let tag = String::deserialize(deserializer)?;
+    Ok(BlockSet::from_ids(&[format!("#{tag}")]))
+}
+
+/// Fluids matched by a holder set of fluid ids or fluid tags
+#[derive(Debug, Default)]
+pub struct FluidSet(Vec<Fluid>);
+
+impl<'de> Deserialize<'de> for FluidSet {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum HolderSet {
+            Single(String),
+            List(Vec<String>),
+        }
+        let ids = match HolderSet::deserialize(deserializer)? {
+            HolderSet::Single(id) => vec![id],
+            HolderSet::List(ids) => ids,
+        };
+        let mut fluids = Vec::new();
+        for id in ids {
+            let id = id.replace("minecraft:", "");
+            fluids.extend_from_slice(match id.as_str() {
+                "water" => &[Fluid::Water][..],
+                "flowing_water" => &[Fluid::FlowingWater],
+                "#water" => &[Fluid::Water, Fluid::FlowingWater],
+                "lava" => &[Fluid::Lava],
+                "flowing_lava" => &[Fluid::FlowingLava],
+                "#lava" => &[Fluid::Lava, Fluid::FlowingLava],
+                "empty" => &[Fluid::Empty],
+                _ => &[],
+            });
+        }
+        Ok(FluidSet(fluids))
+    }
+}
+
+/// Condition on the block at a position offset from the tested position
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum BlockPredicate {
+    #[serde(rename = "minecraft:matching_blocks")]
+    MatchingBlocks {
+        #[serde(default)]
+        offset: [i32; 3],
+        blocks: BlockSet,
+    },
+    #[serde(rename = "minecraft:matching_block_tag")]
+    MatchingBlockTag {
+        #[serde(default)]
+        offset: [i32; 3],
+        #[serde(deserialize_with = "deserialize_tag")]
+        tag: BlockSet,
+    },
+    #[serde(rename = "minecraft:matching_fluids")]
+    MatchingFluids {
+        #[serde(default)]
+        offset: [i32; 3],
+        fluids: FluidSet,
+    },
+    #[serde(rename = "minecraft:solid")]
+    Solid {
+        #[serde(default)]
+        offset: [i32; 3],
+    },
+    #[serde(rename = "minecraft:replaceable")]
+    Replaceable {
+        #[serde(default)]
+        offset: [i32; 3],
+    },
+    #[serde(rename = "minecraft:would_survive")]
+    WouldSurvive {
+        #[serde(default)]
+        offset: [i32; 3],
+        state: BlockState,
+    },
+    #[serde(rename = "minecraft:inside_world_bounds")]
+    InsideWorldBounds {
+        #[serde(default)]
+        offset: [i32; 3],
+    },
+    /// Every face of a solid block is taken as sturdy
+    #[serde(rename = "minecraft:has_sturdy_face")]
+    HasSturdyFace {
+        #[serde(default)]
+        offset: [i32; 3],
+    },
+    #[serde(rename = "minecraft:any_of")]
+    AnyOf { predicates: Vec<BlockPredicate> },
+    #[serde(rename = "minecraft:all_of")]
+    AllOf { predicates: Vec<BlockPredicate> },
+    #[serde(rename = "minecraft:not")]
+    Not { predicate: Box<BlockPredicate> },
+    #[serde(rename = "minecraft:true")]
+    True,
+    /// Checks for entities in the way, which are never present while generating
+    #[serde(other)]
+    Unobstructed,
+}
+
+fn offset((x, y, z): (i32, i32, i32), [dx, dy, dz]: [i32; 3]) -> (i32, i32, i32) {
+    (x + dx, y + dy, z + dz)
+}
+
+impl BlockPredicate {
+    pub fn test(&self, region: &FeatureRegion, pos: (i32, i32, i32)) -> bool {
+        let block_at = |offset_by: &[i32; 3]| {
+            let (x, y, z) = offset(pos, *offset_by);
+            region.get_block(x, y, z)
+        };
+        match self {
+            BlockPredicate::MatchingBlocks { offset, blocks }
+            | BlockPredicate::MatchingBlockTag {
+                offset,
+                tag: blocks,
+            } => blocks.contains(block_at(offset)),
+            BlockPredicate::MatchingFluids { offset, fluids } => {
+                fluids.0.contains(&Fluid::of(block_at(offset)))
+            }
+            BlockPredicate::Solid { offset } => is_solid(block_at(offset)),
+            BlockPredicate::Replaceable { offset } => {
+                FEATURE_BLOCKS.replaceable.contains(block_at(offset))
+            }
+            BlockPredicate::WouldSurvive {
+                offset: offset_by,
+                state,
+            } => can_survive(region, state.0, offset(pos, *offset_by)),
+            BlockPredicate::InsideWorldBounds { offset: offset_by } => {
+                !region.is_outside_build_height(offset(pos, *offset_by).1)
+            }
+            BlockPredicate::HasSturdyFace { offset } => is_solid(block_at(offset)),
+            BlockPredicate::AnyOf { predicates } => predicates
+                .iter()
+                .any(|predicate| predicate.test(region, pos)),
+            BlockPredicate::AllOf { predicates } => predicates
+                .iter()
+                .all(|predicate| predicate.test(region, pos)),
+            BlockPredicate::Not { predicate } => !predicate.test(region, pos),
+            BlockPredicate::True | BlockPredicate::Unobstructed => true,
+        }
+    }
+}
+
+/// Test of the block an ore replaces
+#[derive(Deserialize)]
+#[serde(tag = "predicate_type")]
+pub enum RuleTest {
+    #[serde(rename = "minecraft:always_true")]
+    AlwaysTrue,
+    #[serde(rename = "minecraft:block_match")]
+    BlockMatch { block: BlockSet },
+    #[serde(rename = "minecraft:blockstate_match")]
+    BlockStateMatch { block_state: BlockState },
+    #[serde(rename = "minecraft:tag_match")]
+    TagMatch {
+        #[serde(deserialize_with = "deserialize_tag")]
+        tag: BlockSet,
+    },
+    #[serde(rename = "minecraft:random_block_match")]
+    RandomBlockMatch { block: BlockSet, probability: f32 },
+    #[serde(rename = "minecraft:random_blockstate_match")]
+    RandomBlockStateMatch {
+        block_state: BlockState,
+        probability: f32,
+    },
+}
+
+impl RuleTest {
+    pub fn test(&self, block: u32, random: &mut dyn RandomGenerator) -> bool {
+        match self {
+            RuleTest::AlwaysTrue => true,
+            RuleTest::BlockMatch { block: blocks } | RuleTest::TagMatch { tag: blocks } => {
+                blocks.contains(block)
+            }
+            RuleTest::BlockStateMatch { block_state } => block_state.0 == block,
+            RuleTest::RandomBlockMatch {
+                block: blocks,
+                probability,
+            } => blocks.contains(block) && random.next_f32() < *probability,
+            RuleTest::RandomBlockStateMatch {
+                block_state,
+                probability,
+            } => block_state.0 == block && random.next_f32() < *probability,
+        }
+    }
+}
+
+/// Whether a block has a collision shape filling most of its space, approximated by whether it
+/// blocks motion
+pub fn is_solid(block: u32) -> bool {
+    HEIGHTMAP_BLOCKS[block as usize].blocks_motion
+}
+
+pub fn is_air(block: u32) -> bool {
+    HEIGHTMAP_BLOCKS[block as usize].is_air
+}
+
+/// Whether a block could stay at a position, approximated for the plants placed by features
+pub fn can_survive(region: &FeatureRegion, block: u32, (x, y, z): (i32, i32, i32)) -> bool {
+    let blocks = &*FEATURE_BLOCKS;
+    let name = split_block_state(&BLOCKSTATES[block as usize]).0;
+    let below = region.get_block(x, y - 1, z);
+    let on_dirt = blocks.dirt.contains(below) || block_name(below) == "farmland";
+    let horizontal = || {
+        Direction::HORIZONTAL.map(|direction| {
+            let (x, y, z) = direction.offset((x, y, z), 1);
+            region.get_block(x, y, z)
+        })
+    };
+    if property(block, "half") == Some("upper") && !name.ends_with("_door") {
+        return block_name(below) == name && property(below, "half") == Some("lower");
+    }
+    match name {
+        "cactus" => {
+            horizontal()
+                .iter()
+                .all(|side| !is_solid(*side) && !Fluid::of(*side).is_lava())
+                && (block_name(below) == "cactus" || blocks.sand.contains(below))
+                && Fluid::of(region.get_block(x, y + 1, z)) == Fluid::Empty
+        }
+        "sugar_cane" => {
+            block_name(below) == "sugar_cane"
+                || (blocks.dirt.contains(below) || blocks.sand.contains(below))
+                    && Direction::HORIZONTAL.iter().any(|direction| {
+                        let (x, y, z) = direction.offset((x, y - 1, z), 1);
+                        let side = region.get_block(x, y, z);
+                        Fluid::of(side).is_water() || block_name(side) == "frosted_ice"
+                    })
+        }
+        "lily_pad" => {
+            (Fluid::of(below) == Fluid::Water || block_name(below) == "ice")
+                && Fluid::of(region.get_block(x, y, z)) == Fluid::Empty
+        }
+        "brown_mushroom" | "red_mushroom" => {
+            blocks.mushroom_grow_block.contains(below)
+                || is_solid(below) && y < region.get_height(WorldHeightmap::WorldSurface, x, z)
+        }
+        "seagrass" | "tall_seagrass" | "kelp" | "sea_pickle" => {
+            Fluid::of(region.get_block(x, y, z)).is_water()
+                && (is_solid(below) && block_name(below) != "magma_block"
+                    || matches!(block_name(below), "kelp" | "kelp_plant") && name == "kelp")
+        }
+        "dead_bush" | "short_dry_grass" | "tall_dry_grass" => {
+            on_dirt || blocks.sand.contains(below) || blocks.terracotta.contains(below)
+        }
+        "crimson_roots" | "warped_roots" | "nether_sprouts" | "crimson_fungus"
+        | "warped_fungus" => {
+            blocks.nylium.contains(below) || on_dirt || block_name(below) == "soul_soil"
+        }
+        "wither_rose" => {
+            on_dirt || matches!(block_name(below), "netherrack" | "soul_sand" | "soul_soil")
+        }
+        "snow" => {
+            !blocks.snow_layer_cannot_survive_on.contains(below)
+                && (blocks.snow_layer_can_survive_on.contains(below)
+                    || is_solid(below) && !blocks.leaves.contains(below)
+                    || block_name(below) == "snow" && property(below, "layers") == Some("8"))
+        }
+        "short_grass" | "fern" | "tall_grass" | "large_fern" | "sweet_berry_bush" | "bush"
+        | "firefly_bush" | "pink_petals" | "wildflowers" | "leaf_litter" | "sunflower"
+        | "lilac" | "rose_bush" | "peony" | "pitcher_plant" => on_dirt,
+        _ if blocks.small_flowers.contains(block) || blocks.saplings.contains(block) => on_dirt,
+        _ => true,
+    }
+}
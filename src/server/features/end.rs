@@ -0,0 +1,273 @@
+use super::super::chunk_system::BLOCKSTATE_MAPPINGS;
+use super::super::dimension::Dimension;
+use super::super::randomness::LCG48;
+use super::super::structures::jigsaw::TerrainHeights;
+use super::region::{FeatureRegion, WorldHeightmap};
+use crate::RandomGenerator;
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+type Pos = (i32, i32, i32);
+
+fn block(name: &str) -> u32 {
+    *BLOCKSTATE_MAPPINGS.get(name).unwrap()
+}
+
+/// Obsidian pillar around the main end island
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct EndSpike {
+    center_x: i32,
+    center_z: i32,
+    #[serde(default)]
+    radius: i32,
+    #[serde(default)]
+    height: i32,
+    #[serde(default)]
+    guarded: bool,
+}
+
+#[derive(Deserialize)]
+pub struct EndSpikeConfig {
+    #[serde(default)]
+    spikes: Vec<EndSpike>,
+}
+
+/// The 10 pillars of a world, their sizes are shuffled by the world seed
+fn spikes_for_seed(seed: i64) -> Vec<EndSpike> {
+    let mut random = LCG48::new(LCG48::new(seed).next_i64() & 65535);
+    let mut sizes = (0..10).collect::<Vec<i32>>();
+    for i in (2..=sizes.len()).rev() {
+        let j = random.next_i32_range(i as u32) as usize;
+        sizes.swap(i - 1, j);
+    }
+    sizes
+        .into_iter()
+        .enumerate()
+        .map(|(i, size)| {
+            let angle = 2.0 * (-PI + PI / 10.0 * i as f64);
+            EndSpike {
+                center_x: (42.0 * angle.cos()).floor() as i32,
+                center_z: (42.0 * angle.sin()).floor() as i32,
+                radius: 2 + size / 3,
+                height: 76 + size * 3,
+                guarded: size == 1 || size == 2,
+            }
+        })
+        .collect()
+}
+
+impl EndSpikeConfig {
+    /// Places the pillars centered in the chunk of the origin, like vanilla's `EndSpikeFeature`.
+    /// The end crystals on top are not spawned since entities are not saved with the chunks
+    pub fn place(&self, region: &mut FeatureRegion, (x, _, z): Pos) -> bool {
+        let spikes = if self.spikes.is_empty() {
+            spikes_for_seed(region.world_state.seed)
+        } else {
+            self.spikes.clone()
+        };
+        for spike in spikes {
+            if spike.center_x >> 4 == x >> 4 && spike.center_z >> 4 == z >> 4 {
+                place_spike(region, &spike);
+            }
+        }
+        true
+    }
+}
+
+fn place_spike(region: &mut FeatureRegion, spike: &EndSpike) {
+    let (obsidian, air) = (block("obsidian"), block("air"));
+    let radius = spike.radius;
+    for x in spike.center_x - radius..=spike.center_x + radius {
+        for z in spike.center_z - radius..=spike.center_z + radius {
+            let (dx, dz) = (x - spike.center_x, z - spike.center_z);
+            let inside = dx * dx + dz * dz <= radius * radius + 1;
+            for y in region.min_y..=spike.height + 10 {
+                if inside && y < spike.height {
+                    region.set_block(x, y, z, obsidian);
+                } else if y > 65 {
+                    region.set_block(x, y, z, air);
+                }
+            }
+        }
+    }
+    if spike.guarded {
+        for dx in -2i32..=2 {
+            for dz in -2i32..=2 {
+                for dy in 0..=3 {
+                    let top = dy == 3;
+                    if dx.abs() != 2 && dz.abs() != 2 && !top {
+                        continue;
+                    }
+                    let along_x = dx.abs() == 2 || top;
+                    let along_z = dz.abs() == 2 || top;
+                    let bars = block(&format!(
+                        "iron_bars[east={},north={},south={},waterlogged=false,west={}]",
+                        along_z && dx != 2,
+                        along_x && dz != -2,
+                        along_x && dz != 2,
+                        along_z && dx != -2,
+                    ));
+                    region.set_block(
+                        spike.center_x + dx,
+                        spike.height + dy,
+                        spike.center_z + dz,
+                        bars,
+                    );
+                }
+            }
+        }
+    }
+    region.set_block(
+        spike.center_x,
+        spike.height,
+        spike.center_z,
+        block("bedrock"),
+    );
+    region.set_block(
+        spike.center_x,
+        spike.height + 1,
+        spike.center_z,
+        block("fire[age=0,east=false,north=false,south=false,up=false,west=false]"),
+    );
+}
+
+/// Gateway framed by bedrock, like vanilla's `EndGatewayFeature`. The gateway has no block
+/// entity so it does not teleport yet
+pub fn place_end_gateway(region: &mut FeatureRegion, (x, y, z): Pos) -> bool {
+    let (gateway, bedrock, air) = (block("end_gateway"), block("bedrock"), block("air"));
+    for dx in -1i32..=1 {
+        for dy in -2i32..=2 {
+            for dz in -1i32..=1 {
+                let center_column = dx == 0 && dz == 0;
+                let block = if center_column && dy == 0 {
+                    gateway
+                } else if dy == 0 {
+                    air
+                } else if (dx == 0 || dz == 0) && (dy.abs() != 2 || center_column) {
+                    bedrock
+                } else {
+                    air
+                };
+                region.set_block(x + dx, y + dy, z + dz, block);
+            }
+        }
+    }
+    true
+}
+
+/// Stack of shrinking end stone disks, like vanilla's `EndIslandFeature`
+pub fn place_end_island(
+    region: &mut FeatureRegion,
+    random: &mut dyn RandomGenerator,
+    (x, y, z): Pos,
+) -> bool {
+    let end_stone = block("end_stone");
+    let mut radius = random.next_i32_range(3) as f32 + 4.0;
+    let mut dy = 0;
+    while radius > 0.5 {
+        for dx in (-radius).floor() as i32..=radius.ceil() as i32 {
+            for dz in (-radius).floor() as i32..=radius.ceil() as i32 {
+                if ((dx * dx + dz * dz) as f32) <= (radius + 1.0) * (radius + 1.0) {
+                    region.set_block(x + dx, y + dy, z + dz, end_stone);
+                }
+            }
+        }
+        radius -= random.next_i32_range(2) as f32 + 0.5;
+        dy -= 1;
+    }
+    true
+}
+
+/// Inactive exit portal at the world origin, like the podium vanilla's dragon fight places when
+/// the end is first loaded. It is placed by each of the 4 chunks it overlaps, its height is taken
+/// from the terrain noise so every chunk agrees on it
+pub fn place_end_podium(region: &mut FeatureRegion) {
+    if region.dimension != Dimension::End.name()
+        || !(-1..=0).contains(&region.center_x)
+        || !(-1..=0).contains(&region.center_z)
+    {
+        return;
+    }
+    let surface = TerrainHeights::new(region.world_state, region.dimension).first_free_height(
+        0,
+        0,
+        WorldHeightmap::MotionBlockingNoLeaves,
+    );
+    let y = (surface - 1).max(region.min_y + 1);
+    let (bedrock, end_stone, air) = (block("bedrock"), block("end_stone"), block("air"));
+    for dx in -4i32..=4 {
+        for dz in -4i32..=4 {
+            for dy in -1i32..=32 {
+                let distance = dx * dx + dy * dy + dz * dz;
+                let inner = (distance as f64) < 2.5 * 2.5;
+                if !inner && distance as f64 >= 3.5 * 3.5 {
+                    continue;
+                }
+                let block = if dy < 0 {
+                    if inner {
+                        bedrock
+                    } else {
+                        end_stone
+                    }
+                } else if dy > 0 || inner {
+                    air
+                } else {
+                    bedrock
+                };
+                region.set_block(dx, y + dy, dz, block);
+            }
+        }
+    }
+    for dy in 0..4 {
+        region.set_block(0, y + dy, 0, bedrock);
+    }
+    for (facing, dx, dz) in [
+        ("north", 0, -1),
+        ("east", 1, 0),
+        ("south", 0, 1),
+        ("west", -1, 0),
+    ] {
+        region.set_block(
+            dx,
+            y + 2,
+            dz,
+            block(&format!("wall_torch[facing={facing}]")),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spike_sizes_are_shuffled_by_the_seed() {
+        let spikes = spikes_for_seed(1);
+        assert_eq!(spikes.len(), 10);
+        let mut sizes = spikes
+            .iter()
+            .map(|spike| (spike.height - 76) / 3)
+            .collect::<Vec<_>>();
+        for (spike, size) in spikes.iter().zip(&sizes) {
+            assert_eq!(spike.radius, 2 + size / 3);
+            assert_eq!(spike.guarded, *size == 1 || *size == 2);
+            let distance = ((spike.center_x.pow(2) + spike.center_z.pow(2)) as f64).sqrt();
+            assert!((40.0..=43.0).contains(&distance));
+        }
+        let order = sizes.clone();
+        sizes.sort();
+        assert_eq!(sizes, (0..10).collect::<Vec<_>>());
+        // The pillars keep their places, only their sizes move between seeds
+        let other = spikes_for_seed(2);
+        assert!(spikes
+            .iter()
+            .zip(&other)
+            .all(|(a, b)| (a.center_x, a.center_z) == (b.center_x, b.center_z)));
+        let other_order = other
+            .iter()
+            .map(|spike| (spike.height - 76) / 3)
+            .collect::<Vec<_>>();
+        assert_ne!(order, other_order);
+    }
+}
@@ -0,0 +1,810 @@
+use super::super::biome_source::BIOME_CLIMATES;
+use super::super::chunk_system::BLOCKSTATE_MAPPINGS;
+use super::super::tags::BlockSet;
+use super::super::util::{table_cos, table_sin};
+use super::super::value_providers::IntProvider;
+use super::block_predicate::{can_survive, is_air, is_solid, BlockPredicate, RuleTest};
+use super::end::{place_end_gateway, place_end_island, EndSpikeConfig};
+use super::region::{FeatureRegion, WorldHeightmap};
+use super::state_provider::{BlockState, BlockStateProvider, FluidBlock, RuleBasedStateProvider};
+use super::tree::TreeConfig;
+use super::{
+    block_name, property, with_property, Direction, Fluid, PlacedFeatureRef, FEATURE_BLOCKS,
+    UNSUPPORTED_FEATURES,
+};
+use crate::RandomGenerator;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use std::f32::consts::PI;
+
+#[derive(Deserialize)]
+pub struct OreTarget {
+    target: RuleTest,
+    state: BlockState,
+}
+
+#[derive(Deserialize)]
+pub struct OreConfig {
+    size: i32,
+    discard_chance_on_air_exposure: f32,
+    targets: Vec<OreTarget>,
+}
+
+fn default_tries() -> i32 {
+    128
+}
+
+fn default_xz_spread() -> i32 {
+    7
+}
+
+fn default_y_spread() -> i32 {
+    3
+}
+
+#[derive(Deserialize)]
+pub struct RandomPatchConfig {
+    #[serde(default = "default_tries")]
+    tries: i32,
+    #[serde(default = "default_xz_spread")]
+    xz_spread: i32,
+    #[serde(default = "default_y_spread")]
+    y_spread: i32,
+    feature: PlacedFeatureRef,
+}
+
+#[derive(Deserialize)]
+pub struct DiskConfig {
+    state_provider: RuleBasedStateProvider,
+    target: BlockPredicate,
+    radius: IntProvider,
+    half_height: i32,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_rock_count() -> i32 {
+    4
+}
+
+fn default_hole_count() -> i32 {
+    1
+}
+
+#[derive(Deserialize)]
+pub struct SpringConfig {
+    state: FluidBlock,
+    #[serde(default = "default_true")]
+    requires_block_below: bool,
+    #[serde(default = "default_rock_count")]
+    rock_count: i32,
+    #[serde(default = "default_hole_count")]
+    hole_count: i32,
+    valid_blocks: BlockSet,
+}
+
+#[derive(Deserialize)]
+pub struct WeightedPlacedFeature {
+    feature: PlacedFeatureRef,
+    chance: f32,
+}
+
+#[derive(Deserialize)]
+pub struct ColumnLayer {
+    height: IntProvider,
+    provider: BlockStateProvider,
+}
+
+#[derive(Deserialize)]
+pub struct BlockColumnConfig {
+    layers: Vec<ColumnLayer>,
+    direction: Direction,
+    allowed_placement: BlockPredicate,
+    prioritize_tip: bool,
+}
+
+/// Feature type with its configuration, read from `worldgen/configured_feature`
+pub enum ConfiguredFeature {
+    Ore(OreConfig),
+    ScatteredOre(OreConfig),
+    /// Also used by the flower features
+    RandomPatch(RandomPatchConfig),
+    SimpleBlock {
+        to_place: BlockStateProvider,
+    },
+    Disk(DiskConfig),
+    Lake {
+        fluid: BlockStateProvider,
+        barrier: BlockStateProvider,
+    },
+    Spring(SpringConfig),
+    RandomSelector {
+        features: Vec<WeightedPlacedFeature>,
+        default: PlacedFeatureRef,
+    },
+    SimpleRandomSelector {
+        features: Vec<PlacedFeatureRef>,
+    },
+    RandomBooleanSelector {
+        feature_true: PlacedFeatureRef,
+        feature_false: PlacedFeatureRef,
+    },
+    BlockColumn(BlockColumnConfig),
+    /// Snow and ice on the top blocks of a chunk
+    FreezeTopLayer,
+    Tree(Box<TreeConfig>),
+    EndSpike(EndSpikeConfig),
+    EndGateway,
+    EndIsland,
+    /// Features of other types, which place nothing
+    Unsupported,
+}
+
+impl<'de> Deserialize<'de> for ConfiguredFeature {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct RawFeature {
+            #[serde(rename = "type")]
+            kind: String,
+            #[serde(default)]
+            config: serde_json::Value,
+        }
+        #[derive(Deserialize)]
+        struct SimpleBlockConfig {
+            to_place: BlockStateProvider,
+        }
+        #[derive(Deserialize)]
+        struct LakeConfig {
+            fluid: BlockStateProvider,
+            barrier: BlockStateProvider,
+        }
+        #[derive(Deserialize)]
+        struct RandomSelectorConfig {
+            features: Vec<WeightedPlacedFeature>,
+            default: PlacedFeatureRef,
+        }
+        #[derive(Deserialize)]
+        struct SimpleRandomSelectorConfig {
+            features: Vec<PlacedFeatureRef>,
+        }
+        #[derive(Deserialize)]
+        struct RandomBooleanSelectorConfig {
+            feature_true: PlacedFeatureRef,
+            feature_false: PlacedFeatureRef,
+        }
+
+        let raw = RawFeature::deserialize(deserializer)?;
+        let config = raw.config;
+        let feature = match raw.kind.trim_start_matches("minecraft:") {
+            "ore" => {
+                ConfiguredFeature::Ore(serde_json::from_value(config).map_err(D::Error::custom)?)
+            }
+            "scattered_ore" => ConfiguredFeature::ScatteredOre(
+                serde_json::from_value(config).map_err(D::Error::custom)?,
+            ),
+            "random_patch" | "flower" | "no_bonemeal_flower" => ConfiguredFeature::RandomPatch(
+                serde_json::from_value(config).map_err(D::Error::custom)?,
+            ),
+            "simple_block" => {
+                let config: SimpleBlockConfig =
+                    serde_json::from_value(config).map_err(D::Error::custom)?;
+                ConfiguredFeature::SimpleBlock {
+                    to_place: config.to_place,
+                }
+            }
+            "disk" => {
+                ConfiguredFeature::Disk(serde_json::from_value(config).map_err(D::Error::custom)?)
+            }
+            "lake" => {
+                let config: LakeConfig =
+                    serde_json::from_value(config).map_err(D::Error::custom)?;
+                ConfiguredFeature::Lake {
+                    fluid: config.fluid,
+                    barrier: config.barrier,
+                }
+            }
+            "spring_feature" => {
+                ConfiguredFeature::Spring(serde_json::from_value(config).map_err(D::Error::custom)?)
+            }
+            "random_selector" => {
+                let config: RandomSelectorConfig =
+                    serde_json::from_value(config).map_err(D::Error::custom)?;
+                ConfiguredFeature::RandomSelector {
+                    features: config.features,
+                    default: config.default,
+                }
+            }
+            "simple_random_selector" => {
+                let config: SimpleRandomSelectorConfig =
+                    serde_json::from_value(config).map_err(D::Error::custom)?;
+                ConfiguredFeature::SimpleRandomSelector {
+                    features: config.features,
+                }
+            }
+            "random_boolean_selector" => {
+                let config: RandomBooleanSelectorConfig =
+                    serde_json::from_value(config).map_err(D::Error::custom)?;
+                ConfiguredFeature::RandomBooleanSelector {
+                    feature_true: config.feature_true,
+                    feature_false: config.feature_false,
+                }
+            }
+            "block_column" => ConfiguredFeature::BlockColumn(
+                serde_json::from_value(config).map_err(D::Error::custom)?,
+            ),
+            "freeze_top_layer" => ConfiguredFeature::FreezeTopLayer,
+            "tree" => ConfiguredFeature::Tree(Box::new(
+                serde_json::from_value(config).map_err(D::Error::custom)?,
+            )),
+            "end_spike" => ConfiguredFeature::EndSpike(
+                serde_json::from_value(config).map_err(D::Error::custom)?,
+            ),
+            "end_gateway" => ConfiguredFeature::EndGateway,
+            "end_island" => ConfiguredFeature::EndIsland,
+            _ => {
+                UNSUPPORTED_FEATURES
+                    .lock()
+                    .unwrap()
+                    .insert(raw.kind.clone());
+                ConfiguredFeature::Unsupported
+            }
+        };
+        Ok(feature)
+    }
+}
+
+impl ConfiguredFeature {
+    /// Places the feature at a position, returns whether anything was placed
+    pub fn place(
+        &self,
+        region: &mut FeatureRegion,
+        random: &mut dyn RandomGenerator,
+        pos: (i32, i32, i32),
+    ) -> bool {
+        if !region.can_write(pos.0, pos.2) {
+            return false;
+        }
+        match self {
+            ConfiguredFeature::Ore(config) => place_ore(region, random, pos, config),
+            ConfiguredFeature::ScatteredOre(config) => {
+                place_scattered_ore(region, random, pos, config)
+            }
+            ConfiguredFeature::RandomPatch(config) => {
+                let xz_bound = (config.xz_spread + 1) as u32;
+                let y_bound = (config.y_spread + 1) as u32;
+                let mut placed = 0;
+                for _ in 0..config.tries {
+                    let x = random.next_i32_range(xz_bound) - random.next_i32_range(xz_bound);
+                    let y = random.next_i32_range(y_bound) - random.next_i32_range(y_bound);
+                    let z = random.next_i32_range(xz_bound) - random.next_i32_range(xz_bound);
+                    if config
+                        .feature
+                        .place(region, random, (pos.0 + x, pos.1 + y, pos.2 + z))
+                    {
+                        placed += 1;
+                    }
+                }
+                placed > 0
+            }
+            ConfiguredFeature::SimpleBlock { to_place } => {
+                let block = to_place.get_state(random, pos);
+                place_simple_block(region, block, pos)
+            }
+            ConfiguredFeature::Disk(config) => place_disk(region, random, pos, config),
+            ConfiguredFeature::Lake { fluid, barrier } => {
+                place_lake(region, random, pos, fluid, barrier)
+            }
+            ConfiguredFeature::Spring(config) => place_spring(region, pos, config),
+            ConfiguredFeature::RandomSelector { features, default } => {
+                for feature in features {
+                    if random.next_f32() < feature.chance {
+                        return feature.feature.place(region, random, pos);
+                    }
+                }
+                default.place(region, random, pos)
+            }
+            ConfiguredFeature::SimpleRandomSelector { features } => {
+                let index = random.next_i32_range(features.len() as u32) as usize;
+                features[index].place(region, random, pos)
+            }
+            ConfiguredFeature::RandomBooleanSelector {
+                feature_true,
+                feature_false,
+            } => {
+                if random.next_bool() {
+                    feature_true.place(region, random, pos)
+                } else {
+                    feature_false.place(region, random, pos)
+                }
+            }
+            ConfiguredFeature::BlockColumn(config) => {
+                place_block_column(region, random, pos, config)
+            }
+            ConfiguredFeature::FreezeTopLayer => freeze_top_layer(region, pos),
+            ConfiguredFeature::Tree(config) => config.place(region, random, pos),
+            ConfiguredFeature::EndSpike(config) => config.place(region, pos),
+            ConfiguredFeature::EndGateway => place_end_gateway(region, pos),
+            ConfiguredFeature::EndIsland => place_end_island(region, random, pos),
+            ConfiguredFeature::Unsupported => false,
+        }
+    }
+}
+
+/// Blob of ore along a random segment, like vanilla's `OreFeature`
+fn place_ore(
+    region: &mut FeatureRegion,
+    random: &mut dyn RandomGenerator,
+    (x, y, z): (i32, i32, i32),
+    config: &OreConfig,
+) -> bool {
+    let angle = random.next_f32() * PI;
+    let spread = config.size as f32 / 8.0;
+    let radius = ((config.size as f32 / 16.0 * 2.0 + 1.0) / 2.0).ceil() as i32;
+    let min_x = x as f64 + (table_sin(angle as f64) * spread) as f64;
+    let max_x = x as f64 - (table_sin(angle as f64) * spread) as f64;
+    let min_z = z as f64 + (table_cos(angle as f64) * spread) as f64;
+    let max_z = z as f64 - (table_cos(angle as f64) * spread) as f64;
+    let min_y = (y + random.next_i32_range(3) - 2) as f64;
+    let max_y = (y + random.next_i32_range(3) - 2) as f64;
+    let start_x = x - spread.ceil() as i32 - radius;
+    let start_y = y - 2 - radius;
+    let start_z = z - spread.ceil() as i32 - radius;
+    let width = 2 * (spread.ceil() as i32 + radius);
+    let height = 2 * (2 + radius);
+    for column_x in start_x..=start_x + width {
+        for column_z in start_z..=start_z + width {
+            if start_y <= region.get_height(WorldHeightmap::OceanFloorWg, column_x, column_z) {
+                let segment = (min_x, max_x, min_y, max_y, min_z, max_z);
+                return place_ore_blob(
+                    region,
+                    random,
+                    config,
+                    segment,
+                    (start_x, start_y, start_z),
+                    width,
+                    height,
+                );
+            }
+        }
+    }
+    false
+}
+
+fn place_ore_blob(
+    region: &mut FeatureRegion,
+    random: &mut dyn RandomGenerator,
+    config: &OreConfig,
+    (min_x, max_x, min_y, max_y, min_z, max_z): (f64, f64, f64, f64, f64, f64),
+    (start_x, start_y, start_z): (i32, i32, i32),
+    width: i32,
+    height: i32,
+) -> bool {
+    let size = config.size.max(0) as usize;
+    let mut spheres = vec![[0.0f64; 4]; size];
+    for (i, sphere) in spheres.iter_mut().enumerate() {
+        let progress = i as f32 / size as f32;
+        let radius = random.next_f64() * size as f64 / 16.0;
+        *sphere = [
+            min_x + progress as f64 * (max_x - min_x),
+            min_y + progress as f64 * (max_y - min_y),
+            min_z + progress as f64 * (max_z - min_z),
+            ((table_sin((PI * progress) as f64) + 1.0) as f64 * radius + 1.0) / 2.0,
+        ];
+    }
+    // Spheres inside of another sphere are dropped
+    for i in 0..size.saturating_sub(1) {
+        if spheres[i][3] <= 0.0 {
+            continue;
+        }
+        for j in i + 1..size {
+            if spheres[j][3] <= 0.0 {
+                continue;
+            }
+            let dx = spheres[i][0] - spheres[j][0];
+            let dy = spheres[i][1] - spheres[j][1];
+            let dz = spheres[i][2] - spheres[j][2];
+            let dr = spheres[i][3] - spheres[j][3];
+            if dr * dr > dx * dx + dy * dy + dz * dz {
+                if dr > 0.0 {
+                    spheres[j][3] = -1.0;
+                } else {
+                    spheres[i][3] = -1.0;
+                }
+            }
+        }
+    }
+    let mut visited = vec![false; (width * height * width).max(0) as usize];
+    let mut placed = 0;
+    for [center_x, center_y, center_z, radius] in spheres {
+        if radius < 0.0 {
+            continue;
+        }
+        let from_x = ((center_x - radius).floor() as i32).max(start_x);
+        let from_y = ((center_y - radius).floor() as i32).max(start_y);
+        let from_z = ((center_z - radius).floor() as i32).max(start_z);
+        let to_x = ((center_x + radius).floor() as i32).max(from_x);
+        let to_y = ((center_y + radius).floor() as i32).max(from_y);
+        let to_z = ((center_z + radius).floor() as i32).max(from_z);
+        for x in from_x..=to_x {
+            let fx = (x as f64 + 0.5 - center_x) / radius;
+            if fx * fx >= 1.0 {
+                continue;
+            }
+            for y in from_y..=to_y {
+                let fy = (y as f64 + 0.5 - center_y) / radius;
+                if fx * fx + fy * fy >= 1.0 {
+                    continue;
+                }
+                for z in from_z..=to_z {
+                    let fz = (z as f64 + 0.5 - center_z) / radius;
+                    if fx * fx + fy * fy + fz * fz >= 1.0 || region.is_outside_build_height(y) {
+                        continue;
+                    }
+                    let index = (x - start_x
+                        + (y - start_y) * width
+                        + (z - start_z) * width * height) as usize;
+                    if index >= visited.len() {
+                        visited.resize(index + 1, false);
+                    }
+                    if visited[index] {
+                        continue;
+                    }
+                    visited[index] = true;
+                    if !region.can_write(x, z) {
+                        continue;
+                    }
+                    let block = region.get_block(x, y, z);
+                    for target in &config.targets {
+                        if can_place_ore(region, random, block, (x, y, z), config, target) {
+                            region.set_block_raw(x, y, z, target.state.0);
+                            placed += 1;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    placed > 0
+}
+
+fn can_place_ore(
+    region: &FeatureRegion,
+    random: &mut dyn RandomGenerator,
+    block: u32,
+    pos: (i32, i32, i32),
+    config: &OreConfig,
+    target: &OreTarget,
+) -> bool {
+    if !target.target.test(block, random) {
+        return false;
+    }
+    let chance = config.discard_chance_on_air_exposure;
+    let skip_air_check = chance <= 0.0 || chance < 1.0 && random.next_f32() >= chance;
+    skip_air_check
+        || !Direction::ALL.iter().any(|direction| {
+            let (x, y, z) = direction.offset(pos, 1);
+            is_air(region.get_block(x, y, z))
+        })
+}
+
+/// Ore blocks scattered around a position, like vanilla's `ScatteredOreFeature`
+fn place_scattered_ore(
+    region: &mut FeatureRegion,
+    random: &mut dyn RandomGenerator,
+    (x, y, z): (i32, i32, i32),
+    config: &OreConfig,
+) -> bool {
+    let count = random.next_i32_range((config.size + 1) as u32);
+    for i in 0..count {
+        let spread = i.min(7) as f32;
+        let mut offset = || ((random.next_f32() - random.next_f32()) * spread + 0.5).floor() as i32;
+        let pos = (x + offset(), y + offset(), z + offset());
+        let block = region.get_block(pos.0, pos.1, pos.2);
+        for target in &config.targets {
+            if can_place_ore(region, random, block, pos, config, target) {
+                region.set_block(pos.0, pos.1, pos.2, target.state.0);
+                break;
+            }
+        }
+    }
+    true
+}
+
+/// Places a block that can survive at a position, both halves of double plants
+fn place_simple_block(region: &mut FeatureRegion, block: u32, (x, y, z): (i32, i32, i32)) -> bool {
+    if !can_survive(region, block, (x, y, z)) {
+        return false;
+    }
+    let name = block_name(block);
+    if property(block, "half") == Some("lower") && !name.ends_with("_door") {
+        if !is_air(region.get_block(x, y + 1, z)) {
+            return false;
+        }
+        let waterlogged = |block: u32, y: i32| {
+            let water = Fluid::of(region.get_block(x, y, z)) == Fluid::Water;
+            with_property(block, "waterlogged", if water { "true" } else { "false" })
+        };
+        let lower = waterlogged(block, y);
+        let upper = waterlogged(with_property(block, "half", "upper"), y + 1);
+        region.set_block(x, y, z, lower);
+        region.set_block(x, y + 1, z, upper);
+    } else {
+        region.set_block(x, y, z, block);
+    }
+    true
+}
+
+/// Replaces the blocks of a vertical cylinder, like vanilla's `DiskFeature`
+fn place_disk(
+    region: &mut FeatureRegion,
+    random: &mut dyn RandomGenerator,
+    (x, y, z): (i32, i32, i32),
+    config: &DiskConfig,
+) -> bool {
+    let top = y + config.half_height;
+    let bottom = y - config.half_height - 1;
+    let radius = config.radius.sample(random);
+    let mut placed = false;
+    for column_z in z - radius..=z + radius {
+        for column_x in x - radius..=x + radius {
+            let (dx, dz) = (column_x - x, column_z - z);
+            if dx * dx + dz * dz > radius * radius {
+                continue;
+            }
+            for block_y in (bottom + 1..=top).rev() {
+                let pos = (column_x, block_y, column_z);
+                if !config.target.test(region, pos) {
+                    continue;
+                }
+                if let Some(block) = config.state_provider.get_state(region, random, pos) {
+                    region.set_block(column_x, block_y, column_z, block);
+                }
+                placed = true;
+            }
+        }
+    }
+    placed
+}
+
+/// Whether the water at a position turns into ice in a biome, like vanilla's `Biome.shouldFreeze`
+fn should_freeze(region: &FeatureRegion, biome: u16, (x, y, z): (i32, i32, i32)) -> bool {
+    let sea_level = region.world_state.settings.sea_level;
+    BIOME_CLIMATES[biome as usize].cold_enough_to_snow(x, y, z, sea_level)
+        && !region.is_outside_build_height(y)
+        && region.get_block(x, y, z) == FEATURE_BLOCKS.water
+}
+
+/// Pool of fluid surrounded by a barrier, like vanilla's `LakeFeature`
+fn place_lake(
+    region: &mut FeatureRegion,
+    random: &mut dyn RandomGenerator,
+    (x, y, z): (i32, i32, i32),
+    fluid: &BlockStateProvider,
+    barrier: &BlockStateProvider,
+) -> bool {
+    let blocks = &*FEATURE_BLOCKS;
+    if y <= region.min_y + 4 {
+        return false;
+    }
+    let origin = (x, y - 4, z);
+    let at = |dx: i32, dy: i32, dz: i32| (origin.0 + dx, origin.1 + dy, origin.2 + dz);
+    let mut shape = [false; 2048];
+    let blobs = random.next_i32_range(4) + 4;
+    for _ in 0..blobs {
+        let size_x = random.next_f64() * 6.0 + 3.0;
+        let size_y = random.next_f64() * 4.0 + 2.0;
+        let size_z = random.next_f64() * 6.0 + 3.0;
+        let center_x = random.next_f64() * (16.0 - size_x - 2.0) + 1.0 + size_x / 2.0;
+        let center_y = random.next_f64() * (8.0 - size_y - 4.0) + 2.0 + size_y / 2.0;
+        let center_z = random.next_f64() * (16.0 - size_z - 2.0) + 1.0 + size_z / 2.0;
+        for dx in 1..15 {
+            for dz in 1..15 {
+                for dy in 1..7 {
+                    let fx = (dx as f64 - center_x) / (size_x / 2.0);
+                    let fy = (dy as f64 - center_y) / (size_y / 2.0);
+                    let fz = (dz as f64 - center_z) / (size_z / 2.0);
+                    if fx * fx + fy * fy + fz * fz < 1.0 {
+                        shape[((dx * 16 + dz) * 8 + dy) as usize] = true;
+                    }
+                }
+            }
+        }
+    }
+    let inside = |dx: i32, dz: i32, dy: i32| shape[((dx * 16 + dz) * 8 + dy) as usize];
+    let is_edge = |dx: i32, dz: i32, dy: i32| {
+        !inside(dx, dz, dy)
+            && (dx < 15 && inside(dx + 1, dz, dy)
+                || dx > 0 && inside(dx - 1, dz, dy)
+                || dz < 15 && inside(dx, dz + 1, dy)
+                || dz > 0 && inside(dx, dz - 1, dy)
+                || dy < 7 && inside(dx, dz, dy + 1)
+                || dy > 0 && inside(dx, dz, dy - 1))
+    };
+    let fluid = fluid.get_state(random, origin);
+    for dx in 0..16 {
+        for dz in 0..16 {
+            for dy in 0..8 {
+                if !is_edge(dx, dz, dy) {
+                    continue;
+                }
+                let (x, y, z) = at(dx, dy, dz);
+                let block = region.get_block(x, y, z);
+                if dy >= 4 && Fluid::of(block) != Fluid::Empty {
+                    return false;
+                }
+                if dy < 4 && !is_solid(block) && block != fluid {
+                    return false;
+                }
+            }
+        }
+    }
+    for dx in 0..16 {
+        for dz in 0..16 {
+            for dy in 0..8 {
+                let (x, y, z) = at(dx, dy, dz);
+                if inside(dx, dz, dy)
+                    && !blocks
+                        .features_cannot_replace
+                        .contains(region.get_block(x, y, z))
+                {
+                    region.set_block(x, y, z, if dy >= 4 { blocks.air } else { fluid });
+                }
+            }
+        }
+    }
+    let barrier = barrier.get_state(random, origin);
+    if !is_air(barrier) {
+        for dx in 0..16 {
+            for dz in 0..16 {
+                for dy in 0..8 {
+                    if !is_edge(dx, dz, dy) || dy >= 4 && random.next_i32_range(2) == 0 {
+                        continue;
+                    }
+                    let (x, y, z) = at(dx, dy, dz);
+                    let block = region.get_block(x, y, z);
+                    if is_solid(block) && !blocks.lava_pool_stone_cannot_replace.contains(block) {
+                        region.set_block(x, y, z, barrier);
+                    }
+                }
+            }
+        }
+    }
+    if Fluid::of(fluid).is_water() {
+        for dx in 0..16 {
+            for dz in 0..16 {
+                let (x, y, z) = at(dx, 4, dz);
+                if should_freeze(region, region.get_biome(x, y, z), (x, y, z))
+                    && !blocks
+                        .features_cannot_replace
+                        .contains(region.get_block(x, y, z))
+                {
+                    region.set_block(x, y, z, blocks.ice);
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Fluid source in a wall with a given number of solid and open sides, like vanilla's
+/// `SpringFeature`
+fn place_spring(
+    region: &mut FeatureRegion,
+    (x, y, z): (i32, i32, i32),
+    config: &SpringConfig,
+) -> bool {
+    let valid = |block: u32| config.valid_blocks.contains(block);
+    if !valid(region.get_block(x, y + 1, z)) {
+        return false;
+    }
+    if config.requires_block_below && !valid(region.get_block(x, y - 1, z)) {
+        return false;
+    }
+    let block = region.get_block(x, y, z);
+    if !is_air(block) && !valid(block) {
+        return false;
+    }
+    let sides = [
+        (x - 1, y, z),
+        (x + 1, y, z),
+        (x, y, z - 1),
+        (x, y, z + 1),
+        (x, y - 1, z),
+    ]
+    .map(|(x, y, z)| region.get_block(x, y, z));
+    let rocks = sides.iter().filter(|side| valid(**side)).count() as i32;
+    let holes = sides.iter().filter(|side| is_air(**side)).count() as i32;
+    if rocks == config.rock_count && holes == config.hole_count {
+        region.set_block(x, y, z, config.state.0);
+        return true;
+    }
+    false
+}
+
+/// Column of block layers that stops at the first position not allowed, like vanilla's
+/// `BlockColumnFeature`
+fn place_block_column(
+    region: &mut FeatureRegion,
+    random: &mut dyn RandomGenerator,
+    pos: (i32, i32, i32),
+    config: &BlockColumnConfig,
+) -> bool {
+    let mut heights = config
+        .layers
+        .iter()
+        .map(|layer| layer.height.sample(random))
+        .collect::<Vec<_>>();
+    let total = heights.iter().sum::<i32>();
+    if total == 0 {
+        return false;
+    }
+    let mut next = config.direction.offset(pos, 1);
+    for placed in 0..total {
+        if !config.allowed_placement.test(region, next) {
+            // Shortens the layers from the tip or from the base until the column fits
+            let mut left = total - placed;
+            let layers: Box<dyn Iterator<Item = &mut i32>> = if config.prioritize_tip {
+                Box::new(heights.iter_mut())
+            } else {
+                Box::new(heights.iter_mut().rev())
+            };
+            for height in layers {
+                if left <= 0 {
+                    break;
+                }
+                let cut = (*height).min(left);
+                left -= cut;
+                *height -= cut;
+            }
+            break;
+        }
+        next = config.direction.offset(next, 1);
+    }
+    let mut pos = pos;
+    for (layer, height) in config.layers.iter().zip(heights) {
+        for _ in 0..height {
+            let block = layer.provider.get_state(random, pos);
+            region.set_block(pos.0, pos.1, pos.2, block);
+            pos = config.direction.offset(pos, 1);
+        }
+    }
+    true
+}
+
+/// Freezes water and covers the top blocks of a chunk with snow where it is cold enough, like
+/// vanilla's `SnowAndFreezeFeature`
+fn freeze_top_layer(region: &mut FeatureRegion, (x, _, z): (i32, i32, i32)) -> bool {
+    let blocks = &*FEATURE_BLOCKS;
+    let snow = *BLOCKSTATE_MAPPINGS.get("snow[layers=1]").unwrap();
+    let sea_level = region.world_state.settings.sea_level;
+    for dx in 0..16 {
+        for dz in 0..16 {
+            let (x, z) = (x + dx, z + dz);
+            let y = region.get_height(WorldHeightmap::MotionBlocking, x, z);
+            let biome = region.get_biome(x, y, z);
+            if should_freeze(region, biome, (x, y - 1, z)) {
+                region.set_block(x, y - 1, z, blocks.ice);
+            }
+            let block = region.get_block(x, y, z);
+            if !BIOME_CLIMATES[biome as usize].cold_enough_to_snow(x, y, z, sea_level)
+                || region.is_outside_build_height(y)
+                || !(is_air(block) || block_name(block) == "snow")
+                || !can_survive(region, snow, (x, y, z))
+            {
+                continue;
+            }
+            region.set_block(x, y, z, snow);
+            let below = region.get_block(x, y - 1, z);
+            if property(below, "snowy").is_some() {
+                region.set_block(x, y - 1, z, with_property(below, "snowy", "true"));
+            }
+        }
+    }
+    true
+}
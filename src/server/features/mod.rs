@@ -0,0 +1,549 @@
+pub mod block_predicate;
+pub mod end;
+pub mod feature;
+pub mod placement;
+pub mod region;
+pub mod state_provider;
+pub mod tree;
+
+use super::biome_source::BiomeSource;
+use super::chunk_system::{
+    block_state_property, split_block_state, BIOMES, BLOCKSTATES, BLOCKSTATE_MAPPINGS,
+};
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use super::randomness::WorldgenRandom;
+use super::structures::{place_structures, STRUCTURE_STEPS};
+use super::tags::BlockSet;
+use super::util::get_dir_files;
+use super::world_state::WorldState;
+use crate::{RandomGenerator, JAR_RESOURCES_DIR};
+use ahash::{AHashMap, AHashSet};
+use feature::ConfiguredFeature;
+use once_cell::sync::Lazy;
+use placement::PlacementModifier;
+use region::FeatureRegion;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::sync::Mutex;
+
+/// Feature types found in the data pack that are not placed, reported once after loading
+static UNSUPPORTED_FEATURES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Every configured feature of the data pack, keyed by its namespaced id
+static CONFIGURED_FEATURES: Lazy<AHashMap<String, ConfiguredFeature>> =
+    Lazy::new(|| read_registry("configured_feature"));
+
+/// Every placed feature of the data pack, keyed by its namespaced id
+static PLACED_FEATURES: Lazy<AHashMap<String, PlacedFeature>> =
+    Lazy::new(|| read_registry("placed_feature"));
+
+/// Placed features of every biome for each decoration step, indexed by biome id
+static BIOME_FEATURES: Lazy<Vec<Vec<Vec<String>>>> = Lazy::new(|| {
+    BIOMES
+        .iter()
+        .map(|name| {
+            #[derive(Deserialize)]
+            struct BiomeFeatures {
+                #[serde(default)]
+                features: Vec<Vec<String>>,
+            }
+            std::fs::read_to_string(format!("{JAR_RESOURCES_DIR}/worldgen/biome/{name}.json"))
+                .ok()
+                .and_then(|data| serde_json::from_str::<BiomeFeatures>(&data).ok())
+                .map(|biome| biome.features)
+                .unwrap_or_default()
+        })
+        .collect()
+});
+
+/// Placed features of every biome regardless of their step, checked by the biome filter
+static BIOME_FEATURE_SETS: Lazy<Vec<AHashSet<String>>> = Lazy::new(|| {
+    BIOME_FEATURES
+        .iter()
+        .map(|steps| steps.iter().flatten().cloned().collect())
+        .collect()
+});
+
+/// Blocks and block tags features check against
+pub struct FeatureBlocks {
+    pub air: u32,
+    pub water: u32,
+    pub lava: u32,
+    pub ice: u32,
+    pub dirt: BlockSet,
+    pub logs: BlockSet,
+    pub leaves: BlockSet,
+    pub sand: BlockSet,
+    pub terracotta: BlockSet,
+    pub nylium: BlockSet,
+    pub small_flowers: BlockSet,
+    pub saplings: BlockSet,
+    pub mushroom_grow_block: BlockSet,
+    pub replaceable: BlockSet,
+    pub replaceable_by_trees: BlockSet,
+    pub features_cannot_replace: BlockSet,
+    pub lava_pool_stone_cannot_replace: BlockSet,
+    pub snow_layer_can_survive_on: BlockSet,
+    pub snow_layer_cannot_survive_on: BlockSet,
+}
+
+pub static FEATURE_BLOCKS: Lazy<FeatureBlocks> = Lazy::new(|| {
+    let block = |state: &str| *BLOCKSTATE_MAPPINGS.get(state).unwrap();
+    let tag = |tag: &str| BlockSet::from_ids(&[format!("#minecraft:{tag}")]);
+    FeatureBlocks {
+        air: block("air"),
+        water: block("water[level=0]"),
+        lava: block("lava[level=0]"),
+        ice: block("ice"),
+        dirt: tag("dirt"),
+        logs: tag("logs"),
+        leaves: tag("leaves"),
+        sand: tag("sand"),
+        terracotta: tag("terracotta"),
+        nylium: tag("nylium"),
+        small_flowers: tag("small_flowers"),
+        saplings: tag("saplings"),
+        mushroom_grow_block: tag("mushroom_grow_block"),
+        replaceable: tag("replaceable"),
+        replaceable_by_trees: tag("replaceable_by_trees"),
+        features_cannot_replace: tag("features_cannot_replace"),
+        lava_pool_stone_cannot_replace: tag("lava_pool_stone_cannot_replace"),
+        snow_layer_can_survive_on: tag("snow_layer_can_survive_on"),
+        snow_layer_cannot_survive_on: tag("snow_layer_cannot_survive_on"),
+    }
+});
+
+/// Name of a block state without namespace and properties
+pub fn block_name(block: u32) -> &'static str {
+    split_block_state(&BLOCKSTATES[block as usize]).0
+}
+
+/// Block state with a property changed, the state itself when the block has no such property
+pub fn with_property(block: u32, key: &str, value: &str) -> u32 {
+    let (name, properties) = split_block_state(&BLOCKSTATES[block as usize]);
+    if block_state_property(properties, key).is_none() {
+        return block;
+    }
+    let properties = properties
+        .split(',')
+        .map(|property| match property.split_once('=') {
+            Some((k, _)) if k == key => format!("{key}={value}"),
+            _ => property.to_owned(),
+        })
+        .collect::<Vec<_>>();
+    BLOCKSTATE_MAPPINGS
+        .get(format!("{name}[{}]", properties.join(",")).as_str())
+        .copied()
+        .unwrap_or(block)
+}
+
+/// Value of a property of a block state
+pub fn property(block: u32, key: &str) -> Option<&'static str> {
+    block_state_property(split_block_state(&BLOCKSTATES[block as usize]).1, key)
+}
+
+/// Fluid held by a block state
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fluid {
+    Empty,
+    Water,
+    FlowingWater,
+    Lava,
+    FlowingLava,
+}
+
+impl Fluid {
+    pub fn of(block: u32) -> Self {
+        let (name, properties) = split_block_state(&BLOCKSTATES[block as usize]);
+        let level = block_state_property(properties, "level");
+        match name {
+            "water" if level == Some("0") => Fluid::Water,
+            "water" => Fluid::FlowingWater,
+            "lava" if level == Some("0") => Fluid::Lava,
+            "lava" => Fluid::FlowingLava,
+            "bubble_column" | "kelp" | "kelp_plant" | "seagrass" | "tall_seagrass" => Fluid::Water,
+            _ if block_state_property(properties, "waterlogged") == Some("true") => Fluid::Water,
+            _ => Fluid::Empty,
+        }
+    }
+
+    pub fn is_water(self) -> bool {
+        matches!(self, Fluid::Water | Fluid::FlowingWater)
+    }
+
+    pub fn is_lava(self) -> bool {
+        matches!(self, Fluid::Lava | Fluid::FlowingLava)
+    }
+}
+
+/// Horizontal and vertical directions in vanilla's order
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Down,
+    Up,
+    North,
+    South,
+    West,
+    East,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::Down,
+        Direction::Up,
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ];
+    /// Horizontal directions in the order of vanilla's `Direction.Plane.HORIZONTAL`
+    pub const HORIZONTAL: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    pub fn step(self) -> (i32, i32, i32) {
+        match self {
+            Direction::Down => (0, -1, 0),
+            Direction::Up => (0, 1, 0),
+            Direction::North => (0, 0, -1),
+            Direction::South => (0, 0, 1),
+            Direction::West => (-1, 0, 0),
+            Direction::East => (1, 0, 0),
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Down => Direction::Up,
+            Direction::Up => Direction::Down,
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+            Direction::East => Direction::West,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Down => "down",
+            Direction::Up => "up",
+            Direction::North => "north",
+            Direction::South => "south",
+            Direction::West => "west",
+            Direction::East => "east",
+        }
+    }
+
+    pub fn offset(self, (x, y, z): (i32, i32, i32), distance: i32) -> (i32, i32, i32) {
+        let (dx, dy, dz) = self.step();
+        (x + dx * distance, y + dy * distance, z + dz * distance)
+    }
+
+    /// Random horizontal direction like vanilla's `Direction.Plane.HORIZONTAL.getRandomDirection`
+    pub fn random_horizontal(random: &mut dyn RandomGenerator) -> Self {
+        Self::HORIZONTAL[random.next_i32_range(4) as usize]
+    }
+}
+
+/// Reads every entry of a worldgen registry folder, entries that fail to parse are skipped.
+/// Entries in sub folders like the template pools get the folders in their id
+pub fn read_registry<T: for<'de> Deserialize<'de>>(registry: &str) -> AHashMap<String, T> {
+    let mut entries = AHashMap::new();
+    let Ok(dir) = std::fs::read_dir(format!("{JAR_RESOURCES_DIR}/worldgen/{registry}")) else {
+        return entries;
+    };
+    let mut files = Vec::new();
+    if get_dir_files(dir, &mut files, "").is_err() {
+        return entries;
+    }
+    for (name, mut file) in files {
+        let mut data = String::new();
+        let parsed = file
+            .read_to_string(&mut data)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::from_str::<T>(&data).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(parsed) => {
+                entries.insert(format!("minecraft:{name}"), parsed);
+            }
+            Err(e) => LOGGER.get().unwrap().println_as(
+                &format!("Invalid {registry} minecraft:{name}: {e}"),
+                LogDomain::Server,
+                LogLevel::Warn,
+            ),
+        }
+    }
+    let unsupported = std::mem::take(&mut *UNSUPPORTED_FEATURES.lock().unwrap());
+    if !unsupported.is_empty() {
+        LOGGER.get().unwrap().println_as(
+            &format!(
+                "Features of these types are not placed: {}",
+                unsupported.into_iter().collect::<Vec<_>>().join(", ")
+            ),
+            LogDomain::Server,
+            LogLevel::Warn,
+        );
+    }
+    entries
+}
+
+/// Placed feature given by its id or written inline
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum PlacedFeatureRef {
+    Id(String),
+    Inline(Box<PlacedFeature>),
+}
+
+impl PlacedFeatureRef {
+    pub fn get(&self) -> Option<&PlacedFeature> {
+        match self {
+            PlacedFeatureRef::Id(id) => PLACED_FEATURES.get(id),
+            PlacedFeatureRef::Inline(feature) => Some(feature),
+        }
+    }
+
+    /// Places the feature without a biome check, returns whether anything was placed
+    pub fn place(
+        &self,
+        region: &mut FeatureRegion,
+        random: &mut dyn RandomGenerator,
+        pos: (i32, i32, i32),
+    ) -> bool {
+        self.get()
+            .is_some_and(|feature| feature.place(region, random, pos, None))
+    }
+}
+
+/// Configured feature given by its id or written inline
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ConfiguredFeatureRef {
+    Id(String),
+    Inline(Box<ConfiguredFeature>),
+}
+
+impl ConfiguredFeatureRef {
+    pub fn get(&self) -> Option<&ConfiguredFeature> {
+        match self {
+            ConfiguredFeatureRef::Id(id) => CONFIGURED_FEATURES.get(id),
+            ConfiguredFeatureRef::Inline(feature) => Some(feature),
+        }
+    }
+}
+
+/// Feature with the placement modifiers choosing where it is placed, read from
+/// `worldgen/placed_feature`
+#[derive(Deserialize)]
+pub struct PlacedFeature {
+    feature: ConfiguredFeatureRef,
+    #[serde(default)]
+    placement: Vec<PlacementModifier>,
+}
+
+impl PlacedFeature {
+    /// Runs the placement modifiers from a position and places the feature at every resulting
+    /// position. `top` is the id of the feature placed by the chunk decoration, which the biome
+    /// filter looks for in the biome at a position
+    pub fn place(
+        &self,
+        region: &mut FeatureRegion,
+        random: &mut dyn RandomGenerator,
+        pos: (i32, i32, i32),
+        top: Option<&str>,
+    ) -> bool {
+        let Some(feature) = self.feature.get() else {
+            return false;
+        };
+        let mut placed = false;
+        self.place_from(region, random, pos, top, feature, 0, &mut placed);
+        placed
+    }
+
+    /// Modifiers are applied depth first, every position is fully placed before the next one
+    /// like the streams of vanilla
+    #[allow(clippy::too_many_arguments)]
+    fn place_from(
+        &self,
+        region: &mut FeatureRegion,
+        random: &mut dyn RandomGenerator,
+        pos: (i32, i32, i32),
+        top: Option<&str>,
+        feature: &ConfiguredFeature,
+        modifier: usize,
+        placed: &mut bool,
+    ) {
+        let Some(placement) = self.placement.get(modifier) else {
+            if feature.place(region, random, pos) {
+                *placed = true;
+            }
+            return;
+        };
+        for pos in placement.positions(region, random, pos, top) {
+            self.place_from(region, random, pos, top, feature, modifier + 1, placed);
+        }
+    }
+}
+
+/// Whether the biome at a position lists a placed feature
+pub fn biome_has_feature(biome: u16, feature: &str) -> bool {
+    BIOME_FEATURE_SETS
+        .get(biome as usize)
+        .is_some_and(|features| features.contains(feature))
+}
+
+/// Order features of every decoration step are placed in, shared by every biome of a dimension
+#[derive(Debug, Default)]
+pub struct FeatureSteps {
+    steps: Vec<Vec<String>>,
+    /// Index of every feature in the sorted list of its step
+    indices: Vec<AHashMap<String, usize>>,
+}
+
+impl FeatureSteps {
+    /// Whether no feature is placed in the dimension
+    pub fn is_empty(&self) -> bool {
+        self.steps.iter().all(|step| step.is_empty())
+    }
+
+    /// Sorts the features of the biomes a biome source places like vanilla's `FeatureSorter`.
+    /// Every biome lists its features in order and the sorted order keeps all of those orders,
+    /// features that are not ordered relative to each other keep the order they were first seen
+    pub fn new(biome_source: Option<&BiomeSource>) -> Self {
+        let Some(biome_source) = biome_source else {
+            return Self::default();
+        };
+        // Features are numbered in the order they are first seen and sorted by step first
+        let mut ids: AHashMap<&str, usize> = AHashMap::new();
+        let mut edges: BTreeMap<(usize, usize), BTreeSet<(usize, usize)>> = BTreeMap::new();
+        let mut names: AHashMap<(usize, usize), &str> = AHashMap::new();
+        for biome in biome_source.possible_biomes() {
+            let Some(steps) = BIOME_FEATURES.get(*biome as usize) else {
+                continue;
+            };
+            let mut features = Vec::new();
+            for (step, step_features) in steps.iter().enumerate() {
+                for feature in step_features {
+                    let next_index = ids.len();
+                    let index = *ids.entry(feature.as_str()).or_insert(next_index);
+                    names.insert((step, index), feature.as_str());
+                    features.push((step, index));
+                }
+            }
+            for (i, feature) in features.iter().enumerate() {
+                let following = edges.entry(*feature).or_default();
+                if let Some(next) = features.get(i + 1) {
+                    following.insert(*next);
+                }
+            }
+        }
+        let mut visited = AHashSet::new();
+        let mut in_progress = AHashSet::new();
+        let mut sorted = Vec::new();
+        for feature in edges.keys() {
+            if !Self::visit(
+                *feature,
+                &edges,
+                &mut visited,
+                &mut in_progress,
+                &mut sorted,
+            ) {
+                LOGGER.get().unwrap().println_as(
+                    "Feature order cycle found, features are placed in an arbitrary order",
+                    LogDomain::Server,
+                    LogLevel::Warn,
+                );
+                break;
+            }
+        }
+        sorted.reverse();
+        let step_count = BIOME_FEATURES.iter().map(Vec::len).max().unwrap_or(0);
+        let mut feature_steps = FeatureSteps {
+            steps: vec![Vec::new(); step_count],
+            indices: vec![AHashMap::new(); step_count],
+        };
+        for feature in sorted {
+            let (step, _) = feature;
+            let name = names[&feature].to_owned();
+            feature_steps.indices[step].insert(name.clone(), feature_steps.steps[step].len());
+            feature_steps.steps[step].push(name);
+        }
+        feature_steps
+    }
+
+    /// Depth first visit of the feature graph, returns `false` on cycles
+    fn visit(
+        feature: (usize, usize),
+        edges: &BTreeMap<(usize, usize), BTreeSet<(usize, usize)>>,
+        visited: &mut AHashSet<(usize, usize)>,
+        in_progress: &mut AHashSet<(usize, usize)>,
+        sorted: &mut Vec<(usize, usize)>,
+    ) -> bool {
+        if visited.contains(&feature) {
+            return true;
+        }
+        if !in_progress.insert(feature) {
+            return false;
+        }
+        for next in edges.get(&feature).into_iter().flatten() {
+            if !Self::visit(*next, edges, visited, in_progress, sorted) {
+                return false;
+            }
+        }
+        in_progress.remove(&feature);
+        visited.insert(feature);
+        sorted.push(feature);
+        true
+    }
+}
+
+/// Places the features of the biomes around a chunk, like vanilla's `applyBiomeDecoration`. Each
+/// feature is seeded from the world seed, the chunk position and its index in its step
+pub fn decorate_chunk(world_state: &WorldState, region: &mut FeatureRegion) {
+    let feature_steps = &world_state.features;
+    let min_x = region.center_x * 16;
+    let min_z = region.center_z * 16;
+    let origin = (min_x, region.min_y, min_z);
+    let possible_biomes = world_state
+        .biome_source
+        .as_ref()
+        .map(|source| source.possible_biomes())
+        .unwrap_or_default();
+    let biomes = region
+        .biomes()
+        .into_iter()
+        .filter(|biome| possible_biomes.contains(biome))
+        .collect::<Vec<_>>();
+    let starts = world_state.structure_starts(region.center_x, region.center_z, region.dimension);
+    let mut random = WorldgenRandom::new(0);
+    let decoration_seed = random.set_decoration_seed(world_state.seed, min_x, min_z);
+    // Structures of a step are placed before its features
+    for step in 0..feature_steps.steps.len().max(STRUCTURE_STEPS) {
+        place_structures(region, &starts, &mut random, decoration_seed, step);
+        let Some(step_features) = feature_steps.steps.get(step) else {
+            continue;
+        };
+        let mut indices = biomes
+            .iter()
+            .filter_map(|biome| BIOME_FEATURES.get(*biome as usize)?.get(step))
+            .flatten()
+            .filter_map(|feature| feature_steps.indices[step].get(feature).copied())
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+        for index in indices {
+            let id = &step_features[index];
+            let Some(feature) = PLACED_FEATURES.get(id) else {
+                continue;
+            };
+            random.set_feature_seed(decoration_seed, index, step);
+            feature.place(region, &mut random, origin, Some(id));
+        }
+    }
+    end::place_end_podium(region);
+}
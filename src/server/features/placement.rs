@@ -0,0 +1,326 @@
+use super::super::biome_source::BIOME_INFO_NOISE;
+use super::super::value_providers::{HeightProvider, IntProvider};
+use super::block_predicate::{is_air, BlockPredicate};
+use super::region::{FeatureRegion, WorldHeightmap};
+use super::{biome_has_feature, block_name, Direction};
+use crate::RandomGenerator;
+use serde::Deserialize;
+
+fn default_min_inclusive() -> i32 {
+    i32::MIN
+}
+
+fn default_max_inclusive() -> i32 {
+    i32::MAX
+}
+
+fn default_true() -> BlockPredicate {
+    BlockPredicate::True
+}
+
+/// Turns the position a placed feature starts from into the positions its feature is placed at
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum PlacementModifier {
+    #[serde(rename = "minecraft:count")]
+    Count { count: IntProvider },
+    #[serde(rename = "minecraft:rarity_filter")]
+    RarityFilter { chance: i32 },
+    /// Random position in the chunk
+    #[serde(rename = "minecraft:in_square")]
+    InSquare,
+    #[serde(rename = "minecraft:heightmap")]
+    Heightmap { heightmap: WorldHeightmap },
+    #[serde(rename = "minecraft:height_range")]
+    HeightRange { height: HeightProvider },
+    /// Keeps positions in biomes that list the placed feature
+    #[serde(rename = "minecraft:biome")]
+    Biome,
+    #[serde(rename = "minecraft:surface_relative_threshold_filter")]
+    SurfaceRelativeThresholdFilter {
+        heightmap: WorldHeightmap,
+        #[serde(default = "default_min_inclusive")]
+        min_inclusive: i32,
+        #[serde(default = "default_max_inclusive")]
+        max_inclusive: i32,
+    },
+    #[serde(rename = "minecraft:surface_water_depth_filter")]
+    SurfaceWaterDepthFilter { max_water_depth: i32 },
+    #[serde(rename = "minecraft:random_offset")]
+    RandomOffset {
+        xz_spread: IntProvider,
+        y_spread: IntProvider,
+    },
+    /// Positions on every floor of a column, counted from the top
+    #[serde(rename = "minecraft:count_on_every_layer")]
+    CountOnEveryLayer { count: IntProvider },
+    #[serde(rename = "minecraft:noise_based_count")]
+    NoiseBasedCount {
+        noise_to_count_ratio: i32,
+        noise_factor: f64,
+        #[serde(default)]
+        noise_offset: f64,
+    },
+    #[serde(rename = "minecraft:noise_threshold_count")]
+    NoiseThresholdCount {
+        noise_level: f64,
+        below_noise: i32,
+        above_noise: i32,
+    },
+    #[serde(rename = "minecraft:environment_scan")]
+    EnvironmentScan {
+        direction_of_search: Direction,
+        target_condition: BlockPredicate,
+        #[serde(default = "default_true")]
+        allowed_search_condition: BlockPredicate,
+        max_steps: i32,
+    },
+    #[serde(rename = "minecraft:block_predicate_filter")]
+    BlockPredicateFilter { predicate: BlockPredicate },
+    /// Fixed positions, kept when they are in the decorated chunk
+    #[serde(rename = "minecraft:fixed_placement")]
+    FixedPlacement { positions: Vec<[i32; 3]> },
+    /// Every position carved in the chunk
+    #[serde(rename = "minecraft:carving_mask")]
+    CarvingMask,
+    /// Modifiers not supported leave the position unchanged
+    #[serde(other)]
+    Unsupported,
+}
+
+impl PlacementModifier {
+    pub fn positions(
+        &self,
+        region: &FeatureRegion,
+        random: &mut dyn RandomGenerator,
+        pos: (i32, i32, i32),
+        top: Option<&str>,
+    ) -> Vec<(i32, i32, i32)> {
+        let (x, y, z) = pos;
+        let keep = |condition: bool| if condition { vec![pos] } else { Vec::new() };
+        match self {
+            PlacementModifier::Count { count } => vec![pos; count.sample(random).max(0) as usize],
+            PlacementModifier::RarityFilter { chance } => {
+                keep(random.next_f32() < 1.0 / *chance as f32)
+            }
+            PlacementModifier::InSquare => {
+                let x = random.next_i32_range(16) + x;
+                let z = random.next_i32_range(16) + z;
+                vec![(x, y, z)]
+            }
+            PlacementModifier::Heightmap { heightmap } => {
+                let height = region.get_height(*heightmap, x, z);
+                if height > region.min_y {
+                    vec![(x, height, z)]
+                } else {
+                    Vec::new()
+                }
+            }
+            PlacementModifier::HeightRange { height } => {
+                let settings = &region.world_state.settings.noise;
+                let min_y = region.min_y.max(settings.min_y);
+                let depth = (region.max_y - region.min_y).min(settings.height);
+                vec![(x, height.sample(random, min_y, depth), z)]
+            }
+            PlacementModifier::Biome => keep(
+                top.is_none_or(|feature| biome_has_feature(region.get_biome(x, y, z), feature)),
+            ),
+            PlacementModifier::SurfaceRelativeThresholdFilter {
+                heightmap,
+                min_inclusive,
+                max_inclusive,
+            } => {
+                let height = region.get_height(*heightmap, x, z) as i64;
+                keep(
+                    height + *min_inclusive as i64 <= y as i64
+                        && y as i64 <= height + *max_inclusive as i64,
+                )
+            }
+            PlacementModifier::SurfaceWaterDepthFilter { max_water_depth } => {
+                let floor = region.get_height(WorldHeightmap::OceanFloor, x, z);
+                let surface = region.get_height(WorldHeightmap::WorldSurface, x, z);
+                keep(surface - floor <= *max_water_depth)
+            }
+            PlacementModifier::RandomOffset {
+                xz_spread,
+                y_spread,
+            } => {
+                let x = x + xz_spread.sample(random);
+                let y = y + y_spread.sample(random);
+                let z = z + xz_spread.sample(random);
+                vec![(x, y, z)]
+            }
+            PlacementModifier::CountOnEveryLayer { count } => {
+                count_on_every_layer(region, random, pos, count)
+            }
+            PlacementModifier::NoiseBasedCount {
+                noise_to_count_ratio,
+                noise_factor,
+                noise_offset,
+            } => {
+                let noise = BIOME_INFO_NOISE.get_val(
+                    x as f64 / noise_factor,
+                    z as f64 / noise_factor,
+                    false,
+                );
+                let count = ((noise + noise_offset) * *noise_to_count_ratio as f64).ceil() as i32;
+                vec![pos; count.max(0) as usize]
+            }
+            PlacementModifier::NoiseThresholdCount {
+                noise_level,
+                below_noise,
+                above_noise,
+            } => {
+                let noise = BIOME_INFO_NOISE.get_val(x as f64 / 200.0, z as f64 / 200.0, false);
+                let count = if noise < *noise_level {
+                    *below_noise
+                } else {
+                    *above_noise
+                };
+                vec![pos; count.max(0) as usize]
+            }
+            PlacementModifier::EnvironmentScan {
+                direction_of_search,
+                target_condition,
+                allowed_search_condition,
+                max_steps,
+            } => {
+                let mut pos = pos;
+                if !allowed_search_condition.test(region, pos) {
+                    return Vec::new();
+                }
+                for _ in 0..*max_steps {
+                    if target_condition.test(region, pos) {
+                        return vec![pos];
+                    }
+                    pos = direction_of_search.offset(pos, 1);
+                    if region.is_outside_build_height(pos.1) {
+                        return Vec::new();
+                    }
+                    if !allowed_search_condition.test(region, pos) {
+                        break;
+                    }
+                }
+                if target_condition.test(region, pos) {
+                    vec![pos]
+                } else {
+                    Vec::new()
+                }
+            }
+            PlacementModifier::BlockPredicateFilter { predicate } => {
+                keep(predicate.test(region, pos))
+            }
+            PlacementModifier::FixedPlacement { positions } => positions
+                .iter()
+                .filter(|[fixed_x, _, fixed_z]| fixed_x >> 4 == x >> 4 && fixed_z >> 4 == z >> 4)
+                .map(|[x, y, z]| (*x, *y, *z))
+                .collect(),
+            PlacementModifier::CarvingMask => region
+                .carving_mask()
+                .map(|mask| {
+                    mask.positions()
+                        .map(|(mask_x, mask_y, mask_z)| {
+                            ((x & !15) + mask_x as i32, mask_y, (z & !15) + mask_z as i32)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            PlacementModifier::Unsupported => vec![pos],
+        }
+    }
+}
+
+/// Like vanilla the count is sampled again for every position of a layer
+fn count_on_every_layer(
+    region: &FeatureRegion,
+    random: &mut dyn RandomGenerator,
+    (x, _, z): (i32, i32, i32),
+    count: &IntProvider,
+) -> Vec<(i32, i32, i32)> {
+    let is_empty = |block: u32| is_air(block) || matches!(block_name(block), "water" | "lava");
+    let mut positions = Vec::new();
+    let mut layer = 0;
+    loop {
+        let mut found = false;
+        let mut i = 0;
+        while i < count.sample(random) {
+            let column_x = random.next_i32_range(16) + x;
+            let column_z = random.next_i32_range(16) + z;
+            let top = region.get_height(WorldHeightmap::MotionBlocking, column_x, column_z);
+            let mut above = region.get_block(column_x, top, column_z);
+            let mut floors = 0;
+            for y in (region.min_y + 1..=top).rev() {
+                let block = region.get_block(column_x, y - 1, column_z);
+                if !is_empty(block) && is_empty(above) && block_name(block) != "bedrock" {
+                    if floors == layer {
+                        positions.push((column_x, y, column_z));
+                        found = true;
+                        break;
+                    }
+                    floors += 1;
+                }
+                above = block;
+            }
+            i += 1;
+        }
+        if !found {
+            return positions;
+        }
+        layer += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::randomness::Xoroshiro;
+
+    #[test]
+    fn placement_modifiers_are_read() {
+        let modifiers: Vec<PlacementModifier> = serde_json::from_str(
+            r#"[
+                { "type": "minecraft:count", "count": 90 },
+                { "type": "minecraft:in_square" },
+                {
+                    "type": "minecraft:height_range",
+                    "height": {
+                        "type": "minecraft:uniform",
+                        "min_inclusive": { "above_bottom": 0 },
+                        "max_inclusive": { "absolute": 72 }
+                    }
+                },
+                { "type": "minecraft:heightmap", "heightmap": "OCEAN_FLOOR_WG" },
+                {
+                    "type": "minecraft:random_offset",
+                    "xz_spread": { "type": "minecraft:uniform", "min_inclusive": -3, "max_inclusive": 3 },
+                    "y_spread": 0
+                },
+                { "type": "minecraft:biome" },
+                { "type": "minecraft:not_a_modifier", "value": 3 }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(modifiers.len(), 7);
+        let mut random = Xoroshiro::new_from_i64(7);
+        let PlacementModifier::Count { count } = &modifiers[0] else {
+            panic!("Expected a count modifier");
+        };
+        assert_eq!(count.sample(random.as_mut()), 90);
+        assert!(matches!(modifiers[1], PlacementModifier::InSquare));
+        assert!(matches!(
+            modifiers[3],
+            PlacementModifier::Heightmap {
+                heightmap: WorldHeightmap::OceanFloorWg
+            }
+        ));
+        let PlacementModifier::RandomOffset { xz_spread, .. } = &modifiers[4] else {
+            panic!("Expected a random offset modifier");
+        };
+        for _ in 0..100 {
+            assert!((-3..=3).contains(&xz_spread.sample(random.as_mut())));
+        }
+        assert!(matches!(modifiers[5], PlacementModifier::Biome));
+        // Modifiers added by later versions are skipped rather than failing the feature
+        assert!(matches!(modifiers[6], PlacementModifier::Unsupported));
+    }
+}
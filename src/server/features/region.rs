@@ -0,0 +1,330 @@
+use super::super::biome_source::zoomed_biome;
+use super::super::carvers::CarvingMask;
+use super::super::chunk_system::{Chunk, ChunkBlockStates, BLOCKSTATE_MAPPINGS};
+use super::super::heightmap::{self, HeightmapType, HEIGHTMAP_BLOCKS};
+use super::super::world_state::WorldState;
+use serde::Deserialize;
+
+/// Chunk of the region decorated around a chunk, only chunks that were not sent yet can be
+/// written to
+pub enum RegionChunk<'a> {
+    Writable(&'a mut Chunk),
+    ReadOnly(&'a Chunk),
+    Missing,
+}
+
+/// Heightmaps features are placed on, the worldgen heightmaps follow every block placed by a
+/// feature while the others keep the heights from before the decoration like vanilla
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum WorldHeightmap {
+    WorldSurfaceWg,
+    WorldSurface,
+    OceanFloorWg,
+    OceanFloor,
+    MotionBlocking,
+    MotionBlockingNoLeaves,
+}
+
+type Heights = [[i32; 16]; 16];
+
+struct LoadedChunk {
+    blocks: Vec<Vec<u32>>,
+    modified: Vec<bool>,
+    writable: bool,
+    /// Heights above the highest matching block for every `HeightmapType`, in world height
+    heights: [Heights; 4],
+    /// Live `WorldSurfaceWg` and `OceanFloorWg` heights
+    worldgen_heights: [Heights; 2],
+    biomes: Vec<[u16; 64]>,
+    carving_mask: Option<CarvingMask>,
+}
+
+/// Blocks of a chunk and of its 8 neighbours while the features of the chunk are placed, like
+/// vanilla's `WorldGenRegion`
+pub struct FeatureRegion<'a> {
+    pub world_state: &'a WorldState,
+    pub dimension: &'a str,
+    pub center_x: i32,
+    pub center_z: i32,
+    pub min_y: i32,
+    pub max_y: i32,
+    chunks: Vec<Option<LoadedChunk>>,
+    air: u32,
+}
+
+impl<'a> FeatureRegion<'a> {
+    /// Decodes the chunks around a center chunk, ordered by z then x with the center at index 4.
+    /// The center chunk must be writable
+    pub fn new(world_state: &'a WorldState, dimension: &'a str, chunks: &[RegionChunk]) -> Self {
+        let RegionChunk::Writable(center) = &chunks[4] else {
+            panic!("The center chunk of a feature region must be writable");
+        };
+        let min_y = center.y as i32 * 16;
+        let max_y = min_y + center.world_height();
+        let chunks = chunks
+            .iter()
+            .map(|chunk| match chunk {
+                RegionChunk::Writable(chunk) => Some(LoadedChunk::new(chunk, true)),
+                RegionChunk::ReadOnly(chunk) => Some(LoadedChunk::new(chunk, false)),
+                RegionChunk::Missing => None,
+            })
+            .collect();
+        FeatureRegion {
+            world_state,
+            dimension,
+            center_x: center.x,
+            center_z: center.z,
+            min_y,
+            max_y,
+            chunks,
+            air: *BLOCKSTATE_MAPPINGS.get("air").unwrap(),
+        }
+    }
+
+    fn chunk_index(&self, x: i32, z: i32) -> Option<usize> {
+        let dx = (x >> 4) - self.center_x;
+        let dz = (z >> 4) - self.center_z;
+        if dx.abs() > 1 || dz.abs() > 1 {
+            return None;
+        }
+        Some(((dz + 1) * 3 + dx + 1) as usize)
+    }
+
+    fn chunk(&self, x: i32, z: i32) -> Option<&LoadedChunk> {
+        self.chunk_index(x, z)
+            .and_then(|index| self.chunks[index].as_ref())
+    }
+
+    pub fn is_outside_build_height(&self, y: i32) -> bool {
+        y < self.min_y || y >= self.max_y
+    }
+
+    /// Block state at a world position, air outside of the region
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> u32 {
+        if self.is_outside_build_height(y) {
+            return self.air;
+        }
+        let Some(chunk) = self.chunk(x, z) else {
+            return self.air;
+        };
+        let offset = y - self.min_y;
+        chunk.blocks[(offset >> 4) as usize]
+            [((offset & 15) as usize) << 8 | ((z & 15) as usize) << 4 | (x & 15) as usize]
+    }
+
+    /// Whether a feature may place blocks in a column, like vanilla's `ensureCanWrite`
+    pub fn can_write(&self, x: i32, z: i32) -> bool {
+        self.chunk(x, z).is_some()
+    }
+
+    /// Places a block and updates the worldgen heightmaps. Blocks placed outside of the build
+    /// height or in chunks that were already sent are dropped but still reported as placed, so
+    /// features keep drawing the same random values
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: u32) -> bool {
+        if !self.set_block_raw(x, y, z, block) {
+            return false;
+        }
+        if self.is_outside_build_height(y) {
+            return true;
+        }
+        let (min_y, max_y) = (self.min_y, self.max_y);
+        let index = self.chunk_index(x, z).unwrap();
+        let chunk = self.chunks[index].as_mut().unwrap();
+        if chunk.writable {
+            chunk.update_worldgen_heights(x & 15, y, z & 15, block, min_y, max_y);
+        }
+        true
+    }
+
+    /// Places a block without updating the heightmaps, like vanilla features writing straight
+    /// into chunk sections
+    pub fn set_block_raw(&mut self, x: i32, y: i32, z: i32, block: u32) -> bool {
+        if !self.can_write(x, z) {
+            return false;
+        }
+        if self.is_outside_build_height(y) {
+            return true;
+        }
+        let min_y = self.min_y;
+        let index = self.chunk_index(x, z).unwrap();
+        let chunk = self.chunks[index].as_mut().unwrap();
+        if chunk.writable {
+            let offset = y - min_y;
+            let section = (offset >> 4) as usize;
+            chunk.blocks[section]
+                [((offset & 15) as usize) << 8 | ((z & 15) as usize) << 4 | (x & 15) as usize] =
+                block;
+            chunk.modified[section] = true;
+        }
+        true
+    }
+
+    /// Height above the highest block matching a heightmap, the bottom of the world when the
+    /// column is empty or outside of the region
+    pub fn get_height(&self, heightmap: WorldHeightmap, x: i32, z: i32) -> i32 {
+        let Some(chunk) = self.chunk(x, z) else {
+            return self.min_y;
+        };
+        let heights = match heightmap {
+            WorldHeightmap::WorldSurfaceWg => &chunk.worldgen_heights[0],
+            WorldHeightmap::OceanFloorWg => &chunk.worldgen_heights[1],
+            WorldHeightmap::WorldSurface => &chunk.heights[0],
+            WorldHeightmap::OceanFloor => &chunk.heights[1],
+            WorldHeightmap::MotionBlocking => &chunk.heights[2],
+            WorldHeightmap::MotionBlockingNoLeaves => &chunk.heights[3],
+        };
+        heights[(x & 15) as usize][(z & 15) as usize]
+    }
+
+    /// Biome at a block position with the biome zoom applied
+    pub fn get_biome(&self, x: i32, y: i32, z: i32) -> u16 {
+        zoomed_biome(self.world_state.biome_zoom_seed, x, y, z, |qx, qy, qz| {
+            self.noise_biome(qx, qy, qz)
+        })
+    }
+
+    /// Biome at a quart position, sampled from the biome source outside of the region
+    fn noise_biome(&self, quart_x: i32, quart_y: i32, quart_z: i32) -> u16 {
+        let min_quart = self.min_y >> 2;
+        let max_quart = (self.max_y >> 2) - 1;
+        if let Some(chunk) = self
+            .chunk(quart_x << 2, quart_z << 2)
+            .filter(|chunk| !chunk.biomes.is_empty())
+        {
+            let offset = quart_y.clamp(min_quart, max_quart) - min_quart;
+            let section = ((offset >> 2) as usize).min(chunk.biomes.len() - 1);
+            return chunk.biomes[section][((offset & 3) as usize) << 4
+                | ((quart_z & 3) as usize) << 2
+                | (quart_x & 3) as usize];
+        }
+        self.world_state
+            .biome_source
+            .as_ref()
+            .and_then(|source| {
+                source.noise_biome(
+                    &self.world_state.settings.noise_router,
+                    self.dimension,
+                    quart_x,
+                    quart_y,
+                    quart_z,
+                )
+            })
+            .unwrap_or(0)
+    }
+
+    /// Every biome found in the sections of the region
+    pub fn biomes(&self) -> Vec<u16> {
+        let mut biomes = Vec::new();
+        for chunk in self.chunks.iter().flatten() {
+            for biome in chunk.biomes.iter().flatten() {
+                if !biomes.contains(biome) {
+                    biomes.push(*biome);
+                }
+            }
+        }
+        biomes
+    }
+
+    /// Positions carved in the center chunk
+    pub fn carving_mask(&self) -> Option<&CarvingMask> {
+        self.chunks[4].as_ref()?.carving_mask.as_ref()
+    }
+
+    /// Writes the placed blocks back into the writable chunks and recomputes their heightmaps
+    pub fn write_back(self, chunks: &mut [RegionChunk]) {
+        for (loaded, chunk) in self.chunks.into_iter().zip(chunks.iter_mut()) {
+            let (Some(loaded), RegionChunk::Writable(chunk)) = (loaded, chunk) else {
+                continue;
+            };
+            if !loaded.modified.contains(&true) {
+                continue;
+            }
+            for ((section, block_ids), modified) in chunk
+                .sections
+                .iter_mut()
+                .zip(loaded.blocks)
+                .zip(loaded.modified)
+            {
+                if modified {
+                    section.block_states = ChunkBlockStates::from_block_ids(&block_ids);
+                }
+            }
+            heightmap::compute_heightmaps(chunk);
+        }
+    }
+}
+
+impl LoadedChunk {
+    fn new(chunk: &Chunk, writable: bool) -> Self {
+        let min_y = chunk.y as i32 * 16;
+        let world_height = chunk.world_height();
+        let heights = HeightmapType::ALL.map(|heightmap| {
+            let mut heights = chunk
+                .heightmaps
+                .get(heightmap)
+                .map(|packed| Chunk::decode_heightmap(packed, world_height))
+                .unwrap_or_default();
+            for height in heights.iter_mut().flatten() {
+                *height += min_y;
+            }
+            heights
+        });
+        LoadedChunk {
+            blocks: chunk
+                .sections
+                .iter()
+                .map(|section| section.block_states.block_ids())
+                .collect(),
+            modified: vec![false; chunk.sections.len()],
+            writable,
+            worldgen_heights: [heights[0], heights[1]],
+            heights,
+            biomes: chunk
+                .sections
+                .iter()
+                .map(|section| section.biomes.biome_ids())
+                .collect(),
+            carving_mask: chunk.carving_mask.clone(),
+        }
+    }
+
+    /// Updates a column of the worldgen heightmaps like vanilla's `Heightmap.update`
+    fn update_worldgen_heights(
+        &mut self,
+        x: i32,
+        y: i32,
+        z: i32,
+        block: u32,
+        min_y: i32,
+        max_y: i32,
+    ) {
+        let (x, z) = (x as usize, z as usize);
+        for (i, heightmap) in [HeightmapType::WorldSurface, HeightmapType::OceanFloor]
+            .into_iter()
+            .enumerate()
+        {
+            let height = self.worldgen_heights[i][x][z];
+            if y <= height - 2 {
+                continue;
+            }
+            if heightmap.matches(&HEIGHTMAP_BLOCKS[block as usize]) {
+                if y >= height {
+                    self.worldgen_heights[i][x][z] = y + 1;
+                }
+            } else if height - 1 == y {
+                let mut new_height = min_y;
+                for below in (min_y..y.min(max_y)).rev() {
+                    let offset = below - min_y;
+                    let block = self.blocks[(offset >> 4) as usize]
+                        [((offset & 15) as usize) << 8 | z << 4 | x];
+                    if heightmap.matches(&HEIGHTMAP_BLOCKS[block as usize]) {
+                        new_height = below + 1;
+                        break;
+                    }
+                }
+                self.worldgen_heights[i][x][z] = new_height;
+            }
+        }
+    }
+}
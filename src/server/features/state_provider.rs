@@ -0,0 +1,296 @@
+use super::super::randomness::LCG48;
+use super::super::terrain_gen::noise_generator::{NoiseArguments, VanillaNoise};
+use super::super::util::clamped_map_f64;
+use super::super::value_providers::IntProvider;
+use super::super::world_state::NoiseBlockState;
+use super::block_predicate::BlockPredicate;
+use super::region::FeatureRegion;
+use super::{property, with_property};
+use crate::RandomGenerator;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+
+/// Block state written as `{"Name": ..., "Properties": {...}}`, resolved to its id
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockState(pub u32);
+
+impl<'de> Deserialize<'de> for BlockState {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let state = NoiseBlockState::deserialize(deserializer)?;
+        state
+            .id()
+            .map(BlockState)
+            .ok_or_else(|| D::Error::custom(format!("Unknown block state {}", state.name)))
+    }
+}
+
+/// Fluid state written like a block state, resolved to the block of its source
+#[derive(Debug, Clone, Copy)]
+pub struct FluidBlock(pub u32);
+
+impl<'de> Deserialize<'de> for FluidBlock {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let state = NoiseBlockState::deserialize(deserializer)?;
+        let fluid = match state.name.trim_start_matches("minecraft:") {
+            "water" | "flowing_water" => NoiseBlockState {
+                name: "minecraft:water".to_owned(),
+                properties: Some([("level".to_owned(), "0".to_owned())].into()),
+            },
+            "lava" | "flowing_lava" => NoiseBlockState {
+                name: "minecraft:lava".to_owned(),
+                properties: Some([("level".to_owned(), "0".to_owned())].into()),
+            },
+            _ => state,
+        };
+        fluid
+            .id()
+            .map(FluidBlock)
+            .ok_or_else(|| D::Error::custom(format!("Unknown fluid {}", fluid.name)))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WeightedState {
+    data: BlockState,
+    weight: i32,
+}
+
+/// Noise picking the block of noise based providers, created from a legacy random like vanilla
+#[derive(Deserialize)]
+#[serde(from = "StateNoiseConfig")]
+pub struct StateNoise {
+    noise: VanillaNoise,
+    scale: f64,
+}
+
+#[derive(Deserialize)]
+struct StateNoiseConfig {
+    seed: i64,
+    noise: NoiseArguments,
+    scale: f32,
+}
+
+impl StateNoise {
+    fn new(seed: i64, noise: &NoiseArguments, scale: f64) -> Self {
+        StateNoise {
+            noise: VanillaNoise::new(&mut LCG48::new(seed), noise, true),
+            scale,
+        }
+    }
+
+    fn value(&self, (x, y, z): (i32, i32, i32), scale: f64) -> f64 {
+        self.noise
+            .get_val(x as f64 * scale, y as f64 * scale, z as f64 * scale)
+    }
+}
+
+impl From<StateNoiseConfig> for StateNoise {
+    fn from(config: StateNoiseConfig) -> Self {
+        StateNoise::new(config.seed, &config.noise, config.scale as f64)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum InclusiveRange {
+    List([i32; 2]),
+    Bounds {
+        min_inclusive: i32,
+        max_inclusive: i32,
+    },
+}
+
+#[derive(Deserialize)]
+struct DualNoiseConfig {
+    #[serde(flatten)]
+    noise: StateNoiseConfig,
+    states: Vec<BlockState>,
+    variety: InclusiveRange,
+    slow_noise: NoiseArguments,
+    slow_scale: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(from = "DualNoiseConfig")]
+pub struct DualNoiseProvider {
+    noise: StateNoise,
+    slow_noise: StateNoise,
+    states: Vec<u32>,
+    variety: (i32, i32),
+}
+
+impl From<DualNoiseConfig> for DualNoiseProvider {
+    fn from(config: DualNoiseConfig) -> Self {
+        DualNoiseProvider {
+            slow_noise: StateNoise::new(
+                config.noise.seed,
+                &config.slow_noise,
+                config.slow_scale as f64,
+            ),
+            noise: config.noise.into(),
+            states: config.states.into_iter().map(|state| state.0).collect(),
+            variety: match config.variety {
+                InclusiveRange::List([min, max])
+                | InclusiveRange::Bounds {
+                    min_inclusive: min,
+                    max_inclusive: max,
+                } => (min, max),
+            },
+        }
+    }
+}
+
+/// Picks the block states placed by features
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum BlockStateProvider {
+    #[serde(rename = "minecraft:simple_state_provider")]
+    Simple { state: BlockState },
+    #[serde(rename = "minecraft:weighted_state_provider")]
+    Weighted { entries: Vec<WeightedState> },
+    /// The state with a random axis
+    #[serde(rename = "minecraft:rotated_block_provider")]
+    Rotated { state: BlockState },
+    #[serde(rename = "minecraft:randomized_int_state_provider")]
+    RandomizedInt {
+        source: Box<BlockStateProvider>,
+        property: String,
+        values: IntProvider,
+    },
+    #[serde(rename = "minecraft:noise_threshold_provider")]
+    NoiseThreshold {
+        #[serde(flatten)]
+        noise: StateNoise,
+        threshold: f32,
+        high_chance: f32,
+        default_state: BlockState,
+        low_states: Vec<BlockState>,
+        high_states: Vec<BlockState>,
+    },
+    #[serde(rename = "minecraft:noise_provider")]
+    Noise {
+        #[serde(flatten)]
+        noise: StateNoise,
+        states: Vec<BlockState>,
+    },
+    #[serde(rename = "minecraft:dual_noise_provider")]
+    DualNoise(Box<DualNoiseProvider>),
+}
+
+/// State at a noise value in -1..1 from a list spread evenly over the noise
+fn state_from_noise(states: &[u32], noise: f64) -> u32 {
+    let value = ((1.0 + noise) / 2.0).clamp(0.0, 0.9999);
+    states[(value * states.len() as f64) as usize]
+}
+
+fn random_state(states: &[BlockState], random: &mut dyn RandomGenerator) -> u32 {
+    states[random.next_i32_range(states.len() as u32) as usize].0
+}
+
+impl BlockStateProvider {
+    pub fn get_state(&self, random: &mut dyn RandomGenerator, pos: (i32, i32, i32)) -> u32 {
+        match self {
+            BlockStateProvider::Simple { state } => state.0,
+            BlockStateProvider::Weighted { entries } => {
+                let total = entries.iter().map(|entry| entry.weight).sum::<i32>();
+                let mut pick = random.next_i32_range(total.max(1) as u32);
+                for entry in entries {
+                    pick -= entry.weight;
+                    if pick < 0 {
+                        return entry.data.0;
+                    }
+                }
+                entries[0].data.0
+            }
+            BlockStateProvider::Rotated { state } => {
+                let axis = ["x", "y", "z"][random.next_i32_range(3) as usize];
+                with_property(state.0, "axis", axis)
+            }
+            BlockStateProvider::RandomizedInt {
+                source,
+                property: key,
+                values,
+            } => {
+                let state = source.get_state(random, pos);
+                if property(state, key).is_none() {
+                    return state;
+                }
+                with_property(state, key, &values.sample(random).to_string())
+            }
+            BlockStateProvider::NoiseThreshold {
+                noise,
+                threshold,
+                high_chance,
+                default_state,
+                low_states,
+                high_states,
+            } => {
+                if noise.value(pos, noise.scale) < *threshold as f64 {
+                    random_state(low_states, random)
+                } else if random.next_f32() < *high_chance {
+                    random_state(high_states, random)
+                } else {
+                    default_state.0
+                }
+            }
+            BlockStateProvider::Noise { noise, states } => {
+                let states = states.iter().map(|state| state.0).collect::<Vec<_>>();
+                state_from_noise(&states, noise.value(pos, noise.scale))
+            }
+            BlockStateProvider::DualNoise(provider) => {
+                let slow = |pos| provider.slow_noise.value(pos, provider.slow_noise.scale);
+                let (x, y, z) = pos;
+                let variety = clamped_map_f64(
+                    slow(pos),
+                    -1.0,
+                    1.0,
+                    provider.variety.0 as f64,
+                    (provider.variety.1 + 1) as f64,
+                ) as i32;
+                let states = (0..variety)
+                    .map(|i| {
+                        state_from_noise(&provider.states, slow((x + i * 54545, y, z + i * 34234)))
+                    })
+                    .collect::<Vec<_>>();
+                state_from_noise(&states, provider.noise.value(pos, provider.noise.scale))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct StateRule {
+    if_true: BlockPredicate,
+    then: BlockStateProvider,
+}
+
+/// Provider choosing the state by the first rule matching the position, used by disks
+#[derive(Deserialize)]
+pub struct RuleBasedStateProvider {
+    fallback: Option<BlockStateProvider>,
+    rules: Vec<StateRule>,
+}
+
+impl RuleBasedStateProvider {
+    pub fn get_state(
+        &self,
+        region: &FeatureRegion,
+        random: &mut dyn RandomGenerator,
+        pos: (i32, i32, i32),
+    ) -> Option<u32> {
+        for rule in &self.rules {
+            if rule.if_true.test(region, pos) {
+                return Some(rule.then.get_state(random, pos));
+            }
+        }
+        self.fallback
+            .as_ref()
+            .map(|fallback| fallback.get_state(random, pos))
+    }
+}
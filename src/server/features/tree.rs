@@ -0,0 +1,1209 @@
+use super::super::chunk_system::BLOCKSTATE_MAPPINGS;
+use super::super::util::{table_cos, table_sin};
+use super::super::value_providers::IntProvider;
+use super::block_predicate::is_air;
+use super::region::FeatureRegion;
+use super::state_provider::BlockStateProvider;
+use super::{block_name, property, with_property, Direction, Fluid, FEATURE_BLOCKS};
+use crate::RandomGenerator;
+use ahash::AHashSet;
+use serde::Deserialize;
+use std::f32::consts::PI;
+
+type Pos = (i32, i32, i32);
+
+/// Like vanilla's `Mth.floor` on floats, which turns NaN into 0
+fn floor(value: f32) -> i32 {
+    let truncated = value as i32;
+    if value < truncated as f32 {
+        truncated - 1
+    } else {
+        truncated
+    }
+}
+
+/// Set of positions iterated in the order of a java `HashSet` of `BlockPos`, which decides the
+/// order tree decorators visit the logs and leaves in
+#[derive(Default)]
+struct JavaHashSet {
+    positions: Vec<Pos>,
+    contained: AHashSet<Pos>,
+}
+
+impl JavaHashSet {
+    fn insert(&mut self, pos: Pos) {
+        if self.contained.insert(pos) {
+            self.positions.push(pos);
+        }
+    }
+
+    /// Positions by bucket of the hash table and by insertion order within a bucket, then stably
+    /// sorted by height like the lists of vanilla's `TreeDecorator.Context`
+    fn sorted_by_height(&self) -> Vec<Pos> {
+        let mut capacity = 16;
+        while self.positions.len() * 4 > capacity * 3 {
+            capacity *= 2;
+        }
+        let bucket = |(x, y, z): &Pos| {
+            let hash = y
+                .wrapping_add(z.wrapping_mul(31))
+                .wrapping_mul(31)
+                .wrapping_add(*x);
+            (hash ^ (hash as u32 >> 16) as i32) as usize & (capacity - 1)
+        };
+        let mut positions = self.positions.clone();
+        positions.sort_by_key(bucket);
+        positions.sort_by_key(|(_, y, _)| *y);
+        positions
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum TrunkKind {
+    #[serde(rename = "minecraft:straight_trunk_placer")]
+    Straight,
+    /// Acacia trunks
+    #[serde(rename = "minecraft:forking_trunk_placer")]
+    Forking,
+    /// Two by two trunks
+    #[serde(rename = "minecraft:giant_trunk_placer")]
+    Giant,
+    #[serde(rename = "minecraft:mega_jungle_trunk_placer")]
+    MegaJungle,
+    #[serde(rename = "minecraft:dark_oak_trunk_placer")]
+    DarkOak,
+    #[serde(rename = "minecraft:fancy_trunk_placer")]
+    Fancy,
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Deserialize)]
+struct TrunkPlacer {
+    #[serde(flatten)]
+    kind: TrunkKind,
+    base_height: i32,
+    height_rand_a: i32,
+    height_rand_b: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum FoliageKind {
+    #[serde(rename = "minecraft:blob_foliage_placer")]
+    Blob { height: i32 },
+    #[serde(rename = "minecraft:fancy_foliage_placer")]
+    Fancy { height: i32 },
+    #[serde(rename = "minecraft:bush_foliage_placer")]
+    Bush { height: i32 },
+    #[serde(rename = "minecraft:spruce_foliage_placer")]
+    Spruce { trunk_height: IntProvider },
+    #[serde(rename = "minecraft:pine_foliage_placer")]
+    Pine { height: IntProvider },
+    #[serde(rename = "minecraft:acacia_foliage_placer")]
+    Acacia,
+    #[serde(rename = "minecraft:jungle_foliage_placer")]
+    Jungle { height: i32 },
+    #[serde(rename = "minecraft:mega_pine_foliage_placer")]
+    MegaPine { crown_height: IntProvider },
+    #[serde(rename = "minecraft:dark_oak_foliage_placer")]
+    DarkOak,
+    #[serde(rename = "minecraft:random_spread_foliage_placer")]
+    RandomSpread {
+        foliage_height: IntProvider,
+        leaf_placement_attempts: i32,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Deserialize)]
+struct FoliagePlacer {
+    #[serde(flatten)]
+    kind: FoliageKind,
+    radius: IntProvider,
+    offset: IntProvider,
+}
+
+fn default_one() -> i32 {
+    1
+}
+
+/// Width of the space a tree needs to be free at every height above its base
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum FeatureSize {
+    #[serde(rename = "minecraft:two_layers_feature_size")]
+    TwoLayers {
+        #[serde(default = "default_one")]
+        limit: i32,
+        #[serde(default)]
+        lower_size: i32,
+        #[serde(default = "default_one")]
+        upper_size: i32,
+        min_clipped_height: Option<i32>,
+    },
+    #[serde(rename = "minecraft:three_layers_feature_size")]
+    ThreeLayers {
+        #[serde(default = "default_one")]
+        limit: i32,
+        #[serde(default = "default_one")]
+        upper_limit: i32,
+        #[serde(default)]
+        lower_size: i32,
+        #[serde(default = "default_one")]
+        middle_size: i32,
+        #[serde(default = "default_one")]
+        upper_size: i32,
+        min_clipped_height: Option<i32>,
+    },
+}
+
+impl FeatureSize {
+    fn size_at_height(&self, tree_height: i32, y: i32) -> i32 {
+        match self {
+            FeatureSize::TwoLayers {
+                limit,
+                lower_size,
+                upper_size,
+                ..
+            } => {
+                if y < *limit {
+                    *lower_size
+                } else {
+                    *upper_size
+                }
+            }
+            FeatureSize::ThreeLayers {
+                limit,
+                upper_limit,
+                lower_size,
+                middle_size,
+                upper_size,
+                ..
+            } => {
+                if y < *limit {
+                    *lower_size
+                } else if y >= tree_height - upper_limit {
+                    *upper_size
+                } else {
+                    *middle_size
+                }
+            }
+        }
+    }
+
+    fn min_clipped_height(&self) -> Option<i32> {
+        match self {
+            FeatureSize::TwoLayers {
+                min_clipped_height, ..
+            }
+            | FeatureSize::ThreeLayers {
+                min_clipped_height, ..
+            } => *min_clipped_height,
+        }
+    }
+}
+
+/// Blocks added around a tree once it is placed
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum TreeDecorator {
+    #[serde(rename = "minecraft:trunk_vine")]
+    TrunkVine,
+    #[serde(rename = "minecraft:leave_vine")]
+    LeaveVine { probability: f32 },
+    #[serde(rename = "minecraft:cocoa")]
+    Cocoa { probability: f32 },
+    #[serde(rename = "minecraft:beehive")]
+    Beehive { probability: f32 },
+    /// Replaces the ground around the trunk, podzol under giant spruces
+    #[serde(rename = "minecraft:alter_ground")]
+    AlterGround { provider: BlockStateProvider },
+    #[serde(other)]
+    Unsupported,
+}
+
+/// Configuration of the tree feature. Trees with root placers or with trunk or foliage placers
+/// that are not supported are not placed
+#[derive(Deserialize)]
+pub struct TreeConfig {
+    trunk_provider: BlockStateProvider,
+    trunk_placer: TrunkPlacer,
+    foliage_provider: BlockStateProvider,
+    foliage_placer: FoliagePlacer,
+    root_placer: Option<serde_json::Value>,
+    dirt_provider: BlockStateProvider,
+    minimum_size: FeatureSize,
+    #[serde(default)]
+    decorators: Vec<TreeDecorator>,
+    #[serde(default)]
+    ignore_vines: bool,
+    #[serde(default)]
+    force_dirt: bool,
+}
+
+/// Position leaves are placed around, returned by the trunk placers
+struct FoliageAttachment {
+    pos: Pos,
+    radius_offset: i32,
+    double_trunk: bool,
+}
+
+impl FoliageAttachment {
+    fn new(pos: Pos, radius_offset: i32, double_trunk: bool) -> Self {
+        FoliageAttachment {
+            pos,
+            radius_offset,
+            double_trunk,
+        }
+    }
+}
+
+/// Blocks a tree placed so far, like the block setters of vanilla's `TreeFeature`
+struct TreeBuilder<'r, 'a, 'c> {
+    region: &'r mut FeatureRegion<'a>,
+    config: &'c TreeConfig,
+    logs: JavaHashSet,
+    leaves: JavaHashSet,
+    decorations: JavaHashSet,
+}
+
+fn offset((x, y, z): Pos, dx: i32, dy: i32, dz: i32) -> Pos {
+    (x + dx, y + dy, z + dz)
+}
+
+impl TreeBuilder<'_, '_, '_> {
+    fn block(&self, (x, y, z): Pos) -> u32 {
+        self.region.get_block(x, y, z)
+    }
+
+    /// Whether a tree may replace the block at a position
+    fn valid_tree_pos(&self, pos: Pos) -> bool {
+        let block = self.block(pos);
+        is_air(block) || FEATURE_BLOCKS.replaceable_by_trees.contains(block)
+    }
+
+    fn is_free(&self, pos: Pos) -> bool {
+        self.valid_tree_pos(pos) || FEATURE_BLOCKS.logs.contains(self.block(pos))
+    }
+
+    fn is_air_or_leaves(&self, pos: Pos) -> bool {
+        let block = self.block(pos);
+        is_air(block) || FEATURE_BLOCKS.leaves.contains(block)
+    }
+
+    fn set_log(&mut self, pos: Pos, block: u32) {
+        self.logs.insert(pos);
+        self.region.set_block(pos.0, pos.1, pos.2, block);
+    }
+
+    fn set_decoration(&mut self, pos: Pos, block: u32) {
+        self.decorations.insert(pos);
+        self.region.set_block(pos.0, pos.1, pos.2, block);
+    }
+
+    /// Replaces the block below a trunk with dirt unless it already is a dirt block, the dirt
+    /// counts as a log like in vanilla
+    fn set_dirt_at(&mut self, random: &mut dyn RandomGenerator, pos: Pos) {
+        let block = self.block(pos);
+        let is_dirt = FEATURE_BLOCKS.dirt.contains(block)
+            && !matches!(block_name(block), "grass_block" | "mycelium");
+        if self.config.force_dirt || !is_dirt {
+            let dirt = self.config.dirt_provider.get_state(random, pos);
+            self.set_log(pos, dirt);
+        }
+    }
+
+    fn place_log(
+        &mut self,
+        random: &mut dyn RandomGenerator,
+        pos: Pos,
+        axis: Option<&str>,
+    ) -> bool {
+        if !self.valid_tree_pos(pos) {
+            return false;
+        }
+        let mut log = self.config.trunk_provider.get_state(random, pos);
+        if let Some(axis) = axis {
+            log = with_property(log, "axis", axis);
+        }
+        self.set_log(pos, log);
+        true
+    }
+
+    fn place_log_if_free(&mut self, random: &mut dyn RandomGenerator, pos: Pos) {
+        if self.is_free(pos) {
+            self.place_log(random, pos, None);
+        }
+    }
+
+    fn try_place_leaf(&mut self, random: &mut dyn RandomGenerator, pos: Pos) -> bool {
+        let persistent = property(self.block(pos), "persistent") == Some("true");
+        if !persistent && !self.valid_tree_pos(pos) {
+            return false;
+        }
+        let water = Fluid::of(self.block(pos)) == Fluid::Water;
+        let leaf = with_property(
+            self.config.foliage_provider.get_state(random, pos),
+            "waterlogged",
+            if water { "true" } else { "false" },
+        );
+        self.leaves.insert(pos);
+        self.region.set_block(pos.0, pos.1, pos.2, leaf);
+        true
+    }
+}
+
+impl TreeConfig {
+    fn is_supported(&self) -> bool {
+        self.root_placer.is_none()
+            && !matches!(self.trunk_placer.kind, TrunkKind::Unsupported)
+            && !matches!(self.foliage_placer.kind, FoliageKind::Unsupported)
+    }
+
+    /// Places a tree growing from a position, like vanilla's `TreeFeature`
+    pub fn place(
+        &self,
+        region: &mut FeatureRegion,
+        random: &mut dyn RandomGenerator,
+        pos: Pos,
+    ) -> bool {
+        if !self.is_supported() {
+            return false;
+        }
+        let mut tree = TreeBuilder {
+            region,
+            config: self,
+            logs: JavaHashSet::default(),
+            leaves: JavaHashSet::default(),
+            decorations: JavaHashSet::default(),
+        };
+        if !self.place_tree(&mut tree, random, pos)
+            || tree.logs.positions.is_empty() && tree.leaves.positions.is_empty()
+        {
+            return false;
+        }
+        if !self.decorators.is_empty() {
+            let logs = tree.logs.sorted_by_height();
+            let leaves = tree.leaves.sorted_by_height();
+            for decorator in &self.decorators {
+                decorator.place(&mut tree, random, &logs, &leaves);
+            }
+        }
+        update_leaves(&mut tree);
+        true
+    }
+
+    fn place_tree(
+        &self,
+        tree: &mut TreeBuilder,
+        random: &mut dyn RandomGenerator,
+        pos: Pos,
+    ) -> bool {
+        let height = self.trunk_placer.tree_height(random);
+        let foliage_height = self.foliage_placer.foliage_height(random, height);
+        let foliage_radius = self
+            .foliage_placer
+            .foliage_radius(random, height - foliage_height);
+        if pos.1 < tree.region.min_y + 1 || pos.1 + height + 1 > tree.region.max_y {
+            return false;
+        }
+        let free_height = self.max_free_tree_height(tree, height, pos);
+        if free_height < height
+            && self
+                .minimum_size
+                .min_clipped_height()
+                .is_none_or(|min_height| free_height < min_height)
+        {
+            return false;
+        }
+        let attachments = self
+            .trunk_placer
+            .place_trunk(tree, random, free_height, pos);
+        for attachment in attachments {
+            self.foliage_placer.create_foliage(
+                tree,
+                random,
+                &attachment,
+                foliage_height,
+                foliage_radius,
+            );
+        }
+        true
+    }
+
+    /// Height up to which the space the tree needs is free, `height` when it is free everywhere
+    fn max_free_tree_height(&self, tree: &TreeBuilder, height: i32, pos: Pos) -> i32 {
+        for y in 0..=height + 1 {
+            let size = self.minimum_size.size_at_height(height, y);
+            for dx in -size..=size {
+                for dz in -size..=size {
+                    let pos = offset(pos, dx, y, dz);
+                    if !tree.is_free(pos)
+                        || !self.ignore_vines && block_name(tree.block(pos)) == "vine"
+                    {
+                        return y - 2;
+                    }
+                }
+            }
+        }
+        height
+    }
+}
+
+impl TrunkPlacer {
+    fn tree_height(&self, random: &mut dyn RandomGenerator) -> i32 {
+        self.base_height
+            + random.next_i32_range((self.height_rand_a + 1) as u32)
+            + random.next_i32_range((self.height_rand_b + 1) as u32)
+    }
+
+    fn place_trunk(
+        &self,
+        tree: &mut TreeBuilder,
+        random: &mut dyn RandomGenerator,
+        height: i32,
+        pos: Pos,
+    ) -> Vec<FoliageAttachment> {
+        match self.kind {
+            TrunkKind::Straight => {
+                tree.set_dirt_at(random, offset(pos, 0, -1, 0));
+                for y in 0..height {
+                    tree.place_log(random, offset(pos, 0, y, 0), None);
+                }
+                vec![FoliageAttachment::new(offset(pos, 0, height, 0), 0, false)]
+            }
+            TrunkKind::Forking => place_forking_trunk(tree, random, height, pos),
+            TrunkKind::Giant => place_giant_trunk(tree, random, height, pos),
+            TrunkKind::MegaJungle => {
+                let mut attachments = place_giant_trunk(tree, random, height, pos);
+                let mut y = height - 2 - random.next_i32_range(4);
+                while y > height / 2 {
+                    let angle = random.next_f32() * (PI * 2.0);
+                    let (mut x, mut z) = (0, 0);
+                    for step in 0..5 {
+                        x = (1.5 + table_cos(angle as f64) * step as f32) as i32;
+                        z = (1.5 + table_sin(angle as f64) * step as f32) as i32;
+                        tree.place_log(random, offset(pos, x, y - 3 + step / 2, z), None);
+                    }
+                    attachments.push(FoliageAttachment::new(offset(pos, x, y, z), -2, false));
+                    y -= 2 + random.next_i32_range(4);
+                }
+                attachments
+            }
+            TrunkKind::DarkOak => place_dark_oak_trunk(tree, random, height, pos),
+            TrunkKind::Fancy => place_fancy_trunk(tree, random, height, pos),
+            TrunkKind::Unsupported => Vec::new(),
+        }
+    }
+}
+
+fn place_forking_trunk(
+    tree: &mut TreeBuilder,
+    random: &mut dyn RandomGenerator,
+    height: i32,
+    pos: Pos,
+) -> Vec<FoliageAttachment> {
+    tree.set_dirt_at(random, offset(pos, 0, -1, 0));
+    let mut attachments = Vec::new();
+    let direction = Direction::random_horizontal(random);
+    let lean_start = height - random.next_i32_range(4) - 1;
+    let mut lean_steps = 3 - random.next_i32_range(3);
+    let (mut x, mut z) = (pos.0, pos.2);
+    let mut top = None;
+    for dy in 0..height {
+        let y = pos.1 + dy;
+        if dy >= lean_start && lean_steps > 0 {
+            let (step_x, _, step_z) = direction.step();
+            x += step_x;
+            z += step_z;
+            lean_steps -= 1;
+        }
+        if tree.place_log(random, (x, y, z), None) {
+            top = Some(y + 1);
+        }
+    }
+    if let Some(top) = top {
+        attachments.push(FoliageAttachment::new((x, top, z), 1, false));
+    }
+    let (mut x, mut z) = (pos.0, pos.2);
+    let branch_direction = Direction::random_horizontal(random);
+    if branch_direction != direction {
+        let branch_start = lean_start - random.next_i32_range(2) - 1;
+        let mut branch_steps = 1 + random.next_i32_range(3);
+        let mut top = None;
+        let mut dy = branch_start;
+        while dy < height && branch_steps > 0 {
+            if dy >= 1 {
+                let y = pos.1 + dy;
+                let (step_x, _, step_z) = branch_direction.step();
+                x += step_x;
+                z += step_z;
+                if tree.place_log(random, (x, y, z), None) {
+                    top = Some(y + 1);
+                }
+            }
+            dy += 1;
+            branch_steps -= 1;
+        }
+        if let Some(top) = top {
+            attachments.push(FoliageAttachment::new((x, top, z), 0, false));
+        }
+    }
+    attachments
+}
+
+fn place_giant_trunk(
+    tree: &mut TreeBuilder,
+    random: &mut dyn RandomGenerator,
+    height: i32,
+    pos: Pos,
+) -> Vec<FoliageAttachment> {
+    for (dx, dz) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        tree.set_dirt_at(random, offset(pos, dx, -1, dz));
+    }
+    for y in 0..height {
+        tree.place_log_if_free(random, offset(pos, 0, y, 0));
+        if y < height - 1 {
+            tree.place_log_if_free(random, offset(pos, 1, y, 0));
+            tree.place_log_if_free(random, offset(pos, 1, y, 1));
+            tree.place_log_if_free(random, offset(pos, 0, y, 1));
+        }
+    }
+    vec![FoliageAttachment::new(offset(pos, 0, height, 0), 0, true)]
+}
+
+fn place_dark_oak_trunk(
+    tree: &mut TreeBuilder,
+    random: &mut dyn RandomGenerator,
+    height: i32,
+    pos: Pos,
+) -> Vec<FoliageAttachment> {
+    let mut attachments = Vec::new();
+    for (dx, dz) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        tree.set_dirt_at(random, offset(pos, dx, -1, dz));
+    }
+    let direction = Direction::random_horizontal(random);
+    let lean_start = height - random.next_i32_range(4);
+    let mut lean_steps = 2 - random.next_i32_range(3);
+    let (mut x, mut z) = (pos.0, pos.2);
+    let top = pos.1 + height - 1;
+    for dy in 0..height {
+        if dy >= lean_start && lean_steps > 0 {
+            let (step_x, _, step_z) = direction.step();
+            x += step_x;
+            z += step_z;
+            lean_steps -= 1;
+        }
+        let log = (x, pos.1 + dy, z);
+        if tree.is_air_or_leaves(log) {
+            tree.place_log(random, log, None);
+            tree.place_log(random, offset(log, 1, 0, 0), None);
+            tree.place_log(random, offset(log, 0, 0, 1), None);
+            tree.place_log(random, offset(log, 1, 0, 1), None);
+        }
+    }
+    attachments.push(FoliageAttachment::new((x, top, z), 0, true));
+    for dx in -1..=2 {
+        for dz in -1..=2 {
+            if (0..=1).contains(&dx) && (0..=1).contains(&dz) || random.next_i32_range(3) > 0 {
+                continue;
+            }
+            let length = random.next_i32_range(3) + 2;
+            for dy in 0..length {
+                tree.place_log(random, (pos.0 + dx, top - dy - 1, pos.2 + dz), None);
+            }
+            attachments.push(FoliageAttachment::new(
+                (pos.0 + dx, top, pos.2 + dz),
+                0,
+                false,
+            ));
+        }
+    }
+    attachments
+}
+
+/// Height of the branches of a fancy tree at a height of its trunk, negative without branches
+fn fancy_tree_shape(height: i32, y: i32) -> f32 {
+    if (y as f32) < height as f32 * 0.3 {
+        return -1.0;
+    }
+    let half = height as f32 / 2.0;
+    let distance = half - y as f32;
+    if distance == 0.0 {
+        half * 0.5
+    } else if distance.abs() >= half {
+        0.0
+    } else {
+        (half * half - distance * distance).sqrt() * 0.5
+    }
+}
+
+/// Places or checks the logs of a straight line between two positions
+fn make_limb(
+    tree: &mut TreeBuilder,
+    random: &mut dyn RandomGenerator,
+    from: Pos,
+    to: Pos,
+    place: bool,
+) -> bool {
+    if !place && from == to {
+        return true;
+    }
+    let (dx, dy, dz) = (to.0 - from.0, to.1 - from.1, to.2 - from.2);
+    let steps = dx.abs().max(dy.abs()).max(dz.abs());
+    let (step_x, step_y, step_z) = (
+        dx as f32 / steps as f32,
+        dy as f32 / steps as f32,
+        dz as f32 / steps as f32,
+    );
+    for i in 0..=steps {
+        let pos = offset(
+            from,
+            floor(0.5 + i as f32 * step_x),
+            floor(0.5 + i as f32 * step_y),
+            floor(0.5 + i as f32 * step_z),
+        );
+        if place {
+            let (x_span, z_span) = ((pos.0 - from.0).abs(), (pos.2 - from.2).abs());
+            let axis = match x_span.max(z_span) {
+                0 => "y",
+                span if span == x_span => "x",
+                _ => "z",
+            };
+            tree.place_log(random, pos, Some(axis));
+        } else if !tree.is_free(pos) {
+            return false;
+        }
+    }
+    true
+}
+
+fn place_fancy_trunk(
+    tree: &mut TreeBuilder,
+    random: &mut dyn RandomGenerator,
+    height: i32,
+    pos: Pos,
+) -> Vec<FoliageAttachment> {
+    let height = height + 2;
+    let trunk_height = (height as f64 * 0.618).floor() as i32;
+    tree.set_dirt_at(random, offset(pos, 0, -1, 0));
+    let branches_per_layer = 1.min((1.382 + (height as f64 / 13.0).powi(2)).floor() as i32);
+    let branch_top = pos.1 + trunk_height;
+    let mut y = height - 5;
+    // Foliage positions with the height their branch starts at
+    let mut foliage = vec![(offset(pos, 0, y, 0), branch_top)];
+    while y >= 0 {
+        let shape = fancy_tree_shape(height, y);
+        if shape >= 0.0 {
+            for _ in 0..branches_per_layer {
+                let distance = shape as f64 * (random.next_f32() as f64 + 0.328);
+                let angle = (random.next_f32() * 2.0) as f64 * std::f64::consts::PI;
+                let end = offset(
+                    pos,
+                    (distance * angle.sin() + 0.5).floor() as i32,
+                    y - 1,
+                    (distance * angle.cos() + 0.5).floor() as i32,
+                );
+                if !make_limb(tree, random, end, offset(end, 0, 5, 0), false) {
+                    continue;
+                }
+                let (dx, dz) = (pos.0 - end.0, pos.2 - end.2);
+                let base = end.1 as f64 - ((dx * dx + dz * dz) as f64).sqrt() * 0.381;
+                let base_y = if base > branch_top as f64 {
+                    branch_top
+                } else {
+                    base as i32
+                };
+                if make_limb(tree, random, (pos.0, base_y, pos.2), end, false) {
+                    foliage.push((end, base_y));
+                }
+            }
+        }
+        y -= 1;
+    }
+    make_limb(tree, random, pos, offset(pos, 0, trunk_height, 0), true);
+    let trim = |base_y: i32| (base_y - pos.1) as f64 >= height as f64 * 0.2;
+    for (end, base_y) in &foliage {
+        let base = (pos.0, *base_y, pos.2);
+        if base != *end && trim(*base_y) {
+            make_limb(tree, random, base, *end, true);
+        }
+    }
+    foliage
+        .into_iter()
+        .filter(|(_, base_y)| trim(*base_y))
+        .map(|(end, _)| FoliageAttachment::new(end, 0, false))
+        .collect()
+}
+
+impl FoliagePlacer {
+    fn foliage_height(&self, random: &mut dyn RandomGenerator, tree_height: i32) -> i32 {
+        match &self.kind {
+            FoliageKind::Blob { height }
+            | FoliageKind::Fancy { height }
+            | FoliageKind::Bush { height }
+            | FoliageKind::Jungle { height } => *height,
+            FoliageKind::Spruce { trunk_height } => {
+                4.max(tree_height - trunk_height.sample(random))
+            }
+            FoliageKind::Pine { height } => height.sample(random),
+            FoliageKind::MegaPine { crown_height } => crown_height.sample(random),
+            FoliageKind::RandomSpread { foliage_height, .. } => foliage_height.sample(random),
+            FoliageKind::Acacia => 0,
+            FoliageKind::DarkOak => 4,
+            FoliageKind::Unsupported => 0,
+        }
+    }
+
+    fn foliage_radius(&self, random: &mut dyn RandomGenerator, trunk_height: i32) -> i32 {
+        let radius = self.radius.sample(random);
+        match self.kind {
+            FoliageKind::Pine { .. } => radius + random.next_i32_range(trunk_height.max(1) as u32),
+            _ => radius,
+        }
+    }
+
+    /// Whether a leaf at a distance from the center of a row is left out
+    fn should_skip_location(
+        &self,
+        random: &mut dyn RandomGenerator,
+        dx: i32,
+        y: i32,
+        dz: i32,
+        radius: i32,
+        large: bool,
+    ) -> bool {
+        match self.kind {
+            FoliageKind::Blob { .. } => {
+                dx == radius && dz == radius && (random.next_i32_range(2) == 0 || y == 0)
+            }
+            FoliageKind::Fancy { .. } => {
+                (dx as f32 + 0.5).powi(2) + (dz as f32 + 0.5).powi(2) > (radius * radius) as f32
+            }
+            FoliageKind::Bush { .. } => {
+                dx == radius && dz == radius && random.next_i32_range(2) == 0
+            }
+            FoliageKind::Spruce { .. } | FoliageKind::Pine { .. } => {
+                dx == radius && dz == radius && radius > 0
+            }
+            FoliageKind::Acacia => {
+                if y == 0 {
+                    (dx > 1 || dz > 1) && dx != 0 && dz != 0
+                } else {
+                    dx == radius && dz == radius && radius > 0
+                }
+            }
+            FoliageKind::Jungle { .. } | FoliageKind::MegaPine { .. } => {
+                dx + dz >= 7 || dx * dx + dz * dz > radius * radius
+            }
+            FoliageKind::DarkOak => {
+                if y == -1 && !large {
+                    dx == radius && dz == radius
+                } else if y == 1 {
+                    dx + dz > radius * 2 - 2
+                } else {
+                    false
+                }
+            }
+            FoliageKind::RandomSpread { .. } | FoliageKind::Unsupported => false,
+        }
+    }
+
+    fn should_skip_location_signed(
+        &self,
+        random: &mut dyn RandomGenerator,
+        dx: i32,
+        y: i32,
+        dz: i32,
+        radius: i32,
+        large: bool,
+    ) -> bool {
+        if matches!(self.kind, FoliageKind::DarkOak)
+            && y == 0
+            && large
+            && (dx == -radius || dx >= radius)
+            && (dz == -radius || dz >= radius)
+        {
+            return true;
+        }
+        let (dx, dz) = if large {
+            (dx.abs().min((dx - 1).abs()), dz.abs().min((dz - 1).abs()))
+        } else {
+            (dx.abs(), dz.abs())
+        };
+        self.should_skip_location(random, dx, y, dz, radius, large)
+    }
+
+    /// Square of leaves around a position, one wider for trees with a two by two trunk
+    #[allow(clippy::too_many_arguments)]
+    fn place_leaves_row(
+        &self,
+        tree: &mut TreeBuilder,
+        random: &mut dyn RandomGenerator,
+        pos: Pos,
+        radius: i32,
+        y: i32,
+        large: bool,
+    ) {
+        let extra = large as i32;
+        for dx in -radius..=radius + extra {
+            for dz in -radius..=radius + extra {
+                if !self.should_skip_location_signed(random, dx, y, dz, radius, large) {
+                    tree.try_place_leaf(random, offset(pos, dx, y, dz));
+                }
+            }
+        }
+    }
+
+    fn create_foliage(
+        &self,
+        tree: &mut TreeBuilder,
+        random: &mut dyn RandomGenerator,
+        attachment: &FoliageAttachment,
+        foliage_height: i32,
+        radius: i32,
+    ) {
+        let offset_y = self.offset.sample(random);
+        let pos = attachment.pos;
+        let large = attachment.double_trunk;
+        let radius_offset = attachment.radius_offset;
+        match &self.kind {
+            FoliageKind::Blob { .. } => {
+                for y in (offset_y - foliage_height..=offset_y).rev() {
+                    let row_radius = (radius + radius_offset - 1 - y / 2).max(0);
+                    self.place_leaves_row(tree, random, pos, row_radius, y, large);
+                }
+            }
+            FoliageKind::Fancy { .. } => {
+                for y in (offset_y - foliage_height..=offset_y).rev() {
+                    let edge = y == offset_y || y == offset_y - foliage_height;
+                    let row_radius = radius + if edge { 0 } else { 1 };
+                    self.place_leaves_row(tree, random, pos, row_radius, y, large);
+                }
+            }
+            FoliageKind::Bush { .. } => {
+                for y in (offset_y - foliage_height..=offset_y).rev() {
+                    let row_radius = radius + radius_offset - 1 - y;
+                    self.place_leaves_row(tree, random, pos, row_radius, y, large);
+                }
+            }
+            FoliageKind::Spruce { .. } => {
+                let mut row_radius = random.next_i32_range(2);
+                let mut max_radius = 1;
+                let mut min_radius = 0;
+                for y in (-foliage_height..=offset_y).rev() {
+                    self.place_leaves_row(tree, random, pos, row_radius, y, large);
+                    if row_radius >= max_radius {
+                        row_radius = min_radius;
+                        min_radius = 1;
+                        max_radius = (max_radius + 1).min(radius + radius_offset);
+                    } else {
+                        row_radius += 1;
+                    }
+                }
+            }
+            FoliageKind::Pine { .. } => {
+                let mut row_radius = 0;
+                for y in (offset_y - foliage_height..=offset_y).rev() {
+                    self.place_leaves_row(tree, random, pos, row_radius, y, large);
+                    if row_radius >= 1 && y == offset_y - foliage_height + 1 {
+                        row_radius -= 1;
+                    } else if row_radius < radius + radius_offset {
+                        row_radius += 1;
+                    }
+                }
+            }
+            FoliageKind::Acacia => {
+                let pos = offset(pos, 0, offset_y, 0);
+                let rows = [
+                    (radius + radius_offset, -1 - foliage_height),
+                    (radius - 1, -foliage_height),
+                    (radius + radius_offset - 1, 0),
+                ];
+                for (row_radius, y) in rows {
+                    self.place_leaves_row(tree, random, pos, row_radius, y, large);
+                }
+            }
+            FoliageKind::Jungle { .. } => {
+                let height = if large {
+                    foliage_height
+                } else {
+                    1 + random.next_i32_range(2)
+                };
+                for y in (offset_y - height..=offset_y).rev() {
+                    let row_radius = radius + radius_offset + 1 - y;
+                    self.place_leaves_row(tree, random, pos, row_radius, y, large);
+                }
+            }
+            FoliageKind::MegaPine { .. } => {
+                let mut previous_radius = 0;
+                for y in pos.1 - foliage_height + offset_y..=pos.1 + offset_y {
+                    let depth = pos.1 - y;
+                    let row_radius =
+                        radius + radius_offset + floor(depth as f32 / foliage_height as f32 * 3.5);
+                    let placed_radius = if depth > 0 && row_radius == previous_radius && y & 1 == 0
+                    {
+                        row_radius + 1
+                    } else {
+                        row_radius
+                    };
+                    let row = (pos.0, y, pos.2);
+                    self.place_leaves_row(tree, random, row, placed_radius, 0, large);
+                    previous_radius = row_radius;
+                }
+            }
+            FoliageKind::DarkOak => {
+                let pos = offset(pos, 0, offset_y, 0);
+                let rows: &[(i32, i32)] = if large {
+                    &[(radius + 2, -1), (radius + 3, 0), (radius + 2, 1)]
+                } else {
+                    &[(radius + 2, -1), (radius + 1, 0)]
+                };
+                for (row_radius, y) in rows {
+                    self.place_leaves_row(tree, random, pos, *row_radius, *y, large);
+                }
+                if large && random.next_bool() {
+                    self.place_leaves_row(tree, random, pos, radius, 2, large);
+                }
+            }
+            FoliageKind::RandomSpread {
+                leaf_placement_attempts,
+                ..
+            } => {
+                let (radius, height) = (radius as u32, foliage_height as u32);
+                for _ in 0..*leaf_placement_attempts {
+                    let dx = random.next_i32_range(radius) - random.next_i32_range(radius);
+                    let dy = random.next_i32_range(height) - random.next_i32_range(height);
+                    let dz = random.next_i32_range(radius) - random.next_i32_range(radius);
+                    tree.try_place_leaf(random, offset(pos, dx, dy, dz));
+                }
+            }
+            FoliageKind::Unsupported => {}
+        }
+    }
+}
+
+impl TreeDecorator {
+    fn place(
+        &self,
+        tree: &mut TreeBuilder,
+        random: &mut dyn RandomGenerator,
+        logs: &[Pos],
+        leaves: &[Pos],
+    ) {
+        let blocks = &*FEATURE_BLOCKS;
+        let vine = *BLOCKSTATE_MAPPINGS
+            .get("vine[east=false,north=false,south=false,up=false,west=false]")
+            .unwrap();
+        // Vines are placed on the side of the air block facing the log or leaves they hang from
+        let sides = [
+            (Direction::West, "east"),
+            (Direction::East, "west"),
+            (Direction::North, "south"),
+            (Direction::South, "north"),
+        ];
+        match self {
+            TreeDecorator::TrunkVine => {
+                for log in logs {
+                    for (direction, face) in sides {
+                        if random.next_i32_range(3) > 0 {
+                            let pos = direction.offset(*log, 1);
+                            if is_air(tree.block(pos)) {
+                                tree.set_decoration(pos, with_property(vine, face, "true"));
+                            }
+                        }
+                    }
+                }
+            }
+            TreeDecorator::LeaveVine { probability } => {
+                for leaf in leaves {
+                    for (direction, face) in sides {
+                        if random.next_f32() < *probability {
+                            let mut pos = direction.offset(*leaf, 1);
+                            if !is_air(tree.block(pos)) {
+                                continue;
+                            }
+                            let vine = with_property(vine, face, "true");
+                            tree.set_decoration(pos, vine);
+                            pos = offset(pos, 0, -1, 0);
+                            let mut length = 4;
+                            while is_air(tree.block(pos)) && length > 0 {
+                                tree.set_decoration(pos, vine);
+                                pos = offset(pos, 0, -1, 0);
+                                length -= 1;
+                            }
+                        }
+                    }
+                }
+            }
+            TreeDecorator::Cocoa { probability } => {
+                if random.next_f32() >= *probability {
+                    return;
+                }
+                let Some(bottom) = logs.first().map(|log| log.1) else {
+                    return;
+                };
+                let cocoa = *BLOCKSTATE_MAPPINGS
+                    .get("cocoa[age=0,facing=north]")
+                    .unwrap();
+                for log in logs.iter().filter(|log| log.1 - bottom <= 2) {
+                    for direction in Direction::HORIZONTAL {
+                        if random.next_f32() > 0.25 {
+                            continue;
+                        }
+                        let pos = direction.opposite().offset(*log, 1);
+                        if !is_air(tree.block(pos)) {
+                            continue;
+                        }
+                        let age = random.next_i32_range(3).to_string();
+                        let block = with_property(cocoa, "age", &age);
+                        tree.set_decoration(pos, with_property(block, "facing", direction.name()));
+                    }
+                }
+            }
+            TreeDecorator::Beehive { probability } => {
+                if random.next_f32() >= *probability || logs.is_empty() {
+                    return;
+                }
+                let y = match leaves.first() {
+                    Some(leaf) => (leaf.1 - 1).max(logs[0].1 + 1),
+                    None => (logs[0].1 + 1 + random.next_i32_range(3)).min(logs[logs.len() - 1].1),
+                };
+                // Nests face south and are not placed on the north side of the trunk
+                let mut positions = logs
+                    .iter()
+                    .filter(|log| log.1 == y)
+                    .flat_map(|log| {
+                        [Direction::East, Direction::South, Direction::West]
+                            .map(|direction| direction.offset(*log, 1))
+                    })
+                    .collect::<Vec<_>>();
+                if positions.is_empty() {
+                    return;
+                }
+                for i in (2..=positions.len()).rev() {
+                    let j = random.next_i32_range(i as u32) as usize;
+                    positions.swap(i - 1, j);
+                }
+                let Some(pos) = positions.into_iter().find(|pos| {
+                    is_air(tree.block(*pos)) && is_air(tree.block(Direction::South.offset(*pos, 1)))
+                }) else {
+                    return;
+                };
+                let nest = *BLOCKSTATE_MAPPINGS
+                    .get("bee_nest[facing=south,honey_level=0]")
+                    .unwrap();
+                tree.set_decoration(pos, nest);
+                // The bees are not stored but still draw from the random
+                let bees = 2 + random.next_i32_range(2);
+                for _ in 0..bees {
+                    random.next_i32_range(599);
+                }
+            }
+            TreeDecorator::AlterGround { provider } => {
+                let Some(bottom) = logs.first().map(|log| log.1) else {
+                    return;
+                };
+                let place_block =
+                    |tree: &mut TreeBuilder, random: &mut dyn RandomGenerator, pos: Pos| {
+                        for dy in (-3..=2).rev() {
+                            let ground = offset(pos, 0, dy, 0);
+                            let block = tree.block(ground);
+                            if blocks.dirt.contains(block) {
+                                let state = provider.get_state(random, pos);
+                                tree.set_decoration(ground, state);
+                                break;
+                            }
+                            if !is_air(block) && dy < 0 {
+                                break;
+                            }
+                        }
+                    };
+                let place_circle =
+                    |tree: &mut TreeBuilder, random: &mut dyn RandomGenerator, pos: Pos| {
+                        for dx in -2i32..=2 {
+                            for dz in -2i32..=2 {
+                                if dx.abs() != 2 || dz.abs() != 2 {
+                                    place_block(tree, random, offset(pos, dx, 0, dz));
+                                }
+                            }
+                        }
+                    };
+                for log in logs.iter().filter(|log| log.1 == bottom) {
+                    place_circle(tree, random, offset(*log, -1, 0, -1));
+                    place_circle(tree, random, offset(*log, 2, 0, -1));
+                    place_circle(tree, random, offset(*log, -1, 0, 2));
+                    place_circle(tree, random, offset(*log, 2, 0, 2));
+                    for _ in 0..5 {
+                        let square = random.next_i32_range(64);
+                        let (dx, dz) = (square % 8, square / 8);
+                        if dx == 0 || dx == 7 || dz == 0 || dz == 7 {
+                            place_circle(tree, random, offset(*log, -3 + dx, 0, -3 + dz));
+                        }
+                    }
+                }
+            }
+            TreeDecorator::Unsupported => {}
+        }
+    }
+}
+
+/// Sets the distance of the leaves around a tree to the closest log like vanilla's
+/// `TreeFeature.updateLeaves`, leaves further than 6 blocks keep their distance of 7
+fn update_leaves(tree: &mut TreeBuilder) {
+    let all = || {
+        tree.logs
+            .positions
+            .iter()
+            .chain(&tree.leaves.positions)
+            .chain(&tree.decorations.positions)
+    };
+    let min = all().fold((i32::MAX, i32::MAX, i32::MAX), |min, pos| {
+        (min.0.min(pos.0), min.1.min(pos.1), min.2.min(pos.2))
+    });
+    let max = all().fold((i32::MIN, i32::MIN, i32::MIN), |max, pos| {
+        (max.0.max(pos.0), max.1.max(pos.1), max.2.max(pos.2))
+    });
+    let inside = |(x, y, z): Pos| {
+        (min.0..=max.0).contains(&x) && (min.1..=max.1).contains(&y) && (min.2..=max.2).contains(&z)
+    };
+    let mut visited = tree.decorations.contained.clone();
+    let mut distances: Vec<Vec<Pos>> = vec![Vec::new(); 7];
+    distances[0] = tree.logs.positions.clone();
+    let mut distance = 0;
+    while distance < 7 {
+        let Some(pos) = distances[distance].pop() else {
+            distance += 1;
+            continue;
+        };
+        if !inside(pos) || !visited.insert(pos) {
+            continue;
+        }
+        if distance != 0 {
+            let leaf = with_property(tree.block(pos), "distance", &distance.to_string());
+            tree.region.set_block(pos.0, pos.1, pos.2, leaf);
+        }
+        for direction in Direction::ALL {
+            let next = direction.offset(pos, 1);
+            if !inside(next) || visited.contains(&next) {
+                continue;
+            }
+            let block = tree.block(next);
+            let current = if FEATURE_BLOCKS.logs.contains(block) {
+                0
+            } else if let Some(current) = property(block, "distance") {
+                current.parse::<usize>().unwrap_or(7)
+            } else {
+                continue;
+            };
+            let next_distance = current.min(distance + 1);
+            if next_distance < 7 {
+                distances[next_distance].push(next);
+                distance = distance.min(next_distance);
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use super::chunk_system::{
+    split_block_state, Chunk, ChunkBlock, ChunkBlockStates, ChunkSection, LudiChunkLoader,
+    BLOCKSTATES, BLOCKSTATE_MAPPINGS,
+};
+use super::heightmap::{self, HeightmapType, HEIGHTMAP_BLOCKS};
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use super::tags::BLOCK_STATE_IDS;
+use crate::server::DimensionType;
+use serde::{Deserialize, Serialize};
+
+fn default_biome() -> String {
+    "minecraft:plains".to_owned()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct FlatLayer {
+    pub block: String,
+    pub height: i32,
+}
+
+/// Structure sets of a flat world, a single id or a list of ids
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum StructureOverrides {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Settings of a `minecraft:flat` generator as stored in `level.dat`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct FlatSettings {
+    /// Layers from the bottom of the world up
+    #[serde(default)]
+    pub layers: Vec<FlatLayer>,
+    #[serde(default = "default_biome")]
+    pub biome: String,
+    /// Whether the features of the biome are placed
+    #[serde(default)]
+    pub features: bool,
+    /// Structure sets placed in the world, every set when missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structure_overrides: Option<StructureOverrides>,
+}
+
+impl FlatSettings {
+    /// Empty world without features or structures
+    pub fn void() -> Self {
+        FlatSettings {
+            layers: Vec::new(),
+            biome: "minecraft:the_void".to_owned(),
+            features: false,
+            structure_overrides: Some(StructureOverrides::Many(Vec::new())),
+        }
+    }
+
+    /// Ids of the structure sets placed in the world, `None` when every set is
+    pub fn structure_sets(&self) -> Option<Vec<String>> {
+        let ids = match self.structure_overrides.as_ref()? {
+            StructureOverrides::One(id) => vec![id.clone()],
+            StructureOverrides::Many(ids) => ids.clone(),
+        };
+        Some(
+            ids.into_iter()
+                .filter(|id| {
+                    if id.starts_with('#') {
+                        LOGGER.get().unwrap().println_as(
+                            &format!("Structure set tags are not supported in flat worlds: {id}"),
+                            LogDomain::Server,
+                            LogLevel::Warn,
+                        );
+                    }
+                    !id.starts_with('#')
+                })
+                .map(|id| {
+                    if id.contains(':') {
+                        id
+                    } else {
+                        format!("minecraft:{id}")
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Default state of a block. The block mappings do not say which state is the default one, so
+/// the state with the most properties at their usual default value is picked
+fn default_state(block: &str) -> Option<u32> {
+    let name = block.strip_prefix("minecraft:").unwrap_or(block);
+    if let Some(id) = BLOCKSTATE_MAPPINGS.get(name) {
+        return Some(*id);
+    }
+    let score = |id: &u32| {
+        split_block_state(&BLOCKSTATES[*id as usize])
+            .1
+            .split(',')
+            .filter(|property| {
+                property.ends_with("=false")
+                    || property.ends_with("=0")
+                    || property.ends_with("=north")
+                    || property.ends_with("=y")
+                    || property.ends_with("=bottom")
+                    || property.ends_with("=lower")
+                    || property.ends_with("=none")
+            })
+            .count()
+    };
+    BLOCK_STATE_IDS
+        .get(name)?
+        .iter()
+        .rev()
+        .max_by_key(|id| score(id))
+        .copied()
+}
+
+/// Blocks of a flat world, one per height from the bottom of the world
+#[derive(Debug)]
+pub struct FlatLevel {
+    blocks: Vec<u32>,
+}
+
+impl FlatLevel {
+    pub fn new(settings: &FlatSettings) -> Self {
+        let mut blocks = Vec::new();
+        for layer in &settings.layers {
+            let block = default_state(&layer.block).unwrap_or_else(|| {
+                LOGGER.get().unwrap().println_as(
+                    &format!("Unknown flat layer block {}, using air", layer.block),
+                    LogDomain::Server,
+                    LogLevel::Warn,
+                );
+                0
+            });
+            blocks.extend(std::iter::repeat_n(block, layer.height.max(0) as usize));
+        }
+        // Air on top of the layers is not stored
+        while blocks.last() == Some(&0) {
+            blocks.pop();
+        }
+        FlatLevel { blocks }
+    }
+
+    /// Height above the highest layer matching a heightmap, like vanilla's
+    /// `FlatLevelSource.getBaseHeight`
+    pub fn first_free_height(&self, heightmap: HeightmapType, min_y: i32) -> i32 {
+        self.blocks
+            .iter()
+            .rposition(|block| heightmap.matches(&HEIGHTMAP_BLOCKS[*block as usize]))
+            .map_or(min_y, |i| min_y + i as i32 + 1)
+    }
+
+    /// Chunk filled with the layers, sections made of a single layer are stored without data
+    pub fn generate(&self, packed_coord: u64, dimension_type: &DimensionType) -> Chunk {
+        let min_section_y = dimension_type.min_y.div_euclid(16) as i8;
+        let (x, z) = LudiChunkLoader::unpack_coords(packed_coord);
+        let sections = (0..dimension_type.height / 16)
+            .map(|i| {
+                let bottom = i as usize * 16;
+                let layers = (bottom..bottom + 16)
+                    .map(|y| self.blocks.get(y).copied().unwrap_or(0))
+                    .collect::<Vec<_>>();
+                let block_states = if layers.iter().all(|block| *block == layers[0]) {
+                    ChunkBlockStates {
+                        palette: vec![ChunkBlock { id: layers[0] }],
+                        data: None,
+                    }
+                } else {
+                    let block_ids = layers
+                        .iter()
+                        .flat_map(|block| std::iter::repeat_n(*block, 256))
+                        .collect::<Vec<_>>();
+                    ChunkBlockStates::from_block_ids(&block_ids)
+                };
+                ChunkSection {
+                    block_states,
+                    ..ChunkSection::air(i as i8 + min_section_y)
+                }
+            })
+            .collect();
+        let mut chunk = Chunk {
+            sections,
+            x,
+            y: min_section_y,
+            z,
+            ..Default::default()
+        };
+        heightmap::compute_heightmaps(&mut chunk);
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::logger::ServerLogger;
+
+    #[test]
+    fn chunks_are_filled_with_the_layers() {
+        LOGGER.get_or_init(ServerLogger::new);
+        let settings: FlatSettings = serde_json::from_str(
+            r#"{
+                "layers": [
+                    { "block": "minecraft:bedrock", "height": 1 },
+                    { "block": "minecraft:dirt", "height": 2 },
+                    { "block": "minecraft:grass_block", "height": 1 },
+                    { "block": "minecraft:not_a_block", "height": 3 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(settings.biome, "minecraft:plains");
+        assert!(settings.structure_sets().is_none());
+        let level = FlatLevel::new(&settings);
+        // The unknown layer on top is air, which is not stored
+        assert_eq!(level.blocks.len(), 4);
+        assert_eq!(
+            level.first_free_height(HeightmapType::WorldSurface, -64),
+            -60
+        );
+        assert_eq!(
+            FlatLevel::new(&FlatSettings::void())
+                .first_free_height(HeightmapType::WorldSurface, -64),
+            -64
+        );
+
+        let overworld = DimensionType {
+            height: 384,
+            logical_height: 384,
+            min_y: -64,
+            coordinate_scale: 1.0,
+            has_skylight: true,
+        };
+        let chunk = level.generate(LudiChunkLoader::pack_coords((3, -2)), &overworld);
+        assert_eq!((chunk.x, chunk.y, chunk.z), (3, -4, -2));
+        assert_eq!(chunk.sections.len(), 24);
+        assert_eq!(
+            chunk.get_block(5, -64, 9),
+            Some(BLOCKSTATE_MAPPINGS["bedrock"])
+        );
+        assert_eq!(
+            chunk.get_block(5, -62, 9),
+            Some(BLOCKSTATE_MAPPINGS["dirt"])
+        );
+        assert_eq!(
+            chunk.get_block(5, -61, 9),
+            Some(BLOCKSTATE_MAPPINGS["grass_block[snowy=false]"])
+        );
+        assert_eq!(chunk.get_block(5, -60, 9), Some(0));
+        // Sections above the layers hold a single block without data
+        assert!(chunk.sections[1..]
+            .iter()
+            .all(|section| section.block_states.data.is_none()));
+        let heights = Chunk::decode_heightmap(
+            chunk.heightmaps.get(HeightmapType::MotionBlocking).unwrap(),
+            chunk.world_height(),
+        );
+        assert_eq!(heights[15][0], 4);
+    }
+}
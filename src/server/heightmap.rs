@@ -0,0 +1,455 @@
+use super::chunk_system::{
+    block_state_property, split_block_state, Chunk, ChunkHeightmaps, BLOCKSTATES,
+};
+use super::tags::BlockSet;
+use once_cell::sync::Lazy;
+
+/// Heightmap relevant properties of every block state, indexed by block state id
+pub static HEIGHTMAP_BLOCKS: Lazy<Vec<HeightmapBlock>> = Lazy::new(|| {
+    let no_collision = BlockSet::from_ids(
+        &NO_COLLISION
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>(),
+    );
+    BLOCKSTATES
+        .iter()
+        .enumerate()
+        .map(|(id, state)| HeightmapBlock::from_state(state, no_collision.contains(id as u32)))
+        .collect()
+});
+
+/// Blocks without a collision shape, most are covered by vanilla block tags
+const NO_COLLISION: &[&str] = &[
+    "#minecraft:replaceable",
+    "#minecraft:small_flowers",
+    "#minecraft:crops",
+    "#minecraft:rails",
+    "#minecraft:buttons",
+    "#minecraft:pressure_plates",
+    "#minecraft:all_signs",
+    "#minecraft:banners",
+    "#minecraft:fire",
+    "#minecraft:cave_vines",
+    "#minecraft:corals",
+    "#minecraft:wall_corals",
+    "minecraft:oak_sapling",
+    "minecraft:spruce_sapling",
+    "minecraft:birch_sapling",
+    "minecraft:jungle_sapling",
+    "minecraft:acacia_sapling",
+    "minecraft:cherry_sapling",
+    "minecraft:dark_oak_sapling",
+    "minecraft:pale_oak_sapling",
+    "minecraft:mangrove_propagule",
+    "minecraft:bamboo_sapling",
+    "minecraft:torch",
+    "minecraft:wall_torch",
+    "minecraft:soul_torch",
+    "minecraft:soul_wall_torch",
+    "minecraft:redstone_torch",
+    "minecraft:redstone_wall_torch",
+    "minecraft:redstone_wire",
+    "minecraft:tripwire",
+    "minecraft:tripwire_hook",
+    "minecraft:lever",
+    "minecraft:sculk_vein",
+    "minecraft:nether_portal",
+    "minecraft:end_portal",
+    "minecraft:end_gateway",
+    "minecraft:cobweb",
+    "minecraft:brown_mushroom",
+    "minecraft:red_mushroom",
+    "minecraft:crimson_fungus",
+    "minecraft:warped_fungus",
+    "minecraft:kelp",
+    "minecraft:kelp_plant",
+    "minecraft:weeping_vines",
+    "minecraft:weeping_vines_plant",
+    "minecraft:twisting_vines",
+    "minecraft:twisting_vines_plant",
+    "minecraft:sugar_cane",
+    "minecraft:nether_wart",
+    "minecraft:attached_melon_stem",
+    "minecraft:attached_pumpkin_stem",
+    "minecraft:spore_blossom",
+    "minecraft:small_dripleaf",
+    "minecraft:big_dripleaf_stem",
+    "minecraft:sweet_berry_bush",
+    "minecraft:firefly_bush",
+    "minecraft:frogspawn",
+    "minecraft:pink_petals",
+    "minecraft:wildflowers",
+    "minecraft:cactus_flower",
+    "minecraft:pale_hanging_moss",
+    "minecraft:sunflower",
+    "minecraft:lilac",
+    "minecraft:rose_bush",
+    "minecraft:peony",
+    "minecraft:pitcher_plant",
+    "minecraft:dead_tube_coral",
+    "minecraft:dead_brain_coral",
+    "minecraft:dead_bubble_coral",
+    "minecraft:dead_fire_coral",
+    "minecraft:dead_horn_coral",
+    "minecraft:dead_tube_coral_fan",
+    "minecraft:dead_brain_coral_fan",
+    "minecraft:dead_bubble_coral_fan",
+    "minecraft:dead_fire_coral_fan",
+    "minecraft:dead_horn_coral_fan",
+    "minecraft:dead_tube_coral_wall_fan",
+    "minecraft:dead_brain_coral_wall_fan",
+    "minecraft:dead_bubble_coral_wall_fan",
+    "minecraft:dead_fire_coral_wall_fan",
+    "minecraft:dead_horn_coral_wall_fan",
+];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeightmapBlock {
+    pub is_air: bool,
+    /// Has a collision shape entities can stand on
+    pub blocks_motion: bool,
+    /// Is a fluid or contains one
+    pub has_fluid: bool,
+    pub is_leaves: bool,
+}
+
+impl HeightmapBlock {
+    /// Reads the heightmap properties off a block state, `no_collision` is whether the block is
+    /// in `NO_COLLISION`
+    fn from_state(state: &str, no_collision: bool) -> Self {
+        // Blocks that always hold water without a `waterlogged` property
+        const WATER_PLANTS: &[&str] = &[
+            "bubble_column",
+            "kelp",
+            "kelp_plant",
+            "seagrass",
+            "tall_seagrass",
+        ];
+        let (name, properties) = split_block_state(state);
+        let property = |key: &str| block_state_property(properties, key);
+        let is_air = matches!(name, "air" | "cave_air" | "void_air");
+        let is_fluid = matches!(name, "water" | "lava");
+        let has_fluid =
+            is_fluid || WATER_PLANTS.contains(&name) || property("waterlogged") == Some("true");
+        // Snow is replaceable, only its lowest layer has no collision
+        let no_collision = match name {
+            "snow" => property("layers") == Some("1"),
+            _ => no_collision,
+        };
+        Self {
+            is_air,
+            blocks_motion: !is_air && !is_fluid && !no_collision,
+            has_fluid,
+            // Only leaves remember whether they were placed by a player
+            is_leaves: property("persistent").is_some(),
+        }
+    }
+}
+
+/// Heightmaps stored with a chunk, each holds the height above the highest block that matches it
+#[derive(Clone, Copy, Debug)]
+pub enum HeightmapType {
+    WorldSurface,
+    OceanFloor,
+    MotionBlocking,
+    MotionBlockingNoLeaves,
+}
+
+impl HeightmapType {
+    pub const ALL: [HeightmapType; 4] = [
+        Self::WorldSurface,
+        Self::OceanFloor,
+        Self::MotionBlocking,
+        Self::MotionBlockingNoLeaves,
+    ];
+
+    /// Id of the heightmap in the chunk data packet, `None` for heightmaps only used by the
+    /// server
+    pub fn protocol_id(&self) -> Option<i32> {
+        match self {
+            Self::WorldSurface => Some(1),
+            Self::OceanFloor => None,
+            Self::MotionBlocking => Some(4),
+            Self::MotionBlockingNoLeaves => Some(5),
+        }
+    }
+
+    pub fn matches(&self, block: &HeightmapBlock) -> bool {
+        match self {
+            Self::WorldSurface => !block.is_air,
+            Self::OceanFloor => block.blocks_motion,
+            Self::MotionBlocking => block.blocks_motion || block.has_fluid,
+            Self::MotionBlockingNoLeaves => {
+                (block.blocks_motion || block.has_fluid) && !block.is_leaves
+            }
+        }
+    }
+}
+
+impl ChunkHeightmaps {
+    pub fn get(&self, heightmap: HeightmapType) -> Option<&fastnbt::LongArray> {
+        match heightmap {
+            HeightmapType::WorldSurface => self.world_surface.as_ref(),
+            HeightmapType::OceanFloor => self.ocean_floor.as_ref(),
+            HeightmapType::MotionBlocking => self.motion_blocking.as_ref(),
+            HeightmapType::MotionBlockingNoLeaves => self.motion_blocking_no_leaves.as_ref(),
+        }
+    }
+
+    pub fn get_mut(&mut self, heightmap: HeightmapType) -> &mut Option<fastnbt::LongArray> {
+        match heightmap {
+            HeightmapType::WorldSurface => &mut self.world_surface,
+            HeightmapType::OceanFloor => &mut self.ocean_floor,
+            HeightmapType::MotionBlocking => &mut self.motion_blocking,
+            HeightmapType::MotionBlockingNoLeaves => &mut self.motion_blocking_no_leaves,
+        }
+    }
+}
+
+fn heightmap_block(id: u32) -> HeightmapBlock {
+    HEIGHTMAP_BLOCKS
+        .get(id as usize)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Computes every heightmap of a chunk from its block states
+pub fn compute_heightmaps(chunk: &mut Chunk) {
+    let world_height = chunk.world_height();
+    let mut heights = [[[0i32; 16]; 16]; HeightmapType::ALL.len()];
+    let mut sections = chunk.sections.iter().collect::<Vec<_>>();
+    sections.sort_unstable_by_key(|section| std::cmp::Reverse(section.y));
+    for section in sections {
+        let base = (section.y as i32 - chunk.y as i32) * 16;
+        if base < 0 {
+            continue;
+        }
+        if section.block_states.data.is_none() {
+            // Every block of the section is the same
+            let block = heightmap_block(section.block_states.palette.first().map_or(0, |b| b.id));
+            for (i, heightmap) in HeightmapType::ALL.iter().enumerate() {
+                if heightmap.matches(&block) {
+                    for height in heights[i].iter_mut().flatten().filter(|h| **h == 0) {
+                        *height = base + 16;
+                    }
+                }
+            }
+            continue;
+        }
+        let block_ids = section.block_states.block_ids();
+        for y in (0..16).rev() {
+            for z in 0..16 {
+                for x in 0..16 {
+                    let block = heightmap_block(block_ids[y << 8 | z << 4 | x]);
+                    for (i, heightmap) in HeightmapType::ALL.iter().enumerate() {
+                        if heights[i][x][z] == 0 && heightmap.matches(&block) {
+                            heights[i][x][z] = base + y as i32 + 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for (i, heightmap) in HeightmapType::ALL.iter().enumerate() {
+        *chunk.heightmaps.get_mut(*heightmap) =
+            Some(Chunk::encode_heightmap(heights[i], world_height));
+    }
+}
+
+/// Updates the heightmaps of the column of a changed block, `y` is the world height
+pub fn update_heightmaps(chunk: &mut Chunk, x: usize, y: i32, z: usize) {
+    if HeightmapType::ALL
+        .iter()
+        .any(|heightmap| chunk.heightmaps.get(*heightmap).is_none())
+    {
+        compute_heightmaps(chunk);
+        return;
+    }
+    let world_height = chunk.world_height();
+    let min_y = chunk.y as i32 * 16;
+    let block = heightmap_block(chunk.get_block(x, y, z).unwrap_or(0));
+    for heightmap in HeightmapType::ALL {
+        let packed = chunk.heightmaps.get(heightmap).unwrap();
+        let mut heights = Chunk::decode_heightmap(packed, world_height);
+        let height = heights[x][z];
+        let new_height = if heightmap.matches(&block) {
+            height.max(y - min_y + 1)
+        } else if height == y - min_y + 1 {
+            // The highest block was removed, searches the next one below it
+            highest_match(chunk, heightmap, x, z, y - min_y)
+        } else {
+            height
+        };
+        if new_height != height {
+            heights[x][z] = new_height;
+            *chunk.heightmaps.get_mut(heightmap) =
+                Some(Chunk::encode_heightmap(heights, world_height));
+        }
+    }
+}
+
+/// Height above the highest block matching the heightmap in a column below `below`, both
+/// relative to the bottom of the chunk
+fn highest_match(chunk: &Chunk, heightmap: HeightmapType, x: usize, z: usize, below: i32) -> i32 {
+    let mut sections = chunk
+        .sections
+        .iter()
+        .filter(|section| section.y >= chunk.y)
+        .collect::<Vec<_>>();
+    sections.sort_unstable_by_key(|section| std::cmp::Reverse(section.y));
+    for section in sections {
+        let base = (section.y as i32 - chunk.y as i32) * 16;
+        if base >= below {
+            continue;
+        }
+        let block_ids = section.block_states.block_ids();
+        for y in (0..(below - base).min(16)).rev() {
+            let block = heightmap_block(block_ids[(y as usize) << 8 | z << 4 | x]);
+            if heightmap.matches(&block) {
+                return base + y + 1;
+            }
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::chunk_system::{ChunkBlockStates, ChunkSection, BLOCKSTATE_MAPPINGS};
+    use crate::server::logger::{ServerLogger, LOGGER};
+
+    fn block(state: &str) -> HeightmapBlock {
+        LOGGER.get_or_init(ServerLogger::new);
+        HEIGHTMAP_BLOCKS[BLOCKSTATE_MAPPINGS[state] as usize]
+    }
+
+    #[test]
+    fn blocks_are_classified_by_their_properties() {
+        let blocks_motion = |state: &str| block(state).blocks_motion;
+        assert!(blocks_motion("stone"));
+        assert!(blocks_motion(
+            "campfire[facing=north,lit=true,signal_fire=false,waterlogged=false]"
+        ));
+        assert!(blocks_motion("mangrove_roots[waterlogged=false]"));
+        assert!(blocks_motion("snow[layers=2]"));
+        assert!(!blocks_motion("snow[layers=1]"));
+        assert!(!blocks_motion("torch"));
+        assert!(!blocks_motion("water[level=0]"));
+        assert!(block("mangrove_roots[waterlogged=true]").has_fluid);
+        assert!(block("kelp[age=0]").has_fluid);
+        assert!(!block("light[level=15,waterlogged=false]").has_fluid);
+        assert!(block("oak_leaves[distance=7,persistent=false,waterlogged=false]").is_leaves);
+        assert!(!block("leaf_litter[facing=north,segment_amount=1]").is_leaves);
+    }
+
+    #[test]
+    fn heights_are_packed_per_column() {
+        let mut chunk = Chunk {
+            sections: (-4..20).map(ChunkSection::air).collect(),
+            y: -4,
+            ..Default::default()
+        };
+        let mut blocks = chunk
+            .sections
+            .iter()
+            .map(|section| section.block_states.block_ids())
+            .collect::<Vec<_>>();
+        let mut set = |x: usize, y: i32, z: usize, state: &str| {
+            let idx = (y.rem_euclid(16) as usize) << 8 | z << 4 | x;
+            blocks[(y.div_euclid(16) + 4) as usize][idx] = BLOCKSTATE_MAPPINGS[state];
+        };
+        for y in -64..=0 {
+            set(3, y, 5, "stone");
+        }
+        for y in 1..=4 {
+            set(3, y, 5, "water[level=0]");
+        }
+        set(
+            3,
+            10,
+            5,
+            "oak_leaves[distance=7,persistent=false,waterlogged=false]",
+        );
+        set(3, 12, 5, "torch");
+        set(
+            15,
+            -64,
+            15,
+            "campfire[facing=north,lit=true,signal_fire=false,waterlogged=false]",
+        );
+        for (section, block_ids) in chunk.sections.iter_mut().zip(blocks) {
+            section.block_states = ChunkBlockStates::from_block_ids(&block_ids);
+        }
+
+        compute_heightmaps(&mut chunk);
+        // 384 blocks high worlds use 9 bits per height, 7 heights per long
+        let height = |heightmap: HeightmapType, x: usize, z: usize| {
+            let packed = chunk.heightmaps.get(heightmap).unwrap();
+            assert_eq!(packed.len(), 37);
+            let index = z * 16 + x;
+            (packed[index / 7] as u64 >> (index % 7 * 9) & 0x1FF) as i32
+        };
+        assert_eq!(height(HeightmapType::WorldSurface, 3, 5), 77);
+        assert_eq!(height(HeightmapType::OceanFloor, 3, 5), 75);
+        assert_eq!(height(HeightmapType::MotionBlocking, 3, 5), 75);
+        assert_eq!(height(HeightmapType::MotionBlockingNoLeaves, 3, 5), 69);
+        assert_eq!(height(HeightmapType::OceanFloor, 15, 15), 1);
+        for heightmap in HeightmapType::ALL {
+            assert_eq!(height(heightmap, 4, 5), 0);
+        }
+    }
+
+    #[test]
+    fn block_changes_update_their_column() {
+        LOGGER.get_or_init(ServerLogger::new);
+        let mut chunk = Chunk {
+            sections: (-4..20).map(ChunkSection::air).collect(),
+            y: -4,
+            ..Default::default()
+        };
+        for y in -64..=0 {
+            chunk.set_block(3, y, 5, BLOCKSTATE_MAPPINGS["stone"]);
+        }
+        let height = |chunk: &Chunk, heightmap: HeightmapType| {
+            let packed = chunk.heightmaps.get(heightmap).unwrap();
+            Chunk::decode_heightmap(packed, chunk.world_height())[3][5]
+        };
+        let heights = |chunk: &Chunk| {
+            [
+                HeightmapType::WorldSurface,
+                HeightmapType::MotionBlocking,
+                HeightmapType::OceanFloor,
+            ]
+            .map(|heightmap| height(chunk, heightmap))
+        };
+        assert_eq!(heights(&chunk), [65, 65, 65]);
+
+        let water = BLOCKSTATE_MAPPINGS["water[level=0]"];
+        let torch = BLOCKSTATE_MAPPINGS["torch"];
+        let air = BLOCKSTATE_MAPPINGS["air"];
+        for y in 1..=3 {
+            chunk.set_block(3, y, 5, water);
+        }
+        assert_eq!(heights(&chunk), [68, 68, 65]);
+        chunk.set_block(3, 4, 5, torch);
+        assert_eq!(heights(&chunk), [69, 68, 65]);
+        // Removing the top block searches the column below it
+        chunk.set_block(3, 4, 5, air);
+        assert_eq!(heights(&chunk), [68, 68, 65]);
+        chunk.set_block(3, 3, 5, air);
+        assert_eq!(heights(&chunk), [67, 67, 65]);
+        chunk.set_block(3, 0, 5, air);
+        assert_eq!(heights(&chunk), [67, 67, 64]);
+        chunk.set_block(3, 2, 5, air);
+        chunk.set_block(3, 1, 5, air);
+        assert_eq!(heights(&chunk), [64, 64, 64]);
+        // The rest of the chunk is untouched
+        let world_surface = chunk.heightmaps.get(HeightmapType::WorldSurface).unwrap();
+        assert_eq!(
+            Chunk::decode_heightmap(world_surface, chunk.world_height())[4][5],
+            0
+        );
+    }
+}
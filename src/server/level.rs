@@ -1,8 +1,22 @@
+use super::biome_source::BiomeParameters;
+use super::dimension::Dimension;
+use super::features::region::WorldHeightmap;
+use super::flat::FlatSettings;
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use super::structures::jigsaw::TerrainHeights;
+use super::world_state::WORLD_STATES;
+use super::ServerProperties;
+use crate::{JAR_RESOURCES_DIR, MC_DATA_VERSION, MC_VERSION};
 use flate2::read::GzDecoder;
-use serde::Deserialize;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
+
+/// Chunks searched for a spawn surface around the origin, like vanilla's 11 by 11 spiral
+const SPAWN_SEARCH_DIAMETER: i32 = 11;
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -12,12 +26,21 @@ pub struct ServerLevel {
     pub spawn_y: i32,
     pub spawn_z: i32,
     pub spawn_angle: f32,
+    /// Dimensions of a world created by this run, written to `level.dat` once its spawn is chosen
+    #[serde(skip)]
+    new_dimensions: Option<serde_json::Value>,
 }
 
 impl ServerLevel {
-    pub fn new(level_name: &str) -> Self {
-        let level_file = File::open(format!("{level_name}/level.dat"))
-            .expect("level.dat is missing in world file");
+    /// Reads `level.dat`, a new world is prepared from the `level-seed` and `level-type`
+    /// properties when there is none
+    pub fn new(config: &ServerProperties) -> Self {
+        let Ok(level_file) = File::open(format!("{}/level.dat", config.level_name)) else {
+            return Self::create(
+                config,
+                &format!("{JAR_RESOURCES_DIR}/worldgen/world_preset"),
+            );
+        };
 
         let mut decoder = GzDecoder::new(level_file);
         let mut level_data = Vec::new();
@@ -26,6 +49,223 @@ impl ServerLevel {
             fastnbt::from_bytes::<LevelDataNBTContainer>(&level_data).expect("Invalid level.dat");
         fastnbt::from_value(&data_container.data).expect("Invalid level.dat")
     }
+
+    fn create(config: &ServerProperties, presets_dir: &str) -> Self {
+        LOGGER.get().unwrap().println_as(
+            &format!(
+                "No level.dat found, creating a new {} world",
+                config.level_type
+            ),
+            LogDomain::Server,
+            LogLevel::Warn,
+        );
+        let dimensions = world_preset_dimensions(presets_dir, &config.level_type);
+        ServerLevel {
+            world_gen_settings: WorldGenSettings {
+                dimensions: serde_json::from_value(dimensions.clone())
+                    .expect("Unsupported world preset"),
+                seed: parse_seed(&config.level_seed),
+                generate_features: true,
+            },
+            spawn_x: 0,
+            spawn_y: 64,
+            spawn_z: 0,
+            spawn_angle: 0.0,
+            new_dimensions: Some(dimensions),
+        }
+    }
+
+    /// Chooses the spawn of a world created by this run and writes its `level.dat`, must be
+    /// called once the world states are initialized. Loaded worlds are left untouched
+    pub fn initialize_new(&mut self, level_name: &str) {
+        let Some(dimensions) = self.new_dimensions.take() else {
+            return;
+        };
+        (self.spawn_x, self.spawn_y, self.spawn_z) = find_spawn();
+        LOGGER.get().unwrap().println(&format!(
+            "Spawn set to {} {} {}",
+            self.spawn_x, self.spawn_y, self.spawn_z
+        ));
+        self.write_new(level_name, dimensions);
+    }
+
+    /// Writes the `level.dat` of a new world in the layout vanilla reads
+    fn write_new(&self, level_name: &str, dimensions: serde_json::Value) {
+        let level_file = NewLevelFile {
+            data: NewLevelData {
+                data_version: MC_DATA_VERSION,
+                version: NewLevelVersion {
+                    id: MC_DATA_VERSION,
+                    name: MC_VERSION,
+                    series: "main",
+                    snapshot: false,
+                },
+                level_name,
+                storage_version: 19133,
+                initialized: true,
+                game_type: 0,
+                spawn_x: self.spawn_x,
+                spawn_y: self.spawn_y,
+                spawn_z: self.spawn_z,
+                spawn_angle: self.spawn_angle,
+                world_gen_settings: NewWorldGenSettings {
+                    seed: self.world_gen_settings.seed,
+                    generate_features: self.world_gen_settings.generate_features,
+                    bonus_chest: false,
+                    dimensions,
+                },
+            },
+        };
+        std::fs::create_dir_all(level_name).expect("world folder");
+        let mut encoder = GzEncoder::new(
+            File::create(format!("{level_name}/level.dat")).expect("level.dat file"),
+            Compression::default(),
+        );
+        encoder
+            .write_all(&fastnbt::to_bytes(&level_file).expect("level.dat data"))
+            .expect("level.dat file");
+        encoder.finish().expect("level.dat file");
+    }
+}
+
+/// Dimensions of a `world_preset` from `presets_dir`, the jar's `worldgen/world_preset`.
+/// `level-type` takes the preset name with or without namespace. The `void` type is the normal preset with an empty overworld
+fn world_preset_dimensions(presets_dir: &str, level_type: &str) -> serde_json::Value {
+    #[derive(Deserialize)]
+    struct WorldPreset {
+        dimensions: serde_json::Value,
+    }
+    let read_preset = |name: &str| {
+        std::fs::read_to_string(format!("{presets_dir}/{name}.json"))
+            .ok()
+            .and_then(|data| serde_json::from_str::<WorldPreset>(&data).ok())
+            .map(|preset| preset.dimensions)
+    };
+    let name = match level_type
+        .trim_start_matches("minecraft:")
+        .to_lowercase()
+        .as_str()
+    {
+        "default" => "normal".to_owned(),
+        "largebiomes" => "large_biomes".to_owned(),
+        name => name.to_owned(),
+    };
+    if name == "void" {
+        let mut dimensions = read_preset("normal").expect("normal world preset");
+        dimensions["minecraft:overworld"]["generator"] = serde_json::json!({
+            "type": "minecraft:flat",
+            "settings": FlatSettings::void(),
+        });
+        return dimensions;
+    }
+    read_preset(&name).unwrap_or_else(|| {
+        LOGGER.get().unwrap().println_as(
+            &format!("Unknown level type {level_type}, using the normal world preset"),
+            LogDomain::Server,
+            LogLevel::Warn,
+        );
+        read_preset("normal").expect("normal world preset")
+    })
+}
+
+/// Seed of a new world like vanilla's `WorldOptions.parseSeed`, random when empty and the java
+/// string hash of text that is not a number
+fn parse_seed(level_seed: &str) -> i64 {
+    let level_seed = level_seed.trim();
+    if level_seed.is_empty() {
+        return rand::random();
+    }
+    level_seed.parse().unwrap_or_else(|_| {
+        level_seed
+            .encode_utf16()
+            .fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32)) as i64
+    })
+}
+
+/// First dry surface of the overworld in a spiral of chunks around the origin, the top of the
+/// column at the origin when none is found
+fn find_spawn() -> (i32, i32, i32) {
+    let dimension = Dimension::Overworld.name();
+    let world_state = &WORLD_STATES.get().unwrap()[dimension];
+    let mut heights = TerrainHeights::new(world_state, dimension);
+    spiral_spawn(
+        world_state.settings.noise.min_y,
+        world_state.settings.sea_level,
+        |x, z| {
+            (
+                heights.first_free_height(x, z, WorldHeightmap::MotionBlocking),
+                heights.first_free_height(x, z, WorldHeightmap::OceanFloorWg),
+            )
+        },
+    )
+}
+
+/// Searches the spawn of `find_spawn`, `heights` gives the surface and ocean floor of a column
+fn spiral_spawn(
+    min_y: i32,
+    sea_level: i32,
+    mut heights: impl FnMut(i32, i32) -> (i32, i32),
+) -> (i32, i32, i32) {
+    let (mut chunk_x, mut chunk_z, mut step_x, mut step_z) = (0, 0, 0, -1);
+    for _ in 0..SPAWN_SEARCH_DIAMETER * SPAWN_SEARCH_DIAMETER {
+        let (x, z) = (chunk_x * 16 + 8, chunk_z * 16 + 8);
+        let (surface, floor) = heights(x, z);
+        // Water lies above the ocean floor
+        if surface == floor && surface > min_y && surface >= sea_level {
+            return (x, surface, z);
+        }
+        if chunk_x == chunk_z
+            || (chunk_x < 0 && chunk_x == -chunk_z)
+            || (chunk_x > 0 && chunk_x == 1 - chunk_z)
+        {
+            (step_x, step_z) = (-step_z, step_x);
+        }
+        chunk_x += step_x;
+        chunk_z += step_z;
+    }
+    let (surface, _) = heights(8, 8);
+    (8, surface.max(sea_level + 1), 8)
+}
+
+#[derive(Serialize)]
+struct NewLevelFile<'a> {
+    #[serde(rename = "Data")]
+    data: NewLevelData<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct NewLevelData<'a> {
+    data_version: i32,
+    version: NewLevelVersion,
+    level_name: &'a str,
+    #[serde(rename = "version")]
+    storage_version: i32,
+    #[serde(rename = "initialized")]
+    initialized: bool,
+    game_type: i32,
+    spawn_x: i32,
+    spawn_y: i32,
+    spawn_z: i32,
+    spawn_angle: f32,
+    world_gen_settings: NewWorldGenSettings,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct NewLevelVersion {
+    id: i32,
+    name: &'static str,
+    series: &'static str,
+    snapshot: bool,
+}
+
+#[derive(Serialize)]
+struct NewWorldGenSettings {
+    seed: i64,
+    generate_features: bool,
+    bonus_chest: bool,
+    dimensions: serde_json::Value,
 }
 
 #[derive(Deserialize)]
@@ -38,6 +278,13 @@ pub struct LevelDataNBTContainer {
 pub struct WorldGenSettings {
     pub dimensions: HashMap<String, WorldDimension>,
     pub seed: i64,
+    /// Whether structures are generated
+    #[serde(default = "default_true")]
+    pub generate_features: bool,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Deserialize, Debug)]
@@ -49,15 +296,234 @@ pub struct WorldDimension {
 
 #[derive(Deserialize, Debug)]
 pub struct WorldGenerator {
-    pub biome_source: WorldBiomeSource,
-    pub settings: String,
+    /// Biome source of a `noise` generator, flat generators place a single biome
+    pub biome_source: Option<WorldBiomeSource>,
+    pub settings: GeneratorSettings,
     #[serde(rename = "type")]
     pub noise_type: String,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum GeneratorSettings {
+    /// Id of the noise settings of a `noise` generator
+    Noise(String),
+    Flat(FlatSettings),
+}
+
 #[derive(Deserialize, Debug)]
 pub struct WorldBiomeSource {
     pub preset: Option<String>,
+    /// Inline parameter list of a `multi_noise` source used instead of a preset
+    pub biomes: Option<Vec<BiomeParameters>>,
+    /// Biome of a `fixed` source
+    pub biome: Option<String>,
     #[serde(rename = "type")]
     pub noise_type: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::logger::ServerLogger;
+    use super::*;
+
+    #[test]
+    fn seeds_are_numbers_or_java_string_hashes() {
+        assert_eq!(parse_seed("12345"), 12345);
+        assert_eq!(parse_seed(" -9223372036854775808 "), i64::MIN);
+        assert_eq!(parse_seed("hello"), 99162322);
+        // The hash wraps around like a java int
+        assert_eq!(parse_seed("Minecraft"), -1595926131);
+        // Blank seeds take the random path instead of being hashed like text
+        let blank = (0..4)
+            .map(|_| parse_seed(" \t "))
+            .collect::<std::collections::HashSet<_>>();
+        assert!(blank.len() > 1);
+    }
+
+    /// Presets in the layout of the jar's `worldgen/world_preset`, with only the parts the
+    /// server reads
+    fn write_presets(dir: &std::path::Path) {
+        let preset = |overworld_settings: &str| {
+            serde_json::json!({"dimensions": {
+                "minecraft:overworld": {
+                    "type": "minecraft:overworld",
+                    "generator": {
+                        "type": "minecraft:noise",
+                        "settings": overworld_settings,
+                        "biome_source": {
+                            "type": "minecraft:multi_noise",
+                            "preset": "minecraft:overworld"
+                        }
+                    }
+                },
+                "minecraft:the_nether": {
+                    "type": "minecraft:the_nether",
+                    "generator": {
+                        "type": "minecraft:noise",
+                        "settings": "minecraft:nether",
+                        "biome_source": {
+                            "type": "minecraft:multi_noise",
+                            "preset": "minecraft:nether"
+                        }
+                    }
+                }
+            }})
+            .to_string()
+        };
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("normal.json"), preset("minecraft:overworld")).unwrap();
+        std::fs::write(
+            dir.join("large_biomes.json"),
+            preset("minecraft:large_biomes"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn level_types_map_to_world_presets() {
+        LOGGER.get_or_init(ServerLogger::new);
+        let dir = std::env::temp_dir().join(format!("ludicrous-presets-{}", std::process::id()));
+        write_presets(&dir);
+        let presets = dir.to_str().unwrap();
+        let overworld = |level_type: &str| {
+            world_preset_dimensions(presets, level_type)["minecraft:overworld"]["generator"].clone()
+        };
+        let normal = world_preset_dimensions(presets, "normal");
+        assert_eq!(
+            normal["minecraft:overworld"]["generator"]["settings"],
+            "minecraft:overworld"
+        );
+        assert_eq!(world_preset_dimensions(presets, "default"), normal);
+        assert_eq!(
+            overworld("largebiomes")["settings"],
+            "minecraft:large_biomes"
+        );
+        assert_eq!(
+            overworld("minecraft:LARGE_BIOMES")["settings"],
+            "minecraft:large_biomes"
+        );
+
+        // The void type only empties the overworld
+        let void = world_preset_dimensions(presets, "void");
+        assert_eq!(
+            void["minecraft:overworld"]["generator"]["type"],
+            "minecraft:flat"
+        );
+        let settings = &void["minecraft:overworld"]["generator"]["settings"];
+        assert_eq!(settings["layers"], serde_json::json!([]));
+        assert_eq!(settings["biome"], "minecraft:the_void");
+        assert_eq!(void["minecraft:the_nether"], normal["minecraft:the_nether"]);
+        // Unknown types fall back to the normal preset
+        assert_eq!(world_preset_dimensions(presets, "skyblock"), normal);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn new_worlds_are_saved_and_read_back() {
+        LOGGER.get_or_init(ServerLogger::new);
+        let dir = std::env::temp_dir().join(format!("ludicrous-level-{}", std::process::id()));
+        write_presets(&dir.join("presets"));
+        let config = ServerProperties {
+            level_name: dir.join("world").to_str().unwrap().to_string(),
+            level_seed: "hello".to_string(),
+            level_type: "largebiomes".to_string(),
+            ..ServerProperties::default()
+        };
+        let mut level = ServerLevel::create(&config, dir.join("presets").to_str().unwrap());
+        assert_eq!(level.world_gen_settings.seed, 99162322);
+        assert_eq!((level.spawn_x, level.spawn_y, level.spawn_z), (0, 64, 0));
+        (level.spawn_x, level.spawn_y, level.spawn_z) = (24, 71, -40);
+        let dimensions = level.new_dimensions.take().unwrap();
+        level.write_new(&config.level_name, dimensions);
+
+        let mut data = Vec::new();
+        GzDecoder::new(File::open(dir.join("world/level.dat")).unwrap())
+            .read_to_end(&mut data)
+            .unwrap();
+        let fastnbt::Value::Compound(root) = fastnbt::from_bytes(&data).unwrap() else {
+            panic!("level.dat is not a compound");
+        };
+        let Some(fastnbt::Value::Compound(level_data)) = root.get("Data") else {
+            panic!("level.dat has no Data compound");
+        };
+        assert_eq!(
+            level_data["DataVersion"],
+            fastnbt::Value::Int(MC_DATA_VERSION)
+        );
+        // Vanilla's storage version and the game version are separate keys
+        assert_eq!(level_data["version"], fastnbt::Value::Int(19133));
+        let fastnbt::Value::Compound(version) = &level_data["Version"] else {
+            panic!("Version is not a compound");
+        };
+        assert_eq!(
+            version["Name"],
+            fastnbt::Value::String(MC_VERSION.to_string())
+        );
+        assert_eq!(version["Id"], fastnbt::Value::Int(MC_DATA_VERSION));
+        assert_eq!(level_data["initialized"], fastnbt::Value::Byte(1));
+        assert_eq!(level_data["SpawnZ"], fastnbt::Value::Int(-40));
+        let fastnbt::Value::Compound(settings) = &level_data["WorldGenSettings"] else {
+            panic!("WorldGenSettings is not a compound");
+        };
+        assert_eq!(settings["seed"], fastnbt::Value::Long(99162322));
+
+        let mut loaded = ServerLevel::new(&config);
+        assert_eq!(loaded.world_gen_settings.seed, 99162322);
+        assert_eq!(
+            (loaded.spawn_x, loaded.spawn_y, loaded.spawn_z),
+            (24, 71, -40)
+        );
+        let GeneratorSettings::Noise(overworld) = &loaded.world_gen_settings.dimensions
+            ["minecraft:overworld"]
+            .generator
+            .settings
+        else {
+            panic!("The overworld is not a noise generator");
+        };
+        assert_eq!(overworld, "minecraft:large_biomes");
+        // A loaded world is not written again
+        assert!(loaded.new_dimensions.is_none());
+        loaded.initialize_new(&config.level_name);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn spawn_is_the_first_dry_column_of_the_spiral() {
+        let (min_y, sea_level) = (-64, 63);
+        let mut visited = Vec::new();
+        let spawn = spiral_spawn(min_y, sea_level, |x, z| {
+            visited.push((x >> 4, z >> 4));
+            (sea_level, 40)
+        });
+        // Nothing dry, the origin column is raised above the sea
+        assert_eq!(spawn, (8, 64, 8));
+        assert_eq!(visited.len(), 11 * 11 + 1);
+        // Vanilla's spiral order
+        assert_eq!(
+            visited[..10],
+            [
+                (0, 0),
+                (1, 0),
+                (1, 1),
+                (0, 1),
+                (-1, 1),
+                (-1, 0),
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (2, -1)
+            ]
+        );
+        let spawn = spiral_spawn(min_y, sea_level, |x, z| match (x >> 4, z >> 4) {
+            (2, -1) | (-1, 0) => (80, 80),
+            // A dry floor below the sea level is not a spawn
+            (1, 0) => (50, 50),
+            // Nor is the void
+            (1, 1) => (min_y, min_y),
+            (0, 1) => (64, 64),
+            _ => (sea_level, 40),
+        });
+        assert_eq!(spawn, (8, 64, 24));
+    }
+}
@@ -0,0 +1,704 @@
+use super::chunk_system::{
+    block_name_matches, block_state_property, split_block_state, Chunk, BLOCKSTATES,
+};
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+
+/// Light properties of every block state, indexed by block state id
+pub static LIGHT_PROPERTIES: Lazy<Vec<LightProperties>> = Lazy::new(|| {
+    BLOCKSTATES
+        .iter()
+        .map(|state| LightProperties::from_state(state))
+        .collect()
+});
+
+/// Neighbouring chunks used to carry light across chunk borders, in the order -x, +x, -z, +z
+pub type ChunkNeighbors<'a> = [Option<&'a Chunk>; 4];
+
+const MAX_LIGHT: u8 = 15;
+const SECTION_BLOCKS: usize = 4096;
+
+/// Light emitted and absorbed by a block state
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LightProperties {
+    /// Block light level emitted by the block
+    pub emission: u8,
+    /// Light levels lost passing through the block, 15 for blocks that stop light
+    pub opacity: u8,
+}
+
+impl LightProperties {
+    /// Reads emission and opacity off a block state such as `redstone_torch[lit=true]`
+    fn from_state(state: &str) -> Self {
+        let (name, properties) = split_block_state(state);
+        let property = |key: &str| block_state_property(properties, key);
+        Self {
+            emission: Self::emission(name, &property),
+            opacity: Self::opacity(name, &property),
+        }
+    }
+
+    fn emission<'a>(name: &str, property: &impl Fn(&str) -> Option<&'a str>) -> u8 {
+        let lit = property("lit") == Some("true");
+        let count = |key: &str| {
+            property(key)
+                .and_then(|v| v.parse::<u8>().ok())
+                .unwrap_or(0)
+        };
+        match name {
+            "light" => count("level"),
+            "lava"
+            | "fire"
+            | "glowstone"
+            | "sea_lantern"
+            | "jack_o_lantern"
+            | "beacon"
+            | "conduit"
+            | "end_gateway"
+            | "end_portal"
+            | "lantern"
+            | "shroomlight"
+            | "ochre_froglight"
+            | "verdant_froglight"
+            | "pearlescent_froglight" => 15,
+            "redstone_lamp" | "campfire" | "copper_bulb" | "waxed_copper_bulb" if lit => 15,
+            "exposed_copper_bulb" | "waxed_exposed_copper_bulb" if lit => 12,
+            "weathered_copper_bulb" | "waxed_weathered_copper_bulb" if lit => 8,
+            "oxidized_copper_bulb" | "waxed_oxidized_copper_bulb" if lit => 4,
+            "torch" | "wall_torch" | "end_rod" => 14,
+            "cave_vines" | "cave_vines_plant" if property("berries") == Some("true") => 14,
+            "furnace" | "blast_furnace" | "smoker" if lit => 13,
+            "nether_portal" => 11,
+            "soul_torch" | "soul_wall_torch" | "soul_lantern" | "soul_fire" | "crying_obsidian" => {
+                10
+            }
+            "soul_campfire" if lit => 10,
+            "redstone_ore" | "deepslate_redstone_ore" if lit => 9,
+            "redstone_torch" | "redstone_wall_torch" if lit => 7,
+            "enchanting_table" | "ender_chest" | "glow_lichen" => 7,
+            "sculk_catalyst" => 6,
+            "amethyst_cluster" => 5,
+            "large_amethyst_bud" => 4,
+            "magma_block" => 3,
+            "medium_amethyst_bud" => 2,
+            "small_amethyst_bud"
+            | "brewing_stand"
+            | "brown_mushroom"
+            | "dragon_egg"
+            | "end_portal_frame"
+            | "sculk_sensor"
+            | "calibrated_sculk_sensor" => 1,
+            "sea_pickle" if property("waterlogged") == Some("true") => (count("pickles") + 1) * 3,
+            "respawn_anchor" => (count("charges") * 4).saturating_sub(1),
+            _ if name.ends_with("candle") && lit => count("candles") * 3,
+            _ => 0,
+        }
+    }
+
+    fn opacity<'a>(name: &str, property: &impl Fn(&str) -> Option<&'a str>) -> u8 {
+        // Parts of block names that are not full blocks
+        const TRANSPARENT: &[&str] = &[
+            "air",
+            "glass",
+            "torch",
+            "sapling",
+            "fence",
+            "_wall",
+            "door",
+            "stairs",
+            "pane",
+            "bars",
+            "rail",
+            "button",
+            "pressure_plate",
+            "sign",
+            "banner",
+            "carpet",
+            "chain",
+            "ladder",
+            "vine",
+            "vein",
+            "lever",
+            "tripwire",
+            "redstone_wire",
+            "repeater",
+            "comparator",
+            "detector",
+            "candle",
+            "cake",
+            "_head",
+            "skull",
+            "pot",
+            "chest",
+            "fire",
+            "portal",
+            "end_rod",
+            "lightning_rod",
+            "scaffolding",
+            "campfire",
+            "anvil",
+            "bell",
+            "grindstone",
+            "stonecutter",
+            "hopper",
+            "cauldron",
+            "brewing_stand",
+            "enchanting_table",
+            "lectern",
+            "cactus",
+            "sugar_cane",
+            "bamboo",
+            "dripleaf",
+            "roots",
+            "sprouts",
+            "lichen",
+            "amethyst_cluster",
+            "_bud",
+            "pointed_dripstone",
+            "spawner",
+            "sensor",
+            "shrieker",
+            "mushroom",
+            "grass",
+            "coral",
+            "bush",
+            "litter",
+            "pitcher",
+            "propagule",
+            "_egg",
+            "hanging_moss",
+            "eyeblossom",
+        ];
+        const TRANSPARENT_BLOCKS: &[&str] = &[
+            "light",
+            "barrier",
+            "structure_void",
+            "lantern",
+            "soul_lantern",
+            "beacon",
+            "conduit",
+            "fern",
+            "large_fern",
+            "kelp",
+            "kelp_plant",
+            "wheat",
+            "carrots",
+            "potatoes",
+            "beetroots",
+            "nether_wart",
+            "sea_pickle",
+            "azalea",
+            "flowering_azalea",
+            "snow",
+            "cocoa",
+            "frogspawn",
+            "lily_pad",
+            "spore_blossom",
+            "dandelion",
+            "poppy",
+            "blue_orchid",
+            "allium",
+            "azure_bluet",
+            "red_tulip",
+            "orange_tulip",
+            "white_tulip",
+            "pink_tulip",
+            "oxeye_daisy",
+            "cornflower",
+            "lily_of_the_valley",
+            "wither_rose",
+            "sunflower",
+            "lilac",
+            "peony",
+            "pink_petals",
+            "wildflowers",
+            "chorus_plant",
+            "chorus_flower",
+        ];
+        const FILTERING: &[&str] = &[
+            "water",
+            "bubble_column",
+            "ice",
+            "frosted_ice",
+            "cobweb",
+            "slime_block",
+            "honey_block",
+        ];
+        if name == "tinted_glass" || name == "mushroom_stem" || name.ends_with("_block") {
+            return if FILTERING.contains(&name) {
+                1
+            } else {
+                MAX_LIGHT
+            };
+        }
+        if FILTERING.contains(&name) || name.ends_with("_leaves") || name == "lava" {
+            return 1;
+        }
+        if name.ends_with("_slab") {
+            return if property("type") == Some("double") {
+                MAX_LIGHT
+            } else {
+                0
+            };
+        }
+        let transparent =
+            name.ends_with("_bed") || block_name_matches(name, TRANSPARENT_BLOCKS, TRANSPARENT);
+        match transparent {
+            true if property("waterlogged") == Some("true") => 1,
+            true => 0,
+            false => MAX_LIGHT,
+        }
+    }
+}
+
+/// Unpacked blocks and light of a chunk, indexed by `y << 8 | z << 4 | x` with y relative to
+/// the bottom of the chunk
+///
+/// Light leaving the chunk is not written to its neighbours, neighbours only seed light into
+/// the chunk. Chunks are lit again from their neighbours once those are available
+pub struct ChunkLight {
+    /// Lowest section y of the chunk
+    min_section: i32,
+    has_skylight: bool,
+    opacity: Vec<u8>,
+    emission: Vec<u8>,
+    sky: Vec<u8>,
+    block: Vec<u8>,
+}
+
+impl ChunkLight {
+    /// Reads the blocks and stored light of a chunk
+    pub fn new(chunk: &Chunk, has_skylight: bool) -> Self {
+        let min_section = chunk.y as i32;
+        let section_count = chunk
+            .sections
+            .iter()
+            .map(|section| section.y as i32 - min_section + 1)
+            .max()
+            .unwrap_or(0)
+            .max(0) as usize;
+        let len = section_count * SECTION_BLOCKS;
+        let mut light = Self {
+            min_section,
+            has_skylight,
+            opacity: vec![0; len],
+            emission: vec![0; len],
+            sky: vec![0; len],
+            block: vec![0; len],
+        };
+        for section in chunk.sections.iter() {
+            let Some(start) = light.section_start(section.y) else {
+                continue;
+            };
+            let range = start..start + SECTION_BLOCKS;
+            for (i, id) in section.block_states.block_ids().into_iter().enumerate() {
+                let properties = LIGHT_PROPERTIES
+                    .get(id as usize)
+                    .copied()
+                    .unwrap_or_default();
+                light.opacity[start + i] = properties.opacity;
+                light.emission[start + i] = properties.emission;
+            }
+            if let Some(sky_light) = &section.sky_light {
+                Self::unpack_nibbles(sky_light, &mut light.sky[range.clone()]);
+            }
+            if let Some(block_light) = &section.block_light {
+                Self::unpack_nibbles(block_light, &mut light.block[range]);
+            }
+        }
+        light
+    }
+
+    /// Lights the chunk from scratch, discarding the stored light
+    pub fn light_all(&mut self, neighbors: &ChunkNeighbors) {
+        self.sky.fill(0);
+        self.block.fill(0);
+        let mut queue = VecDeque::new();
+        if self.has_skylight {
+            self.seed_sky(&mut queue);
+            self.seed_borders(true, neighbors, &mut queue);
+            Self::propagate(&mut self.sky, &self.opacity, true, queue);
+        }
+        let mut queue = VecDeque::new();
+        for (i, emission) in self.emission.iter().enumerate() {
+            if *emission > 0 {
+                self.block[i] = *emission;
+                queue.push_back(i);
+            }
+        }
+        self.seed_borders(false, neighbors, &mut queue);
+        Self::propagate(&mut self.block, &self.opacity, false, queue);
+    }
+
+    /// Spreads light coming from neighbouring chunks into the chunk, keeping the stored light
+    pub fn light_borders(&mut self, neighbors: &ChunkNeighbors) {
+        if self.has_skylight {
+            let mut queue = VecDeque::new();
+            self.seed_borders(true, neighbors, &mut queue);
+            Self::propagate(&mut self.sky, &self.opacity, true, queue);
+        }
+        let mut queue = VecDeque::new();
+        self.seed_borders(false, neighbors, &mut queue);
+        Self::propagate(&mut self.block, &self.opacity, false, queue);
+    }
+
+    /// Relights the blocks affected by a block change at a position relative to the chunk, the
+    /// light has to be read after the new block state was set in the chunk
+    pub fn update_block(&mut self, neighbors: &ChunkNeighbors, x: usize, y: i32, z: usize) {
+        let Some(start) = self.section_start(y.div_euclid(16) as i8) else {
+            return;
+        };
+        let idx = start + ((y.rem_euclid(16) as usize) << 8 | z << 4 | x);
+        let (opacity, emission) = (self.opacity[idx], self.emission[idx]);
+        if self.has_skylight {
+            let mut queue = self.remove_light(true, idx);
+            if idx + 256 >= self.sky.len() {
+                // Sunlight enters the top of the chunk from above
+                let level = Self::spread_level(MAX_LIGHT, opacity, true);
+                if level > self.sky[idx] {
+                    self.sky[idx] = level;
+                    queue.push_back(idx);
+                }
+            }
+            self.seed_borders(true, neighbors, &mut queue);
+            Self::propagate(&mut self.sky, &self.opacity, true, queue);
+        }
+        let mut queue = self.remove_light(false, idx);
+        if emission > self.block[idx] {
+            self.block[idx] = emission;
+            queue.push_back(idx);
+        }
+        self.seed_borders(false, neighbors, &mut queue);
+        Self::propagate(&mut self.block, &self.opacity, false, queue);
+    }
+
+    /// Writes the light back to the chunk sections, sections without block light store none
+    pub fn store(&self, chunk: &mut Chunk) {
+        for section in chunk.sections.iter_mut() {
+            let Some(start) = self.section_start(section.y) else {
+                continue;
+            };
+            let range = start..start + SECTION_BLOCKS;
+            section.sky_light = self
+                .has_skylight
+                .then(|| Self::pack_nibbles(&self.sky[range.clone()]));
+            let block_light = &self.block[range];
+            section.block_light = block_light
+                .iter()
+                .any(|level| *level > 0)
+                .then(|| Self::pack_nibbles(block_light));
+        }
+        chunk.is_light_on = true;
+    }
+
+    fn section_start(&self, section_y: i8) -> Option<usize> {
+        let section = section_y as i32 - self.min_section;
+        let start = usize::try_from(section).ok()? * SECTION_BLOCKS;
+        (start < self.opacity.len()).then_some(start)
+    }
+
+    /// Lights each column from the top down to the first block that stops light, the queue gets
+    /// every cell that can spread light sideways
+    fn seed_sky(&mut self, queue: &mut VecDeque<usize>) {
+        let height = self.sky.len() / 256;
+        // Lowest y still in full sunlight of each column
+        let mut sunlit = [[height; 16]; 16];
+        for z in 0..16 {
+            for x in 0..16 {
+                let mut level = MAX_LIGHT;
+                for y in (0..height).rev() {
+                    let idx = y << 8 | z << 4 | x;
+                    level = Self::spread_level(level, self.opacity[idx], true);
+                    if level == 0 {
+                        break;
+                    }
+                    self.sky[idx] = level;
+                    if level == MAX_LIGHT {
+                        sunlit[x][z] = y;
+                    }
+                }
+            }
+        }
+        for z in 0..16 {
+            for x in 0..16 {
+                // Sunlit cells only spread sideways where a neighbouring column is darker
+                let neighbor_sunlit = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                    .iter()
+                    .filter_map(|(dx, dz)| {
+                        let nx = usize::try_from(x as i32 + dx).ok().filter(|v| *v < 16)?;
+                        let nz = usize::try_from(z as i32 + dz).ok().filter(|v| *v < 16)?;
+                        Some(sunlit[nx][nz])
+                    })
+                    .max()
+                    .unwrap_or(0);
+                for y in (0..height).rev() {
+                    let idx = y << 8 | z << 4 | x;
+                    if self.sky[idx] == 0 {
+                        break;
+                    }
+                    if self.sky[idx] < MAX_LIGHT || y < neighbor_sunlit {
+                        queue.push_back(idx);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Raises the light on the chunk edges to the light of the adjacent neighbour blocks
+    fn seed_borders(&mut self, sky: bool, neighbors: &ChunkNeighbors, queue: &mut VecDeque<usize>) {
+        for (side, neighbor) in neighbors.iter().enumerate() {
+            let Some(neighbor) = neighbor else {
+                continue;
+            };
+            for section in neighbor.sections.iter() {
+                let Some(start) = self.section_start(section.y) else {
+                    continue;
+                };
+                let stored = if sky {
+                    &section.sky_light
+                } else {
+                    &section.block_light
+                };
+                let Some(stored) = stored else {
+                    continue;
+                };
+                for y in 0..16 {
+                    for t in 0..16 {
+                        // Position on the edge of this chunk and of the neighbour
+                        let ((x, z), (nx, nz)) = match side {
+                            0 => ((0, t), (15, t)),
+                            1 => ((15, t), (0, t)),
+                            2 => ((t, 0), (t, 15)),
+                            _ => ((t, 15), (t, 0)),
+                        };
+                        let neighbor_level = Self::nibble(stored, y << 8 | nz << 4 | nx);
+                        let idx = start + (y << 8 | z << 4 | x);
+                        let level = Self::spread_level(neighbor_level, self.opacity[idx], false);
+                        let light = if sky { &mut self.sky } else { &mut self.block };
+                        if level > light[idx] {
+                            light[idx] = level;
+                            queue.push_back(idx);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Darkens every block lit through `idx`, returns the blocks lit by other sources that have
+    /// to spread their light again
+    fn remove_light(&mut self, sky: bool, idx: usize) -> VecDeque<usize> {
+        let light = if sky { &mut self.sky } else { &mut self.block };
+        let mut removal = VecDeque::from([(idx, light[idx])]);
+        let mut queue = VecDeque::new();
+        light[idx] = 0;
+        while let Some((idx, level)) = removal.pop_front() {
+            for (neighbor, down) in Self::neighbors(idx, light.len()) {
+                let neighbor_level = light[neighbor];
+                if neighbor_level == 0 {
+                    continue;
+                }
+                // Full sunlight below was only lit through the removed block
+                let sunlight = sky && down && level == MAX_LIGHT && neighbor_level == MAX_LIGHT;
+                if neighbor_level < level || sunlight {
+                    light[neighbor] = 0;
+                    removal.push_back((neighbor, neighbor_level));
+                } else {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        queue
+    }
+
+    /// Breadth first light spread from the queued blocks
+    fn propagate(light: &mut [u8], opacity: &[u8], sky: bool, mut queue: VecDeque<usize>) {
+        while let Some(idx) = queue.pop_front() {
+            let level = light[idx];
+            if level <= 1 {
+                continue;
+            }
+            for (neighbor, down) in Self::neighbors(idx, light.len()) {
+                let new_level = Self::spread_level(level, opacity[neighbor], sky && down);
+                if new_level > light[neighbor] {
+                    light[neighbor] = new_level;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    /// Light level after entering a block, full sunlight travels down without dimming
+    #[inline]
+    fn spread_level(level: u8, opacity: u8, sunlight_down: bool) -> u8 {
+        if sunlight_down && level == MAX_LIGHT && opacity == 0 {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(opacity.max(1))
+        }
+    }
+
+    /// Blocks next to `idx` within the chunk, with whether the neighbour is below
+    #[inline]
+    fn neighbors(idx: usize, len: usize) -> impl Iterator<Item = (usize, bool)> {
+        let x = idx & 15;
+        let z = (idx >> 4) & 15;
+        [
+            (x > 0).then(|| (idx - 1, false)),
+            (x < 15).then(|| (idx + 1, false)),
+            (z > 0).then(|| (idx - 16, false)),
+            (z < 15).then(|| (idx + 16, false)),
+            (idx >= 256).then(|| (idx - 256, true)),
+            (idx + 256 < len).then(|| (idx + 256, false)),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    #[inline]
+    fn nibble(data: &[i8], idx: usize) -> u8 {
+        (data.get(idx >> 1).copied().unwrap_or(0) as u8 >> ((idx & 1) * 4)) & 0xF
+    }
+
+    fn unpack_nibbles(data: &[i8], light: &mut [u8]) {
+        for (i, level) in light.iter_mut().enumerate() {
+            *level = Self::nibble(data, i);
+        }
+    }
+
+    /// Packs two levels per byte, the lower nibble holds the even index
+    fn pack_nibbles(light: &[u8]) -> fastnbt::ByteArray {
+        fastnbt::ByteArray::new(
+            light
+                .chunks(2)
+                .map(|pair| (pair[0] | pair[1] << 4) as i8)
+                .collect(),
+        )
+    }
+}
+
+/// Lights a chunk from scratch using the light of its loaded neighbours
+pub fn light_chunk(chunk: &mut Chunk, has_skylight: bool, neighbors: &ChunkNeighbors) {
+    let mut light = ChunkLight::new(chunk, has_skylight);
+    light.light_all(neighbors);
+    light.store(chunk);
+}
+
+/// Sets a block at a position relative to the chunk and relights around it, returns the
+/// previous block state
+pub fn update_block(
+    chunk: &mut Chunk,
+    has_skylight: bool,
+    neighbors: &ChunkNeighbors,
+    (x, y, z): (usize, i32, usize),
+    block: u32,
+) -> Option<u32> {
+    let previous = chunk.set_block(x, y, z, block)?;
+    let mut light = ChunkLight::new(chunk, has_skylight);
+    light.update_block(neighbors, x, y, z);
+    light.store(chunk);
+    Some(previous)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::chunk_system::{ChunkBlockStates, ChunkSection, BLOCKSTATE_MAPPINGS};
+    use crate::server::logger::{ServerLogger, LOGGER};
+
+    fn light_at(data: &Option<fastnbt::ByteArray>, x: usize, y: i32, z: usize) -> u8 {
+        let idx = (y.rem_euclid(16) as usize) << 8 | z << 4 | x;
+        data.as_ref()
+            .map_or(0, |data| ChunkLight::nibble(data, idx))
+    }
+
+    #[test]
+    fn torch_light_falls_off_by_one_per_block() {
+        let mut chunk = Chunk {
+            sections: (-4..20).map(ChunkSection::air).collect(),
+            y: -4,
+            ..Default::default()
+        };
+        let torch = *BLOCKSTATE_MAPPINGS.get("torch").unwrap();
+        let section = &mut chunk.sections[4];
+        let mut block_ids = section.block_states.block_ids();
+        block_ids[8 << 4 | 8] = torch;
+        section.block_states = ChunkBlockStates::from_block_ids(&block_ids);
+
+        light_chunk(&mut chunk, true, &[None; 4]);
+        assert!(chunk.is_light_on);
+        let block_light = |x: usize, y: i32, z: usize| {
+            let section = &chunk.sections[(y.div_euclid(16) + 4) as usize];
+            light_at(&section.block_light, x, y, z)
+        };
+        assert_eq!(block_light(8, 0, 8), 14);
+        for distance in 1..=13 {
+            assert_eq!(block_light(8, distance, 8), 14 - distance as u8);
+            assert_eq!(block_light(8, -distance, 8), 14 - distance as u8);
+        }
+        // Light spreads around corners, so it is dimmed by the Manhattan distance
+        assert_eq!(block_light(15, 0, 15), 0);
+        assert_eq!(block_light(12, 3, 5), 4);
+        assert_eq!(block_light(0, 0, 8), 6);
+        // Sections the torch does not reach store no block light
+        assert!(chunk.sections[3].block_light.is_some());
+        assert!(chunk.sections[5].block_light.is_none());
+        // Nothing stops the sunlight in an empty chunk
+        for section in &chunk.sections {
+            assert_eq!(light_at(&section.sky_light, 3, -64, 11), 15);
+        }
+    }
+
+    #[test]
+    fn block_changes_relight_their_neighbors() {
+        LOGGER.get_or_init(ServerLogger::new);
+        let mut chunk = Chunk {
+            sections: (-4..20).map(ChunkSection::air).collect(),
+            y: -4,
+            ..Default::default()
+        };
+        light_chunk(&mut chunk, true, &[None; 4]);
+        let (air, stone) = (BLOCKSTATE_MAPPINGS["air"], BLOCKSTATE_MAPPINGS["stone"]);
+        let torch = BLOCKSTATE_MAPPINGS["torch"];
+        let light = |chunk: &Chunk, x: usize, y: i32, z: usize| {
+            let section = &chunk.sections[(y.div_euclid(16) + 4) as usize];
+            (
+                light_at(&section.sky_light, x, y, z),
+                light_at(&section.block_light, x, y, z),
+            )
+        };
+
+        assert_eq!(
+            update_block(&mut chunk, true, &[None; 4], (8, 0, 8), torch),
+            Some(air)
+        );
+        assert_eq!(light(&chunk, 8, 0, 8).1, 14);
+        assert_eq!(light(&chunk, 9, 0, 8).1, 13);
+        assert_eq!(light(&chunk, 8, -5, 8).1, 9);
+        // Light goes around an opaque block
+        update_block(&mut chunk, true, &[None; 4], (9, 0, 8), stone);
+        assert_eq!(light(&chunk, 9, 0, 8).1, 0);
+        assert_eq!(light(&chunk, 10, 0, 8).1, 10);
+        assert_eq!(light(&chunk, 8, 1, 8).1, 13);
+        update_block(&mut chunk, true, &[None; 4], (9, 0, 8), air);
+        assert_eq!(light(&chunk, 10, 0, 8).1, 12);
+        assert_eq!(
+            update_block(&mut chunk, true, &[None; 4], (8, 0, 8), air),
+            Some(torch)
+        );
+        for (x, y, z) in [(8, 0, 8), (9, 0, 8), (10, 0, 8), (8, -5, 8)] {
+            assert_eq!(light(&chunk, x, y, z).1, 0);
+        }
+
+        // An opaque block under the sky shades its column, which is lit from the side
+        update_block(&mut chunk, true, &[None; 4], (3, 310, 11), stone);
+        assert_eq!(light(&chunk, 3, 311, 11).0, 15);
+        assert_eq!(light(&chunk, 3, 310, 11).0, 0);
+        assert_eq!(light(&chunk, 3, 309, 11).0, 14);
+        assert_eq!(light(&chunk, 3, -64, 11).0, 14);
+        assert_eq!(light(&chunk, 2, 309, 11).0, 15);
+        update_block(&mut chunk, true, &[None; 4], (3, 310, 11), air);
+        assert_eq!(light(&chunk, 3, 310, 11).0, 15);
+        assert_eq!(light(&chunk, 3, -64, 11).0, 15);
+    }
+}
@@ -0,0 +1,426 @@
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use once_cell::sync::Lazy;
+use std::fmt::Write as FmtWrite;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Server wide metrics registry.
+/// Values are always collected since updating them is only an atomic operation, the http
+/// endpoint that exposes them is only started when `enable-metrics` is set in server.properties
+pub static METRICS: Lazy<ServerMetrics> = Lazy::new(ServerMetrics::new);
+
+/// Labels for per connection state counters, indexed by `PlayerState as u8`
+const CONNECTION_STATES: [&str; 4] = ["handshake", "login", "configuration", "play"];
+
+/// Buckets (in seconds) used for latency histograms
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    #[inline]
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn add(&self, amount: u64) {
+        self.0.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    #[inline]
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Cumulative histogram with fixed buckets
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: &'static [f64],
+    counts: Box<[AtomicU64]>,
+    /// Sum of all observations in microseconds
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    pub fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            counts: (0..buckets.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: std::time::Duration) {
+        let secs = duration.as_secs_f64();
+        for (i, bound) in self.buckets.iter().enumerate() {
+            if secs <= *bound {
+                self.counts[i].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, name: &str, help: &str, out: &mut String) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for (i, bound) in self.buckets.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{le=\"{bound}\"}} {}",
+                self.counts[i].load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(
+            out,
+            "{name}_sum {}",
+            self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+#[derive(Debug)]
+pub struct ServerMetrics {
+    pub players_connected: Gauge,
+    pub region_cache_size: Gauge,
+    pub region_cache_bytes: Gauge,
+    pub region_cache_budget: Gauge,
+    pub region_cache_hits: Counter,
+    pub region_cache_misses: Counter,
+    pub region_cache_evictions: Counter,
+    pub chunks_loaded: Counter,
+    pub chunks_resident: Gauge,
+    pub chunks_generated: Counter,
+    pub chunk_requests_cancelled: Counter,
+    pub chunk_generation_time: Histogram,
+    pub load_permit_wait: Histogram,
+    pub gen_permit_wait: Histogram,
+    pub keep_alive_latency: Histogram,
+    pub packets_in: [Counter; 4],
+    pub packets_out: [Counter; 4],
+    pub bytes_in: [Counter; 4],
+    pub bytes_out: [Counter; 4],
+    /// Pending events per dispatcher shard, initialized by `ServerDispatcher::new`
+    shard_queue_depths: OnceLock<Box<[Gauge]>>,
+}
+
+impl ServerMetrics {
+    fn new() -> Self {
+        Self {
+            players_connected: Gauge::default(),
+            region_cache_size: Gauge::default(),
+            region_cache_bytes: Gauge::default(),
+            region_cache_budget: Gauge::default(),
+            region_cache_hits: Counter::default(),
+            region_cache_misses: Counter::default(),
+            region_cache_evictions: Counter::default(),
+            chunks_loaded: Counter::default(),
+            chunks_resident: Gauge::default(),
+            chunks_generated: Counter::default(),
+            chunk_requests_cancelled: Counter::default(),
+            chunk_generation_time: Histogram::new(&LATENCY_BUCKETS),
+            load_permit_wait: Histogram::new(&LATENCY_BUCKETS),
+            gen_permit_wait: Histogram::new(&LATENCY_BUCKETS),
+            keep_alive_latency: Histogram::new(&LATENCY_BUCKETS),
+            packets_in: Default::default(),
+            packets_out: Default::default(),
+            bytes_in: Default::default(),
+            bytes_out: Default::default(),
+            shard_queue_depths: OnceLock::new(),
+        }
+    }
+
+    pub fn init_shards(&self, num_of_shards: usize) {
+        let _ = self
+            .shard_queue_depths
+            .set((0..num_of_shards).map(|_| Gauge::default()).collect());
+    }
+
+    pub fn shard_queue_depth(&self, shard: usize) -> Option<&Gauge> {
+        self.shard_queue_depths.get()?.get(shard)
+    }
+
+    /// Records a packet of `len` bytes received in the given connection state
+    #[inline]
+    pub fn packet_in(&self, state: u8, len: usize) {
+        let state = (state as usize).min(CONNECTION_STATES.len() - 1);
+        self.packets_in[state].inc();
+        self.bytes_in[state].add(len as u64);
+    }
+
+    /// Records a packet of `len` bytes sent in the given connection state
+    #[inline]
+    pub fn packet_out(&self, state: u8, len: usize) {
+        let state = (state as usize).min(CONNECTION_STATES.len() - 1);
+        self.packets_out[state].inc();
+        self.bytes_out[state].add(len as u64);
+    }
+
+    /// Formats all metrics in the prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        Self::render_gauge(
+            &mut out,
+            "ludicrous_players_connected",
+            "Number of players registered with the server",
+            self.players_connected.get(),
+        );
+        Self::render_gauge(
+            &mut out,
+            "ludicrous_region_cache_regions",
+            "Number of region files held in the region cache",
+            self.region_cache_size.get(),
+        );
+        Self::render_gauge(
+            &mut out,
+            "ludicrous_region_cache_bytes",
+            "Bytes of region headers and chunk data held in the region cache",
+            self.region_cache_bytes.get(),
+        );
+        Self::render_gauge(
+            &mut out,
+            "ludicrous_region_cache_budget_bytes",
+            "Maximum bytes the region cache may hold",
+            self.region_cache_budget.get(),
+        );
+        Self::render_counter(
+            &mut out,
+            "ludicrous_region_cache_hits_total",
+            "Region lookups served from the region cache",
+            self.region_cache_hits.get(),
+        );
+        Self::render_counter(
+            &mut out,
+            "ludicrous_region_cache_misses_total",
+            "Region lookups that had to open the region file",
+            self.region_cache_misses.get(),
+        );
+        Self::render_counter(
+            &mut out,
+            "ludicrous_region_cache_evictions_total",
+            "Regions evicted from the region cache",
+            self.region_cache_evictions.get(),
+        );
+        Self::render_counter(
+            &mut out,
+            "ludicrous_chunks_loaded_total",
+            "Chunks read from region files",
+            self.chunks_loaded.get(),
+        );
+        Self::render_gauge(
+            &mut out,
+            "ludicrous_chunks_resident",
+            "Chunks held in the server chunk map",
+            self.chunks_resident.get(),
+        );
+        Self::render_counter(
+            &mut out,
+            "ludicrous_chunks_generated_total",
+            "Chunks produced by terrain generation",
+            self.chunks_generated.get(),
+        );
+        Self::render_counter(
+            &mut out,
+            "ludicrous_chunk_requests_cancelled_total",
+            "Chunk loads and generations skipped because no player viewed the chunk anymore",
+            self.chunk_requests_cancelled.get(),
+        );
+        self.chunk_generation_time.render(
+            "ludicrous_chunk_generation_seconds",
+            "Time spent generating a single chunk",
+            &mut out,
+        );
+        self.load_permit_wait.render(
+            "ludicrous_chunk_load_permit_wait_seconds",
+            "Time chunk load tasks waited for a load permit",
+            &mut out,
+        );
+        self.gen_permit_wait.render(
+            "ludicrous_chunk_gen_permit_wait_seconds",
+            "Time chunk generation tasks waited for a generation permit",
+            &mut out,
+        );
+        self.keep_alive_latency.render(
+            "ludicrous_keep_alive_latency_seconds",
+            "Time between sending a keep alive and receiving its answer",
+            &mut out,
+        );
+        if let Some(depths) = self.shard_queue_depths.get() {
+            let _ = writeln!(
+                out,
+                "# HELP ludicrous_shard_queue_depth Events waiting to be handled by a dispatcher shard"
+            );
+            let _ = writeln!(out, "# TYPE ludicrous_shard_queue_depth gauge");
+            for (shard, depth) in depths.iter().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "ludicrous_shard_queue_depth{{shard=\"{shard}\"}} {}",
+                    depth.get()
+                );
+            }
+        }
+        Self::render_state_counters(
+            &mut out,
+            "ludicrous_packets_received_total",
+            "Packets received from clients",
+            &self.packets_in,
+        );
+        Self::render_state_counters(
+            &mut out,
+            "ludicrous_packets_sent_total",
+            "Packets sent to clients",
+            &self.packets_out,
+        );
+        Self::render_state_counters(
+            &mut out,
+            "ludicrous_bytes_received_total",
+            "Bytes received from clients",
+            &self.bytes_in,
+        );
+        Self::render_state_counters(
+            &mut out,
+            "ludicrous_bytes_sent_total",
+            "Bytes sent to clients",
+            &self.bytes_out,
+        );
+        out
+    }
+
+    fn render_gauge(out: &mut String, name: &str, help: &str, value: i64) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        let _ = writeln!(out, "{name} {value}");
+    }
+
+    fn render_counter(out: &mut String, name: &str, help: &str, value: u64) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} counter");
+        let _ = writeln!(out, "{name} {value}");
+    }
+
+    fn render_state_counters(out: &mut String, name: &str, help: &str, counters: &[Counter; 4]) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} counter");
+        for (state, counter) in CONNECTION_STATES.iter().zip(counters.iter()) {
+            let _ = writeln!(out, "{name}{{state=\"{state}\"}} {}", counter.get());
+        }
+    }
+
+    /// Starts the http endpoint serving `/metrics`
+    pub async fn serve(port: u32) {
+        let listener = match TcpListener::bind(format!("0.0.0.0:{port}")).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                LOGGER.get().unwrap().println_as(
+                    &format!("Could not start metrics endpoint on port {port}: {e}"),
+                    LogDomain::Server,
+                    LogLevel::Error,
+                );
+                return;
+            }
+        };
+        LOGGER
+            .get()
+            .unwrap()
+            .println(&format!("Serving metrics on http://0.0.0.0:{port}/metrics"));
+        loop {
+            let Ok((mut socket, _addr)) = listener.accept().await else {
+                continue;
+            };
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let response = if request.starts_with("GET /metrics ") {
+                    let body = METRICS.render();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    )
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let metrics = ServerMetrics::new();
+        metrics
+            .chunk_generation_time
+            .observe(Duration::from_millis(3));
+        metrics
+            .chunk_generation_time
+            .observe(Duration::from_secs(5));
+        let out = metrics.render();
+        assert!(out.contains("ludicrous_chunk_generation_seconds_bucket{le=\"0.0025\"} 0\n"));
+        assert!(out.contains("ludicrous_chunk_generation_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(out.contains("ludicrous_chunk_generation_seconds_bucket{le=\"2.5\"} 1\n"));
+        assert!(out.contains("ludicrous_chunk_generation_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(out.contains("ludicrous_chunk_generation_seconds_sum 5.003\n"));
+        assert!(out.contains("ludicrous_chunk_generation_seconds_count 2\n"));
+    }
+
+    #[test]
+    fn packets_are_counted_per_state() {
+        let metrics = ServerMetrics::new();
+        metrics.init_shards(2);
+        metrics.shard_queue_depth(1).unwrap().set(7);
+        metrics.packet_in(1, 10);
+        metrics.packet_in(1, 5);
+        // Unknown states are counted as play
+        metrics.packet_out(9, 100);
+        let out = metrics.render();
+        assert!(out.contains("ludicrous_packets_received_total{state=\"login\"} 2\n"));
+        assert!(out.contains("ludicrous_bytes_received_total{state=\"login\"} 15\n"));
+        assert!(out.contains("ludicrous_bytes_sent_total{state=\"play\"} 100\n"));
+        assert!(out.contains("ludicrous_shard_queue_depth{shard=\"1\"} 7\n"));
+        assert!(metrics.shard_queue_depth(2).is_none());
+    }
+}
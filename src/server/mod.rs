@@ -1,22 +1,47 @@
+pub mod aquifer;
+pub mod biome_source;
+pub mod blender;
+pub mod carvers;
+pub mod chunk_map;
 pub mod chunk_system;
+pub mod commands;
+pub mod dimension;
 pub mod events;
+pub mod features;
+pub mod flat;
 pub mod gen_test;
+pub mod heightmap;
 mod level;
+pub mod light;
 pub mod logger;
+pub mod metrics;
+pub mod operators;
+pub mod ore_veins;
+pub mod pregen;
 pub mod randomness;
 pub mod region;
+pub mod structures;
+pub mod surface;
+pub mod tags;
 pub mod terrain_gen;
+pub mod value_providers;
 pub mod world_state;
+use chunk_map::ChunkMap;
 use events::{ChunkLoadTask, ServerEvent};
 use region::RegionManager;
 use world_state::WorldState;
 mod util;
-use super::packets::play::SetCenterChunk;
+use super::packets::play::{
+    GameEvent, PlayerAbilities, Respawn, SetCenterChunk, SynchronizePlayerPos,
+};
 use super::player::Player;
 use crate::JAR_RESOURCES_DIR;
 pub use chunk_system::{Chunk, LudiChunkLoader};
+pub use dimension::Dimension;
 use level::ServerLevel;
 use logger::LOGGER;
+use metrics::METRICS;
+use operators::Operators;
 use serde::de::{self, Deserializer};
 use serde::{ser, Deserialize, Serialize};
 use std::collections::HashMap;
@@ -38,6 +63,7 @@ pub struct ServerData {
     pub dimension_settings: HashMap<String, DimensionType>,
     pub dispatcher: ServerDispatcher,
     pub num_of_shards: usize,
+    pub operators: Operators,
     counters: Mutex<ServerCounters>,
 }
 
@@ -49,11 +75,16 @@ impl std::fmt::Debug for ServerData {
 
 impl ServerData {
     pub fn new() -> Self {
-        let config = ServerProperties::load_properties();
-        let level = ServerLevel::new(&config.level_name);
+        Self::with_properties(ServerProperties::load_properties())
+    }
+
+    /// Server using the given properties instead of `server.properties`
+    pub fn with_properties(config: ServerProperties) -> Self {
+        let mut level = ServerLevel::new(&config);
         let num_of_shards = 12;
         let dispatcher = ServerDispatcher::new(num_of_shards, &config);
         WorldState::initialize_world_states(&level.world_gen_settings);
+        level.initialize_new(&config.level_name);
         Self {
             level,
             dimension_settings: DimensionType::get_dimension_configs(),
@@ -63,6 +94,7 @@ impl ServerData {
             mappings: ServerMappings::load_mappings(),
             dispatcher,
             num_of_shards,
+            operators: Operators::load(),
             counters: Mutex::new(ServerCounters::default()),
         }
     }
@@ -78,18 +110,36 @@ impl ServerData {
     }
 
     pub fn add_player(&self, player: Arc<Player>) {
-        self.players.write().unwrap().insert(player.id, player);
+        if self
+            .players
+            .write()
+            .unwrap()
+            .insert(player.id, player)
+            .is_none()
+        {
+            METRICS.players_connected.inc();
+        }
     }
 
     pub fn remove_player(&self, player_id: i32) {
-        self.players.write().unwrap().remove(&player_id);
+        if self.players.write().unwrap().remove(&player_id).is_some() {
+            METRICS.players_connected.dec();
+        }
+        self.dispatcher.release_player(player_id, None);
+    }
+
+    /// Permission level of a player from `ops.json`
+    pub fn permission_level(&self, player: &Player) -> u8 {
+        self.operators.level(&player.get_uuid_string())
+    }
+
+    /// Whether a player is still registered with the server
+    pub fn has_player(&self, player_id: i32) -> bool {
+        self.players.read().unwrap().contains_key(&player_id)
     }
 
     pub async fn load_init_chunks(&self, player: Arc<Player>, view_distance: u32) {
-        let center_chunk = (
-            player.chunk.0.load(Ordering::Relaxed),
-            player.chunk.1.load(Ordering::Relaxed),
-        );
+        let center_chunk = player.get_center_chunk();
         if let Some(tx) = player.outbound.upgrade() {
             let _ = tx.send(SetCenterChunk::new(center_chunk).into()).await;
         }
@@ -116,10 +166,48 @@ impl ServerData {
         .await
         .unwrap();
         for e in events {
-            self.dispatcher.shards[e.0]
-                .send(ServerEvent::ChunkLoad(e.1))
-                .unwrap();
+            self.dispatcher.send(e.0, ServerEvent::ChunkLoad(e.1));
+        }
+    }
+
+    /// Moves a player to a position, respawning them in the new dimension if it changes
+    pub async fn teleport_player(
+        &self,
+        player: Arc<Player>,
+        dimension: Dimension,
+        pos: (f64, f64, f64),
+    ) {
+        let old_pos = player.get_position();
+        let old_center_chunk = LudiChunkLoader::pos_to_chunk(old_pos.0, old_pos.2);
+        let new_center_chunk = LudiChunkLoader::pos_to_chunk(pos.0, pos.2);
+        // Movement from the client is ignored until it accepts the teleport
+        player.awaiting_teleport.store(true, Ordering::Relaxed);
+        player.update_position(pos.0, pos.1, pos.2).await;
+        let Some(tx) = player.outbound.upgrade() else {
+            return;
+        };
+        if dimension == player.get_dimension() {
+            let _ = tx.send(SynchronizePlayerPos::new().into()).await;
+            self.load_chunks(new_center_chunk, old_center_chunk, player)
+                .await;
+            return;
         }
+        // The client drops every loaded chunk on respawn so the whole view distance is loaded
+        // again, pending chunks of the previous dimension are discarded by the chunk loader
+        let old_dimension = player.get_dimension();
+        player.dimension.store(dimension as u8, Ordering::Relaxed);
+        self.dispatcher
+            .release_player(player.id, Some(old_dimension));
+        player.set_center_chunk(new_center_chunk);
+        let _ = tx.send(Respawn::new(dimension).into()).await;
+        let _ = tx.send(SynchronizePlayerPos::new().into()).await;
+        self.load_init_chunks(player.clone(), 2).await;
+        let _ = tx.send(GameEvent::new(13, 0.0).into()).await;
+        self.load_init_chunks(player.clone(), self.config.view_distance)
+            .await;
+        let _ = tx
+            .send(PlayerAbilities::new(0b00000110, 3.0, 0.0).into())
+            .await;
     }
 
     pub async fn load_chunks(
@@ -129,6 +217,7 @@ impl ServerData {
         player: Arc<Player>,
     ) {
         if new_center_chunk.0 != old_center_chunk.0 || new_center_chunk.1 != old_center_chunk.1 {
+            player.set_center_chunk(new_center_chunk);
             if let Some(tx) = player.low_priority_outbound.upgrade() {
                 let _ = tx.send(SetCenterChunk::new(new_center_chunk).into()).await;
             }
@@ -171,9 +260,7 @@ impl ServerData {
             .await
             .unwrap();
             for e in events {
-                self.dispatcher.shards[e.0]
-                    .send(ServerEvent::ChunkLoad(e.1))
-                    .unwrap();
+                self.dispatcher.send(e.0, ServerEvent::ChunkLoad(e.1));
             }
         }
     }
@@ -187,15 +274,22 @@ pub struct ServerCounters {
 
 pub struct ServerDispatcher {
     shards: Vec<UnboundedSender<ServerEvent>>,
+    pub chunk_map: Arc<ChunkMap>,
+    pub region_manager: Arc<RegionManager>,
 }
 
 impl ServerDispatcher {
     pub fn new(num_of_shards: usize, config: &ServerProperties) -> Self {
-        let region_manager = RegionManager::new(config.level_name.clone(), 32);
+        let region_manager = RegionManager::new(
+            config.level_name.clone(),
+            config.region_cache_size_mb as usize * 1024 * 1024,
+        );
         RegionManager::spawn_stale_checker(region_manager.clone());
+        let chunk_map = ChunkMap::new();
+        METRICS.init_shards(num_of_shards);
         let mut shards = Vec::new();
         for i in 0..num_of_shards {
-            let shard = ServerShard::new(region_manager.clone());
+            let shard = ServerShard::new(region_manager.clone(), chunk_map.clone());
             let (tx, mut rx) = unbounded_channel();
             shards.push(tx);
             tokio::spawn(async move {
@@ -205,29 +299,63 @@ impl ServerDispatcher {
                     let num_of_events = rx.recv_many(&mut buf, events_limit).await;
                     for event in buf.iter().take(num_of_events) {
                         shard.handle_event(event).await;
+                        if let Some(depth) = METRICS.shard_queue_depth(i) {
+                            depth.dec();
+                        }
                     }
                     buf.clear();
                 }
             });
         }
-        Self { shards }
+        Self {
+            shards,
+            chunk_map,
+            region_manager,
+        }
+    }
+
+    /// Queues an event on the given shard
+    pub fn send(&self, shard: usize, event: ServerEvent) {
+        if let Some(depth) = METRICS.shard_queue_depth(shard) {
+            depth.inc();
+        }
+        self.shards[shard].send(event).unwrap();
+    }
+
+    /// Releases the chunks a player views once every chunk load queued before is handled
+    pub fn release_player(&self, player_id: i32, dimension: Option<Dimension>) {
+        for shard in 0..self.shards.len() {
+            self.send(
+                shard,
+                ServerEvent::ReleasePlayer {
+                    player_id,
+                    dimension,
+                },
+            );
+        }
     }
 }
 
 struct ServerShard {
     chunk_loader: LudiChunkLoader,
+    chunk_map: Arc<ChunkMap>,
 }
 
 impl ServerShard {
-    pub fn new(region_manager: Arc<RegionManager>) -> Self {
+    pub fn new(region_manager: Arc<RegionManager>, chunk_map: Arc<ChunkMap>) -> Self {
         Self {
-            chunk_loader: LudiChunkLoader::new(region_manager),
+            chunk_loader: LudiChunkLoader::new(region_manager, chunk_map.clone()),
+            chunk_map,
         }
     }
 
     pub async fn handle_event(&self, event: &ServerEvent) {
         match event {
             ServerEvent::ChunkLoad(task) => self.chunk_loader.handle_chunk_task(task).await,
+            ServerEvent::ReleasePlayer {
+                player_id,
+                dimension,
+            } => self.chunk_map.release_player(*player_id, *dimension),
         }
     }
 }
@@ -238,6 +366,7 @@ pub struct DimensionType {
     pub logical_height: i32,
     pub min_y: i32,
     pub coordinate_scale: f32,
+    pub has_skylight: bool,
 }
 
 impl DimensionType {
@@ -309,6 +438,12 @@ impl std::fmt::Debug for ServerMappings {
 #[serde(rename_all = "kebab-case")]
 pub struct ServerProperties {
     pub level_name: String,
+    /// Seed of a new world, random when empty and hashed when it is not a number
+    #[serde(default)]
+    pub level_seed: String,
+    /// World preset of a new world: normal, flat, large_biomes, amplified or void
+    #[serde(default = "ServerProperties::default_level_type")]
+    pub level_type: String,
     #[serde(deserialize_with = "int_type")]
     pub network_compression_threshold: i32,
     #[serde(deserialize_with = "int_type")]
@@ -317,19 +452,69 @@ pub struct ServerProperties {
     pub server_port: u32,
     #[serde(deserialize_with = "int_type")]
     pub simulation_distance: u32,
+    #[serde(default, deserialize_with = "int_type")]
+    pub enable_metrics: bool,
+    #[serde(
+        default = "ServerProperties::default_metrics_port",
+        deserialize_with = "int_type"
+    )]
+    pub metrics_port: u32,
+    /// Records every session to `captures/` for debugging (See `packets::capture`)
+    #[serde(default, deserialize_with = "int_type")]
+    pub packet_capture: bool,
+    /// Memory budget of the region cache, its usage and hit rate are exposed as metrics
+    #[serde(
+        default = "ServerProperties::default_region_cache_size_mb",
+        deserialize_with = "int_type"
+    )]
+    pub region_cache_size_mb: u32,
+    /// What to send in place of chunks that are stored but can not be read
+    #[serde(default)]
+    pub corrupt_chunk_policy: CorruptChunkPolicy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CorruptChunkPolicy {
+    /// Generate the chunk again, replacing the corrupt data on disk
+    #[default]
+    Regenerate,
+    /// Send a chunk with only air and leave the data on disk untouched
+    Empty,
+    /// Do not send the chunk at all
+    Refuse,
 }
 
 impl ServerProperties {
     pub fn default() -> Self {
         ServerProperties {
             level_name: "world".into(),
+            level_seed: String::new(),
+            level_type: Self::default_level_type(),
             network_compression_threshold: 256,
             view_distance: 10,
             server_port: 25565,
             simulation_distance: 10,
+            enable_metrics: false,
+            metrics_port: Self::default_metrics_port(),
+            packet_capture: false,
+            region_cache_size_mb: Self::default_region_cache_size_mb(),
+            corrupt_chunk_policy: CorruptChunkPolicy::default(),
         }
     }
 
+    fn default_level_type() -> String {
+        "minecraft:normal".into()
+    }
+
+    fn default_metrics_port() -> u32 {
+        9225
+    }
+
+    fn default_region_cache_size_mb() -> u32 {
+        256
+    }
+
     pub fn load_properties() -> Self {
         let prop_file = File::open("server.properties");
         if let Result::Err(_e) = prop_file {
@@ -0,0 +1,81 @@
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use serde::Deserialize;
+
+const OPS_FILE: &str = "ops.json";
+/// Permission level vanilla requires for commands such as `/tp`
+pub const GAMEMASTER_LEVEL: u8 = 2;
+/// Permission level of commands that affect the whole server
+pub const ADMIN_LEVEL: u8 = 4;
+
+fn default_level() -> u8 {
+    ADMIN_LEVEL
+}
+
+/// Entry of `ops.json`, the same file vanilla servers use
+#[derive(Deserialize, Debug, Clone)]
+pub struct Operator {
+    pub uuid: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_level")]
+    pub level: u8,
+}
+
+#[derive(Debug, Default)]
+pub struct Operators {
+    operators: Vec<Operator>,
+}
+
+impl Operators {
+    /// Reads `ops.json`, no player is an operator when the file is missing
+    pub fn load() -> Self {
+        let Ok(json) = std::fs::read_to_string(OPS_FILE) else {
+            return Self::default();
+        };
+        Self::from_json(&json).unwrap_or_else(|e| {
+            LOGGER.get().unwrap().println_as(
+                &format!("Could not read {OPS_FILE}, no player is an operator: {e}"),
+                LogDomain::Server,
+                LogLevel::Warn,
+            );
+            Self::default()
+        })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            operators: serde_json::from_str(json)?,
+        })
+    }
+
+    /// Permission level of a player by uuid, 0 for players that are not operators
+    pub fn level(&self, uuid: &str) -> u8 {
+        self.operators
+            .iter()
+            .find(|op| op.uuid.eq_ignore_ascii_case(uuid))
+            .map_or(0, |op| op.level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators_have_their_level() {
+        let operators = Operators::from_json(
+            r#"[
+                {"uuid": "069a79f4-44e9-4726-a5be-fca90e38aaf5", "name": "Notch", "level": 2},
+                {"uuid": "853C80EF-3C37-49FD-AA49-938B674ADAE6", "name": "jeb_"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(operators.level("069a79f4-44e9-4726-a5be-fca90e38aaf5"), 2);
+        assert_eq!(
+            operators.level("853c80ef-3c37-49fd-aa49-938b674adae6"),
+            ADMIN_LEVEL
+        );
+        assert_eq!(operators.level("00000000-0000-0000-0000-000000000000"), 0);
+        assert!(Operators::from_json("{}").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use super::chunk_system::BLOCKSTATE_MAPPINGS;
+use super::terrain_gen::func_deserialize::{DensityArg, DensityFnArgs};
+use super::util::clamped_map_f64;
+use super::world_state::WorldState;
+
+/// Blocks and height range of a kind of ore vein
+struct VeinType {
+    ore: u32,
+    raw_ore_block: u32,
+    filler: u32,
+    min_y: i32,
+    max_y: i32,
+}
+
+impl VeinType {
+    fn new(ore: &str, raw_ore_block: &str, filler: &str, min_y: i32, max_y: i32) -> Self {
+        let block = |name: &str| *BLOCKSTATE_MAPPINGS.get(name).unwrap();
+        VeinType {
+            ore: block(ore),
+            raw_ore_block: block(raw_ore_block),
+            filler: block(filler),
+            min_y,
+            max_y,
+        }
+    }
+
+    /// Whether a vein of this type is strong enough at a height, veins thin out over the 20
+    /// blocks next to the ends of their height range
+    fn reaches(&self, y: i32, toggle: f64) -> bool {
+        let distance_to_top = self.max_y - y;
+        let distance_to_bottom = y - self.min_y;
+        if distance_to_bottom < 0 || distance_to_top < 0 {
+            return false;
+        }
+        let edge_distance = distance_to_top.min(distance_to_bottom);
+        let edge_falloff = clamped_map_f64(edge_distance as f64, 0.0, 20.0, -0.2, 0.0);
+        toggle + edge_falloff >= 0.4f32 as f64
+    }
+}
+
+/// Places large copper veins in granite and iron veins in tuff inside of solid terrain
+pub struct OreVeinifier<'a> {
+    world_state: &'a WorldState,
+    dimension: &'a str,
+    pub vein_toggle: &'a DensityArg,
+    pub vein_ridged: &'a DensityArg,
+    vein_gap: &'a DensityArg,
+    copper: VeinType,
+    iron: VeinType,
+}
+
+impl<'a> OreVeinifier<'a> {
+    /// `None` when ore veins are disabled for the dimension
+    pub fn new(world_state: &'a WorldState, dimension: &'a str) -> Option<Self> {
+        if !world_state.settings.ore_veins_enabled {
+            return None;
+        }
+        let router = &world_state.settings.noise_router;
+        Some(OreVeinifier {
+            world_state,
+            dimension,
+            vein_toggle: router.get("vein_toggle")?,
+            vein_ridged: router.get("vein_ridged")?,
+            vein_gap: router.get("vein_gap")?,
+            copper: VeinType::new("copper_ore", "raw_copper_block", "granite", 0, 50),
+            iron: VeinType::new("deepslate_iron_ore", "raw_iron_block", "tuff", -60, -8),
+        })
+    }
+
+    /// Block of an ore vein replacing solid terrain, `toggle` and `ridged` are the interpolated
+    /// values of the vein router functions at the position
+    pub fn vein_block(&self, x: i32, y: i32, z: i32, toggle: f64, ridged: f64) -> Option<u32> {
+        let vein_type = if toggle > 0.0 {
+            &self.copper
+        } else {
+            &self.iron
+        };
+        let toggle = toggle.abs();
+        if !vein_type.reaches(y, toggle) {
+            return None;
+        }
+        let mut rand = self.world_state.ore_random.pos_to_rand(x, y, z);
+        if rand.next_f32() > 0.7 {
+            return None;
+        }
+        if ridged >= 0.0 {
+            return None;
+        }
+        let ore_chance = clamped_map_f64(
+            toggle,
+            0.4f32 as f64,
+            0.6f32 as f64,
+            0.1f32 as f64,
+            0.3f32 as f64,
+        );
+        if (rand.next_f32() as f64) < ore_chance
+            && self
+                .vein_gap
+                .compute(&mut DensityFnArgs::new(x, y, z, self.dimension))
+                > -0.3f32 as f64
+        {
+            if rand.next_f32() < 0.02 {
+                Some(vein_type.raw_ore_block)
+            } else {
+                Some(vein_type.ore)
+            }
+        } else {
+            Some(vein_type.filler)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn veins_thin_out_at_the_ends_of_their_range() {
+        let copper = VeinType::new("copper_ore", "raw_copper_block", "granite", 0, 50);
+        assert!(copper.reaches(25, 0.41));
+        assert!(!copper.reaches(25, 0.39));
+        // Half way into the edge the toggle has to make up for half of the falloff
+        assert!(!copper.reaches(10, 0.45));
+        assert!(copper.reaches(10, 0.51));
+        assert!(copper.reaches(0, 0.61));
+        assert!(!copper.reaches(-1, 1.0));
+        assert!(!copper.reaches(51, 1.0));
+        let iron = VeinType::new("deepslate_iron_ore", "raw_iron_block", "tuff", -60, -8);
+        assert!(iron.reaches(-30, 0.41));
+        assert!(!iron.reaches(0, 1.0));
+    }
+}
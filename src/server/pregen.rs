@@ -0,0 +1,390 @@
+use super::chunk_map::ChunkMap;
+use super::chunk_system::LudiChunkLoader;
+use super::dimension::Dimension;
+use super::world_state::WORLD_STATES;
+use super::ServerData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// Width in chunks of the squares generated as one batch, a tile always lies in a single region
+const TILE_SIZE: i32 = 16;
+/// Time between two progress reports
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// Largest pregenerated radius in chunks, the distance of the vanilla world border from the
+/// origin
+pub const MAX_RADIUS: i32 = 1_875_000;
+
+/// Set while chunks are pregenerated, only one pregeneration runs at a time
+static PREGEN_RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone)]
+pub struct PregenProgress {
+    pub dimension: Dimension,
+    pub total: usize,
+    /// Chunks generated or skipped so far
+    pub done: usize,
+    /// Chunks that were already stored in their region or resident
+    pub skipped: usize,
+    pub elapsed: Duration,
+}
+
+impl PregenProgress {
+    /// Remaining time at the generation speed so far, `None` before a chunk was generated
+    pub fn eta(&self) -> Option<Duration> {
+        let generated = self.done - self.skipped;
+        if generated == 0 {
+            return None;
+        }
+        Some(
+            self.elapsed
+                .mul_f64((self.total - self.done) as f64 / generated as f64),
+        )
+    }
+
+    pub fn describe(&self) -> String {
+        let percent = self.done as f64 * 100.0 / self.total.max(1) as f64;
+        let eta = self.eta().map_or("unknown".to_string(), |eta| {
+            let secs = eta.as_secs();
+            format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+        });
+        format!(
+            "Pregenerating {}: {}/{} chunks ({percent:.1}%, {} already generated), ETA {eta}",
+            self.dimension.name(),
+            self.done,
+            self.total,
+            self.skipped
+        )
+    }
+}
+
+pub fn is_running() -> bool {
+    PREGEN_RUNNING.load(Ordering::SeqCst)
+}
+
+/// Number of chunks within `radius` chunks of a center, `None` for a negative radius or one
+/// larger than `MAX_RADIUS`
+pub fn chunk_count(radius: i32) -> Option<usize> {
+    if !(0..=MAX_RADIUS).contains(&radius) {
+        return None;
+    }
+    let width = 2 * radius as i64 + 1;
+    usize::try_from(width.checked_mul(width)?).ok()
+}
+
+/// Releases what a running pregeneration holds when it finishes, fails or is dropped
+struct PregenGuard {
+    chunk_map: Arc<ChunkMap>,
+    dimension: Dimension,
+    /// Border chunks of the previous row of tiles
+    previous_row_borders: Vec<u64>,
+    /// Border chunks of the row of tiles being generated
+    row_borders: Vec<u64>,
+}
+
+impl PregenGuard {
+    /// Releases the borders of the previous row once the next row is generated
+    fn finish_row(&mut self) {
+        for coord in std::mem::take(&mut self.previous_row_borders) {
+            self.chunk_map.remove_ticket(self.dimension, coord);
+        }
+        self.previous_row_borders = std::mem::take(&mut self.row_borders);
+    }
+}
+
+impl Drop for PregenGuard {
+    fn drop(&mut self) {
+        for coord in self
+            .previous_row_borders
+            .drain(..)
+            .chain(self.row_borders.drain(..))
+        {
+            self.chunk_map.remove_ticket(self.dimension, coord);
+        }
+        LudiChunkLoader::reserve_proto_chunks(0);
+        PREGEN_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Chunk at the center of the pregenerated area, the spawn chunk in the overworld and the origin
+/// in the other dimensions
+pub fn pregen_center(server: &ServerData, dimension: Dimension) -> (i32, i32) {
+    if dimension == Dimension::Overworld {
+        (server.level.spawn_x >> 4, server.level.spawn_z >> 4)
+    } else {
+        (0, 0)
+    }
+}
+
+/// Generates every chunk within `radius` chunks of `center` and saves it to its region. Chunks
+/// already stored are skipped, so an interrupted pregeneration resumes where it stopped.
+///
+/// The area is generated in rows of tiles. Every other tile of a row is generated at once on
+/// all cores so no two tiles place features into the same chunks concurrently. The border
+/// chunks of finished tiles stay resident until the next row is done, features of the tiles
+/// next to them see them like chunks of players
+pub async fn pregenerate(
+    server: Arc<ServerData>,
+    dimension: Dimension,
+    center: (i32, i32),
+    radius: i32,
+    mut report: impl FnMut(&PregenProgress),
+) -> Result<PregenProgress, String> {
+    if !WORLD_STATES.get().unwrap().contains_key(dimension.name()) {
+        return Err(format!("No generator for dimension {}", dimension.name()));
+    }
+    let total = chunk_count(radius).ok_or_else(|| format!("Invalid radius {radius}"))?;
+    let bounds = |center: i32| Some((center.checked_sub(radius)?, center.checked_add(radius)?));
+    let (Some((min_x, max_x)), Some((min_z, max_z))) = (bounds(center.0), bounds(center.1)) else {
+        return Err(format!("Radius {radius} reaches outside of the world"));
+    };
+    if PREGEN_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("Chunks are already being pregenerated".to_string());
+    }
+    let mut guard = PregenGuard {
+        chunk_map: server.dispatcher.chunk_map.clone(),
+        dimension,
+        previous_row_borders: Vec::new(),
+        row_borders: Vec::new(),
+    };
+    let width = 2 * radius as usize + 1;
+    let start = Instant::now();
+    let mut progress = PregenProgress {
+        dimension,
+        total,
+        done: 0,
+        skipped: 0,
+        elapsed: Duration::ZERO,
+    };
+    // Decorated protos of the next row of tiles and the protos around them are kept until it is
+    // generated
+    LudiChunkLoader::reserve_proto_chunks((width + TILE_SIZE as usize) * 4);
+    let parallelism = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let mut last_report = Instant::now();
+    report(&progress);
+    for tile_z in min_z.div_euclid(TILE_SIZE)..=max_z.div_euclid(TILE_SIZE) {
+        for phase in 0..2 {
+            let mut tiles = (min_x.div_euclid(TILE_SIZE)..=max_x.div_euclid(TILE_SIZE))
+                .filter(|tile_x| tile_x.rem_euclid(2) == phase);
+            let mut tasks = JoinSet::new();
+            let mut error = None;
+            loop {
+                // After a failed tile only the running tiles are awaited, their border chunks
+                // are held until then
+                while error.is_none() && tasks.len() < parallelism {
+                    let Some(tile_x) = tiles.next() else {
+                        break;
+                    };
+                    let (from_x, from_z) = (tile_x * TILE_SIZE, tile_z * TILE_SIZE);
+                    let bounds = (
+                        from_x.max(min_x),
+                        (from_x + TILE_SIZE - 1).min(max_x),
+                        from_z.max(min_z),
+                        (from_z + TILE_SIZE - 1).min(max_z),
+                    );
+                    tasks.spawn(generate_tile(server.clone(), dimension, bounds));
+                }
+                let Some(result) = tasks.join_next().await else {
+                    break;
+                };
+                let (tile_size, skipped, borders) =
+                    match result.map_err(|e| e.to_string()).and_then(|tile| tile) {
+                        Ok(tile) => tile,
+                        Err(e) => {
+                            error.get_or_insert(e);
+                            continue;
+                        }
+                    };
+                progress.done += tile_size;
+                progress.skipped += skipped;
+                guard.row_borders.extend(borders);
+                if last_report.elapsed() >= REPORT_INTERVAL {
+                    progress.elapsed = start.elapsed();
+                    report(&progress);
+                    last_report = Instant::now();
+                }
+            }
+            if let Some(e) = error {
+                return Err(format!("Pregenerating {} failed: {e}", dimension.name()));
+            }
+        }
+        guard.finish_row();
+    }
+    drop(guard);
+    progress.elapsed = start.elapsed();
+    report(&progress);
+    Ok(progress)
+}
+
+/// Generates and saves the missing chunks of a tile, returns the number of chunks in the tile,
+/// how many of them were skipped and the border chunks kept resident
+async fn generate_tile(
+    server: Arc<ServerData>,
+    dimension: Dimension,
+    (min_x, max_x, min_z, max_z): (i32, i32, i32, i32),
+) -> Result<(usize, usize, Vec<u64>), String> {
+    let region_manager = server.dispatcher.region_manager.clone();
+    let chunk_map = server.dispatcher.chunk_map.clone();
+    let region = LudiChunkLoader::pack_coords(LudiChunkLoader::chunk_to_region(min_x, min_z));
+    let stored = region_manager.stored_chunks(dimension, region).await;
+    let tile_size = ((max_x - min_x + 1) * (max_z - min_z + 1)) as usize;
+    let coords = missing_chunks(&stored, (min_x, max_x, min_z, max_z), |coord| {
+        chunk_map
+            .get(dimension, coord)
+            .is_some_and(|load| load.is_loaded())
+    });
+    let skipped = tile_size - coords.len();
+    if coords.is_empty() {
+        return Ok((tile_size, skipped, Vec::new()));
+    }
+    let chunks = {
+        let server = server.clone();
+        let chunk_map = chunk_map.clone();
+        let coords = coords.clone();
+        tokio::task::spawn_blocking(move || {
+            let world_state = &WORLD_STATES.get().unwrap()[dimension.name()];
+            LudiChunkLoader::generate_batch(
+                world_state,
+                dimension,
+                &server.dimension_settings,
+                &coords,
+                &chunk_map,
+            )
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    };
+    let chunks = chunks.into_iter().map(Arc::new).collect::<Vec<_>>();
+    let mut borders = Vec::new();
+    for chunk in &chunks {
+        if chunk.x != min_x && chunk.x != max_x && chunk.z != min_z && chunk.z != max_z {
+            continue;
+        }
+        let coord = LudiChunkLoader::pack_coords((chunk.x, chunk.z));
+        let (load, is_new) = chunk_map.add_ticket(dimension, coord);
+        if is_new {
+            load.complete(Some(chunk.clone()));
+        }
+        borders.push(coord);
+    }
+    region_manager
+        .write_region_chunks(dimension, region, chunks)
+        .await;
+    LudiChunkLoader::forget_finished_protos(dimension, coords);
+    Ok((tile_size, skipped, borders))
+}
+
+/// Chunks of a tile that are neither stored in its region nor resident, `stored` holds whether
+/// each chunk of the region is stored
+fn missing_chunks(
+    stored: &[bool],
+    (min_x, max_x, min_z, max_z): (i32, i32, i32, i32),
+    is_resident: impl Fn(u64) -> bool,
+) -> Vec<u64> {
+    let mut coords = Vec::new();
+    for z in min_z..=max_z {
+        for x in min_x..=max_x {
+            let (region_x, region_z) = LudiChunkLoader::chunk_to_region_relative(x, z);
+            let coord = LudiChunkLoader::pack_coords((x, z));
+            if !stored[(region_x + region_z * 32) as usize] && !is_resident(coord) {
+                coords.push(coord);
+            }
+        }
+    }
+    coords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::chunk_system::{Chunk, ChunkSection};
+    use crate::server::logger::{ServerLogger, LOGGER};
+    use crate::server::region::RegionManager;
+
+    #[test]
+    fn progress_estimates_the_remaining_time() {
+        let mut progress = PregenProgress {
+            dimension: Dimension::Overworld,
+            total: 100,
+            done: 30,
+            skipped: 30,
+            elapsed: Duration::from_secs(10),
+        };
+        // Skipped chunks say nothing about the generation speed
+        assert_eq!(progress.eta(), None);
+        assert!(progress.describe().ends_with("ETA unknown"));
+        progress.done = 50;
+        assert_eq!(progress.eta(), Some(Duration::from_secs(25)));
+        assert_eq!(
+            progress.describe(),
+            "Pregenerating minecraft:overworld: 50/100 chunks (50.0%, 30 already generated), \
+             ETA 0h 00m 25s"
+        );
+    }
+
+    #[test]
+    fn radius_is_limited() {
+        assert_eq!(chunk_count(0), Some(1));
+        assert_eq!(chunk_count(2), Some(25));
+        assert_eq!(chunk_count(MAX_RADIUS), Some(3_750_001 * 3_750_001));
+        assert_eq!(chunk_count(MAX_RADIUS + 1), None);
+        assert_eq!(chunk_count(i32::MAX), None);
+        assert_eq!(chunk_count(-1), None);
+    }
+
+    #[tokio::test]
+    async fn stored_and_resident_chunks_are_skipped() {
+        LOGGER.get_or_init(ServerLogger::new);
+        let dir = std::env::temp_dir().join(format!("ludicrous-pregen-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let regions = RegionManager::new(dir.to_str().unwrap().to_string(), 1 << 20);
+        let chunk = |x, z| {
+            Arc::new(Chunk {
+                sections: (-4..20).map(ChunkSection::air).collect(),
+                x,
+                y: -4,
+                z,
+                ..Default::default()
+            })
+        };
+        let region = LudiChunkLoader::pack_coords((-1, 0));
+        regions
+            .write_region_chunks(
+                Dimension::Overworld,
+                region,
+                vec![chunk(-32, 0), chunk(-31, 1)],
+            )
+            .await;
+        let stored = regions.stored_chunks(Dimension::Overworld, region).await;
+        let resident = LudiChunkLoader::pack_coords((-32, 1));
+        let missing = missing_chunks(&stored, (-32, -31, 0, 1), |coord| coord == resident);
+        assert_eq!(missing, vec![LudiChunkLoader::pack_coords((-31, 0))]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn guard_releases_the_borders() {
+        let chunk_map = ChunkMap::new();
+        PREGEN_RUNNING.store(true, Ordering::SeqCst);
+        let mut guard = PregenGuard {
+            chunk_map: chunk_map.clone(),
+            dimension: Dimension::Overworld,
+            previous_row_borders: Vec::new(),
+            row_borders: Vec::new(),
+        };
+        for coord in 0..4 {
+            chunk_map.add_ticket(Dimension::Overworld, coord);
+        }
+        guard.row_borders.extend([0, 1]);
+        guard.finish_row();
+        guard.row_borders.extend([2, 3]);
+        assert_eq!(chunk_map.len(), 4);
+        // The borders of the first row are released once the next row is done
+        guard.finish_row();
+        assert_eq!(chunk_map.len(), 2);
+        drop(guard);
+        assert_eq!(chunk_map.len(), 0);
+        assert!(!is_running());
+    }
+}
@@ -3,6 +3,8 @@ pub trait RandomGenerator {
     fn next_i32_range(&mut self, max: u32) -> i32;
     fn next_i64(&mut self) -> i64;
     fn next_f64(&mut self) -> f64;
+    fn next_f32(&mut self) -> f32;
+    fn next_bool(&mut self) -> bool;
     fn skip(&mut self, amount: usize);
     fn branch(&mut self) -> Box<dyn RandomGenerator>;
     fn branch_positional(&mut self) -> Box<dyn RandomPositionalGenerator>;
@@ -14,8 +16,14 @@ pub trait RandomPositionalGenerator: Send + Sync {
     where
         Self: Sized,
     {
-        let pos_hash = (x as i64 * 3129871) ^ (z as i64 * 116129781) ^ (y as i64);
-        ((pos_hash * pos_hash * 42317861) + (pos_hash * 11)) >> 16
+        // The x multiplication overflows as an int like vanilla
+        let pos_hash =
+            (x.wrapping_mul(3129871) as i64) ^ (z as i64).wrapping_mul(116129781) ^ (y as i64);
+        pos_hash
+            .wrapping_mul(pos_hash)
+            .wrapping_mul(42317861)
+            .wrapping_add(pos_hash.wrapping_mul(11))
+            >> 16
     }
 
     fn pos_to_rand(&self, x: i32, y: i32, z: i32) -> Box<dyn RandomGenerator>;
@@ -41,15 +49,19 @@ pub struct XoroshiroPositional {
 
 impl Xoroshiro {
     pub fn new_from_i64(seed: i64) -> Box<dyn RandomGenerator> {
+        Box::new(Self::from_seed(seed))
+    }
+
+    pub fn from_seed(seed: i64) -> Self {
         let mut seed_128 = Self::to_128bit(seed);
         if (seed_128.0 | seed_128.1) == 0 {
             seed_128.0 = -7046029254386353131;
             seed_128.1 = 7640891576956012809;
         }
-        Box::new(Xoroshiro {
+        Xoroshiro {
             seed_lo: seed_128.0,
             seed_hi: seed_128.1,
-        })
+        }
     }
 
     #[inline(always)]
@@ -108,6 +120,14 @@ impl RandomGenerator for Xoroshiro {
         self.next_bits(53) as f64 * 1.110223E-16f32 as f64
     }
 
+    fn next_f32(&mut self) -> f32 {
+        self.next_bits(24) as f32 * 5.9604645E-8f32
+    }
+
+    fn next_bool(&mut self) -> bool {
+        self.next_i64() & 1 != 0
+    }
+
     fn skip(&mut self, amount: usize) {
         for _ in 0..amount {
             self.next_i64();
@@ -161,12 +181,43 @@ pub struct LCG48Positional {
 
 impl LCG48 {
     pub fn new(seed: i64) -> Box<dyn RandomGenerator> {
-        Box::new(Self { seed })
+        Box::new(Self::from_seed(seed))
+    }
+
+    /// Scrambles the seed the same way as `java.util.Random`
+    pub fn from_seed(seed: i64) -> Self {
+        Self {
+            seed: (seed ^ 25214903917) & ((1 << 48) - 1),
+        }
+    }
+
+    /// Random of the carvers and structures starting in a chunk, like vanilla's
+    /// `WorldgenRandom.setLargeFeatureSeed`
+    pub fn with_large_feature_seed(seed: i64, chunk_x: i32, chunk_z: i32) -> Self {
+        let mut random = Self::from_seed(seed);
+        let x_factor = random.next_i64();
+        let z_factor = random.next_i64();
+        Self::from_seed(
+            (chunk_x as i64).wrapping_mul(x_factor)
+                ^ (chunk_z as i64).wrapping_mul(z_factor)
+                ^ seed,
+        )
+    }
+
+    /// Random of structure placements, like vanilla's `WorldgenRandom.setLargeFeatureWithSalt`
+    pub fn with_large_feature_salt(seed: i64, x: i32, z: i32, salt: i32) -> Self {
+        Self::from_seed(
+            (x as i64)
+                .wrapping_mul(341873128712)
+                .wrapping_add((z as i64).wrapping_mul(132897987541))
+                .wrapping_add(seed)
+                .wrapping_add(salt as i64),
+        )
     }
 
     pub fn next(&mut self, bits: u32) -> i32 {
         self.seed = (self.seed.wrapping_mul(25214903917).wrapping_add(11)) & ((1 << 48) - 1);
-        self.seed as i32 >> (48 - bits)
+        (self.seed >> (48 - bits)) as i32
     }
 }
 
@@ -177,7 +228,8 @@ impl RandomGenerator for LCG48 {
 
     fn next_i32_range(&mut self, max: u32) -> i32 {
         if max & (max - 1) == 0 {
-            (max as i32 * self.next(31)) >> 31
+            // Multiplied as a long like vanilla
+            ((max as i64 * self.next(31) as i64) >> 31) as i32
         } else {
             let mut upper: i32;
             let mut lower: i32;
@@ -198,6 +250,14 @@ impl RandomGenerator for LCG48 {
         (((self.next(26) as i64) << 27) + self.next(27) as i64) as f64 * 1.110223E-16f32 as f64
     }
 
+    fn next_f32(&mut self) -> f32 {
+        self.next(24) as f32 * 5.9604645E-8f32
+    }
+
+    fn next_bool(&mut self) -> bool {
+        self.next(1) != 0
+    }
+
     fn skip(&mut self, amount: usize) {
         for _ in 0..amount {
             self.next_i32();
@@ -205,9 +265,7 @@ impl RandomGenerator for LCG48 {
     }
 
     fn branch(&mut self) -> Box<dyn RandomGenerator> {
-        Box::new(LCG48 {
-            seed: self.next_i64(),
-        })
+        LCG48::new(self.next_i64())
     }
 
     fn branch_positional(&mut self) -> Box<dyn RandomPositionalGenerator> {
@@ -219,9 +277,7 @@ impl RandomGenerator for LCG48 {
 
 impl RandomPositionalGenerator for LCG48Positional {
     fn pos_to_rand(&self, x: i32, y: i32, z: i32) -> Box<dyn RandomGenerator> {
-        Box::new(LCG48 {
-            seed: Self::seed_from_pos(x, y, z) ^ self.seed,
-        })
+        LCG48::new(Self::seed_from_pos(x, y, z) ^ self.seed)
     }
 
     fn hash_to_rand(&self, value: &str) -> Box<dyn RandomGenerator> {
@@ -229,8 +285,102 @@ impl RandomPositionalGenerator for LCG48Positional {
         let hash = value.chars().enumerate().fold(0u32, |acc, (i, c)| {
             acc.wrapping_add((c as u32).wrapping_mul(31u32.wrapping_pow((len - (i + 1)) as u32)))
         });
-        Box::new(LCG48 {
-            seed: hash as i64 ^ self.seed,
-        })
+        // Sign extended like the `String.hashCode` int
+        LCG48::new(hash as i32 as i64 ^ self.seed)
+    }
+}
+
+/// Random of the feature decoration, like vanilla's `WorldgenRandom` over a xoroshiro source.
+/// Every value is built from the upper bits of the xoroshiro output the way `java.util.Random`
+/// builds them from its own bits
+pub struct WorldgenRandom {
+    source: Xoroshiro,
+}
+
+impl WorldgenRandom {
+    pub fn new(seed: i64) -> Self {
+        Self {
+            source: Xoroshiro::from_seed(seed),
+        }
+    }
+
+    pub fn set_seed(&mut self, seed: i64) {
+        self.source = Xoroshiro::from_seed(seed);
+    }
+
+    /// Seeds the random for a chunk whose corner block is at `min_x`, `min_z` and returns the
+    /// resulting decoration seed
+    pub fn set_decoration_seed(&mut self, seed: i64, min_x: i32, min_z: i32) -> i64 {
+        self.set_seed(seed);
+        let x_factor = self.next_i64() | 1;
+        let z_factor = self.next_i64() | 1;
+        let decoration_seed =
+            ((min_x as i64).wrapping_mul(x_factor) + (min_z as i64).wrapping_mul(z_factor)) ^ seed;
+        self.set_seed(decoration_seed);
+        decoration_seed
+    }
+
+    /// Seeds the random for the feature at `index` of the decoration `step`
+    pub fn set_feature_seed(&mut self, decoration_seed: i64, index: usize, step: usize) {
+        self.set_seed(
+            decoration_seed
+                .wrapping_add(index as i64)
+                .wrapping_add(10000 * step as i64),
+        );
+    }
+
+    fn next(&mut self, bits: i32) -> i32 {
+        self.source.next_bits(bits) as i32
+    }
+}
+
+impl RandomGenerator for WorldgenRandom {
+    fn next_i32(&mut self) -> i32 {
+        self.next(32)
+    }
+
+    fn next_i32_range(&mut self, max: u32) -> i32 {
+        if max & (max - 1) == 0 {
+            ((max as i64 * self.next(31) as i64) >> 31) as i32
+        } else {
+            let mut upper: i32;
+            let mut lower: i32;
+            while {
+                upper = self.next(31);
+                lower = upper % max as i32;
+                upper - lower + (max as i32 - 1) < 0
+            } {}
+            lower
+        }
+    }
+
+    fn next_i64(&mut self) -> i64 {
+        ((self.next(32) as i64) << 32).wrapping_add(self.next(32) as i64)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (((self.next(26) as i64) << 27) + self.next(27) as i64) as f64 * 1.110223E-16f32 as f64
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        self.next(24) as f32 * 5.9604645E-8f32
+    }
+
+    fn next_bool(&mut self) -> bool {
+        self.next(1) != 0
+    }
+
+    fn skip(&mut self, amount: usize) {
+        for _ in 0..amount {
+            self.next_i32();
+        }
+    }
+
+    fn branch(&mut self) -> Box<dyn RandomGenerator> {
+        self.source.branch()
+    }
+
+    fn branch_positional(&mut self) -> Box<dyn RandomPositionalGenerator> {
+        self.source.branch_positional()
     }
 }
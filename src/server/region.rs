@@ -1,140 +1,557 @@
 use super::chunk_system::{Chunk, LudiChunkLoader};
-use dashmap::DashMap;
+use super::dimension::Dimension;
+use super::heightmap::{self, HeightmapType};
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use super::metrics::METRICS;
+use dashmap::{mapref::entry::Entry, DashMap};
 use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::ZlibEncoder;
 use lz4::Decoder as Lz4Decoder;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::hash::Hash;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::sync::{
     atomic::{AtomicU64, AtomicUsize, Ordering},
     Arc,
 };
+use tokio::sync::RwLock;
+
+const SECTOR_SIZE: usize = 4096;
+/// Location table and timestamp table
+const HEADER_SECTORS: usize = 2;
+/// Sector counts are stored in a single byte of the location table
+const MAX_CHUNK_SECTORS: usize = 255;
+const COMPRESSION_GZIP: u8 = 1;
+const COMPRESSION_ZLIB: u8 = 2;
+const COMPRESSION_NONE: u8 = 3;
+const COMPRESSION_LZ4: u8 = 4;
+/// Followed by the namespaced id of the algorithm, no custom algorithms are supported
+const COMPRESSION_CUSTOM: u8 = 127;
+/// Set on the compression type when the chunk data is stored in a `c.X.Z.mcc` file
+const EXTERNAL_CHUNK_FLAG: u8 = 128;
+/// Generation steps vanilla stores as the `Status` of a chunk
+const CHUNK_STATUSES: [&str; 12] = [
+    "empty",
+    "structure_starts",
+    "structure_references",
+    "biomes",
+    "noise",
+    "surface",
+    "carvers",
+    "features",
+    "initialize_light",
+    "light",
+    "spawn",
+    "full",
+];
 
 #[derive(Debug)]
 pub struct RegionManager {
     pub level_name: String,
     pub start_time: std::time::Instant,
-    /// Must be higher than the number of server shards to avoid a deadlock situation
-    cache_capacity: usize,
+    /// Maximum bytes held by cached regions, regions that are in use are never evicted so a
+    /// region that does not fit is read without being cached
+    cache_budget: usize,
     pub cache: DashMap<RegionKey, Arc<CachedRegion>>,
+    /// Bytes held by cached regions, updated whenever a region or chunk data is cached or a
+    /// region is removed from the cache
+    cached_bytes: AtomicUsize,
+    /// Writers hold the write lock of a region while readers hold the read lock while reading
+    /// from the region file, so a region file is never read while partially written
+    file_locks: DashMap<RegionKey, Arc<RwLock<()>>>,
 }
 
 impl RegionManager {
-    pub fn new(level_name: String, cache_capacity: usize) -> Arc<Self> {
+    pub fn new(level_name: String, cache_budget: usize) -> Arc<Self> {
+        METRICS.region_cache_budget.set(cache_budget as i64);
         Arc::new(Self {
             level_name,
             start_time: std::time::Instant::now(),
-            // Must be higher than the number of server shards to avoid a deadlock situation
-            cache_capacity,
-            cache: DashMap::with_capacity(cache_capacity),
+            cache_budget,
+            cache: DashMap::new(),
+            cached_bytes: AtomicUsize::new(0),
+            file_locks: DashMap::new(),
         })
     }
 
+    /// Returns the loaded chunks, the indexes of chunks that are not stored and the indexes of
+    /// chunks that are stored but corrupt
     pub async fn get_region_chunks(
         &self,
-        dim: i32,
+        dim: Dimension,
         coord: u64,
         chunks_relative_idx: Vec<usize>,
-    ) -> (Vec<Arc<Chunk>>, Vec<usize>) {
-        let key = RegionKey::new(dim, coord);
-        let cached_region = self.cache.get(&key);
-        let region = if let Some(r) = cached_region {
+    ) -> (Vec<Arc<Chunk>>, Vec<usize>, Vec<usize>) {
+        let key = RegionKey::new(dim.id(), coord);
+        // Held until the chunks are read since chunk sectors are read from the file on demand
+        let _file_guard = self.file_lock(&key).read_owned().await;
+        let cached_region = self.cache.get(&key).map(|r| {
+            // Pinned while the cache shard is locked so it can not be evicted in between
             r.in_use.fetch_add(1, Ordering::SeqCst);
             r.last_use.store(self.calc_curr_time(), Ordering::Relaxed);
             r.clone()
+        });
+        let region = if let Some(r) = cached_region {
+            METRICS.region_cache_hits.inc();
+            r
         } else {
-            // if over capacity, evict least recently used
-            // goes over capacity if no evictable lru
-            if self.cache.len() >= self.cache_capacity {
-                self.remove_lru();
-            }
-            let (rx, rz) = LudiChunkLoader::unpack_coords(coord);
-            let region_file = File::open(format!("{}/region/r.{rx}.{rz}.mca", self.level_name));
-            if let Ok(file) = region_file {
-                let r = CachedRegion::new(file, self.calc_curr_time());
-                self.cache.insert(key, r.clone());
-                r
+            METRICS.region_cache_misses.inc();
+            let region_file = File::open(self.region_path(dim, coord))
+                .and_then(|file| CachedRegion::new(file, self.calc_curr_time()));
+            let r = match region_file {
+                Ok(r) => r,
+                Err(e) => {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        let (rx, rz) = LudiChunkLoader::unpack_coords(coord);
+                        LOGGER.get().unwrap().println_as(
+                            &format!("Could not open region ({rx}, {rz}): {e}"),
+                            LogDomain::LudiLoader,
+                            LogLevel::Warn,
+                        );
+                    }
+                    return (vec![], chunks_relative_idx, vec![]);
+                }
+            };
+            // If every cached region is in use the region is read without being cached rather
+            // than exceeding the budget
+            if self.make_room(r.size()) {
+                match self.cache.entry(key.clone()) {
+                    Entry::Occupied(entry) => {
+                        // Loaded by another task in the meantime
+                        let existing = entry.get().clone();
+                        existing.in_use.fetch_add(1, Ordering::SeqCst);
+                        existing
+                    }
+                    Entry::Vacant(entry) => {
+                        self.cached_bytes.fetch_add(r.size(), Ordering::SeqCst);
+                        entry.insert(r.clone());
+                        self.update_cache_metrics();
+                        r
+                    }
+                }
             } else {
-                return (vec![], chunks_relative_idx);
+                r
             }
         };
 
         let region_data = region.clone();
-        let (chunks, not_found) = tokio::task::spawn_blocking(move || {
+        let region_dir = self.region_dir(dim);
+        let corrupted_dir = self.corrupted_dir(dim);
+        let (chunks, not_found, corrupted, read_data) = tokio::task::spawn_blocking(move || {
             let mut not_found = Vec::new();
+            let mut corrupted = Vec::new();
             let mut chunks = Vec::new();
+            let mut read_data = Vec::new();
             for chunk_idx in chunks_relative_idx {
-                let location_idx = chunk_idx * 4;
-                let chunk_location = &region_data.data.get(location_idx..location_idx + 4);
-                if chunk_location.is_none() {
-                    not_found.push(chunk_idx);
-                    continue;
-                }
-                let chunk_location = chunk_location.unwrap();
-                let offset = ((chunk_location[0] as usize) << 16)
-                    | ((chunk_location[1] as usize) << 8)
-                    | chunk_location[2] as usize;
-                if offset == 0 || chunk_location[3] == 0 {
-                    not_found.push(chunk_idx);
-                    continue;
-                }
-                let full_chunk_data = &region_data.data.get(offset * 4096..);
-                if full_chunk_data.is_none() {
-                    not_found.push(chunk_idx);
-                    continue;
-                }
-                let full_chunk_data = full_chunk_data.unwrap();
-                let mut len_bytes = [0u8; 4];
-                len_bytes.copy_from_slice(&full_chunk_data[0..4]);
-                let length = u32::from_be_bytes(len_bytes) as usize;
-                let compression_type = full_chunk_data[4];
-                let raw_data = &full_chunk_data[5..(length + 5)];
-                let mut data = vec![];
-                match compression_type {
-                    1 => {
-                        GzDecoder::new(raw_data).read_to_end(&mut data).unwrap();
-                    }
-                    2 => {
-                        ZlibDecoder::new(raw_data).read_to_end(&mut data).unwrap();
+                let chunk_coord = LudiChunkLoader::chunk_idx_to_coord(chunk_idx, coord);
+                match Self::load_chunk(
+                    &region_data,
+                    chunk_idx,
+                    chunk_coord,
+                    &region_dir,
+                    &mut read_data,
+                ) {
+                    Ok(Some(chunk)) => chunks.push(Arc::new(chunk)),
+                    Ok(None) => not_found.push(chunk_idx),
+                    Err(corrupt) => {
+                        let (rx, rz) = LudiChunkLoader::unpack_coords(coord);
+                        LOGGER.get().unwrap().println_as(
+                            &format!(
+                                "Corrupt chunk ({}, {}) in region ({rx}, {rz}): {}",
+                                chunk_coord.0, chunk_coord.1, corrupt.reason
+                            ),
+                            LogDomain::LudiLoader,
+                            LogLevel::Warn,
+                        );
+                        Self::quarantine_chunk(
+                            &corrupted_dir,
+                            chunk_coord,
+                            region_data.timestamp(chunk_idx),
+                            &corrupt.raw_data,
+                        );
+                        corrupted.push(chunk_idx);
                     }
-                    3 => {
-                        data = raw_data.to_vec();
-                    }
-                    4 => {
-                        Lz4Decoder::new(raw_data)
-                            .unwrap()
-                            .read_to_end(&mut data)
-                            .unwrap();
-                    }
-                    _ => {
-                        not_found.push(chunk_idx);
-                        continue;
-                    }
-                }
-                if data.is_empty() {
-                    not_found.push(chunk_idx);
-                    continue;
-                }
-                let chunk = Chunk::from_data(&data);
-                if chunk.is_err() {
-                    not_found.push(chunk_idx);
-                    continue;
                 }
-                let chunk = Arc::new(chunk.unwrap());
-                if chunk.heightmaps.world_surface.is_none() {
-                    not_found.push(chunk_idx);
-                    continue;
-                }
-                chunks.push(chunk);
             }
-            (chunks, not_found)
+            (chunks, not_found, corrupted, read_data)
         })
         .await
         .unwrap();
 
+        // Keep the raw chunk data while it fits, only for regions that are still cached
+        let is_cached = self
+            .cache
+            .get(&key)
+            .is_some_and(|r| Arc::ptr_eq(&r, &region));
+        if is_cached {
+            for (chunk_idx, raw_data) in read_data {
+                if !self.make_room(raw_data.len()) {
+                    break;
+                }
+                // The entry is held while the data is added so the region is not removed from
+                // the cache before its bytes are counted
+                let Some(_cached) = self
+                    .cache
+                    .get(&key)
+                    .filter(|r| Arc::ptr_eq(r.value(), &region))
+                else {
+                    break;
+                };
+                let added = region.cache_chunk_data(chunk_idx, raw_data);
+                self.cached_bytes.fetch_add(added, Ordering::SeqCst);
+            }
+            self.update_cache_metrics();
+        }
+
         region.in_use.fetch_sub(1, Ordering::SeqCst);
+        METRICS.chunks_loaded.add(chunks.len() as u64);
         // Signals tasks waiting for available region reading
-        (chunks, not_found)
+        (chunks, not_found, corrupted)
+    }
+
+    /// Whether each chunk of a region is stored, by chunk index. Only the location table is read
+    /// so stored chunks are not checked for corruption
+    pub async fn stored_chunks(&self, dim: Dimension, coord: u64) -> Vec<bool> {
+        let key = RegionKey::new(dim.id(), coord);
+        let _file_guard = self.file_lock(&key).read_owned().await;
+        let mut locations = vec![0u8; SECTOR_SIZE];
+        let read = File::open(self.region_path(dim, coord))
+            .and_then(|mut file| file.read_exact(&mut locations));
+        if read.is_err() {
+            return vec![false; 1024];
+        }
+        (0..1024)
+            .map(|idx| Self::read_location(&locations, idx).1 != 0)
+            .collect()
+    }
+
+    /// Reads and parses a single chunk, returns `None` if the chunk has not been saved yet
+    fn load_chunk(
+        region: &CachedRegion,
+        chunk_idx: usize,
+        chunk_coord: (i32, i32),
+        region_dir: &str,
+        read_data: &mut Vec<(usize, Arc<[u8]>)>,
+    ) -> Result<Option<Chunk>, CorruptChunk> {
+        let stored = region
+            .read_chunk_data(chunk_idx)
+            .map_err(|reason| CorruptChunk {
+                reason,
+                raw_data: Vec::new(),
+            })?;
+        let Some((raw_data, from_file)) = stored else {
+            return Ok(None);
+        };
+        if from_file {
+            read_data.push((chunk_idx, raw_data.clone()));
+        }
+        let external_data;
+        let (compression_type, compressed) = if raw_data[0] & EXTERNAL_CHUNK_FLAG == 0 {
+            (raw_data[0], &raw_data[1..])
+        } else {
+            // Chunk is stored in its own file since it did not fit in the region
+            let path = Self::external_chunk_path(region_dir, chunk_coord);
+            external_data = std::fs::read(&path).map_err(|e| CorruptChunk {
+                reason: format!("could not read external chunk {path}: {e}"),
+                raw_data: raw_data.to_vec(),
+            })?;
+            (raw_data[0] & !EXTERNAL_CHUNK_FLAG, &external_data[..])
+        };
+        let corrupt = |reason: String| CorruptChunk {
+            reason,
+            raw_data: [&[compression_type], compressed].concat(),
+        };
+        let data = Self::decompress_chunk(compression_type, compressed).map_err(corrupt)?;
+        let mut chunk =
+            Chunk::from_data(&data).map_err(|e| corrupt(format!("invalid chunk nbt: {e}")))?;
+        if (chunk.x, chunk.z) != chunk_coord {
+            return Err(corrupt(format!(
+                "chunk is stored as ({}, {})",
+                chunk.x, chunk.z
+            )));
+        }
+        // Only chunks without a known status are generated again. Chunks vanilla saved before
+        // they were fully generated are loaded as they are, regenerating them would write over
+        // their progress
+        let status = chunk
+            .status
+            .as_deref()
+            .map(|s| s.trim_start_matches("minecraft:"));
+        if !status.is_some_and(|status| CHUNK_STATUSES.contains(&status)) {
+            return Ok(None);
+        }
+        if HeightmapType::ALL
+            .iter()
+            .any(|heightmap| chunk.heightmaps.get(*heightmap).is_none())
+        {
+            heightmap::compute_heightmaps(&mut chunk);
+        }
+        Ok(Some(chunk))
+    }
+
+    /// Saves the stored bytes of a corrupt chunk, named after the chunk and the time it was saved
+    /// so the same corrupt data is only kept once
+    fn quarantine_chunk(
+        corrupted_dir: &str,
+        chunk_coord: (i32, i32),
+        timestamp: u32,
+        raw_data: &[u8],
+    ) {
+        if raw_data.is_empty() {
+            return;
+        }
+        let path = format!(
+            "{corrupted_dir}c.{}.{}.{timestamp}.bin",
+            chunk_coord.0, chunk_coord.1
+        );
+        if std::fs::exists(&path).unwrap_or(false) {
+            return;
+        }
+        let result =
+            std::fs::create_dir_all(corrupted_dir).and_then(|_| std::fs::write(&path, raw_data));
+        match result {
+            Ok(_) => LOGGER.get().unwrap().println_as(
+                &format!("Saved corrupt chunk data to {path}"),
+                LogDomain::LudiLoader,
+                LogLevel::Warn,
+            ),
+            Err(e) => LOGGER.get().unwrap().println_as(
+                &format!("Could not save corrupt chunk data to {path}: {e}"),
+                LogDomain::LudiLoader,
+                LogLevel::Error,
+            ),
+        }
+    }
+
+    fn decompress_chunk(compression_type: u8, raw_data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut data = vec![];
+        let result = match compression_type {
+            COMPRESSION_GZIP => GzDecoder::new(raw_data).read_to_end(&mut data),
+            COMPRESSION_ZLIB => ZlibDecoder::new(raw_data).read_to_end(&mut data),
+            COMPRESSION_NONE => {
+                data = raw_data.to_vec();
+                Ok(data.len())
+            }
+            COMPRESSION_LZ4 => {
+                Lz4Decoder::new(raw_data).and_then(|mut decoder| decoder.read_to_end(&mut data))
+            }
+            COMPRESSION_CUSTOM => {
+                // Custom compression starts with the namespaced id of the algorithm
+                let name_len = raw_data
+                    .get(0..2)
+                    .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+                    .unwrap_or(0);
+                let name = raw_data
+                    .get(2..2 + name_len)
+                    .map(String::from_utf8_lossy)
+                    .unwrap_or_default();
+                return Err(format!("unsupported custom compression {name}"));
+            }
+            _ => return Err(format!("unknown compression type {compression_type}")),
+        };
+        result.map_err(|e| format!("could not decompress: {e}"))?;
+        Ok(data)
+    }
+
+    /// Saves chunks of a single region, replacing any previous version of those chunks
+    pub async fn write_region_chunks(&self, dim: Dimension, coord: u64, chunks: Vec<Arc<Chunk>>) {
+        let key = RegionKey::new(dim.id(), coord);
+        let file_lock = self.file_lock(&key);
+        let _file_guard = file_lock.write().await;
+        let path = self.region_path(dim, coord);
+        let region_dir = self.region_dir(dim);
+        let result = tokio::task::spawn_blocking(move || {
+            Self::write_chunks_to_file(&path, &region_dir, &chunks)
+        })
+        .await
+        .unwrap();
+        // Cached copy is outdated, tasks still holding it keep reading the old data
+        if let Some((_, region)) = self.cache.remove(&key) {
+            self.uncache(&region);
+            self.update_cache_metrics();
+        }
+        if let Err(e) = result {
+            let (rx, rz) = LudiChunkLoader::unpack_coords(coord);
+            LOGGER.get().unwrap().println_as(
+                &format!("Could not save chunks to region ({rx}, {rz}): {e}"),
+                LogDomain::LudiLoader,
+                LogLevel::Error,
+            );
+        }
+    }
+
+    fn write_chunks_to_file(
+        path: &str,
+        region_dir: &str,
+        chunks: &[Arc<Chunk>],
+    ) -> std::io::Result<()> {
+        if let Some(dir) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or(0);
+        let open = || {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        };
+        let mut file = open()?;
+        let file_len = file.metadata()?.len() as usize;
+        let mut header = vec![0u8; HEADER_SECTORS * SECTOR_SIZE];
+        if file_len >= header.len() {
+            file.read_exact(&mut header)?;
+        } else if file_len > 0 {
+            // The chunks of a file with a truncated header can not be located anymore, the file
+            // is kept for recovery instead of being overwritten
+            drop(file);
+            let backup = format!("{path}.{timestamp}.corrupt");
+            std::fs::rename(path, &backup)?;
+            LOGGER.get().unwrap().println_as(
+                &format!("Region file {path} has a truncated header, moved it to {backup}"),
+                LogDomain::LudiLoader,
+                LogLevel::Error,
+            );
+            file = open()?;
+        }
+
+        // Sectors of chunks being replaced stay marked until the next write so the header on
+        // disk never points at partially written data
+        let chunk_indexes: Vec<usize> = chunks
+            .iter()
+            .map(|c| {
+                let (x, z) = LudiChunkLoader::chunk_to_region_relative(c.x, c.z);
+                x as usize + z as usize * 32
+            })
+            .collect();
+        let mut used_sectors = vec![true; HEADER_SECTORS];
+        for idx in 0..1024 {
+            let (offset, count) = Self::read_location(&header, idx);
+            if offset < HEADER_SECTORS || count == 0 {
+                continue;
+            }
+            if used_sectors.len() < offset + count {
+                used_sectors.resize(offset + count, false);
+            }
+            used_sectors[offset..offset + count].fill(true);
+        }
+
+        // External files of chunks that fit in the region again, removed once the header no
+        // longer points at them
+        let mut stale_external = Vec::new();
+        for (chunk, idx) in chunks.iter().zip(chunk_indexes) {
+            let nbt = chunk
+                .to_data()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+            let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&nbt)?;
+            let compressed = encoder.finish()?;
+            let mut payload = Vec::with_capacity(compressed.len() + 5);
+            payload.extend((compressed.len() as u32 + 1).to_be_bytes());
+            payload.push(COMPRESSION_ZLIB);
+            payload.extend(compressed);
+            let external_path = Self::external_chunk_path(region_dir, (chunk.x, chunk.z));
+            if payload.len().div_ceil(SECTOR_SIZE) > MAX_CHUNK_SECTORS {
+                // Too large for the region, store the data in its own file like vanilla. The file
+                // is replaced as a whole so the header never points at a partially written file
+                let temp_path = format!("{external_path}.tmp");
+                let mut external = File::create(&temp_path)?;
+                external.write_all(&payload[5..])?;
+                external.sync_data()?;
+                std::fs::rename(&temp_path, &external_path)?;
+                payload.truncate(4);
+                payload.copy_from_slice(&1u32.to_be_bytes());
+                payload.push(COMPRESSION_ZLIB | EXTERNAL_CHUNK_FLAG);
+            } else if std::fs::exists(&external_path).unwrap_or(false) {
+                stale_external.push(external_path);
+            }
+            let sector_count = payload.len().div_ceil(SECTOR_SIZE);
+            payload.resize(sector_count * SECTOR_SIZE, 0);
+
+            let offset = Self::allocate_sectors(&mut used_sectors, sector_count);
+            file.seek(SeekFrom::Start((offset * SECTOR_SIZE) as u64))?;
+            file.write_all(&payload)?;
+
+            let location = ((offset as u32) << 8) | sector_count as u32;
+            header[idx * 4..idx * 4 + 4].copy_from_slice(&location.to_be_bytes());
+            let timestamp_idx = SECTOR_SIZE + idx * 4;
+            header[timestamp_idx..timestamp_idx + 4].copy_from_slice(&timestamp.to_be_bytes());
+        }
+        file.sync_data()?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header)?;
+        file.sync_data()?;
+        for path in stale_external {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Returns (sector offset, sector count) of a chunk in the location table
+    fn read_location(header: &[u8], idx: usize) -> (usize, usize) {
+        let location = &header[idx * 4..idx * 4 + 4];
+        let offset =
+            ((location[0] as usize) << 16) | ((location[1] as usize) << 8) | location[2] as usize;
+        (offset, location[3] as usize)
+    }
+
+    fn allocate_sectors(used_sectors: &mut Vec<bool>, count: usize) -> usize {
+        let mut start = HEADER_SECTORS;
+        while start < used_sectors.len() {
+            let end = (start + count).min(used_sectors.len());
+            match used_sectors[start..end].iter().position(|used| *used) {
+                Some(used) => start += used + 1,
+                None => break,
+            }
+        }
+        if used_sectors.len() < start + count {
+            used_sectors.resize(start + count, false);
+        }
+        used_sectors[start..start + count].fill(true);
+        start
+    }
+
+    fn region_dir(&self, dim: Dimension) -> String {
+        format!("{}/{}region", self.level_name, dim.folder())
+    }
+
+    fn corrupted_dir(&self, dim: Dimension) -> String {
+        format!("{}/corrupted/{}", self.level_name, dim.folder())
+    }
+
+    fn region_path(&self, dim: Dimension, coord: u64) -> String {
+        let (rx, rz) = LudiChunkLoader::unpack_coords(coord);
+        format!("{}/r.{rx}.{rz}.mca", self.region_dir(dim))
+    }
+
+    fn external_chunk_path(region_dir: &str, chunk_coord: (i32, i32)) -> String {
+        format!("{region_dir}/c.{}.{}.mcc", chunk_coord.0, chunk_coord.1)
+    }
+
+    fn file_lock(&self, key: &RegionKey) -> Arc<RwLock<()>> {
+        self.file_locks.entry(key.clone()).or_default().clone()
+    }
+
+    /// Drops the locks of regions no task is reading or writing, a task holds a clone of the
+    /// lock from `file_lock` until it is done with the file
+    fn prune_file_locks(&self) {
+        self.file_locks
+            .retain(|_, lock| Arc::strong_count(lock) > 1);
+    }
+
+    /// Evicts least recently used regions until `additional` bytes fit in the cache budget,
+    /// returns false if the budget can not be met because the remaining regions are in use
+    fn make_room(&self, additional: usize) -> bool {
+        while self.cached_bytes() + additional > self.cache_budget {
+            if !self.remove_lru() {
+                return false;
+            }
+        }
+        true
     }
 
     /// Remove least recently used cached region
@@ -146,13 +563,35 @@ impl RegionManager {
                 del_regions.push((entry.key().clone(), entry.last_use.load(Ordering::Relaxed)));
             }
         });
-        if del_regions.is_empty() {
-            return false;
-        }
-        // Delete oldest cached region
+        // Delete oldest cached region, a region may have been pinned since the list was made so
+        // the check is repeated while the cache shard is locked
         del_regions.sort_by_key(|(_, val)| *val);
-        self.cache.remove(&del_regions.first().unwrap().0);
-        true
+        for (key, _) in del_regions {
+            let removed = self
+                .cache
+                .remove_if(&key, |_, region| region.in_use.load(Ordering::SeqCst) == 0);
+            if let Some((_, region)) = removed {
+                self.uncache(&region);
+                METRICS.region_cache_evictions.inc();
+                self.update_cache_metrics();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn cached_bytes(&self) -> usize {
+        self.cached_bytes.load(Ordering::SeqCst)
+    }
+
+    /// Accounts for a region that was removed from the cache
+    fn uncache(&self, region: &CachedRegion) {
+        self.cached_bytes.fetch_sub(region.size(), Ordering::SeqCst);
+    }
+
+    fn update_cache_metrics(&self) {
+        METRICS.region_cache_size.set(self.cache.len() as i64);
+        METRICS.region_cache_bytes.set(self.cached_bytes() as i64);
     }
 
     pub fn spawn_stale_checker(manager: Arc<Self>) {
@@ -161,16 +600,19 @@ impl RegionManager {
                 let curr_time = manager.calc_curr_time();
                 manager.cache.retain(|_key, value| {
                     // If region is in use by more than 1 task or is less than 60 seconds old, keep it
-                    value.in_use.load(Ordering::SeqCst) != 0
-                        || curr_time - value.last_use.load(Ordering::Relaxed) < 60
+                    let keep = value.in_use.load(Ordering::SeqCst) != 0
+                        || curr_time - value.last_use.load(Ordering::Relaxed) < 60;
+                    if !keep {
+                        manager.uncache(value);
+                        METRICS.region_cache_evictions.inc();
+                    }
+                    keep
                 });
-                // while exceeding capacity, remove least recently used
+                manager.update_cache_metrics();
+                // while exceeding the budget, remove least recently used
                 // if there is no evictable lru, break
-                while manager.cache.len() >= manager.cache_capacity {
-                    if !manager.remove_lru() {
-                        break;
-                    }
-                }
+                manager.make_room(0);
+                manager.prune_file_locks();
                 tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
             }
         });
@@ -185,23 +627,121 @@ impl RegionManager {
 
 #[derive(Debug)]
 pub struct CachedRegion {
-    pub data: Vec<u8>,
+    file: File,
+    file_len: u64,
+    /// Location and timestamp tables, chunk sectors are only read when requested
+    header: Vec<u8>,
+    /// Compression type followed by the raw chunk data, by chunk index
+    chunk_data: DashMap<usize, Arc<[u8]>>,
+    chunk_data_size: AtomicUsize,
     pub in_use: AtomicUsize,
     pub last_use: AtomicU64,
 }
 
 impl CachedRegion {
-    pub fn new(mut file: File, last_use: u64) -> Arc<Self> {
-        let mut data = Vec::new();
-        file.read_to_end(&mut data).unwrap();
-        Arc::new(Self {
-            data,
+    pub fn new(file: File, last_use: u64) -> std::io::Result<Arc<Self>> {
+        let file_len = file.metadata()?.len();
+        // A truncated header is padded with zeros so the missing chunks read as absent
+        let mut header = vec![0u8; HEADER_SECTORS * SECTOR_SIZE];
+        let header_len = header.len().min(file_len as usize);
+        read_exact_at(&file, &mut header[..header_len], 0)?;
+        Ok(Arc::new(Self {
+            file,
+            file_len,
+            header,
+            chunk_data: DashMap::new(),
+            chunk_data_size: AtomicUsize::new(0),
             in_use: AtomicUsize::new(1),
             last_use: AtomicU64::new(last_use),
-        })
+        }))
+    }
+
+    /// Bytes held in memory for this region
+    pub fn size(&self) -> usize {
+        self.header.len() + self.chunk_data_size.load(Ordering::Relaxed)
+    }
+
+    /// Returns the compression type followed by the raw chunk data and whether it was read from
+    /// the file, or `None` if the chunk is not stored in this region
+    fn read_chunk_data(&self, chunk_idx: usize) -> Result<Option<(Arc<[u8]>, bool)>, String> {
+        if let Some(data) = self.chunk_data.get(&chunk_idx) {
+            return Ok(Some((data.clone(), false)));
+        }
+        let (offset, count) = RegionManager::read_location(&self.header, chunk_idx);
+        if count == 0 {
+            return Ok(None);
+        }
+        if offset < HEADER_SECTORS {
+            return Err(format!(
+                "chunk data overlaps the region header at sector {offset}"
+            ));
+        }
+        // Bounds checked against truncated region files
+        let start = (offset * SECTOR_SIZE) as u64;
+        if start + 4 > self.file_len {
+            return Err(format!(
+                "chunk data at sector {offset} is past the end of the file"
+            ));
+        }
+        let mut length = [0u8; 4];
+        read_exact_at(&self.file, &mut length, start).map_err(|e| e.to_string())?;
+        // Length includes the compression type byte
+        let length = u32::from_be_bytes(length) as u64;
+        if length == 0 || length + 4 > (count * SECTOR_SIZE) as u64 {
+            return Err(format!("invalid chunk length {length} for {count} sectors"));
+        }
+        if start + 4 + length > self.file_len {
+            return Err("chunk data is truncated".to_string());
+        }
+        let mut data = vec![0u8; length as usize];
+        read_exact_at(&self.file, &mut data, start + 4).map_err(|e| e.to_string())?;
+        Ok(Some((data.into(), true)))
+    }
+
+    /// Time the chunk was last saved in seconds since the epoch
+    fn timestamp(&self, chunk_idx: usize) -> u32 {
+        let idx = SECTOR_SIZE + chunk_idx * 4;
+        u32::from_be_bytes(self.header[idx..idx + 4].try_into().unwrap())
+    }
+
+    /// Keeps the raw data of a chunk, returns the number of bytes added to the region
+    fn cache_chunk_data(&self, chunk_idx: usize, data: Arc<[u8]>) -> usize {
+        let len = data.len();
+        if self.chunk_data.insert(chunk_idx, data).is_some() {
+            return 0;
+        }
+        self.chunk_data_size.fetch_add(len, Ordering::Relaxed);
+        len
     }
 }
 
+/// Why a stored chunk could not be used
+#[derive(Debug)]
+struct CorruptChunk {
+    reason: String,
+    /// Compression type followed by the data as stored on disk
+    raw_data: Vec<u8>,
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buf, offset)? {
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
 #[derive(Eq, Debug, Clone)]
 pub struct RegionKey {
     pub dim: i32,
@@ -226,3 +766,272 @@ impl Hash for RegionKey {
         self.coord.hash(state);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::chunk_system::ChunkSection;
+    use crate::server::logger::ServerLogger;
+
+    /// Manager saving to an empty world in the temp directory
+    fn manager(name: &str, cache_budget: usize) -> Arc<RegionManager> {
+        LOGGER.get_or_init(ServerLogger::new);
+        let dir = std::env::temp_dir().join(format!("ludicrous-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        RegionManager::new(dir.to_str().unwrap().to_string(), cache_budget)
+    }
+
+    fn chunk(x: i32, z: i32) -> Arc<Chunk> {
+        Arc::new(Chunk {
+            sections: (-4..20).map(ChunkSection::air).collect(),
+            x,
+            y: -4,
+            z,
+            ..Default::default()
+        })
+    }
+
+    fn region(x: i32, z: i32) -> u64 {
+        LudiChunkLoader::pack_coords((x, z))
+    }
+
+    #[tokio::test]
+    async fn saved_chunks_are_loaded_back() {
+        let manager = manager("region-round-trip", 1 << 20);
+        let dim = Dimension::Overworld;
+        manager
+            .write_region_chunks(dim, region(-1, 0), vec![chunk(-32, 0), chunk(-31, 0)])
+            .await;
+        // Replacing a chunk keeps the other chunks of the region
+        manager
+            .write_region_chunks(dim, region(-1, 0), vec![chunk(-31, 0)])
+            .await;
+        let (chunks, not_found, corrupted) = manager
+            .get_region_chunks(dim, region(-1, 0), vec![0, 1, 2])
+            .await;
+        let mut coords = chunks.iter().map(|c| (c.x, c.z)).collect::<Vec<_>>();
+        coords.sort();
+        assert_eq!(coords, [(-32, 0), (-31, 0)]);
+        assert_eq!(not_found, [2]);
+        assert!(corrupted.is_empty());
+        assert_eq!(chunks[0].sections.len(), 24);
+        let stored = manager.stored_chunks(dim, region(-1, 0)).await;
+        assert_eq!(stored.iter().filter(|s| **s).count(), 2);
+        std::fs::remove_dir_all(&manager.level_name).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_chunks_without_a_known_status_are_generated_again() {
+        let manager = manager("region-status", 1 << 20);
+        let dim = Dimension::Overworld;
+        let with_status = |x: i32, status: &str| {
+            Arc::new(Chunk {
+                sections: (-4..20).map(ChunkSection::air).collect(),
+                x,
+                y: -4,
+                status: Some(status.to_string()),
+                ..Default::default()
+            })
+        };
+        manager
+            .write_region_chunks(
+                dim,
+                region(0, 0),
+                vec![
+                    chunk(0, 0),
+                    with_status(1, "minecraft:features"),
+                    with_status(2, "noise"),
+                    with_status(3, "minecraft:not_a_status"),
+                ],
+            )
+            .await;
+        let (chunks, not_found, corrupted) = manager
+            .get_region_chunks(dim, region(0, 0), vec![0, 1, 2, 3])
+            .await;
+        let mut statuses = chunks
+            .iter()
+            .map(|c| (c.x, c.status.clone().unwrap()))
+            .collect::<Vec<_>>();
+        statuses.sort();
+        assert_eq!(
+            statuses,
+            [
+                (0, "minecraft:full".to_string()),
+                (1, "minecraft:features".to_string()),
+                (2, "noise".to_string()),
+            ]
+        );
+        assert_eq!(not_found, [3]);
+        assert!(corrupted.is_empty());
+        std::fs::remove_dir_all(&manager.level_name).unwrap();
+    }
+
+    #[tokio::test]
+    async fn truncated_region_is_kept_aside() {
+        let manager = manager("region-truncated", 1 << 20);
+        let dim = Dimension::Overworld;
+        let path = manager.region_path(dim, region(0, 0));
+        std::fs::create_dir_all(manager.region_dir(dim)).unwrap();
+        std::fs::write(&path, [7u8; 100]).unwrap();
+        manager
+            .write_region_chunks(dim, region(0, 0), vec![chunk(0, 0)])
+            .await;
+        let backups = std::fs::read_dir(manager.region_dir(dim))
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".corrupt"))
+            .collect::<Vec<_>>();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(std::fs::read(backups[0].path()).unwrap(), [7u8; 100]);
+        let (chunks, _, _) = manager.get_region_chunks(dim, region(0, 0), vec![0]).await;
+        assert_eq!(chunks.len(), 1);
+        std::fs::remove_dir_all(&manager.level_name).unwrap();
+    }
+
+    #[tokio::test]
+    async fn oversized_chunks_are_stored_externally() {
+        let manager = manager("region-external", 1 << 20);
+        let dim = Dimension::Overworld;
+        // Random light data does not compress, so the chunk does not fit in 255 sectors
+        let mut random = rand::thread_rng();
+        let mut large = Chunk {
+            sections: (0..600)
+                .map(|_| ChunkSection {
+                    sky_light: Some(fastnbt::ByteArray::new(
+                        (0..2048).map(|_| rand::Rng::gen(&mut random)).collect(),
+                    )),
+                    ..ChunkSection::air(0)
+                })
+                .collect(),
+            x: 3,
+            z: 0,
+            ..Default::default()
+        };
+        heightmap::compute_heightmaps(&mut large);
+        manager
+            .write_region_chunks(dim, region(0, 0), vec![Arc::new(large)])
+            .await;
+        let external = RegionManager::external_chunk_path(&manager.region_dir(dim), (3, 0));
+        assert!(std::fs::exists(&external).unwrap());
+        assert!(!std::fs::exists(format!("{external}.tmp")).unwrap());
+        let (chunks, _, corrupted) = manager.get_region_chunks(dim, region(0, 0), vec![3]).await;
+        assert!(corrupted.is_empty());
+        assert_eq!(chunks[0].sections.len(), 600);
+
+        // The external file is removed once the chunk fits in the region again
+        manager
+            .write_region_chunks(dim, region(0, 0), vec![chunk(3, 0)])
+            .await;
+        assert!(!std::fs::exists(&external).unwrap());
+        let (chunks, _, _) = manager.get_region_chunks(dim, region(0, 0), vec![3]).await;
+        assert_eq!(chunks[0].sections.len(), 24);
+        std::fs::remove_dir_all(&manager.level_name).unwrap();
+    }
+
+    #[tokio::test]
+    async fn chunk_length_must_fit_its_sectors() {
+        let manager = manager("region-bounds", 1 << 20);
+        let dim = Dimension::Overworld;
+        manager
+            .write_region_chunks(dim, region(0, 0), vec![chunk(0, 0)])
+            .await;
+        let path = manager.region_path(dim, region(0, 0));
+        let data = std::fs::read(&path).unwrap();
+        let (offset, count) = RegionManager::read_location(&data, 0);
+        // Length prefix plus a length filling every sector is one sector too long
+        let mut corrupt = data.clone();
+        let start = offset * SECTOR_SIZE;
+        let length = (count * SECTOR_SIZE) as u32 - 3;
+        corrupt[start..start + 4].copy_from_slice(&length.to_be_bytes());
+        std::fs::write(&path, &corrupt).unwrap();
+        let region = CachedRegion::new(File::open(&path).unwrap(), 0).unwrap();
+        assert!(region.read_chunk_data(0).is_err());
+        std::fs::write(&path, &data).unwrap();
+        let region = CachedRegion::new(File::open(&path).unwrap(), 0).unwrap();
+        assert!(region.read_chunk_data(0).unwrap().is_some());
+        std::fs::remove_dir_all(&manager.level_name).unwrap();
+    }
+
+    #[tokio::test]
+    async fn unused_file_locks_are_pruned() {
+        let manager = manager("region-locks", 1 << 20);
+        let held = manager.file_lock(&RegionKey::new(0, 1));
+        manager.file_lock(&RegionKey::new(0, 2));
+        manager.prune_file_locks();
+        assert_eq!(manager.file_locks.len(), 1);
+        drop(held);
+        manager.prune_file_locks();
+        assert!(manager.file_locks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn region_cache_stays_within_its_budget() {
+        let header = HEADER_SECTORS * SECTOR_SIZE;
+        // Room for two headers and the data of a few chunks
+        let manager = manager("region-cache", header * 2 + 2048);
+        let dim = Dimension::Overworld;
+        for x in 0..3 {
+            manager
+                .write_region_chunks(dim, region(x, 0), vec![chunk(x * 32, 0)])
+                .await;
+        }
+        for x in 0..3 {
+            let (chunks, _, _) = manager.get_region_chunks(dim, region(x, 0), vec![0]).await;
+            assert_eq!(chunks.len(), 1);
+            assert!(manager.cached_bytes() <= header * 2 + 2048);
+        }
+        assert_eq!(manager.cache.len(), 2);
+        // The running count matches the regions left after evictions and writes
+        let count = |manager: &RegionManager| manager.cache.iter().map(|r| r.size()).sum::<usize>();
+        assert_eq!(manager.cached_bytes(), count(&manager));
+        let key = RegionKey::new(dim.id(), region(2, 0));
+        let cached = manager.cache.get(&key).unwrap().clone();
+        // The chunk data read last is served from memory
+        assert!(matches!(cached.read_chunk_data(0), Ok(Some((_, false)))));
+
+        // Regions being read from are never evicted
+        for entry in manager.cache.iter() {
+            entry.in_use.fetch_add(1, Ordering::SeqCst);
+        }
+        assert!(!manager.make_room(header * 2));
+        assert_eq!(manager.cache.len(), 2);
+        for entry in manager.cache.iter() {
+            entry.in_use.fetch_sub(1, Ordering::SeqCst);
+        }
+        manager
+            .write_region_chunks(dim, region(2, 0), vec![chunk(64, 1)])
+            .await;
+        assert_eq!(manager.cached_bytes(), count(&manager));
+        std::fs::remove_dir_all(&manager.level_name).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupt_chunks_are_quarantined() {
+        let manager = manager("region-corrupt", 1 << 20);
+        let dim = Dimension::Overworld;
+        manager
+            .write_region_chunks(dim, region(0, 0), vec![chunk(0, 0), chunk(1, 0)])
+            .await;
+        let path = manager.region_path(dim, region(0, 0));
+        let mut data = std::fs::read(&path).unwrap();
+        let (offset, _) = RegionManager::read_location(&data, 1);
+        // Garbage in place of the zlib stream of chunk (1, 0)
+        let start = offset * SECTOR_SIZE + 5;
+        data[start..start + 16].fill(0xAB);
+        std::fs::write(&path, &data).unwrap();
+        let (chunks, not_found, corrupted) = manager
+            .get_region_chunks(dim, region(0, 0), vec![0, 1])
+            .await;
+        assert_eq!(chunks.len(), 1);
+        assert!(not_found.is_empty());
+        assert_eq!(corrupted, [1]);
+        let quarantined = std::fs::read_dir(manager.corrupted_dir(dim))
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(quarantined.len(), 1);
+        assert!(quarantined[0].starts_with("c.1.0."));
+        std::fs::remove_dir_all(&manager.level_name).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+use super::super::util::clamped_map_f64;
+use super::jigsaw::JigsawJunction;
+use super::pool::Projection;
+use super::template::BoundingBox;
+use super::{StructureStart, TerrainAdjustment};
+use once_cell::sync::Lazy;
+use std::sync::Arc;
+
+/// Reach of the beard around pieces and junctions in blocks
+const BEARD_RANGE: i32 = 12;
+const KERNEL_SIZE: i32 = BEARD_RANGE * 2;
+
+/// Falloff of the beard around a block, indexed by z, x and y offsets from -12 to 11
+static BEARD_KERNEL: Lazy<Vec<f32>> = Lazy::new(|| {
+    let mut kernel = vec![0f32; (KERNEL_SIZE * KERNEL_SIZE * KERNEL_SIZE) as usize];
+    for z in 0..KERNEL_SIZE {
+        for x in 0..KERNEL_SIZE {
+            for y in 0..KERNEL_SIZE {
+                let (dx, dy, dz) = (
+                    (x - BEARD_RANGE) as f64,
+                    (y - BEARD_RANGE) as f64 + 0.5,
+                    (z - BEARD_RANGE) as f64,
+                );
+                let distance_squared = dx * dx + dy * dy + dz * dz;
+                kernel[(z * KERNEL_SIZE * KERNEL_SIZE + x * KERNEL_SIZE + y) as usize] =
+                    (-distance_squared / 16.0).exp() as f32;
+            }
+        }
+    }
+    kernel
+});
+
+/// Rigid piece the terrain is adapted around
+struct Rigid {
+    bounding_box: BoundingBox,
+    terrain_adjustment: TerrainAdjustment,
+    ground_level_delta: i32,
+}
+
+/// Density added to the terrain around structures so their pieces sit on ground and are not
+/// cut by it, like vanilla's `Beardifier`
+#[derive(Default)]
+pub struct Beardifier {
+    rigids: Vec<Rigid>,
+    junctions: Vec<JigsawJunction>,
+}
+
+impl Beardifier {
+    /// Collects the pieces and junctions of the starts reaching into a chunk that are close
+    /// enough to change its terrain
+    pub fn new(starts: &[Arc<StructureStart>], chunk_x: i32, chunk_z: i32) -> Self {
+        let (min_x, min_z) = (chunk_x * 16, chunk_z * 16);
+        let reach = BoundingBox {
+            min_x: min_x - BEARD_RANGE,
+            min_y: 0,
+            min_z: min_z - BEARD_RANGE,
+            max_x: min_x + 15 + BEARD_RANGE,
+            max_y: 0,
+            max_z: min_z + 15 + BEARD_RANGE,
+        };
+        let mut beardifier = Beardifier::default();
+        for start in starts {
+            if start.terrain_adaptation == TerrainAdjustment::None {
+                continue;
+            }
+            for piece in &start.pieces {
+                if !piece.bounding_box.intersects_xz(&reach) {
+                    continue;
+                }
+                if piece.element.projection == Projection::Rigid {
+                    beardifier.rigids.push(Rigid {
+                        bounding_box: piece.bounding_box,
+                        terrain_adjustment: start.terrain_adaptation,
+                        ground_level_delta: piece.ground_level_delta,
+                    });
+                }
+                beardifier
+                    .junctions
+                    .extend(piece.junctions.iter().filter(|junction| {
+                        junction.source_x > min_x - BEARD_RANGE
+                            && junction.source_z > min_z - BEARD_RANGE
+                            && junction.source_x < min_x + 15 + BEARD_RANGE
+                            && junction.source_z < min_z + 15 + BEARD_RANGE
+                    }));
+            }
+        }
+        beardifier
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rigids.is_empty() && self.junctions.is_empty()
+    }
+
+    /// Density added at a block position
+    pub fn compute(&self, x: i32, y: i32, z: i32) -> f64 {
+        let mut density = 0.0;
+        for rigid in &self.rigids {
+            let bounding_box = &rigid.bounding_box;
+            let dx = 0.max((bounding_box.min_x - x).max(x - bounding_box.max_x));
+            let dz = 0.max((bounding_box.min_z - z).max(z - bounding_box.max_z));
+            let ground = bounding_box.min_y + rigid.ground_level_delta;
+            let to_ground = y - ground;
+            density += match rigid.terrain_adjustment {
+                TerrainAdjustment::None => 0.0,
+                TerrainAdjustment::Bury => {
+                    bury_contribution(dx as f64, to_ground as f64 / 2.0, dz as f64)
+                }
+                TerrainAdjustment::BeardThin => {
+                    beard_contribution(dx, to_ground, dz, to_ground) * 0.8
+                }
+                TerrainAdjustment::BeardBox => {
+                    let dy = 0.max((ground - y).max(y - bounding_box.max_y));
+                    beard_contribution(dx, dy, dz, to_ground) * 0.8
+                }
+                TerrainAdjustment::Encapsulate => {
+                    let dy = 0.max((bounding_box.min_y - y).max(y - bounding_box.max_y));
+                    bury_contribution(dx as f64 / 2.0, dy as f64 / 2.0, dz as f64 / 2.0) * 0.8
+                }
+            };
+        }
+        for junction in &self.junctions {
+            let dy = y - junction.source_ground_y;
+            density +=
+                beard_contribution(x - junction.source_x, dy, z - junction.source_z, dy) * 0.4;
+        }
+        density
+    }
+}
+
+fn bury_contribution(x: f64, y: f64, z: f64) -> f64 {
+    clamped_map_f64((x * x + y * y + z * z).sqrt(), 0.0, 6.0, 1.0, 0.0)
+}
+
+fn beard_contribution(x: i32, y: i32, z: i32, to_ground: i32) -> f64 {
+    let (kernel_x, kernel_y, kernel_z) = (x + BEARD_RANGE, y + BEARD_RANGE, z + BEARD_RANGE);
+    let in_range = |i: i32| (0..KERNEL_SIZE).contains(&i);
+    if !(in_range(kernel_x) && in_range(kernel_y) && in_range(kernel_z)) {
+        return 0.0;
+    }
+    let dy = to_ground as f64 + 0.5;
+    let distance_squared = (x * x) as f64 + dy * dy + (z * z) as f64;
+    let falloff = -dy * fast_inv_sqrt(distance_squared / 2.0) / 2.0;
+    falloff
+        * BEARD_KERNEL
+            [(kernel_z * KERNEL_SIZE * KERNEL_SIZE + kernel_x * KERNEL_SIZE + kernel_y) as usize]
+            as f64
+}
+
+/// Vanilla's `Mth.fastInvSqrt`, kept for the same terrain
+fn fast_inv_sqrt(x: f64) -> f64 {
+    let half = 0.5 * x;
+    let estimate = f64::from_bits((6910469410427058090i64 - ((x.to_bits() as i64) >> 1)) as u64);
+    estimate * (1.5 - half * estimate * estimate)
+}
@@ -0,0 +1,551 @@
+use super::super::aquifer::Aquifer;
+use super::super::features::region::WorldHeightmap;
+use super::super::heightmap::{HeightmapType, HEIGHTMAP_BLOCKS};
+use super::super::logger::{LogDomain, LogLevel, LOGGER};
+use super::super::terrain_gen::func_deserialize::DensityFnArgs;
+use super::super::util::lerp_f64;
+use super::super::value_providers::HeightProvider;
+use super::super::world_state::WorldState;
+use super::pool::{
+    LiquidSettings, PoolAliasBinding, PoolAliases, PoolElement, Projection, TemplatePool,
+};
+use super::template::{BoundingBox, Rotation};
+use super::{StartContext, TEMPLATE_POOLS};
+use crate::{RandomGenerator, MAIN_DENSITY_FUNCTION};
+use ahash::AHashMap;
+use serde::Deserialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+const EMPTY_POOL: &str = "minecraft:empty";
+
+/// Connection between two pieces of a jigsaw structure, used to smooth the terrain around it
+#[derive(Clone, Copy, Debug)]
+pub struct JigsawJunction {
+    pub source_x: i32,
+    pub source_ground_y: i32,
+    pub source_z: i32,
+    pub delta_y: i32,
+    pub dest_projection: Projection,
+}
+
+/// Element of a template pool placed in the world
+pub struct PoolPiece {
+    pub element: Arc<PoolElement>,
+    pub position: (i32, i32, i32),
+    pub rotation: Rotation,
+    pub bounding_box: BoundingBox,
+    pub ground_level_delta: i32,
+    pub junctions: Vec<JigsawJunction>,
+    pub liquid_settings: LiquidSettings,
+}
+
+impl PoolPiece {
+    fn move_by(&mut self, y: i32) {
+        self.position.1 += y;
+        self.bounding_box = self.bounding_box.moved(0, y, 0);
+    }
+}
+
+/// Farthest distance from the start pieces may be placed at
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum MaxDistance {
+    Both(i32),
+    Split { horizontal: i32, vertical: i32 },
+}
+
+impl MaxDistance {
+    fn horizontal(self) -> i32 {
+        match self {
+            MaxDistance::Both(distance) => distance,
+            MaxDistance::Split { horizontal, .. } => horizontal,
+        }
+    }
+
+    fn vertical(self) -> i32 {
+        match self {
+            MaxDistance::Both(distance) => distance,
+            MaxDistance::Split { vertical, .. } => vertical,
+        }
+    }
+}
+
+/// Blocks kept free at the bottom and top of the world
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(untagged)]
+enum DimensionPadding {
+    #[default]
+    None,
+    Both(i32),
+    Split {
+        #[serde(default)]
+        bottom: i32,
+        #[serde(default)]
+        top: i32,
+    },
+}
+
+impl DimensionPadding {
+    fn bottom(self) -> i32 {
+        match self {
+            DimensionPadding::None => 0,
+            DimensionPadding::Both(padding) => padding,
+            DimensionPadding::Split { bottom, .. } => bottom,
+        }
+    }
+
+    fn top(self) -> i32 {
+        match self {
+            DimensionPadding::None => 0,
+            DimensionPadding::Both(padding) => padding,
+            DimensionPadding::Split { top, .. } => top,
+        }
+    }
+}
+
+/// Structure assembled from template pools by connecting jigsaws, like villages and pillager
+/// outposts
+#[derive(Deserialize)]
+pub struct JigsawStructure {
+    start_pool: String,
+    #[serde(default)]
+    start_jigsaw_name: Option<String>,
+    size: i32,
+    start_height: HeightProvider,
+    #[serde(default)]
+    use_expansion_hack: bool,
+    #[serde(default)]
+    project_start_to_heightmap: Option<WorldHeightmap>,
+    max_distance_from_center: MaxDistance,
+    #[serde(default)]
+    pool_aliases: Vec<PoolAliasBinding>,
+    #[serde(default)]
+    dimension_padding: DimensionPadding,
+    #[serde(default)]
+    liquid_settings: LiquidSettings,
+}
+
+impl JigsawStructure {
+    /// Assembles the pieces of a structure starting in a chunk, like vanilla's
+    /// `JigsawPlacement.addPieces`. No pieces are returned when the start is not in a biome of
+    /// the structure
+    pub fn generate(&self, context: &mut StartContext) -> Vec<PoolPiece> {
+        let mut heights = TerrainHeights::new(context.world_state, context.dimension);
+        let (min_y, height) = (context.min_y, context.height);
+        let start_y = self.start_height.sample(&mut context.random, min_y, height);
+        let start = (context.chunk_x * 16, start_y, context.chunk_z * 16);
+        let aliases = PoolAliases::new(&self.pool_aliases, start, context.world_state.seed);
+        let rotation = Rotation::random(&mut context.random);
+        let Some(pool) = TEMPLATE_POOLS.get(aliases.lookup(&self.start_pool)) else {
+            return Vec::new();
+        };
+        let Some(element) = pool
+            .random_template(&mut context.random)
+            .filter(|element| !element.is_empty())
+        else {
+            return Vec::new();
+        };
+        let offset = match &self.start_jigsaw_name {
+            Some(name) => {
+                let jigsaws = element.shuffled_jigsaws(start, rotation, &mut context.random);
+                let Some(jigsaw) = jigsaws.iter().find(|jigsaw| jigsaw.name == *name) else {
+                    LOGGER.get().unwrap().println_as(
+                        &format!(
+                            "No starting jigsaw {name} found in start pool {}",
+                            self.start_pool
+                        ),
+                        LogDomain::Server,
+                        LogLevel::Warn,
+                    );
+                    return Vec::new();
+                };
+                (
+                    jigsaw.pos.0 - start.0,
+                    jigsaw.pos.1 - start.1,
+                    jigsaw.pos.2 - start.2,
+                )
+            }
+            None => (0, 0, 0),
+        };
+        let position = (start.0 - offset.0, start.1 - offset.1, start.2 - offset.2);
+        let bounding_box = element.bounding_box(position, rotation);
+        let mut piece = PoolPiece {
+            ground_level_delta: element.ground_level_delta(),
+            element,
+            position,
+            rotation,
+            bounding_box,
+            junctions: Vec::new(),
+            liquid_settings: self.liquid_settings,
+        };
+        let center_x = (bounding_box.max_x + bounding_box.min_x) / 2;
+        let center_z = (bounding_box.max_z + bounding_box.min_z) / 2;
+        let surface = match self.project_start_to_heightmap {
+            Some(heightmap) => start.1 + heights.first_free_height(center_x, center_z, heightmap),
+            None => position.1,
+        };
+        let ground = bounding_box.min_y + piece.ground_level_delta;
+        piece.move_by(surface - ground);
+        let center_y = surface + offset.1;
+        if !context.is_valid_biome((center_x, center_y, center_z)) {
+            return Vec::new();
+        }
+        let mut pieces = vec![piece];
+        if self.size <= 0 {
+            return pieces;
+        }
+        let horizontal = self.max_distance_from_center.horizontal();
+        let vertical = self.max_distance_from_center.vertical();
+        let max_y = min_y + height - 1;
+        let bounds = BoundingBox {
+            min_x: center_x - horizontal,
+            min_y: (center_y - vertical).max(min_y + self.dimension_padding.bottom()),
+            min_z: center_z - horizontal,
+            max_x: center_x + horizontal,
+            max_y: (center_y + vertical).min(max_y - self.dimension_padding.top()),
+            max_z: center_z + horizontal,
+        };
+        let mut placer = Placer {
+            max_depth: self.size,
+            pieces: std::mem::take(&mut pieces),
+            spaces: vec![FreeSpace {
+                bounds,
+                taken: Vec::new(),
+            }],
+            placing: BTreeMap::new(),
+            random: &mut context.random,
+            heights: &mut heights,
+            aliases: &aliases,
+            liquid_settings: self.liquid_settings,
+            use_expansion_hack: self.use_expansion_hack,
+        };
+        let start_box = placer.pieces[0].bounding_box;
+        placer.spaces[0].taken.push(start_box);
+        placer.place_children(0, 0, 0);
+        // Pieces with a higher placement priority are extended first, the others in the order
+        // they were placed
+        while let Some(mut entry) = placer.placing.last_entry() {
+            let Some(state) = entry.get_mut().pop_front() else {
+                entry.remove();
+                continue;
+            };
+            placer.place_children(state.piece, state.space, state.depth);
+        }
+        placer.pieces
+    }
+}
+
+/// Space pieces can be placed in, inside the bounds and outside of every taken box
+struct FreeSpace {
+    bounds: BoundingBox,
+    taken: Vec<BoundingBox>,
+}
+
+impl FreeSpace {
+    fn fits(&self, bounding_box: &BoundingBox) -> bool {
+        bounding_box.is_within(&self.bounds)
+            && !self
+                .taken
+                .iter()
+                .any(|taken| taken.intersects(bounding_box))
+    }
+}
+
+/// Piece waiting for its jigsaws to be extended
+struct PieceState {
+    piece: usize,
+    space: usize,
+    depth: i32,
+}
+
+/// Extends the jigsaws of placed pieces, like vanilla's `JigsawPlacement.Placer`
+struct Placer<'a, 'b> {
+    max_depth: i32,
+    pieces: Vec<PoolPiece>,
+    spaces: Vec<FreeSpace>,
+    /// Pieces to extend by placement priority, in the order they were placed
+    placing: BTreeMap<i32, VecDeque<PieceState>>,
+    random: &'b mut dyn RandomGenerator,
+    heights: &'b mut TerrainHeights<'a>,
+    aliases: &'b PoolAliases,
+    liquid_settings: LiquidSettings,
+    use_expansion_hack: bool,
+}
+
+impl Placer<'_, '_> {
+    fn pool(&self, id: &str) -> Option<(&'static TemplatePool, bool)> {
+        let id = self.aliases.lookup(id);
+        TEMPLATE_POOLS.get(id).map(|pool| (pool, id == EMPTY_POOL))
+    }
+
+    /// Tries to attach a piece to every jigsaw of a placed piece, like vanilla's
+    /// `tryPlacingChildren`
+    fn place_children(&mut self, piece_index: usize, space: usize, depth: i32) {
+        let piece = &self.pieces[piece_index];
+        let element = piece.element.clone();
+        let (position, rotation) = (piece.position, piece.rotation);
+        let bounding_box = piece.bounding_box;
+        let ground_level_delta = piece.ground_level_delta;
+        let rigid = element.projection == Projection::Rigid;
+        let min_y = bounding_box.min_y;
+        // Space inside the piece, shared by the pieces placed in it
+        let mut inner_space = None;
+        'jigsaws: for jigsaw in element.shuffled_jigsaws(position, rotation, self.random) {
+            let front = jigsaw.front;
+            let target = front.offset(jigsaw.pos, 1);
+            let relative_y = jigsaw.pos.1 - min_y;
+            let mut surface = None;
+            let Some((pool, is_empty)) = self.pool(&jigsaw.pool) else {
+                self.warn_pool(&jigsaw.pool);
+                continue;
+            };
+            if pool.size() == 0 && !is_empty {
+                self.warn_pool(&jigsaw.pool);
+                continue;
+            }
+            let Some((fallback, fallback_is_empty)) = self.pool(&pool.fallback) else {
+                self.warn_pool(&pool.fallback);
+                continue;
+            };
+            if fallback.size() == 0 && !fallback_is_empty {
+                self.warn_pool(&pool.fallback);
+                continue;
+            }
+            let target_space = if bounding_box.is_inside(target) {
+                *inner_space.get_or_insert_with(|| {
+                    self.spaces.push(FreeSpace {
+                        bounds: bounding_box,
+                        taken: Vec::new(),
+                    });
+                    self.spaces.len() - 1
+                })
+            } else {
+                space
+            };
+            let mut candidates = Vec::new();
+            if depth != self.max_depth {
+                candidates.extend(pool.shuffled_templates(self.random));
+            }
+            candidates.extend(fallback.shuffled_templates(self.random));
+            for candidate in candidates {
+                if candidate.is_empty() {
+                    break;
+                }
+                for candidate_rotation in Rotation::shuffled(self.random) {
+                    let candidate_jigsaws =
+                        candidate.shuffled_jigsaws((0, 0, 0), candidate_rotation, self.random);
+                    let candidate_box = candidate.bounding_box((0, 0, 0), candidate_rotation);
+                    let expansion = if self.use_expansion_hack && candidate_box.y_span() <= 16 {
+                        candidate_jigsaws
+                            .iter()
+                            .map(|candidate_jigsaw| {
+                                if !candidate_box.is_inside(
+                                    candidate_jigsaw.front.offset(candidate_jigsaw.pos, 1),
+                                ) {
+                                    return 0;
+                                }
+                                let Some((pool, _)) = self.pool(&candidate_jigsaw.pool) else {
+                                    return 0;
+                                };
+                                let fallback_size = self
+                                    .pool(&pool.fallback)
+                                    .map_or(0, |(fallback, _)| fallback.max_size());
+                                pool.max_size().max(fallback_size)
+                            })
+                            .max()
+                            .unwrap_or(0)
+                    } else {
+                        0
+                    };
+                    for candidate_jigsaw in &candidate_jigsaws {
+                        if !jigsaw.can_attach(candidate_jigsaw) {
+                            continue;
+                        }
+                        let candidate_pos = candidate_jigsaw.pos;
+                        let offset = (
+                            target.0 - candidate_pos.0,
+                            target.1 - candidate_pos.1,
+                            target.2 - candidate_pos.2,
+                        );
+                        let placed_box = candidate.bounding_box(offset, candidate_rotation);
+                        let candidate_rigid = candidate.projection == Projection::Rigid;
+                        let candidate_y = candidate_pos.1;
+                        let delta_y = relative_y - candidate_y + front.step().1;
+                        let y = if rigid && candidate_rigid {
+                            min_y + delta_y
+                        } else {
+                            *surface.get_or_insert_with(|| {
+                                self.heights.first_free_height(
+                                    jigsaw.pos.0,
+                                    jigsaw.pos.2,
+                                    WorldHeightmap::WorldSurfaceWg,
+                                )
+                            }) - candidate_y
+                        };
+                        let shift = y - placed_box.min_y;
+                        let mut moved_box = placed_box.moved(0, shift, 0);
+                        let moved_pos = (offset.0, offset.1 + shift, offset.2);
+                        if expansion > 0 {
+                            let height = (expansion + 1).max(moved_box.max_y - moved_box.min_y);
+                            moved_box.encapsulate((
+                                moved_box.min_x,
+                                moved_box.min_y + height,
+                                moved_box.min_z,
+                            ));
+                        }
+                        if !self.spaces[target_space].fits(&moved_box) {
+                            continue;
+                        }
+                        self.spaces[target_space].taken.push(moved_box);
+                        let candidate_ground_delta = if candidate_rigid {
+                            ground_level_delta - delta_y
+                        } else {
+                            candidate.ground_level_delta()
+                        };
+                        let junction_y = if rigid {
+                            min_y + relative_y
+                        } else if candidate_rigid {
+                            y + candidate_y
+                        } else {
+                            *surface.get_or_insert_with(|| {
+                                self.heights.first_free_height(
+                                    jigsaw.pos.0,
+                                    jigsaw.pos.2,
+                                    WorldHeightmap::WorldSurfaceWg,
+                                )
+                            }) + delta_y / 2
+                        };
+                        self.pieces[piece_index].junctions.push(JigsawJunction {
+                            source_x: target.0,
+                            source_ground_y: junction_y - relative_y + ground_level_delta,
+                            source_z: target.2,
+                            delta_y,
+                            dest_projection: candidate.projection,
+                        });
+                        self.pieces.push(PoolPiece {
+                            element: candidate.clone(),
+                            position: moved_pos,
+                            rotation: candidate_rotation,
+                            bounding_box: moved_box,
+                            ground_level_delta: candidate_ground_delta,
+                            junctions: vec![JigsawJunction {
+                                source_x: jigsaw.pos.0,
+                                source_ground_y: junction_y - candidate_y + candidate_ground_delta,
+                                source_z: jigsaw.pos.2,
+                                delta_y: -delta_y,
+                                dest_projection: element.projection,
+                            }],
+                            liquid_settings: self.liquid_settings,
+                        });
+                        if depth < self.max_depth {
+                            self.placing
+                                .entry(jigsaw.placement_priority)
+                                .or_default()
+                                .push_back(PieceState {
+                                    piece: self.pieces.len() - 1,
+                                    space: target_space,
+                                    depth: depth + 1,
+                                });
+                        }
+                        continue 'jigsaws;
+                    }
+                }
+            }
+        }
+    }
+
+    fn warn_pool(&self, pool: &str) {
+        LOGGER.get().unwrap().println_as(
+            &format!("Empty or non-existent pool: {pool}"),
+            LogDomain::Server,
+            LogLevel::Warn,
+        );
+    }
+}
+
+/// Height of the terrain before chunks are generated, like vanilla's `getFirstFreeHeight` which
+/// runs the noise of a column. Densities are interpolated between noise cell corners like in
+/// generated chunks, the corners are kept for the whole structure start
+pub struct TerrainHeights<'a> {
+    world_state: &'a WorldState,
+    dimension: &'a str,
+    corners: AHashMap<(i32, i32, i32), f64>,
+    aquifers: AHashMap<(i32, i32), Aquifer<'a>>,
+}
+
+impl<'a> TerrainHeights<'a> {
+    pub fn new(world_state: &'a WorldState, dimension: &'a str) -> Self {
+        TerrainHeights {
+            world_state,
+            dimension,
+            corners: AHashMap::new(),
+            aquifers: AHashMap::new(),
+        }
+    }
+
+    fn corner(&mut self, x: i32, y: i32, z: i32) -> f64 {
+        let (world_state, dimension) = (self.world_state, self.dimension);
+        *self.corners.entry((x, y, z)).or_insert_with(|| {
+            world_state
+                .settings
+                .noise_router
+                .get(MAIN_DENSITY_FUNCTION)
+                .map_or(0.0, |density| {
+                    density.compute(&mut DensityFnArgs::new(x, y, z, dimension))
+                })
+        })
+    }
+
+    /// Height above the highest block of a column matching a heightmap, the bottom of the
+    /// world when none does
+    pub fn first_free_height(&mut self, x: i32, z: i32, heightmap: WorldHeightmap) -> i32 {
+        let heightmap = match heightmap {
+            WorldHeightmap::WorldSurfaceWg | WorldHeightmap::WorldSurface => {
+                HeightmapType::WorldSurface
+            }
+            WorldHeightmap::OceanFloorWg | WorldHeightmap::OceanFloor => HeightmapType::OceanFloor,
+            WorldHeightmap::MotionBlocking => HeightmapType::MotionBlocking,
+            WorldHeightmap::MotionBlockingNoLeaves => HeightmapType::MotionBlockingNoLeaves,
+        };
+        let noise = &self.world_state.settings.noise;
+        let min_y = noise.min_y;
+        if let Some(flat) = &self.world_state.flat {
+            return flat.first_free_height(heightmap, min_y);
+        }
+        let cell_width = noise.size_horizontal as i32 * 4;
+        let cell_height = noise.size_vertical as i32 * 4;
+        let (corner_x, corner_z) = (x - x.rem_euclid(cell_width), z - z.rem_euclid(cell_width));
+        let fx = x.rem_euclid(cell_width) as f64 / cell_width as f64;
+        let fz = z.rem_euclid(cell_width) as f64 / cell_width as f64;
+        let min_cell = min_y.div_euclid(cell_height);
+        let cells = noise.height.div_euclid(cell_height);
+        let (world_state, dimension) = (self.world_state, self.dimension);
+        for cell in (0..cells).rev() {
+            let bottom = (min_cell + cell) * cell_height;
+            let mut layer = |y: i32| {
+                let s00 = self.corner(corner_x, y, corner_z);
+                let s10 = self.corner(corner_x + cell_width, y, corner_z);
+                let s01 = self.corner(corner_x, y, corner_z + cell_width);
+                let s11 = self.corner(corner_x + cell_width, y, corner_z + cell_width);
+                lerp_f64(fz, lerp_f64(fx, s00, s10), lerp_f64(fx, s01, s11))
+            };
+            let (low, high) = (layer(bottom), layer(bottom + cell_height));
+            let aquifer = self
+                .aquifers
+                .entry((x >> 4, z >> 4))
+                .or_insert_with(|| Aquifer::new(world_state, x >> 4, z >> 4, dimension));
+            for offset in (0..cell_height).rev() {
+                let y = bottom + offset;
+                let density = lerp_f64(offset as f64 / cell_height as f64, low, high);
+                let block = aquifer
+                    .compute_substance(x, y, z, density)
+                    .unwrap_or(aquifer.default_block());
+                if heightmap.matches(&HEIGHTMAP_BLOCKS[block as usize]) {
+                    return y + 1;
+                }
+            }
+        }
+        min_y
+    }
+}
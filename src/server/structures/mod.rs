@@ -0,0 +1,631 @@
+pub mod beardifier;
+pub mod jigsaw;
+pub mod placement;
+pub mod pool;
+pub mod processor;
+pub mod template;
+
+use super::features::read_registry;
+use super::features::region::FeatureRegion;
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use super::randomness::{WorldgenRandom, LCG48};
+use super::tags::BiomeSet;
+use super::world_state::WorldState;
+use crate::RandomGenerator;
+use ahash::AHashMap;
+use jigsaw::{JigsawStructure, PoolPiece};
+use once_cell::sync::{Lazy, OnceCell};
+use placement::{PlacementKind, StructurePlacement};
+use pool::TemplatePool;
+use processor::ProcessorList;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use template::BoundingBox;
+
+/// Structure types found in the data pack that are not generated, reported once after loading
+static UNSUPPORTED_STRUCTURES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Structure processor types found in the data pack that are skipped, reported once after
+/// loading
+static UNSUPPORTED_PROCESSORS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Every structure of the data pack sorted by id, the order vanilla numbers the structures of a
+/// decoration step in
+static STRUCTURES: Lazy<BTreeMap<String, Structure>> = Lazy::new(|| {
+    let structures = read_registry("structure").into_iter().collect();
+    report_unsupported(
+        &UNSUPPORTED_STRUCTURES,
+        "Structures of these types are not generated",
+    );
+    structures
+});
+
+static STRUCTURE_SETS: Lazy<BTreeMap<String, StructureSet>> =
+    Lazy::new(|| read_registry("structure_set").into_iter().collect());
+
+static TEMPLATE_POOLS: Lazy<AHashMap<String, TemplatePool>> =
+    Lazy::new(|| read_registry("template_pool"));
+
+static PROCESSOR_LISTS: Lazy<AHashMap<String, ProcessorList>> = Lazy::new(|| {
+    let lists = read_registry("processor_list");
+    report_unsupported(
+        &UNSUPPORTED_PROCESSORS,
+        "Structure processors of these types are skipped",
+    );
+    lists
+});
+
+/// Number of decoration steps structures can be placed in
+pub const STRUCTURE_STEPS: usize = 11;
+
+/// Names of vanilla's `GenerationStep.Decoration`, structures are placed at the start of their
+/// step before the features of the step
+const DECORATION_STEPS: [&str; STRUCTURE_STEPS] = [
+    "raw_generation",
+    "lakes",
+    "local_modifications",
+    "underground_structures",
+    "surface_structures",
+    "strongholds",
+    "underground_ores",
+    "underground_decoration",
+    "fluid_springs",
+    "vegetal_decoration",
+    "top_layer_modification",
+];
+
+/// Number of structure starts kept in the start cache of a dimension
+const START_CACHE_CAPACITY: usize = 16384;
+
+/// Chunks around a chunk whose structure starts may reach into it, like vanilla's
+/// `STRUCTURE_REFERENCES` range
+const REFERENCE_RANGE: i32 = 8;
+
+/// Ids without a namespace are in the minecraft namespace
+pub fn namespaced(id: &str) -> String {
+    if id.contains(':') {
+        id.to_owned()
+    } else {
+        format!("minecraft:{id}")
+    }
+}
+
+fn report_unsupported(unsupported: &Mutex<BTreeSet<String>>, message: &str) {
+    let unsupported = std::mem::take(&mut *unsupported.lock().unwrap());
+    if !unsupported.is_empty() {
+        LOGGER.get().unwrap().println_as(
+            &format!(
+                "{message}: {}",
+                unsupported.into_iter().collect::<Vec<_>>().join(", ")
+            ),
+            LogDomain::Server,
+            LogLevel::Warn,
+        );
+    }
+}
+
+/// How the terrain is shaped around the pieces of a structure
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TerrainAdjustment {
+    #[default]
+    None,
+    Bury,
+    BeardThin,
+    BeardBox,
+    Encapsulate,
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "RawStructure")]
+pub struct Structure {
+    pub biomes: BiomeSet,
+    /// Index of the decoration step the structure is placed in
+    pub step: usize,
+    pub terrain_adaptation: TerrainAdjustment,
+    kind: StructureKind,
+}
+
+enum StructureKind {
+    Jigsaw(Box<JigsawStructure>),
+    Unsupported,
+}
+
+#[derive(Deserialize)]
+struct RawStructure {
+    #[serde(rename = "type")]
+    structure_type: String,
+    biomes: BiomeSet,
+    step: String,
+    #[serde(default)]
+    terrain_adaptation: TerrainAdjustment,
+    #[serde(flatten)]
+    config: serde_json::Value,
+}
+
+impl TryFrom<RawStructure> for Structure {
+    type Error = String;
+
+    fn try_from(raw: RawStructure) -> Result<Self, Self::Error> {
+        let step = DECORATION_STEPS
+            .iter()
+            .position(|step| *step == raw.step)
+            .ok_or_else(|| format!("unknown decoration step {}", raw.step))?;
+        let kind = match raw.structure_type.as_str() {
+            "minecraft:jigsaw" => StructureKind::Jigsaw(Box::new(
+                serde_json::from_value(raw.config).map_err(|e| e.to_string())?,
+            )),
+            _ => {
+                UNSUPPORTED_STRUCTURES
+                    .lock()
+                    .unwrap()
+                    .insert(raw.structure_type);
+                StructureKind::Unsupported
+            }
+        };
+        Ok(Structure {
+            biomes: raw.biomes,
+            step,
+            terrain_adaptation: raw.terrain_adaptation,
+            kind,
+        })
+    }
+}
+
+/// Structures sharing a placement, one of them is picked by weight for every start chunk
+#[derive(Deserialize)]
+pub struct StructureSet {
+    structures: Vec<StructureSetEntry>,
+    placement: StructurePlacement,
+}
+
+#[derive(Deserialize)]
+struct StructureSetEntry {
+    structure: String,
+    weight: i32,
+}
+
+/// State of the generation of a structure start, like vanilla's `Structure.GenerationContext`
+pub struct StartContext<'a> {
+    pub world_state: &'a WorldState,
+    pub dimension: &'a str,
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub random: LCG48,
+    pub min_y: i32,
+    pub height: i32,
+    biomes: &'a BiomeSet,
+}
+
+impl StartContext<'_> {
+    /// Whether the biome at a position is one the structure may start in
+    pub fn is_valid_biome(&self, (x, y, z): (i32, i32, i32)) -> bool {
+        self.world_state
+            .biome_source
+            .as_ref()
+            .and_then(|source| {
+                source.noise_biome(
+                    &self.world_state.settings.noise_router,
+                    self.dimension,
+                    x >> 2,
+                    y >> 2,
+                    z >> 2,
+                )
+            })
+            .is_some_and(|biome| self.biomes.contains(biome))
+    }
+}
+
+/// Structure generated in a chunk with all of its pieces
+pub struct StructureStart {
+    pub structure: &'static str,
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub pieces: Vec<PoolPiece>,
+    /// Box around the pieces, grown by the reach of the terrain adaptation
+    pub bounding_box: BoundingBox,
+    pub terrain_adaptation: TerrainAdjustment,
+}
+
+impl StructureStart {
+    fn new(structure: &'static str, chunk_x: i32, chunk_z: i32, pieces: Vec<PoolPiece>) -> Self {
+        let terrain_adaptation = STRUCTURES[structure].terrain_adaptation;
+        let bounding_box = pieces
+            .iter()
+            .map(|piece| piece.bounding_box)
+            .reduce(|a, b| a.union(&b))
+            .unwrap();
+        let bounding_box = if terrain_adaptation == TerrainAdjustment::None {
+            bounding_box
+        } else {
+            bounding_box.inflated(12)
+        };
+        StructureStart {
+            structure,
+            chunk_x,
+            chunk_z,
+            pieces,
+            bounding_box,
+            terrain_adaptation,
+        }
+    }
+
+    /// Start nbt as vanilla saves it in the chunk the structure starts in
+    fn to_nbt(&self) -> fastnbt::Value {
+        let pieces = self
+            .pieces
+            .iter()
+            .map(|piece| {
+                let junctions = piece
+                    .junctions
+                    .iter()
+                    .map(|junction| {
+                        fastnbt::Value::Compound(
+                            [
+                                ("source_x", fastnbt::Value::Int(junction.source_x)),
+                                (
+                                    "source_ground_y",
+                                    fastnbt::Value::Int(junction.source_ground_y),
+                                ),
+                                ("source_z", fastnbt::Value::Int(junction.source_z)),
+                                ("delta_y", fastnbt::Value::Int(junction.delta_y)),
+                                (
+                                    "dest_proj",
+                                    fastnbt::Value::String(
+                                        junction.dest_projection.name().to_owned(),
+                                    ),
+                                ),
+                            ]
+                            .into_iter()
+                            .map(|(key, value)| (key.to_owned(), value))
+                            .collect(),
+                        )
+                    })
+                    .collect();
+                fastnbt::Value::Compound(
+                    [
+                        ("id", fastnbt::Value::String("minecraft:jigsaw".to_owned())),
+                        (
+                            "BB",
+                            fastnbt::Value::IntArray(fastnbt::IntArray::new(
+                                piece.bounding_box.to_array().to_vec(),
+                            )),
+                        ),
+                        ("O", fastnbt::Value::Int(-1)),
+                        ("GD", fastnbt::Value::Int(0)),
+                        ("PosX", fastnbt::Value::Int(piece.position.0)),
+                        ("PosY", fastnbt::Value::Int(piece.position.1)),
+                        ("PosZ", fastnbt::Value::Int(piece.position.2)),
+                        (
+                            "ground_level_delta",
+                            fastnbt::Value::Int(piece.ground_level_delta),
+                        ),
+                        (
+                            "pool_element",
+                            fastnbt::to_value(&piece.element.json)
+                                .unwrap_or(fastnbt::Value::Compound(Default::default())),
+                        ),
+                        (
+                            "rotation",
+                            fastnbt::Value::String(piece.rotation.name().to_owned()),
+                        ),
+                        ("junctions", fastnbt::Value::List(junctions)),
+                        (
+                            "liquid_settings",
+                            fastnbt::Value::String(piece.liquid_settings.name().to_owned()),
+                        ),
+                    ]
+                    .into_iter()
+                    .map(|(key, value)| (key.to_owned(), value))
+                    .collect(),
+                )
+            })
+            .collect();
+        fastnbt::Value::Compound(
+            [
+                ("id", fastnbt::Value::String(self.structure.to_owned())),
+                ("ChunkX", fastnbt::Value::Int(self.chunk_x)),
+                ("ChunkZ", fastnbt::Value::Int(self.chunk_z)),
+                ("references", fastnbt::Value::Int(0)),
+                ("Children", fastnbt::Value::List(pieces)),
+            ]
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value))
+            .collect(),
+        )
+    }
+}
+
+/// Structure starts and references saved with a chunk, only written for vanilla. Starts are
+/// generated again from the seed when they are needed
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct ChunkStructures {
+    #[serde(default)]
+    starts: BTreeMap<String, fastnbt::Value>,
+    #[serde(rename = "References", default)]
+    references: BTreeMap<String, fastnbt::LongArray>,
+}
+
+impl ChunkStructures {
+    /// Saves the starts of a chunk and references every start reaching into it
+    pub fn new(starts: &[Arc<StructureStart>], chunk_x: i32, chunk_z: i32) -> Self {
+        let mut structures = ChunkStructures::default();
+        let mut references: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+        for start in starts {
+            if start.chunk_x == chunk_x && start.chunk_z == chunk_z {
+                structures
+                    .starts
+                    .insert(start.structure.to_owned(), start.to_nbt());
+            }
+            references
+                .entry(start.structure.to_owned())
+                .or_default()
+                .push((start.chunk_x as i64 & 0xffffffff) | ((start.chunk_z as i64) << 32));
+        }
+        structures.references = references
+            .into_iter()
+            .map(|(structure, chunks)| (structure, fastnbt::LongArray::new(chunks)))
+            .collect();
+        structures
+    }
+}
+
+/// Structure starts of a dimension, the oldest are dropped first once the cache is full
+#[derive(Default)]
+struct StartCache {
+    starts: AHashMap<(usize, i32, i32), Option<Arc<StructureStart>>>,
+    order: VecDeque<(usize, i32, i32)>,
+}
+
+/// Structure sets generated in a dimension and the starts generated so far
+pub struct WorldStructures {
+    /// Ids of the sets with a structure in the biomes of the dimension
+    sets: Vec<&'static str>,
+    /// Chunks of the concentric rings placements, computed when first needed
+    rings: AHashMap<&'static str, OnceCell<Vec<(i32, i32)>>>,
+    starts: Mutex<StartCache>,
+}
+
+impl std::fmt::Debug for WorldStructures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorldStructures")
+            .field("sets", &self.sets)
+            .finish()
+    }
+}
+
+impl WorldStructures {
+    /// Structures of a dimension placing biomes from `possible_biomes`, none when features are
+    /// not generated. Flat worlds may only allow some of the structure sets
+    pub fn new(
+        possible_biomes: &[u16],
+        generate_features: bool,
+        allowed_sets: Option<&[String]>,
+    ) -> Self {
+        let sets = if generate_features {
+            STRUCTURE_SETS
+                .iter()
+                .filter(|(id, _)| allowed_sets.is_none_or(|allowed| allowed.contains(id)))
+                .filter(|(_, set)| {
+                    set.structures.iter().any(|entry| {
+                        STRUCTURES.get(&entry.structure).is_some_and(|structure| {
+                            possible_biomes
+                                .iter()
+                                .any(|biome| structure.biomes.contains(*biome))
+                        })
+                    })
+                })
+                .map(|(id, _)| id.as_str())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let rings = STRUCTURE_SETS
+            .iter()
+            .filter(|(_, set)| matches!(set.placement.kind, PlacementKind::ConcentricRings { .. }))
+            .map(|(id, _)| (id.as_str(), OnceCell::new()))
+            .collect();
+        WorldStructures {
+            sets,
+            rings,
+            starts: Mutex::new(StartCache::default()),
+        }
+    }
+
+    /// Whether no structure set is generated in the dimension
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    /// Chunks a concentric rings set places its structures in
+    pub fn ring_chunks(
+        &self,
+        world_state: &WorldState,
+        dimension: &str,
+        set: &str,
+    ) -> &[(i32, i32)] {
+        let Some(rings) = self.rings.get(set) else {
+            return &[];
+        };
+        rings.get_or_init(|| {
+            STRUCTURE_SETS[set]
+                .placement
+                .ring_chunks(world_state, dimension)
+        })
+    }
+
+    /// Starts whose bounding box reaches into a chunk horizontally, like the references vanilla
+    /// saves in chunks
+    pub fn starts_referencing(
+        &self,
+        world_state: &WorldState,
+        dimension: &str,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> Vec<Arc<StructureStart>> {
+        let chunk_box = BoundingBox {
+            min_x: chunk_x * 16,
+            min_y: 0,
+            min_z: chunk_z * 16,
+            max_x: chunk_x * 16 + 15,
+            max_y: 0,
+            max_z: chunk_z * 16 + 15,
+        };
+        let mut starts = Vec::new();
+        for set in 0..self.sets.len() {
+            for x in chunk_x - REFERENCE_RANGE..=chunk_x + REFERENCE_RANGE {
+                for z in chunk_z - REFERENCE_RANGE..=chunk_z + REFERENCE_RANGE {
+                    if let Some(start) = self.start(world_state, dimension, set, x, z) {
+                        if start.bounding_box.intersects_xz(&chunk_box) {
+                            starts.push(start);
+                        }
+                    }
+                }
+            }
+        }
+        starts
+    }
+
+    /// Start of a set in a chunk, generated on the first request
+    fn start(
+        &self,
+        world_state: &WorldState,
+        dimension: &str,
+        set: usize,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> Option<Arc<StructureStart>> {
+        let set_id = self.sets[set];
+        // Only chunks the placement picks are cached, checking the placement is cheap
+        if !STRUCTURE_SETS[set_id].placement.is_structure_chunk(
+            world_state,
+            dimension,
+            set_id,
+            chunk_x,
+            chunk_z,
+        ) {
+            return None;
+        }
+        let key = (set, chunk_x, chunk_z);
+        if let Some(start) = self.starts.lock().unwrap().starts.get(&key) {
+            return start.clone();
+        }
+        // Generated without holding the lock, other threads generate the same start
+        let start =
+            Self::generate_start(world_state, dimension, set_id, chunk_x, chunk_z).map(Arc::new);
+        let mut cache = self.starts.lock().unwrap();
+        if cache.starts.insert(key, start.clone()).is_none() {
+            cache.order.push_back(key);
+        }
+        while cache.starts.len() > START_CACHE_CAPACITY {
+            let Some(oldest) = cache.order.pop_front() else {
+                break;
+            };
+            cache.starts.remove(&oldest);
+        }
+        start
+    }
+
+    /// Generates the structure of a set starting in a placement chunk, like vanilla's
+    /// `ChunkGenerator.createStructures`. Structures of sets with several structures are tried
+    /// in a random weighted order until one of them has a valid start
+    fn generate_start(
+        world_state: &WorldState,
+        dimension: &str,
+        set_id: &'static str,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> Option<StructureStart> {
+        let set = &STRUCTURE_SETS[set_id];
+        let try_generate = |entry: &StructureSetEntry| -> Result<Option<StructureStart>, ()> {
+            let Some((id, structure)) = STRUCTURES.get_key_value(&entry.structure) else {
+                return Ok(None);
+            };
+            let StructureKind::Jigsaw(jigsaw) = &structure.kind else {
+                return Err(());
+            };
+            let noise = &world_state.settings.noise;
+            let mut context = StartContext {
+                world_state,
+                dimension,
+                chunk_x,
+                chunk_z,
+                random: LCG48::with_large_feature_seed(world_state.seed, chunk_x, chunk_z),
+                min_y: noise.min_y,
+                height: noise.height,
+                biomes: &structure.biomes,
+            };
+            let pieces = jigsaw.generate(&mut context);
+            Ok((!pieces.is_empty()).then(|| StructureStart::new(id, chunk_x, chunk_z, pieces)))
+        };
+        if let [entry] = set.structures.as_slice() {
+            return try_generate(entry).ok().flatten();
+        }
+        let mut entries = set.structures.iter().collect::<Vec<_>>();
+        let mut random = LCG48::with_large_feature_seed(world_state.seed, chunk_x, chunk_z);
+        let mut total_weight = entries.iter().map(|entry| entry.weight).sum::<i32>();
+        while !entries.is_empty() && total_weight > 0 {
+            let mut remaining = random.next_i32_range(total_weight as u32);
+            let mut index = 0;
+            for entry in &entries {
+                remaining -= entry.weight;
+                if remaining < 0 {
+                    break;
+                }
+                index += 1;
+            }
+            let index = index.min(entries.len() - 1);
+            match try_generate(entries[index]) {
+                Ok(Some(start)) => return Some(start),
+                Ok(None) => {}
+                // The start of a structure that is not generated can not be known
+                Err(()) => return None,
+            }
+            total_weight -= entries.remove(index).weight;
+        }
+        None
+    }
+}
+
+/// Places the pieces of the structures of a decoration step reaching into the center chunk of a
+/// region, like the structure part of vanilla's `applyBiomeDecoration`
+pub fn place_structures(
+    region: &mut FeatureRegion,
+    starts: &[Arc<StructureStart>],
+    random: &mut WorldgenRandom,
+    decoration_seed: i64,
+    step: usize,
+) {
+    let chunk_box = BoundingBox {
+        min_x: region.center_x * 16,
+        min_y: region.min_y + 1,
+        min_z: region.center_z * 16,
+        max_x: region.center_x * 16 + 15,
+        max_y: region.max_y - 1,
+        max_z: region.center_z * 16 + 15,
+    };
+    let step_structures = STRUCTURES
+        .iter()
+        .filter(|(_, structure)| structure.step == step);
+    for (index, (id, _)) in step_structures.enumerate() {
+        random.set_feature_seed(decoration_seed, index, step);
+        for start in starts.iter().filter(|start| start.structure == id) {
+            let first = start.pieces[0].bounding_box;
+            let pivot = (first.center().0, first.min_y, first.center().2);
+            for piece in &start.pieces {
+                if piece.bounding_box.intersects(&chunk_box) {
+                    piece.element.place(
+                        region,
+                        random,
+                        piece.position,
+                        pivot,
+                        piece.rotation,
+                        &chunk_box,
+                        piece.liquid_settings,
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use super::super::randomness::LCG48;
+use super::super::tags::BiomeSet;
+use super::super::world_state::WorldState;
+use super::STRUCTURE_SETS;
+use crate::RandomGenerator;
+use serde::Deserialize;
+
+/// Where the structures of a structure set may start
+#[derive(Deserialize)]
+pub struct StructurePlacement {
+    #[serde(flatten)]
+    pub kind: PlacementKind,
+    #[serde(default)]
+    salt: i32,
+    #[serde(default = "default_frequency")]
+    frequency: f32,
+    #[serde(default)]
+    frequency_reduction_method: FrequencyReductionMethod,
+    #[serde(default)]
+    exclusion_zone: Option<ExclusionZone>,
+}
+
+fn default_frequency() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum PlacementKind {
+    /// One start in a random chunk of every cell of a grid
+    #[serde(rename = "minecraft:random_spread")]
+    RandomSpread {
+        spacing: i32,
+        separation: i32,
+        #[serde(default)]
+        spread_type: SpreadType,
+    },
+    /// Starts on rings around the world origin, moved towards preferred biomes
+    #[serde(rename = "minecraft:concentric_rings")]
+    ConcentricRings {
+        distance: i32,
+        spread: i32,
+        count: i32,
+        preferred_biomes: BiomeSet,
+    },
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SpreadType {
+    #[default]
+    Linear,
+    Triangular,
+}
+
+impl SpreadType {
+    fn evaluate(self, random: &mut dyn RandomGenerator, limit: i32) -> i32 {
+        match self {
+            SpreadType::Linear => random.next_i32_range(limit as u32),
+            SpreadType::Triangular => {
+                (random.next_i32_range(limit as u32) + random.next_i32_range(limit as u32)) / 2
+            }
+        }
+    }
+}
+
+/// How chunks are dropped when the frequency of a placement is below 1, the legacy methods keep
+/// the placement of older versions
+#[derive(Deserialize, Default, Clone, Copy)]
+pub enum FrequencyReductionMethod {
+    #[default]
+    #[serde(rename = "default")]
+    Default,
+    #[serde(rename = "legacy_type_1")]
+    LegacyType1,
+    #[serde(rename = "legacy_type_2")]
+    LegacyType2,
+    #[serde(rename = "legacy_type_3")]
+    LegacyType3,
+}
+
+impl FrequencyReductionMethod {
+    fn should_generate(self, seed: i64, salt: i32, x: i32, z: i32, frequency: f32) -> bool {
+        match self {
+            FrequencyReductionMethod::Default => {
+                LCG48::with_large_feature_salt(seed, salt, x, z).next_f32() < frequency
+            }
+            FrequencyReductionMethod::LegacyType1 => {
+                let mut random = LCG48::from_seed(((x >> 4) ^ ((z >> 4) << 4)) as i64 ^ seed);
+                random.next_i32();
+                random.next_i32_range((1.0 / frequency) as i32 as u32) == 0
+            }
+            FrequencyReductionMethod::LegacyType2 => {
+                LCG48::with_large_feature_salt(seed, x, z, 10387320).next_f32() < frequency
+            }
+            FrequencyReductionMethod::LegacyType3 => {
+                LCG48::with_large_feature_seed(seed, x, z).next_f64() < (frequency as f64)
+            }
+        }
+    }
+}
+
+/// Keeps the structures of a set away from the structures of another set
+#[derive(Deserialize)]
+struct ExclusionZone {
+    other_set: String,
+    chunk_count: i32,
+}
+
+impl StructurePlacement {
+    /// Whether a structure of the set starts in a chunk, like vanilla's `isStructureChunk`
+    pub fn is_structure_chunk(
+        &self,
+        world_state: &WorldState,
+        dimension: &str,
+        set: &str,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> bool {
+        let seed = world_state.seed;
+        let placement_chunk = match &self.kind {
+            PlacementKind::RandomSpread {
+                spacing,
+                separation,
+                spread_type,
+            } => {
+                self.potential_chunk(seed, *spacing, *separation, *spread_type, chunk_x, chunk_z)
+                    == (chunk_x, chunk_z)
+            }
+            PlacementKind::ConcentricRings { .. } => world_state
+                .structures
+                .ring_chunks(world_state, dimension, set)
+                .contains(&(chunk_x, chunk_z)),
+        };
+        if !placement_chunk {
+            return false;
+        }
+        if self.frequency < 1.0
+            && !self.frequency_reduction_method.should_generate(
+                seed,
+                self.salt,
+                chunk_x,
+                chunk_z,
+                self.frequency,
+            )
+        {
+            return false;
+        }
+        let Some(zone) = &self.exclusion_zone else {
+            return true;
+        };
+        let Some(other) = STRUCTURE_SETS.get(&zone.other_set) else {
+            return true;
+        };
+        let range = zone.chunk_count;
+        !(chunk_x - range..=chunk_x + range).any(|x| {
+            (chunk_z - range..=chunk_z + range).any(|z| {
+                other
+                    .placement
+                    .is_structure_chunk(world_state, dimension, &zone.other_set, x, z)
+            })
+        })
+    }
+
+    /// Chunk of the grid cell containing a chunk where the structure may start
+    fn potential_chunk(
+        &self,
+        seed: i64,
+        spacing: i32,
+        separation: i32,
+        spread_type: SpreadType,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> (i32, i32) {
+        let cell_x = chunk_x.div_euclid(spacing);
+        let cell_z = chunk_z.div_euclid(spacing);
+        let mut random = LCG48::with_large_feature_salt(seed, cell_x, cell_z, self.salt);
+        let limit = spacing - separation;
+        let offset_x = spread_type.evaluate(&mut random, limit);
+        let offset_z = spread_type.evaluate(&mut random, limit);
+        (cell_x * spacing + offset_x, cell_z * spacing + offset_z)
+    }
+
+    /// Chunks of a concentric rings placement, like vanilla's `generateRingPositions`. Every
+    /// position is moved to a random preferred biome within 112 blocks when there is one
+    pub fn ring_chunks(&self, world_state: &WorldState, dimension: &str) -> Vec<(i32, i32)> {
+        let PlacementKind::ConcentricRings {
+            distance,
+            spread,
+            count,
+            preferred_biomes,
+        } = &self.kind
+        else {
+            return Vec::new();
+        };
+        let (distance, count) = (*distance as f64, *count);
+        let mut spread = *spread;
+        let mut random = LCG48::from_seed(world_state.seed);
+        let mut angle = random.next_f64() * std::f64::consts::PI * 2.0;
+        let mut chunks = Vec::with_capacity(count.max(0) as usize);
+        let (mut in_ring, mut ring) = (0, 0);
+        for i in 0..count {
+            let radius = 4.0 * distance
+                + distance * ring as f64 * 6.0
+                + (random.next_f64() - 0.5) * (distance * 2.5);
+            // Java's `Math.round`
+            let x = (angle.cos() * radius + 0.5).floor() as i32;
+            let z = (angle.sin() * radius + 0.5).floor() as i32;
+            let mut biome_random = LCG48::from_seed(random.next_i64());
+            chunks.push(
+                find_biome_horizontal(
+                    world_state,
+                    dimension,
+                    (x << 4) + 8,
+                    (z << 4) + 8,
+                    112,
+                    preferred_biomes,
+                    &mut biome_random,
+                )
+                .map_or((x, z), |(x, z)| (x >> 4, z >> 4)),
+            );
+            angle += std::f64::consts::PI * 2.0 / spread as f64;
+            in_ring += 1;
+            if in_ring == spread {
+                ring += 1;
+                in_ring = 0;
+                spread += 2 * spread / (ring + 1);
+                spread = spread.min(count - i);
+                angle += random.next_f64() * std::f64::consts::PI * 2.0;
+            }
+        }
+        chunks
+    }
+}
+
+/// Random position of a matching biome at the bottom of a square of quarts, like vanilla's
+/// `BiomeSource.findBiomeHorizontal`
+fn find_biome_horizontal(
+    world_state: &WorldState,
+    dimension: &str,
+    x: i32,
+    z: i32,
+    radius: i32,
+    biomes: &BiomeSet,
+    random: &mut dyn RandomGenerator,
+) -> Option<(i32, i32)> {
+    let biome_source = world_state.biome_source.as_ref()?;
+    let (quart_x, quart_z, quart_radius) = (x >> 2, z >> 2, radius >> 2);
+    let mut found = None;
+    let mut matches = 0;
+    for offset_z in -quart_radius..=quart_radius {
+        for offset_x in -quart_radius..=quart_radius {
+            let (biome_x, biome_z) = (quart_x + offset_x, quart_z + offset_z);
+            let Some(biome) = biome_source.noise_biome(
+                &world_state.settings.noise_router,
+                dimension,
+                biome_x,
+                0,
+                biome_z,
+            ) else {
+                continue;
+            };
+            if !biomes.contains(biome) {
+                continue;
+            }
+            if found.is_none() || random.next_i32_range(matches + 1) == 0 {
+                found = Some((biome_x << 2, biome_z << 2));
+            }
+            matches += 1;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Placement of a vanilla 1.21.6 structure set. The expected starts are for seed 1, worked
+    /// out by following vanilla's `RandomSpreadStructurePlacement` and frequency reducers
+    fn placement(json: &str) -> StructurePlacement {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// Chunks of an area where a random spread placement starts a structure, without exclusion
+    /// zones
+    fn starts(placement: &StructurePlacement, seed: i64, range: i32) -> Vec<(i32, i32)> {
+        let PlacementKind::RandomSpread {
+            spacing,
+            separation,
+            spread_type,
+        } = placement.kind
+        else {
+            panic!("Not a random spread placement");
+        };
+        let mut starts = Vec::new();
+        for x in -range..range {
+            for z in -range..range {
+                if placement.potential_chunk(seed, spacing, separation, spread_type, x, z) == (x, z)
+                    && (placement.frequency >= 1.0
+                        || placement.frequency_reduction_method.should_generate(
+                            seed,
+                            placement.salt,
+                            x,
+                            z,
+                            placement.frequency,
+                        ))
+                {
+                    starts.push((x, z));
+                }
+            }
+        }
+        starts
+    }
+
+    #[test]
+    fn random_spread_starts_match_vanilla() {
+        let villages = placement(
+            r#"{"type": "minecraft:random_spread", "salt": 10387312, "separation": 8,
+                "spacing": 34}"#,
+        );
+        for ((cell_x, cell_z), start) in [
+            ((0, 0), (15, 8)),
+            ((-1, 0), (-28, 7)),
+            ((1, -1), (59, -26)),
+            ((-3, 2), (-102, 70)),
+            ((100, -100), (3413, -3392)),
+        ] {
+            // Every chunk of a cell has the same start
+            for (x, z) in [(0, 0), (33, 17), (5, 33)] {
+                let chunk = (cell_x * 34 + x, cell_z * 34 + z);
+                assert_eq!(
+                    villages.potential_chunk(1, 34, 8, SpreadType::Linear, chunk.0, chunk.1),
+                    start
+                );
+            }
+        }
+        let monuments = placement(
+            r#"{"type": "minecraft:random_spread", "salt": 10387313, "separation": 5,
+                "spacing": 32, "spread_type": "triangular"}"#,
+        );
+        for ((cell_x, cell_z), start) in [
+            ((0, 0), (12, 23)),
+            ((-1, -1), (-22, -19)),
+            ((2, -3), (76, -76)),
+            ((-50, 70), (-1586, 2241)),
+        ] {
+            assert_eq!(
+                monuments.potential_chunk(
+                    1,
+                    32,
+                    5,
+                    SpreadType::Triangular,
+                    cell_x * 32,
+                    cell_z * 32
+                ),
+                start
+            );
+        }
+    }
+
+    #[test]
+    fn frequency_reduction_matches_vanilla() {
+        let outposts = placement(
+            r#"{"type": "minecraft:random_spread", "exclusion_zone":
+                {"chunk_count": 10, "other_set": "minecraft:villages"}, "frequency": 0.2,
+                "frequency_reduction_method": "legacy_type_1", "salt": 165745296,
+                "separation": 8, "spacing": 32}"#,
+        );
+        for (cell, start, generates) in [
+            ((0, 0), (5, 20), true),
+            ((-1, 0), (-20, 7), true),
+            ((1, 1), (43, 45), false),
+            ((-2, -2), (-41, -51), false),
+            ((3, -1), (116, -13), false),
+            ((-7, 3), (-201, 101), false),
+            ((10, -4), (328, -115), true),
+        ] {
+            let chunk = (cell.0 * 32, cell.1 * 32);
+            assert_eq!(
+                outposts.potential_chunk(1, 32, 8, SpreadType::Linear, chunk.0, chunk.1),
+                start
+            );
+            assert_eq!(
+                FrequencyReductionMethod::LegacyType1
+                    .should_generate(1, 165745296, start.0, start.1, 0.2),
+                generates
+            );
+        }
+        let buried_treasure = placement(
+            r#"{"type": "minecraft:random_spread", "frequency": 0.01,
+                "frequency_reduction_method": "legacy_type_2", "locate_offset": [9, 0, 9],
+                "salt": 0, "separation": 0, "spacing": 1}"#,
+        );
+        assert_eq!(
+            starts(&buried_treasure, 1, 24),
+            [
+                (-23, -4),
+                (-17, -10),
+                (-17, 19),
+                (-15, 7),
+                (-11, -13),
+                (-10, -9),
+                (-7, 17),
+                (-1, 6),
+                (0, -22),
+                (2, -3),
+                (3, 4),
+                (15, -22),
+                (16, -20),
+                (17, -4),
+                (18, 19),
+                (23, 13)
+            ]
+        );
+        let mineshafts = placement(
+            r#"{"type": "minecraft:random_spread", "frequency": 0.004,
+                "frequency_reduction_method": "legacy_type_3", "salt": 0, "separation": 0,
+                "spacing": 1}"#,
+        );
+        assert_eq!(
+            starts(&mineshafts, 1, 24),
+            [
+                (-24, -15),
+                (-19, 23),
+                (-10, -15),
+                (-8, -15),
+                (-8, 23),
+                (-4, 0),
+                (8, -23),
+                (8, -15),
+                (8, 17),
+                (10, 15),
+                (12, -21),
+                (19, -23)
+            ]
+        );
+    }
+}
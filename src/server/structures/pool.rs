@@ -0,0 +1,503 @@
+use super::super::features::region::FeatureRegion;
+use super::super::features::{with_property, Direction, Fluid, PlacedFeatureRef};
+use super::super::randomness::{LCG48Positional, LCG48};
+use super::processor::{
+    ProcessedBlock, ProcessorListRef, StructureProcessor, JIGSAW_REPLACEMENT,
+    STRUCTURE_AND_AIR_IGNORE, STRUCTURE_BLOCK_IGNORE, TERRAIN_MATCHING_GRAVITY,
+};
+use super::template::{shuffle, BoundingBox, JigsawBlock, Rotation, StructureTemplate};
+use crate::{RandomGenerator, RandomPositionalGenerator};
+use ahash::AHashMap;
+use once_cell::sync::OnceCell;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use std::sync::Arc;
+
+/// How a piece follows the terrain, rigid pieces keep their shape while terrain matching pieces
+/// drop every column onto the surface
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    Rigid,
+    TerrainMatching,
+}
+
+impl Projection {
+    pub fn name(self) -> &'static str {
+        match self {
+            Projection::Rigid => "rigid",
+            Projection::TerrainMatching => "terrain_matching",
+        }
+    }
+}
+
+/// Whether blocks of a piece placed in water get waterlogged
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LiquidSettings {
+    IgnoreWaterlogging,
+    #[default]
+    ApplyWaterlogging,
+}
+
+impl LiquidSettings {
+    pub fn name(self) -> &'static str {
+        match self {
+            LiquidSettings::IgnoreWaterlogging => "ignore_waterlogging",
+            LiquidSettings::ApplyWaterlogging => "apply_waterlogging",
+        }
+    }
+}
+
+/// Element of a template pool, a piece a jigsaw structure is built from
+pub struct PoolElement {
+    pub kind: ElementKind,
+    pub projection: Projection,
+    /// Element as written in the pool, saved with the pieces of structure starts
+    pub json: serde_json::Value,
+}
+
+pub enum ElementKind {
+    /// Template placed with processors, legacy elements also skip the air of their template
+    Single {
+        location: String,
+        processors: ProcessorListRef,
+        override_liquid_settings: Option<LiquidSettings>,
+        legacy: bool,
+    },
+    /// Elements placed on top of each other, the first one gives the jigsaws
+    List(Vec<PoolElement>),
+    Feature(PlacedFeatureRef),
+    Empty,
+}
+
+impl<'de> Deserialize<'de> for PoolElement {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let json = serde_json::Value::deserialize(deserializer)?;
+        PoolElement::from_json(json, None).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "element_type")]
+enum RawElement {
+    #[serde(rename = "minecraft:single_pool_element")]
+    Single(RawSingle),
+    #[serde(rename = "minecraft:legacy_single_pool_element")]
+    Legacy(RawSingle),
+    #[serde(rename = "minecraft:list_pool_element")]
+    List {
+        elements: Vec<serde_json::Value>,
+        projection: Projection,
+    },
+    #[serde(rename = "minecraft:feature_pool_element")]
+    Feature {
+        feature: PlacedFeatureRef,
+        projection: Projection,
+    },
+    #[serde(rename = "minecraft:empty_pool_element")]
+    Empty,
+}
+
+#[derive(Deserialize)]
+struct RawSingle {
+    location: String,
+    #[serde(default)]
+    processors: ProcessorListRef,
+    projection: Projection,
+    #[serde(default)]
+    override_liquid_settings: Option<LiquidSettings>,
+}
+
+impl PoolElement {
+    /// Parses an element, the elements of a list take the projection of the list
+    fn from_json(
+        json: serde_json::Value,
+        projection: Option<Projection>,
+    ) -> Result<Self, serde_json::Error> {
+        let single = |raw: RawSingle, legacy: bool| {
+            (
+                ElementKind::Single {
+                    location: raw.location,
+                    processors: raw.processors,
+                    override_liquid_settings: raw.override_liquid_settings,
+                    legacy,
+                },
+                raw.projection,
+            )
+        };
+        let (kind, own_projection) = match RawElement::deserialize(&json)? {
+            RawElement::Single(raw) => single(raw, false),
+            RawElement::Legacy(raw) => single(raw, true),
+            RawElement::List {
+                elements,
+                projection,
+            } => (
+                ElementKind::List(
+                    elements
+                        .into_iter()
+                        .map(|element| PoolElement::from_json(element, Some(projection)))
+                        .collect::<Result<_, _>>()?,
+                ),
+                projection,
+            ),
+            RawElement::Feature {
+                feature,
+                projection,
+            } => (ElementKind::Feature(feature), projection),
+            RawElement::Empty => (ElementKind::Empty, Projection::Rigid),
+        };
+        Ok(PoolElement {
+            kind,
+            projection: projection.unwrap_or(own_projection),
+            json,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self.kind, ElementKind::Empty)
+    }
+
+    /// Vertical offset of the ground from the bottom of the piece
+    pub fn ground_level_delta(&self) -> i32 {
+        1
+    }
+
+    /// Box of the element placed at `pos` with a rotation. Elements without a template take a
+    /// single block
+    pub fn bounding_box(&self, pos: (i32, i32, i32), rotation: Rotation) -> BoundingBox {
+        let single_block = BoundingBox::from_corners(pos, pos);
+        match &self.kind {
+            ElementKind::Single { location, .. } => StructureTemplate::get(location)
+                .map_or(single_block, |template| {
+                    template.bounding_box(pos, rotation)
+                }),
+            ElementKind::List(elements) => elements
+                .iter()
+                .filter(|element| !element.is_empty())
+                .map(|element| element.bounding_box(pos, rotation))
+                .reduce(|a, b| a.union(&b))
+                .unwrap_or(single_block),
+            ElementKind::Feature(_) | ElementKind::Empty => single_block,
+        }
+    }
+
+    /// Jigsaws of the element placed at `pos` in a random order, jigsaws with a higher
+    /// selection priority first
+    pub fn shuffled_jigsaws(
+        &self,
+        pos: (i32, i32, i32),
+        rotation: Rotation,
+        random: &mut dyn RandomGenerator,
+    ) -> Vec<JigsawBlock> {
+        match &self.kind {
+            ElementKind::Single { location, .. } => {
+                let Some(template) = StructureTemplate::get(location) else {
+                    return Vec::new();
+                };
+                let mut jigsaws = template.jigsaws(pos, rotation);
+                shuffle(&mut jigsaws, random);
+                jigsaws.sort_by_key(|jigsaw| std::cmp::Reverse(jigsaw.selection_priority));
+                jigsaws
+            }
+            ElementKind::List(elements) => elements
+                .first()
+                .map(|element| element.shuffled_jigsaws(pos, rotation, random))
+                .unwrap_or_default(),
+            // Features connect through a jigsaw at their position facing down
+            ElementKind::Feature(_) => vec![JigsawBlock {
+                pos,
+                front: Direction::Down,
+                top: Direction::South,
+                name: "minecraft:bottom".to_owned(),
+                target: "minecraft:empty".to_owned(),
+                pool: "minecraft:empty".to_owned(),
+                rollable: true,
+                placement_priority: 0,
+                selection_priority: 0,
+            }],
+            ElementKind::Empty => Vec::new(),
+        }
+    }
+
+    /// Places the part of the element inside `bounds`. `pivot` is the position the processors
+    /// measure distances from
+    #[allow(clippy::too_many_arguments)]
+    pub fn place(
+        &self,
+        region: &mut FeatureRegion,
+        random: &mut dyn RandomGenerator,
+        pos: (i32, i32, i32),
+        pivot: (i32, i32, i32),
+        rotation: Rotation,
+        bounds: &BoundingBox,
+        liquid_settings: LiquidSettings,
+    ) {
+        match &self.kind {
+            ElementKind::Single {
+                location,
+                processors,
+                override_liquid_settings,
+                legacy,
+            } => {
+                let Some(template) = StructureTemplate::get(location) else {
+                    return;
+                };
+                // Processors in the order vanilla adds them to the placement settings
+                let mut chain: Vec<&StructureProcessor> = Vec::new();
+                if !legacy {
+                    chain.push(&STRUCTURE_BLOCK_IGNORE);
+                }
+                chain.push(&JIGSAW_REPLACEMENT);
+                chain.extend(processors.get());
+                if self.projection == Projection::TerrainMatching {
+                    chain.push(&TERRAIN_MATCHING_GRAVITY);
+                }
+                if *legacy {
+                    chain.push(&STRUCTURE_AND_AIR_IGNORE);
+                }
+                let liquid_settings = override_liquid_settings.unwrap_or(liquid_settings);
+                place_template(
+                    region,
+                    &template,
+                    pos,
+                    pivot,
+                    rotation,
+                    bounds,
+                    &chain,
+                    liquid_settings,
+                );
+            }
+            ElementKind::List(elements) => {
+                for element in elements {
+                    element.place(
+                        region,
+                        random,
+                        pos,
+                        pivot,
+                        rotation,
+                        bounds,
+                        liquid_settings,
+                    );
+                }
+            }
+            ElementKind::Feature(feature) => {
+                feature.place(region, random, pos);
+            }
+            ElementKind::Empty => {}
+        }
+    }
+}
+
+/// Places the blocks of a template like vanilla's `StructureTemplate.placeInWorld`. Block
+/// entities are not placed
+#[allow(clippy::too_many_arguments)]
+fn place_template(
+    region: &mut FeatureRegion,
+    template: &StructureTemplate,
+    (x, y, z): (i32, i32, i32),
+    pivot: (i32, i32, i32),
+    rotation: Rotation,
+    bounds: &BoundingBox,
+    processors: &[&StructureProcessor],
+    liquid_settings: LiquidSettings,
+) {
+    let mut palette_random = LCG48::from_seed(LCG48Positional::seed_from_pos(x, y, z));
+    let mut processed = Vec::new();
+    for block in template.blocks(&mut palette_random) {
+        let (dx, dy, dz) = rotation.transform(block.pos);
+        let pos = (x + dx, y + dy, z + dz);
+        // Processors only move blocks vertically, columns outside of the bounds are skipped
+        if pos.0 < bounds.min_x
+            || pos.0 > bounds.max_x
+            || pos.2 < bounds.min_z
+            || pos.2 > bounds.max_z
+        {
+            continue;
+        }
+        let mut info = Some(ProcessedBlock {
+            pos,
+            state: block.state,
+        });
+        for processor in processors {
+            info = info.and_then(|info| processor.process(region, pivot, block, info));
+        }
+        if let Some(info) = info.filter(|info| bounds.is_inside(info.pos)) {
+            processed.push(info);
+        }
+    }
+    for ProcessedBlock {
+        pos: (x, y, z),
+        state,
+    } in processed
+    {
+        let mut state = rotation.rotate_state(state);
+        if liquid_settings == LiquidSettings::ApplyWaterlogging
+            && Fluid::of(region.get_block(x, y, z)) == Fluid::Water
+        {
+            state = with_property(state, "waterlogged", "true");
+        }
+        region.set_block(x, y, z, state);
+    }
+}
+
+/// Pool of the elements a jigsaw picks its next piece from, read from
+/// `worldgen/template_pool`
+#[derive(Deserialize)]
+#[serde(from = "RawPool")]
+pub struct TemplatePool {
+    pub fallback: String,
+    /// Every element repeated by its weight
+    templates: Vec<Arc<PoolElement>>,
+    max_size: OnceCell<i32>,
+}
+
+#[derive(Deserialize)]
+struct RawPool {
+    fallback: String,
+    elements: Vec<WeightedElement>,
+}
+
+#[derive(Deserialize)]
+struct WeightedElement {
+    element: PoolElement,
+    weight: i32,
+}
+
+impl From<RawPool> for TemplatePool {
+    fn from(raw: RawPool) -> Self {
+        let mut templates = Vec::new();
+        for WeightedElement { element, weight } in raw.elements {
+            let element = Arc::new(element);
+            for _ in 0..weight {
+                templates.push(element.clone());
+            }
+        }
+        TemplatePool {
+            fallback: raw.fallback,
+            templates,
+            max_size: OnceCell::new(),
+        }
+    }
+}
+
+impl TemplatePool {
+    pub fn size(&self) -> usize {
+        self.templates.len()
+    }
+
+    /// Random element weighted by the element weights, `None` for an empty pool
+    pub fn random_template(&self, random: &mut dyn RandomGenerator) -> Option<Arc<PoolElement>> {
+        if self.templates.is_empty() {
+            return None;
+        }
+        Some(self.templates[random.next_i32_range(self.templates.len() as u32) as usize].clone())
+    }
+
+    /// Weighted elements in a random order
+    pub fn shuffled_templates(&self, random: &mut dyn RandomGenerator) -> Vec<Arc<PoolElement>> {
+        let mut templates = self.templates.clone();
+        shuffle(&mut templates, random);
+        templates
+    }
+
+    /// Height of the tallest element
+    pub fn max_size(&self) -> i32 {
+        *self.max_size.get_or_init(|| {
+            self.templates
+                .iter()
+                .filter(|element| !element.is_empty())
+                .map(|element| element.bounding_box((0, 0, 0), Rotation::None).y_span())
+                .max()
+                .unwrap_or(0)
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Weighted<T> {
+    data: T,
+    weight: i32,
+}
+
+/// Picks an entry of a weighted list like vanilla's `WeightedList.getRandom`
+fn weighted<'a, T>(entries: &'a [Weighted<T>], random: &mut dyn RandomGenerator) -> Option<&'a T> {
+    let total = entries.iter().map(|entry| entry.weight).sum::<i32>();
+    if total <= 0 {
+        return None;
+    }
+    let mut i = random.next_i32_range(total as u32);
+    for entry in entries {
+        i -= entry.weight;
+        if i < 0 {
+            return Some(&entry.data);
+        }
+    }
+    None
+}
+
+/// Replaces a pool id by another one for a structure start
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum PoolAliasBinding {
+    #[serde(rename = "minecraft:direct")]
+    Direct { alias: String, target: String },
+    #[serde(rename = "minecraft:random")]
+    Random {
+        alias: String,
+        targets: Vec<Weighted<String>>,
+    },
+    #[serde(rename = "minecraft:random_group")]
+    RandomGroup {
+        groups: Vec<Weighted<Vec<PoolAliasBinding>>>,
+    },
+}
+
+impl PoolAliasBinding {
+    fn resolve(&self, random: &mut dyn RandomGenerator, aliases: &mut AHashMap<String, String>) {
+        match self {
+            PoolAliasBinding::Direct { alias, target } => {
+                aliases.insert(alias.clone(), target.clone());
+            }
+            PoolAliasBinding::Random { alias, targets } => {
+                if let Some(target) = weighted(targets, random) {
+                    aliases.insert(alias.clone(), target.clone());
+                }
+            }
+            PoolAliasBinding::RandomGroup { groups } => {
+                for binding in weighted(groups, random).into_iter().flatten() {
+                    binding.resolve(random, aliases);
+                }
+            }
+        }
+    }
+}
+
+/// Pool aliases resolved for one structure start, like vanilla's `PoolAliasLookup`
+#[derive(Default)]
+pub struct PoolAliases(AHashMap<String, String>);
+
+impl PoolAliases {
+    /// Resolves the random aliases with a random seeded from the world seed and the start
+    /// position
+    pub fn new(bindings: &[PoolAliasBinding], (x, y, z): (i32, i32, i32), seed: i64) -> Self {
+        if bindings.is_empty() {
+            return Self::default();
+        }
+        let positional_seed = LCG48::from_seed(seed).next_i64();
+        let mut random =
+            LCG48::from_seed(LCG48Positional::seed_from_pos(x, y, z) ^ positional_seed);
+        let mut aliases = AHashMap::new();
+        for binding in bindings {
+            binding.resolve(&mut random, &mut aliases);
+        }
+        PoolAliases(aliases)
+    }
+
+    pub fn lookup<'a>(&'a self, pool: &'a str) -> &'a str {
+        self.0.get(pool).map_or(pool, String::as_str)
+    }
+}
@@ -0,0 +1,381 @@
+use super::super::chunk_system::BLOCKSTATE_MAPPINGS;
+use super::super::features::block_name;
+use super::super::features::block_predicate::{is_solid, RuleTest};
+use super::super::features::region::{FeatureRegion, WorldHeightmap};
+use super::super::features::state_provider::BlockState;
+use super::super::randomness::{LCG48Positional, LCG48};
+use super::super::tags::BlockSet;
+use super::super::world_state::NoiseBlockState;
+use super::template::TemplateBlock;
+use super::{PROCESSOR_LISTS, UNSUPPORTED_PROCESSORS};
+use crate::{RandomGenerator, RandomPositionalGenerator};
+use once_cell::sync::Lazy;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+
+/// Keeps structure blocks of templates out of the world
+pub static STRUCTURE_BLOCK_IGNORE: Lazy<StructureProcessor> =
+    Lazy::new(|| StructureProcessor::BlockIgnore {
+        blocks: BlockSet::from_ids(&["minecraft:structure_block".to_owned()]),
+    });
+
+/// Added to legacy elements, whose air does not replace the terrain
+pub static STRUCTURE_AND_AIR_IGNORE: Lazy<StructureProcessor> =
+    Lazy::new(|| StructureProcessor::BlockIgnore {
+        blocks: BlockSet::from_ids(&[
+            "minecraft:structure_block".to_owned(),
+            "minecraft:air".to_owned(),
+        ]),
+    });
+
+pub static JIGSAW_REPLACEMENT: StructureProcessor = StructureProcessor::JigsawReplacement;
+
+/// Drops the columns of terrain matching pieces onto the surface
+pub static TERRAIN_MATCHING_GRAVITY: StructureProcessor = StructureProcessor::Gravity {
+    heightmap: WorldHeightmap::WorldSurfaceWg,
+    offset: -1,
+};
+
+/// Block of a template moved to its position in the world
+pub struct ProcessedBlock {
+    pub pos: (i32, i32, i32),
+    pub state: u32,
+}
+
+/// Processor list given by its id or written inline
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ProcessorListRef {
+    Id(String),
+    Inline(ProcessorList),
+}
+
+impl Default for ProcessorListRef {
+    fn default() -> Self {
+        ProcessorListRef::Id("minecraft:empty".to_owned())
+    }
+}
+
+impl ProcessorListRef {
+    pub fn get(&self) -> &[StructureProcessor] {
+        match self {
+            ProcessorListRef::Id(id) => PROCESSOR_LISTS
+                .get(id)
+                .map_or(&[], |list| list.processors.as_slice()),
+            ProcessorListRef::Inline(list) => &list.processors,
+        }
+    }
+}
+
+/// Processors changing the blocks of a template as it is placed, read from
+/// `worldgen/processor_list`
+#[derive(Deserialize)]
+#[serde(from = "RawProcessorList")]
+pub struct ProcessorList {
+    processors: Vec<StructureProcessor>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawProcessorList {
+    Object { processors: Vec<StructureProcessor> },
+    List(Vec<StructureProcessor>),
+}
+
+impl From<RawProcessorList> for ProcessorList {
+    fn from(raw: RawProcessorList) -> Self {
+        let (RawProcessorList::Object { processors } | RawProcessorList::List(processors)) = raw;
+        ProcessorList { processors }
+    }
+}
+
+fn default_always_true() -> PosRuleTest {
+    PosRuleTest::AlwaysTrue
+}
+
+/// Test of the position of a block relative to the start of its structure
+#[derive(Deserialize)]
+#[serde(tag = "predicate_type")]
+pub enum PosRuleTest {
+    #[serde(rename = "minecraft:always_true")]
+    AlwaysTrue,
+    /// Chance growing with the distance to the structure pivot
+    #[serde(rename = "minecraft:linear_pos")]
+    LinearPos(LinearChances),
+    /// Chance growing with the distance to the structure pivot along an axis
+    #[serde(rename = "minecraft:axis_aligned_linear_pos")]
+    AxisAlignedLinearPos(LinearChances),
+}
+
+fn default_axis() -> String {
+    "y".to_owned()
+}
+
+#[derive(Deserialize)]
+pub struct LinearChances {
+    #[serde(default)]
+    min_chance: f32,
+    #[serde(default)]
+    max_chance: f32,
+    #[serde(default)]
+    min_dist: i32,
+    #[serde(default)]
+    max_dist: i32,
+    /// Only used by the axis aligned test
+    #[serde(default = "default_axis")]
+    axis: String,
+}
+
+impl LinearChances {
+    /// Like vanilla the chance is not a number when both distances are equal, which fails
+    fn test(&self, distance: i32, random: &mut dyn RandomGenerator) -> bool {
+        let roll = random.next_f32();
+        let delta = (distance as f32 - self.min_dist as f32)
+            / (self.max_dist as f32 - self.min_dist as f32);
+        let chance = if delta < 0.0 {
+            self.min_chance
+        } else if delta > 1.0 {
+            self.max_chance
+        } else {
+            self.min_chance + delta * (self.max_chance - self.min_chance)
+        };
+        roll <= chance
+    }
+}
+
+impl PosRuleTest {
+    fn test(
+        &self,
+        (x, y, z): (i32, i32, i32),
+        (pivot_x, pivot_y, pivot_z): (i32, i32, i32),
+        random: &mut dyn RandomGenerator,
+    ) -> bool {
+        match self {
+            PosRuleTest::AlwaysTrue => true,
+            PosRuleTest::LinearPos(chances) => {
+                let distance = (x - pivot_x).abs() + (y - pivot_y).abs() + (z - pivot_z).abs();
+                chances.test(distance, random)
+            }
+            PosRuleTest::AxisAlignedLinearPos(chances) => {
+                let distance = match chances.axis.as_str() {
+                    "x" => x - pivot_x,
+                    "z" => z - pivot_z,
+                    _ => y - pivot_y,
+                };
+                chances.test(distance.abs(), random)
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ProcessorRule {
+    input_predicate: RuleTest,
+    location_predicate: RuleTest,
+    #[serde(default = "default_always_true")]
+    position_predicate: PosRuleTest,
+    output_state: BlockState,
+}
+
+/// Changes the blocks of a template while it is placed
+pub enum StructureProcessor {
+    /// Replaces blocks by the output of the first matching rule
+    Rule(Vec<ProcessorRule>),
+    BlockIgnore {
+        blocks: BlockSet,
+    },
+    /// Removes blocks at random, only the rottable blocks when they are given
+    BlockRot {
+        integrity: f32,
+        rottable_blocks: Option<BlockSet>,
+    },
+    /// Moves blocks to the height of the heightmap offset by their height in the template
+    Gravity {
+        heightmap: WorldHeightmap,
+        offset: i32,
+    },
+    /// Keeps the blocks of a tag from being replaced
+    ProtectedBlocks(BlockSet),
+    /// Turns blocks without a full shape into lava where lava was
+    LavaSubmergedBlock,
+    /// Turns jigsaws into their final state
+    JigsawReplacement,
+    Nop,
+    /// Processors of other types, which leave the blocks unchanged
+    Unsupported,
+}
+
+impl<'de> Deserialize<'de> for StructureProcessor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct RawProcessor {
+            processor_type: String,
+            #[serde(flatten)]
+            config: serde_json::Value,
+        }
+        #[derive(Deserialize)]
+        struct RuleConfig {
+            rules: Vec<ProcessorRule>,
+        }
+        #[derive(Deserialize)]
+        struct BlockIgnoreConfig {
+            blocks: Vec<NoiseBlockState>,
+        }
+        #[derive(Deserialize)]
+        struct BlockRotConfig {
+            integrity: f32,
+            #[serde(default)]
+            rottable_blocks: Option<BlockSet>,
+        }
+        fn default_heightmap() -> WorldHeightmap {
+            WorldHeightmap::WorldSurfaceWg
+        }
+        #[derive(Deserialize)]
+        struct GravityConfig {
+            #[serde(default = "default_heightmap")]
+            heightmap: WorldHeightmap,
+            #[serde(default)]
+            offset: i32,
+        }
+        #[derive(Deserialize)]
+        struct ProtectedBlocksConfig {
+            value: BlockSet,
+        }
+        let raw = RawProcessor::deserialize(deserializer)?;
+        let config = raw.config;
+        let processor = match raw.processor_type.trim_start_matches("minecraft:") {
+            "rule" => StructureProcessor::Rule(
+                serde_json::from_value::<RuleConfig>(config)
+                    .map_err(D::Error::custom)?
+                    .rules,
+            ),
+            "block_ignore" => {
+                let config: BlockIgnoreConfig =
+                    serde_json::from_value(config).map_err(D::Error::custom)?;
+                let names = config
+                    .blocks
+                    .into_iter()
+                    .map(|state| state.name)
+                    .collect::<Vec<_>>();
+                StructureProcessor::BlockIgnore {
+                    blocks: BlockSet::from_ids(&names),
+                }
+            }
+            "block_rot" => {
+                let config: BlockRotConfig =
+                    serde_json::from_value(config).map_err(D::Error::custom)?;
+                StructureProcessor::BlockRot {
+                    integrity: config.integrity,
+                    rottable_blocks: config.rottable_blocks,
+                }
+            }
+            "gravity" => {
+                let config: GravityConfig =
+                    serde_json::from_value(config).map_err(D::Error::custom)?;
+                StructureProcessor::Gravity {
+                    heightmap: config.heightmap,
+                    offset: config.offset,
+                }
+            }
+            "protected_blocks" => StructureProcessor::ProtectedBlocks(
+                serde_json::from_value::<ProtectedBlocksConfig>(config)
+                    .map_err(D::Error::custom)?
+                    .value,
+            ),
+            "lava_submerged_block" => StructureProcessor::LavaSubmergedBlock,
+            "jigsaw_replacement" => StructureProcessor::JigsawReplacement,
+            "nop" => StructureProcessor::Nop,
+            _ => {
+                UNSUPPORTED_PROCESSORS
+                    .lock()
+                    .unwrap()
+                    .insert(raw.processor_type.clone());
+                StructureProcessor::Unsupported
+            }
+        };
+        Ok(processor)
+    }
+}
+
+impl StructureProcessor {
+    /// Processes a block of a template placed in the world, `None` removes it. `original` is the
+    /// block as saved in the template, `pivot` the position distances are measured from
+    pub fn process(
+        &self,
+        region: &FeatureRegion,
+        pivot: (i32, i32, i32),
+        original: &TemplateBlock,
+        block: ProcessedBlock,
+    ) -> Option<ProcessedBlock> {
+        let (x, y, z) = block.pos;
+        // Random seeded from the world position like vanilla's `RandomSource.create(Mth.getSeed)`
+        let position_random = || LCG48::from_seed(LCG48Positional::seed_from_pos(x, y, z));
+        match self {
+            StructureProcessor::Rule(rules) => {
+                let mut random = position_random();
+                let existing = region.get_block(x, y, z);
+                for rule in rules {
+                    if rule.input_predicate.test(block.state, &mut random)
+                        && rule.location_predicate.test(existing, &mut random)
+                        && rule.position_predicate.test(block.pos, pivot, &mut random)
+                    {
+                        return Some(ProcessedBlock {
+                            pos: block.pos,
+                            state: rule.output_state.0,
+                        });
+                    }
+                }
+                Some(block)
+            }
+            StructureProcessor::BlockIgnore { blocks } => {
+                (!blocks.contains(block.state)).then_some(block)
+            }
+            StructureProcessor::BlockRot {
+                integrity,
+                rottable_blocks,
+            } => {
+                let rottable = rottable_blocks
+                    .as_ref()
+                    .is_none_or(|blocks| blocks.contains(original.state));
+                (!rottable || position_random().next_f32() <= *integrity).then_some(block)
+            }
+            StructureProcessor::Gravity { heightmap, offset } => {
+                let height = region.get_height(*heightmap, x, z) + offset;
+                Some(ProcessedBlock {
+                    pos: (x, height + original.pos.1, z),
+                    state: block.state,
+                })
+            }
+            StructureProcessor::ProtectedBlocks(protected) => {
+                (!protected.contains(region.get_block(x, y, z))).then_some(block)
+            }
+            StructureProcessor::LavaSubmergedBlock => {
+                if block_name(region.get_block(x, y, z)) == "lava" && !is_solid(block.state) {
+                    Some(ProcessedBlock {
+                        pos: block.pos,
+                        state: *BLOCKSTATE_MAPPINGS.get("lava[level=0]").unwrap(),
+                    })
+                } else {
+                    Some(block)
+                }
+            }
+            StructureProcessor::JigsawReplacement => {
+                if block_name(block.state) != "jigsaw" {
+                    return Some(block);
+                }
+                match original.final_state {
+                    Some(state) if block_name(state) == "structure_void" => None,
+                    Some(state) => Some(ProcessedBlock {
+                        pos: block.pos,
+                        state,
+                    }),
+                    None => Some(block),
+                }
+            }
+            StructureProcessor::Nop | StructureProcessor::Unsupported => Some(block),
+        }
+    }
+}
@@ -0,0 +1,619 @@
+use super::super::chunk_system::{
+    block_state_property, split_block_state, BLOCKSTATES, BLOCKSTATE_MAPPINGS,
+};
+use super::super::features::block_predicate::is_solid;
+use super::super::features::{block_name, property, Direction};
+use super::super::logger::{LogDomain, LogLevel, LOGGER};
+use super::super::tags::BLOCK_STATE_IDS;
+use super::super::world_state::NoiseBlockState;
+use super::namespaced;
+use crate::{RandomGenerator, JAR_RESOURCES_DIR};
+use ahash::AHashMap;
+use flate2::read::GzDecoder;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+/// Templates read from `structure`, `None` for templates that could not be read
+static TEMPLATES: Lazy<Mutex<AHashMap<String, Option<Arc<StructureTemplate>>>>> =
+    Lazy::new(Default::default);
+
+/// Every block state turned by each rotation, indexed by block state id
+static ROTATED_STATES: Lazy<Vec<[u32; 4]>> = Lazy::new(|| {
+    (0..BLOCKSTATES.len() as u32)
+        .map(|block| Rotation::ALL.map(|rotation| rotated_state(block, rotation)))
+        .collect()
+});
+
+/// Rotation of a structure piece around the vertical axis
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotation {
+    None,
+    Clockwise90,
+    Clockwise180,
+    Counterclockwise90,
+}
+
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [
+        Rotation::None,
+        Rotation::Clockwise90,
+        Rotation::Clockwise180,
+        Rotation::Counterclockwise90,
+    ];
+
+    pub fn random(random: &mut dyn RandomGenerator) -> Self {
+        Self::ALL[random.next_i32_range(4) as usize]
+    }
+
+    /// Every rotation in a random order, like vanilla's `Rotation.getShuffled`
+    pub fn shuffled(random: &mut dyn RandomGenerator) -> [Rotation; 4] {
+        let mut rotations = Self::ALL;
+        shuffle(&mut rotations, random);
+        rotations
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rotation::None => "NONE",
+            Rotation::Clockwise90 => "CLOCKWISE_90",
+            Rotation::Clockwise180 => "CLOCKWISE_180",
+            Rotation::Counterclockwise90 => "COUNTERCLOCKWISE_90",
+        }
+    }
+
+    fn quarter_turns(self) -> usize {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 1,
+            Rotation::Clockwise180 => 2,
+            Rotation::Counterclockwise90 => 3,
+        }
+    }
+
+    /// Position in a template turned around the template origin
+    pub fn transform(self, (x, y, z): (i32, i32, i32)) -> (i32, i32, i32) {
+        match self {
+            Rotation::None => (x, y, z),
+            Rotation::Clockwise90 => (-z, y, x),
+            Rotation::Clockwise180 => (-x, y, -z),
+            Rotation::Counterclockwise90 => (z, y, -x),
+        }
+    }
+
+    pub fn rotate_direction(self, direction: Direction) -> Direction {
+        match Direction::HORIZONTAL.iter().position(|d| *d == direction) {
+            Some(i) => Direction::HORIZONTAL[(i + self.quarter_turns()) % 4],
+            None => direction,
+        }
+    }
+
+    /// Block state turned with the rotation, like vanilla's `BlockState.rotate`
+    pub fn rotate_state(self, block: u32) -> u32 {
+        if self == Rotation::None {
+            return block;
+        }
+        ROTATED_STATES[block as usize][self.quarter_turns()]
+    }
+}
+
+/// Shuffles a list in place like vanilla's `Util.shuffle`
+pub fn shuffle<T>(list: &mut [T], random: &mut dyn RandomGenerator) {
+    for i in (2..=list.len()).rev() {
+        let j = random.next_i32_range(i as u32) as usize;
+        list.swap(i - 1, j);
+    }
+}
+
+fn direction_from_name(name: &str) -> Option<Direction> {
+    Direction::ALL
+        .into_iter()
+        .find(|direction| direction.name() == name)
+}
+
+/// Turns the properties of a block state that depend on its facing. Properties that do not name
+/// a direction are kept, the block is unchanged when the rotated state does not exist
+fn rotated_state(block: u32, rotation: Rotation) -> u32 {
+    let (name, properties) = split_block_state(&BLOCKSTATES[block as usize]);
+    if properties.is_empty() || rotation == Rotation::None {
+        return block;
+    }
+    let turns = rotation.quarter_turns();
+    let rotate_name = |value: &str| {
+        direction_from_name(value)
+            .map(|direction| rotation.rotate_direction(direction).name())
+            .unwrap_or(value)
+            .to_owned()
+    };
+    let rotated = properties
+        .split(',')
+        .filter_map(|property| property.split_once('='))
+        .map(|(key, value)| {
+            let value = match key {
+                "facing" => rotate_name(value),
+                "axis" if turns % 2 == 1 => match value {
+                    "x" => "z".to_owned(),
+                    "z" => "x".to_owned(),
+                    _ => value.to_owned(),
+                },
+                "rotation" => value
+                    .parse::<usize>()
+                    .map_or(value.to_owned(), |r| ((r + turns * 4) % 16).to_string()),
+                "orientation" => value
+                    .split('_')
+                    .map(rotate_name)
+                    .collect::<Vec<_>>()
+                    .join("_"),
+                "shape" => rotate_rail_shape(value, &rotate_name),
+                "north" | "east" | "south" | "west" => {
+                    // The side turned into this one gives its value
+                    let direction = direction_from_name(key).unwrap();
+                    let i = Direction::HORIZONTAL
+                        .iter()
+                        .position(|d| *d == direction)
+                        .unwrap();
+                    let source = Direction::HORIZONTAL[(i + 4 - turns) % 4];
+                    block_state_property(properties, source.name())
+                        .unwrap_or(value)
+                        .to_owned()
+                }
+                _ => value.to_owned(),
+            };
+            format!("{key}={value}")
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    BLOCKSTATE_MAPPINGS
+        .get(format!("{name}[{rotated}]").as_str())
+        .copied()
+        .unwrap_or(block)
+}
+
+/// Rail shapes name the directions they connect, stair shapes like `inner_left` are kept
+fn rotate_rail_shape(value: &str, rotate_name: &dyn Fn(&str) -> String) -> String {
+    if let Some(direction) = value.strip_prefix("ascending_") {
+        return format!("ascending_{}", rotate_name(direction));
+    }
+    let Some((first, second)) = value.split_once('_') else {
+        return value.to_owned();
+    };
+    if direction_from_name(first).is_none() || direction_from_name(second).is_none() {
+        return value.to_owned();
+    }
+    let (first, second) = (rotate_name(first), rotate_name(second));
+    let is_north_south = |side: &str| side == "north" || side == "south";
+    match (is_north_south(&first), is_north_south(&second)) {
+        (true, true) => "north_south".to_owned(),
+        (false, false) => "east_west".to_owned(),
+        (true, false) => format!("{first}_{second}"),
+        (false, true) => format!("{second}_{first}"),
+    }
+}
+
+/// Block state id from a block state string like `minecraft:oak_log[axis=y]`. Properties that
+/// are not given keep the values of the first state of the block
+pub fn parse_block_state(state: &str) -> Option<u32> {
+    let (name, properties) = match state.split_once('[') {
+        Some((name, properties)) => (name, properties.trim_end_matches(']')),
+        None => (state, ""),
+    };
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    let properties = properties
+        .split(',')
+        .filter_map(|property| property.split_once('='))
+        .collect::<Vec<_>>();
+    BLOCK_STATE_IDS.get(name)?.iter().copied().find(|block| {
+        properties
+            .iter()
+            .all(|(key, value)| property(*block, key) == Some(value))
+    })
+}
+
+/// Axis aligned box of blocks, both corners included
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min_x: i32,
+    pub min_y: i32,
+    pub min_z: i32,
+    pub max_x: i32,
+    pub max_y: i32,
+    pub max_z: i32,
+}
+
+impl BoundingBox {
+    pub fn from_corners(a: (i32, i32, i32), b: (i32, i32, i32)) -> Self {
+        BoundingBox {
+            min_x: a.0.min(b.0),
+            min_y: a.1.min(b.1),
+            min_z: a.2.min(b.2),
+            max_x: a.0.max(b.0),
+            max_y: a.1.max(b.1),
+            max_z: a.2.max(b.2),
+        }
+    }
+
+    pub fn moved(&self, x: i32, y: i32, z: i32) -> Self {
+        BoundingBox {
+            min_x: self.min_x + x,
+            min_y: self.min_y + y,
+            min_z: self.min_z + z,
+            max_x: self.max_x + x,
+            max_y: self.max_y + y,
+            max_z: self.max_z + z,
+        }
+    }
+
+    pub fn inflated(&self, amount: i32) -> Self {
+        BoundingBox {
+            min_x: self.min_x - amount,
+            min_y: self.min_y - amount,
+            min_z: self.min_z - amount,
+            max_x: self.max_x + amount,
+            max_y: self.max_y + amount,
+            max_z: self.max_z + amount,
+        }
+    }
+
+    pub fn is_inside(&self, (x, y, z): (i32, i32, i32)) -> bool {
+        x >= self.min_x
+            && x <= self.max_x
+            && z >= self.min_z
+            && z <= self.max_z
+            && y >= self.min_y
+            && y <= self.max_y
+    }
+
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.intersects_xz(other) && self.max_y >= other.min_y && self.min_y <= other.max_y
+    }
+
+    pub fn intersects_xz(&self, other: &BoundingBox) -> bool {
+        self.max_x >= other.min_x
+            && self.min_x <= other.max_x
+            && self.max_z >= other.min_z
+            && self.min_z <= other.max_z
+    }
+
+    /// Whether the box lies fully inside another one
+    pub fn is_within(&self, other: &BoundingBox) -> bool {
+        self.min_x >= other.min_x
+            && self.max_x <= other.max_x
+            && self.min_y >= other.min_y
+            && self.max_y <= other.max_y
+            && self.min_z >= other.min_z
+            && self.max_z <= other.max_z
+    }
+
+    /// Grows the box to include a position
+    pub fn encapsulate(&mut self, (x, y, z): (i32, i32, i32)) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.min_z = self.min_z.min(z);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+        self.max_z = self.max_z.max(z);
+    }
+
+    /// Smallest box holding both boxes
+    pub fn union(&self, other: &BoundingBox) -> Self {
+        BoundingBox {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            min_z: self.min_z.min(other.min_z),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+            max_z: self.max_z.max(other.max_z),
+        }
+    }
+
+    pub fn center(&self) -> (i32, i32, i32) {
+        (
+            self.min_x + (self.max_x - self.min_x + 1) / 2,
+            self.min_y + (self.max_y - self.min_y + 1) / 2,
+            self.min_z + (self.max_z - self.min_z + 1) / 2,
+        )
+    }
+
+    pub fn y_span(&self) -> i32 {
+        self.max_y - self.min_y + 1
+    }
+
+    /// Corners in the order vanilla writes the `BB` int array of a structure piece
+    pub fn to_array(self) -> [i32; 6] {
+        [
+            self.min_x, self.min_y, self.min_z, self.max_x, self.max_y, self.max_z,
+        ]
+    }
+}
+
+/// Jigsaw block of a template, the connection point between pieces of a jigsaw structure
+#[derive(Clone, Debug)]
+pub struct JigsawBlock {
+    pub pos: (i32, i32, i32),
+    /// Side the jigsaw connects to
+    pub front: Direction,
+    pub top: Direction,
+    pub name: String,
+    pub target: String,
+    pub pool: String,
+    /// Rollable jigsaws connect whatever way the top of the other jigsaw faces
+    pub rollable: bool,
+    pub placement_priority: i32,
+    pub selection_priority: i32,
+}
+
+impl JigsawBlock {
+    /// Jigsaw moved into a piece placed at `pos` with a rotation
+    pub fn placed(&self, pos: (i32, i32, i32), rotation: Rotation) -> Self {
+        let (x, y, z) = rotation.transform(self.pos);
+        JigsawBlock {
+            pos: (pos.0 + x, pos.1 + y, pos.2 + z),
+            front: rotation.rotate_direction(self.front),
+            top: rotation.rotate_direction(self.top),
+            ..self.clone()
+        }
+    }
+
+    /// Whether a jigsaw of another piece can connect to this one, like vanilla's
+    /// `JigsawBlock.canAttach`
+    pub fn can_attach(&self, other: &JigsawBlock) -> bool {
+        self.front == other.front.opposite()
+            && (self.rollable || self.top == other.top)
+            && self.target == other.name
+    }
+}
+
+/// Block of a template at its position in the template
+pub struct TemplateBlock {
+    pub pos: (i32, i32, i32),
+    pub state: u32,
+    /// Block the jigsaw turns into once the piece is placed, jigsaws without data keep the
+    /// jigsaw block
+    pub final_state: Option<u32>,
+}
+
+/// Blocks saved by a structure block, read from `structure/*.nbt`
+pub struct StructureTemplate {
+    pub size: (i32, i32, i32),
+    palettes: Vec<Vec<TemplateBlock>>,
+    /// Jigsaws of the first palette ordered by height then x then z
+    jigsaws: Vec<JigsawBlock>,
+}
+
+#[derive(Deserialize)]
+struct TemplateNbt {
+    size: Vec<i32>,
+    #[serde(default)]
+    palette: Option<Vec<NoiseBlockState>>,
+    #[serde(default)]
+    palettes: Option<Vec<Vec<NoiseBlockState>>>,
+    blocks: Vec<BlockNbt>,
+}
+
+#[derive(Deserialize)]
+struct BlockNbt {
+    state: i32,
+    pos: Vec<i32>,
+    #[serde(default)]
+    nbt: Option<fastnbt::Value>,
+}
+
+impl StructureTemplate {
+    pub fn get(id: &str) -> Option<Arc<StructureTemplate>> {
+        if let Some(template) = TEMPLATES.lock().unwrap().get(id) {
+            return template.clone();
+        }
+        let template = Self::read(id).map(Arc::new);
+        if template.is_none() {
+            LOGGER.get().unwrap().println_as(
+                &format!("Could not read structure template {id}"),
+                LogDomain::Server,
+                LogLevel::Warn,
+            );
+        }
+        TEMPLATES
+            .lock()
+            .unwrap()
+            .insert(id.to_owned(), template.clone());
+        template
+    }
+
+    fn read(id: &str) -> Option<Self> {
+        let path = id.strip_prefix("minecraft:").unwrap_or(id);
+        let file = File::open(format!("{JAR_RESOURCES_DIR}/structure/{path}.nbt")).ok()?;
+        let mut data = Vec::new();
+        GzDecoder::new(file).read_to_end(&mut data).ok()?;
+        let nbt = fastnbt::from_bytes::<TemplateNbt>(&data).ok()?;
+        let [size_x, size_y, size_z] = nbt.size[..] else {
+            return None;
+        };
+        let palettes = match (nbt.palettes, nbt.palette) {
+            (Some(palettes), _) => palettes,
+            (None, Some(palette)) => vec![palette],
+            (None, None) => return None,
+        };
+        let palettes = palettes
+            .iter()
+            .map(|palette| {
+                let states = palette
+                    .iter()
+                    .map(|state| {
+                        state
+                            .id()
+                            .or_else(|| parse_block_state(&state.name))
+                            .unwrap_or(0)
+                    })
+                    .collect::<Vec<_>>();
+                Self::palette_blocks(&nbt.blocks, &states)
+            })
+            .collect::<Vec<_>>();
+        let jigsaws = palettes
+            .first()?
+            .iter()
+            .zip(&nbt.blocks)
+            .filter(|(block, _)| block_name(block.state) == "jigsaw")
+            .filter_map(|(block, raw)| Self::jigsaw(block, raw.nbt.as_ref()?))
+            .collect::<Vec<_>>();
+        Some(StructureTemplate {
+            size: (size_x, size_y, size_z),
+            palettes,
+            jigsaws,
+        })
+    }
+
+    /// Orders blocks like vanilla: full blocks first, then the other blocks and the blocks with
+    /// block entity data last, each group by height then x then z
+    fn palette_blocks(blocks: &[BlockNbt], states: &[u32]) -> Vec<TemplateBlock> {
+        let mut sorted = blocks
+            .iter()
+            .filter_map(|block| {
+                let [x, y, z] = block.pos[..] else {
+                    return None;
+                };
+                let state = *states.get(block.state as usize)?;
+                let group = if block.nbt.is_some() {
+                    2
+                } else if is_solid(state) {
+                    0
+                } else {
+                    1
+                };
+                let final_state = block
+                    .nbt
+                    .as_ref()
+                    .filter(|_| block_name(state) == "jigsaw")
+                    .map(|nbt| {
+                        let final_state = nbt_string(nbt, "final_state")
+                            .unwrap_or_else(|| "minecraft:air".to_owned());
+                        parse_block_state(&final_state).unwrap_or(0)
+                    });
+                Some((
+                    (group, y, x, z),
+                    TemplateBlock {
+                        pos: (x, y, z),
+                        state,
+                        final_state,
+                    },
+                ))
+            })
+            .collect::<Vec<_>>();
+        sorted.sort_by_key(|(key, _)| *key);
+        sorted.into_iter().map(|(_, block)| block).collect()
+    }
+
+    fn jigsaw(block: &TemplateBlock, nbt: &fastnbt::Value) -> Option<JigsawBlock> {
+        let (front, top) = property(block.state, "orientation")?.split_once('_')?;
+        let front = direction_from_name(front)?;
+        let id = |key: &str| namespaced(&nbt_string(nbt, key).unwrap_or_else(|| "empty".into()));
+        let rollable = match nbt_string(nbt, "joint").as_deref() {
+            Some("rollable") => true,
+            Some("aligned") => false,
+            _ => matches!(front, Direction::Up | Direction::Down),
+        };
+        Some(JigsawBlock {
+            pos: block.pos,
+            front,
+            top: direction_from_name(top)?,
+            name: id("name"),
+            target: id("target"),
+            pool: id("pool"),
+            rollable,
+            placement_priority: nbt_int(nbt, "placement_priority").unwrap_or(0),
+            selection_priority: nbt_int(nbt, "selection_priority").unwrap_or(0),
+        })
+    }
+
+    /// Box of the template placed at `pos` with a rotation
+    pub fn bounding_box(&self, pos: (i32, i32, i32), rotation: Rotation) -> BoundingBox {
+        let (x, y, z) = self.size;
+        let far = rotation.transform((x - 1, y - 1, z - 1));
+        BoundingBox::from_corners((0, 0, 0), far).moved(pos.0, pos.1, pos.2)
+    }
+
+    /// Jigsaws of the template placed at `pos` with a rotation
+    pub fn jigsaws(&self, pos: (i32, i32, i32), rotation: Rotation) -> Vec<JigsawBlock> {
+        self.jigsaws
+            .iter()
+            .map(|jigsaw| jigsaw.placed(pos, rotation))
+            .collect()
+    }
+
+    /// Blocks of one of the palettes, picked with a random seeded from the piece position like
+    /// vanilla's `getRandomPalette`
+    pub fn blocks(&self, random: &mut dyn RandomGenerator) -> &[TemplateBlock] {
+        &self.palettes[random.next_i32_range(self.palettes.len() as u32) as usize]
+    }
+}
+
+fn nbt_string(nbt: &fastnbt::Value, key: &str) -> Option<String> {
+    match nbt {
+        fastnbt::Value::Compound(compound) => match compound.get(key)? {
+            fastnbt::Value::String(value) => Some(value.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn nbt_int(nbt: &fastnbt::Value, key: &str) -> Option<i32> {
+    match nbt {
+        fastnbt::Value::Compound(compound) => match compound.get(key)? {
+            fastnbt::Value::Int(value) => Some(*value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotated_states_turn_their_facing() {
+        let state = |state: &str| parse_block_state(state).unwrap();
+        let stairs = state("oak_stairs[facing=north,half=top,shape=straight,waterlogged=false]");
+        assert_eq!(
+            rotated_state(stairs, Rotation::Clockwise90),
+            state("oak_stairs[facing=east,half=top,shape=straight,waterlogged=false]")
+        );
+        assert_eq!(
+            rotated_state(stairs, Rotation::Counterclockwise90),
+            state("oak_stairs[facing=west,half=top,shape=straight,waterlogged=false]")
+        );
+        assert_eq!(
+            rotated_state(state("oak_log[axis=x]"), Rotation::Clockwise90),
+            state("oak_log[axis=z]")
+        );
+        assert_eq!(
+            rotated_state(state("oak_log[axis=x]"), Rotation::Clockwise180),
+            state("oak_log[axis=x]")
+        );
+        assert_eq!(
+            rotated_state(state("rail[shape=north_east]"), Rotation::Clockwise90),
+            state("rail[shape=south_east]")
+        );
+        assert_eq!(
+            rotated_state(state("oak_sign[rotation=14]"), Rotation::Clockwise90),
+            state("oak_sign[rotation=2]")
+        );
+    }
+
+    #[test]
+    fn bounding_boxes_include_both_corners() {
+        let bounds = BoundingBox::from_corners((4, 70, -3), (-2, 60, 5));
+        assert_eq!(bounds.to_array(), [-2, 60, -3, 4, 70, 5]);
+        assert_eq!(bounds.y_span(), 11);
+        assert!(bounds.is_inside((4, 70, 5)));
+        assert!(!bounds.is_inside((5, 70, 5)));
+        let neighbour = BoundingBox::from_corners((5, 0, 0), (8, 200, 0));
+        assert!(!bounds.intersects(&neighbour));
+        assert!(bounds.inflated(1).intersects(&neighbour));
+        assert!(bounds.is_within(&bounds.union(&neighbour)));
+        let mut grown = bounds.moved(10, 0, 0);
+        grown.encapsulate((0, 0, 0));
+        assert_eq!(grown.to_array(), [0, 0, -3, 14, 70, 5]);
+    }
+}
@@ -0,0 +1,1021 @@
+use super::biome_source::{biome_id, zoomed_biome, BIOME_CLIMATES};
+use super::chunk_system::{
+    split_block_state, Chunk, ChunkBlockStates, BIOME_MAPPINGS, BLOCKSTATES, BLOCKSTATE_MAPPINGS,
+};
+use super::heightmap::{self, HEIGHTMAP_BLOCKS};
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use super::terrain_gen::func_deserialize::NoiseArg;
+use super::util::{lerp2_f64, map_f64};
+use super::value_providers::VerticalAnchor;
+use super::world_state::{deserialize_block_state, WorldState};
+use crate::RandomPositionalGenerator;
+use ahash::AHashMap;
+use serde::{Deserialize, Deserializer};
+
+/// Noises sampled by the surface system itself rather than by the rules
+const SURFACE_NOISES: [&str; 9] = [
+    "minecraft:surface",
+    "minecraft:surface_secondary",
+    "minecraft:clay_bands_offset",
+    "minecraft:badlands_pillar",
+    "minecraft:badlands_pillar_roof",
+    "minecraft:badlands_surface",
+    "minecraft:iceberg_pillar",
+    "minecraft:iceberg_pillar_roof",
+    "minecraft:iceberg_surface",
+];
+const CLAY_BAND_COUNT: usize = 192;
+
+/// Rule deciding which block replaces the default block, deserialized from the `surface_rule` of
+/// the noise settings
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum SurfaceRule {
+    /// The first rule that places a block
+    #[serde(rename = "minecraft:sequence")]
+    Sequence { sequence: Vec<SurfaceRule> },
+    #[serde(rename = "minecraft:condition")]
+    Condition {
+        if_true: SurfaceCondition,
+        then_run: Box<SurfaceRule>,
+    },
+    #[serde(rename = "minecraft:block")]
+    Block {
+        #[serde(deserialize_with = "deserialize_block_state")]
+        result_state: u32,
+    },
+    /// Terracotta bands of badlands
+    #[serde(rename = "minecraft:bandlands")]
+    Bandlands,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum SurfaceCondition {
+    #[serde(rename = "minecraft:biome")]
+    Biome {
+        #[serde(deserialize_with = "deserialize_biomes")]
+        biome_is: Vec<u16>,
+    },
+    #[serde(rename = "minecraft:noise_threshold")]
+    NoiseThreshold {
+        noise: NoiseArg,
+        min_threshold: f64,
+        max_threshold: f64,
+    },
+    /// Always true below one anchor and always false above the other with a random transition
+    #[serde(rename = "minecraft:vertical_gradient")]
+    VerticalGradient {
+        random_name: String,
+        true_at_and_below: VerticalAnchor,
+        false_at_and_above: VerticalAnchor,
+    },
+    #[serde(rename = "minecraft:y_above")]
+    YAbove {
+        anchor: VerticalAnchor,
+        surface_depth_multiplier: i32,
+        add_stone_depth: bool,
+    },
+    #[serde(rename = "minecraft:water")]
+    Water {
+        offset: i32,
+        surface_depth_multiplier: i32,
+        add_stone_depth: bool,
+    },
+    #[serde(rename = "minecraft:temperature")]
+    Temperature,
+    #[serde(rename = "minecraft:steep")]
+    Steep,
+    #[serde(rename = "minecraft:not")]
+    Not { invert: Box<SurfaceCondition> },
+    #[serde(rename = "minecraft:hole")]
+    Hole,
+    #[serde(rename = "minecraft:above_preliminary_surface")]
+    AbovePreliminarySurface,
+    #[serde(rename = "minecraft:stone_depth")]
+    StoneDepth {
+        offset: i32,
+        add_surface_depth: bool,
+        secondary_depth_range: i32,
+        surface_type: CaveSurface,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaveSurface {
+    Floor,
+    Ceiling,
+}
+
+fn deserialize_biomes<'de, D>(deserializer: D) -> Result<Vec<u16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let biomes: Vec<String> = Deserialize::deserialize(deserializer)?;
+    Ok(biomes.iter().filter_map(|biome| biome_id(biome)).collect())
+}
+
+impl SurfaceRule {
+    /// Registers the noises of the rules and of the surface system, see
+    /// `NoiseArg::precompute_noise_instance`
+    pub fn precompute_noise_instances(&self, dimension: &str) {
+        for noise in SURFACE_NOISES {
+            NoiseArg::ExternalNoise(noise.to_string()).precompute_noise_instance(dimension);
+        }
+        self.visit_conditions(&mut |condition| {
+            if let SurfaceCondition::NoiseThreshold { noise, .. } = condition {
+                noise.precompute_noise_instance(dimension);
+            }
+        });
+    }
+
+    fn visit_conditions(&self, visit: &mut impl FnMut(&SurfaceCondition)) {
+        match self {
+            SurfaceRule::Sequence { sequence } => {
+                for rule in sequence {
+                    rule.visit_conditions(visit);
+                }
+            }
+            SurfaceRule::Condition { if_true, then_run } => {
+                if_true.visit(visit);
+                then_run.visit_conditions(visit);
+            }
+            SurfaceRule::Block { .. } | SurfaceRule::Bandlands => {}
+        }
+    }
+}
+
+impl SurfaceCondition {
+    fn visit(&self, visit: &mut impl FnMut(&SurfaceCondition)) {
+        visit(self);
+        if let SurfaceCondition::Not { invert } = self {
+            invert.visit(visit);
+        }
+    }
+}
+
+/// State of the surface system derived from the world seed
+#[derive(Debug)]
+pub struct SurfaceSystem {
+    clay_bands: Vec<u32>,
+    /// Random factories of the vertical gradients by random name
+    gradient_randoms: AHashMap<String, Box<dyn RandomPositionalGenerator>>,
+    blocks: SurfaceBlocks,
+}
+
+/// Block states placed by the surface system outside of the rules
+#[derive(Debug)]
+struct SurfaceBlocks {
+    air: u32,
+    snow_block: u32,
+    packed_ice: u32,
+    eroded_badlands: Option<u16>,
+    frozen_oceans: Vec<u16>,
+}
+
+impl SurfaceSystem {
+    pub fn new(rule: &SurfaceRule, random: &dyn RandomPositionalGenerator) -> Self {
+        let mut gradient_randoms = AHashMap::new();
+        rule.visit_conditions(&mut |condition| {
+            if let SurfaceCondition::VerticalGradient { random_name, .. } = condition {
+                gradient_randoms
+                    .entry(random_name.clone())
+                    .or_insert_with(|| random.hash_to_rand(random_name).branch_positional());
+            }
+        });
+        SurfaceSystem {
+            clay_bands: Self::generate_clay_bands(random),
+            gradient_randoms,
+            blocks: SurfaceBlocks {
+                air: block_id("air"),
+                snow_block: block_id("snow_block"),
+                packed_ice: block_id("packed_ice"),
+                eroded_badlands: BIOME_MAPPINGS.get("eroded_badlands").copied(),
+                frozen_oceans: ["frozen_ocean", "deep_frozen_ocean"]
+                    .into_iter()
+                    .filter_map(|biome| BIOME_MAPPINGS.get(biome).copied())
+                    .collect(),
+            },
+        }
+    }
+
+    fn generate_clay_bands(random: &dyn RandomPositionalGenerator) -> Vec<u32> {
+        let mut rand = random.hash_to_rand("minecraft:clay_bands");
+        let mut bands = vec![block_id("terracotta"); CLAY_BAND_COUNT];
+        let mut i = 0;
+        while i < CLAY_BAND_COUNT {
+            i += rand.next_i32_range(5) as usize + 1;
+            if i < CLAY_BAND_COUNT {
+                bands[i] = block_id("orange_terracotta");
+            }
+            i += 1;
+        }
+        for (min_size, block) in [
+            (1, "yellow_terracotta"),
+            (2, "brown_terracotta"),
+            (1, "red_terracotta"),
+        ] {
+            let block = block_id(block);
+            let count = rand.next_i32_range(10) + 6;
+            for _ in 0..count {
+                let size = min_size + rand.next_i32_range(3) as usize;
+                let start = rand.next_i32_range(CLAY_BAND_COUNT as u32) as usize;
+                for band in bands.iter_mut().skip(start).take(size) {
+                    *band = block;
+                }
+            }
+        }
+        let white = block_id("white_terracotta");
+        let light_gray = block_id("light_gray_terracotta");
+        let count = rand.next_i32_range(7) + 9;
+        let mut placed = 0;
+        let mut i = 0;
+        while placed < count && i < CLAY_BAND_COUNT {
+            bands[i] = white;
+            if i > 1 && rand.next_bool() {
+                bands[i - 1] = light_gray;
+            }
+            if i + 1 < CLAY_BAND_COUNT && rand.next_bool() {
+                bands[i + 1] = light_gray;
+            }
+            placed += 1;
+            i += rand.next_i32_range(16) as usize + 4;
+        }
+        bands
+    }
+
+    /// Replaces the default block of a chunk after noise generation following the surface rule,
+    /// the biomes of the chunk must already be placed
+    pub fn build_surface(&self, world_state: &WorldState, chunk: &mut Chunk, dimension: &str) {
+        let settings = &world_state.settings;
+        let blocks = chunk
+            .sections
+            .iter()
+            .map(|section| section.block_states.block_ids())
+            .collect();
+        let Some(mut ctx) = SurfaceContext::new(self, world_state, chunk, dimension, blocks) else {
+            return;
+        };
+        let min_y = ctx.min_y;
+        let default_block = settings.default_block;
+        let chunk_x = chunk.x * 16;
+        let chunk_z = chunk.z * 16;
+        for x in 0..16 {
+            for z in 0..16 {
+                let block_x = chunk_x + x as i32;
+                let block_z = chunk_z + z as i32;
+                let start_height = ctx.heights[x][z] + 1;
+                let biome_y = if settings.legacy_random_source {
+                    0
+                } else {
+                    start_height
+                };
+                let column_biome = ctx.biomes.biome(block_x, biome_y, block_z);
+                if Some(column_biome) == self.blocks.eroded_badlands {
+                    ctx.eroded_badlands_extension(x, z, start_height, default_block);
+                }
+                let height = ctx.heights[x][z] + 1;
+                ctx.update_xz(block_x, block_z);
+                let mut stone_depth_above = 0;
+                let mut water_height = i32::MIN;
+                let mut next_ceiling_stone_y = i32::MAX;
+                for y in (min_y..=height).rev() {
+                    let block = ctx.get_block(x, y, z);
+                    let properties = HEIGHTMAP_BLOCKS[block as usize];
+                    if properties.is_air {
+                        stone_depth_above = 0;
+                        water_height = i32::MIN;
+                    } else if properties.has_fluid {
+                        if water_height == i32::MIN {
+                            water_height = y + 1;
+                        }
+                    } else {
+                        if next_ceiling_stone_y >= y {
+                            next_ceiling_stone_y = i32::MIN;
+                            for below_y in (min_y - 1..y).rev() {
+                                let below = HEIGHTMAP_BLOCKS[ctx.get_block(x, below_y, z) as usize];
+                                if below.is_air || below.has_fluid {
+                                    next_ceiling_stone_y = below_y + 1;
+                                    break;
+                                }
+                            }
+                        }
+                        stone_depth_above += 1;
+                        let stone_depth_below = y - next_ceiling_stone_y + 1;
+                        ctx.update_y(stone_depth_above, stone_depth_below, water_height, y);
+                        if block == default_block {
+                            if let Some(new_block) = ctx.apply(&settings.surface_rule) {
+                                ctx.set_block(x, y, z, new_block);
+                            }
+                        }
+                    }
+                }
+                if self.blocks.frozen_oceans.contains(&column_biome) {
+                    let min_surface_level = ctx.min_surface_level();
+                    ctx.frozen_ocean_extension(min_surface_level, column_biome, x, z, start_height);
+                }
+            }
+        }
+        for (section, block_ids) in chunk.sections.iter_mut().zip(ctx.blocks) {
+            section.block_states = ChunkBlockStates::from_block_ids(&block_ids);
+        }
+        heightmap::compute_heightmaps(chunk);
+    }
+
+    /// Evaluates the surface rule at single positions of a chunk, `None` when the chunk has no
+    /// surface heightmap
+    pub fn probe<'a>(
+        &'a self,
+        world_state: &'a WorldState,
+        chunk: &Chunk,
+        dimension: &'a str,
+    ) -> Option<SurfaceProbe<'a>> {
+        SurfaceContext::new(self, world_state, chunk, dimension, Vec::new())
+            .map(|ctx| SurfaceProbe { ctx })
+    }
+
+    /// Whether a vertical gradient holds at a position, always below `true_y` and never above
+    /// `false_y` with a chance falling linearly in between
+    fn gradient(
+        &self,
+        random_name: &str,
+        (true_y, false_y): (i32, i32),
+        x: i32,
+        y: i32,
+        z: i32,
+    ) -> bool {
+        if y <= true_y {
+            return true;
+        }
+        if y >= false_y {
+            return false;
+        }
+        let chance = map_f64(y as f64, true_y as f64, false_y as f64, 1.0, 0.0);
+        let mut rand = self.gradient_randoms[random_name].pos_to_rand(x, y, z);
+        (rand.next_f32() as f64) < chance
+    }
+
+    fn band(&self, x: i32, y: i32, z: i32, dimension: &str) -> u32 {
+        let offset = NoiseArg::ExternalNoise("minecraft:clay_bands_offset".to_string())
+            .get_or_create(dimension)
+            .get_val(x as f64, 0.0, z as f64);
+        let offset = (offset * 4.0).round() as i32;
+        self.clay_bands[(y + offset).rem_euclid(CLAY_BAND_COUNT as i32) as usize]
+    }
+}
+
+/// Block state of a block the surface system places itself, air when the mappings lack it
+fn block_id(name: &str) -> u32 {
+    BLOCKSTATE_MAPPINGS.get(name).copied().unwrap_or_else(|| {
+        LOGGER.get().unwrap().println_as(
+            &format!("Unknown block {name}, placing air instead"),
+            LogDomain::Server,
+            LogLevel::Error,
+        );
+        0
+    })
+}
+
+fn is_water(block: u32) -> bool {
+    split_block_state(&BLOCKSTATES[block as usize]).0 == "water"
+}
+
+fn noise_value(noise: &str, dimension: &str, x: f64, z: f64) -> f64 {
+    NoiseArg::ExternalNoise(noise.to_string())
+        .get_or_create(dimension)
+        .get_val(x, 0.0, z)
+}
+
+/// Biomes around a chunk at block resolution, biomes of neighbouring chunks are sampled from the
+/// biome source since they may not be generated yet
+struct BiomeLookup<'a> {
+    world_state: &'a WorldState,
+    dimension: &'a str,
+    chunk_x: i32,
+    chunk_z: i32,
+    min_quart_y: i32,
+    max_quart_y: i32,
+    sections: Vec<[u16; 64]>,
+    neighbours: AHashMap<(i32, i32, i32), u16>,
+}
+
+impl<'a> BiomeLookup<'a> {
+    fn new(world_state: &'a WorldState, chunk: &Chunk, dimension: &'a str) -> Self {
+        let sections = chunk
+            .sections
+            .iter()
+            .map(|section| section.biomes.biome_ids())
+            .collect::<Vec<_>>();
+        let min_quart_y = chunk.y as i32 * 4;
+        BiomeLookup {
+            world_state,
+            dimension,
+            chunk_x: chunk.x,
+            chunk_z: chunk.z,
+            min_quart_y,
+            max_quart_y: min_quart_y + sections.len() as i32 * 4 - 1,
+            sections,
+            neighbours: AHashMap::new(),
+        }
+    }
+
+    fn biome(&mut self, x: i32, y: i32, z: i32) -> u16 {
+        zoomed_biome(
+            self.world_state.biome_zoom_seed,
+            x,
+            y,
+            z,
+            |quart_x, quart_y, quart_z| self.noise_biome(quart_x, quart_y, quart_z),
+        )
+    }
+
+    fn noise_biome(&mut self, quart_x: i32, quart_y: i32, quart_z: i32) -> u16 {
+        let quart_y = quart_y.clamp(self.min_quart_y, self.max_quart_y);
+        let in_chunk = quart_x >> 2 == self.chunk_x && quart_z >> 2 == self.chunk_z;
+        let stored = |sections: &[[u16; 64]]| {
+            let section = ((quart_y - self.min_quart_y) >> 2) as usize;
+            let idx = ((quart_y & 3) << 4 | (quart_z & 3) << 2 | (quart_x & 3)) as usize;
+            sections[section][idx]
+        };
+        if in_chunk {
+            return stored(&self.sections);
+        }
+        let Some(biome_source) = &self.world_state.biome_source else {
+            return stored(&self.sections);
+        };
+        let noise_router = &self.world_state.settings.noise_router;
+        let dimension = self.dimension;
+        *self
+            .neighbours
+            .entry((quart_x, quart_y, quart_z))
+            .or_insert_with(|| {
+                biome_source
+                    .noise_biome(noise_router, dimension, quart_x, quart_y, quart_z)
+                    .unwrap_or_else(|| stored(&self.sections))
+            })
+    }
+}
+
+/// Surface rule evaluation outside of the surface pass, used by carvers to turn the dirt below a
+/// carved surface block into the top block of the biome
+pub struct SurfaceProbe<'a> {
+    ctx: SurfaceContext<'a>,
+}
+
+impl SurfaceProbe<'_> {
+    /// Block the surface rule places at a position as the topmost block of its column, with
+    /// fluid right above when `under_fluid` is set
+    pub fn top_material(&mut self, x: i32, y: i32, z: i32, under_fluid: bool) -> Option<u32> {
+        self.ctx.update_xz(x, z);
+        let water_height = if under_fluid { y + 1 } else { i32::MIN };
+        self.ctx.update_y(1, 1, water_height, y);
+        let world_state = self.ctx.world_state;
+        self.ctx.apply(&world_state.settings.surface_rule)
+    }
+}
+
+/// Position and depths of the block the surface rule is applied to, values only needed by some
+/// conditions are computed when first used
+struct SurfaceContext<'a> {
+    system: &'a SurfaceSystem,
+    world_state: &'a WorldState,
+    dimension: &'a str,
+    biomes: BiomeLookup<'a>,
+    /// Block states of each section, indexed by `y << 8 | z << 4 | x`
+    blocks: Vec<Vec<u32>>,
+    min_y: i32,
+    max_y: i32,
+    /// Height of the highest non air block of each column
+    heights: [[i32; 16]; 16],
+    /// Preliminary surface levels at the corners of the chunk
+    preliminary_levels: Option<[i32; 4]>,
+    block_x: i32,
+    block_y: i32,
+    block_z: i32,
+    surface_depth: i32,
+    surface_secondary: Option<f64>,
+    min_surface_level: Option<i32>,
+    stone_depth_above: i32,
+    stone_depth_below: i32,
+    water_height: i32,
+    biome: Option<u16>,
+}
+
+impl<'a> SurfaceContext<'a> {
+    /// Context over the blocks of a chunk, `None` when the chunk has no surface heightmap
+    fn new(
+        system: &'a SurfaceSystem,
+        world_state: &'a WorldState,
+        chunk: &Chunk,
+        dimension: &'a str,
+        blocks: Vec<Vec<u32>>,
+    ) -> Option<Self> {
+        let world_surface = chunk.heightmaps.world_surface.as_ref()?;
+        let min_y = chunk.y as i32 * 16;
+        let world_height = chunk.world_height();
+        let mut heights = Chunk::decode_heightmap(world_surface, world_height);
+        for height in heights.iter_mut().flatten() {
+            // Height of the highest non air block
+            *height += min_y - 1;
+        }
+        Some(SurfaceContext {
+            system,
+            world_state,
+            dimension,
+            biomes: BiomeLookup::new(world_state, chunk, dimension),
+            blocks,
+            min_y,
+            max_y: min_y + world_height,
+            heights,
+            preliminary_levels: None,
+            block_x: 0,
+            block_y: 0,
+            block_z: 0,
+            surface_depth: 0,
+            surface_secondary: None,
+            min_surface_level: None,
+            stone_depth_above: 0,
+            stone_depth_below: 0,
+            water_height: i32::MIN,
+            biome: None,
+        })
+    }
+
+    fn get_block(&self, x: usize, y: i32, z: usize) -> u32 {
+        if y < self.min_y || y >= self.max_y {
+            return self.system.blocks.air;
+        }
+        let y = y - self.min_y;
+        self.blocks[(y >> 4) as usize][((y & 15) as usize) << 8 | z << 4 | x]
+    }
+
+    fn set_block(&mut self, x: usize, y: i32, z: usize, block: u32) {
+        if y < self.min_y || y >= self.max_y {
+            return;
+        }
+        let rel_y = y - self.min_y;
+        self.blocks[(rel_y >> 4) as usize][((rel_y & 15) as usize) << 8 | z << 4 | x] = block;
+        if !HEIGHTMAP_BLOCKS[block as usize].is_air && y > self.heights[x][z] {
+            self.heights[x][z] = y;
+        }
+    }
+
+    fn update_xz(&mut self, x: i32, z: i32) {
+        self.block_x = x;
+        self.block_z = z;
+        let surface = noise_value("minecraft:surface", self.dimension, x as f64, z as f64);
+        let random = self.world_state.random.pos_to_rand(x, 0, z).next_f64();
+        self.surface_depth = (surface * 2.75 + 3.0 + random * 0.25) as i32;
+        self.surface_secondary = None;
+        self.min_surface_level = None;
+    }
+
+    fn update_y(
+        &mut self,
+        stone_depth_above: i32,
+        stone_depth_below: i32,
+        water_height: i32,
+        y: i32,
+    ) {
+        self.block_y = y;
+        self.stone_depth_above = stone_depth_above;
+        self.stone_depth_below = stone_depth_below;
+        self.water_height = water_height;
+        self.biome = None;
+    }
+
+    fn biome(&mut self) -> u16 {
+        if let Some(biome) = self.biome {
+            return biome;
+        }
+        let biome = self.biomes.biome(self.block_x, self.block_y, self.block_z);
+        self.biome = Some(biome);
+        biome
+    }
+
+    fn surface_secondary(&mut self) -> f64 {
+        *self.surface_secondary.get_or_insert_with(|| {
+            noise_value(
+                "minecraft:surface_secondary",
+                self.dimension,
+                self.block_x as f64,
+                self.block_z as f64,
+            )
+        })
+    }
+
+    /// Lowest height the surface of the column can be at, interpolated from the preliminary
+    /// surface level at the corners of the chunk
+    fn min_surface_level(&mut self) -> i32 {
+        if let Some(level) = self.min_surface_level {
+            return level;
+        }
+        let levels = match self.preliminary_levels {
+            Some(levels) => levels,
+            None => {
+                let x = self.block_x & !15;
+                let z = self.block_z & !15;
+                let levels = [
+                    self.world_state
+                        .preliminary_surface_level(x, z, self.dimension),
+                    self.world_state
+                        .preliminary_surface_level(x + 16, z, self.dimension),
+                    self.world_state
+                        .preliminary_surface_level(x, z + 16, self.dimension),
+                    self.world_state
+                        .preliminary_surface_level(x + 16, z + 16, self.dimension),
+                ];
+                self.preliminary_levels = Some(levels);
+                levels
+            }
+        };
+        let level = lerp2_f64(
+            (self.block_x & 15) as f64 / 16.0,
+            (self.block_z & 15) as f64 / 16.0,
+            levels[0] as f64,
+            levels[1] as f64,
+            levels[2] as f64,
+            levels[3] as f64,
+        )
+        .floor() as i32;
+        let level = level.wrapping_add(self.surface_depth).wrapping_sub(8);
+        self.min_surface_level = Some(level);
+        level
+    }
+
+    fn apply(&mut self, rule: &SurfaceRule) -> Option<u32> {
+        match rule {
+            SurfaceRule::Sequence { sequence } => {
+                for rule in sequence {
+                    if let Some(block) = self.apply(rule) {
+                        return Some(block);
+                    }
+                }
+                None
+            }
+            SurfaceRule::Condition { if_true, then_run } => {
+                if self.test(if_true) {
+                    self.apply(then_run)
+                } else {
+                    None
+                }
+            }
+            SurfaceRule::Block { result_state } => Some(*result_state),
+            SurfaceRule::Bandlands => {
+                Some(
+                    self.system
+                        .band(self.block_x, self.block_y, self.block_z, self.dimension),
+                )
+            }
+        }
+    }
+
+    fn test(&mut self, condition: &SurfaceCondition) -> bool {
+        let bounds = &self.world_state.settings.noise;
+        match condition {
+            SurfaceCondition::Biome { biome_is } => biome_is.contains(&self.biome()),
+            SurfaceCondition::NoiseThreshold {
+                noise,
+                min_threshold,
+                max_threshold,
+            } => {
+                let value = noise.get_or_create(self.dimension).get_val(
+                    self.block_x as f64,
+                    0.0,
+                    self.block_z as f64,
+                );
+                value >= *min_threshold && value <= *max_threshold
+            }
+            SurfaceCondition::VerticalGradient {
+                random_name,
+                true_at_and_below,
+                false_at_and_above,
+            } => {
+                let true_y = true_at_and_below.resolve_y(bounds.min_y, bounds.height);
+                let false_y = false_at_and_above.resolve_y(bounds.min_y, bounds.height);
+                self.system.gradient(
+                    random_name,
+                    (true_y, false_y),
+                    self.block_x,
+                    self.block_y,
+                    self.block_z,
+                )
+            }
+            SurfaceCondition::YAbove {
+                anchor,
+                surface_depth_multiplier,
+                add_stone_depth,
+            } => {
+                let y = self.block_y
+                    + if *add_stone_depth {
+                        self.stone_depth_above
+                    } else {
+                        0
+                    };
+                y >= anchor.resolve_y(bounds.min_y, bounds.height)
+                    + self.surface_depth * surface_depth_multiplier
+            }
+            SurfaceCondition::Water {
+                offset,
+                surface_depth_multiplier,
+                add_stone_depth,
+            } => {
+                if self.water_height == i32::MIN {
+                    return true;
+                }
+                let y = self.block_y
+                    + if *add_stone_depth {
+                        self.stone_depth_above
+                    } else {
+                        0
+                    };
+                y >= self.water_height + offset + self.surface_depth * surface_depth_multiplier
+            }
+            SurfaceCondition::Temperature => {
+                let biome = self.biome();
+                BIOME_CLIMATES[biome as usize].cold_enough_to_snow(
+                    self.block_x,
+                    self.block_y,
+                    self.block_z,
+                    self.world_state.settings.sea_level,
+                )
+            }
+            SurfaceCondition::Steep => {
+                let x = (self.block_x & 15) as usize;
+                let z = (self.block_z & 15) as usize;
+                let north = self.heights[x][z.saturating_sub(1)];
+                let south = self.heights[x][(z + 1).min(15)];
+                if south >= north + 4 {
+                    return true;
+                }
+                let west = self.heights[x.saturating_sub(1)][z];
+                let east = self.heights[(x + 1).min(15)][z];
+                west >= east + 4
+            }
+            SurfaceCondition::Not { invert } => !self.test(invert),
+            SurfaceCondition::Hole => self.surface_depth <= 0,
+            SurfaceCondition::AbovePreliminarySurface => self.block_y >= self.min_surface_level(),
+            SurfaceCondition::StoneDepth {
+                offset,
+                add_surface_depth,
+                secondary_depth_range,
+                surface_type,
+            } => {
+                let depth = if *surface_type == CaveSurface::Ceiling {
+                    self.stone_depth_below
+                } else {
+                    self.stone_depth_above
+                };
+                let surface_depth = if *add_surface_depth {
+                    self.surface_depth
+                } else {
+                    0
+                };
+                let secondary_depth = if *secondary_depth_range == 0 {
+                    0
+                } else {
+                    map_f64(
+                        self.surface_secondary(),
+                        -1.0,
+                        1.0,
+                        0.0,
+                        *secondary_depth_range as f64,
+                    ) as i32
+                };
+                depth <= 1 + offset + surface_depth + secondary_depth
+            }
+        }
+    }
+
+    /// Raises pillars of the default block out of eroded badlands
+    fn eroded_badlands_extension(&mut self, x: usize, z: usize, height: i32, default_block: u32) {
+        let block_x = (self.biomes.chunk_x * 16 + x as i32) as f64;
+        let block_z = (self.biomes.chunk_z * 16 + z as i32) as f64;
+        let dimension = self.dimension;
+        let pillar = f64::min(
+            (noise_value("minecraft:badlands_surface", dimension, block_x, block_z) * 8.25).abs(),
+            noise_value(
+                "minecraft:badlands_pillar",
+                dimension,
+                block_x * 0.2,
+                block_z * 0.2,
+            ) * 15.0,
+        );
+        if pillar <= 0.0 {
+            return;
+        }
+        let roof = (noise_value(
+            "minecraft:badlands_pillar_roof",
+            dimension,
+            block_x * 0.75,
+            block_z * 0.75,
+        ) * 1.5)
+            .abs();
+        let top =
+            (64.0 + f64::min(pillar * pillar * 2.5, (roof * 50.0).ceil() + 24.0)).floor() as i32;
+        if height > top {
+            return;
+        }
+        for y in (self.min_y..=top).rev() {
+            let block = self.get_block(x, y, z);
+            if block == default_block {
+                break;
+            }
+            if is_water(block) {
+                return;
+            }
+        }
+        for y in (self.min_y..=top).rev() {
+            if !HEIGHTMAP_BLOCKS[self.get_block(x, y, z) as usize].is_air {
+                break;
+            }
+            self.set_block(x, y, z, default_block);
+        }
+    }
+
+    /// Places icebergs of packed ice topped with snow in frozen oceans
+    fn frozen_ocean_extension(
+        &mut self,
+        min_surface_level: i32,
+        biome: u16,
+        x: usize,
+        z: usize,
+        height: i32,
+    ) {
+        let block_x = self.biomes.chunk_x * 16 + x as i32;
+        let block_z = self.biomes.chunk_z * 16 + z as i32;
+        let dimension = self.dimension;
+        let sea_level = self.world_state.settings.sea_level;
+        let (noise_x, noise_z) = (block_x as f64, block_z as f64);
+        let pillar = f64::min(
+            (noise_value("minecraft:iceberg_surface", dimension, noise_x, noise_z) * 8.25).abs(),
+            noise_value(
+                "minecraft:iceberg_pillar",
+                dimension,
+                noise_x * 1.28,
+                noise_z * 1.28,
+            ) * 15.0,
+        );
+        if pillar <= 1.8 {
+            return;
+        }
+        let roof = (noise_value(
+            "minecraft:iceberg_pillar_roof",
+            dimension,
+            noise_x * 1.17,
+            noise_z * 1.17,
+        ) * 1.5)
+            .abs();
+        let mut top = f64::min(pillar * pillar * 1.2, (roof * 40.0).ceil() + 14.0);
+        if BIOME_CLIMATES[biome as usize].temperature_at(block_x, sea_level, block_z, sea_level)
+            > 0.1
+        {
+            top -= 2.0;
+        }
+        let bottom = if top > 2.0 {
+            let bottom = sea_level as f64 - top - 7.0;
+            top += sea_level as f64;
+            bottom
+        } else {
+            top = 0.0;
+            0.0
+        };
+        let mut rand = self.world_state.random.pos_to_rand(block_x, 0, block_z);
+        let max_snow = 2 + rand.next_i32_range(4);
+        let snow_y = sea_level + 18 + rand.next_i32_range(10);
+        let mut snow_count = 0;
+        for y in (min_surface_level..=height.max(top as i32 + 1)).rev() {
+            let block = self.get_block(x, y, z);
+            let is_iceberg = (HEIGHTMAP_BLOCKS[block as usize].is_air
+                && y < top as i32
+                && rand.next_f64() > 0.01)
+                || (is_water(block)
+                    && y > bottom as i32
+                    && y < sea_level
+                    && bottom != 0.0
+                    && rand.next_f64() > 0.15);
+            if is_iceberg {
+                if snow_count <= max_snow && y > snow_y {
+                    self.set_block(x, y, z, self.system.blocks.snow_block);
+                    snow_count += 1;
+                } else {
+                    self.set_block(x, y, z, self.system.blocks.packed_ice);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::logger::ServerLogger;
+    use crate::server::randomness::Xoroshiro;
+
+    const RULE: &str = r#"{
+        "type": "minecraft:sequence",
+        "sequence": [
+            {
+                "type": "minecraft:condition",
+                "if_true": {
+                    "type": "minecraft:vertical_gradient",
+                    "random_name": "minecraft:bedrock_floor",
+                    "true_at_and_below": { "above_bottom": 0 },
+                    "false_at_and_above": { "above_bottom": 5 }
+                },
+                "then_run": {
+                    "type": "minecraft:block",
+                    "result_state": { "Name": "minecraft:bedrock" }
+                }
+            },
+            {
+                "type": "minecraft:condition",
+                "if_true": {
+                    "type": "minecraft:not",
+                    "invert": {
+                        "type": "minecraft:stone_depth",
+                        "offset": 0,
+                        "add_surface_depth": false,
+                        "secondary_depth_range": 0,
+                        "surface_type": "floor"
+                    }
+                },
+                "then_run": { "type": "minecraft:bandlands" }
+            },
+            {
+                "type": "minecraft:block",
+                "result_state": {
+                    "Name": "minecraft:grass_block",
+                    "Properties": { "snowy": "false" }
+                }
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn rules_are_read_from_noise_settings() {
+        let rule: SurfaceRule = serde_json::from_str(RULE).unwrap();
+        let SurfaceRule::Sequence { sequence } = &rule else {
+            panic!("Expected a sequence, got {rule:?}");
+        };
+        assert!(matches!(
+            sequence[2],
+            SurfaceRule::Block { result_state } if result_state == block_id("grass_block[snowy=false]")
+        ));
+        let mut conditions = 0;
+        rule.visit_conditions(&mut |_| conditions += 1);
+        assert_eq!(conditions, 3);
+
+        let unknown = r#"{"type": "minecraft:block", "result_state": {"Name": "minecraft:nope"}}"#;
+        assert!(serde_json::from_str::<SurfaceRule>(unknown).is_err());
+        LOGGER.get_or_init(ServerLogger::new);
+        assert_eq!(BLOCKSTATES[block_id("nope") as usize], "air");
+    }
+
+    #[test]
+    fn clay_bands_only_hold_terracotta() {
+        let rule: SurfaceRule = serde_json::from_str(RULE).unwrap();
+        let random = Xoroshiro::new_from_i64(1).branch_positional();
+        let system = SurfaceSystem::new(&rule, random.as_ref());
+        assert!(system
+            .gradient_randoms
+            .contains_key("minecraft:bedrock_floor"));
+        assert_eq!(system.clay_bands.len(), CLAY_BAND_COUNT);
+        assert!(system
+            .clay_bands
+            .iter()
+            .all(|band| BLOCKSTATES[*band as usize].ends_with("terracotta")));
+        assert!(system.clay_bands.contains(&block_id("white_terracotta")));
+        // The bands only depend on the seed
+        let same_seed = SurfaceSystem::generate_clay_bands(random.as_ref());
+        assert_eq!(system.clay_bands, same_seed);
+    }
+
+    #[test]
+    fn bedrock_floor_matches_vanilla() {
+        let rule: SurfaceRule = serde_json::from_str(RULE).unwrap();
+        // Seeded like the overworld, which uses the xoroshiro random source
+        let random = Xoroshiro::new_from_i64(1).branch_positional();
+        let system = SurfaceSystem::new(&rule, random.as_ref());
+        // Bedrock from y -64 to -59 in columns of seed 1, from vanilla's `bedrock_floor`
+        // vertical gradient
+        let expected = [
+            (0, 0, "##...."),
+            (5, -3, "####.."),
+            (-1234, 567, "###..."),
+            (100000, -20000, "###.#."),
+        ];
+        for (x, z, column) in expected {
+            let placed = (-64..=-59)
+                .map(
+                    |y| match system.gradient("minecraft:bedrock_floor", (-64, -59), x, y, z) {
+                        true => '#',
+                        false => '.',
+                    },
+                )
+                .collect::<String>();
+            assert_eq!(placed, column, "bedrock at {x} {z}");
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use super::biome_source::biome_id;
+use super::chunk_system::{split_block_state, BIOMES, BLOCKSTATES};
+use super::logger::{LogDomain, LogLevel, LOGGER};
+use crate::JAR_RESOURCES_DIR;
+use ahash::AHashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Deserializer};
+
+/// Block states of every block, keyed by the block name without namespace
+pub static BLOCK_STATE_IDS: Lazy<AHashMap<&'static str, Vec<u32>>> = Lazy::new(|| {
+    let mut ids: AHashMap<&'static str, Vec<u32>> = AHashMap::new();
+    for (id, state) in BLOCKSTATES.iter().enumerate() {
+        ids.entry(split_block_state(state).0)
+            .or_default()
+            .push(id as u32);
+    }
+    ids
+});
+
+/// Entries of a tag of a registry like `block` or `worldgen/biome`, none when the tag can not be
+/// read
+fn read_tag(registry: &str, tag: &str) -> Vec<String> {
+    let path = tag.strip_prefix("minecraft:").unwrap_or(tag);
+    let Some(file) =
+        std::fs::read_to_string(format!("{JAR_RESOURCES_DIR}/tags/{registry}/{path}.json"))
+            .ok()
+            .and_then(|data| serde_json::from_str::<TagFile>(&data).ok())
+    else {
+        LOGGER.get().unwrap().println_as(
+            &format!("Could not read {registry} tag #{tag}"),
+            LogDomain::Server,
+            LogLevel::Warn,
+        );
+        return Vec::new();
+    };
+    file.values
+        .into_iter()
+        .map(|entry| match entry {
+            TagEntry::Id(id) | TagEntry::Optional { id } => id,
+        })
+        .collect()
+}
+
+/// Tags included by other tags are read this many levels deep, which guards against tags
+/// including each other
+const MAX_TAG_DEPTH: u32 = 16;
+
+/// Calls `add` with every id of a holder set entry like `#minecraft:dirt` or `minecraft:stone`,
+/// following the tags it includes
+fn visit_entry(registry: &str, id: &str, depth: u32, add: &mut impl FnMut(&str)) {
+    let Some(tag) = id.strip_prefix('#') else {
+        add(id);
+        return;
+    };
+    if depth <= MAX_TAG_DEPTH {
+        for id in read_tag(registry, tag) {
+            visit_entry(registry, &id, depth + 1, add);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TagFile {
+    values: Vec<TagEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TagEntry {
+    Id(String),
+    Optional { id: String },
+}
+
+/// Set of block states matched by a block tag or a list of blocks, with every state of a block
+/// included
+#[derive(Debug, Clone, Default)]
+pub struct BlockSet {
+    states: Vec<bool>,
+}
+
+impl BlockSet {
+    /// Parses a holder set like `#minecraft:dirt`, `minecraft:stone` or a list of block ids
+    pub fn from_ids(ids: &[String]) -> Self {
+        let mut set = BlockSet {
+            states: vec![false; BLOCKSTATES.len()],
+        };
+        for id in ids {
+            visit_entry("block", id, 0, &mut |id| set.add(id));
+        }
+        set
+    }
+
+    pub fn contains(&self, block: u32) -> bool {
+        self.states.get(block as usize).copied().unwrap_or(false)
+    }
+
+    fn add(&mut self, id: &str) {
+        let name = id.strip_prefix("minecraft:").unwrap_or(id);
+        match BLOCK_STATE_IDS.get(name) {
+            Some(states) => {
+                for state in states {
+                    self.states[*state as usize] = true;
+                }
+            }
+            None => LOGGER.get().unwrap().println_as(
+                &format!("Unknown block {id} in block set"),
+                LogDomain::Server,
+                LogLevel::Warn,
+            ),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockSet {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum HolderSet {
+            Single(String),
+            List(Vec<String>),
+        }
+        Ok(match HolderSet::deserialize(deserializer)? {
+            HolderSet::Single(id) => BlockSet::from_ids(&[id]),
+            HolderSet::List(ids) => BlockSet::from_ids(&ids),
+        })
+    }
+}
+
+/// Set of biomes matched by a biome tag or a list of biomes
+#[derive(Debug, Clone, Default)]
+pub struct BiomeSet {
+    biomes: Vec<bool>,
+}
+
+impl BiomeSet {
+    /// Parses a holder set like `#minecraft:is_ocean`, `minecraft:plains` or a list of biome ids
+    pub fn from_ids(ids: &[String]) -> Self {
+        let mut set = BiomeSet {
+            biomes: vec![false; BIOMES.len()],
+        };
+        for id in ids {
+            visit_entry("worldgen/biome", id, 0, &mut |id| set.add(id));
+        }
+        set
+    }
+
+    pub fn contains(&self, biome: u16) -> bool {
+        self.biomes.get(biome as usize).copied().unwrap_or(false)
+    }
+
+    fn add(&mut self, id: &str) {
+        if let Some(biome) = biome_id(id) {
+            self.biomes[biome as usize] = true;
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BiomeSet {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum HolderSet {
+            Single(String),
+            List(Vec<String>),
+        }
+        Ok(match HolderSet::deserialize(deserializer)? {
+            HolderSet::Single(id) => BiomeSet::from_ids(&[id]),
+            HolderSet::List(ids) => BiomeSet::from_ids(&ids),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::chunk_system::{BIOME_MAPPINGS, BLOCKSTATE_MAPPINGS};
+    use crate::server::logger::ServerLogger;
+
+    #[test]
+    fn holder_sets_hold_every_listed_entry() {
+        LOGGER.get_or_init(ServerLogger::new);
+        let blocks: BlockSet = serde_json::from_str(
+            r##"["minecraft:stone", "oak_log", "minecraft:not_a_block", "#minecraft:not_a_tag"]"##,
+        )
+        .unwrap();
+        assert!(blocks.contains(BLOCKSTATE_MAPPINGS["stone"]));
+        // Every state of a listed block is included
+        assert!(blocks.contains(BLOCKSTATE_MAPPINGS["oak_log[axis=x]"]));
+        assert!(blocks.contains(BLOCKSTATE_MAPPINGS["oak_log[axis=z]"]));
+        assert!(!blocks.contains(BLOCKSTATE_MAPPINGS["dirt"]));
+        assert_eq!(blocks.states.iter().filter(|state| **state).count(), 4);
+
+        let biomes: BiomeSet = serde_json::from_str(r#""minecraft:plains""#).unwrap();
+        assert!(biomes.contains(BIOME_MAPPINGS["plains"]));
+        assert!(!biomes.contains(BIOME_MAPPINGS["desert"]));
+        assert!(!biomes.contains(u16::MAX));
+    }
+}
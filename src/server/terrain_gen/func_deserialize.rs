@@ -3,6 +3,7 @@
 * being deleted or almost completely rewritten since this code is kinda hectic and there
 * is a much more optimal way of evaluating the density function through precomputing it's pipeline.
 */
+use super::super::blender::Blender;
 use super::super::chunk_system::LudiChunkLoader;
 use super::super::util::get_dir_files;
 use super::{
@@ -291,6 +292,8 @@ pub struct DensityFnArgs<'a> {
     /// A Vec of packked coords (x, y, z) used for context in compute_slice methods
     pub slice_positions: &'a [u64],
     pub c_count: u64,
+    /// Blends the terrain into old chunks nearby, blend functions pass through without it
+    pub blender: Option<&'a Blender>,
 }
 
 impl<'a> DensityFnArgs<'a> {
@@ -467,15 +470,15 @@ impl DensityFnType {
             DensityFnType::WeirdScaledSampler(x) => x.compute(args),
             DensityFnType::Interpolated(x) => x.compute(args),
             DensityFnType::BlendDensity(x) => x.compute(args),
-            DensityFnType::BlendOffset(x) => 0.0,
-            DensityFnType::BlendAlpha(x) => 1.0,
+            DensityFnType::BlendOffset(x) => x.compute(args),
+            DensityFnType::BlendAlpha(x) => x.compute(args),
             DensityFnType::CacheOnce(x) => x.compute(args),
             DensityFnType::FlatCache(x) => x.compute(args),
             DensityFnType::Cache2D(x) => x.compute(args),
             DensityFnType::ShiftA(x) => x.compute(args),
             DensityFnType::ShiftB(x) => x.compute(args),
             DensityFnType::OldBlendedNoise(x) => x.compute(args),
-            DensityFnType::EndIslands(x) => 1.0, // unimplemented
+            DensityFnType::EndIslands(x) => x.compute(args),
         }
     }
 
@@ -501,15 +504,15 @@ impl DensityFnType {
             DensityFnType::WeirdScaledSampler(x) => x.compute_slice(args, data),
             DensityFnType::Interpolated(x) => x.compute_slice(args, data),
             DensityFnType::BlendDensity(x) => x.compute_slice(args, data),
-            DensityFnType::BlendOffset(x) => data.fill(0.0),
-            DensityFnType::BlendAlpha(x) => data.fill(1.0),
+            DensityFnType::BlendOffset(x) => x.compute_slice(args, data),
+            DensityFnType::BlendAlpha(x) => x.compute_slice(args, data),
             DensityFnType::CacheOnce(x) => x.compute_slice(args, data),
             DensityFnType::FlatCache(x) => x.compute_slice(args, data),
             DensityFnType::Cache2D(x) => x.compute_slice(args, data),
             DensityFnType::ShiftA(x) => x.compute_slice(args, data),
             DensityFnType::ShiftB(x) => x.compute_slice(args, data),
             DensityFnType::OldBlendedNoise(x) => x.compute_slice(args, data),
-            DensityFnType::EndIslands(x) => unimplemented!("end islands"),
+            DensityFnType::EndIslands(x) => x.compute_slice(args, data),
         }
     }
 
@@ -535,15 +538,15 @@ impl DensityFnType {
             DensityFnType::WeirdScaledSampler(x) => x.compute_slice_keep_cache(args, data),
             DensityFnType::Interpolated(x) => x.compute_slice_keep_cache(args, data),
             DensityFnType::BlendDensity(x) => x.compute_slice_keep_cache(args, data),
-            DensityFnType::BlendOffset(x) => data.fill(0.0),
-            DensityFnType::BlendAlpha(x) => data.fill(1.0),
+            DensityFnType::BlendOffset(x) => x.compute_slice_keep_cache(args, data),
+            DensityFnType::BlendAlpha(x) => x.compute_slice_keep_cache(args, data),
             DensityFnType::CacheOnce(x) => x.compute_slice_keep_cache(args, data),
             DensityFnType::FlatCache(x) => x.compute_slice_keep_cache(args, data),
             DensityFnType::Cache2D(x) => x.compute_slice_keep_cache(args, data),
             DensityFnType::ShiftA(x) => x.compute_slice_keep_cache(args, data),
             DensityFnType::ShiftB(x) => x.compute_slice_keep_cache(args, data),
             DensityFnType::OldBlendedNoise(x) => x.compute_slice_keep_cache(args, data),
-            DensityFnType::EndIslands(x) => unimplemented!("end islands"),
+            DensityFnType::EndIslands(x) => x.compute_slice_keep_cache(args, data),
         }
     }
 
@@ -569,15 +572,15 @@ impl DensityFnType {
             DensityFnType::WeirdScaledSampler(x) => 0.0,
             DensityFnType::Interpolated(x) => x.get_min(args),
             DensityFnType::BlendDensity(x) => f64::NEG_INFINITY,
-            DensityFnType::BlendOffset(x) => 0.0,
-            DensityFnType::BlendAlpha(x) => 1.0,
+            DensityFnType::BlendOffset(x) => x.get_min(args),
+            DensityFnType::BlendAlpha(x) => x.get_min(args),
             DensityFnType::CacheOnce(x) => x.get_min(args),
             DensityFnType::FlatCache(x) => x.get_min(args),
             DensityFnType::Cache2D(x) => x.get_min(args),
             DensityFnType::ShiftA(x) => x.get_min(args),
             DensityFnType::ShiftB(x) => x.get_min(args),
             DensityFnType::OldBlendedNoise(x) => x.get_min(args),
-            DensityFnType::EndIslands(x) => unimplemented!("end islands"),
+            DensityFnType::EndIslands(x) => x.get_min(args),
         }
     }
 
@@ -603,15 +606,15 @@ impl DensityFnType {
             DensityFnType::WeirdScaledSampler(x) => x.get_max(args),
             DensityFnType::Interpolated(x) => x.get_max(args),
             DensityFnType::BlendDensity(x) => f64::INFINITY,
-            DensityFnType::BlendOffset(x) => 0.0,
-            DensityFnType::BlendAlpha(x) => 1.0,
+            DensityFnType::BlendOffset(x) => x.get_max(args),
+            DensityFnType::BlendAlpha(x) => x.get_max(args),
             DensityFnType::CacheOnce(x) => x.get_max(args),
             DensityFnType::FlatCache(x) => x.get_max(args),
             DensityFnType::Cache2D(x) => x.get_max(args),
             DensityFnType::ShiftA(x) => x.get_max(args),
             DensityFnType::ShiftB(x) => x.get_max(args),
             DensityFnType::OldBlendedNoise(x) => x.get_max(args),
-            DensityFnType::EndIslands(x) => unimplemented!("end islands"),
+            DensityFnType::EndIslands(x) => x.get_max(args),
         }
     }
 
@@ -636,15 +639,15 @@ impl DensityFnType {
             DensityFnType::WeirdScaledSampler(x) => x.get_tree_hash(state),
             DensityFnType::Interpolated(x) => x.get_tree_hash(state),
             DensityFnType::BlendDensity(x) => x.get_tree_hash(state),
-            DensityFnType::BlendOffset(x) => "blend_offset".hash(state),
-            DensityFnType::BlendAlpha(x) => "blend_alpha".hash(state),
+            DensityFnType::BlendOffset(x) => x.get_tree_hash(state),
+            DensityFnType::BlendAlpha(x) => x.get_tree_hash(state),
             DensityFnType::CacheOnce(x) => x.get_tree_hash(state),
             DensityFnType::FlatCache(x) => x.get_tree_hash(state),
             DensityFnType::Cache2D(x) => x.get_tree_hash(state),
             DensityFnType::ShiftA(x) => x.get_tree_hash(state),
             DensityFnType::ShiftB(x) => x.get_tree_hash(state),
             DensityFnType::OldBlendedNoise(x) => x.get_tree_hash(state),
-            DensityFnType::EndIslands(x) => {} // unimplemented
+            DensityFnType::EndIslands(x) => x.get_tree_hash(state),
         }
     }
 
@@ -677,7 +680,7 @@ impl DensityFnType {
             DensityFnType::ShiftA(x) => x.precompute_noise_instance(dimension),
             DensityFnType::ShiftB(x) => x.precompute_noise_instance(dimension),
             DensityFnType::OldBlendedNoise(x) => {}
-            DensityFnType::EndIslands(x) => {} // unimplemented
+            DensityFnType::EndIslands(x) => x.precompute_noise_instance(dimension),
         }
     }
 
@@ -736,21 +739,15 @@ impl DensityFnType {
             DensityFnType::WeirdScaledSampler(x) => x.generate_state(dimension, outline),
             DensityFnType::Interpolated(x) => x.generate_state(dimension, outline),
             DensityFnType::BlendDensity(x) => x.generate_state(dimension, outline),
-            DensityFnType::BlendOffset(x) => {
-                outline.new_stack_frame(DensityFnOutlineType::BlendOffset)
-            }
-            DensityFnType::BlendAlpha(x) => {
-                outline.new_stack_frame(DensityFnOutlineType::BlendAlpha)
-            }
+            DensityFnType::BlendOffset(x) => x.generate_state(dimension, outline),
+            DensityFnType::BlendAlpha(x) => x.generate_state(dimension, outline),
             DensityFnType::CacheOnce(x) => x.generate_state(dimension, outline),
             DensityFnType::FlatCache(x) => x.generate_state(dimension, outline),
             DensityFnType::Cache2D(x) => x.generate_state(dimension, outline),
             DensityFnType::ShiftA(x) => x.generate_state(dimension, outline),
             DensityFnType::ShiftB(x) => x.generate_state(dimension, outline),
             DensityFnType::OldBlendedNoise(x) => x.generate_state(dimension, outline),
-            DensityFnType::EndIslands(x) => {
-                outline.function_flow.push(DensityFnOutlineType::EndIslands)
-            }
+            DensityFnType::EndIslands(x) => x.generate_state(dimension, outline),
         }
     }
 }
@@ -1089,12 +1086,3 @@ pub enum DensityOutlineArgType {
     Constant = 0,
     Function = 1,
 }
-
-#[derive(Deserialize, Debug)]
-pub struct BlendOffsetFn;
-
-#[derive(Deserialize, Debug)]
-pub struct BlendAlphaFn;
-
-#[derive(Deserialize, Debug)]
-pub struct EndIslandsFn;
@@ -1,10 +1,13 @@
 mod abs;
 mod add;
+mod blend_alpha;
 mod blend_density;
+mod blend_offset;
 mod cache_2d;
 mod cache_once;
 mod clamp;
 mod cube;
+mod end_islands;
 mod flat_cache;
 mod half_negative;
 mod interpolated;
@@ -29,11 +32,14 @@ use super::func_deserialize::{DensityArg, DensityFnArgs, NoiseArg};
 pub use abs::AbsFn;
 pub use add::AddFn;
 use ahash::AHasher;
+pub use blend_alpha::BlendAlphaFn;
 pub use blend_density::BlendDensityFn;
+pub use blend_offset::BlendOffsetFn;
 pub use cache_2d::Cache2DFn;
 pub use cache_once::CacheOnceFn;
 pub use clamp::ClampFn;
 pub use cube::CubeFn;
+pub use end_islands::EndIslandsFn;
 pub use flat_cache::FlatCacheFn;
 pub use half_negative::HalfNegativeFn;
 pub use interpolated::InterpolatedFn;
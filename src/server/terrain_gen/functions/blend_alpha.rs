@@ -0,0 +1,43 @@
+use std::hash::Hash;
+
+use crate::server::terrain_gen::func_deserialize::{DensityFnOutline, DensityFnOutlineType};
+
+use super::{DensityFn, DensityFnArgs};
+use serde::Deserialize;
+
+/// How much of the new terrain is kept at a column, lower next to old chunks
+#[derive(Deserialize, Debug)]
+pub struct BlendAlphaFn;
+
+impl DensityFn for BlendAlphaFn {
+    #[inline]
+    fn compute(&self, args: &mut DensityFnArgs) -> f64 {
+        args.blender.map_or(1.0, |blender| {
+            blender
+                .blend_offset_and_factor(args.block_x, args.block_z)
+                .0
+        })
+    }
+
+    #[inline]
+    fn get_min(&self, _args: &mut DensityFnArgs) -> f64 {
+        0.0
+    }
+
+    #[inline]
+    fn get_max(&self, _args: &mut DensityFnArgs) -> f64 {
+        1.0
+    }
+
+    fn get_tree_hash(&self, state: &mut ahash::AHasher) {
+        "blend_alpha".hash(state);
+    }
+
+    fn get_max_branch_depth(&self) -> u16 {
+        0
+    }
+
+    fn generate_state(&self, _dimension: &str, outline: &mut DensityFnOutline) {
+        outline.new_stack_frame(DensityFnOutlineType::BlendAlpha);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::server::terrain_gen::func_deserialize::{DensityFnOutline, DensityFnOutlineType};
 
+use super::super::super::chunk_system::LudiChunkLoader;
 use super::{DensityArg, DensityFn, DensityFnArgs};
 use serde::Deserialize;
 
@@ -11,14 +12,21 @@ pub struct BlendDensityFn {
 impl DensityFn for BlendDensityFn {
     #[inline]
     fn compute(&self, args: &mut DensityFnArgs) -> f64 {
-        // Temporary until blender is implemented in args
-        // Will eventually be wrapped in call to blend_density function
-        self.argument.compute(args)
+        let density = self.argument.compute(args);
+        args.blender.map_or(density, |blender| {
+            blender.blend_density(args.block_x, args.block_y, args.block_z, density)
+        })
     }
 
     #[inline]
     fn compute_slice(&self, args: &mut DensityFnArgs, data: &mut Vec<f64>) {
         self.argument.compute_slice(args, data);
+        if let Some(blender) = args.blender {
+            for (i, density) in data.iter_mut().enumerate() {
+                let (x, y, z) = LudiChunkLoader::unpack_xyz(args.slice_positions[i]);
+                *density = blender.blend_density(x, y, z, *density);
+            }
+        }
     }
 
     #[inline]
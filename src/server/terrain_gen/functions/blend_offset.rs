@@ -0,0 +1,43 @@
+use std::hash::Hash;
+
+use crate::server::terrain_gen::func_deserialize::{DensityFnOutline, DensityFnOutlineType};
+
+use super::{DensityFn, DensityFnArgs};
+use serde::Deserialize;
+
+/// Offset moving the terrain height of a column towards the height of old chunks nearby
+#[derive(Deserialize, Debug)]
+pub struct BlendOffsetFn;
+
+impl DensityFn for BlendOffsetFn {
+    #[inline]
+    fn compute(&self, args: &mut DensityFnArgs) -> f64 {
+        args.blender.map_or(0.0, |blender| {
+            blender
+                .blend_offset_and_factor(args.block_x, args.block_z)
+                .1
+        })
+    }
+
+    #[inline]
+    fn get_min(&self, _args: &mut DensityFnArgs) -> f64 {
+        f64::NEG_INFINITY
+    }
+
+    #[inline]
+    fn get_max(&self, _args: &mut DensityFnArgs) -> f64 {
+        f64::INFINITY
+    }
+
+    fn get_tree_hash(&self, state: &mut ahash::AHasher) {
+        "blend_offset".hash(state);
+    }
+
+    fn get_max_branch_depth(&self) -> u16 {
+        0
+    }
+
+    fn generate_state(&self, _dimension: &str, outline: &mut DensityFnOutline) {
+        outline.new_stack_frame(DensityFnOutlineType::BlendOffset);
+    }
+}
@@ -0,0 +1,148 @@
+use std::hash::Hash;
+use std::sync::OnceLock;
+
+use crate::server::randomness::LCG48;
+use crate::server::terrain_gen::func_deserialize::{DensityFnOutline, DensityFnOutlineType};
+use crate::server::world_state::WORLD_STATES;
+
+use super::super::noise_generator::SimplexNoise;
+use super::{DensityFn, DensityFnArgs};
+use ahash::AHashMap;
+use serde::Deserialize;
+
+/// Central island of the End and the rings of outer islands, like vanilla's
+/// `EndIslandDensityFunction`
+#[derive(Deserialize, Debug)]
+pub struct EndIslandsFn {
+    /// Seeded from the world seed once the world states exist
+    #[serde(skip)]
+    island_noise: OnceLock<SimplexNoise>,
+}
+
+impl EndIslandsFn {
+    /// Islands of a world seed, used without the world states
+    pub fn with_seed(seed: i64) -> Self {
+        Self {
+            island_noise: OnceLock::from(Self::island_noise(seed)),
+        }
+    }
+
+    fn island_noise(seed: i64) -> SimplexNoise {
+        let mut random = LCG48::new(seed);
+        random.skip(17292);
+        SimplexNoise::new(&mut random)
+    }
+
+    /// Height of the islands at a position in units of 8 blocks
+    fn height_value(noise: &SimplexNoise, x: i32, z: i32) -> f32 {
+        let (cell_x, cell_z) = (x / 2, z / 2);
+        let (offset_x, offset_z) = (x % 2, z % 2);
+        // Java's int arithmetic, far from the origin the distance wraps around like in vanilla
+        let distance = (x.wrapping_mul(x).wrapping_add(z.wrapping_mul(z)) as f32).sqrt();
+        let mut height = (100.0 - distance * 8.0).clamp(-100.0, 80.0);
+        for dx in -12..=12 {
+            for dz in -12..=12 {
+                let island_x = (cell_x + dx) as i64;
+                let island_z = (cell_z + dz) as i64;
+                if island_x * island_x + island_z * island_z <= 4096
+                    || noise.get_val_2d(island_x as f64, island_z as f64) >= (-0.9f32) as f64
+                {
+                    continue;
+                }
+                let falloff = ((island_x as f32).abs() * 3439.0 + (island_z as f32).abs() * 147.0)
+                    % 13.0
+                    + 9.0;
+                let distance_x = (offset_x - dx * 2) as f32;
+                let distance_z = (offset_z - dz * 2) as f32;
+                let island = (100.0
+                    - (distance_x * distance_x + distance_z * distance_z).sqrt() * falloff)
+                    .clamp(-100.0, 80.0);
+                height = height.max(island);
+            }
+        }
+        height
+    }
+
+    #[inline]
+    fn compute_at(noise: &SimplexNoise, x: i32, z: i32) -> f64 {
+        (Self::height_value(noise, x, z) as f64 - 8.0) / 128.0
+    }
+}
+
+impl DensityFn for EndIslandsFn {
+    #[inline]
+    fn compute(&self, args: &mut DensityFnArgs) -> f64 {
+        Self::compute_at(
+            self.island_noise.get().unwrap(),
+            args.block_x / 8,
+            args.block_z / 8,
+        )
+    }
+
+    /// The value only changes every 8 blocks horizontally so it is computed once per column of
+    /// the slice
+    #[inline]
+    fn compute_slice(&self, args: &mut DensityFnArgs, data: &mut Vec<f64>) {
+        self.compute_slice_keep_cache(args, data);
+    }
+
+    #[inline]
+    fn compute_slice_keep_cache(&self, args: &mut DensityFnArgs, data: &mut Vec<f64>) {
+        let noise = self.island_noise.get().unwrap();
+        let mut columns = AHashMap::new();
+        for (i, value) in data.iter_mut().enumerate() {
+            args.mutate_coord_from_slice(i);
+            let column = (args.block_x / 8, args.block_z / 8);
+            *value = *columns
+                .entry(column)
+                .or_insert_with(|| Self::compute_at(noise, column.0, column.1));
+        }
+    }
+
+    #[inline]
+    fn get_min(&self, _args: &mut DensityFnArgs) -> f64 {
+        -0.84375
+    }
+
+    #[inline]
+    fn get_max(&self, _args: &mut DensityFnArgs) -> f64 {
+        0.5625
+    }
+
+    fn get_tree_hash(&self, state: &mut ahash::AHasher) {
+        "end_islands".hash(state);
+    }
+
+    fn precompute_noise_instance(&self, dimension: &str) {
+        self.island_noise
+            .get_or_init(|| Self::island_noise(WORLD_STATES.get().unwrap()[dimension].seed));
+    }
+
+    fn get_max_branch_depth(&self) -> u16 {
+        0
+    }
+
+    fn generate_state(&self, _dimension: &str, outline: &mut DensityFnOutline) {
+        outline.function_flow.push(DensityFnOutlineType::EndIslands);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_island_sits_at_the_origin() {
+        let noise = EndIslandsFn::island_noise(1);
+        assert_eq!(EndIslandsFn::compute_at(&noise, 0, 0), 0.5625);
+        // The main island slopes down by 8 units per cell
+        assert_eq!(EndIslandsFn::compute_at(&noise, 8, 0), 0.21875);
+        assert_eq!(EndIslandsFn::compute_at(&noise, 0, -8), 0.21875);
+        // The void between the main island and the outer islands
+        assert_eq!(EndIslandsFn::compute_at(&noise, 30, 0), -0.84375);
+        for x in 200..260 {
+            let density = EndIslandsFn::compute_at(&noise, x, 40);
+            assert!((-0.84375..=0.5625).contains(&density));
+        }
+    }
+}
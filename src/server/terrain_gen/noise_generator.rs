@@ -471,3 +471,125 @@ const GRAD_Y: [f64; 16] = [
 const GRAD_Z: [f64; 16] = [
     0.0, 0.0, 0.0, 0.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 0.0, 1.0, 0.0, -1.0,
 ];
+
+#[derive(Debug, Clone)]
+pub struct SimplexNoise {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    values: [i32; 256],
+}
+
+impl SimplexNoise {
+    const F2: f64 = 0.5 * (1.7320508075688772 - 1.0);
+    const G2: f64 = (3.0 - 1.7320508075688772) / 6.0;
+
+    pub fn new(rand: &mut Box<dyn RandomGenerator>) -> Self {
+        let x = rand.next_f64() * 256.0;
+        let y = rand.next_f64() * 256.0;
+        let z = rand.next_f64() * 256.0;
+        let mut values = [0i32; 256];
+        for (i, val) in values.iter_mut().enumerate() {
+            *val = i as i32;
+        }
+        for i in 0..256 {
+            let t = i + rand.next_i32_range(256 - i as u32) as usize;
+            values.swap(i, t);
+        }
+        SimplexNoise { x, y, z, values }
+    }
+
+    #[inline(always)]
+    fn value_at(&self, i: i32) -> i32 {
+        self.values[i as usize & 0xFF]
+    }
+
+    #[inline(always)]
+    fn corner_noise(grad: i32, x: f64, y: f64, offset: f64) -> f64 {
+        let falloff = offset - x * x - y * y;
+        if falloff < 0.0 {
+            0.0
+        } else {
+            let falloff = falloff * falloff;
+            let grad = SIMPLEX_GRADIENT[grad as usize];
+            falloff * falloff * (grad[0] as f64 * x + grad[1] as f64 * y)
+        }
+    }
+
+    pub fn get_val_2d(&self, x: f64, y: f64) -> f64 {
+        let skew = (x + y) * Self::F2;
+        let x_cell = (x + skew).floor() as i32;
+        let y_cell = (y + skew).floor() as i32;
+        let unskew = (x_cell + y_cell) as f64 * Self::G2;
+        let x0 = x - (x_cell as f64 - unskew);
+        let y0 = y - (y_cell as f64 - unskew);
+        let (x_step, y_step) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let x1 = x0 - x_step as f64 + Self::G2;
+        let y1 = y0 - y_step as f64 + Self::G2;
+        let x2 = x0 - 1.0 + 2.0 * Self::G2;
+        let y2 = y0 - 1.0 + 2.0 * Self::G2;
+        let xi = x_cell & 0xFF;
+        let yi = y_cell & 0xFF;
+        let grad0 = self.value_at(xi + self.value_at(yi)) % 12;
+        let grad1 = self.value_at(xi + x_step + self.value_at(yi + y_step)) % 12;
+        let grad2 = self.value_at(xi + 1 + self.value_at(yi + 1)) % 12;
+        70.0 * (Self::corner_noise(grad0, x0, y0, 0.5)
+            + Self::corner_noise(grad1, x1, y1, 0.5)
+            + Self::corner_noise(grad2, x2, y2, 0.5))
+    }
+}
+
+/// Octaves of 2d simplex noise, only octaves up to 0 are supported which covers every use in
+/// vanilla world generation
+#[derive(Debug, Clone)]
+pub struct PerlinSimplexNoise {
+    noise_levels: Vec<Option<SimplexNoise>>,
+    highest_freq_input_factor: f64,
+    highest_freq_value_factor: f64,
+}
+
+impl PerlinSimplexNoise {
+    pub fn new(rand: &mut Box<dyn RandomGenerator>, octaves: &[i32]) -> Self {
+        let lowest = -octaves.iter().copied().min().unwrap();
+        let highest = octaves.iter().copied().max().unwrap();
+        assert!(highest <= 0, "Simplex octaves above 0 are not supported");
+        let count = lowest + highest + 1;
+        let first = SimplexNoise::new(rand);
+        let mut noise_levels = vec![None; count as usize];
+        if highest >= 0 && highest < count && octaves.contains(&0) {
+            noise_levels[highest as usize] = Some(first);
+        }
+        for i in highest + 1..count {
+            if i >= 0 && octaves.contains(&(highest - i)) {
+                noise_levels[i as usize] = Some(SimplexNoise::new(rand));
+            } else {
+                rand.skip(262);
+            }
+        }
+        PerlinSimplexNoise {
+            noise_levels,
+            highest_freq_input_factor: 2f64.powi(highest),
+            highest_freq_value_factor: 1.0 / (2f64.powi(count) - 1.0),
+        }
+    }
+
+    pub fn get_val(&self, x: f64, y: f64, use_offsets: bool) -> f64 {
+        let mut value = 0.0;
+        let mut input_factor = self.highest_freq_input_factor;
+        let mut value_factor = self.highest_freq_value_factor;
+        for noise in self.noise_levels.iter() {
+            if let Some(noise) = noise {
+                let (x_offset, y_offset) = if use_offsets {
+                    (noise.x, noise.y)
+                } else {
+                    (0.0, 0.0)
+                };
+                value += noise.get_val_2d(x * input_factor + x_offset, y * input_factor + y_offset)
+                    * value_factor;
+            }
+            input_factor /= 2.0;
+            value_factor *= 2.0;
+        }
+        value
+    }
+}
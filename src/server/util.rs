@@ -4,6 +4,14 @@ use std::{
 };
 
 use ahash::AHasher;
+use once_cell::sync::Lazy;
+
+/// Table of the vanilla `Mth.sin`, carvers and features rely on its rounding
+static SIN_TABLE: Lazy<Vec<f32>> = Lazy::new(|| {
+    (0..65536)
+        .map(|i| (i as f64 / 10430.378350470453).sin() as f32)
+        .collect()
+});
 
 #[inline(always)]
 pub fn lerp_f64(pos: f64, x: f64, y: f64) -> f64 {
@@ -51,6 +59,11 @@ pub fn smoothstep(value: f64) -> f64 {
     value * value * value * (value * (value * 6.0 - 15.0) + 10.0)
 }
 
+#[inline(always)]
+pub fn map_f64(pos1: f64, x: f64, y: f64, val1: f64, val2: f64) -> f64 {
+    lerp_f64(inverse_lerp_f64(pos1, x, y), val1, val2)
+}
+
 #[inline(always)]
 pub fn clamped_map_f64(pos1: f64, x: f64, y: f64, val1: f64, val2: f64) -> f64 {
     let inv_lerp = inverse_lerp_f64(pos1, x, y);
@@ -63,6 +76,18 @@ pub fn clamped_map_f64(pos1: f64, x: f64, y: f64, val1: f64, val2: f64) -> f64 {
     }
 }
 
+/// Sine looked up from a table like vanilla's `Mth.sin`
+#[inline(always)]
+pub fn table_sin(value: f64) -> f32 {
+    SIN_TABLE[((value * 10430.378350470453) as i64 & 65535) as usize]
+}
+
+/// Cosine looked up from a table like vanilla's `Mth.cos`
+#[inline(always)]
+pub fn table_cos(value: f64) -> f32 {
+    SIN_TABLE[((value * 10430.378350470453 + 16384.0) as i64 & 65535) as usize]
+}
+
 pub fn get_dir_files(
     dir: fs::ReadDir,
     files: &mut Vec<(String, fs::File)>,
@@ -0,0 +1,481 @@
+use crate::RandomGenerator;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum VerticalAnchor {
+    Absolute(i32),
+    AboveBottom(i32),
+    BelowTop(i32),
+}
+
+impl VerticalAnchor {
+    pub fn resolve_y(&self, min_y: i32, height: i32) -> i32 {
+        match self {
+            VerticalAnchor::Absolute(y) => *y,
+            VerticalAnchor::AboveBottom(offset) => min_y + offset,
+            VerticalAnchor::BelowTop(offset) => height - 1 + min_y - offset,
+        }
+    }
+}
+
+/// Random integer in `min..=max` like vanilla's `Mth.randomBetweenInclusive`
+fn random_between_inclusive(random: &mut dyn RandomGenerator, min: i32, max: i32) -> i32 {
+    random.next_i32_range((max - min + 1) as u32) + min
+}
+
+/// Like vanilla's `Mth.nextInt`, which returns `min` instead of failing on empty ranges
+fn next_int_between(random: &mut dyn RandomGenerator, min: i32, max: i32) -> i32 {
+    if min >= max {
+        min
+    } else {
+        random_between_inclusive(random, min, max)
+    }
+}
+
+/// Float drawn from a distribution, written either as a plain number or as a typed object
+#[derive(Deserialize, Debug, Clone)]
+#[serde(from = "FloatProviderRepr")]
+pub enum FloatProvider {
+    Constant(f32),
+    Uniform {
+        min_inclusive: f32,
+        max_exclusive: f32,
+    },
+    Trapezoid {
+        min: f32,
+        max: f32,
+        plateau: f32,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FloatProviderRepr {
+    Constant(f32),
+    Typed(TypedFloatProvider),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum TypedFloatProvider {
+    #[serde(rename = "minecraft:constant")]
+    Constant { value: f32 },
+    #[serde(rename = "minecraft:uniform")]
+    Uniform {
+        min_inclusive: f32,
+        max_exclusive: f32,
+    },
+    #[serde(rename = "minecraft:trapezoid")]
+    Trapezoid { min: f32, max: f32, plateau: f32 },
+}
+
+impl From<FloatProviderRepr> for FloatProvider {
+    fn from(repr: FloatProviderRepr) -> Self {
+        match repr {
+            FloatProviderRepr::Constant(value)
+            | FloatProviderRepr::Typed(TypedFloatProvider::Constant { value }) => {
+                FloatProvider::Constant(value)
+            }
+            FloatProviderRepr::Typed(TypedFloatProvider::Uniform {
+                min_inclusive,
+                max_exclusive,
+            }) => FloatProvider::Uniform {
+                min_inclusive,
+                max_exclusive,
+            },
+            FloatProviderRepr::Typed(TypedFloatProvider::Trapezoid { min, max, plateau }) => {
+                FloatProvider::Trapezoid { min, max, plateau }
+            }
+        }
+    }
+}
+
+impl FloatProvider {
+    pub fn sample(&self, random: &mut dyn RandomGenerator) -> f32 {
+        match *self {
+            FloatProvider::Constant(value) => value,
+            FloatProvider::Uniform {
+                min_inclusive,
+                max_exclusive,
+            } => random.next_f32() * (max_exclusive - min_inclusive) + min_inclusive,
+            FloatProvider::Trapezoid { min, max, plateau } => {
+                let range = max - min;
+                let slope = (range - plateau) / 2.0;
+                let rest = range - slope;
+                min + random.next_f32() * rest + random.next_f32() * slope
+            }
+        }
+    }
+}
+
+/// Height drawn from a distribution between two anchors, written either as an anchor or as a
+/// typed object
+#[derive(Deserialize, Debug, Clone)]
+#[serde(from = "HeightProviderRepr")]
+pub enum HeightProvider {
+    Constant(VerticalAnchor),
+    Uniform {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+    },
+    BiasedToBottom {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+        inner: i32,
+    },
+    VeryBiasedToBottom {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+        inner: i32,
+    },
+    Trapezoid {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+        plateau: i32,
+    },
+    WeightedList(Vec<(HeightProvider, i32)>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HeightProviderRepr {
+    Constant(VerticalAnchor),
+    Typed(TypedHeightProvider),
+}
+
+fn default_inner() -> i32 {
+    1
+}
+
+#[derive(Deserialize)]
+struct WeightedHeight {
+    data: HeightProvider,
+    weight: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum TypedHeightProvider {
+    #[serde(rename = "minecraft:constant")]
+    Constant { value: VerticalAnchor },
+    #[serde(rename = "minecraft:uniform")]
+    Uniform {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+    },
+    #[serde(rename = "minecraft:biased_to_bottom")]
+    BiasedToBottom {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+        #[serde(default = "default_inner")]
+        inner: i32,
+    },
+    #[serde(rename = "minecraft:very_biased_to_bottom")]
+    VeryBiasedToBottom {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+        #[serde(default = "default_inner")]
+        inner: i32,
+    },
+    #[serde(rename = "minecraft:trapezoid")]
+    Trapezoid {
+        min_inclusive: VerticalAnchor,
+        max_inclusive: VerticalAnchor,
+        #[serde(default)]
+        plateau: i32,
+    },
+    #[serde(rename = "minecraft:weighted_list")]
+    WeightedList { distribution: Vec<WeightedHeight> },
+}
+
+impl From<HeightProviderRepr> for HeightProvider {
+    fn from(repr: HeightProviderRepr) -> Self {
+        let typed = match repr {
+            HeightProviderRepr::Constant(anchor) => return HeightProvider::Constant(anchor),
+            HeightProviderRepr::Typed(typed) => typed,
+        };
+        match typed {
+            TypedHeightProvider::Constant { value } => HeightProvider::Constant(value),
+            TypedHeightProvider::Uniform {
+                min_inclusive,
+                max_inclusive,
+            } => HeightProvider::Uniform {
+                min_inclusive,
+                max_inclusive,
+            },
+            TypedHeightProvider::BiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+                inner,
+            } => HeightProvider::BiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+                inner,
+            },
+            TypedHeightProvider::VeryBiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+                inner,
+            } => HeightProvider::VeryBiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+                inner,
+            },
+            TypedHeightProvider::Trapezoid {
+                min_inclusive,
+                max_inclusive,
+                plateau,
+            } => HeightProvider::Trapezoid {
+                min_inclusive,
+                max_inclusive,
+                plateau,
+            },
+            TypedHeightProvider::WeightedList { distribution } => HeightProvider::WeightedList(
+                distribution
+                    .into_iter()
+                    .map(|entry| (entry.data, entry.weight))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl HeightProvider {
+    /// Samples a height of a dimension whose generation starts at `min_y` and spans `height`
+    /// blocks
+    pub fn sample(&self, random: &mut dyn RandomGenerator, min_y: i32, height: i32) -> i32 {
+        match self {
+            HeightProvider::Constant(anchor) => anchor.resolve_y(min_y, height),
+            HeightProvider::Uniform {
+                min_inclusive,
+                max_inclusive,
+            } => {
+                let min = min_inclusive.resolve_y(min_y, height);
+                let max = max_inclusive.resolve_y(min_y, height);
+                if min > max {
+                    min
+                } else {
+                    random_between_inclusive(random, min, max)
+                }
+            }
+            HeightProvider::BiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+                inner,
+            } => {
+                let min = min_inclusive.resolve_y(min_y, height);
+                let max = max_inclusive.resolve_y(min_y, height);
+                if max - min - inner + 1 <= 0 {
+                    return min;
+                }
+                let bound = random.next_i32_range((max - min - inner + 1) as u32);
+                random.next_i32_range((bound + inner) as u32) + min
+            }
+            HeightProvider::VeryBiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+                inner,
+            } => {
+                let min = min_inclusive.resolve_y(min_y, height);
+                let max = max_inclusive.resolve_y(min_y, height);
+                if max - min - inner + 1 <= 0 {
+                    return min;
+                }
+                let upper = next_int_between(random, min + inner, max);
+                let lower = next_int_between(random, min, upper - 1);
+                next_int_between(random, min, lower - 1 + inner)
+            }
+            HeightProvider::Trapezoid {
+                min_inclusive,
+                max_inclusive,
+                plateau,
+            } => {
+                let min = min_inclusive.resolve_y(min_y, height);
+                let max = max_inclusive.resolve_y(min_y, height);
+                if min > max {
+                    return min;
+                }
+                let range = max - min;
+                if *plateau >= range {
+                    return random_between_inclusive(random, min, max);
+                }
+                let slope = (range - plateau) / 2;
+                let rest = range - slope;
+                min + random_between_inclusive(random, 0, rest)
+                    + random_between_inclusive(random, 0, slope)
+            }
+            HeightProvider::WeightedList(entries) => {
+                let total = entries.iter().map(|(_, weight)| weight).sum::<i32>();
+                if total <= 0 {
+                    return min_y;
+                }
+                let mut pick = random.next_i32_range(total as u32);
+                for (provider, weight) in entries {
+                    pick -= weight;
+                    if pick < 0 {
+                        return provider.sample(random, min_y, height);
+                    }
+                }
+                min_y
+            }
+        }
+    }
+}
+
+/// Integer drawn from a distribution, written either as a plain number or as a typed object
+#[derive(Deserialize, Debug, Clone)]
+#[serde(from = "IntProviderRepr")]
+pub enum IntProvider {
+    Constant(i32),
+    Uniform {
+        min_inclusive: i32,
+        max_inclusive: i32,
+    },
+    BiasedToBottom {
+        min_inclusive: i32,
+        max_inclusive: i32,
+    },
+    Clamped {
+        source: Box<IntProvider>,
+        min_inclusive: i32,
+        max_inclusive: i32,
+    },
+    WeightedList(Vec<(IntProvider, i32)>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IntProviderRepr {
+    Constant(i32),
+    Typed(TypedIntProvider),
+}
+
+#[derive(Deserialize)]
+struct WeightedInt {
+    data: IntProvider,
+    weight: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum TypedIntProvider {
+    #[serde(rename = "minecraft:constant")]
+    Constant { value: i32 },
+    #[serde(rename = "minecraft:uniform")]
+    Uniform {
+        min_inclusive: i32,
+        max_inclusive: i32,
+    },
+    #[serde(rename = "minecraft:biased_to_bottom")]
+    BiasedToBottom {
+        min_inclusive: i32,
+        max_inclusive: i32,
+    },
+    #[serde(rename = "minecraft:clamped")]
+    Clamped {
+        source: Box<IntProvider>,
+        min_inclusive: i32,
+        max_inclusive: i32,
+    },
+    #[serde(rename = "minecraft:weighted_list")]
+    WeightedList { distribution: Vec<WeightedInt> },
+}
+
+impl From<IntProviderRepr> for IntProvider {
+    fn from(repr: IntProviderRepr) -> Self {
+        let typed = match repr {
+            IntProviderRepr::Constant(value) => return IntProvider::Constant(value),
+            IntProviderRepr::Typed(typed) => typed,
+        };
+        match typed {
+            TypedIntProvider::Constant { value } => IntProvider::Constant(value),
+            TypedIntProvider::Uniform {
+                min_inclusive,
+                max_inclusive,
+            } => IntProvider::Uniform {
+                min_inclusive,
+                max_inclusive,
+            },
+            TypedIntProvider::BiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+            } => IntProvider::BiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+            },
+            TypedIntProvider::Clamped {
+                source,
+                min_inclusive,
+                max_inclusive,
+            } => IntProvider::Clamped {
+                source,
+                min_inclusive,
+                max_inclusive,
+            },
+            TypedIntProvider::WeightedList { distribution } => IntProvider::WeightedList(
+                distribution
+                    .into_iter()
+                    .map(|entry| (entry.data, entry.weight))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl IntProvider {
+    pub fn sample(&self, random: &mut dyn RandomGenerator) -> i32 {
+        match self {
+            IntProvider::Constant(value) => *value,
+            IntProvider::Uniform {
+                min_inclusive,
+                max_inclusive,
+            } => random_between_inclusive(random, *min_inclusive, *max_inclusive),
+            IntProvider::BiasedToBottom {
+                min_inclusive,
+                max_inclusive,
+            } => {
+                let bound = random.next_i32_range((max_inclusive - min_inclusive + 1) as u32) + 1;
+                min_inclusive + random.next_i32_range(bound as u32)
+            }
+            IntProvider::Clamped {
+                source,
+                min_inclusive,
+                max_inclusive,
+            } => source.sample(random).clamp(*min_inclusive, *max_inclusive),
+            IntProvider::WeightedList(entries) => {
+                let total = entries.iter().map(|(_, weight)| weight).sum::<i32>();
+                if total <= 0 {
+                    return 0;
+                }
+                let mut pick = random.next_i32_range(total as u32);
+                for (provider, weight) in entries {
+                    pick -= weight;
+                    if pick < 0 {
+                        return provider.sample(random);
+                    }
+                }
+                0
+            }
+        }
+    }
+
+    /// Largest value the provider can return
+    pub fn max_value(&self) -> i32 {
+        match self {
+            IntProvider::Constant(value) => *value,
+            IntProvider::Uniform { max_inclusive, .. }
+            | IntProvider::BiasedToBottom { max_inclusive, .. } => *max_inclusive,
+            IntProvider::Clamped {
+                source,
+                max_inclusive,
+                ..
+            } => source.max_value().min(*max_inclusive),
+            IntProvider::WeightedList(entries) => entries
+                .iter()
+                .map(|(provider, _)| provider.max_value())
+                .max()
+                .unwrap_or(0),
+        }
+    }
+}
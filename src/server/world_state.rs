@@ -1,44 +1,177 @@
-use super::level::{WorldDimension, WorldGenSettings};
-use super::logger::LOGGER;
+use super::aquifer::Aquifer;
+use super::biome_source::{biome_id, obfuscate_seed, BiomeSource};
+use super::carvers;
+use super::chunk_system::{deserialize_format_blockstate, Chunk, BLOCKSTATE_MAPPINGS};
+use super::features::{self, region::FeatureRegion, FeatureSteps};
+use super::flat::FlatLevel;
+use super::level::{GeneratorSettings, WorldDimension, WorldGenSettings};
+use super::logger::{LogDomain, LogLevel, LOGGER};
 use super::randomness::{Xoroshiro, LCG48};
-use super::terrain_gen::func_deserialize::DensityArg;
+use super::structures::{StructureStart, WorldStructures};
+use super::surface::{SurfaceRule, SurfaceSystem};
+use super::terrain_gen::func_deserialize::{DensityArg, DensityFnArgs};
 use crate::{RandomPositionalGenerator, MC_VERSION};
 use ahash::AHashMap;
 use once_cell::sync::OnceCell;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::io::Read;
+use std::sync::Arc;
 
 pub static WORLD_STATES: OnceCell<AHashMap<String, WorldState>> = OnceCell::new();
 
 #[derive(Debug)]
 pub struct WorldState {
+    pub seed: i64,
     pub random: Box<dyn RandomPositionalGenerator>,
     pub settings: NoiseSettings,
+    /// `None` when the biome source is not supported, chunks then keep a placeholder biome
+    pub biome_source: Option<BiomeSource>,
+    /// Seed of the jitter applied to biome borders, see `biome_source::zoomed_biome`
+    pub biome_zoom_seed: i64,
+    pub surface: SurfaceSystem,
+    /// Random factory placing the aquifer centers
+    pub aquifer_random: Box<dyn RandomPositionalGenerator>,
+    /// Random factory of the ore vein blocks
+    pub ore_random: Box<dyn RandomPositionalGenerator>,
+    /// Order the placed features of the biomes of the dimension are placed in
+    pub features: FeatureSteps,
+    pub structures: WorldStructures,
+    /// Layers of a flat world, which skips the noise, surface and carver steps
+    pub flat: Option<FlatLevel>,
 }
 
+/// Sea level of flat worlds, like vanilla's `FlatLevelSource`
+const FLAT_SEA_LEVEL: i32 = -63;
+
 impl WorldState {
-    pub fn new(dimension: &WorldDimension, seed: i64) -> Self {
-        let file_name = dimension
-            .generator
-            .settings
-            .split_once(":")
-            .unwrap()
-            .1
-            .to_owned()
-            + ".json";
+    pub fn new(dimension: &WorldDimension, seed: i64, generate_features: bool) -> Self {
+        let (noise_settings, flat) = match &dimension.generator.settings {
+            GeneratorSettings::Noise(settings) => (settings.as_str(), None),
+            // Flat worlds have no noise, they keep the overworld bounds
+            GeneratorSettings::Flat(settings) => ("minecraft:overworld", Some(settings)),
+        };
+        let file_name = noise_settings.split_once(":").unwrap().1.to_owned() + ".json";
         let mut noise_settings_file = std::fs::File::open(format!(
             "versions/{MC_VERSION}/minecraft/worldgen/noise_settings/{file_name}"
         ))
         .unwrap_or_else(|_| panic!("Could not find dimension noise settings file: {file_name}"));
         let mut data = String::new();
         noise_settings_file.read_to_string(&mut data).unwrap();
-        let settings: NoiseSettings = serde_json::from_str(&data).unwrap();
+        let mut settings: NoiseSettings = serde_json::from_str(&data).unwrap_or_else(|e| {
+            LOGGER.get().unwrap().println_as(
+                &format!("Invalid dimension noise settings {file_name}: {e}"),
+                LogDomain::Server,
+                LogLevel::Error,
+            );
+            std::process::exit(1);
+        });
         let random = if settings.legacy_random_source {
             LCG48::new(seed).branch_positional()
         } else {
             Xoroshiro::new_from_i64(seed).branch_positional()
         };
-        WorldState { random, settings }
+        let biome_source = match (flat, &dimension.generator.biome_source) {
+            (Some(flat), _) => biome_id(&flat.biome).map(BiomeSource::Fixed),
+            (None, Some(biome_source)) => BiomeSource::new(biome_source),
+            (None, None) => None,
+        };
+        if flat.is_some() {
+            settings.sea_level = FLAT_SEA_LEVEL;
+        }
+        let surface = SurfaceSystem::new(&settings.surface_rule, random.as_ref());
+        let aquifer_random = random.hash_to_rand("minecraft:aquifer").branch_positional();
+        let ore_random = random.hash_to_rand("minecraft:ore").branch_positional();
+        let features = FeatureSteps::new(
+            biome_source
+                .as_ref()
+                .filter(|_| flat.is_none_or(|flat| flat.features)),
+        );
+        let structures = WorldStructures::new(
+            biome_source
+                .as_ref()
+                .map(|source| source.possible_biomes())
+                .unwrap_or_default(),
+            generate_features,
+            flat.and_then(|flat| flat.structure_sets()).as_deref(),
+        );
+        WorldState {
+            seed,
+            random,
+            settings,
+            biome_source,
+            biome_zoom_seed: obfuscate_seed(seed),
+            surface,
+            aquifer_random,
+            ore_random,
+            features,
+            structures,
+            flat: flat.map(FlatLevel::new),
+        }
+    }
+
+    /// Places the biomes of a generated chunk of the dimension
+    pub fn fill_biomes(&self, chunk: &mut Chunk, dimension: &str) {
+        if let Some(biome_source) = &self.biome_source {
+            biome_source.fill_biomes(chunk, &self.settings.noise_router, dimension);
+        }
+    }
+
+    /// Replaces the default block of a generated chunk with the surface blocks of its biomes
+    pub fn build_surface(&self, chunk: &mut Chunk, dimension: &str) {
+        self.surface.build_surface(self, chunk, dimension);
+    }
+
+    /// Carves the caves and canyons reaching into a generated chunk after its surface was built
+    pub fn apply_carvers<'a>(
+        &'a self,
+        chunk: &mut Chunk,
+        aquifer: &mut Aquifer<'a>,
+        dimension: &'a str,
+    ) {
+        carvers::carve_chunk(self, chunk, aquifer, dimension);
+    }
+
+    /// Structure starts of the dimension reaching into a chunk
+    pub fn structure_starts(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        dimension: &str,
+    ) -> Vec<Arc<StructureStart>> {
+        self.structures
+            .starts_referencing(self, dimension, chunk_x, chunk_z)
+    }
+
+    /// Places the features of the biomes around the center chunk of a region
+    pub fn decorate(&self, region: &mut FeatureRegion) {
+        features::decorate_chunk(self, region);
+    }
+
+    /// Highest height of a noise cell where the initial density shows solid terrain, sampled at
+    /// quart resolution. `i32::MAX` when no terrain is found
+    pub fn preliminary_surface_level(&self, x: i32, z: i32, dimension: &str) -> i32 {
+        let Some(density) = self
+            .settings
+            .noise_router
+            .get("initial_density_without_jaggedness")
+        else {
+            return i32::MAX;
+        };
+        let (x, z) = (x & !3, z & !3);
+        let min_y = self.settings.noise.min_y;
+        let cell_height = self.settings.noise.size_vertical as usize * 4;
+        for y in (min_y..=min_y + self.settings.noise.height)
+            .rev()
+            .step_by(cell_height)
+        {
+            let mut args = DensityFnArgs::new(x, y, z, dimension);
+            if density.compute(&mut args) > 0.390625 {
+                return y;
+            }
+        }
+        i32::MAX
     }
 
     pub fn initialize_world_states(world_gen_settings: &WorldGenSettings) {
@@ -50,7 +183,11 @@ impl WorldState {
         for (dim_name, dim_settings) in &world_gen_settings.dimensions {
             world_states.insert(
                 dim_name.to_owned(),
-                WorldState::new(dim_settings, world_gen_settings.seed),
+                WorldState::new(
+                    dim_settings,
+                    world_gen_settings.seed,
+                    world_gen_settings.generate_features,
+                ),
             );
         }
         // Set global World States
@@ -60,6 +197,11 @@ impl WorldState {
             for dense_func in dimension.1.settings.noise_router.values() {
                 dense_func.precompute_noise_instance(&dimension.0);
             }
+            dimension
+                .1
+                .settings
+                .surface_rule
+                .precompute_noise_instances(&dimension.0);
         }
     }
 }
@@ -67,14 +209,52 @@ impl WorldState {
 #[derive(Deserialize, Debug)]
 pub struct NoiseSettings {
     pub aquifers_enabled: bool,
-    //default_block
-    //default_fluid
+    /// Block that fills the terrain before surface rules
+    #[serde(deserialize_with = "deserialize_block_state")]
+    pub default_block: u32,
+    /// Fluid filling seas and aquifers
+    #[serde(deserialize_with = "deserialize_block_state")]
+    pub default_fluid: u32,
     pub disable_mob_generation: bool,
     pub legacy_random_source: bool,
     pub noise: NoiseBounds,
     pub noise_router: AHashMap<String, DensityArg>,
     pub ore_veins_enabled: bool,
     pub sea_level: i32,
+    pub surface_rule: SurfaceRule,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NoiseBlockState {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Properties", default)]
+    pub properties: Option<HashMap<String, String>>,
+}
+
+impl NoiseBlockState {
+    pub fn id(&self) -> Option<u32> {
+        let properties = self.properties.as_ref().map(|properties| {
+            properties
+                .iter()
+                .map(|(k, v)| (Cow::Owned(k.as_str()), Cow::Owned(v.as_str())))
+                .collect()
+        });
+        BLOCKSTATE_MAPPINGS
+            .get(deserialize_format_blockstate(&self.name, properties).as_str())
+            .copied()
+    }
+}
+
+/// Reads a block state straight into its id, unknown block states fail deserialization
+pub fn deserialize_block_state<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let state = NoiseBlockState::deserialize(deserializer)?;
+    state
+        .id()
+        .ok_or_else(|| serde::de::Error::custom(format!("Unknown block state {}", state.name)))
 }
 
 #[derive(Deserialize, Debug)]
@@ -84,3 +264,25 @@ pub struct NoiseBounds {
     pub size_horizontal: u32,
     pub size_vertical: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_blocks_are_read_as_block_states() {
+        let water = serde_json::json!({"Name": "minecraft:water", "Properties": {"level": "0"}});
+        assert_eq!(
+            deserialize_block_state(water).unwrap(),
+            BLOCKSTATE_MAPPINGS["water[level=0]"]
+        );
+        let stone = serde_json::json!({"Name": "minecraft:stone"});
+        assert_eq!(
+            deserialize_block_state(stone).unwrap(),
+            BLOCKSTATE_MAPPINGS["stone"]
+        );
+        let unknown = serde_json::json!({"Name": "minecraft:water", "Properties": {"level": "16"}});
+        let error = deserialize_block_state(unknown).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown block state minecraft:water");
+    }
+}
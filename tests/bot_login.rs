@@ -0,0 +1,69 @@
+//! Starts a server on a free port and logs a `BotClient` in
+
+use ludicrous::client::{BotClient, ClientEvent};
+use ludicrous::server::logger::{ServerLogger, LOGGER};
+use ludicrous::server::terrain_gen::noise_generator::initialize_noise_instances;
+use ludicrous::server::ServerProperties;
+use ludicrous::{generate_server_encryption, handle_client, player, server, JAR_RESOURCES_DIR};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs the game data the server extracts from the client jar on its first start, \
+            run `cargo test -- --ignored` afterwards"]
+async fn bot_logs_in_and_receives_chunks() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    assert!(
+        root.join(JAR_RESOURCES_DIR).is_dir(),
+        "{JAR_RESOURCES_DIR} was not extracted yet, start the server once"
+    );
+    // A copy of the fixture world, its spawn is set so only the chunks around it are generated
+    let world = std::env::temp_dir().join(format!("ludicrous-bot-login-{}", std::process::id()));
+    std::fs::create_dir_all(&world).unwrap();
+    std::fs::copy(
+        root.join("tests/fixtures/world/level.dat"),
+        world.join("level.dat"),
+    )
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let properties = ServerProperties {
+        level_name: world.to_str().unwrap().to_string(),
+        view_distance: 2,
+        simulation_distance: 2,
+        server_port: port as u32,
+        ..ServerProperties::default()
+    };
+
+    LOGGER.get_or_init(ServerLogger::new);
+    generate_server_encryption();
+    let server = Arc::new(server::ServerData::with_properties(properties));
+    once_cell::sync::Lazy::force(
+        &server::terrain_gen::func_deserialize::EXTERNAL_DENSITY_FUNCTIONS,
+    );
+    initialize_noise_instances();
+    tokio::spawn(async move {
+        loop {
+            let (socket, _addr) = listener.accept().await.unwrap();
+            socket.set_nodelay(true).unwrap();
+            tokio::spawn(handle_client(
+                player::PlayerStream::new(socket),
+                server.clone(),
+            ));
+        }
+    });
+
+    let mut bot = BotClient::connect(&format!("127.0.0.1:{port}"), "Bot")
+        .await
+        .unwrap();
+    bot.wait_for(Duration::from_secs(60), |_, event| {
+        matches!(event, ClientEvent::ChunkData { .. })
+    })
+    .await
+    .unwrap();
+    assert!(bot.entity_id.is_some());
+    assert!(!bot.chunks.is_empty());
+    std::fs::remove_dir_all(&world).unwrap();
+}